@@ -0,0 +1,71 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use cs431_homework::hello_server::Cache;
+use cs431_homework::workload::{Contention, KeyDist, Mix, Op, Workload};
+
+const KEYS: u64 = 4096;
+const OPS: usize = 4096;
+
+/// Creates an empty cache with one of the map locks.
+type NewCache = fn() -> Cache<u64, u64>;
+
+/// The cache has no plain lookup, so both the reads and the insertions are `get_or_insert_with`,
+/// and the removals invalidate the key. The map is guarded by either lock the cache offers.
+fn cache(c: &mut Criterion) {
+    let locks: [(&str, NewCache); 2] = [
+        ("rwlock", Cache::default),
+        ("bravo", || Cache::read_biased(None)),
+    ];
+    let mut group = c.benchmark_group("cache");
+    let dists = [
+        KeyDist::Uniform { keys: KEYS },
+        KeyDist::Zipf {
+            keys: KEYS,
+            exponent: 1.0,
+        },
+    ];
+    for keys in dists {
+        for mix in [Mix::READ_ONLY, Mix::READ_MOSTLY, Mix::BALANCED] {
+            let workload = Workload::new(keys, mix, OPS);
+            for (contention, (lock, new)) in Contention::ALL
+                .into_iter()
+                .flat_map(|contention| locks.map(|lock| (contention, lock)))
+            {
+                let threads = contention.threads();
+                let _ = group.throughput(Throughput::Elements((threads * OPS) as u64));
+                let _ = group.bench_with_input(
+                    BenchmarkId::new(format!("{lock}/{workload}"), contention),
+                    &threads,
+                    |b, &threads| {
+                        b.iter_custom(|iters| {
+                            (0..iters)
+                                .map(|_| {
+                                    let cache = new();
+                                    for key in workload.prefill() {
+                                        let _ = cache.get_or_insert_with(key, |key| key);
+                                    }
+                                    let cache = &cache;
+                                    workload.run(threads, move |_| {
+                                        move |op| match op {
+                                            Op::Read(key) | Op::Insert(key) => {
+                                                let _ = black_box(
+                                                    cache.get_or_insert_with(key, |key| key),
+                                                );
+                                            }
+                                            Op::Remove(key) => {
+                                                let _ = cache.invalidate(&key);
+                                            }
+                                        }
+                                    })
+                                })
+                                .sum()
+                        })
+                    },
+                );
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cache);
+criterion_main!(benches);
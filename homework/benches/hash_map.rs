@@ -0,0 +1,124 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use crossbeam_epoch as epoch;
+use cs431_homework::workload::{Contention, KeyDist, Mix, Op, Workload};
+use cs431_homework::{CuckooMap, LockedHashMap, NonblockingMap, SplitOrderedList};
+
+const KEYS: u64 = 4096;
+const OPS: usize = 4096;
+
+/// The operations of a workload on one of the maps compared.
+trait Map: Sync {
+    fn new() -> Self;
+    fn apply(&self, op: Op);
+}
+
+impl Map for LockedHashMap<u64, u64> {
+    fn new() -> Self {
+        LockedHashMap::new()
+    }
+
+    fn apply(&self, op: Op) {
+        match op {
+            Op::Read(key) => {
+                let _ = black_box(self.get(&key));
+            }
+            Op::Insert(key) => {
+                let _ = self.insert(key, key);
+            }
+            Op::Remove(key) => {
+                let _ = self.remove(&key);
+            }
+        }
+    }
+}
+
+impl Map for CuckooMap<u64, u64> {
+    fn new() -> Self {
+        CuckooMap::new()
+    }
+
+    fn apply(&self, op: Op) {
+        match op {
+            Op::Read(key) => {
+                let _ = black_box(self.get(&key));
+            }
+            Op::Insert(key) => {
+                let _ = self.insert(key, key);
+            }
+            Op::Remove(key) => {
+                let _ = self.remove(&key);
+            }
+        }
+    }
+}
+
+impl Map for SplitOrderedList<u64> {
+    fn new() -> Self {
+        SplitOrderedList::new()
+    }
+
+    fn apply(&self, op: Op) {
+        let guard = epoch::pin();
+        match op {
+            Op::Read(key) => {
+                let _ = black_box(self.lookup(&(key as usize), &guard));
+            }
+            Op::Insert(key) => {
+                let _ = self.insert(&(key as usize), key, &guard);
+            }
+            Op::Remove(key) => {
+                let _ = self.delete(&(key as usize), &guard);
+            }
+        }
+    }
+}
+
+/// Runs the workloads on a map, starting from an empty one that grows as it is prefilled.
+fn bench<M: Map>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(format!("hash_map/{name}"));
+    let dists = [
+        KeyDist::Uniform { keys: KEYS },
+        KeyDist::Zipf {
+            keys: KEYS,
+            exponent: 1.0,
+        },
+    ];
+    for keys in dists {
+        for mix in [Mix::READ_ONLY, Mix::READ_MOSTLY, Mix::BALANCED] {
+            let workload = Workload::new(keys, mix, OPS);
+            for contention in Contention::ALL {
+                let threads = contention.threads();
+                let _ = group.throughput(Throughput::Elements((threads * OPS) as u64));
+                let _ = group.bench_with_input(
+                    BenchmarkId::new(workload.to_string(), contention),
+                    &threads,
+                    |b, &threads| {
+                        b.iter_custom(|iters| {
+                            (0..iters)
+                                .map(|_| {
+                                    let map = M::new();
+                                    for key in workload.prefill() {
+                                        map.apply(Op::Insert(key));
+                                    }
+                                    let map = &map;
+                                    workload.run(threads, move |_| move |op| map.apply(op))
+                                })
+                                .sum()
+                        })
+                    },
+                );
+            }
+        }
+    }
+    group.finish();
+}
+
+/// The lock-based map is the baseline of the cuckoo and the split-ordered maps.
+fn hash_map(c: &mut Criterion) {
+    bench::<LockedHashMap<u64, u64>>(c, "locked");
+    bench::<CuckooMap<u64, u64>>(c, "cuckoo");
+    bench::<SplitOrderedList<u64>>(c, "split_ordered");
+}
+
+criterion_group!(benches, hash_map);
+criterion_main!(benches);
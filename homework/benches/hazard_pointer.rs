@@ -0,0 +1,91 @@
+use core::ptr;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use cs431_homework::hazard_pointer::{retire, Shield};
+use cs431_homework::workload::{Contention, KeyDist, Mix, Op, Workload};
+
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-sched")]
+use cs431_homework::sched::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+const KEYS: u64 = 1024;
+const OPS: usize = 4096;
+
+/// Each key is a slot that points to a value or is null. The reads protect the value of the slot,
+/// and the insertions and the removals replace it with a new value or null, retiring the old one.
+fn hazard_pointer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hazard_pointer");
+    let dists = [
+        KeyDist::Uniform { keys: KEYS },
+        KeyDist::Hotspot {
+            keys: KEYS,
+            hot: 8,
+            ratio: 0.9,
+        },
+    ];
+    for keys in dists {
+        for mix in [Mix::READ_ONLY, Mix::READ_MOSTLY, Mix::WRITE_ONLY] {
+            let workload = Workload::new(keys, mix, OPS);
+            for contention in Contention::ALL {
+                let threads = contention.threads();
+                let _ = group.throughput(Throughput::Elements((threads * OPS) as u64));
+                let _ = group.bench_with_input(
+                    BenchmarkId::new(workload.to_string(), contention),
+                    &threads,
+                    |b, &threads| {
+                        b.iter_custom(|iters| {
+                            (0..iters)
+                                .map(|_| {
+                                    let slots = (0..KEYS)
+                                        .map(|_| AtomicPtr::new(ptr::null_mut()))
+                                        .collect::<Vec<_>>();
+                                    for key in workload.prefill() {
+                                        let value = Box::into_raw(Box::new(key));
+                                        slots[key as usize].store(value, Ordering::Relaxed);
+                                    }
+                                    let slots = &slots;
+                                    let elapsed = workload.run(threads, move |_| {
+                                        let shield = Shield::default();
+                                        move |op| match op {
+                                            Op::Read(key) => {
+                                                let value = shield.protect(&slots[key as usize]);
+                                                if !value.is_null() {
+                                                    let _ = black_box(unsafe { *value });
+                                                }
+                                                shield.clear();
+                                            }
+                                            Op::Insert(key) | Op::Remove(key) => {
+                                                let new = match op {
+                                                    Op::Insert(_) => Box::into_raw(Box::new(key)),
+                                                    _ => ptr::null_mut(),
+                                                };
+                                                let old =
+                                                    slots[key as usize].swap(new, Ordering::AcqRel);
+                                                if !old.is_null() {
+                                                    unsafe { retire(old) };
+                                                }
+                                            }
+                                        }
+                                    });
+                                    for slot in slots {
+                                        let value = slot.swap(ptr::null_mut(), Ordering::Relaxed);
+                                        if !value.is_null() {
+                                            drop(unsafe { Box::from_raw(value) });
+                                        }
+                                    }
+                                    elapsed
+                                })
+                                .sum()
+                        })
+                    },
+                );
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hazard_pointer);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use cs431_homework::workload::{Contention, KeyDist, Mix, Op, Workload};
+use cs431_homework::OrderedListSet;
+
+/// The operations traverse the list, so it is kept short.
+const KEYS: u64 = 256;
+const OPS: usize = 1024;
+
+fn list_set(c: &mut Criterion) {
+    let mut group = c.benchmark_group("list_set");
+    for mix in [Mix::READ_MOSTLY, Mix::BALANCED, Mix::WRITE_ONLY] {
+        let workload = Workload::new(KeyDist::Uniform { keys: KEYS }, mix, OPS);
+        for contention in Contention::ALL {
+            let threads = contention.threads();
+            let _ = group.throughput(Throughput::Elements((threads * OPS) as u64));
+            let _ = group.bench_with_input(
+                BenchmarkId::new(workload.to_string(), contention),
+                &threads,
+                |b, &threads| {
+                    b.iter_custom(|iters| {
+                        (0..iters)
+                            .map(|_| {
+                                let set = OrderedListSet::new();
+                                for key in workload.prefill() {
+                                    let _ = set.insert(key);
+                                }
+                                let set = &set;
+                                workload.run(threads, move |_| {
+                                    move |op| match op {
+                                        Op::Read(key) => {
+                                            let _ = set.contains(&key);
+                                        }
+                                        Op::Insert(key) => {
+                                            let _ = set.insert(key);
+                                        }
+                                        Op::Remove(key) => {
+                                            let _ = set.remove(&key);
+                                        }
+                                    }
+                                })
+                            })
+                            .sum()
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, list_set);
+criterion_main!(benches);
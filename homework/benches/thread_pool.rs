@@ -0,0 +1,69 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use cs431_homework::hello_server::ThreadPool;
+use cs431_homework::workload::{Contention, KeyDist, Mix, Op, Workload};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+const KEYS: u64 = 64;
+const OPS: usize = 4096;
+
+/// The threads of the workload submit a tiny job for each operation to a pool of a worker for
+/// each hardware thread, which reads, increments or decrements the counter of the key. The time
+/// includes waiting for the pool to run the jobs.
+fn thread_pool(c: &mut Criterion) {
+    let mut group = c.benchmark_group("thread_pool");
+    let workers = thread::available_parallelism().map_or(1, usize::from);
+    for mix in [Mix::READ_ONLY, Mix::BALANCED] {
+        let workload = Workload::new(KeyDist::Uniform { keys: KEYS }, mix, OPS);
+        for contention in Contention::ALL {
+            let threads = contention.threads();
+            let _ = group.throughput(Throughput::Elements((threads * OPS) as u64));
+            let _ = group.bench_with_input(
+                BenchmarkId::new(workload.to_string(), contention),
+                &threads,
+                |b, &threads| {
+                    let pool = ThreadPool::new(workers);
+                    let counters =
+                        Arc::new((0..KEYS).map(|_| AtomicU64::new(0)).collect::<Vec<_>>());
+                    b.iter_custom(|iters| {
+                        (0..iters)
+                            .map(|_| {
+                                let pool = &pool;
+                                let counters = &counters;
+                                let submitted = workload.run(threads, move |_| {
+                                    move |op| {
+                                        let counters = counters.clone();
+                                        pool.execute(move || match op {
+                                            Op::Read(key) => {
+                                                let _ = black_box(
+                                                    counters[key as usize].load(Ordering::Relaxed),
+                                                );
+                                            }
+                                            Op::Insert(key) => {
+                                                let _ = counters[key as usize]
+                                                    .fetch_add(1, Ordering::Relaxed);
+                                            }
+                                            Op::Remove(key) => {
+                                                let _ = counters[key as usize]
+                                                    .fetch_sub(1, Ordering::Relaxed);
+                                            }
+                                        });
+                                    }
+                                });
+                                let start = Instant::now();
+                                pool.join();
+                                submitted + start.elapsed()
+                            })
+                            .sum()
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, thread_pool);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+#!/usr/bin/env bash
+# set -e
+set -uo pipefail
+IFS=$'\n\t'
+
+# Imports library.
+BASEDIR=$(dirname "$0")
+source $BASEDIR/grade-utils.sh
+
+# The cells copy their values as integers, which is only miscompiled with optimizations if a value
+# has uninitialized bytes, so the tests also run in release mode.
+RUNNERS=(
+    "cargo"
+    "cargo --release"
+)
+TESTS=("--test atomic_cell")
+
+FAILED=0
+for RUNNER in "${RUNNERS[@]}"; do
+    echo "Testing atomic_cell.rs with $RUNNER..."
+    FAILED=$((FAILED + $(run_tests)))
+done
+
+[ "$FAILED" -eq 0 ]
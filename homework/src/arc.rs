@@ -4,18 +4,23 @@
 
 use std::fmt;
 use std::marker::PhantomData;
-use std::mem;
+use std::mem::{self, ManuallyDrop};
 use std::ops::Deref;
-use std::ptr::NonNull;
+use std::process;
+use std::ptr::{self, NonNull};
 
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicUsize, Ordering};
 #[cfg(feature = "check-loom")]
 use loom::sync::atomic::{fence, AtomicUsize, Ordering};
-#[cfg(not(feature = "check-loom"))]
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
 use std::sync::atomic::{fence, AtomicUsize, Ordering};
 
+use crate::backoff::Backoff;
+
 const MAX_REFCOUNT: usize = (isize::MAX) as usize;
 
-/// Simplified `Arc` without `Weak` support.
+/// Simplified `Arc`.
 ///
 /// The main correctness guarantee of `Arc` is that the deallocation of its data and counter field
 /// happens-after all accesses to those fields.  An access (by `Deref::deref`, `get_mut`, ...) to an
@@ -33,6 +38,10 @@ const MAX_REFCOUNT: usize = (isize::MAX) as usize;
 /// `try_unwrap` also provides a similar guarantee as it returns the exclusive ownership of the
 /// data.
 ///
+/// A `Weak` pointer does not keep the data alive, but keeps the allocation alive. Its `upgrade`
+/// returns an `Arc` only if the data is not dropped yet. So the drop of the data happens-after the
+/// drops of all `Arc`s, and the deallocation happens-after the drops of all `Weak`s as well.
+///
 /// The above explanation is based on the paper [RustBelt Meets Relaxed Memory by Dang et
 /// al.](https://plv.mpi-sws.org/rustbelt/rbrlx/).
 pub struct Arc<T> {
@@ -53,8 +62,33 @@ impl<T> Arc<T> {
 }
 
 struct ArcInner<T> {
-    count: AtomicUsize,
-    data: T,
+    /// The number of `Arc`s.
+    strong: AtomicUsize,
+    /// The number of `Weak`s, plus one held by all `Arc`s together. `usize::MAX` while it is locked
+    /// by `is_unique`.
+    weak: AtomicUsize,
+    /// Dropped when `strong` drops to 0, while the allocation may still be alive.
+    data: ManuallyDrop<T>,
+}
+
+impl<T> ArcInner<T> {
+    fn new(data: T) -> NonNull<Self> {
+        let inner = Box::new(Self {
+            strong: AtomicUsize::new(1),
+            weak: AtomicUsize::new(1),
+            data: ManuallyDrop::new(data),
+        });
+        Box::leak(inner).into()
+    }
+}
+
+/// Aborts if a count is incremented from `old` past `MAX_REFCOUNT`, as it may overflow before the
+/// panic unwinds and decrements it.
+#[inline]
+fn check_refcount(old: usize) {
+    if old > MAX_REFCOUNT {
+        process::abort();
+    }
 }
 
 unsafe impl<T: Sync + Send> Send for ArcInner<T> {}
@@ -64,11 +98,40 @@ impl<T> Arc<T> {
     /// Constructs a new `Arc<T>`.
     #[inline]
     pub fn new(data: T) -> Arc<T> {
-        let x = Box::new(ArcInner {
-            count: AtomicUsize::new(1),
-            data,
-        });
-        Self::from_inner(Box::leak(x).into())
+        Self::from_inner(ArcInner::new(data))
+    }
+
+    /// Creates a new `Weak` pointer to this allocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cs431_homework::Arc;
+    ///
+    /// let five = Arc::new(5);
+    /// let weak_five = Arc::downgrade(&five);
+    /// assert_eq!(*weak_five.upgrade().unwrap(), 5);
+    /// ```
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        let backoff = Backoff::new();
+        let weak = &this.inner().weak;
+        let mut count = weak.load(Ordering::Relaxed);
+        loop {
+            // Waits while `is_unique` locks the count.
+            if count == usize::MAX {
+                backoff.snooze();
+                count = weak.load(Ordering::Relaxed);
+                continue;
+            }
+            check_refcount(count);
+            // Acquire: synchronizes with the unlock in `is_unique`, so that its read of the strong
+            // count happens before this `Weak` may upgrade.
+            match weak.compare_exchange_weak(count, count + 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return Weak::from_inner(this.ptr),
+                Err(actual) => count = actual,
+            }
+        }
     }
 
     /// Returns a mutable reference into the given `Arc` if there are
@@ -100,9 +163,24 @@ impl<T> Arc<T> {
 
     // Used in `get_mut` and `make_mut` to check if the given `Arc` is the unique reference to the
     // underlying data.
+    //
+    // The weak count is locked meanwhile, so that no `Weak` is created from another `Arc` between
+    // the checks of the two counts. Acquire: the drops of the other `Arc`s and `Weak`s happen
+    // before the returned `&mut` is accessed.
     #[inline]
     fn is_unique(&mut self) -> bool {
-        self.inner().count.load(Ordering::Acquire).eq(&1)
+        let inner = self.inner();
+        if inner
+            .weak
+            .compare_exchange(1, usize::MAX, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        let unique = inner.strong.load(Ordering::Acquire) == 1;
+        // Release: see `downgrade`.
+        inner.weak.store(1, Ordering::Release);
+        unique
     }
 
     /// Returns a mutable reference into the given `Arc` without any check.
@@ -153,7 +231,27 @@ impl<T> Arc<T> {
     /// ```
     #[inline]
     pub fn count(this: &Self) -> usize {
-        this.inner().count.load(Ordering::Acquire)
+        this.inner().strong.load(Ordering::Acquire)
+    }
+
+    /// Gets the number of `Weak` pointers to this allocation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cs431_homework::Arc;
+    ///
+    /// let five = Arc::new(5);
+    /// let _weak_five = Arc::downgrade(&five);
+    /// assert_eq!(1, Arc::weak_count(&five));
+    /// ```
+    #[inline]
+    pub fn weak_count(this: &Self) -> usize {
+        match this.inner().weak.load(Ordering::Acquire) {
+            // `is_unique` locks the count only if there is no `Weak`.
+            usize::MAX => 0,
+            count => count - 1,
+        }
     }
 
     #[inline]
@@ -204,15 +302,22 @@ impl<T> Arc<T> {
     /// ```
     #[inline]
     pub fn try_unwrap(this: Self) -> Result<T, Self> {
-        if this.inner().count.load(Ordering::Acquire) != 1 {
-            Err(this)
-        } else {
-            unsafe {
-                let result = Box::from_raw(this.ptr.as_ptr()).data;
-                mem::forget(this);
-                Ok(result)
-            }
+        // The count drops to 0 as if `this` is dropped, so that no `Weak` upgrades afterwards.
+        // Acquire: the drops of the other `Arc`s happen before the data is moved out.
+        if this
+            .inner()
+            .strong
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(this);
         }
+        // SAFETY: The strong count dropped to 0, so no other thread accesses the data.
+        let data = unsafe { ptr::read(&*this.inner().data) };
+        // Drops the weak reference held by the `Arc`s.
+        drop(Weak::from_inner(this.ptr));
+        mem::forget(this);
+        Ok(data)
     }
 }
 
@@ -244,18 +349,12 @@ impl<T: Clone> Arc<T> {
     /// ```
     #[inline]
     pub fn make_mut(this: &mut Self) -> &mut T {
-        let data = unsafe { &mut this.ptr.as_mut().data };
-        if this.is_unique() {
-            data
-        } else {
-            this.inner().count.fetch_sub(1, Ordering::Release);
-            let x = Box::new(ArcInner {
-                count: AtomicUsize::new(1),
-                data: data.clone(),
-            });
-            this.ptr = Box::leak(x).into();
-            unsafe { &mut (*this.ptr.as_ptr()).data }
+        if !this.is_unique() {
+            // The old `Arc` is dropped as usual, as other threads may drop theirs meanwhile.
+            *this = Arc::new((**this).clone());
         }
+        // SAFETY: `this` is the only `Arc` to the allocation, and there is no `Weak` to it.
+        unsafe { Self::get_mut_unchecked(this) }
     }
 }
 
@@ -265,9 +364,9 @@ impl<T> Clone for Arc<T> {
     /// This creates another pointer to the same allocation, increasing the
     /// reference count.
     ///
-    /// # Panics
+    /// # Aborts
     ///
-    /// This panics if the number of `Arc`s is larger than `isize::Max`.
+    /// This aborts if the number of `Arc`s is larger than `isize::Max`.
     ///
     /// # Examples
     ///
@@ -280,7 +379,8 @@ impl<T> Clone for Arc<T> {
     /// ```
     #[inline]
     fn clone(&self) -> Arc<T> {
-        self.inner().count.fetch_add(1, Ordering::Release);
+        // Relaxed: the new `Arc` is created from an existing one, which keeps the data alive.
+        check_refcount(self.inner().strong.fetch_add(1, Ordering::Relaxed));
         Arc::from_inner(self.ptr)
     }
 }
@@ -320,11 +420,16 @@ impl<T> Drop for Arc<T> {
     /// drop(foo2);   // Prints "dropped!"
     /// ```
     fn drop(&mut self) {
-        if self.inner().count.fetch_sub(1, Ordering::AcqRel) == 1 {
-            unsafe {
-                drop(Box::from_raw(self.ptr.as_ptr()));
-            }
+        // Release: the accesses through this `Arc` happen before the data is dropped.
+        if self.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return;
         }
+        // Acquire: the accesses through the other `Arc`s happen before the data is dropped.
+        fence(Ordering::Acquire);
+        // SAFETY: This is the last `Arc`, and no `Weak` upgrades once the count is 0.
+        unsafe { ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).data) };
+        // Drops the weak reference held by the `Arc`s.
+        drop(Weak::from_inner(self.ptr));
     }
 }
 
@@ -345,3 +450,114 @@ impl<T> fmt::Pointer for Arc<T> {
         fmt::Pointer::fmt(&(&**self as *const T), f)
     }
 }
+
+/// `Weak` is a version of `Arc` that holds a non-owning reference to the allocation. The data is
+/// accessed by `upgrade`, which returns `None` if it is dropped already.
+pub struct Weak<T> {
+    ptr: NonNull<ArcInner<T>>,
+    phantom: PhantomData<ArcInner<T>>,
+}
+
+unsafe impl<T: Sync + Send> Send for Weak<T> {}
+unsafe impl<T: Sync + Send> Sync for Weak<T> {}
+
+impl<T> Weak<T> {
+    fn from_inner(ptr: NonNull<ArcInner<T>>) -> Self {
+        Self {
+            ptr,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the counts. The data may be dropped already, so no reference covers it.
+    #[inline]
+    fn counts(&self) -> (&AtomicUsize, &AtomicUsize) {
+        let inner = self.ptr.as_ptr();
+        // SAFETY: The allocation is alive while this `Weak` is.
+        unsafe { (&(*inner).strong, &(*inner).weak) }
+    }
+
+    /// Attempts to upgrade the `Weak` pointer to an `Arc`, preventing the data from being dropped
+    /// while the `Arc` is alive. Returns `None` if the data has been dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cs431_homework::Arc;
+    ///
+    /// let five = Arc::new(5);
+    /// let weak_five = Arc::downgrade(&five);
+    /// assert!(weak_five.upgrade().is_some());
+    ///
+    /// drop(five);
+    /// assert!(weak_five.upgrade().is_none());
+    /// ```
+    pub fn upgrade(&self) -> Option<Arc<T>> {
+        let (strong, _) = self.counts();
+        // Never increments the count from 0, as the data is dropped then. Relaxed: as in
+        // `Arc::clone`, the data is alive while the count is not 0.
+        strong
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                check_refcount(count);
+                (count != 0).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Arc::from_inner(self.ptr))
+    }
+
+    /// Gets the number of `Arc`s to this allocation.
+    pub fn strong_count(&self) -> usize {
+        self.counts().0.load(Ordering::Acquire)
+    }
+
+    /// Gets the number of `Weak`s to this allocation, or 0 if there is no `Arc` left.
+    pub fn weak_count(&self) -> usize {
+        let (strong, weak) = self.counts();
+        let weak = weak.load(Ordering::Acquire);
+        if strong.load(Ordering::Acquire) == 0 {
+            0
+        } else {
+            // Not locked by `is_unique`, as this `Weak` exists.
+            weak - 1
+        }
+    }
+
+    /// Returns `true` if the two `Weak`s point to the same allocation.
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr.as_ptr() == other.ptr.as_ptr()
+    }
+}
+
+impl<T> Clone for Weak<T> {
+    /// Makes a clone of the `Weak` pointer that points to the same allocation.
+    ///
+    /// # Aborts
+    ///
+    /// This aborts if the number of `Weak`s is larger than `isize::Max`.
+    #[inline]
+    fn clone(&self) -> Weak<T> {
+        // Relaxed: as in `Arc::clone`. Not locked by `is_unique`, as this `Weak` exists.
+        check_refcount(self.counts().1.fetch_add(1, Ordering::Relaxed));
+        Weak::from_inner(self.ptr)
+    }
+}
+
+impl<T> Drop for Weak<T> {
+    /// Drops the `Weak` pointer, deallocating the allocation if it is the last reference.
+    fn drop(&mut self) {
+        // Release: the accesses to the counts through this `Weak` happen before the deallocation.
+        if self.counts().1.fetch_sub(1, Ordering::Release) == 1 {
+            // Acquire: so do the accesses through the other `Weak`s and the `Arc`s.
+            fence(Ordering::Acquire);
+            // SAFETY: This is the last reference, and the data is dropped already.
+            drop(unsafe { Box::from_raw(self.ptr.as_ptr()) });
+        }
+    }
+}
+
+impl<T> fmt::Debug for Weak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(Weak)")
+    }
+}
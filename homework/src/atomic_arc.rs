@@ -0,0 +1,161 @@
+//! Atomic storage of an `Arc`, read without locking.
+
+use core::fmt;
+use core::marker::PhantomData;
+use std::sync::Arc;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::backoff::Backoff;
+use crate::reclaim::{Hp, Protect, Reclaimer};
+
+/// `Arc` that is loaded and replaced atomically, whose replaced versions are reclaimed with `R`,
+/// hazard pointers by default.
+///
+/// Suited for read-mostly state shared by many threads, such as configuration: `load` clones the
+/// current `Arc`, which the reader keeps for as long as it likes, and a writer replaces it as a
+/// whole, so that readers never see a mix of the old and new versions.
+///
+/// The cell holds a box of the `Arc`, which a reader protects while it increments the count. The
+/// box is retired when it is replaced, and frees its count once no reader protects it.
+///
+/// A retired box may be freed later by another thread, after the cell and any borrow it holds are
+/// gone, so the cell is only used with values that are `Send + Sync + 'static`.
+pub struct AtomicArc<T, R = Hp> {
+    /// Never null.
+    ptr: AtomicPtr<Arc<T>>,
+    _marker: PhantomData<(Arc<T>, R)>,
+}
+
+// The `Arc`s are cloned and dropped by any thread.
+unsafe impl<T: Send + Sync, R> Send for AtomicArc<T, R> {}
+unsafe impl<T: Send + Sync, R> Sync for AtomicArc<T, R> {}
+
+impl<T, R> AtomicArc<T, R> {
+    /// Creates a cell holding `arc`.
+    pub fn new(arc: Arc<T>) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(arc))),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the `Arc` held by the cell.
+    pub fn into_inner(self) -> Arc<T> {
+        let ptr = self.ptr.load(Ordering::Relaxed);
+        core::mem::forget(self);
+        // SAFETY: No other thread accesses the cell, and its box is not retired.
+        *unsafe { Box::from_raw(ptr) }
+    }
+}
+
+impl<T: Send + Sync + 'static, R: Reclaimer> AtomicArc<T, R> {
+    /// Returns the current `Arc`.
+    pub fn load(&self) -> Arc<T> {
+        let shield = R::Shield::default();
+        let ptr = shield.protect(&self.ptr);
+        // SAFETY: `ptr` is protected, so the box and its count are not freed while `shield` is
+        // set.
+        unsafe { (*ptr).clone() }
+    }
+
+    /// Replaces the `Arc` with `new`.
+    pub fn store(&self, new: Arc<T>) {
+        drop(self.swap(new));
+    }
+
+    /// Replaces the `Arc` with `new`, and returns the previous one.
+    pub fn swap(&self, new: Arc<T>) -> Arc<T> {
+        let new = Box::into_raw(Box::new(new));
+        // Release: the box is initialized before it is published. Acquire: so is the previous one.
+        let old = self.ptr.swap(new, Ordering::AcqRel);
+        // SAFETY: `old` is detached by the swap above, so only this thread retires it, and other
+        // threads only clone its `Arc` while they protect it.
+        unsafe { Self::take(old) }
+    }
+
+    /// Replaces the `Arc` with `new` if it points to the same value as `current`. Returns the
+    /// previous `Arc` if it is replaced, and otherwise the current one, dropping `new`.
+    pub fn compare_and_swap(&self, current: &Arc<T>, new: Arc<T>) -> Result<Arc<T>, Arc<T>> {
+        let shield = R::Shield::default();
+        let new = Box::into_raw(Box::new(new));
+        let backoff = Backoff::new();
+        loop {
+            let ptr = shield.protect(&self.ptr);
+            // SAFETY: `ptr` is protected, so the box is not freed while `shield` is set.
+            let actual = unsafe { &*ptr };
+            if !Arc::ptr_eq(actual, current) {
+                let actual = actual.clone();
+                // SAFETY: `new` was not published.
+                drop(unsafe { Box::from_raw(new) });
+                return Err(actual);
+            }
+
+            // ABA is impossible: `ptr` is protected, so it is not reused until the shield is
+            // cleared. Release: the box is initialized before it is published.
+            if self
+                .ptr
+                .compare_exchange(ptr, new, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                shield.clear();
+                // SAFETY: `ptr` is detached by the CAS above, so only this thread retires it.
+                return Ok(unsafe { Self::take(ptr) });
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Replaces the `Arc` with `f` applied to it, retrying if another thread replaces it
+    /// meanwhile, and returns the previous one. `f` may be called more than once.
+    pub fn update<F: FnMut(&Arc<T>) -> Arc<T>>(&self, mut f: F) -> Arc<T> {
+        let mut current = self.load();
+        loop {
+            match self.compare_and_swap(&current, f(&current)) {
+                Ok(previous) => return previous,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Returns the `Arc` of the detached box `ptr`, and retires the box.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be removed from the cell by this thread.
+    unsafe fn take(ptr: *mut Arc<T>) -> Arc<T> {
+        let arc = (*ptr).clone();
+        R::retire(ptr);
+        arc
+    }
+}
+
+impl<T, R> Drop for AtomicArc<T, R> {
+    fn drop(&mut self) {
+        // SAFETY: No other thread accesses the cell, and its box is not retired.
+        drop(unsafe { Box::from_raw(self.ptr.load(Ordering::Relaxed)) });
+    }
+}
+
+impl<T: Default, R> Default for AtomicArc<T, R> {
+    fn default() -> Self {
+        Self::new(Arc::default())
+    }
+}
+
+impl<T, R> From<Arc<T>> for AtomicArc<T, R> {
+    fn from(arc: Arc<T>) -> Self {
+        Self::new(arc)
+    }
+}
+
+impl<T: fmt::Debug + Send + Sync + 'static, R: Reclaimer> fmt::Debug for AtomicArc<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicArc").field(&self.load()).finish()
+    }
+}
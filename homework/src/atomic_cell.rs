@@ -0,0 +1,384 @@
+//! Atomic cell for small plain values.
+//!
+//! A value of the size of a native atomic, and aligned as it, is accessed through the atomic in
+//! place. Other values are guarded by sequence locks, shared by all cells and picked by the
+//! address of the cell: a reader copies the value out and retries if a writer raced with it, as in
+//! [`SeqLock`](crate::seqlock::SeqLock), so readers never block.
+//!
+//! Either way, the value is copied as integers, so the cells hold only [`Plain`] values, whose
+//! bytes are all initialized. Padding, as in `(u64, u32)`, or the bytes a variant leaves out, as
+//! the `Duration` of `None` in `Option<Duration>`, would be read as integers uninitialized.
+//!
+//! The atomics are those of `core` even with `check-loom`, as the value is accessed through them
+//! in place.
+
+use arr_macro::arr;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::{self, MaybeUninit};
+use core::num::{NonZeroU128, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize};
+use core::slice;
+use core::sync::atomic::{fence, AtomicU16, AtomicU32, AtomicU8, AtomicUsize, Ordering};
+
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::AtomicU64;
+
+use crate::backoff::Backoff;
+use crate::CachePadded;
+
+/// Number of the sequence locks, a power of two.
+const LOCKS: usize = 64;
+
+/// Sequence locks of the cells without a native atomic. Odd while a writer holds the lock, and
+/// incremented by 2 on each write.
+static SEQS: [CachePadded<AtomicUsize>; LOCKS] = arr![CachePadded::new(AtomicUsize::new(0)); 64];
+
+/// Values whose bytes are all initialized, so that they can be copied as integers.
+///
+/// # Safety
+///
+/// No value of the type has padding or other uninitialized bytes.
+pub unsafe trait Plain: Copy {}
+
+macro_rules! plain {
+    ($($ty:ty),*) => {
+        $(unsafe impl Plain for $ty {})*
+    };
+}
+
+plain!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+plain!(bool, char, f32, f64, ());
+plain!(
+    NonZeroU8,
+    NonZeroU16,
+    NonZeroU32,
+    NonZeroU64,
+    NonZeroU128,
+    NonZeroUsize
+);
+plain!(
+    Option<NonZeroU8>,
+    Option<NonZeroU16>,
+    Option<NonZeroU32>,
+    Option<NonZeroU64>,
+    Option<NonZeroU128>,
+    Option<NonZeroUsize>
+);
+
+// The elements of an array are laid out without padding between them.
+unsafe impl<T: Plain, const N: usize> Plain for [T; N] {}
+
+/// Cell whose value is loaded and stored atomically.
+#[repr(transparent)]
+pub struct AtomicCell<T> {
+    value: UnsafeCell<T>,
+}
+
+// The values are copied between threads.
+unsafe impl<T: Send> Send for AtomicCell<T> {}
+unsafe impl<T: Send> Sync for AtomicCell<T> {}
+
+/// Returns whether a `T` can be accessed as an `A`.
+const fn fits<T, A>() -> bool {
+    mem::size_of::<T>() == mem::size_of::<A>() && mem::align_of::<T>() >= mem::align_of::<A>()
+}
+
+/// Runs `$native` with `$atomic` bound to the native atomic of `$cell`, and `Int` to its integer
+/// type, if `T` fits one. Otherwise, runs `$fallback`.
+macro_rules! atomic {
+    ($cell:expr, $atomic:ident, $native:expr, $fallback:expr) => {
+        loop {
+            atomic!(@try $cell, $atomic, u8, AtomicU8, $native);
+            atomic!(@try $cell, $atomic, u16, AtomicU16, $native);
+            atomic!(@try $cell, $atomic, u32, AtomicU32, $native);
+            #[cfg(target_has_atomic = "64")]
+            atomic!(@try $cell, $atomic, u64, AtomicU64, $native);
+            break $fallback;
+        }
+    };
+    (@try $cell:expr, $atomic:ident, $int:ty, $ty:ty, $native:expr) => {
+        if fits::<T, $ty>() {
+            #[allow(dead_code)]
+            type Int = $int;
+            // SAFETY: The value has the size of the atomic and is aligned as it, and is accessed
+            // only through atomics.
+            let $atomic = unsafe { &*($cell.value.get() as *const $ty) };
+            break $native;
+        }
+    };
+}
+
+/// Returns the bits of `t` as `I`, of the same size.
+fn to_int<T: Plain, I>(t: T) -> I {
+    // SAFETY: `I` is an integer of the size of `T`, whose bytes are all initialized.
+    unsafe { mem::transmute_copy(&t) }
+}
+
+/// Returns the value of `T` whose bits are `i`, of the same size.
+fn from_int<I, T>(i: I) -> T {
+    // SAFETY: The bits of `i` are those of a `T`.
+    unsafe { mem::transmute_copy(&i) }
+}
+
+/// Atomic in whose units the values without a native atomic are copied.
+trait Unit {
+    type Int;
+
+    fn load(&self) -> Self::Int;
+    fn store(&self, int: Self::Int);
+}
+
+macro_rules! unit {
+    ($ty:ty, $int:ty) => {
+        impl Unit for $ty {
+            type Int = $int;
+
+            fn load(&self) -> $int {
+                <$ty>::load(self, Ordering::Relaxed)
+            }
+
+            fn store(&self, int: $int) {
+                <$ty>::store(self, int, Ordering::Relaxed)
+            }
+        }
+    };
+}
+
+unit!(AtomicU8, u8);
+unit!(AtomicU16, u16);
+unit!(AtomicU32, u32);
+unit!(AtomicUsize, usize);
+
+/// Copies the value at `src` out in units of `A`.
+///
+/// # Safety
+///
+/// `src` must be valid for reads of a `T`, aligned as `A`, and accessed only through `A`s.
+unsafe fn load_units<T: Plain, A: Unit>(src: *const T) -> MaybeUninit<T> {
+    let mut t = MaybeUninit::<T>::uninit();
+    let dst = t.as_mut_ptr() as *mut A::Int;
+    for i in 0..mem::size_of::<T>() / mem::size_of::<A>() {
+        dst.add(i).write((*(src as *const A).add(i)).load());
+    }
+    t
+}
+
+/// Copies `t` to `dst` in units of `A`.
+///
+/// # Safety
+///
+/// `dst` must be valid for writes of a `T`, aligned as `A`, and accessed only through `A`s.
+unsafe fn store_units<T: Plain, A: Unit>(dst: *const T, t: &T) {
+    // The bytes of `t` are all initialized, so they are read as integers.
+    let src = t as *const T as *const A::Int;
+    for i in 0..mem::size_of::<T>() / mem::size_of::<A>() {
+        (*(dst as *const A).add(i)).store(src.add(i).read());
+    }
+}
+
+/// Returns `true` if `a` and `b` have the same bits.
+fn same_bits<T: Plain>(a: &T, b: &T) -> bool {
+    let len = mem::size_of::<T>();
+    // SAFETY: The bytes of a `Plain` value are all initialized.
+    unsafe {
+        slice::from_raw_parts(a as *const T as *const u8, len)
+            == slice::from_raw_parts(b as *const T as *const u8, len)
+    }
+}
+
+impl<T> AtomicCell<T> {
+    /// Creates a cell holding `value`.
+    pub const fn new(value: T) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Returns the value.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// Returns `true` if the values of `T` are accessed through a native atomic, and `false` if
+    /// they are guarded by a lock.
+    pub const fn is_lock_free() -> bool {
+        #[cfg(target_has_atomic = "64")]
+        if fits::<T, AtomicU64>() {
+            return true;
+        }
+        fits::<T, AtomicU8>() || fits::<T, AtomicU16>() || fits::<T, AtomicU32>()
+    }
+
+    /// Returns the sequence lock of this cell.
+    fn seq(&self) -> &'static AtomicUsize {
+        let addr = self as *const Self as usize;
+        // Fibonacci hashing, as the cells are aligned.
+        &SEQS[(addr.wrapping_mul(0x9E37_79B9) >> 16) & (LOCKS - 1)]
+    }
+}
+
+impl<T: Plain> AtomicCell<T> {
+    /// Copies the value out in units as large as its alignment, without validating the copy.
+    fn load_raw(&self) -> MaybeUninit<T> {
+        let src = self.value.get() as *const T;
+        // SAFETY: The value is aligned as the units, and accessed only through them.
+        unsafe {
+            match mem::align_of::<T>().min(mem::size_of::<usize>()) {
+                1 => load_units::<T, AtomicU8>(src),
+                2 => load_units::<T, AtomicU16>(src),
+                4 => load_units::<T, AtomicU32>(src),
+                _ => load_units::<T, AtomicUsize>(src),
+            }
+        }
+    }
+
+    /// Copies `value` in, as `load_raw` copies it out.
+    fn store_raw(&self, value: T) {
+        let dst = self.value.get() as *const T;
+        // SAFETY: The value is aligned as the units, and accessed only through them.
+        unsafe {
+            match mem::align_of::<T>().min(mem::size_of::<usize>()) {
+                1 => store_units::<T, AtomicU8>(dst, &value),
+                2 => store_units::<T, AtomicU16>(dst, &value),
+                4 => store_units::<T, AtomicU32>(dst, &value),
+                _ => store_units::<T, AtomicUsize>(dst, &value),
+            }
+        }
+    }
+
+    /// Reads the value under the sequence lock, retrying while writes race with the read.
+    fn read(&self) -> T {
+        let seq = self.seq();
+        let backoff = Backoff::new();
+        loop {
+            // Acquire: the previous write happens before the copy.
+            let before = seq.load(Ordering::Acquire);
+            if before & 1 == 0 {
+                let value = self.load_raw();
+                // Acquire: the copy happens before the sequence number is checked again.
+                fence(Ordering::Acquire);
+                if seq.load(Ordering::Relaxed) == before {
+                    // SAFETY: No write raced with the copy.
+                    return unsafe { value.assume_init() };
+                }
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Replaces the value with `value` under the sequence lock, unless `current` is given and the
+    /// value has other bits. Returns the previous value, and whether it was replaced.
+    ///
+    /// No code of the caller runs under the lock, which is shared with other cells, so the lock is
+    /// never left held by a panic, nor taken again by the same thread.
+    fn write(&self, current: Option<T>, value: T) -> (T, bool) {
+        let seq = self.seq();
+        let backoff = Backoff::new();
+        let before = loop {
+            let before = seq.load(Ordering::Relaxed);
+            if before & 1 == 0
+                && seq
+                    .compare_exchange(
+                        before,
+                        before.wrapping_add(1),
+                        Ordering::Acquire,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+            {
+                // Release: readers that see the writes below see the odd sequence number as well.
+                fence(Ordering::Release);
+                break before;
+            }
+            backoff.snooze();
+        };
+        // SAFETY: Only this thread writes the value while holding the lock.
+        let prev = unsafe { self.load_raw().assume_init() };
+        let replace = current.map_or(true, |current| same_bits(&prev, &current));
+        if replace {
+            self.store_raw(value);
+        }
+        // Release: the writes happen before the next readers' copies. The sequence number is
+        // advanced even without a write, as it cannot go back to `before` once it is odd.
+        seq.store(before.wrapping_add(2), Ordering::Release);
+        (prev, replace)
+    }
+
+    /// Returns the value.
+    pub fn load(&self) -> T {
+        atomic!(self, a, from_int(a.load(Ordering::Acquire)), self.read())
+    }
+
+    /// Replaces the value with `value`.
+    pub fn store(&self, value: T) {
+        atomic!(self, a, a.store(to_int(value), Ordering::Release), {
+            let _ = self.write(None, value);
+        })
+    }
+
+    /// Replaces the value with `value`, and returns the previous one.
+    pub fn swap(&self, value: T) -> T {
+        atomic!(
+            self,
+            a,
+            from_int(a.swap(to_int(value), Ordering::AcqRel)),
+            self.write(None, value).0
+        )
+    }
+
+    /// Replaces the value with `f` applied to it, retrying if another thread changes it
+    /// meanwhile, until `f` returns `None`. Returns the previous value, in `Ok` if it was
+    /// replaced.
+    pub fn fetch_update<F: FnMut(T) -> Option<T>>(&self, mut f: F) -> Result<T, T> {
+        atomic!(
+            self,
+            a,
+            a.fetch_update(Ordering::AcqRel, Ordering::Acquire, |int| {
+                f(from_int(int)).map(to_int::<T, Int>)
+            })
+            .map(from_int)
+            .map_err(from_int),
+            {
+                // `f` runs on a copy without the lock, and its result is stored only if the value
+                // is still the copy, as with the native atomics.
+                let backoff = Backoff::new();
+                let mut prev = self.read();
+                loop {
+                    let next = some_or!(f(prev), break Err(prev));
+                    match self.write(Some(prev), next) {
+                        (_, true) => break Ok(prev),
+                        (value, false) => prev = value,
+                    }
+                    backoff.spin();
+                }
+            }
+        )
+    }
+}
+
+impl<T: Plain + Eq> AtomicCell<T> {
+    /// Replaces the value with `new` if it is equal to `current`. Returns the previous value, in
+    /// `Ok` if it was replaced.
+    pub fn compare_exchange(&self, current: T, new: T) -> Result<T, T> {
+        // Compares with `Eq` rather than the bits, which may differ between equal values.
+        self.fetch_update(|prev| (prev == current).then_some(new))
+    }
+}
+
+impl<T: Default> Default for AtomicCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for AtomicCell<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Plain + fmt::Debug> fmt::Debug for AtomicCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicCell").field(&self.load()).finish()
+    }
+}
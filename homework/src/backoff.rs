@@ -0,0 +1,84 @@
+//! Exponential backoff for retry and wait loops.
+
+use core::cell::Cell;
+
+/// Steps up to which the backoff spins, doubling the spins on each step.
+const SPIN_LIMIT: u32 = 6;
+/// Steps up to which `snooze` backs off before the caller had better park.
+#[cfg(not(feature = "check-loom"))]
+const YIELD_LIMIT: u32 = 10;
+/// Loom explores every interleaving of the retries, so the caller parks after a single one.
+#[cfg(feature = "check-loom")]
+const YIELD_LIMIT: u32 = 0;
+
+/// Exponential backoff for a loop that retries an operation, or waits for another thread.
+///
+/// A loop retrying a CAS calls `spin` after each failure, which spins twice as long as the last
+/// time up to a limit, so that contending threads spread their retries out. A loop waiting for
+/// another thread to make progress calls `snooze`, which spins at first and then yields the CPU.
+/// Once `is_completed` returns `true`, the wait is long enough that the caller had better block,
+/// e.g., on a condition variable.
+///
+/// Under loom, both just yield to the model checker, which would otherwise explore the spinning
+/// forever, and likewise to the scheduler under `check-sched`, which would otherwise not run the
+/// thread waited for.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    step: Cell<u32>,
+}
+
+impl Backoff {
+    /// Creates a backoff at the first step.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts over from the first step, e.g., after the operation made progress.
+    pub fn reset(&self) {
+        self.step.set(0);
+    }
+
+    /// Backs off after losing a race to another thread, which has made progress. Never yields the
+    /// CPU.
+    pub fn spin(&self) {
+        let step = self.step.get();
+        #[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+        for _ in 0..1 << step.min(SPIN_LIMIT) {
+            core::hint::spin_loop();
+        }
+        #[cfg(feature = "check-loom")]
+        loom::thread::yield_now();
+        #[cfg(feature = "check-sched")]
+        crate::sched::thread::yield_now();
+
+        if step <= SPIN_LIMIT {
+            self.step.set(step + 1);
+        }
+    }
+
+    /// Backs off while waiting for another thread to make progress. Spins at first, and yields the
+    /// CPU once spinning is long enough.
+    pub fn snooze(&self) {
+        let step = self.step.get();
+        #[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+        if step <= SPIN_LIMIT {
+            for _ in 0..1 << step {
+                core::hint::spin_loop();
+            }
+        } else {
+            std::thread::yield_now();
+        }
+        #[cfg(feature = "check-loom")]
+        loom::thread::yield_now();
+        #[cfg(feature = "check-sched")]
+        crate::sched::thread::yield_now();
+
+        // Stops counting once the caller had better block.
+        self.step.set((step + 1).min(YIELD_LIMIT + 1));
+    }
+
+    /// Returns `true` if the caller has snoozed long enough that it had better block.
+    pub fn is_completed(&self) -> bool {
+        self.step.get() > YIELD_LIMIT
+    }
+}
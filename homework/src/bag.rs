@@ -0,0 +1,284 @@
+//! Lock-free unordered bag with per-thread segments, and an object pool on top of it.
+
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+// The hello server keeps its response buffers in a pool, so it is not modeled with loom.
+use core::sync::atomic::{AtomicPtr, Ordering};
+use std::num::NonZeroUsize;
+use std::thread;
+
+use crate::utils::stripe;
+
+/// Number of slots in a segment of `Bag::new`.
+const DEFAULT_SLOTS: usize = 32;
+
+/// Bounded lock-free bag, which holds boxed values in no particular order.
+///
+/// The slots are divided into segments, and each thread puts into and takes from the segment of
+/// its stripe first, so that threads mostly touch their own slots. A thread whose segment is full
+/// or empty moves on to the other segments, stealing the values put by other threads.
+///
+/// A value is put by a CAS of an empty slot to its box, and taken by swapping its slot with null,
+/// so the box is always owned by either the bag or a single thread, and no memory reclamation is
+/// needed.
+pub struct Bag<T> {
+    /// The segments, whose number is a power of two. A slot is null if empty.
+    segments: Box<[Box<[AtomicPtr<T>]>]>,
+}
+
+unsafe impl<T: Send> Send for Bag<T> {}
+unsafe impl<T: Send> Sync for Bag<T> {}
+
+impl<T> fmt::Debug for Bag<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bag")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Default for Bag<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Bag<T> {
+    /// Creates a bag with a segment of 32 slots for each hardware thread.
+    pub fn new() -> Self {
+        Self::with_segments(
+            thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            DEFAULT_SLOTS,
+        )
+    }
+
+    /// Creates a bag with `segments` segments, rounded up to a power of two, of `slots` slots
+    /// each. Panics if `slots` is 0.
+    pub fn with_segments(segments: usize, slots: usize) -> Self {
+        assert!(slots > 0);
+        Self {
+            segments: (0..segments.max(1).next_power_of_two())
+                .map(|_| {
+                    (0..slots)
+                        .map(|_| AtomicPtr::new(ptr::null_mut()))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the segments, starting from the one of the current thread.
+    fn segments(&self) -> impl Iterator<Item = &[AtomicPtr<T>]> {
+        let start = stripe(self.segments.len());
+        self.segments[start..]
+            .iter()
+            .chain(&self.segments[..start])
+            .map(|segment| &**segment)
+    }
+
+    /// Puts `value` into an empty slot. Returns it back if the bag is full.
+    pub fn put_box(&self, value: Box<T>) -> Result<(), Box<T>> {
+        let new = Box::into_raw(value);
+        for slot in self.segments().flatten() {
+            // Release: the value is written before it is put. Pairs with the Acquire in
+            // `take_box`.
+            if slot
+                .compare_exchange(ptr::null_mut(), new, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+        // SAFETY: `new` is not put into any slot, so this thread still owns it.
+        Err(unsafe { Box::from_raw(new) })
+    }
+
+    /// Takes a value out of a slot. Returns `None` if the bag is empty.
+    pub fn take_box(&self) -> Option<Box<T>> {
+        for slot in self.segments().flatten() {
+            // Skips the empty slots without taking their cache lines exclusively.
+            if slot.load(Ordering::Relaxed).is_null() {
+                continue;
+            }
+            // Acquire: pairs with the Release in `put_box`.
+            let value = slot.swap(ptr::null_mut(), Ordering::Acquire);
+            if !value.is_null() {
+                // SAFETY: The swap took the box out of the bag, so this thread is its only owner.
+                return Some(unsafe { Box::from_raw(value) });
+            }
+        }
+        None
+    }
+
+    /// Puts `value` into an empty slot. Returns it back if the bag is full.
+    pub fn put(&self, value: T) -> Result<(), T> {
+        self.put_box(Box::new(value)).map_err(|value| *value)
+    }
+
+    /// Takes a value out of a slot. Returns `None` if the bag is empty.
+    pub fn take(&self) -> Option<T> {
+        self.take_box().map(|value| *value)
+    }
+
+    /// Returns the number of values in the bag, which may be outdated by the time it returns.
+    pub fn len(&self) -> usize {
+        self.segments()
+            .flatten()
+            .filter(|slot| !slot.load(Ordering::Relaxed).is_null())
+            .count()
+    }
+
+    /// Returns `true` if the bag is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of values in the bag.
+    pub fn capacity(&self) -> usize {
+        self.segments.len() * self.segments[0].len()
+    }
+}
+
+impl<T> Drop for Bag<T> {
+    fn drop(&mut self) {
+        for slot in self
+            .segments
+            .iter_mut()
+            .flat_map(|segment| segment.iter_mut())
+        {
+            let value = *slot.get_mut();
+            if !value.is_null() {
+                // SAFETY: No other thread accesses the bag, and the values in it are owned by it.
+                drop(unsafe { Box::from_raw(value) });
+            }
+        }
+    }
+}
+
+type Make<T> = Box<dyn Fn() -> T + Send + Sync>;
+type Reset<T> = Box<dyn Fn(&mut T) + Send + Sync>;
+
+/// Pool of reusable objects, such as buffers, which are checked out and checked back in without a
+/// global lock.
+///
+/// A checked out object is returned to the pool when its guard is dropped, after it is reset. If
+/// the pool is full, it is dropped instead. If the pool is empty, a new object is made.
+pub struct Pool<T> {
+    bag: Bag<T>,
+    make: Make<T>,
+    reset: Reset<T>,
+}
+
+impl<T> fmt::Debug for Pool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("bag", &self.bag)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Pool<T> {
+    /// Creates a pool that makes new objects with `make`, and checks them in as they are.
+    pub fn new<M>(make: M) -> Self
+    where
+        M: Fn() -> T + Send + Sync + 'static,
+    {
+        Self::with_reset(make, |_| {})
+    }
+
+    /// Creates a pool that makes new objects with `make`, and resets the objects with `reset` when
+    /// they are checked in.
+    pub fn with_reset<M, R>(make: M, reset: R) -> Self
+    where
+        M: Fn() -> T + Send + Sync + 'static,
+        R: Fn(&mut T) + Send + Sync + 'static,
+    {
+        Self::with_bag(Bag::new(), make, reset)
+    }
+
+    /// Creates a pool that keeps the objects checked in in `bag`.
+    pub fn with_bag<M, R>(bag: Bag<T>, make: M, reset: R) -> Self
+    where
+        M: Fn() -> T + Send + Sync + 'static,
+        R: Fn(&mut T) + Send + Sync + 'static,
+    {
+        Self {
+            bag,
+            make: Box::new(make),
+            reset: Box::new(reset),
+        }
+    }
+
+    /// Checks out an object from the pool, or makes a new one if the pool is empty.
+    pub fn checkout(&self) -> Pooled<'_, T> {
+        let value = self
+            .bag
+            .take_box()
+            .unwrap_or_else(|| Box::new((self.make)()));
+        Pooled {
+            pool: self,
+            value: ManuallyDrop::new(value),
+        }
+    }
+
+    /// Returns the number of objects in the pool.
+    pub fn len(&self) -> usize {
+        self.bag.len()
+    }
+
+    /// Returns `true` if the pool is empty.
+    pub fn is_empty(&self) -> bool {
+        self.bag.is_empty()
+    }
+}
+
+/// An object checked out from a [`Pool`], which is checked back in when dropped.
+pub struct Pooled<'p, T> {
+    pool: &'p Pool<T>,
+    /// Taken out only when dropped.
+    value: ManuallyDrop<Box<T>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Pooled<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Pooled").field(&**self.value).finish()
+    }
+}
+
+impl<T> Pooled<'_, T> {
+    /// Takes the object out of the pool for good.
+    pub fn detach(mut this: Self) -> T {
+        // SAFETY: `value` is not used again, as `this` is forgotten.
+        let value = unsafe { ManuallyDrop::take(&mut this.value) };
+        core::mem::forget(this);
+        *value
+    }
+}
+
+impl<T> Deref for Pooled<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Pooled<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> Drop for Pooled<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: `value` is not used again, as it is being dropped.
+        let mut value = unsafe { ManuallyDrop::take(&mut self.value) };
+        (self.pool.reset)(&mut value);
+        // The box is reused, so that checking in does not allocate. It is dropped if the pool is
+        // full.
+        let _ = self.pool.bag.put_box(value);
+    }
+}
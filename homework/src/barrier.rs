@@ -0,0 +1,132 @@
+//! Reusable barrier and count-down latch.
+
+// The tests of the hello server wait on these outside of loom models, so they are not modeled
+// with loom.
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct BarrierState {
+    /// Number of threads that arrived in the current generation.
+    arrived: usize,
+    /// Incremented each time all threads arrive, releasing the ones waiting in that generation.
+    generation: u64,
+}
+
+/// Cyclic barrier, which blocks threads until a given number of them wait on it.
+///
+/// Once all of them arrive, the barrier releases them and starts over, so that the same barrier
+/// can synchronize the same threads repeatedly. The threads that wait in each round are told
+/// apart by the generation, which is advanced by the last one to arrive.
+#[derive(Debug)]
+pub struct Barrier {
+    state: Mutex<BarrierState>,
+    parties: usize,
+    released: Condvar,
+}
+
+impl Barrier {
+    /// Creates a barrier for `parties` threads. Panics if `parties` is 0.
+    pub fn new(parties: usize) -> Self {
+        assert!(parties > 0);
+        Self {
+            state: Mutex::new(BarrierState {
+                arrived: 0,
+                generation: 0,
+            }),
+            parties,
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until `parties` threads wait on the barrier, including this one.
+    ///
+    /// Returns `true` for the last thread to arrive in each round, and `false` for the others.
+    pub fn wait(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.arrived += 1;
+        if state.arrived == self.parties {
+            state.arrived = 0;
+            state.generation += 1;
+            drop(state);
+            self.released.notify_all();
+            return true;
+        }
+
+        let generation = state.generation;
+        while state.generation == generation {
+            state = self.released.wait(state).unwrap();
+        }
+        false
+    }
+
+    /// Returns the number of threads the barrier waits for.
+    pub fn parties(&self) -> usize {
+        self.parties
+    }
+}
+
+/// One-shot latch, which blocks threads until it is counted down to zero.
+///
+/// Unlike a [`Barrier`], the threads counting it down do not wait, and the threads waiting do not
+/// count it down. Once open, it stays open.
+#[derive(Debug)]
+pub struct CountDownLatch {
+    count: Mutex<usize>,
+    opened: Condvar,
+}
+
+impl CountDownLatch {
+    /// Creates a latch that opens after `count` calls to `count_down`.
+    pub fn new(count: usize) -> Self {
+        Self {
+            count: Mutex::new(count),
+            opened: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.count.lock().unwrap()
+    }
+
+    /// Decrements the count, opening the latch and waking up the waiting threads if it reaches
+    /// zero. Does nothing if the latch is already open.
+    pub fn count_down(&self) {
+        let mut count = self.lock();
+        if *count == 0 {
+            return;
+        }
+        *count -= 1;
+        if *count == 0 {
+            drop(count);
+            self.opened.notify_all();
+        }
+    }
+
+    /// Returns the number of `count_down` calls left until the latch opens.
+    pub fn count(&self) -> usize {
+        *self.lock()
+    }
+
+    /// Blocks until the latch opens.
+    pub fn wait(&self) {
+        let mut count = self.lock();
+        while *count > 0 {
+            count = self.opened.wait(count).unwrap();
+        }
+    }
+
+    /// Blocks until the latch opens for at most `timeout`. Returns `false` if it is still closed.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut count = self.lock();
+        while *count > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            count = self.opened.wait_timeout(count, deadline - now).unwrap().0;
+        }
+        true
+    }
+}
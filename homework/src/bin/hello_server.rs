@@ -1,30 +1,128 @@
-use crossbeam_channel::{bounded, unbounded};
-use cs431_homework::hello_server::{CancellableTcpListener, Handler, Statistics, ThreadPool};
+use cs431_homework::hazard_pointer::HAZARDS;
+use cs431_homework::hello_server::{
+    report_channel, AccessLog, Auth, CancellableTcpListener, Config, ConfigStore, ConnectionLimit,
+    Handler, ListenerOptions, Metrics, OverloadPolicy, ReportPolicy, Reporter, ThreadPool,
+};
+use cs431_homework::{metrics, oneshot};
+use log::{debug, info, warn, LevelFilter, Log, Metadata, Record};
+use signal_hook::consts::SIGHUP;
+use signal_hook::iterator::Signals;
+use std::env;
 use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-const ADDR: &str = "localhost:7878";
+/// Addresses to listen on. Those that cannot be bound (e.g., IPv6 on a host without it) are
+/// skipped.
+const ADDRS: [&str; 2] = ["127.0.0.1:7878", "[::1]:7878"];
+
+/// Environment variable for a port to listen on all interfaces instead of `ADDRS`, for both IPv4
+/// and IPv6 whichever are available (e.g., on an IPv6-only network).
+const PUBLIC_PORT_ENV: &str = "HELLO_SERVER_PUBLIC_PORT";
+
+/// Number of times to retry binding an address in use.
+const BIND_RETRIES: u32 = 5;
+
+/// Maximum number of simultaneously handled connections. Further connections get 503.
+const MAX_CONNECTIONS: usize = 64;
+
+/// Kept-alive connections idle for longer than this are closed.
+const KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Results are recomputed if they were computed longer ago than this, unless the configuration
+/// file says otherwise.
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Maximum number of reports waiting for the reporter.
+const REPORT_CAPACITY: usize = 1024;
+
+/// What the workers do with their reports when the reporter falls that far behind. Coalescing
+/// keeps the statistics exact without making the workers wait for the reporter.
+const REPORT_POLICY: ReportPolicy = ReportPolicy::Coalesce;
+
+/// Interval between interim statistics logged by the reporter.
+const STAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Environment variable for the maximum log level: `off`, `error`, `warn`, `info` (default),
+/// `debug`, or `trace`.
+const LOG_ENV: &str = "HELLO_SERVER_LOG";
+
+/// Environment variable for the path of the configuration file, which is reloaded on SIGHUP. See
+/// `Config` for its format.
+const CONFIG_ENV: &str = "HELLO_SERVER_CONFIG";
+
+/// Environment variable for the path of the access log. If unset, requests are not logged.
+const ACCESS_LOG_ENV: &str = "HELLO_SERVER_ACCESS_LOG";
+
+/// The access log is rotated when it would exceed this size.
+const ACCESS_LOG_MAX_LEN: u64 = 16 * 1024 * 1024;
+
+/// Number of rotated access logs to keep.
+const ACCESS_LOG_MAX_FILES: usize = 4;
+
+/// Environment variable for `user:password` required in Basic authentication for the admin routes
+/// (`/metrics`, `/cache` and `/config`). If unset, they are open to anyone.
+const AUTH_ENV: &str = "HELLO_SERVER_AUTH";
+
+/// Realm of the authentication for the admin routes.
+const AUTH_REALM: &str = "hello_server";
+
+/// Environment variable for the number of seconds after which the server shuts down by itself,
+/// e.g., for time-boxed benchmarks. If unset, the server runs until Ctrl-C.
+const RUN_SECS_ENV: &str = "HELLO_SERVER_RUN_SECS";
+
+/// Environment variables for the paths to write the final statistics to in CSV and JSON.
+const STATS_CSV_ENV: &str = "HELLO_SERVER_STATS_CSV";
+const STATS_JSON_ENV: &str = "HELLO_SERVER_STATS_JSON";
+
+/// Minimal logger that prints each record to stderr.
+struct SimpleLogger;
+
+impl Log for SimpleLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{} {}] {}", record.level(), record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
 
 fn main() -> io::Result<()> {
+    static LOGGER: SimpleLogger = SimpleLogger;
+    let level = env::var(LOG_ENV)
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::Info);
+    log::set_logger(&LOGGER).expect("Error setting logger");
+    log::set_max_level(level);
+
     // Use a browser that doesn't cache too eagerly so that request is always sent. For example,
     // Firefox works well.  If you want to test using command line only, use curl. If you want to
     // run it on the lab server, you may need to change the port number to something else.
-    println!(
-        "Run `curl http://{}/KEY` to query the server with KEY",
-        ADDR
-    );
+    info!("Run `curl http://localhost:7878/KEY` to query the server with KEY");
 
     // The thread pool.
     //
     // In the thread pool, we'll execute:
     //
-    // - A listener: it accepts incoming connections, and creates a new worker for each connection.
+    // - Listeners (one for each address): each accepts incoming connections, and creates a new
+    //   worker for each connection.
     //      Connection에 반응하는 애들, Workers를 만들어준다.
     //
     // - Workers (once for each incoming connection): a worker handles an incoming connection and
     //   sends a corresponding report to the reporter.
     //      Connection 하나에 하나씩 만들어짐. 리포터에게 알맞은 반응을 보내준다.
     //
+    // - A reaper: it closes kept-alive connections that are idle for too long, so that idle
+    //   clients do not occupy the workers.
+    //
     // - A reporter: it aggregates the reports from the workers and processes the
     //   statistics.  When it ends, it sends the statistics to the main thread.
     //       리포트를 만든다. Worksers 로 부터 답변을 받고 뭔가 메인 쓰레드에 돌려준다.
@@ -32,55 +130,194 @@ fn main() -> io::Result<()> {
     let pool = Arc::new(ThreadPool::new(7));
 
     // The (MPSC) channel of reports between workers and the reporter.
-    let (report_sender, report_receiver) = unbounded();
+    let (report_sender, report_receiver) = report_channel(REPORT_CAPACITY, REPORT_POLICY);
 
     // The (SPSC one-shot) channel of stats between the reporter and the main thread.
-    let (stat_sender, stat_receiver) = bounded(0);
+    let (stat_sender, stat_receiver) = oneshot::channel();
 
-    // Listens to the address.
-    let listener = Arc::new(CancellableTcpListener::bind(ADDR)?);
+    // Listens to the addresses. If the port is still held, e.g., by the previous run that has
+    // just exited, retries for a while.
+    let listener_options = ListenerOptions {
+        bind_retries: BIND_RETRIES,
+        ..ListenerOptions::default()
+    };
+    let mut listeners = Vec::new();
+    if let Some(port) = env::var_os(PUBLIC_PORT_ENV) {
+        let port = port
+            .to_str()
+            .and_then(|port| port.parse().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid {PUBLIC_PORT_ENV}"),
+                )
+            })?;
+        for listener in CancellableTcpListener::bind_dual_stack(port, &listener_options)? {
+            info!("Listening on {}", listener.local_addr()?);
+            listeners.push(Arc::new(listener));
+        }
+    } else {
+        for addr in ADDRS {
+            match CancellableTcpListener::bind_with(addr, &listener_options) {
+                Ok(listener) => {
+                    info!("Listening on {}", listener.local_addr()?);
+                    listeners.push(Arc::new(listener));
+                }
+                Err(err) => warn!("Cannot listen on {addr}: {err}"),
+            }
+        }
+    }
+    if listeners.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            "cannot listen on any address",
+        ));
+    }
 
     // Installs a Ctrl-C handler.
-    let ctrlc_listner_handle = listener.clone();
+    let ctrlc_listener_handles = listeners.clone();
     ctrlc::set_handler(move || {
-        ctrlc_listner_handle.cancel().unwrap();
+        for listener in &ctrlc_listener_handles {
+            listener.cancel().unwrap();
+        }
     })
     .expect("Error setting Ctrl-C handler");
 
-    // Executes the listener.
-    let listener_pool = pool.clone();
-    pool.execute(move || {
-        // Creates the request handler.
-        let handler = Handler::default();
-
-        // For each incoming connection...
-        for (id, stream) in listener.incoming().enumerate() {
-            // send a job to the thread pool.
-            let report_sender = report_sender.clone();
-            let handler = handler.clone();
-            listener_pool.execute(move || {
-                let report = handler.handle_conn(id, stream.unwrap());
-                report_sender.send(report).unwrap();
-            });
+    // Schedules the shutdown.
+    if let Some(secs) = env::var(RUN_SECS_ENV)
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+    {
+        info!("Shutting down after {secs} seconds");
+        for listener in &listeners {
+            listener.cancel_after(Duration::from_secs_f64(secs))?;
         }
+    }
+
+    // The metrics served at `/metrics`, which are those of the whole process.
+    let registry = metrics::global();
+    pool.register_metrics(registry, "main");
+    HAZARDS.register_metrics(registry, "global");
+    let metrics = Arc::new(Metrics::with_registry(registry));
+
+    // The tunables, read by the workers for each request and replaced as a whole on reload.
+    let config = Arc::new(match env::var_os(CONFIG_ENV) {
+        Some(path) => ConfigStore::open(path)?,
+        None => ConfigStore::new(Config {
+            cache_ttl: Some(CACHE_TTL),
+            ..Config::default()
+        }),
     });
 
+    // The request handler, the connection limit, and the connection ids are shared by the
+    // listeners.
+    let mut handler = Handler::default()
+        .with_metrics(metrics)
+        .with_pool(&pool)
+        .with_keep_alive(KEEP_ALIVE_TIMEOUT, &pool)
+        .with_config_store(config.clone());
+    if let Some(path) = env::var_os(ACCESS_LOG_ENV) {
+        let access_log = AccessLog::open(path, ACCESS_LOG_MAX_LEN, ACCESS_LOG_MAX_FILES)?;
+        handler = handler.with_access_log(access_log);
+    }
+    if let Ok(credentials) = env::var(AUTH_ENV) {
+        let (user, password) = credentials.split_once(':').ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("invalid {AUTH_ENV}"))
+        })?;
+        handler = handler.with_auth(Auth::new(AUTH_REALM).with_basic(user, password));
+    }
+
+    // Reloads the configuration file on SIGHUP. This runs outside the pool, since it does not end
+    // by itself.
+    let mut signals = Signals::new([SIGHUP])?;
+    let signals_handle = signals.handle();
+    let _ = thread::spawn(move || {
+        for _ in signals.forever() {
+            match config.reload() {
+                Ok(version) => info!("Reloaded configuration: {version:?}"),
+                Err(err) => match config.path() {
+                    Some(path) => warn!("Cannot reload {}: {err}", path.display()),
+                    None => warn!("No configuration file to reload; set {CONFIG_ENV}"),
+                },
+            }
+        }
+    });
+    let limit = Arc::new(ConnectionLimit::new(
+        MAX_CONNECTIONS,
+        OverloadPolicy::Reject,
+    ));
+    let next_id = Arc::new(AtomicUsize::new(0));
+
+    // Executes the listeners.
+    for listener in listeners.clone() {
+        let listener_pool = pool.clone();
+        let report_sender = report_sender.clone();
+        let handler = handler.clone();
+        let limit = limit.clone();
+        let next_id = next_id.clone();
+        pool.execute(move || {
+            // For each incoming connection...
+            for stream in listener.incoming() {
+                // send a job to the thread pool.
+                let id = next_id.fetch_add(1, Ordering::Relaxed);
+                let report_sender = report_sender.clone();
+                let handler = handler.clone();
+                let permit = limit.acquire();
+                let pending = listener.track();
+                listener_pool.execute(move || {
+                    let _pending = pending;
+                    let reports = match permit {
+                        Some(_permit) => handler.handle_conn(id, stream.unwrap()),
+                        None => vec![handler.reject_conn(id, stream.unwrap())],
+                    };
+                    for report in reports {
+                        report_sender.send(report).unwrap();
+                    }
+                });
+            }
+            let status = listener.status();
+            info!(
+                "Stopped accepting on {}; {} of {} connections are still being handled",
+                listener.local_addr().unwrap(),
+                status.pending,
+                status.accepted,
+            );
+        });
+    }
+    // The reporter finishes when all listeners and workers drop their senders.
+    drop(report_sender);
+
     // Executes the reporter.
     pool.execute(move || {
-        let mut stats = Statistics::default();
-        for report in report_receiver {
-            println!("[report] {report:?}");
-            stats.add_report(report);
-        }
+        let stats = Reporter::new()
+            .with_interval(STAT_INTERVAL)
+            .run(report_receiver);
 
-        println!("[sending stat]");
+        debug!("[sending stat]");
         stat_sender.send(stats).unwrap();
-        println!("[sent stat]");
+        debug!("[sent stat]");
     });
 
     // Blocks until the reporter sends the statistics.
     let stat = stat_receiver.recv().unwrap();
-    println!("[stat] {stat:?}");
+    signals_handle.close();
+    info!("[stat] {stat:?}");
+    for listener in &listeners {
+        let status = listener.status();
+        info!(
+            "[shutdown] {}: stopped: {}, accepted: {}, unfinished: {}",
+            listener.local_addr()?,
+            status.is_stopped(),
+            status.accepted,
+            status.pending,
+        );
+    }
+    if let Some(path) = env::var_os(STATS_CSV_ENV) {
+        stat.write_csv(path)?;
+    }
+    if let Some(path) = env::var_os(STATS_JSON_ENV) {
+        stat.write_json(path)?;
+    }
 
     Ok(())
     // When the pool is dropped, all worker threads are joined.
@@ -0,0 +1,399 @@
+//! Concurrent bitsets, e.g., to keep track of the slots in use.
+
+use core::fmt;
+use core::ptr;
+use core::slice;
+use std::vec;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+/// Number of the bits in a word.
+const BITS: usize = usize::BITS as usize;
+
+/// Log of the number of the words in the first segment of a `GrowableBitSet`.
+const FIRST_SEGMENT_LOG: u32 = 2;
+/// Number of the segments, each twice as large as the one before, which cover all bit indices.
+const SEGMENTS: usize = (usize::BITS - FIRST_SEGMENT_LOG - BITS.trailing_zeros() + 1) as usize;
+
+/// Returns the word of bit `index`, and the mask of the bit in it.
+fn locate(index: usize) -> (usize, usize) {
+    (index / BITS, 1 << (index % BITS))
+}
+
+/// Sets the lowest bit of `word` that is clear and in `valid`, and returns its position in the
+/// word. Returns `None` if there is none.
+fn set_first_clear_in(word: &AtomicUsize, valid: usize) -> Option<usize> {
+    let mut current = word.load(Ordering::Relaxed);
+    loop {
+        let clear = !current & valid;
+        if clear == 0 {
+            return None;
+        }
+        let bit = clear.trailing_zeros() as usize;
+        // AcqRel: see `AtomicBitSet::test_and_set`.
+        match word.compare_exchange(
+            current,
+            current | 1 << bit,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => return Some(bit),
+            Err(next) => current = next,
+        }
+    }
+}
+
+/// Fixed-size set of bits, each of which can be set and cleared concurrently.
+///
+/// The operations on a bit are atomic, and are ordered as on a lock: setting a bit that was clear
+/// acquires what was released by clearing it before. The operations on different bits are not
+/// atomic with each other, so `count` and `iter` may see some changes made while they run but not
+/// others.
+pub struct AtomicBitSet {
+    words: Box<[AtomicUsize]>,
+    /// Number of the bits. The bits of the last word from here on are always clear.
+    len: usize,
+}
+
+impl fmt::Debug for AtomicBitSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl AtomicBitSet {
+    /// Creates a set of `len` bits, all of them clear.
+    pub fn new(len: usize) -> Self {
+        // The word of the last bit, which may be partly used, is the last one.
+        let (words, _) = locate(len + BITS - 1);
+        Self {
+            words: (0..words).map(|_| AtomicUsize::new(0)).collect(),
+            len,
+        }
+    }
+
+    /// Returns the number of the bits.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the set has no bits at all.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn word(&self, index: usize) -> (&AtomicUsize, usize) {
+        assert!(index < self.len, "bit {index} is out of bounds");
+        let (word, mask) = locate(index);
+        (&self.words[word], mask)
+    }
+
+    /// Returns `true` if bit `index` is set. Panics if `index` is out of bounds.
+    pub fn test(&self, index: usize) -> bool {
+        let (word, mask) = self.word(index);
+        word.load(Ordering::Acquire) & mask != 0
+    }
+
+    /// Sets bit `index`. Panics if `index` is out of bounds.
+    pub fn set(&self, index: usize) {
+        let _ = self.test_and_set(index);
+    }
+
+    /// Clears bit `index`. Panics if `index` is out of bounds.
+    pub fn clear(&self, index: usize) {
+        let _ = self.test_and_clear(index);
+    }
+
+    /// Sets bit `index`, and returns `true` if it was already set. Panics if `index` is out of
+    /// bounds.
+    pub fn test_and_set(&self, index: usize) -> bool {
+        let (word, mask) = self.word(index);
+        // AcqRel: the thread that sets a bit sees what the thread that cleared it did before.
+        word.fetch_or(mask, Ordering::AcqRel) & mask != 0
+    }
+
+    /// Clears bit `index`, and returns `true` if it was set. Panics if `index` is out of bounds.
+    pub fn test_and_clear(&self, index: usize) -> bool {
+        let (word, mask) = self.word(index);
+        // AcqRel: see `test_and_set`.
+        word.fetch_and(!mask, Ordering::AcqRel) & mask != 0
+    }
+
+    /// Sets the lowest bit that is clear, and returns its index. Returns `None` if all bits are
+    /// set.
+    pub fn set_first_clear(&self) -> Option<usize> {
+        for (i, word) in self.words.iter().enumerate() {
+            let valid = match self.len - i * BITS {
+                rest if rest < BITS => (1 << rest) - 1,
+                _ => !0,
+            };
+            if let Some(bit) = set_first_clear_in(word, valid) {
+                return Some(i * BITS + bit);
+            }
+        }
+        None
+    }
+
+    /// Returns the number of the set bits.
+    pub fn count(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.load(Ordering::Relaxed).count_ones() as usize)
+            .sum()
+    }
+
+    /// Clears all bits.
+    pub fn clear_all(&self) {
+        for word in self.words.iter() {
+            word.store(0, Ordering::Release);
+        }
+    }
+
+    /// Iterates over the indices of the set bits, in increasing order. Each word is read once,
+    /// when the iterator reaches it.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(vec![(0, &self.words[..])])
+    }
+}
+
+/// Set of bits that grows as the bits are set, each of which can be set and cleared concurrently.
+///
+/// The bits are in segments, each twice as large as the one before, which are allocated as the
+/// bits in them are first set, and are never moved nor freed until the set is dropped. So the set
+/// grows without blocking the operations on the bits, which are as in [`AtomicBitSet`].
+pub struct GrowableBitSet {
+    /// Segment `i` is null or has `1 << (i + FIRST_SEGMENT_LOG)` words.
+    segments: [AtomicPtr<AtomicUsize>; SEGMENTS],
+}
+
+unsafe impl Send for GrowableBitSet {}
+unsafe impl Sync for GrowableBitSet {}
+
+impl fmt::Debug for GrowableBitSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Default for GrowableBitSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the segment of word `word`, and the offset of it in the segment.
+fn locate_word(word: usize) -> (usize, usize) {
+    let n = word + (1 << FIRST_SEGMENT_LOG);
+    let segment = usize::BITS - 1 - n.leading_zeros() - FIRST_SEGMENT_LOG;
+    (segment as usize, n - (1 << (segment + FIRST_SEGMENT_LOG)))
+}
+
+/// Returns the number of the words in segment `segment`.
+fn segment_len(segment: usize) -> usize {
+    1 << (segment as u32 + FIRST_SEGMENT_LOG)
+}
+
+/// Frees segment `segment` at `words`.
+///
+/// # Safety
+///
+/// `words` is allocated by `GrowableBitSet::alloc_segment` for segment `segment`, and no other
+/// thread accesses it.
+unsafe fn free_segment(words: *mut AtomicUsize, segment: usize) {
+    // SAFETY: Guaranteed by the caller. The segment has `segment_len(segment)` words.
+    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(words, segment_len(segment))) });
+}
+
+impl GrowableBitSet {
+    /// Creates a set whose bits are all clear, without allocating any segment.
+    pub fn new() -> Self {
+        Self {
+            segments: [(); SEGMENTS].map(|_| AtomicPtr::new(ptr::null_mut())),
+        }
+    }
+
+    /// Returns the words of segment `segment`, or `None` if it is not allocated.
+    fn segment(&self, segment: usize) -> Option<&[AtomicUsize]> {
+        // Acquire: the words are initialized before the segment is published. Pairs with the
+        // Release in `alloc_segment`.
+        let words = self.segments[segment].load(Ordering::Acquire);
+        if words.is_null() {
+            return None;
+        }
+        // SAFETY: The segment has `segment_len(segment)` words, and is not freed until the set
+        // is dropped.
+        Some(unsafe { slice::from_raw_parts(words, segment_len(segment)) })
+    }
+
+    /// Returns the words of segment `segment`, allocating it if no thread has.
+    fn alloc_segment(&self, segment: usize) -> &[AtomicUsize] {
+        if let Some(words) = self.segment(segment) {
+            return words;
+        }
+        let words = (0..segment_len(segment))
+            .map(|_| AtomicUsize::new(0))
+            .collect::<Box<[_]>>();
+        let words = Box::into_raw(words) as *mut AtomicUsize;
+        // Release: the words are initialized before the segment is published.
+        if self.segments[segment]
+            .compare_exchange(ptr::null_mut(), words, Ordering::Release, Ordering::Acquire)
+            .is_err()
+        {
+            // SAFETY: Another thread published its segment first, so `words` is never shared.
+            unsafe { free_segment(words, segment) };
+        }
+        self.segment(segment).unwrap()
+    }
+
+    /// Returns the word of bit `index` and the mask of the bit in it, or `None` if its segment is
+    /// not allocated.
+    fn word(&self, index: usize) -> Option<(&AtomicUsize, usize)> {
+        let (word, mask) = locate(index);
+        let (segment, offset) = locate_word(word);
+        Some((&self.segment(segment)?[offset], mask))
+    }
+
+    /// Returns the word of bit `index` and the mask of the bit in it, allocating its segment.
+    fn alloc_word(&self, index: usize) -> (&AtomicUsize, usize) {
+        let (word, mask) = locate(index);
+        let (segment, offset) = locate_word(word);
+        (&self.alloc_segment(segment)[offset], mask)
+    }
+
+    /// Returns `true` if bit `index` is set.
+    pub fn test(&self, index: usize) -> bool {
+        let (word, mask) = some_or!(self.word(index), return false);
+        word.load(Ordering::Acquire) & mask != 0
+    }
+
+    /// Sets bit `index`, growing the set if needed.
+    pub fn set(&self, index: usize) {
+        let _ = self.test_and_set(index);
+    }
+
+    /// Clears bit `index`.
+    pub fn clear(&self, index: usize) {
+        let _ = self.test_and_clear(index);
+    }
+
+    /// Sets bit `index`, growing the set if needed, and returns `true` if it was already set.
+    pub fn test_and_set(&self, index: usize) -> bool {
+        let (word, mask) = self.alloc_word(index);
+        // AcqRel: see `AtomicBitSet::test_and_set`.
+        word.fetch_or(mask, Ordering::AcqRel) & mask != 0
+    }
+
+    /// Clears bit `index`, and returns `true` if it was set.
+    pub fn test_and_clear(&self, index: usize) -> bool {
+        let (word, mask) = some_or!(self.word(index), return false);
+        // AcqRel: see `AtomicBitSet::test_and_set`.
+        word.fetch_and(!mask, Ordering::AcqRel) & mask != 0
+    }
+
+    /// Sets the lowest bit that is clear, growing the set if all bits in it are set, and returns
+    /// its index.
+    pub fn set_first_clear(&self) -> usize {
+        let mut base = 0;
+        for segment in 0..SEGMENTS {
+            let words = self.alloc_segment(segment);
+            for (i, word) in words.iter().enumerate() {
+                if let Some(bit) = set_first_clear_in(word, !0) {
+                    return base + i * BITS + bit;
+                }
+            }
+            base += words.len() * BITS;
+        }
+        panic!("all bits are set")
+    }
+
+    /// Returns the number of the set bits.
+    pub fn count(&self) -> usize {
+        self.segments()
+            .into_iter()
+            .flat_map(|(_, words)| words.iter())
+            .map(|word| word.load(Ordering::Relaxed).count_ones() as usize)
+            .sum()
+    }
+
+    /// Returns the allocated segments in order, with the index of the first bit of each.
+    fn segments(&self) -> Vec<(usize, &[AtomicUsize])> {
+        (0..SEGMENTS)
+            .filter_map(|segment| {
+                let words = self.segment(segment)?;
+                Some(((segment_len(segment) - segment_len(0)) * BITS, words))
+            })
+            .collect()
+    }
+
+    /// Iterates over the indices of the set bits, in increasing order. Each word is read once,
+    /// when the iterator reaches it, and the segments allocated after the iterator is created are
+    /// skipped.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter::new(self.segments())
+    }
+}
+
+impl Drop for GrowableBitSet {
+    fn drop(&mut self) {
+        for (segment, words) in self.segments.iter().enumerate() {
+            let words = words.load(Ordering::Relaxed);
+            if !words.is_null() {
+                // SAFETY: No other thread accesses the set.
+                unsafe { free_segment(words, segment) };
+            }
+        }
+    }
+}
+
+/// Iterator over the indices of the set bits of a bitset.
+#[derive(Debug)]
+pub struct Iter<'a> {
+    /// The segments of words left, with the index of the first bit of each.
+    segments: vec::IntoIter<(usize, &'a [AtomicUsize])>,
+    words: slice::Iter<'a, AtomicUsize>,
+    /// Index of the first bit of the current word.
+    base: usize,
+    /// Bits of the current word that are not yielded yet.
+    bits: usize,
+}
+
+impl<'a> Iter<'a> {
+    fn new(segments: Vec<(usize, &'a [AtomicUsize])>) -> Self {
+        Self {
+            segments: segments.into_iter(),
+            words: [].iter(),
+            base: 0,
+            bits: 0,
+        }
+    }
+}
+
+impl Iterator for Iter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.bits == 0 {
+            let word = match self.words.next() {
+                Some(word) => word,
+                None => {
+                    let (start, words) = self.segments.next()?;
+                    // The first word of the segment is right after the empty one.
+                    self.base = start.wrapping_sub(BITS);
+                    self.words = words.iter();
+                    continue;
+                }
+            };
+            self.base = self.base.wrapping_add(BITS);
+            // Acquire: see `AtomicBitSet::test`.
+            self.bits = word.load(Ordering::Acquire);
+        }
+        let bit = self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1;
+        Some(self.base + bit)
+    }
+}
@@ -0,0 +1,179 @@
+//! Bounded blocking queue with a mutex and two wait lists.
+
+use core::fmt;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::{Mutex, MutexGuard};
+#[cfg(feature = "check-loom")]
+use loom::sync::{Mutex, MutexGuard};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::{Mutex, MutexGuard};
+
+use crate::parker::WaitList;
+
+#[derive(Debug)]
+struct State<T> {
+    items: VecDeque<T>,
+    shutdown: bool,
+}
+
+/// Bounded FIFO queue whose `put` blocks while it is full, and whose `take` blocks while it is
+/// empty.
+///
+/// Almost the textbook monitor: a single mutex guards the items, and the threads blocked in `take`
+/// and in `put` park on their own wait lists, so that each change wakes up only the threads that
+/// can make progress after it. Unlike with condition variables, a parked thread does not take the
+/// mutex again until it is woken up. Unlike the lock-free queues, every operation contends for the
+/// mutex.
+///
+/// After `shutdown`, `put` fails and `take` returns the remaining items and then `None`, without
+/// blocking.
+pub struct BlockingQueue<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    /// The threads blocked in `take`.
+    not_empty: WaitList,
+    /// The threads blocked in `put`.
+    not_full: WaitList,
+}
+
+impl<T> fmt::Debug for BlockingQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingQueue")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> BlockingQueue<T> {
+    /// Creates a queue that holds at most `capacity` items. Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        Self {
+            state: Mutex::new(State {
+                items: VecDeque::new(),
+                shutdown: false,
+            }),
+            capacity,
+            not_empty: WaitList::new(),
+            not_full: WaitList::new(),
+        }
+    }
+
+    /// Creates a queue whose `put` never blocks.
+    pub fn unbounded() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+
+    /// Puts `item` at the back, waiting for room until `deadline` if any.
+    fn put_until(&self, item: T, deadline: Option<Instant>) -> Result<(), T> {
+        let mut item = Some(item);
+        let put = self.not_full.wait_until(deadline, || {
+            let mut state = self.lock();
+            if state.shutdown {
+                return Some(false);
+            }
+            if state.items.len() == self.capacity {
+                return None;
+            }
+            state.items.push_back(item.take().unwrap());
+            Some(true)
+        });
+        if put != Some(true) {
+            return Err(item.unwrap());
+        }
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Takes the item at the front, waiting for one until `deadline` if any.
+    fn take_until(&self, deadline: Option<Instant>) -> Option<T> {
+        let item = self
+            .not_empty
+            .wait_until(deadline, || {
+                let mut state = self.lock();
+                match state.items.pop_front() {
+                    Some(item) => Some(Some(item)),
+                    None => state.shutdown.then_some(None),
+                }
+            })
+            .flatten()?;
+        self.not_full.notify_one();
+        Some(item)
+    }
+
+    /// Puts `item` at the back, blocking while the queue is full. Returns it back if the queue is
+    /// shut down.
+    pub fn put(&self, item: T) -> Result<(), T> {
+        self.put_until(item, None)
+    }
+
+    /// Puts `item` at the back, blocking while the queue is full for at most `timeout`. Returns it
+    /// back if the queue is shut down or still full.
+    pub fn put_timeout(&self, item: T, timeout: Duration) -> Result<(), T> {
+        self.put_until(item, Some(Instant::now() + timeout))
+    }
+
+    /// Puts `item` at the back without blocking. Returns it back if the queue is shut down or full.
+    pub fn try_put(&self, item: T) -> Result<(), T> {
+        self.put_until(item, Some(Instant::now()))
+    }
+
+    /// Takes the item at the front, blocking while the queue is empty. Returns `None` if the queue
+    /// is shut down and empty.
+    pub fn take(&self) -> Option<T> {
+        self.take_until(None)
+    }
+
+    /// Takes the item at the front, blocking while the queue is empty for at most `timeout`.
+    /// Returns `None` if the queue is shut down or still empty.
+    pub fn take_timeout(&self, timeout: Duration) -> Option<T> {
+        self.take_until(Some(Instant::now() + timeout))
+    }
+
+    /// Takes the item at the front without blocking. Returns `None` if the queue is empty.
+    pub fn try_take(&self) -> Option<T> {
+        self.take_until(Some(Instant::now()))
+    }
+
+    /// Shuts down the queue, waking up all blocked threads. Returns `false` if it was already shut
+    /// down.
+    pub fn shutdown(&self) -> bool {
+        let mut state = self.lock();
+        if state.shutdown {
+            return false;
+        }
+        state.shutdown = true;
+        drop(state);
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+        true
+    }
+
+    /// Returns `true` if the queue is shut down.
+    pub fn is_shutdown(&self) -> bool {
+        self.lock().shutdown
+    }
+
+    /// Returns the number of items in the queue.
+    pub fn len(&self) -> usize {
+        self.lock().items.len()
+    }
+
+    /// Returns `true` if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of items in the queue.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
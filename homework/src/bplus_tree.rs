@@ -0,0 +1,411 @@
+//! Concurrent B+ tree with lock coupling.
+//!
+//! Each node is latched with a reader-writer lock, and the operations descend the tree by lock
+//! coupling: a child is latched before its parent is released. Lookups and scans hold read latches
+//! only. Updates descend with read latches and write-latch the leaf, which suffices unless the
+//! leaf splits. Then the insertion restarts and descends with write latches, holding those of the
+//! ancestors that the split may propagate to, i.e., up to the lowest one that is not full (Bayer
+//! and Schkolnick, "Concurrency of Operations on B-Trees", 1977).
+//!
+//! Nodes are never merged. A removal only takes the entry out of its leaf, so that the nodes live
+//! until the tree is dropped, and a node reached from the tree can always be latched.
+
+use core::fmt;
+use core::ops::{Bound, RangeBounds};
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(feature = "check-loom")]
+use loom::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Number of children of an internal node of `BPlusTree::new`.
+const DEFAULT_FANOUT: usize = 32;
+
+type Latch<K, V> = RwLock<Node<K, V>>;
+
+#[derive(Debug)]
+enum Node<K, V> {
+    /// The entries sorted by key, and the right sibling, which is null for the rightmost leaf.
+    Leaf {
+        keys: Vec<K>,
+        values: Vec<V>,
+        next: *mut Latch<K, V>,
+    },
+    /// The `i`-th child holds the keys in `keys[i - 1]..keys[i]`.
+    Internal {
+        keys: Vec<K>,
+        children: Vec<*mut Latch<K, V>>,
+    },
+}
+
+impl<K, V> Node<K, V> {
+    fn keys(&self) -> &[K] {
+        match self {
+            Node::Leaf { keys, .. } | Node::Internal { keys, .. } => keys,
+        }
+    }
+
+    /// Splits off the upper half of the node, and returns the least key of the new node and the
+    /// new node.
+    fn split(&mut self) -> (K, *mut Latch<K, V>)
+    where
+        K: Clone,
+    {
+        match self {
+            Node::Leaf { keys, values, next } => {
+                let mid = keys.len() / 2;
+                let keys = keys.split_off(mid);
+                let separator = keys[0].clone();
+                let right = Box::into_raw(Box::new(RwLock::new(Node::Leaf {
+                    values: values.split_off(mid),
+                    next: *next,
+                    keys,
+                })));
+                *next = right;
+                (separator, right)
+            }
+            Node::Internal { keys, children } => {
+                let mid = keys.len() / 2;
+                let right = Node::Internal {
+                    keys: keys.split_off(mid + 1),
+                    children: children.split_off(mid + 1),
+                };
+                // The separator at `mid` moves up to the parent.
+                let separator = keys.pop().unwrap();
+                (separator, Box::into_raw(Box::new(RwLock::new(right))))
+            }
+        }
+    }
+}
+
+/// Returns the index of the child of an internal node with `keys` that may hold `key`.
+fn child_index<K: Ord>(keys: &[K], key: &K) -> usize {
+    keys.partition_point(|k| k <= key)
+}
+
+#[derive(Debug)]
+struct Root<K, V> {
+    node: *mut Latch<K, V>,
+    /// Number of internal levels, i.e., the root is a leaf if 0.
+    height: usize,
+}
+
+/// Concurrent ordered map as a B+ tree, whose nodes are latched one by one.
+///
+/// The entries are stored in sorted arrays in the leaves, which are linked from left to right, so
+/// that lookups and range scans touch fewer cache lines than in the list-based maps such as
+/// [`SkipMap`](crate::SkipMap).
+pub struct BPlusTree<K, V> {
+    /// Latched before the root node by the operations, and write-latched by the insertions that
+    /// may split the root node.
+    root: RwLock<Root<K, V>>,
+    /// Maximum number of keys in a node.
+    max_keys: usize,
+}
+
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for BPlusTree<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for BPlusTree<K, V> {}
+
+impl<K, V> fmt::Debug for BPlusTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BPlusTree")
+            .field("fanout", &(self.max_keys + 1))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V> Default for BPlusTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> BPlusTree<K, V> {
+    /// Creates an empty tree.
+    pub fn new() -> Self {
+        Self::with_fanout(DEFAULT_FANOUT)
+    }
+
+    /// Creates an empty tree whose internal nodes have at most `fanout` children, and whose
+    /// leaves have at most `fanout - 1` entries. Panics if `fanout` is less than 3.
+    pub fn with_fanout(fanout: usize) -> Self {
+        assert!(fanout >= 3);
+        let leaf = Node::Leaf {
+            keys: Vec::with_capacity(fanout),
+            values: Vec::with_capacity(fanout),
+            next: ptr::null_mut(),
+        };
+        Self {
+            root: RwLock::new(Root {
+                node: Box::into_raw(Box::new(RwLock::new(leaf))),
+                height: 0,
+            }),
+            max_keys: fanout - 1,
+        }
+    }
+}
+
+impl<K: Ord, V> BPlusTree<K, V> {
+    /// Descends to the leaf that may hold `key`, or to the leftmost leaf if `key` is `None`, by
+    /// read lock coupling. Returns the read-latched leaf.
+    fn read_leaf(&self, key: Option<&K>) -> RwLockReadGuard<'_, Node<K, V>> {
+        let root = self.root.read().unwrap();
+        // SAFETY: The nodes are freed only when the tree is dropped.
+        let mut guard = unsafe { &*root.node }.read().unwrap();
+        drop(root);
+        loop {
+            let child = match &*guard {
+                Node::Leaf { .. } => return guard,
+                Node::Internal { keys, children } => {
+                    children[key.map_or(0, |key| child_index(keys, key))]
+                }
+            };
+            // SAFETY: Same as above.
+            guard = unsafe { &*child }.read().unwrap();
+        }
+    }
+
+    /// Descends to the leaf that may hold `key` by read lock coupling. Returns the write-latched
+    /// leaf.
+    fn write_leaf(&self, key: &K) -> RwLockWriteGuard<'_, Node<K, V>> {
+        let root = self.root.read().unwrap();
+        let height = root.height;
+        // SAFETY: The nodes are freed only when the tree is dropped.
+        let node = unsafe { &*root.node };
+        if height == 0 {
+            return node.write().unwrap();
+        }
+
+        let mut guard = node.read().unwrap();
+        drop(root);
+        for level in (0..height).rev() {
+            let child = match &*guard {
+                Node::Internal { keys, children } => children[child_index(keys, key)],
+                Node::Leaf { .. } => unreachable!("a leaf above the leaf level"),
+            };
+            // SAFETY: Same as above.
+            let child = unsafe { &*child };
+            if level == 0 {
+                return child.write().unwrap();
+            }
+            guard = child.read().unwrap();
+        }
+        unreachable!("the loop returns at the leaf level")
+    }
+
+    /// Calls `f` on the entries from `start` in ascending order of the keys, until it returns
+    /// `false`.
+    fn scan<F>(&self, start: Bound<&K>, mut f: F)
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        let mut guard = self.read_leaf(match start {
+            Bound::Included(key) | Bound::Excluded(key) => Some(key),
+            Bound::Unbounded => None,
+        });
+        let mut index = match start {
+            Bound::Included(start) => guard.keys().partition_point(|k| k < start),
+            Bound::Excluded(start) => guard.keys().partition_point(|k| k <= start),
+            Bound::Unbounded => 0,
+        };
+        loop {
+            let next = match &*guard {
+                Node::Leaf { keys, values, next } => {
+                    for (key, value) in keys[index..].iter().zip(&values[index..]) {
+                        if !f(key, value) {
+                            return;
+                        }
+                    }
+                    *next
+                }
+                Node::Internal { .. } => unreachable!("`read_leaf` returns a leaf"),
+            };
+            if next.is_null() {
+                return;
+            }
+            // Latches the leaves from left to right, as the splits link them.
+            // SAFETY: The nodes are freed only when the tree is dropped.
+            guard = unsafe { &*next }.read().unwrap();
+            index = 0;
+        }
+    }
+
+    /// Looks up `key`, calling `f` on its value if any, while the leaf holding it is read-latched.
+    pub fn lookup<F, R>(&self, key: &K, f: F) -> R
+    where
+        F: FnOnce(Option<&V>) -> R,
+    {
+        let guard = self.read_leaf(Some(key));
+        match &*guard {
+            Node::Leaf { keys, values, .. } => f(keys.binary_search(key).ok().map(|i| &values[i])),
+            Node::Internal { .. } => unreachable!("`read_leaf` returns a leaf"),
+        }
+    }
+
+    /// Returns `true` if the tree contains `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.lookup(key, |value| value.is_some())
+    }
+
+    /// Calls `f` on the entries whose keys are in `range`, in ascending order of the keys.
+    ///
+    /// The scan latches one leaf at a time, so it is not a snapshot: it sees the updates to the
+    /// leaves it has not reached yet, but not to those it has passed.
+    pub fn range<R, F>(&self, range: R, mut f: F)
+    where
+        R: RangeBounds<K>,
+        F: FnMut(&K, &V),
+    {
+        self.scan(range.start_bound(), |key, value| {
+            let in_range = match range.end_bound() {
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+                Bound::Unbounded => true,
+            };
+            if in_range {
+                f(key, value);
+            }
+            in_range
+        });
+    }
+
+    /// Removes `key`, returning its value if any.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        match &mut *self.write_leaf(key) {
+            Node::Leaf { keys, values, .. } => {
+                let index = keys.binary_search(key).ok()?;
+                let _ = keys.remove(index);
+                Some(values.remove(index))
+            }
+            Node::Internal { .. } => unreachable!("`write_leaf` returns a leaf"),
+        }
+    }
+
+    /// Returns `true` if the tree is empty.
+    pub fn is_empty(&self) -> bool {
+        let mut empty = true;
+        self.scan(Bound::Unbounded, |_, _| {
+            empty = false;
+            false
+        });
+        empty
+    }
+}
+
+impl<K: Ord + Clone, V> BPlusTree<K, V> {
+    /// Inserts `key` with `value` into the leaf write-latched by `guard`. Returns them back if the
+    /// key is already in the tree.
+    fn insert_entry(
+        guard: &mut RwLockWriteGuard<'_, Node<K, V>>,
+        key: K,
+        value: V,
+    ) -> Result<(), (K, V)> {
+        match &mut **guard {
+            Node::Leaf { keys, values, .. } => match keys.binary_search(&key) {
+                Ok(_) => Err((key, value)),
+                Err(index) => {
+                    keys.insert(index, key);
+                    values.insert(index, value);
+                    Ok(())
+                }
+            },
+            Node::Internal { .. } => unreachable!("entries are inserted into leaves"),
+        }
+    }
+
+    /// Inserts `key` with `value`. Returns them back if the key is already in the tree.
+    pub fn insert(&self, key: K, value: V) -> Result<(), (K, V)> {
+        let mut guard = self.write_leaf(&key);
+        if guard.keys().len() < self.max_keys || guard.keys().binary_search(&key).is_ok() {
+            return Self::insert_entry(&mut guard, key, value);
+        }
+        // The leaf splits, and its parent needs to be latched as well.
+        drop(guard);
+        self.insert_splitting(key, value)
+    }
+
+    /// Inserts `key` with `value` by write lock coupling, splitting the full nodes on the way.
+    fn insert_splitting(&self, key: K, value: V) -> Result<(), (K, V)> {
+        let mut root = Some(self.root.write().unwrap());
+        let mut node = root.as_ref().unwrap().node;
+        // The write-latched nodes from the lowest one that is not full down to the leaf.
+        let mut path = Vec::new();
+        loop {
+            // SAFETY: The nodes are freed only when the tree is dropped.
+            let guard = unsafe { &*node }.write().unwrap();
+            if guard.keys().len() < self.max_keys {
+                // The split, if any, stops at this node.
+                root = None;
+                path.clear();
+            }
+            let child = match &*guard {
+                Node::Leaf { .. } => None,
+                Node::Internal { keys, children } => Some(children[child_index(keys, &key)]),
+            };
+            path.push(guard);
+            match child {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+
+        let mut guard = path.pop().unwrap();
+        Self::insert_entry(&mut guard, key, value)?;
+        while guard.keys().len() > self.max_keys {
+            let (separator, right) = guard.split();
+            match path.pop() {
+                Some(mut parent) => {
+                    match &mut *parent {
+                        Node::Internal { keys, children } => {
+                            let index = keys.partition_point(|k| k < &separator);
+                            keys.insert(index, separator);
+                            children.insert(index + 1, right);
+                        }
+                        Node::Leaf { .. } => unreachable!("a leaf above the leaf level"),
+                    }
+                    guard = parent;
+                }
+                None => {
+                    // The root is split, so it was full and its latch is still held.
+                    let root = root.as_mut().unwrap();
+                    let left = root.node;
+                    let mut keys = Vec::with_capacity(self.max_keys + 1);
+                    keys.push(separator);
+                    let mut children = Vec::with_capacity(self.max_keys + 2);
+                    children.extend([left, right]);
+                    root.node =
+                        Box::into_raw(Box::new(RwLock::new(Node::Internal { keys, children })));
+                    root.height += 1;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> Drop for BPlusTree<K, V> {
+    fn drop(&mut self) {
+        /// Frees `node` and its descendants.
+        ///
+        /// # Safety
+        ///
+        /// No other thread accesses the nodes, and they are freed only once.
+        unsafe fn free<K, V>(node: *mut Latch<K, V>) {
+            // SAFETY: Guaranteed by the caller.
+            let node = unsafe { Box::from_raw(node) }.into_inner().unwrap();
+            if let Node::Internal { children, .. } = node {
+                for child in children {
+                    // SAFETY: Each node is the child of only one node.
+                    unsafe { free(child) };
+                }
+            }
+        }
+
+        // SAFETY: No other thread accesses the tree, and the tree is the only owner of the root.
+        unsafe { free(self.root.read().unwrap().node) };
+    }
+}
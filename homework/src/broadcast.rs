@@ -0,0 +1,326 @@
+//! Broadcast channel, in which each receiver receives every message.
+
+use core::fmt;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::sync::Arc;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::{Mutex, MutexGuard};
+#[cfg(feature = "check-loom")]
+use loom::sync::{Mutex, MutexGuard};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::{Mutex, MutexGuard};
+
+use crate::parker::WaitList;
+
+/// What a sender does when the channel is full, i.e., the slowest receiver lags `capacity`
+/// messages behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LagPolicy {
+    /// Waits until the slowest receiver catches up.
+    #[default]
+    Block,
+    /// Drops the oldest message. The receivers that did not receive it skip it silently.
+    DropOldest,
+    /// Drops the oldest message. The receivers that did not receive it get
+    /// [`RecvError::Lagged`] once, and then continue from the oldest message left.
+    Error,
+}
+
+/// Error returned by [`Receiver::recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// The receiver missed this many messages, which were dropped before it received them.
+    Lagged(u64),
+    /// All senders are dropped, and the receiver received all messages.
+    Closed,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Lagged(missed) => write!(f, "receiver lagged by {missed} messages"),
+            RecvError::Closed => f.write_str("receiving on a closed broadcast channel"),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+/// Error returned by [`Receiver::try_recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// The receiver received all messages sent so far.
+    Empty,
+    /// The receiver missed this many messages, which were dropped before it received them.
+    Lagged(u64),
+    /// All senders are dropped, and the receiver received all messages.
+    Closed,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty broadcast channel"),
+            TryRecvError::Lagged(missed) => write!(f, "receiver lagged by {missed} messages"),
+            TryRecvError::Closed => f.write_str("receiving on a closed broadcast channel"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+/// Messages that some receivers have not received.
+#[derive(Debug)]
+struct State<T> {
+    /// The messages numbered `head..head + messages.len()`.
+    messages: VecDeque<T>,
+    head: u64,
+    /// Number of the receivers by the number of the next message they receive.
+    cursors: BTreeMap<u64, usize>,
+    senders: usize,
+}
+
+impl<T> State<T> {
+    fn tail(&self) -> u64 {
+        self.head + self.messages.len() as u64
+    }
+
+    fn add_cursor(&mut self, next: u64) {
+        *self.cursors.entry(next).or_default() += 1;
+    }
+
+    fn remove_cursor(&mut self, next: u64) {
+        let count = self.cursors.get_mut(&next).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            let _ = self.cursors.remove(&next);
+        }
+    }
+
+    /// Receives the message numbered `next`, and advances `next`.
+    fn receive(&mut self, next: &mut u64, policy: LagPolicy) -> Result<T, TryRecvError>
+    where
+        T: Clone,
+    {
+        if *next < self.head {
+            let missed = self.head - *next;
+            self.remove_cursor(*next);
+            *next = self.head;
+            self.add_cursor(*next);
+            if policy == LagPolicy::Error {
+                return Err(TryRecvError::Lagged(missed));
+            }
+        }
+        let message = match self.messages.get((*next - self.head) as usize) {
+            Some(message) => message.clone(),
+            None if self.senders == 0 => return Err(TryRecvError::Closed),
+            None => return Err(TryRecvError::Empty),
+        };
+        self.remove_cursor(*next);
+        *next += 1;
+        self.add_cursor(*next);
+        self.trim();
+        Ok(message)
+    }
+
+    /// Drops the messages that all receivers have received. Returns `true` if there were any.
+    fn trim(&mut self) -> bool {
+        let slowest = self
+            .cursors
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or_else(|| self.tail());
+        let trimmed = slowest > self.head;
+        while self.head < slowest {
+            let _ = self.messages.pop_front();
+            self.head += 1;
+        }
+        trimmed
+    }
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    capacity: usize,
+    policy: LagPolicy,
+    /// Wakes up the receivers when a message is sent or the senders are dropped.
+    sent: WaitList,
+    /// Wakes up the blocked senders when the slowest receivers catch up.
+    received: WaitList,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+}
+
+/// Creates a broadcast channel that holds at most `capacity` messages not received by some
+/// receiver, handling the messages sent while it is full according to `policy`. Panics if
+/// `capacity` is 0.
+///
+/// A receiver receives the messages sent after it subscribes, each cloned for it.
+pub fn channel<T: Clone>(capacity: usize, policy: LagPolicy) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0);
+    let mut state = State {
+        messages: VecDeque::with_capacity(capacity),
+        head: 0,
+        cursors: BTreeMap::new(),
+        senders: 1,
+    };
+    state.add_cursor(0);
+    let shared = Arc::new(Shared {
+        state: Mutex::new(state),
+        capacity,
+        policy,
+        sent: WaitList::new(),
+        received: WaitList::new(),
+    });
+    let receiver = Receiver {
+        shared: shared.clone(),
+        next: 0,
+    };
+    (Sender { shared }, receiver)
+}
+
+/// Sending half of a broadcast channel. Clones of it send to the same receivers.
+#[derive(Debug)]
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.shared.sent.notify_all();
+        }
+    }
+}
+
+impl<T: Clone> Sender<T> {
+    /// Sends `message` to all receivers. Returns it back if there are none.
+    ///
+    /// If the channel is full, blocks or drops the oldest message according to its policy.
+    pub fn send(&self, message: T) -> Result<(), T> {
+        let mut message = Some(message);
+        let sent = self.shared.received.wait_until(None, || {
+            let mut state = self.shared.lock();
+            loop {
+                if state.cursors.is_empty() {
+                    return Some(false);
+                }
+                if state.messages.len() < self.shared.capacity {
+                    break;
+                }
+                match self.shared.policy {
+                    LagPolicy::Block => return None,
+                    LagPolicy::DropOldest | LagPolicy::Error => {
+                        let _ = state.messages.pop_front();
+                        state.head += 1;
+                    }
+                }
+            }
+            state.messages.push_back(message.take().unwrap());
+            Some(true)
+        });
+        if sent != Some(true) {
+            return Err(message.unwrap());
+        }
+        self.shared.sent.notify_all();
+        Ok(())
+    }
+
+    /// Creates a receiver that receives the messages sent from now on.
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.shared.lock();
+        let next = state.tail();
+        state.add_cursor(next);
+        Receiver {
+            shared: self.shared.clone(),
+            next,
+        }
+    }
+
+    /// Returns the number of receivers.
+    pub fn receiver_count(&self) -> usize {
+        self.shared.lock().cursors.values().sum()
+    }
+}
+
+/// Receiving half of a broadcast channel. A clone of it receives the same messages from where it
+/// is.
+#[derive(Debug)]
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    /// Number of the next message to receive.
+    next: u64,
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().add_cursor(self.next);
+        Self {
+            shared: self.shared.clone(),
+            next: self.next,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.remove_cursor(self.next);
+        if state.trim() || state.cursors.is_empty() {
+            drop(state);
+            self.shared.received.notify_all();
+        }
+    }
+}
+
+impl<T: Clone> Receiver<T> {
+    /// Receives the next message without blocking.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let message = self
+            .shared
+            .lock()
+            .receive(&mut self.next, self.shared.policy)?;
+        self.shared.received.notify_all();
+        Ok(message)
+    }
+
+    /// Blocks until the next message is sent, and receives it.
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        let received = self.shared.sent.wait_until(None, || {
+            match self
+                .shared
+                .lock()
+                .receive(&mut self.next, self.shared.policy)
+            {
+                Ok(message) => Some(Ok(message)),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Lagged(missed)) => Some(Err(RecvError::Lagged(missed))),
+                Err(TryRecvError::Closed) => Some(Err(RecvError::Closed)),
+            }
+        });
+        // Without a deadline, the wait ends only with a result.
+        let message = received.unwrap()?;
+        self.shared.received.notify_all();
+        Ok(message)
+    }
+}
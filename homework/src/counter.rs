@@ -0,0 +1,84 @@
+//! Striped counter.
+
+use core::sync::atomic::{AtomicI64, Ordering};
+use std::num::NonZeroUsize;
+use std::thread;
+
+use crate::registry;
+use crate::CachePadded;
+
+/// Counter whose value is spread over several cells, in the style of Java's `LongAdder`.
+///
+/// Each thread updates the cell of its id in [`REGISTRY`](registry::REGISTRY), so that threads
+/// updating a hot counter do not bounce a single cache line between them. As the ids are dense,
+/// the threads have cells of their own as long as there are no more threads than cells. Reading
+/// the value sums all cells, which is slower than reading an atomic, so this suits counters that
+/// are updated much more often than read.
+#[derive(Debug)]
+pub struct ConcurrentCounter {
+    /// The number of cells is a power of two.
+    cells: Box<[CachePadded<AtomicI64>]>,
+}
+
+impl Default for ConcurrentCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConcurrentCounter {
+    /// Creates a counter with a cell for each hardware thread.
+    pub fn new() -> Self {
+        Self::with_stripes(thread::available_parallelism().map_or(1, NonZeroUsize::get))
+    }
+
+    /// Creates a counter with `stripes` cells, rounded up to a power of two.
+    pub fn with_stripes(stripes: usize) -> Self {
+        Self {
+            cells: (0..stripes.max(1).next_power_of_two())
+                .map(|_| CachePadded::new(AtomicI64::new(0)))
+                .collect(),
+        }
+    }
+
+    /// Returns the cell of the current thread.
+    fn cell(&self) -> &AtomicI64 {
+        &self.cells[registry::current_id() & (self.cells.len() - 1)]
+    }
+
+    /// Adds `delta` to the counter.
+    pub fn add(&self, delta: i64) {
+        let _ = self.cell().fetch_add(delta, Ordering::Relaxed);
+    }
+
+    /// Adds 1 to the counter.
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    /// Subtracts 1 from the counter.
+    pub fn decrement(&self) {
+        self.add(-1);
+    }
+
+    /// Returns the value of the counter.
+    ///
+    /// The cells are read one by one, so updates racing with the sum may or may not be counted. A
+    /// counter that is only incremented and decremented may even read as negative if a decrement
+    /// is counted but the increment before it is not.
+    pub fn sum(&self) -> i64 {
+        self.cells
+            .iter()
+            .map(|cell| cell.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Returns the value of the counter and resets it to 0. Each update is counted by exactly one
+    /// `take`, or remains in the counter.
+    pub fn take(&self) -> i64 {
+        self.cells
+            .iter()
+            .map(|cell| cell.swap(0, Ordering::Relaxed))
+            .sum()
+    }
+}
@@ -0,0 +1,1032 @@
+//! Concurrent hash trie with constant-time snapshots.
+//!
+//! Prokopec et al., "Concurrent Tries with Efficient Non-Blocking Snapshots", PPoPP 2012. The trie
+//! is a hash array mapped trie whose indirection nodes ("I-nodes") are the only mutable nodes: an
+//! update replaces the main node below an I-node with a modified copy. A snapshot swaps the root
+//! for a copy of a new generation, after which the snapshot and the trie share all nodes below the
+//! root, and each copies the I-nodes of the old generation lazily on its way down before updating
+//! below them.
+//!
+//! Each main node remembers the one it replaced until the replacement is committed, which happens
+//! only if the root is still of the I-node's generation ("GCAS"). The root is replaced with a
+//! double-compare single-swap ("RDCSS") that checks the main node below it as well. Both are
+//! helped along by any thread that finds them pending.
+//!
+//! As the nodes are shared between the snapshots, they are reference counted. The references held
+//! by the atomic pointers are dropped with EBR, so that a thread pinned while loading a pointer
+//! may take another reference to it.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+use core::ptr;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+use crate::ebr::{pin, Guard};
+use crate::utils::{hash_of, HashBuilder};
+
+/// Bits of the hash that index a level.
+const BITS: u32 = 6;
+const MASK: u64 = (1 << BITS) - 1;
+/// Keys whose hashes are equal in all levels are kept in a list below the last level.
+const HASH_BITS: u32 = u64::BITS;
+
+/// Generation of an I-node. Only compared for equality.
+type Gen = u64;
+
+/// Returns a generation that was not returned before.
+fn new_gen() -> Gen {
+    // Only needs to be unique, so it is left out of the loom model.
+    static NEXT: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+    NEXT.fetch_add(1, core::sync::atomic::Ordering::Relaxed)
+}
+
+/// Moves `arc` into a pointer to be owned by an atomic.
+fn into_raw<T>(arc: Arc<T>) -> *mut T {
+    Arc::into_raw(arc) as *mut T
+}
+
+/// Takes another reference to a node owned by an atomic.
+///
+/// # Safety
+///
+/// `node` must be loaded from an atomic that owns a reference to it, while pinned by the guard
+/// that releases the reference.
+unsafe fn clone_raw<T>(node: *const T) -> Arc<T> {
+    Arc::increment_strong_count(node);
+    Arc::from_raw(node)
+}
+
+/// Drops the reference to `node` owned by an atomic, once the threads pinned now are unpinned.
+///
+/// # Safety
+///
+/// `node` must be unlinked from the atomic that owned the reference, and only this thread may
+/// release the reference.
+unsafe fn release<T>(guard: &Guard, node: *const T) {
+    guard.defer_destroy(Box::into_raw(Box::new(Arc::from_raw(node))));
+}
+
+/// Leaf holding an entry.
+#[derive(Debug)]
+struct SNode<K, V> {
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+/// Indirection node, the only mutable node.
+#[derive(Debug)]
+struct INode<K, V> {
+    /// Owns a reference. Never null.
+    main: AtomicPtr<MainNode<K, V>>,
+    gen: Gen,
+}
+
+#[derive(Debug)]
+enum Branch<K, V> {
+    I(Arc<INode<K, V>>),
+    S(Arc<SNode<K, V>>),
+}
+
+impl<K, V> Clone for Branch<K, V> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::I(inode) => Self::I(inode.clone()),
+            Self::S(snode) => Self::S(snode.clone()),
+        }
+    }
+}
+
+/// Branching node, which holds the branches whose hashes are present in `bitmap` at its level.
+#[derive(Debug)]
+struct CNode<K, V> {
+    bitmap: u64,
+    array: Box<[Branch<K, V>]>,
+    gen: Gen,
+}
+
+impl<K, V> Clone for CNode<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            bitmap: self.bitmap,
+            array: self.array.clone(),
+            gen: self.gen,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Kind<K, V> {
+    C(CNode<K, V>),
+    /// Tomb of the last entry of a C-node that is about to be merged into its parent.
+    T(Arc<SNode<K, V>>),
+    /// Entries whose hashes are equal.
+    L(Box<[Arc<SNode<K, V>>]>),
+    /// Marks a failed replacement of the main node.
+    Failed(Arc<MainNode<K, V>>),
+}
+
+/// Node below an I-node.
+#[derive(Debug)]
+struct MainNode<K, V> {
+    kind: Kind<K, V>,
+    /// The main node that this one replaces, or a failed node holding it, while the replacement
+    /// is pending. Null once it is committed. Owns a reference.
+    prev: AtomicPtr<MainNode<K, V>>,
+}
+
+impl<K, V> MainNode<K, V> {
+    fn new(kind: Kind<K, V>) -> Arc<Self> {
+        Arc::new(Self {
+            kind,
+            prev: AtomicPtr::new(ptr::null_mut()),
+        })
+    }
+}
+
+impl<K, V> Drop for MainNode<K, V> {
+    fn drop(&mut self) {
+        let prev = self.prev.load(Ordering::Relaxed);
+        if !prev.is_null() {
+            // SAFETY: The reference is owned by this node.
+            drop(unsafe { Arc::from_raw(prev) });
+        }
+    }
+}
+
+impl<K, V> INode<K, V> {
+    fn new(main: Arc<MainNode<K, V>>, gen: Gen) -> Self {
+        Self {
+            main: AtomicPtr::new(into_raw(main)),
+            gen,
+        }
+    }
+
+    /// Returns an I-node of generation `gen` with the same main node.
+    fn copy_to_gen(&self, gen: Gen, ctrie: &Ctrie<K, V>, guard: &Guard) -> Self {
+        let main = self.gcas_read(ctrie, guard);
+        // SAFETY: `main` is read while pinned from `self.main`.
+        Self::new(unsafe { clone_raw(main) }, gen)
+    }
+
+    /// Returns the committed main node, committing or aborting the pending replacement.
+    fn gcas_read<'g>(&self, ctrie: &Ctrie<K, V>, guard: &'g Guard) -> &'g MainNode<K, V> {
+        // Acquire: the main node is initialized before it is published.
+        let main = self.main.load(Ordering::Acquire);
+        // SAFETY: `main` is loaded while pinned, so it is not freed until `guard` is dropped.
+        let main_ref = unsafe { &*main };
+        if main_ref.prev.load(Ordering::Acquire).is_null() {
+            return main_ref;
+        }
+        self.gcas_commit(main, ctrie, guard)
+    }
+
+    /// Commits the replacement by `main` if the root is still of this I-node's generation, and
+    /// aborts it otherwise. Returns the committed main node.
+    fn gcas_commit<'g>(
+        &self,
+        mut main: *mut MainNode<K, V>,
+        ctrie: &Ctrie<K, V>,
+        guard: &'g Guard,
+    ) -> &'g MainNode<K, V> {
+        loop {
+            // SAFETY: `main` is loaded while pinned.
+            let main_ref = unsafe { &*main };
+            let prev = main_ref.prev.load(Ordering::Acquire);
+            if prev.is_null() {
+                return main_ref;
+            }
+
+            // The root is read before the replacement is committed, so that a snapshot taken
+            // after the read fails the replacement.
+            let root = ctrie.read_root(true, guard);
+            // SAFETY: `prev` is loaded while pinned.
+            match unsafe { &(*prev).kind } {
+                Kind::Failed(old) => {
+                    // Rolls back to the replaced main node.
+                    let old = into_raw(old.clone());
+                    match self
+                        .main
+                        .compare_exchange(main, old, Ordering::AcqRel, Ordering::Acquire)
+                    {
+                        Ok(_) => {
+                            // SAFETY: `main` is unlinked by the CAS above.
+                            unsafe { release(guard, main) };
+                            // SAFETY: `old` is linked to `self.main` while pinned.
+                            return unsafe { &*old };
+                        }
+                        Err(actual) => {
+                            // SAFETY: `old` was not published.
+                            drop(unsafe { Arc::from_raw(old) });
+                            main = actual;
+                        }
+                    }
+                }
+                _ if root.gen == self.gen && !ctrie.read_only => {
+                    if main_ref
+                        .prev
+                        .compare_exchange(
+                            prev,
+                            ptr::null_mut(),
+                            Ordering::AcqRel,
+                            Ordering::Acquire,
+                        )
+                        .is_ok()
+                    {
+                        // SAFETY: `prev` is unlinked by the CAS above.
+                        unsafe { release(guard, prev) };
+                        return main_ref;
+                    }
+                }
+                _ => {
+                    // SAFETY: `prev` is loaded while pinned from `main_ref.prev`.
+                    let failed = into_raw(MainNode::new(Kind::Failed(unsafe { clone_raw(prev) })));
+                    match main_ref.prev.compare_exchange(
+                        prev,
+                        failed,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    ) {
+                        // SAFETY: `prev` is unlinked by the CAS above.
+                        Ok(_) => unsafe { release(guard, prev) },
+                        // SAFETY: `failed` was not published.
+                        Err(_) => drop(unsafe { Arc::from_raw(failed) }),
+                    }
+                    main = self.main.load(Ordering::Acquire);
+                }
+            }
+        }
+    }
+
+    /// Replaces `old`, the main node read by `gcas_read`, with `new`. Returns `false` if `old` is
+    /// not the main node anymore, or if the replacement is aborted by a snapshot.
+    fn gcas(
+        &self,
+        old: &MainNode<K, V>,
+        new: Arc<MainNode<K, V>>,
+        ctrie: &Ctrie<K, V>,
+        guard: &Guard,
+    ) -> bool {
+        let old = old as *const _ as *mut MainNode<K, V>;
+        // SAFETY: `old` is read while pinned from `self.main`.
+        new.prev
+            .store(into_raw(unsafe { clone_raw(old) }), Ordering::Relaxed);
+        let new = into_raw(new);
+        // Release: `new` is initialized before it is published.
+        match self
+            .main
+            .compare_exchange(old, new, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {
+                // SAFETY: `old` is unlinked by the CAS above. `new.prev` still holds it.
+                unsafe { release(guard, old) };
+                let _ = self.gcas_commit(new, ctrie, guard);
+                // SAFETY: `new` is linked to `self.main` while pinned.
+                unsafe { (*new).prev.load(Ordering::Acquire).is_null() }
+            }
+            Err(_) => {
+                // SAFETY: `new` was not published.
+                drop(unsafe { Arc::from_raw(new) });
+                false
+            }
+        }
+    }
+}
+
+impl<K, V> Drop for INode<K, V> {
+    fn drop(&mut self) {
+        // SAFETY: The reference is owned by this node.
+        drop(unsafe { Arc::from_raw(self.main.load(Ordering::Relaxed)) });
+    }
+}
+
+impl<K, V> CNode<K, V> {
+    /// Returns the bit of `hash` at level `lev` and the index of its branch.
+    fn flag_pos(&self, hash: u64, lev: u32) -> (u64, usize) {
+        let flag = 1 << ((hash >> lev) & MASK);
+        (flag, (self.bitmap & (flag - 1)).count_ones() as usize)
+    }
+
+    /// Returns a copy of generation `gen`, whose I-nodes are copied to `gen` as well.
+    fn renewed(&self, gen: Gen, ctrie: &Ctrie<K, V>, guard: &Guard) -> Self {
+        Self {
+            bitmap: self.bitmap,
+            array: self
+                .array
+                .iter()
+                .map(|branch| match branch {
+                    Branch::I(inode) => Branch::I(Arc::new(inode.copy_to_gen(gen, ctrie, guard))),
+                    Branch::S(snode) => Branch::S(snode.clone()),
+                })
+                .collect(),
+            gen,
+        }
+    }
+
+    /// Returns this C-node if it is of generation `gen`, and a renewed copy otherwise. A C-node
+    /// is renewed before it gains a branch, so that all its I-nodes are of its generation.
+    fn at_gen(&self, gen: Gen, ctrie: &Ctrie<K, V>, guard: &Guard) -> Cow<'_, Self> {
+        if self.gen == gen {
+            Cow::Borrowed(self)
+        } else {
+            Cow::Owned(self.renewed(gen, ctrie, guard))
+        }
+    }
+
+    fn updated_at(&self, pos: usize, branch: Branch<K, V>, gen: Gen) -> Self {
+        let mut array = self.array.to_vec();
+        array[pos] = branch;
+        Self {
+            bitmap: self.bitmap,
+            array: array.into(),
+            gen,
+        }
+    }
+
+    fn inserted_at(&self, pos: usize, flag: u64, branch: Branch<K, V>, gen: Gen) -> Self {
+        let mut array = self.array.to_vec();
+        array.insert(pos, branch);
+        Self {
+            bitmap: self.bitmap | flag,
+            array: array.into(),
+            gen,
+        }
+    }
+
+    fn removed_at(&self, pos: usize, flag: u64, gen: Gen) -> Self {
+        let mut array = self.array.to_vec();
+        let _ = array.remove(pos);
+        Self {
+            bitmap: self.bitmap & !flag,
+            array: array.into(),
+            gen,
+        }
+    }
+
+    /// Entombs the entry of a C-node below the root that holds nothing else.
+    fn contracted(self, lev: u32) -> Kind<K, V> {
+        if lev > 0 && self.array.len() == 1 {
+            if let Branch::S(snode) = &self.array[0] {
+                return Kind::T(snode.clone());
+            }
+        }
+        Kind::C(self)
+    }
+
+    /// Resurrects the entries of the entombed I-nodes below, and contracts the result.
+    fn compressed(&self, lev: u32, gen: Gen, ctrie: &Ctrie<K, V>, guard: &Guard) -> Kind<K, V> {
+        Self {
+            bitmap: self.bitmap,
+            array: self
+                .array
+                .iter()
+                .map(|branch| match branch {
+                    Branch::I(inode) => match &inode.gcas_read(ctrie, guard).kind {
+                        Kind::T(snode) => Branch::S(snode.clone()),
+                        _ => branch.clone(),
+                    },
+                    Branch::S(_) => branch.clone(),
+                })
+                .collect(),
+            gen,
+        }
+        .contracted(lev)
+    }
+
+    /// Returns the main node holding `x` and `y`, whose hashes are equal below `lev`.
+    fn dual(x: Arc<SNode<K, V>>, y: Arc<SNode<K, V>>, lev: u32, gen: Gen) -> Kind<K, V> {
+        if lev >= HASH_BITS {
+            return Kind::L(vec![x, y].into());
+        }
+        let x_index = (x.hash >> lev) & MASK;
+        let y_index = (y.hash >> lev) & MASK;
+        let bitmap = (1 << x_index) | (1 << y_index);
+        let array = match x_index.cmp(&y_index) {
+            core::cmp::Ordering::Equal => {
+                let inode = INode::new(MainNode::new(Self::dual(x, y, lev + BITS, gen)), gen);
+                vec![Branch::I(Arc::new(inode))]
+            }
+            core::cmp::Ordering::Less => vec![Branch::S(x), Branch::S(y)],
+            core::cmp::Ordering::Greater => vec![Branch::S(y), Branch::S(x)],
+        };
+        Kind::C(Self {
+            bitmap,
+            array: array.into(),
+            gen,
+        })
+    }
+}
+
+/// Descriptor of a pending replacement of the root.
+#[derive(Debug)]
+struct Descriptor<K, V> {
+    old: Arc<INode<K, V>>,
+    /// The replacement succeeds only if this is still the main node of `old`.
+    expected: Arc<MainNode<K, V>>,
+    new: Arc<INode<K, V>>,
+    committed: AtomicBool,
+}
+
+/// The root is a descriptor if the pointer is tagged.
+fn is_descriptor<T>(root: *mut T) -> bool {
+    root as usize & 1 == 1
+}
+
+/// Lock-free concurrent hash map with constant-time atomic snapshots.
+///
+/// A snapshot is an independent map that starts with the entries of the map at the time it is
+/// taken. Taking it does not copy the entries, and neither does iterating over the map, which
+/// iterates over a snapshot and so never blocks the writers nor sees their updates.
+pub struct Ctrie<K, V> {
+    /// An `INode` owning a reference, or a `Descriptor` tagged with 1 while the root is replaced.
+    root: AtomicPtr<INode<K, V>>,
+    /// Read-only snapshots fail all pending replacements instead of committing them.
+    read_only: bool,
+    hash_builder: HashBuilder,
+}
+
+// The entries are shared with the snapshots on any thread, and the thread that reclaims a node
+// drops its entries.
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for Ctrie<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for Ctrie<K, V> {}
+
+impl<K: Hash + Eq, V> Default for Ctrie<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + fmt::Debug, V: fmt::Debug> fmt::Debug for Ctrie<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut map = f.debug_map();
+        for entry in self.iter() {
+            let _ = map.entry(entry.key(), entry.value());
+        }
+        map.finish()
+    }
+}
+
+impl<K, V> Ctrie<K, V> {
+    fn with_root(root: Arc<INode<K, V>>, read_only: bool, hash_builder: HashBuilder) -> Self {
+        Self {
+            root: AtomicPtr::new(into_raw(root)),
+            read_only,
+            hash_builder,
+        }
+    }
+
+    /// Returns the root, completing or aborting the pending replacement of the root.
+    fn read_root<'g>(&self, abort: bool, guard: &'g Guard) -> &'g INode<K, V> {
+        // Acquire: the root is initialized before it is published.
+        let root = self.root.load(Ordering::Acquire);
+        if is_descriptor(root) {
+            return self.rdcss_complete(abort, guard);
+        }
+        // SAFETY: `root` is loaded while pinned.
+        unsafe { &*root }
+    }
+
+    /// Completes the pending replacement of the root if `abort` is `false` and the main node
+    /// below the old root is the expected one, and aborts it otherwise. Returns the root.
+    fn rdcss_complete<'g>(&self, abort: bool, guard: &'g Guard) -> &'g INode<K, V> {
+        loop {
+            let root = self.root.load(Ordering::Acquire);
+            if !is_descriptor(root) {
+                // SAFETY: `root` is loaded while pinned.
+                return unsafe { &*root };
+            }
+            let descriptor = (root as usize & !1) as *mut Descriptor<K, V>;
+            // SAFETY: `descriptor` is loaded while pinned.
+            let desc = unsafe { &*descriptor };
+            let commit = !abort && ptr::eq(desc.old.gcas_read(self, guard), &*desc.expected);
+            let next = if commit { &desc.new } else { &desc.old };
+            let next = into_raw(next.clone());
+            match self
+                .root
+                .compare_exchange(root, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    if commit {
+                        desc.committed.store(true, Ordering::Release);
+                    }
+                    // SAFETY: `descriptor` is unlinked by the CAS above.
+                    unsafe { guard.defer_destroy(descriptor) };
+                    // SAFETY: `next` is linked to `self.root` while pinned.
+                    return unsafe { &*next };
+                }
+                // SAFETY: `next` was not published.
+                Err(_) => drop(unsafe { Arc::from_raw(next) }),
+            }
+        }
+    }
+
+    /// Replaces the root `old` with `new` if `expected` is still the main node below `old`.
+    fn rdcss_root(
+        &self,
+        old: &INode<K, V>,
+        expected: &MainNode<K, V>,
+        new: Arc<INode<K, V>>,
+        guard: &Guard,
+    ) -> bool {
+        let old = old as *const INode<K, V>;
+        // SAFETY: `old` and `expected` are read while pinned from `self.root` and `old.main`.
+        let descriptor = Box::into_raw(Box::new(Descriptor {
+            old: unsafe { clone_raw(old) },
+            expected: unsafe { clone_raw(expected) },
+            new,
+            committed: AtomicBool::new(false),
+        }));
+        // Release: the descriptor is initialized before it is published.
+        if self
+            .root
+            .compare_exchange(
+                old as *mut _,
+                (descriptor as usize | 1) as *mut _,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            // SAFETY: `descriptor` was not published.
+            drop(unsafe { Box::from_raw(descriptor) });
+            return false;
+        }
+        // SAFETY: `old` is unlinked by the CAS above. The descriptor holds it meanwhile.
+        unsafe { release(guard, old) };
+        let _ = self.rdcss_complete(false, guard);
+        // A helper sets the flag only after completing the replacement, so this may miss a
+        // replacement that succeeded. The caller then retries with the new root, which is safe.
+        // SAFETY: `descriptor` is loaded while pinned.
+        unsafe { (*descriptor).committed.load(Ordering::Acquire) }
+    }
+}
+
+impl<K: Hash + Eq, V> Ctrie<K, V> {
+    /// Creates a new map.
+    pub fn new() -> Self {
+        let gen = new_gen();
+        let root = MainNode::new(Kind::C(CNode {
+            bitmap: 0,
+            array: Box::new([]),
+            gen,
+        }));
+        Self::with_root(
+            Arc::new(INode::new(root, gen)),
+            false,
+            HashBuilder::default(),
+        )
+    }
+
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        hash_of(&self.hash_builder, key)
+    }
+
+    /// Returns an independent map with the entries of this map. Takes constant time.
+    pub fn snapshot(&self) -> Self {
+        let guard = pin();
+        loop {
+            let root = self.read_root(false, &guard);
+            let expected = root.gcas_read(self, &guard);
+            let new = Arc::new(root.copy_to_gen(new_gen(), self, &guard));
+            if self.rdcss_root(root, expected, new, &guard) {
+                let copy = Arc::new(root.copy_to_gen(new_gen(), self, &guard));
+                return Self::with_root(copy, false, self.hash_builder.clone());
+            }
+        }
+    }
+
+    /// Returns a snapshot that shares the old root, which is never updated.
+    fn read_only_snapshot(&self) -> Self {
+        let guard = pin();
+        loop {
+            let root = self.read_root(false, &guard);
+            let expected = root.gcas_read(self, &guard);
+            let new = Arc::new(root.copy_to_gen(new_gen(), self, &guard));
+            if self.rdcss_root(root, expected, new, &guard) {
+                // SAFETY: `root` is read while pinned from `self.root`.
+                let root = unsafe { clone_raw(root) };
+                return Self::with_root(root, true, self.hash_builder.clone());
+            }
+        }
+    }
+
+    /// Returns an iterator over a snapshot of the map, in no particular order.
+    pub fn iter(&self) -> Iter<K, V> {
+        let snapshot = self.read_only_snapshot();
+        let guard = pin();
+        let root = snapshot
+            .read_root(false, &guard)
+            .gcas_read(&snapshot, &guard);
+        // SAFETY: `root` is read while pinned from the root's `main`.
+        let root = unsafe { clone_raw(root) };
+        drop(guard);
+        Iter {
+            snapshot,
+            stack: vec![(root, 0)],
+        }
+    }
+
+    /// Returns the number of entries. Takes linear time.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Cleans up the entombed I-nodes below `parent`, which is at level `lev`.
+    fn clean(&self, parent: Option<&INode<K, V>>, lev: u32, guard: &Guard) {
+        // The root is never entombed.
+        let parent = parent.expect("the root is entombed");
+        let main = parent.gcas_read(self, guard);
+        if let Kind::C(cnode) = &main.kind {
+            let compressed = cnode.compressed(lev, parent.gen, self, guard);
+            let _ = parent.gcas(main, MainNode::new(compressed), self, guard);
+        }
+    }
+
+    /// Looks up `key` below `inode` at level `lev`. Returns `Err` if the operation has to restart
+    /// from the root.
+    #[allow(clippy::too_many_arguments)]
+    fn lookup_at<'g, Q>(
+        &self,
+        inode: &'g INode<K, V>,
+        key: &Q,
+        hash: u64,
+        lev: u32,
+        parent: Option<&'g INode<K, V>>,
+        start_gen: Gen,
+        guard: &'g Guard,
+    ) -> Result<Option<&'g SNode<K, V>>, ()>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let main = inode.gcas_read(self, guard);
+        let found = |snode: &SNode<K, V>| snode.hash == hash && snode.key.borrow() == key;
+        match &main.kind {
+            Kind::C(cnode) => {
+                let (flag, pos) = cnode.flag_pos(hash, lev);
+                if cnode.bitmap & flag == 0 {
+                    return Ok(None);
+                }
+                match &cnode.array[pos] {
+                    Branch::I(sub) if self.read_only || sub.gen == start_gen => {
+                        self.lookup_at(sub, key, hash, lev + BITS, Some(inode), start_gen, guard)
+                    }
+                    Branch::I(_) => {
+                        let renewed = cnode.renewed(start_gen, self, guard);
+                        if !inode.gcas(main, MainNode::new(Kind::C(renewed)), self, guard) {
+                            return Err(());
+                        }
+                        self.lookup_at(inode, key, hash, lev, parent, start_gen, guard)
+                    }
+                    Branch::S(snode) => Ok(Some(&**snode).filter(|snode| found(snode))),
+                }
+            }
+            Kind::T(_) if !self.read_only => {
+                self.clean(parent, lev - BITS, guard);
+                Err(())
+            }
+            Kind::T(snode) => Ok(Some(&**snode).filter(|snode| found(snode))),
+            Kind::L(snodes) => Ok(snodes
+                .iter()
+                .map(|snode| &**snode)
+                .find(|snode| found(snode))),
+            Kind::Failed(_) => unreachable!("a failed node is never committed"),
+        }
+    }
+
+    /// Calls `f` with the value of `key`, or `None` if there is none.
+    pub fn lookup<Q, F, R>(&self, key: &Q, f: F) -> R
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(Option<&V>) -> R,
+    {
+        let guard = pin();
+        let hash = self.hash(key);
+        loop {
+            let root = self.read_root(false, &guard);
+            if let Ok(found) = self.lookup_at(root, key, hash, 0, None, root.gen, &guard) {
+                return f(found.map(|snode| &snode.value));
+            }
+        }
+    }
+
+    /// Returns a clone of the value of `key`.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.lookup(key, |value| value.cloned())
+    }
+
+    /// Returns `true` if the map has an entry for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lookup(key, |value| value.is_some())
+    }
+
+    /// Inserts `snode` below `inode` at level `lev`. Returns `false` if the operation has to
+    /// restart from the root.
+    fn insert_at(
+        &self,
+        inode: &INode<K, V>,
+        snode: &Arc<SNode<K, V>>,
+        lev: u32,
+        parent: Option<&INode<K, V>>,
+        start_gen: Gen,
+        guard: &Guard,
+    ) -> bool {
+        let main = inode.gcas_read(self, guard);
+        let new = match &main.kind {
+            Kind::C(cnode) => {
+                let (flag, pos) = cnode.flag_pos(snode.hash, lev);
+                if cnode.bitmap & flag == 0 {
+                    let branch = Branch::S(snode.clone());
+                    Kind::C(
+                        cnode
+                            .at_gen(inode.gen, self, guard)
+                            .inserted_at(pos, flag, branch, inode.gen),
+                    )
+                } else {
+                    match &cnode.array[pos] {
+                        Branch::I(sub) if sub.gen == start_gen => {
+                            return self.insert_at(
+                                sub,
+                                snode,
+                                lev + BITS,
+                                Some(inode),
+                                start_gen,
+                                guard,
+                            );
+                        }
+                        Branch::I(_) => {
+                            let renewed = cnode.renewed(start_gen, self, guard);
+                            return inode.gcas(main, MainNode::new(Kind::C(renewed)), self, guard)
+                                && self.insert_at(inode, snode, lev, parent, start_gen, guard);
+                        }
+                        Branch::S(old) if old.hash == snode.hash && old.key == snode.key => {
+                            Kind::C(cnode.updated_at(pos, Branch::S(snode.clone()), inode.gen))
+                        }
+                        Branch::S(old) => {
+                            let dual =
+                                CNode::dual(old.clone(), snode.clone(), lev + BITS, inode.gen);
+                            let sub = Arc::new(INode::new(MainNode::new(dual), inode.gen));
+                            let cnode = cnode.at_gen(inode.gen, self, guard);
+                            Kind::C(cnode.updated_at(pos, Branch::I(sub), inode.gen))
+                        }
+                    }
+                }
+            }
+            Kind::T(_) => {
+                self.clean(parent, lev - BITS, guard);
+                return false;
+            }
+            Kind::L(snodes) => Kind::L(
+                snodes
+                    .iter()
+                    .filter(|old| old.key != snode.key)
+                    .cloned()
+                    .chain(Some(snode.clone()))
+                    .collect(),
+            ),
+            Kind::Failed(_) => unreachable!("a failed node is never committed"),
+        };
+        inode.gcas(main, MainNode::new(new), self, guard)
+    }
+
+    /// Inserts `key` with `value`, replacing the value of `key` if there is one.
+    pub fn insert(&self, key: K, value: V) {
+        let guard = pin();
+        let hash = self.hash(&key);
+        let snode = Arc::new(SNode { hash, key, value });
+        loop {
+            let root = self.read_root(false, &guard);
+            if self.insert_at(root, &snode, 0, None, root.gen, &guard) {
+                return;
+            }
+        }
+    }
+
+    /// Removes `key` below `inode` at level `lev`. Returns `Err` if the operation has to restart
+    /// from the root.
+    #[allow(clippy::too_many_arguments)]
+    fn remove_at<Q>(
+        &self,
+        inode: &INode<K, V>,
+        key: &Q,
+        hash: u64,
+        lev: u32,
+        parent: Option<&INode<K, V>>,
+        start_gen: Gen,
+        guard: &Guard,
+    ) -> Result<bool, ()>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let main = inode.gcas_read(self, guard);
+        match &main.kind {
+            Kind::C(cnode) => {
+                let (flag, pos) = cnode.flag_pos(hash, lev);
+                if cnode.bitmap & flag == 0 {
+                    return Ok(false);
+                }
+                let removed = match &cnode.array[pos] {
+                    Branch::I(sub) if sub.gen == start_gen => {
+                        self.remove_at(sub, key, hash, lev + BITS, Some(inode), start_gen, guard)
+                    }
+                    Branch::I(_) => {
+                        let renewed = cnode.renewed(start_gen, self, guard);
+                        if !inode.gcas(main, MainNode::new(Kind::C(renewed)), self, guard) {
+                            return Err(());
+                        }
+                        self.remove_at(inode, key, hash, lev, parent, start_gen, guard)
+                    }
+                    Branch::S(snode) if snode.hash == hash && snode.key.borrow() == key => {
+                        let new = cnode.removed_at(pos, flag, inode.gen).contracted(lev);
+                        if !inode.gcas(main, MainNode::new(new), self, guard) {
+                            return Err(());
+                        }
+                        Ok(true)
+                    }
+                    Branch::S(_) => Ok(false),
+                };
+                if let (Ok(true), Some(parent)) = (removed, parent) {
+                    if let Kind::T(tomb) = &inode.gcas_read(self, guard).kind {
+                        self.clean_parent(parent, inode, tomb, hash, lev, start_gen, guard);
+                    }
+                }
+                removed
+            }
+            Kind::T(_) => {
+                self.clean(parent, lev - BITS, guard);
+                Err(())
+            }
+            Kind::L(snodes) => {
+                if !snodes.iter().any(|snode| snode.key.borrow() == key) {
+                    return Ok(false);
+                }
+                let rest = snodes
+                    .iter()
+                    .filter(|snode| snode.key.borrow() != key)
+                    .cloned()
+                    .collect::<Box<[_]>>();
+                // A single entry is entombed, so that it is moved up by the next operation.
+                let new = if rest.len() == 1 {
+                    Kind::T(rest[0].clone())
+                } else {
+                    Kind::L(rest)
+                };
+                if !inode.gcas(main, MainNode::new(new), self, guard) {
+                    return Err(());
+                }
+                Ok(true)
+            }
+            Kind::Failed(_) => unreachable!("a failed node is never committed"),
+        }
+    }
+
+    /// Replaces `inode`, which is entombed with `tomb`, with the entry in `parent`.
+    #[allow(clippy::too_many_arguments)]
+    fn clean_parent(
+        &self,
+        parent: &INode<K, V>,
+        inode: &INode<K, V>,
+        tomb: &Arc<SNode<K, V>>,
+        hash: u64,
+        lev: u32,
+        start_gen: Gen,
+        guard: &Guard,
+    ) {
+        loop {
+            let main = parent.gcas_read(self, guard);
+            let cnode = match &main.kind {
+                Kind::C(cnode) => cnode,
+                _ => return,
+            };
+            let (flag, pos) = cnode.flag_pos(hash, lev - BITS);
+            if cnode.bitmap & flag == 0 {
+                return;
+            }
+            match &cnode.array[pos] {
+                Branch::I(sub) if ptr::eq(&**sub, inode) => {}
+                _ => return,
+            }
+            let new = cnode
+                .updated_at(pos, Branch::S(tomb.clone()), parent.gen)
+                .contracted(lev - BITS);
+            if parent.gcas(main, MainNode::new(new), self, guard)
+                || self.read_root(false, guard).gen != start_gen
+            {
+                return;
+            }
+        }
+    }
+
+    /// Removes `key`. Returns `false` if there is no entry for it.
+    pub fn remove<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let guard = pin();
+        let hash = self.hash(key);
+        loop {
+            let root = self.read_root(false, &guard);
+            if let Ok(removed) = self.remove_at(root, key, hash, 0, None, root.gen, &guard) {
+                return removed;
+            }
+        }
+    }
+}
+
+impl<K, V> Drop for Ctrie<K, V> {
+    fn drop(&mut self) {
+        let root = self.root.load(Ordering::Relaxed);
+        // SAFETY: No other thread accesses the map, so the root owns the reference or descriptor.
+        if is_descriptor(root) {
+            drop(unsafe { Box::from_raw((root as usize & !1) as *mut Descriptor<K, V>) });
+        } else {
+            drop(unsafe { Arc::from_raw(root) });
+        }
+    }
+}
+
+/// Entry of a `Ctrie` returned by its iterator.
+#[derive(Debug)]
+pub struct Entry<K, V> {
+    snode: Arc<SNode<K, V>>,
+}
+
+impl<K, V> Entry<K, V> {
+    /// Returns the key of the entry.
+    pub fn key(&self) -> &K {
+        &self.snode.key
+    }
+
+    /// Returns the value of the entry.
+    pub fn value(&self) -> &V {
+        &self.snode.value
+    }
+}
+
+/// Iterator over a snapshot of a `Ctrie`.
+pub struct Iter<K, V> {
+    snapshot: Ctrie<K, V>,
+    /// The main nodes on the way down to the next entry, with the index of their next branch.
+    stack: Vec<(Arc<MainNode<K, V>>, usize)>,
+}
+
+impl<K, V> fmt::Debug for Iter<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Iter").finish_non_exhaustive()
+    }
+}
+
+impl<K, V> Iterator for Iter<K, V> {
+    type Item = Entry<K, V>;
+
+    fn next(&mut self) -> Option<Entry<K, V>> {
+        let guard = pin();
+        loop {
+            let (main, index) = self.stack.last_mut()?;
+            let branch = match &main.kind {
+                Kind::C(cnode) => cnode.array.get(*index).cloned(),
+                Kind::T(snode) => (*index == 0).then(|| Branch::S(snode.clone())),
+                Kind::L(snodes) => snodes.get(*index).cloned().map(Branch::S),
+                Kind::Failed(_) => unreachable!("a failed node is never committed"),
+            };
+            *index += 1;
+            match branch {
+                None => {
+                    let _ = self.stack.pop();
+                }
+                Some(Branch::S(snode)) => return Some(Entry { snode }),
+                Some(Branch::I(inode)) => {
+                    let main = inode.gcas_read(&self.snapshot, &guard);
+                    // SAFETY: `main` is read while pinned from `inode.main`.
+                    self.stack.push((unsafe { clone_raw(main) }, 0));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,481 @@
+//! Concurrent bucketized cuckoo hash map.
+//!
+//! Each key has two candidate buckets of `SLOTS` slots each, and is in one of them. A lookup
+//! checks both, so it never searches a chain. When both buckets of a new key are full, the insert
+//! searches breadth-first for a path of entries, each of which can move to its other bucket, that
+//! ends at a free slot, and moves them along it from the end. With four slots per bucket, the
+//! table fills up to well over 90% before an insert finds no path and the table grows.
+//!
+//! Each bucket has its own lock. An operation on a key locks both of its buckets, in the order of
+//! their indices, and an entry moves between its buckets only with both of them locked, so a key
+//! is never seen twice or not at all while it moves. The table as a whole is behind a lock that
+//! the operations take for reading, and that growing takes for writing.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+use core::mem;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::RwLock;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::RwLock;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::RwLock;
+
+use crate::utils::{hash_of, HashBuilder};
+
+/// Number of the slots of a bucket.
+const SLOTS: usize = 4;
+
+/// Number of the buckets of `CuckooMap::new`.
+const DEFAULT_BUCKETS: usize = 16;
+
+/// Number of the buckets a search for a free slot visits before giving up, which makes the table
+/// grow.
+const MAX_SEARCHED_BUCKETS: usize = 512;
+
+struct Slot<K, V> {
+    /// Kept to find the other bucket of the entry without hashing the key again.
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+type Bucket<K, V> = [Option<Slot<K, V>>; SLOTS];
+
+/// A bucket a search for a free slot visited.
+#[derive(Clone, Copy)]
+struct Visit {
+    bucket: usize,
+    /// The visit of the bucket from which the search reached this one, and the slot of the entry
+    /// there whose other bucket this is. `None` for the buckets of the new key.
+    from: Option<(usize, usize)>,
+}
+
+struct Table<K, V> {
+    /// The number of the buckets is a power of two.
+    buckets: Box<[RwLock<Bucket<K, V>>]>,
+}
+
+impl<K, V> Table<K, V> {
+    fn new(buckets: usize) -> Self {
+        debug_assert!(buckets.is_power_of_two());
+        Self {
+            buckets: (0..buckets)
+                .map(|_| RwLock::new(Default::default()))
+                .collect(),
+        }
+    }
+
+    /// Returns the two buckets of the keys hashed to `hash`, which may be the same.
+    fn indices(&self, hash: u64) -> (usize, usize) {
+        let mask = self.buckets.len() - 1;
+        (hash as usize & mask, (hash >> 32) as usize & mask)
+    }
+
+    /// Returns the bucket of the keys hashed to `hash` other than `bucket`.
+    fn other(&self, hash: u64, bucket: usize) -> usize {
+        let (first, second) = self.indices(hash);
+        if bucket == first {
+            second
+        } else {
+            first
+        }
+    }
+
+    /// Calls `f` with the buckets `first` and `second` locked for reading, or `None` for the
+    /// second one if they are the same.
+    fn with_read<R>(
+        &self,
+        first: usize,
+        second: usize,
+        f: impl FnOnce(&Bucket<K, V>, Option<&Bucket<K, V>>) -> R,
+    ) -> R {
+        if first == second {
+            return f(&self.buckets[first].read().unwrap(), None);
+        }
+        let (low, high) = (first.min(second), first.max(second));
+        let low = self.buckets[low].read().unwrap();
+        let high = self.buckets[high].read().unwrap();
+        if first < second {
+            f(&low, Some(&high))
+        } else {
+            f(&high, Some(&low))
+        }
+    }
+
+    /// Same as `with_read`, but locks the buckets for writing.
+    fn with_write<R>(
+        &self,
+        first: usize,
+        second: usize,
+        f: impl FnOnce(&mut Bucket<K, V>, Option<&mut Bucket<K, V>>) -> R,
+    ) -> R {
+        if first == second {
+            return f(&mut self.buckets[first].write().unwrap(), None);
+        }
+        let (low, high) = (first.min(second), first.max(second));
+        let mut low = self.buckets[low].write().unwrap();
+        let mut high = self.buckets[high].write().unwrap();
+        if first < second {
+            f(&mut low, Some(&mut high))
+        } else {
+            f(&mut high, Some(&mut low))
+        }
+    }
+
+    /// Looks up `key` in its buckets, and calls `f` with its value.
+    fn lookup<Q, F, R>(&self, hash: u64, key: &Q, f: F) -> R
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+        F: FnOnce(Option<&V>) -> R,
+    {
+        let (first, second) = self.indices(hash);
+        self.with_read(first, second, |first, second| {
+            let value = first
+                .iter()
+                .chain(second.into_iter().flatten())
+                .flatten()
+                .find(|slot| slot.hash == hash && slot.key.borrow() == key)
+                .map(|slot| &slot.value);
+            f(value)
+        })
+    }
+
+    /// Inserts the entry, or replaces the value of the key and returns the old one. Returns the
+    /// entry back in `Err` if there is no room for it.
+    fn insert(&self, hash: u64, key: K, value: V) -> Result<Option<V>, (K, V)>
+    where
+        K: Eq,
+    {
+        let (first, second) = self.indices(hash);
+        let mut entry = (key, value);
+        loop {
+            entry = match self.with_write(first, second, |first, second| {
+                let (key, value) = entry;
+                let mut buckets = [Some(first), second];
+                let mut slots = buckets
+                    .iter_mut()
+                    .flatten()
+                    .flat_map(|bucket| bucket.iter_mut());
+                let mut free = None;
+                for slot in &mut slots {
+                    match slot {
+                        Some(slot) if slot.hash == hash && slot.key == key => {
+                            return Ok(Some(mem::replace(&mut slot.value, value)));
+                        }
+                        Some(_) => {}
+                        None => {
+                            if free.is_none() {
+                                free = Some(slot);
+                            }
+                        }
+                    }
+                }
+                match free {
+                    Some(free) => {
+                        *free = Some(Slot { hash, key, value });
+                        Ok(None)
+                    }
+                    None => Err((key, value)),
+                }
+            }) {
+                Ok(old) => return Ok(old),
+                Err(entry) => entry,
+            };
+
+            // Both buckets are full. Make room in one of them, and try again: other threads may
+            // take the room meanwhile.
+            let path = some_or!(self.search(first, second), return Err(entry));
+            let _ = self.displace(&path);
+        }
+    }
+
+    /// Searches breadth-first from the buckets `first` and `second` for a bucket with a free
+    /// slot. Returns the visits from one of the two buckets to the free slot, or `None` if there
+    /// is none near enough.
+    fn search(&self, first: usize, second: usize) -> Option<Vec<Visit>> {
+        let mut visits = vec![Visit {
+            bucket: first,
+            from: None,
+        }];
+        if second != first {
+            visits.push(Visit {
+                bucket: second,
+                from: None,
+            });
+        }
+
+        let mut next = 0;
+        while next < visits.len() && next < MAX_SEARCHED_BUCKETS {
+            let bucket = visits[next].bucket;
+            let found = {
+                let slots = self.buckets[bucket].read().unwrap();
+                let mut found = false;
+                for (index, slot) in slots.iter().enumerate() {
+                    match slot {
+                        None => {
+                            found = true;
+                            break;
+                        }
+                        Some(slot) => {
+                            let other = self.other(slot.hash, bucket);
+                            if other != bucket {
+                                visits.push(Visit {
+                                    bucket: other,
+                                    from: Some((next, index)),
+                                });
+                            }
+                        }
+                    }
+                }
+                found
+            };
+            if found {
+                // The path is the chain of visits that led here.
+                let mut path = Vec::new();
+                let mut visit = next;
+                loop {
+                    path.push(visits[visit]);
+                    visit = some_or!(visits[visit].from, break).0;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            next += 1;
+        }
+        None
+    }
+
+    /// Moves the entries along `path` from its end, each one to the bucket of the next visit.
+    /// Returns `false` if the path changed since the search and the moves stopped, with the
+    /// entries moved so far in their other buckets.
+    fn displace(&self, path: &[Visit]) -> bool {
+        for (index, visit) in path.iter().enumerate().skip(1).rev() {
+            // The entry of the slot of the previous visit moves to this one.
+            let (_, slot) = visit.from.unwrap();
+            let source = path[index - 1].bucket;
+            let moved = self.with_write(source, visit.bucket, |source_slots, target_slots| {
+                let target_slots = target_slots.unwrap();
+                // Any entry in the slot whose other bucket is the target can move.
+                match &source_slots[slot] {
+                    Some(entry) if self.other(entry.hash, source) == visit.bucket => {}
+                    _ => return false,
+                }
+                let free = some_or!(
+                    target_slots.iter_mut().find(|slot| slot.is_none()),
+                    return false
+                );
+                *free = source_slots[slot].take();
+                true
+            });
+            if !moved {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Removes the entry of `key`, and returns its value.
+    fn remove<Q>(&self, hash: u64, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let (first, second) = self.indices(hash);
+        self.with_write(first, second, |first, second| {
+            let mut buckets = [Some(first), second];
+            let slot = buckets
+                .iter_mut()
+                .flatten()
+                .flat_map(|bucket| bucket.iter_mut())
+                .find(|slot| {
+                    matches!(slot, Some(slot) if slot.hash == hash && slot.key.borrow() == key)
+                })?;
+            slot.take().map(|slot| slot.value)
+        })
+    }
+
+    /// Moves the entries out of the table.
+    fn into_slots(self) -> impl Iterator<Item = Slot<K, V>> {
+        self.buckets
+            .into_vec()
+            .into_iter()
+            .flat_map(|bucket| bucket.into_inner().unwrap())
+            .flatten()
+    }
+}
+
+/// A concurrent hash map that keeps each key in one of two buckets of a few slots, and moves keys
+/// to their other buckets to make room for new ones (cuckoo hashing).
+///
+/// A lookup locks the two buckets of the key for reading, so lookups scale with the number of
+/// threads as long as the writes are few, and the table fills up to well over 90% before it grows.
+/// Unlike the [`Ctrie`](crate::Ctrie) or the [`SplitOrderedList`](crate::SplitOrderedList), it
+/// neither chains the entries of a hash nor defers their reclamation, and the values are read in
+/// place under the bucket locks. Growing the table blocks all other operations.
+pub struct CuckooMap<K, V> {
+    table: RwLock<Table<K, V>>,
+    len: AtomicUsize,
+    hash_builder: HashBuilder,
+}
+
+impl<K, V> fmt::Debug for CuckooMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CuckooMap")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V> Default for CuckooMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> CuckooMap<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::with_buckets(DEFAULT_BUCKETS)
+    }
+
+    /// Creates an empty map that holds at least `capacity` entries without growing, if their
+    /// hashes allow.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_buckets(((capacity.max(1) - 1) / SLOTS + 1).next_power_of_two())
+    }
+
+    fn with_buckets(buckets: usize) -> Self {
+        Self {
+            table: RwLock::new(Table::new(buckets)),
+            len: AtomicUsize::new(0),
+            hash_builder: HashBuilder::default(),
+        }
+    }
+
+    /// Returns the number of the entries. It may be stale if other threads insert or remove
+    /// entries meanwhile.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of the slots of the table.
+    pub fn capacity(&self) -> usize {
+        self.table.read().unwrap().buckets.len() * SLOTS
+    }
+}
+
+impl<K: Hash + Eq, V> CuckooMap<K, V> {
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        hash_of(&self.hash_builder, key)
+    }
+
+    /// Looks up `key`, and calls `f` with its value, which stays locked until `f` returns.
+    pub fn lookup<Q, F, R>(&self, key: &Q, f: F) -> R
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(Option<&V>) -> R,
+    {
+        let hash = self.hash(key);
+        self.table.read().unwrap().lookup(hash, key, f)
+    }
+
+    /// Returns a clone of the value of `key`.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.lookup(key, |value| value.cloned())
+    }
+
+    /// Returns `true` if the map has an entry for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lookup(key, |value| value.is_some())
+    }
+
+    /// Inserts the entry, replacing the value of the key if it has one, which is returned. Grows
+    /// the table if there is no room for the entry.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let hash = self.hash(&key);
+        let mut entry = (key, value);
+        loop {
+            let table = self.table.read().unwrap();
+            match table.insert(hash, entry.0, entry.1) {
+                Ok(old) => {
+                    if old.is_none() {
+                        let _ = self.len.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return old;
+                }
+                Err(rejected) => entry = rejected,
+            }
+            let buckets = table.buckets.len();
+            drop(table);
+            self.grow(buckets);
+        }
+    }
+
+    /// Removes the entry of `key`, and returns its value.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash(key);
+        let value = self.table.read().unwrap().remove(hash, key)?;
+        let _ = self.len.fetch_sub(1, Ordering::Relaxed);
+        Some(value)
+    }
+
+    /// Doubles the table of `buckets` buckets, unless another thread already grew it, and again
+    /// until the entries fit.
+    fn grow(&self, buckets: usize) {
+        let mut table = self.table.write().unwrap();
+        if table.buckets.len() != buckets {
+            return;
+        }
+        let mut slots = mem::replace(&mut *table, Table::new(1))
+            .into_slots()
+            .collect::<Vec<_>>();
+        let mut buckets = buckets * 2;
+        'grow: loop {
+            let grown = Table::new(buckets);
+            while let Some(slot) = slots.pop() {
+                if let Err((key, value)) = grown.insert(slot.hash, slot.key, slot.value) {
+                    slots.push(Slot {
+                        hash: slot.hash,
+                        key,
+                        value,
+                    });
+                    slots.extend(grown.into_slots());
+                    buckets *= 2;
+                    continue 'grow;
+                }
+            }
+            *table = grown;
+            return;
+        }
+    }
+}
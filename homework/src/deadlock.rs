@@ -0,0 +1,460 @@
+//! Locks that check the order they are acquired in, to detect deadlocks.
+//!
+//! [`Mutex`] and [`RwLock`] wrap those of `std::sync` with the same interface. With the
+//! `deadlock-detection` feature, they record the locks each thread holds, and add an edge from
+//! each of them to a lock the thread acquires, so that the edges record the order the locks are
+//! acquired in. A thread that is about to acquire a lock in the order opposite to that of an
+//! earlier acquisition, which could deadlock with another thread acquiring them as before, panics
+//! with the cycle of the acquisitions, before it blocks. So a deadlock is reported even if the
+//! threads do not happen to interleave the way that deadlocks. Acquiring a lock that the thread
+//! already holds is reported as well.
+//!
+//! Read locks are ordered as write locks, as a reader waits for a writer that waits for another
+//! reader. Without the feature, the locks only forward to those of `std::sync`, or to those of
+//! loom with `check-loom`.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "deadlock-detection")]
+use core::panic::Location;
+use std::sync::{LockResult, PoisonError};
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync;
+#[cfg(feature = "check-loom")]
+use loom::sync;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync;
+
+#[cfg(feature = "deadlock-detection")]
+mod order {
+    use core::cell::RefCell;
+    use core::fmt::Write;
+    use core::panic::Location;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{Mutex, MutexGuard};
+
+    #[cfg(feature = "check-loom")]
+    use loom::thread_local;
+    #[cfg(not(feature = "check-loom"))]
+    use std::thread_local;
+
+    use once_cell::sync::Lazy;
+
+    /// Identifies a lock. Ids are not reused, unlike addresses.
+    pub(super) type Id = usize;
+
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    /// An acquisition of `to` while holding the lock the edge is from.
+    #[derive(Clone, Copy)]
+    struct Edge {
+        to: Id,
+        at: &'static Location<'static>,
+    }
+
+    #[derive(Default)]
+    struct Graph {
+        /// Where each live lock is created, which names it in the reports.
+        created: HashMap<Id, &'static Location<'static>>,
+        edges: HashMap<Id, Vec<Edge>>,
+        /// The locks with an edge to each lock, so that a lock is forgotten without a scan.
+        preds: HashMap<Id, Vec<Id>>,
+    }
+
+    impl Graph {
+        fn has_edge(&self, from: Id, to: Id) -> bool {
+            matches!(self.edges.get(&from), Some(edges) if edges.iter().any(|edge| edge.to == to))
+        }
+
+        /// Returns the edges of a path from `from` to `to`, if any.
+        fn path(&self, from: Id, to: Id) -> Option<Vec<(Id, Edge)>> {
+            // Depth-first search, recording the edge that reached each lock.
+            let mut reached = HashMap::new();
+            let mut visited = HashSet::from([from]);
+            let mut stack = vec![from];
+            while let Some(id) = stack.pop() {
+                for edge in self.edges.get(&id).into_iter().flatten() {
+                    if !visited.insert(edge.to) {
+                        continue;
+                    }
+                    let _ = reached.insert(edge.to, (id, *edge));
+                    if edge.to == to {
+                        let mut path = Vec::new();
+                        let mut id = to;
+                        while id != from {
+                            let (prev, edge) = reached[&id];
+                            path.push((prev, edge));
+                            id = prev;
+                        }
+                        path.reverse();
+                        return Some(path);
+                    }
+                    stack.push(edge.to);
+                }
+            }
+            None
+        }
+
+        fn name(&self, id: Id) -> String {
+            match self.created.get(&id) {
+                Some(created) => format!("lock #{id} (created at {created})"),
+                None => format!("lock #{id}"),
+            }
+        }
+    }
+
+    static GRAPH: Lazy<Mutex<Graph>> = Lazy::new(Default::default);
+
+    thread_local! {
+        /// The locks the thread holds, in the order it acquired them.
+        static HELD: RefCell<Vec<(Id, &'static Location<'static>)>> = RefCell::default();
+    }
+
+    fn graph() -> MutexGuard<'static, Graph> {
+        // The graph is consistent even if a thread panicked while holding it.
+        GRAPH.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(super) fn register(created: &'static Location<'static>) -> Id {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let _ = graph().created.insert(id, created);
+        id
+    }
+
+    /// Forgets the lock, so that the graph only has the live locks.
+    pub(super) fn unregister(id: Id) {
+        let mut graph = graph();
+        let graph = &mut *graph;
+        let _ = graph.created.remove(&id);
+        for edge in graph.edges.remove(&id).into_iter().flatten() {
+            if let Some(preds) = graph.preds.get_mut(&edge.to) {
+                preds.retain(|pred| *pred != id);
+            }
+        }
+        for pred in graph.preds.remove(&id).into_iter().flatten() {
+            if let Some(edges) = graph.edges.get_mut(&pred) {
+                edges.retain(|edge| edge.to != id);
+            }
+        }
+    }
+
+    /// Records that the thread is about to acquire `id` at `at`. Panics with the cycle if it is
+    /// acquired in the order opposite to an earlier acquisition, or if the thread already holds it.
+    pub(super) fn acquire(id: Id, at: &'static Location<'static>) {
+        let held = HELD.with(|held| held.borrow().clone());
+        let mut graph = graph();
+        let mut report = None;
+        for &(holding, held_at) in &held {
+            if holding == id {
+                report = Some(format!(
+                    "deadlock: {} acquired at {at} is already held since {held_at}",
+                    graph.name(id),
+                ));
+                break;
+            }
+            // An edge already recorded closed no cycle when it was added.
+            if graph.has_edge(holding, id) {
+                continue;
+            }
+            if let Some(path) = graph.path(id, holding) {
+                let mut cycle = String::from("deadlock: lock order cycle detected:\n");
+                for (from, edge) in path {
+                    let _ = writeln!(
+                        cycle,
+                        "  {} acquired at {} while holding {}",
+                        graph.name(edge.to),
+                        edge.at,
+                        graph.name(from),
+                    );
+                }
+                let _ = write!(
+                    cycle,
+                    "  {} acquired at {at} while holding {} (this thread)",
+                    graph.name(id),
+                    graph.name(holding),
+                );
+                report = Some(cycle);
+                break;
+            }
+        }
+        if report.is_none() {
+            for &(holding, _) in &held {
+                if !graph.has_edge(holding, id) {
+                    graph
+                        .edges
+                        .entry(holding)
+                        .or_default()
+                        .push(Edge { to: id, at });
+                    graph.preds.entry(id).or_default().push(holding);
+                }
+            }
+        }
+        // Panics without holding the graph, so that the other threads go on checking.
+        drop(graph);
+        if let Some(report) = report {
+            panic!("{report}");
+        }
+        HELD.with(|held| held.borrow_mut().push((id, at)));
+    }
+
+    /// Records that the thread released `id`, not necessarily the last lock it acquired.
+    pub(super) fn release(id: Id) {
+        // The thread-locals may be already destroyed if a guard is dropped while the thread exits.
+        let _ = HELD.try_with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(pos) = held.iter().rposition(|(holding, _)| *holding == id) {
+                let _ = held.remove(pos);
+            }
+        });
+    }
+}
+
+/// The identity of a lock in the order of acquisitions, which is empty without the
+/// `deadlock-detection` feature.
+#[derive(Debug)]
+struct Tracked {
+    #[cfg(feature = "deadlock-detection")]
+    id: order::Id,
+}
+
+impl Tracked {
+    #[track_caller]
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "deadlock-detection")]
+            id: order::register(Location::caller()),
+        }
+    }
+
+    #[track_caller]
+    fn acquire(&self) -> Held {
+        #[cfg(feature = "deadlock-detection")]
+        order::acquire(self.id, Location::caller());
+        Held {
+            #[cfg(feature = "deadlock-detection")]
+            id: self.id,
+        }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        #[cfg(feature = "deadlock-detection")]
+        order::unregister(self.id);
+    }
+}
+
+/// A lock held by the thread, which is released when dropped.
+struct Held {
+    #[cfg(feature = "deadlock-detection")]
+    id: order::Id,
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        #[cfg(feature = "deadlock-detection")]
+        order::release(self.id);
+    }
+}
+
+/// Wraps the guard of a result as `Guard`, keeping it poisoned.
+fn map_result<G, T>(result: LockResult<G>, f: impl FnOnce(G) -> T) -> LockResult<T> {
+    match result {
+        Ok(guard) => Ok(f(guard)),
+        Err(e) => Err(PoisonError::new(f(e.into_inner()))),
+    }
+}
+
+/// A mutual exclusion lock, [`std::sync::Mutex`] that checks the order it is acquired in.
+pub struct Mutex<T> {
+    tracked: Tracked,
+    inner: sync::Mutex<T>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> Mutex<T> {
+    /// Creates a mutex holding `t`. With the `deadlock-detection` feature, the reports name the
+    /// mutex by where it is created.
+    #[track_caller]
+    pub fn new(t: T) -> Self {
+        Self {
+            tracked: Tracked::new(),
+            inner: sync::Mutex::new(t),
+        }
+    }
+
+    /// Consumes the mutex and returns its value.
+    pub fn into_inner(self) -> LockResult<T> {
+        let Self { tracked, inner } = self;
+        drop(tracked);
+        inner.into_inner()
+    }
+
+    /// Acquires the mutex, blocking until it is available. With the `deadlock-detection` feature,
+    /// panics instead if that could deadlock.
+    #[track_caller]
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        let held = self.tracked.acquire();
+        map_result(self.inner.lock(), |inner| MutexGuard { inner, held })
+    }
+
+    /// Returns `true` if a thread panicked while holding the mutex.
+    pub fn is_poisoned(&self) -> bool {
+        #[cfg(not(feature = "check-loom"))]
+        let poisoned = self.inner.is_poisoned();
+        // The locks of loom are never poisoned, as a panic fails the model.
+        #[cfg(feature = "check-loom")]
+        let poisoned = false;
+        poisoned
+    }
+}
+
+/// Guard of a [`Mutex`].
+pub struct MutexGuard<'a, T> {
+    inner: sync::MutexGuard<'a, T>,
+    held: Held,
+}
+
+impl<T: fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.inner, f)
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+/// A reader-writer lock, [`std::sync::RwLock`] that checks the order it is acquired in.
+pub struct RwLock<T> {
+    tracked: Tracked,
+    inner: sync::RwLock<T>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> RwLock<T> {
+    /// Creates a lock holding `t`. With the `deadlock-detection` feature, the reports name the
+    /// lock by where it is created.
+    #[track_caller]
+    pub fn new(t: T) -> Self {
+        Self {
+            tracked: Tracked::new(),
+            inner: sync::RwLock::new(t),
+        }
+    }
+
+    /// Consumes the lock and returns its value.
+    pub fn into_inner(self) -> LockResult<T> {
+        let Self { tracked, inner } = self;
+        drop(tracked);
+        inner.into_inner()
+    }
+
+    /// Acquires the lock for reading, blocking until there is no writer. With the
+    /// `deadlock-detection` feature, panics instead if that could deadlock.
+    #[track_caller]
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        let held = self.tracked.acquire();
+        map_result(self.inner.read(), |inner| RwLockReadGuard { inner, held })
+    }
+
+    /// Acquires the lock for writing, blocking until there is no other reader or writer. With the
+    /// `deadlock-detection` feature, panics instead if that could deadlock.
+    #[track_caller]
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        let held = self.tracked.acquire();
+        map_result(self.inner.write(), |inner| RwLockWriteGuard { inner, held })
+    }
+
+    /// Returns `true` if a thread panicked while holding the lock for writing.
+    pub fn is_poisoned(&self) -> bool {
+        #[cfg(not(feature = "check-loom"))]
+        let poisoned = self.inner.is_poisoned();
+        // The locks of loom are never poisoned, as a panic fails the model.
+        #[cfg(feature = "check-loom")]
+        let poisoned = false;
+        poisoned
+    }
+}
+
+/// Read guard of a [`RwLock`].
+pub struct RwLockReadGuard<'a, T> {
+    inner: sync::RwLockReadGuard<'a, T>,
+    held: Held,
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.inner, f)
+    }
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Write guard of a [`RwLock`].
+pub struct RwLockWriteGuard<'a, T> {
+    inner: sync::RwLockWriteGuard<'a, T>,
+    held: Held,
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.inner, f)
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,267 @@
+//! Chase-Lev work-stealing deque.
+//!
+//! The orderings follow Lê et al., "Correct and Efficient Work-Stealing for Weak Memory Models"
+//! (PPoPP 2013).
+
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+use std::sync::Arc;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicIsize, AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{fence, AtomicIsize, AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{fence, AtomicIsize, AtomicPtr, Ordering};
+
+use crate::hazard_pointer::{retire, Shield};
+use crate::CachePadded;
+
+/// Circular array of slots, whose length is a power of two.
+///
+/// The slots do not own their values: a buffer replaced by a larger one still has copies of the
+/// values moved to the new one, which thieves may read until it is reclaimed.
+#[derive(Debug)]
+struct Buffer<T> {
+    slots: Slots<T>,
+}
+
+/// The slots are on the NUMA node of the worker with `numa`, as it is the one that mostly uses
+/// them.
+#[cfg(all(feature = "numa", target_os = "linux"))]
+type Slots<T> = crate::numa::NodeBox<[UnsafeCell<MaybeUninit<T>>]>;
+#[cfg(not(all(feature = "numa", target_os = "linux")))]
+type Slots<T> = Box<[UnsafeCell<MaybeUninit<T>>]>;
+
+impl<T> Buffer<T> {
+    fn new(capacity: usize) -> *mut Self {
+        debug_assert!(capacity.is_power_of_two());
+        Box::into_raw(Box::new(Self {
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+        }))
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the slot at `index`, modulo the capacity.
+    fn at(&self, index: isize) -> *mut MaybeUninit<T> {
+        self.slots[index as usize & (self.capacity() - 1)].get()
+    }
+}
+
+/// State shared by the worker and the stealers.
+///
+/// The values in the deque are at the indices `top..bottom` of `buffer`. Thieves take values from
+/// the top by incrementing `top`, and the worker pushes and pops at the bottom.
+#[derive(Debug)]
+struct Inner<T> {
+    top: CachePadded<AtomicIsize>,
+    bottom: CachePadded<AtomicIsize>,
+    /// Replaced only by the worker, which retires the old one.
+    buffer: AtomicPtr<Buffer<T>>,
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let top = self.top.load(Ordering::Relaxed);
+        let bottom = self.bottom.load(Ordering::Relaxed);
+        // SAFETY: No other thread accesses the deque, and the current buffer is not retired.
+        let buffer = unsafe { Box::from_raw(self.buffer.load(Ordering::Relaxed)) };
+        for index in top..bottom {
+            // SAFETY: The values at `top..bottom` are pushed and not taken.
+            unsafe { (*buffer.at(index)).assume_init_drop() };
+        }
+    }
+}
+
+/// Result of a steal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Steal<T> {
+    /// The deque is empty.
+    Empty,
+    /// A value is stolen.
+    Success(T),
+    /// Another thread took the value first. The deque may still have more values.
+    Retry,
+}
+
+/// Owner of a work-stealing deque, which pushes and pops values at the bottom in LIFO order.
+#[derive(Debug)]
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    /// The current buffer, which only this worker replaces.
+    buffer: Cell<*mut Buffer<T>>,
+}
+
+/// Thief of a work-stealing deque, which steals values from the top in FIFO order.
+#[derive(Debug)]
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+// The values are moved between threads, one thread at a time.
+unsafe impl<T: Send> Send for Worker<T> {}
+unsafe impl<T: Send> Send for Stealer<T> {}
+unsafe impl<T: Send> Sync for Stealer<T> {}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Default for Worker<T> {
+    fn default() -> Self {
+        Self::with_capacity(Self::MIN_CAPACITY)
+    }
+}
+
+impl<T> Worker<T> {
+    /// Default capacity of the buffer.
+    pub const MIN_CAPACITY: usize = 16;
+
+    /// Creates an empty deque whose buffer initially holds `capacity` values, rounded up to a
+    /// power of two. The buffer grows as needed.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let buffer = Buffer::new(capacity.max(1).next_power_of_two());
+        Self {
+            inner: Arc::new(Inner {
+                top: CachePadded::new(AtomicIsize::new(0)),
+                bottom: CachePadded::new(AtomicIsize::new(0)),
+                buffer: AtomicPtr::new(buffer),
+            }),
+            buffer: Cell::new(buffer),
+        }
+    }
+
+    /// Creates a stealer of this deque.
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Replaces the buffer with one of `capacity`, moving the values at `top..bottom`.
+    fn resize(&self, top: isize, bottom: isize, capacity: usize) {
+        let old = self.buffer.get();
+        let new = Buffer::new(capacity);
+        for index in top..bottom {
+            // SAFETY: Only the worker writes to the buffers, and `new` is not published yet.
+            unsafe {
+                (*new)
+                    .at(index)
+                    .copy_from_nonoverlapping((*old).at(index), 1)
+            };
+        }
+        self.buffer.set(new);
+        // Release: the values are copied before the buffer is published.
+        self.inner.buffer.store(new, Ordering::Release);
+        // SAFETY: `old` is detached above and retired only once. Dropping it does not drop the
+        // values.
+        unsafe { retire(old) };
+    }
+
+    /// Pushes `t` to the bottom of the deque, growing the buffer if it is full.
+    pub fn push(&self, t: T) {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        let top = self.inner.top.load(Ordering::Acquire);
+        // SAFETY: The current buffer is retired only by this worker.
+        let capacity = unsafe { (*self.buffer.get()).capacity() };
+        if bottom - top >= capacity as isize {
+            self.resize(top, bottom, capacity * 2);
+        }
+
+        // SAFETY: The slot is outside `top..bottom`, so no thief takes a value from it. A thief
+        // with a stale `top` may read it, but then its CAS on `top` fails and it ignores the value.
+        unsafe { (*(*self.buffer.get()).at(bottom)).write(t) };
+        // Release: the value is written before it is handed over.
+        fence(Ordering::Release);
+        self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+    }
+
+    /// Pops the value at the bottom of the deque. Returns `None` if the deque is empty.
+    pub fn pop(&self) -> Option<T> {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed) - 1;
+        self.inner.bottom.store(bottom, Ordering::Relaxed);
+        // SeqCst: either the thieves see the new `bottom` and back off from the last value, or this
+        // thread sees their `top` and races with them for it.
+        fence(Ordering::SeqCst);
+        let top = self.inner.top.load(Ordering::Relaxed);
+
+        if top > bottom {
+            self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            return None;
+        }
+
+        // SAFETY: The current buffer is retired only by this worker.
+        let t = unsafe { (*self.buffer.get()).at(bottom).read() };
+        if top < bottom {
+            // SAFETY: The value at `bottom` is pushed, and no thief takes it unless it is the last.
+            return Some(unsafe { t.assume_init() });
+        }
+
+        // The last value. Races with the thieves by incrementing `top` as they do.
+        let won = self
+            .inner
+            .top
+            .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+            .is_ok();
+        self.inner.bottom.store(bottom + 1, Ordering::Relaxed);
+        // SAFETY: If the CAS succeeded, this thread took the value. Otherwise a thief did.
+        won.then(|| unsafe { t.assume_init() })
+    }
+
+    /// Returns the number of values in the deque.
+    pub fn len(&self) -> usize {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        let top = self.inner.top.load(Ordering::Relaxed);
+        (bottom - top).max(0) as usize
+    }
+
+    /// Returns `true` if the deque is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Stealer<T> {
+    /// Steals the value at the top of the deque with a single CAS.
+    pub fn steal(&self) -> Steal<T> {
+        let top = self.inner.top.load(Ordering::Acquire);
+        // SeqCst: pairs with the fence in `Worker::pop`.
+        fence(Ordering::SeqCst);
+        let bottom = self.inner.bottom.load(Ordering::Acquire);
+        if top >= bottom {
+            return Steal::Empty;
+        }
+
+        let shield = Shield::default();
+        let buffer = shield.protect(&self.inner.buffer);
+        // SAFETY: `buffer` is protected and validated. The slot may be taken and overwritten
+        // meanwhile, so it is copied without assuming that it is initialized.
+        let t = unsafe { (*buffer).at(top).read() };
+        if self
+            .inner
+            .top
+            .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            return Steal::Retry;
+        }
+        // SAFETY: The CAS above took the value at `top`, which was pushed.
+        Steal::Success(unsafe { t.assume_init() })
+    }
+
+    /// Returns `true` if the deque is empty.
+    pub fn is_empty(&self) -> bool {
+        let top = self.inner.top.load(Ordering::Acquire);
+        let bottom = self.inner.bottom.load(Ordering::Acquire);
+        top >= bottom
+    }
+}
@@ -0,0 +1,836 @@
+//! Lock-free doubly linked list with hazard pointers.
+//!
+//! Sundell and Tsigas, "Lock-free deques and doubly linked lists", JPDC 2008. The next links form
+//! the list, while the prev links are hints that may lag behind, which the operations correct as
+//! they pass by (`correct_prev`, "HelpInsert" in the paper). A node is removed by marking its next
+//! link and then its prev link, after which it is unlinked from the next links.
+//!
+//! The operations follow the links of removed nodes, which may point to other removed nodes. So,
+//! as in the paper, each node counts the links that point to it, and is retired once none does.
+//! A retired node clears its own links, so that a node loaded from a link is not retired as long
+//! as the link still points to it, which validates the hazard pointer protecting it. The thread
+//! that removes a node points its links past the removed neighbors (`remove_cross_reference`),
+//! which breaks the cycles of removed nodes pointing to each other.
+
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use std::collections::HashSet;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::backoff::Backoff;
+use crate::hazard_pointer::{retire, Shield};
+
+/// Returns `true` if the link belongs to a removed node.
+fn is_marked<T>(link: *mut T) -> bool {
+    link as usize & 1 == 1
+}
+
+fn marked<T>(link: *mut T) -> *mut T {
+    (link as usize | 1) as *mut T
+}
+
+fn unmarked<T>(link: *mut T) -> *mut T {
+    (link as usize & !1) as *mut T
+}
+
+#[derive(Debug)]
+struct Node<T> {
+    /// `None` for the head and the tail.
+    value: Option<T>,
+    /// Number of the links pointing to the node, plus one for the thread inserting it and for
+    /// each cursor on it. Once it drops to zero, it never increases again.
+    refs: AtomicUsize,
+    /// Marked once the node is removed. Null once the node is retired.
+    prev: AtomicPtr<Node<T>>,
+    /// Marked when the node is removed. Null once the node is retired, and for the tail.
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(value: Option<T>, refs: usize) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            value,
+            refs: AtomicUsize::new(refs),
+            prev: AtomicPtr::new(ptr::null_mut()),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+
+    /// Returns `true` if the node is removed, or is retired.
+    fn is_removed(&self) -> bool {
+        let next = self.next.load(Ordering::Acquire);
+        is_marked(next) || next.is_null() && self.value.is_some()
+    }
+
+    /// Takes a reference to the node. Fails if it has none left, i.e., it is retired.
+    fn acquire(&self) -> bool {
+        let mut refs = self.refs.load(Ordering::Relaxed);
+        loop {
+            if refs == 0 {
+                return false;
+            }
+            match self
+                .refs
+                .compare_exchange(refs, refs + 1, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => return true,
+                Err(current) => refs = current,
+            }
+        }
+    }
+
+    /// Drops a reference to `node`. A node left without references is retired, which drops the
+    /// references of its links, and so on.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be valid, and the reference must be owned by the caller.
+    unsafe fn release(node: *mut Self) {
+        let mut pending = Vec::new();
+        let mut node = node;
+        loop {
+            // AcqRel: the thread that retires the node sees the accesses of the others, as with
+            // `Arc`.
+            if (*node).refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+                for link in [&(*node).prev, &(*node).next] {
+                    let target = unmarked(link.swap(ptr::null_mut(), Ordering::AcqRel));
+                    if !target.is_null() {
+                        pending.push(target);
+                    }
+                }
+                retire(node);
+            }
+            node = some_or!(pending.pop(), return);
+        }
+    }
+
+    /// Marks the prev link, after the next link is marked.
+    fn mark_prev(&self) {
+        let mut prev = self.prev.load(Ordering::Relaxed);
+        while !is_marked(prev) && !prev.is_null() {
+            match self.prev.compare_exchange(
+                prev,
+                marked(prev),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(current) => prev = current,
+            }
+        }
+    }
+}
+
+/// Points `link` at `new` if it points at `old`, moving the reference the link counts. Fails if
+/// `link` does not point at `old`, or `new` is retired.
+///
+/// # Safety
+///
+/// `link` must be in a node that is not freed, and the node `new` points to must be protected.
+unsafe fn swing<T>(link: &AtomicPtr<Node<T>>, old: *mut Node<T>, new: *mut Node<T>) -> bool {
+    if !(*unmarked(new)).acquire() {
+        return false;
+    }
+    // Release: a new node is initialized before it is published. Acquire: the old node is
+    // released after this thread is done with it.
+    if link
+        .compare_exchange(old, new, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+    {
+        Node::release(unmarked(old));
+        true
+    } else {
+        Node::release(unmarked(new));
+        false
+    }
+}
+
+/// Points a link of a node that is not inserted yet at `target`. Fails if `target` is retired.
+///
+/// # Safety
+///
+/// `target` must be protected.
+unsafe fn relink<T>(link: &AtomicPtr<Node<T>>, target: *mut Node<T>) -> bool {
+    if !(*target).acquire() {
+        return false;
+    }
+    let old = link.swap(target, Ordering::Relaxed);
+    if !old.is_null() {
+        Node::release(unmarked(old));
+    }
+    true
+}
+
+/// Node in a local variable, protected by a shield. Null until it is loaded.
+#[derive(Debug)]
+struct Protected<T> {
+    node: *mut Node<T>,
+    shield: Shield<Node<T>>,
+}
+
+impl<T> Protected<T> {
+    fn new() -> Self {
+        Self {
+            node: ptr::null_mut(),
+            shield: Shield::default(),
+        }
+    }
+
+    /// Protects `node`, which the caller keeps from being retired, e.g., by owning a reference
+    /// or by protecting it with another shield.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be valid, and must not be retired.
+    unsafe fn with(node: *mut Node<T>) -> Self {
+        let protected = Self::new();
+        protected.shield.set(node);
+        Self { node, ..protected }
+    }
+
+    /// Loads `link` and protects the node it points to. Returns the link, which is null if the
+    /// node of the link is retired.
+    fn load(&mut self, link: &AtomicPtr<Node<T>>) -> *mut Node<T> {
+        let mut current = link.load(Ordering::Acquire);
+        loop {
+            self.shield.set(unmarked(current));
+            // The node is not retired while the link still points to it and counts a reference
+            // to it.
+            let validated = link.load(Ordering::Acquire);
+            if validated == current {
+                self.node = unmarked(current);
+                return current;
+            }
+            current = validated;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.node = ptr::null_mut();
+        self.shield.clear();
+    }
+
+    fn node(&self) -> &Node<T> {
+        debug_assert!(!self.node.is_null());
+        // SAFETY: The node is protected, and was not retired when it was protected.
+        unsafe { &*self.node }
+    }
+}
+
+/// Lock-free doubly linked list, which is a deque whose ends and inner nodes can be pushed to and
+/// removed from concurrently.
+///
+/// The values are dropped when their nodes are reclaimed, so that the cursors can read them
+/// concurrently with their removal. `pop_front` and `pop_back` return an [`Entry`] which keeps
+/// the value from being reclaimed.
+#[derive(Debug)]
+pub struct DList<T> {
+    head: *mut Node<T>,
+    tail: *mut Node<T>,
+}
+
+// The values are shared with the cursors, and dropped by whichever thread frees the node.
+unsafe impl<T: Send + Sync> Send for DList<T> {}
+unsafe impl<T: Send + Sync> Sync for DList<T> {}
+
+impl<T> Default for DList<T> {
+    fn default() -> Self {
+        // The list owns a reference to its head and tail, which are never retired.
+        let head = Node::new(None, 2);
+        let tail = Node::new(None, 2);
+        // SAFETY: The nodes are not shared yet.
+        unsafe {
+            (*head).next = AtomicPtr::new(tail);
+            (*tail).prev = AtomicPtr::new(head);
+        }
+        Self { head, tail }
+    }
+}
+
+impl<T> DList<T> {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn head(&self) -> Protected<T> {
+        // SAFETY: The list owns a reference to the head.
+        unsafe { Protected::with(self.head) }
+    }
+
+    fn tail(&self) -> Protected<T> {
+        // SAFETY: The list owns a reference to the tail.
+        unsafe { Protected::with(self.tail) }
+    }
+
+    /// Returns `true` if the list has no value.
+    pub fn is_empty(&self) -> bool {
+        // SAFETY: The list owns a reference to the head.
+        unmarked(unsafe { &*self.head }.next.load(Ordering::Acquire)) == self.tail
+    }
+
+    /// Returns a cursor before the first value.
+    pub fn cursor_front(&self) -> Cursor<'_, T> {
+        Cursor::new(self, self.head)
+    }
+
+    /// Returns a cursor after the last value.
+    pub fn cursor_back(&self) -> Cursor<'_, T> {
+        Cursor::new(self, self.tail)
+    }
+
+    /// Adds `value` to the front.
+    pub fn push_front(&self, value: T) {
+        // The inserting thread owns a reference to the node.
+        let node = Node::new(Some(value), 1);
+        // SAFETY: Same as above.
+        let node = unsafe { Protected::with(node) };
+        let linked = self.link_after(&self.head(), &node);
+        debug_assert!(linked);
+        // SAFETY: The inserting thread owned the reference.
+        unsafe { Node::release(node.node) };
+    }
+
+    /// Adds `value` to the back.
+    pub fn push_back(&self, value: T) {
+        let node = Node::new(Some(value), 1);
+        // SAFETY: The inserting thread owns a reference to the node.
+        let node = unsafe { Protected::with(node) };
+        let linked = self.link_before(&self.tail(), &node);
+        debug_assert!(linked);
+        // SAFETY: The inserting thread owned the reference.
+        unsafe { Node::release(node.node) };
+    }
+
+    /// Removes the first value. Returns `None` if the list is empty.
+    pub fn pop_front(&self) -> Option<Entry<'_, T>> {
+        let head = self.head();
+        let mut node = Protected::new();
+        let backoff = Backoff::new();
+        loop {
+            // The head is never removed.
+            let _ = node.load(&head.node().next);
+            if node.node == self.tail {
+                return None;
+            }
+            let next = node.node().next.load(Ordering::Acquire);
+            if is_marked(next) || next.is_null() {
+                self.help_delete(&node);
+                continue;
+            }
+            if node
+                .node()
+                .next
+                .compare_exchange(next, marked(next), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.finish_remove(&node);
+                return Some(Entry::new(node));
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Removes the last value. Returns `None` if the list is empty.
+    pub fn pop_back(&self) -> Option<Entry<'_, T>> {
+        let tail = self.tail();
+        let mut node = Protected::new();
+        let _ = node.load(&tail.node().prev);
+        let backoff = Backoff::new();
+        loop {
+            if node.node().next.load(Ordering::Acquire) != self.tail {
+                self.correct_prev(&mut node, &tail);
+                continue;
+            }
+            if node.node == self.head {
+                return None;
+            }
+            if node
+                .node()
+                .next
+                .compare_exchange(
+                    self.tail,
+                    marked(self.tail),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                self.finish_remove(&node);
+                return Some(Entry::new(node));
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Links `node`, which is not inserted yet, right after `prev`. Fails if `prev` is removed.
+    fn link_after(&self, prev: &Protected<T>, node: &Protected<T>) -> bool {
+        let mut next = Protected::new();
+        let backoff = Backoff::new();
+        // SAFETY: `prev` is protected.
+        if !unsafe { relink(&node.node().prev, prev.node) } {
+            return false;
+        }
+        loop {
+            let link = next.load(&prev.node().next);
+            if is_marked(link) || link.is_null() {
+                return false;
+            }
+            // SAFETY: `next` and `node` are protected.
+            if unsafe {
+                relink(&node.node().next, next.node)
+                    && swing(&prev.node().next, next.node, node.node)
+            } {
+                break;
+            }
+            backoff.spin();
+        }
+        self.link_prev(node, &next);
+        true
+    }
+
+    /// Links `node`, which is not inserted yet, right before `next`. Fails if `next` is removed.
+    fn link_before(&self, next: &Protected<T>, node: &Protected<T>) -> bool {
+        let mut prev = Protected::new();
+        let backoff = Backoff::new();
+        // SAFETY: `next` is protected.
+        if !unsafe { relink(&node.node().next, next.node) } {
+            return false;
+        }
+        if prev.load(&next.node().prev).is_null() {
+            return false;
+        }
+        loop {
+            if next.node().is_removed() {
+                return false;
+            }
+            if prev.node().next.load(Ordering::Acquire) != next.node {
+                self.correct_prev(&mut prev, next);
+                continue;
+            }
+            // SAFETY: `prev` and `node` are protected.
+            if unsafe {
+                relink(&node.node().prev, prev.node)
+                    && swing(&prev.node().next, next.node, node.node)
+            } {
+                break;
+            }
+            backoff.spin();
+        }
+        self.link_prev(node, next);
+        true
+    }
+
+    /// Points the prev link of `next` at `node`, which was just linked before it ("PushCommon" in
+    /// the paper).
+    fn link_prev(&self, node: &Protected<T>, next: &Protected<T>) {
+        let backoff = Backoff::new();
+        loop {
+            let link = next.node().prev.load(Ordering::Acquire);
+            if is_marked(link)
+                || link.is_null()
+                || node.node().next.load(Ordering::Acquire) != next.node
+            {
+                return;
+            }
+            // SAFETY: `next` and `node` are protected.
+            if unsafe { swing(&next.node().prev, link, node.node) } {
+                if is_marked(node.node().prev.load(Ordering::Acquire)) {
+                    // SAFETY: `node` is protected.
+                    let mut prev = unsafe { Protected::with(node.node) };
+                    self.correct_prev(&mut prev, next);
+                }
+                return;
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Points the prev link of `node` at the node right before it, searching from `prev`, and
+    /// leaves `prev` there. Gives up if `node` is removed ("HelpInsert" in the paper).
+    ///
+    /// The search goes back along the prev links while `prev` is removed, and forward along the
+    /// next links until it reaches `node`. If `prev` is retired or the search passes `node`, it
+    /// starts over from the head.
+    fn correct_prev(&self, prev: &mut Protected<T>, node: &Protected<T>) {
+        let mut last = Protected::new();
+        let mut prev_next = Protected::new();
+        let backoff = Backoff::new();
+        loop {
+            let link = prev_next.load(&prev.node().next);
+            if link.is_null() {
+                *prev = self.head();
+                last.clear();
+                continue;
+            }
+            if is_marked(link) {
+                if last.node.is_null() {
+                    if prev_next.load(&prev.node().prev).is_null() {
+                        *prev = self.head();
+                    } else {
+                        mem::swap(prev, &mut prev_next);
+                    }
+                } else {
+                    // Unlinks `prev`, which is removed.
+                    prev.node().mark_prev();
+                    // SAFETY: `last` and `prev_next` are protected.
+                    let _ = unsafe { swing(&last.node().next, prev.node, prev_next.node) };
+                    mem::swap(prev, &mut last);
+                    last.clear();
+                }
+                continue;
+            }
+            let node_prev = node.node().prev.load(Ordering::Acquire);
+            if is_marked(node_prev) || node_prev.is_null() {
+                return;
+            }
+            if prev_next.node != node.node {
+                mem::swap(&mut last, prev);
+                mem::swap(prev, &mut prev_next);
+                continue;
+            }
+            if node_prev == prev.node {
+                return;
+            }
+            // SAFETY: `prev` is protected.
+            if unsafe { swing(&node.node().prev, node_prev, prev.node) } {
+                if is_marked(prev.node().prev.load(Ordering::Acquire)) {
+                    continue;
+                }
+                return;
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Unlinks `node`, whose next link is marked ("HelpDelete" in the paper).
+    fn help_delete(&self, node: &Protected<T>) {
+        node.node().mark_prev();
+        let mut last = Protected::new();
+        let mut prev = Protected::new();
+        let mut next = Protected::new();
+        let mut scratch = Protected::new();
+        // A retired node is unlinked.
+        if prev.load(&node.node().prev).is_null() || next.load(&node.node().next).is_null() {
+            return;
+        }
+        let backoff = Backoff::new();
+        loop {
+            // The search passed `node`, which is unlinked.
+            if prev.node == next.node || prev.node == self.tail {
+                return;
+            }
+            // Skips the removed nodes after `node`.
+            let link = scratch.load(&next.node().next);
+            if is_marked(link) {
+                next.node().mark_prev();
+                mem::swap(&mut next, &mut scratch);
+                continue;
+            }
+            if link.is_null() && next.node != self.tail {
+                // `next` is retired after it was unlinked. Starts over from the node after `node`,
+                // unless `node` is retired as well.
+                if next.load(&node.node().next).is_null() {
+                    return;
+                }
+                continue;
+            }
+            let link = scratch.load(&prev.node().next);
+            if link.is_null() {
+                prev = self.head();
+                last.clear();
+                continue;
+            }
+            if is_marked(link) {
+                if last.node.is_null() {
+                    if scratch.load(&prev.node().prev).is_null() {
+                        prev = self.head();
+                    } else {
+                        mem::swap(&mut prev, &mut scratch);
+                    }
+                } else {
+                    prev.node().mark_prev();
+                    // SAFETY: `last` and `scratch` are protected.
+                    let _ = unsafe { swing(&last.node().next, prev.node, scratch.node) };
+                    mem::swap(&mut prev, &mut last);
+                    last.clear();
+                }
+                continue;
+            }
+            if scratch.node != node.node {
+                mem::swap(&mut last, &mut prev);
+                mem::swap(&mut prev, &mut scratch);
+                continue;
+            }
+            // SAFETY: `prev` and `next` are protected.
+            if unsafe { swing(&prev.node().next, node.node, next.node) } {
+                return;
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Finishes the removal of `node`, whose next link this thread has marked.
+    fn finish_remove(&self, node: &Protected<T>) {
+        self.help_delete(node);
+        let mut prev = Protected::new();
+        let mut next = Protected::new();
+        if !prev.load(&node.node().prev).is_null() && !next.load(&node.node().next).is_null() {
+            self.correct_prev(&mut prev, &next);
+        }
+        self.remove_cross_reference(node);
+    }
+
+    /// Points the links of `node`, which is removed, past the removed nodes next to it, so that
+    /// removed nodes do not keep each other from being retired.
+    fn remove_cross_reference(&self, node: &Protected<T>) {
+        let mut neighbor = Protected::new();
+        let mut skip = Protected::new();
+        loop {
+            let prev = neighbor.load(&node.node().prev);
+            if prev.is_null() {
+                return;
+            }
+            if neighbor.node().is_removed() {
+                if !skip.load(&neighbor.node().prev).is_null() {
+                    // SAFETY: `skip` is protected.
+                    let _ = unsafe { swing(&node.node().prev, prev, marked(skip.node)) };
+                }
+                continue;
+            }
+            let next = neighbor.load(&node.node().next);
+            if next.is_null() {
+                return;
+            }
+            if neighbor.node().is_removed() {
+                if !skip.load(&neighbor.node().next).is_null() {
+                    // SAFETY: `skip` is protected.
+                    let _ = unsafe { swing(&node.node().next, next, marked(skip.node)) };
+                }
+                continue;
+            }
+            return;
+        }
+    }
+}
+
+impl<T> Drop for DList<T> {
+    fn drop(&mut self) {
+        // The removed nodes that are not retired are still pointed to by some links, and the
+        // retired ones are not.
+        let mut nodes = HashSet::new();
+        let mut pending = vec![self.head, self.tail];
+        while let Some(node) = pending.pop() {
+            if node.is_null() || !nodes.insert(node) {
+                continue;
+            }
+            // SAFETY: No other thread accesses the list, and the node is not retired.
+            let node = unsafe { &*node };
+            pending.push(unmarked(node.prev.load(Ordering::Relaxed)));
+            pending.push(unmarked(node.next.load(Ordering::Relaxed)));
+        }
+        for node in nodes {
+            // SAFETY: Same as above.
+            drop(unsafe { Box::from_raw(node) });
+        }
+    }
+}
+
+/// Value removed from a list, which is not reclaimed while the entry is alive.
+#[derive(Debug)]
+pub struct Entry<'a, T> {
+    node: Protected<T>,
+    _marker: PhantomData<&'a DList<T>>,
+}
+
+impl<T> Entry<'_, T> {
+    fn new(node: Protected<T>) -> Self {
+        Self {
+            node,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the value.
+    pub fn value(&self) -> &T {
+        self.node.node().value.as_ref().unwrap()
+    }
+}
+
+/// Cursor over a list, which is either on a node or at one of the ends.
+///
+/// The cursor owns a reference to its node, which is not reclaimed while the cursor is on it,
+/// even if it is removed. A cursor on a removed node can still move to the nodes that were next
+/// to it, but cannot insert next to it.
+#[derive(Debug)]
+pub struct Cursor<'a, T> {
+    list: &'a DList<T>,
+    node: Protected<T>,
+}
+
+impl<'a, T> Cursor<'a, T> {
+    fn new(list: &'a DList<T>, end: *mut Node<T>) -> Self {
+        // SAFETY: The list owns a reference to its ends.
+        let node = unsafe { Protected::with(end) };
+        let acquired = node.node().acquire();
+        debug_assert!(acquired);
+        Self { list, node }
+    }
+
+    /// Moves to `node`, which is protected and not retired.
+    fn move_to(&mut self, mut node: Protected<T>) {
+        mem::swap(&mut self.node, &mut node);
+        // SAFETY: The cursor owned a reference to its old node.
+        unsafe { Node::release(node.node) };
+    }
+
+    /// Returns the value the cursor is on. Returns `None` if it is at an end, or the value is
+    /// removed.
+    pub fn get(&self) -> Option<&T> {
+        let node = self.node.node();
+        if node.is_removed() {
+            return None;
+        }
+        node.value.as_ref()
+    }
+
+    /// Moves to the next value, skipping the removed ones. Returns `false` if the cursor is at
+    /// the back end.
+    pub fn move_next(&mut self) -> bool {
+        let mut next = Protected::new();
+        let mut skip = Protected::new();
+        loop {
+            if self.node.node == self.list.tail {
+                return false;
+            }
+            // The cursor owns a reference to its node, which is not retired.
+            let link = next.load(&self.node.node().next);
+            let removed = next.node().is_removed();
+            if removed && !is_marked(link) {
+                // Unlinks `next`, which is removed.
+                next.node().mark_prev();
+                if !skip.load(&next.node().next).is_null() {
+                    // SAFETY: `skip` is protected.
+                    let _ = unsafe { swing(&self.node.node().next, next.node, skip.node) };
+                }
+                continue;
+            }
+            if !next.node().acquire() {
+                continue;
+            }
+            self.move_to(mem::replace(&mut next, Protected::new()));
+            if !removed {
+                return self.node.node != self.list.tail;
+            }
+        }
+    }
+
+    /// Moves to the previous value, skipping the removed ones. Returns `false` if the cursor is
+    /// at the front end.
+    pub fn move_prev(&mut self) -> bool {
+        let mut prev = Protected::new();
+        loop {
+            if self.node.node == self.list.head {
+                return false;
+            }
+            let _ = prev.load(&self.node.node().prev);
+            let prev_next = prev.node().next.load(Ordering::Acquire);
+            if self.node.node().is_removed() {
+                // Moves to the node after it first, and then back to the node before that.
+                let _ = self.move_next();
+                continue;
+            }
+            if prev_next != self.node.node {
+                self.list.correct_prev(&mut prev, &self.node);
+                continue;
+            }
+            if !prev.node().acquire() {
+                continue;
+            }
+            self.move_to(mem::replace(&mut prev, Protected::new()));
+            return self.node.node != self.list.head;
+        }
+    }
+
+    /// Inserts `value` right after the cursor, which stays where it is. Returns it back if the
+    /// cursor is on a removed node.
+    pub fn insert_after(&self, value: T) -> Result<(), T> {
+        if self.node.node == self.list.tail {
+            return self.insert_before(value);
+        }
+        self.insert(value, |node| self.list.link_after(&self.node, node))
+    }
+
+    /// Inserts `value` right before the cursor, which stays where it is. Returns it back if the
+    /// cursor is on a removed node.
+    pub fn insert_before(&self, value: T) -> Result<(), T> {
+        if self.node.node == self.list.head {
+            return self.insert_after(value);
+        }
+        self.insert(value, |node| self.list.link_before(&self.node, node))
+    }
+
+    fn insert<F>(&self, value: T, link: F) -> Result<(), T>
+    where
+        F: FnOnce(&Protected<T>) -> bool,
+    {
+        let node = Node::new(Some(value), 1);
+        // SAFETY: The inserting thread owns a reference to the node.
+        let protected = unsafe { Protected::with(node) };
+        if link(&protected) {
+            // SAFETY: The inserting thread owned the reference.
+            unsafe { Node::release(node) };
+            return Ok(());
+        }
+        drop(protected);
+        // SAFETY: The node is not inserted, and the inserting thread owns the only reference.
+        let mut node = unsafe { Box::from_raw(node) };
+        for link in [&node.prev, &node.next] {
+            let target = link.swap(ptr::null_mut(), Ordering::Relaxed);
+            if !target.is_null() {
+                // SAFETY: The node owned the reference of its link.
+                unsafe { Node::release(target) };
+            }
+        }
+        Err(node.value.take().unwrap())
+    }
+
+    /// Removes the value the cursor is on, which stays on the removed node. Returns `false` if
+    /// the cursor is at an end, or the value is already removed.
+    pub fn remove(&self) -> bool {
+        let node = self.node.node();
+        if node.value.is_none() {
+            return false;
+        }
+        let backoff = Backoff::new();
+        loop {
+            let next = node.next.load(Ordering::Acquire);
+            if is_marked(next) {
+                return false;
+            }
+            if node
+                .next
+                .compare_exchange(next, marked(next), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.list.finish_remove(&self.node);
+                return true;
+            }
+            backoff.spin();
+        }
+    }
+}
+
+impl<T> Drop for Cursor<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: The cursor owns a reference to its node.
+        unsafe { Node::release(self.node.node) };
+    }
+}
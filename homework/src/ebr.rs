@@ -0,0 +1,331 @@
+//! Epoch-based reclamation.
+//!
+//! A thread pins itself to the current global epoch while it accesses shared memory. The global
+//! epoch advances only when all pinned threads are pinned to it, so a pointer retired in epoch `e`
+//! is not accessed by any thread once the global epoch reaches `e + 2`.
+//!
+//! # Example
+//!
+//! ```
+//! use std::ptr;
+//! use std::sync::atomic::{AtomicPtr, Ordering};
+//! use cs431_homework::ebr::{collect, pin};
+//!
+//! let atomic = AtomicPtr::new(Box::leak(Box::new(1usize)));
+//! let guard = pin();
+//! let pointer = atomic.load(Ordering::Acquire);
+//! assert_eq!(unsafe { *pointer }, 1);
+//!
+//! // unlink the block and defer freeing it
+//! atomic.store(ptr::null_mut(), Ordering::Relaxed);
+//! unsafe { guard.defer_destroy(pointer) };
+//! drop(guard);
+//!
+//! // manually trigger reclamation (not necessary)
+//! collect();
+//! ```
+
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::thread_local;
+#[cfg(not(feature = "check-loom"))]
+use std::thread_local;
+
+use crate::backoff::Backoff;
+
+/// Registration of a thread in a `Collector`.
+#[derive(Debug)]
+struct Participant {
+    /// Whether this participant is owned by a thread.
+    active: AtomicBool,
+    /// `epoch << 1 | 1` if the thread is pinned to `epoch`, and 0 if it is not pinned.
+    epoch: AtomicUsize,
+    /// Immutable pointer to the next participant in the collector.
+    next: *const Participant,
+}
+
+/// Global epoch and the participants pinned to it.
+///
+/// `participants` forms a grow-only list. A participant is never removed, but deactivated when
+/// its thread exits and recycled for another thread.
+#[derive(Debug)]
+pub struct Collector {
+    epoch: AtomicUsize,
+    participants: AtomicPtr<Participant>,
+}
+
+// The participants are only accessed through atomics.
+unsafe impl Send for Collector {}
+unsafe impl Sync for Collector {}
+
+impl Collector {
+    #[cfg(not(feature = "check-loom"))]
+    /// Creates a new collector.
+    pub const fn new() -> Self {
+        Self {
+            epoch: AtomicUsize::new(0),
+            participants: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    #[cfg(feature = "check-loom")]
+    /// Creates a new collector.
+    pub fn new() -> Self {
+        Self {
+            epoch: AtomicUsize::new(0),
+            participants: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Registers a thread, either by recycling an inactive participant or allocating a new one.
+    fn register(&self) -> &Participant {
+        let mut node: *const Participant = self.participants.load(Ordering::Acquire);
+        // SAFETY: Participants are never freed while the collector is alive.
+        while let Some(participant) = unsafe { node.as_ref() } {
+            if participant
+                .active
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return participant;
+            }
+            node = participant.next;
+        }
+
+        let mut participant = Box::new(Participant {
+            active: AtomicBool::new(true),
+            epoch: AtomicUsize::new(0),
+            next: ptr::null(),
+        });
+        let backoff = Backoff::new();
+        loop {
+            let head = self.participants.load(Ordering::Acquire);
+            participant.next = head;
+            let new = Box::into_raw(participant);
+            match self.participants.compare_exchange(
+                head,
+                new,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                // SAFETY: Participants are never freed while the collector is alive.
+                Ok(_) => return unsafe { &*new },
+                Err(_) => {
+                    // SAFETY: `new` was not published.
+                    participant = unsafe { Box::from_raw(new) };
+                    backoff.spin();
+                }
+            }
+        }
+    }
+
+    /// Advances the global epoch if all pinned threads are pinned to it. Returns the global
+    /// epoch.
+    fn try_advance(&self) -> usize {
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        // SeqCst: pairs with the fence in `pin`. Either this thread sees a thread pinned to an old
+        // epoch, or that thread sees the pointers unlinked before the epoch advanced.
+        fence(Ordering::SeqCst);
+        let mut node: *const Participant = self.participants.load(Ordering::Acquire);
+        // SAFETY: Participants are never freed while the collector is alive.
+        while let Some(participant) = unsafe { node.as_ref() } {
+            let pinned = participant.epoch.load(Ordering::Relaxed);
+            if pinned & 1 == 1 && pinned >> 1 != epoch {
+                return epoch;
+            }
+            node = participant.next;
+        }
+        // Acquire: the threads that were pinned to the previous epoch are done with their accesses.
+        fence(Ordering::Acquire);
+        match self
+            .epoch
+            .compare_exchange(epoch, epoch + 1, Ordering::Release, Ordering::Relaxed)
+        {
+            Ok(_) => epoch + 1,
+            Err(current) => current,
+        }
+    }
+}
+
+impl Default for Collector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Collector {
+    /// Frees all participants.
+    fn drop(&mut self) {
+        let mut node = self.participants.load(Ordering::Relaxed);
+        while !node.is_null() {
+            // SAFETY: No thread is registered any more, so no one accesses the participants.
+            let participant = unsafe { Box::from_raw(node) };
+            node = participant.next as *mut Participant;
+        }
+    }
+}
+
+#[cfg(not(feature = "check-loom"))]
+/// Default global collector.
+pub static COLLECTOR: Collector = Collector::new();
+
+#[cfg(feature = "check-loom")]
+// FIXME: loom does not currently provide the equivalent of Lazy:
+// https://github.com/tokio-rs/loom/issues/263
+loom::lazy_static! {
+    /// Default global collector.
+    pub static ref COLLECTOR: Collector = Collector::new();
+}
+
+/// Pointer deferred to be freed, with the epoch it was retired in.
+type Garbage = (usize, usize, unsafe fn(usize));
+
+/// Thread-local state of a thread registered in `COLLECTOR`.
+#[derive(Debug)]
+struct Local {
+    participant: &'static Participant,
+    /// Number of the guards of this thread that are alive.
+    guards: usize,
+    garbage: Vec<Garbage>,
+}
+
+impl Local {
+    /// `collect` is triggered when `THRESHOLD` pointers are deferred.
+    const THRESHOLD: usize = 64;
+
+    fn new() -> Self {
+        Self {
+            participant: COLLECTOR.register(),
+            guards: 0,
+            garbage: Vec::new(),
+        }
+    }
+
+    /// Removes the garbage that is safe to free in `epoch`.
+    fn take_expired(&mut self, epoch: usize) -> Vec<Garbage> {
+        let (expired, garbage) = mem::take(&mut self.garbage)
+            .into_iter()
+            .partition(|(retired, _, _)| retired + 2 <= epoch);
+        self.garbage = garbage;
+        expired
+    }
+}
+
+// Like the one of `RetiredSet`, this triggers a loom internal bug.
+#[cfg(not(feature = "check-loom"))]
+impl Drop for Local {
+    fn drop(&mut self) {
+        // Like `RetiredSet`, simply waits for the remaining garbage to be safe to free, instead of
+        // handing it over to other threads.
+        while !self.garbage.is_empty() {
+            let epoch = COLLECTOR.try_advance();
+            for (_, pointer, free) in self.take_expired(epoch) {
+                // SAFETY: No thread accesses the pointer since it expired.
+                unsafe { free(pointer) };
+            }
+        }
+        self.participant.active.store(false, Ordering::Release);
+    }
+}
+
+thread_local! {
+    /// Registration of the current thread in `COLLECTOR`.
+    static LOCAL: RefCell<Local> = RefCell::new(Local::new());
+}
+
+/// Pins the current thread, so that the pointers it loads from shared memory are not freed until
+/// the returned guard is dropped. Pinning is reentrant.
+pub fn pin() -> Guard {
+    LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        if local.guards == 0 {
+            let epoch = COLLECTOR.epoch.load(Ordering::Relaxed);
+            local
+                .participant
+                .epoch
+                .store(epoch << 1 | 1, Ordering::Relaxed);
+            // SeqCst: pairs with the fence in `Collector::try_advance`.
+            fence(Ordering::SeqCst);
+        }
+        local.guards += 1;
+    });
+    Guard {
+        _marker: PhantomData,
+    }
+}
+
+/// Advances the global epoch if possible, and frees the pointers deferred by the current thread
+/// that no thread accesses any more.
+pub fn collect() {
+    let epoch = COLLECTOR.try_advance();
+    let expired = LOCAL.with(|local| local.borrow_mut().take_expired(epoch));
+    // The destructors may pin the current thread again, so they run outside of the borrow.
+    for (_, pointer, free) in expired {
+        // SAFETY: No thread accesses the pointer since it expired.
+        unsafe { free(pointer) };
+    }
+}
+
+/// A witness that the current thread is pinned.
+#[derive(Debug)]
+pub struct Guard {
+    _marker: PhantomData<*const ()>, // !Send + !Sync
+}
+
+impl Guard {
+    /// Defers freeing `pointer` until no thread pinned now may access it.
+    ///
+    /// # Safety
+    ///
+    /// * `pointer` must be removed from shared memory before calling this function, and must be
+    ///   valid.
+    /// * The same `pointer` should only be deferred once.
+    pub unsafe fn defer_destroy<T>(&self, pointer: *mut T) {
+        /// Frees a pointer of the type known only here.
+        unsafe fn free<T>(data: usize) {
+            drop(Box::from_raw(data as *mut T))
+        }
+
+        // SeqCst: the pointer is unlinked before the epoch it is retired in is read.
+        fence(Ordering::SeqCst);
+        let epoch = COLLECTOR.epoch.load(Ordering::Relaxed);
+        let collect_now = LOCAL.with(|local| {
+            let mut local = local.borrow_mut();
+            local.garbage.push((epoch, pointer as usize, free::<T>));
+            local.garbage.len() >= Local::THRESHOLD
+        });
+        if collect_now {
+            collect();
+        }
+    }
+}
+
+impl Default for Guard {
+    fn default() -> Self {
+        pin()
+    }
+}
+
+impl Drop for Guard {
+    /// Unpins the current thread if this is its last guard.
+    fn drop(&mut self) {
+        LOCAL.with(|local| {
+            let mut local = local.borrow_mut();
+            local.guards -= 1;
+            if local.guards == 0 {
+                // Release: the accesses while pinned happen before the epoch advances.
+                local.participant.epoch.store(0, Ordering::Release);
+            }
+        });
+    }
+}
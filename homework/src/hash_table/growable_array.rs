@@ -176,7 +176,30 @@ impl Debug for Segment {
 impl<T> Drop for GrowableArray<T> {
     /// Deallocate segments, but not the individual elements.
     fn drop(&mut self) {
-        todo!()
+        /// Deallocates `segment` of `height` and the segments under it.
+        ///
+        /// # Safety
+        ///
+        /// `segment` must be a valid segment of `height` that is not accessed by other threads.
+        unsafe fn free(segment: usize, height: usize) {
+            let segment = Owned::<Segment>::from_usize(segment);
+            if height > 1 {
+                for child in segment.iter() {
+                    let child = child.load(Ordering::Relaxed);
+                    if child != 0 {
+                        free(child, height - 1);
+                    }
+                }
+            }
+        }
+
+        // SAFETY: We have `&mut self`, so no other thread accesses the array.
+        unsafe {
+            let root = self.root.load(Ordering::Relaxed, unprotected());
+            if !root.is_null() {
+                free(root.with_tag(0).into_usize(), root.tag());
+            }
+        }
     }
 }
 
@@ -198,6 +221,60 @@ impl<T> GrowableArray<T> {
     /// Returns the reference to the `Atomic` pointer at `index`. Allocates new segments if
     /// necessary.
     pub fn get(&self, mut index: usize, guard: &Guard) -> &Atomic<T> {
-        todo!()
+        // The number of `SEGMENT_LOGSIZE`-bit digits of `index`.
+        let mut height = 1;
+        while index
+            .checked_shr((height * SEGMENT_LOGSIZE) as u32)
+            .unwrap_or(0)
+            != 0
+        {
+            height += 1;
+        }
+
+        let mut root = self.root.load(Ordering::Acquire, guard);
+        while root.tag() < height {
+            // Puts the current root under the 0th branch of a new root segment.
+            let new = Owned::new(Segment::new());
+            if !root.is_null() {
+                new[0].store(root.with_tag(0).into_usize(), Ordering::Relaxed);
+            }
+            // Release: the new segment is initialized before it is published.
+            match self.root.compare_exchange(
+                root,
+                new.with_tag(root.tag() + 1),
+                Ordering::Release,
+                Ordering::Acquire,
+                guard,
+            ) {
+                Ok(new) => root = new,
+                // Dropping the new segment does not drop the old root under it.
+                Err(e) => root = e.current,
+            }
+        }
+
+        // SAFETY: `root` is not null, and segments are never deallocated while the array is alive.
+        let mut segment = unsafe { root.deref() };
+        for level in (1..root.tag()).rev() {
+            let digit = (index >> (level * SEGMENT_LOGSIZE)) & ((1 << SEGMENT_LOGSIZE) - 1);
+            let mut child = segment[digit].load(Ordering::Acquire);
+            if child == 0 {
+                let new = Owned::new(Segment::new()).into_usize();
+                match segment[digit].compare_exchange(0, new, Ordering::Release, Ordering::Acquire)
+                {
+                    Ok(_) => child = new,
+                    Err(current) => {
+                        // SAFETY: `new` was not published.
+                        drop(unsafe { Owned::<Segment>::from_usize(new) });
+                        child = current;
+                    }
+                }
+            }
+            // SAFETY: Segments are never deallocated while the array is alive.
+            segment = unsafe { &*(child as *const Segment) };
+        }
+
+        index &= (1 << SEGMENT_LOGSIZE) - 1;
+        // SAFETY: `Atomic<T>` has the same representation as `AtomicUsize`.
+        unsafe { &*(&segment[index] as *const AtomicUsize as *const Atomic<T>) }
     }
 }
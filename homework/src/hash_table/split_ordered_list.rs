@@ -2,7 +2,7 @@
 
 use core::mem;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use crossbeam_epoch::{Guard, Owned};
+use crossbeam_epoch::{Guard, Owned, Shared};
 use cs431::lockfree::list::{Cursor, List, Node};
 
 use super::growable_array::GrowableArray;
@@ -46,7 +46,46 @@ impl<V> SplitOrderedList<V> {
     /// Creates a cursor and moves it to the bucket for the given index.  If the bucket doesn't
     /// exist, recursively initializes the buckets.
     fn lookup_bucket<'s>(&'s self, index: usize, guard: &'s Guard) -> Cursor<'s, usize, Option<V>> {
-        todo!()
+        let bucket = self.buckets.get(index, guard);
+        let sentinel = bucket.load(Ordering::Acquire, guard);
+        if !sentinel.is_null() {
+            return Cursor::new(bucket, sentinel);
+        }
+
+        // The sentinel of a bucket is inserted after the sentinel of its parent bucket, which is
+        // the bucket with the most significant bit of `index` cleared.
+        let parent = if index == 0 {
+            None
+        } else {
+            Some(index & !(1 << (usize::BITS - 1 - index.leading_zeros())))
+        };
+        let key = Self::sentinel_key(index);
+        let mut node = Owned::new(Node::new(key, None));
+        let sentinel = loop {
+            let mut cursor = match parent {
+                None => self.list.head(guard),
+                Some(parent) => self.lookup_bucket(parent, guard),
+            };
+            match cursor.find_harris(&key, guard) {
+                Err(()) => continue,
+                // Another thread inserted the sentinel.
+                Ok(true) => break cursor.curr(),
+                Ok(false) => match cursor.insert(node, guard) {
+                    Ok(()) => break cursor.curr(),
+                    Err(n) => node = n,
+                },
+            }
+        };
+
+        // Sentinels are never deleted, so any thread that stores one stores the same.
+        let _ = bucket.compare_exchange(
+            Shared::null(),
+            sentinel,
+            Ordering::Release,
+            Ordering::Relaxed,
+            guard,
+        );
+        Cursor::new(bucket, sentinel)
     }
 
     /// Moves the bucket cursor returned from `lookup_bucket` to the position of the given key.
@@ -56,7 +95,27 @@ impl<V> SplitOrderedList<V> {
         key: &usize,
         guard: &'s Guard,
     ) -> (usize, bool, Cursor<'s, usize, Option<V>>) {
-        todo!()
+        let size = self.size.load(Ordering::Acquire);
+        let index = key % size;
+        let key = Self::regular_key(*key);
+        loop {
+            let mut cursor = self.lookup_bucket(index, guard);
+            if let Ok(found) = cursor.find_harris(&key, guard) {
+                return (size, found, cursor);
+            }
+        }
+    }
+
+    /// Returns the split-order key of the sentinel of the bucket at `index`, whose least
+    /// significant bit is 0.
+    fn sentinel_key(index: usize) -> usize {
+        index.reverse_bits()
+    }
+
+    /// Returns the split-order key of `key`, whose least significant bit is 1 so that it comes
+    /// after the sentinel of its bucket.
+    fn regular_key(key: usize) -> usize {
+        (key | 1 << (usize::BITS - 1)).reverse_bits()
     }
 
     fn assert_valid_key(key: usize) {
@@ -67,16 +126,49 @@ impl<V> SplitOrderedList<V> {
 impl<V> NonblockingMap<usize, V> for SplitOrderedList<V> {
     fn lookup<'a>(&'a self, key: &usize, guard: &'a Guard) -> Option<&'a V> {
         Self::assert_valid_key(*key);
-        todo!()
+        let (_, found, cursor) = self.find(key, guard);
+        if !found {
+            return None;
+        }
+        cursor.lookup().and_then(Option::as_ref)
     }
 
     fn insert(&self, key: &usize, value: V, guard: &Guard) -> Result<(), V> {
         Self::assert_valid_key(*key);
-        todo!()
+        let mut node = Owned::new(Node::new(Self::regular_key(*key), Some(value)));
+        let size = loop {
+            let (size, found, mut cursor) = self.find(key, guard);
+            if found {
+                return Err(node.into_box().into_value().unwrap());
+            }
+            match cursor.insert(node, guard) {
+                Ok(()) => break size,
+                Err(n) => node = n,
+            }
+        };
+
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count > size * Self::LOAD_FACTOR {
+            // Fails if another thread already doubled it.
+            let _ =
+                self.size
+                    .compare_exchange(size, size * 2, Ordering::Release, Ordering::Relaxed);
+        }
+        Ok(())
     }
 
     fn delete<'a>(&'a self, key: &usize, guard: &'a Guard) -> Result<&'a V, ()> {
         Self::assert_valid_key(*key);
-        todo!()
+        loop {
+            let (_, found, cursor) = self.find(key, guard);
+            if !found {
+                return Err(());
+            }
+            // Fails if another thread deleted it first.
+            if let Ok(value) = cursor.delete(guard) {
+                let _ = self.count.fetch_sub(1, Ordering::Relaxed);
+                return Ok(value.as_ref().unwrap());
+            }
+        }
     }
 }
@@ -1,14 +1,32 @@
 use core::marker::PhantomData;
+#[cfg(feature = "leak-detection")]
+use core::panic::Location;
 use core::ptr::{self, NonNull};
 use std::collections::HashSet;
 use std::fmt;
+#[cfg(feature = "leak-detection")]
+use std::thread;
 
-#[cfg(not(feature = "check-loom"))]
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
 use core::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
 #[cfg(feature = "check-loom")]
 use loom::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
 
+#[cfg(feature = "leak-detection")]
+use super::leak::{self, Leak, Site};
 use super::HAZARDS;
+use crate::backoff::Backoff;
+use crate::metrics::Registry;
+use crate::registry::PerThread;
+use crate::CachePadded;
+
+// With `numa`, a slot is on the node of the thread that allocates it, which is the first to use it.
+#[cfg(all(feature = "numa", target_os = "linux"))]
+use crate::numa::NodeBox as SlotBox;
+#[cfg(not(all(feature = "numa", target_os = "linux")))]
+use std::boxed::Box as SlotBox;
 
 /// Represents the ownership of a hazard pointer slot.
 pub struct Shield<T> {
@@ -18,8 +36,13 @@ pub struct Shield<T> {
 
 impl<T> Shield<T> {
     /// Creates a new shield for hazard pointer.
+    #[track_caller]
     pub fn new(hazards: &HazardBag) -> Self {
         let slot = hazards.acquire_slot();
+        // Relaxed: read after the hazards the shield protects.
+        #[cfg(feature = "leak-detection")]
+        slot.created
+            .store(Location::caller() as *const _ as *mut _, Ordering::Relaxed);
         Self {
             slot: slot.into(),
             _marker: PhantomData,
@@ -29,9 +52,9 @@ impl<T> Shield<T> {
     /// Store `pointer` to the hazard slot.
     pub fn set(&self, pointer: *mut T) {
         unsafe {
-            fence(Ordering::SeqCst);
             let slt = self.slot.as_ref();
             slt.hazard.store(pointer as usize, Ordering::Release);
+            fence(Ordering::SeqCst);
         }
     }
 
@@ -69,19 +92,20 @@ impl<T> Shield<T> {
     ///
     /// See `try_protect()`.
     pub fn protect(&self, src: &AtomicPtr<T>) -> *mut T {
+        let backoff = Backoff::new();
         let mut pointer = src.load(Ordering::Relaxed);
         loop {
             match self.try_protect(pointer, src) {
                 Ok(_) => return pointer,
                 Err(new) => pointer = new,
             };
-            #[cfg(feature = "check-loom")]
-            loom::sync::atomic::spin_loop_hint();
+            backoff.spin();
         }
     }
 }
 
 impl<T> Default for Shield<T> {
+    #[track_caller]
     fn default() -> Self {
         Self::new(&HAZARDS)
     }
@@ -113,6 +137,9 @@ impl<T> fmt::Debug for Shield<T> {
 #[derive(Debug)]
 pub struct HazardBag {
     head: AtomicPtr<HazardSlot>,
+    /// The slot each thread acquired last, which it tries first to acquire again. So a thread
+    /// tends to reuse its own slots, which are in its cache, and on its node with `numa`.
+    hints: PerThread<AtomicPtr<HazardSlot>>,
 }
 
 /// See `HazardBag`
@@ -120,18 +147,24 @@ pub struct HazardBag {
 struct HazardSlot {
     // Whether this slot is occupied by a `Shield`.
     active: AtomicBool,
-    // Machine representation of the hazard pointer.
-    hazard: AtomicUsize,
+    // Machine representation of the hazard pointer. Written by the owner on every protection, so it
+    // is kept apart from the slots of the other threads.
+    hazard: CachePadded<AtomicUsize>,
     // Immutable pointer to the next slot in the bag.
     next: *const HazardSlot,
+    // Where the `Shield` that occupies this slot is created.
+    #[cfg(feature = "leak-detection")]
+    created: AtomicPtr<Location<'static>>,
 }
 
 impl HazardSlot {
     fn new(next: *const HazardSlot) -> Self {
         HazardSlot {
             active: AtomicBool::new(true),
-            hazard: AtomicUsize::new(0),
+            hazard: CachePadded::new(AtomicUsize::new(0)),
             next,
+            #[cfg(feature = "leak-detection")]
+            created: AtomicPtr::new(ptr::null_mut()),
         }
     }
 }
@@ -142,6 +175,7 @@ impl HazardBag {
     pub const fn new() -> Self {
         Self {
             head: AtomicPtr::new(ptr::null_mut()),
+            hints: PerThread::new(),
         }
     }
 
@@ -150,19 +184,42 @@ impl HazardBag {
     pub fn new() -> Self {
         Self {
             head: AtomicPtr::new(ptr::null_mut()),
+            hints: PerThread::new(),
         }
     }
 
-    /// Acquires a slot in the hazard set, either by recyling an inactive slot or allocating a new
-    /// slot.
+    /// Acquires a slot in the hazard set, either by recyling an inactive slot, preferably the one
+    /// the current thread acquired last, or allocating a new slot.
     fn acquire_slot(&self) -> &HazardSlot {
-        if let Some(recycle_slot) = self.try_acquire_inactive() {
-            return recycle_slot;
+        // A thread-local destructor may run after the registration of the thread is destroyed,
+        // and then goes without the hint.
+        let hint = self.hints.try_local();
+        // SAFETY: Slots are never freed while the bag is alive.
+        let slot = match hint.and_then(|hint| unsafe { hint.load(Ordering::Relaxed).as_ref() }) {
+            Some(slot) if Self::try_activate(slot) => slot,
+            _ => self
+                .try_acquire_inactive()
+                .unwrap_or_else(|| self.allocate_slot()),
+        };
+        if let Some(hint) = hint {
+            hint.store(slot as *const _ as *mut _, Ordering::Relaxed);
         }
+        slot
+    }
 
+    /// Activates `slot` if it is inactive.
+    fn try_activate(slot: &HazardSlot) -> bool {
+        slot.active
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Allocates an active slot.
+    fn allocate_slot(&self) -> &HazardSlot {
+        let backoff = Backoff::new();
         loop {
             let past_head = self.head.load(Ordering::Acquire);
-            let new_hazard_slot = Box::into_raw(Box::new(HazardSlot::new(past_head)));
+            let new_hazard_slot = SlotBox::into_raw(SlotBox::new(HazardSlot::new(past_head)));
             unsafe {
                 if self
                     .head
@@ -176,8 +233,9 @@ impl HazardBag {
                 {
                     return &*new_hazard_slot;
                 }
-                drop(Box::from_raw(new_hazard_slot));
+                drop(SlotBox::from_raw(new_hazard_slot));
             }
+            backoff.spin();
         }
     }
 
@@ -222,21 +280,101 @@ impl HazardBag {
             }
         }
     }
+
+    /// Returns the number of the slots of the bag, and of those held by shields.
+    fn count_slots(&self) -> (usize, usize) {
+        let (mut slots, mut active) = (0, 0);
+        let mut node: *const HazardSlot = self.head.load(Ordering::Acquire);
+        // SAFETY: Slots are never freed while the bag is alive.
+        while let Some(slot) = unsafe { node.as_ref() } {
+            slots += 1;
+            if slot.active.load(Ordering::Relaxed) {
+                active += 1;
+            }
+            node = slot.next;
+        }
+        (slots, active)
+    }
+
+    /// Registers the slots of this bag into `registry`, labeled with `domain`: all of them, which
+    /// are never freed, and those held by shields.
+    pub fn register_metrics(&'static self, registry: &Registry, domain: &str) {
+        let labels = [("domain", domain)];
+        registry.gauge_fn(
+            "hazard_slots",
+            "Hazard slots allocated.",
+            &labels,
+            move || Some(self.count_slots().0 as i64),
+        );
+        registry.gauge_fn(
+            "hazard_shields",
+            "Hazard slots held by shields.",
+            &labels,
+            move || Some(self.count_slots().1 as i64),
+        );
+    }
+
+    /// Returns the pointers retired to this bag that are not freed yet, in the order of the
+    /// pointers, with the shields that protect them. Only with the `leak-detection` feature.
+    #[cfg(feature = "leak-detection")]
+    pub fn leaks(&self) -> Vec<Leak> {
+        leak::retired_to(self, false)
+            .into_iter()
+            .map(|(pointer, site)| self.leak(pointer, site))
+            .collect()
+    }
+
+    #[cfg(feature = "leak-detection")]
+    fn leak(&self, pointer: usize, site: Site) -> Leak {
+        let mut pinned_by = Vec::new();
+        let mut node: *const HazardSlot = self.head.load(Ordering::Acquire);
+        // SAFETY: Slots are never freed while the bag is alive.
+        while let Some(slot) = unsafe { node.as_ref() } {
+            if slot.active.load(Ordering::Acquire) && slot.hazard.load(Ordering::Acquire) == pointer
+            {
+                // SAFETY: The locations are static.
+                if let Some(created) = unsafe { slot.created.load(Ordering::Relaxed).as_ref() } {
+                    pinned_by.push(created);
+                }
+            }
+            node = slot.next;
+        }
+        Leak {
+            pointer,
+            retired_at: site.at,
+            pinned_by,
+        }
+    }
 }
 
 impl Drop for HazardBag {
-    /// Frees all slots.
+    /// Frees all slots. With the `leak-detection` feature, panics if a pointer retired to the bag
+    /// is not freed.
     fn drop(&mut self) {
+        #[cfg(feature = "leak-detection")]
+        let leaks = leak::retired_to(self, true)
+            .into_iter()
+            .map(|(pointer, site)| self.leak(pointer, site))
+            .collect::<Vec<_>>();
         unsafe {
             let mut node = self.head.load(Ordering::Acquire);
 
             while !node.is_null() {
                 let next_node = (*node).next;
-                drop(Box::from_raw(node));
+                drop(SlotBox::from_raw(node));
 
                 node = next_node as *mut HazardSlot;
             }
         }
+
+        #[cfg(feature = "leak-detection")]
+        if !leaks.is_empty() && !thread::panicking() {
+            let leaks = leaks
+                .iter()
+                .map(|leak| format!("\n  {leak}"))
+                .collect::<String>();
+            panic!("retired pointers are never freed before their hazard bag is dropped:{leaks}");
+        }
     }
 }
 
@@ -245,11 +383,12 @@ unsafe impl Sync for HazardSlot {}
 
 #[cfg(all(test, not(feature = "check-loom")))]
 mod tests {
+    use super::AtomicPtr;
     use super::{HazardBag, Shield};
     use std::collections::HashSet;
     use std::mem;
     use std::ops::Range;
-    use std::sync::{atomic::AtomicPtr, Arc};
+    use std::sync::Arc;
     use std::thread;
 
     const THREADS: usize = 8;
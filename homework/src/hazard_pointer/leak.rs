@@ -0,0 +1,109 @@
+//! Leak detection of the retired pointers.
+//!
+//! With the `leak-detection` feature, each pointer retired to a `RetiredSet` is recorded with
+//! where it is retired until it is freed, and each hazard slot with where its `Shield` is created.
+//! The pointers that are retired but not freed are then reported with the shields that pin them,
+//! on demand by `HazardBag::leaks`, and when their bag is dropped. Without the feature, the
+//! records are empty and this costs nothing.
+
+#[cfg(feature = "leak-detection")]
+use core::fmt;
+#[cfg(feature = "leak-detection")]
+use core::panic::Location;
+#[cfg(feature = "leak-detection")]
+use std::collections::HashMap;
+#[cfg(feature = "leak-detection")]
+use std::sync::{Mutex, MutexGuard};
+
+#[cfg(feature = "leak-detection")]
+use once_cell::sync::Lazy;
+
+use super::HazardBag;
+
+/// Where a pointer is retired or a shield is created, which is empty without the
+/// `leak-detection` feature.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Site {
+    #[cfg(feature = "leak-detection")]
+    pub(crate) at: &'static Location<'static>,
+}
+
+impl Site {
+    #[track_caller]
+    pub(crate) fn caller() -> Self {
+        Self {
+            #[cfg(feature = "leak-detection")]
+            at: Location::caller(),
+        }
+    }
+}
+
+/// The pointers retired and not freed yet, with their bags and where they are retired.
+#[cfg(feature = "leak-detection")]
+static RETIRED: Lazy<Mutex<HashMap<usize, (usize, Site)>>> = Lazy::new(Default::default);
+
+#[cfg(feature = "leak-detection")]
+fn retired() -> MutexGuard<'static, HashMap<usize, (usize, Site)>> {
+    // The records are consistent even if a thread panicked while holding them.
+    RETIRED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Records that `pointer` is retired to be freed once no shield of `hazards` protects it.
+pub(crate) fn retire(pointer: usize, hazards: &HazardBag, site: Site) {
+    #[cfg(feature = "leak-detection")]
+    let _ = retired().insert(pointer, (hazards as *const HazardBag as usize, site));
+}
+
+/// Forgets `pointer`, which is about to be freed. It must be forgotten before it is freed, as the
+/// address may be retired again as soon as it is.
+pub(crate) fn free(pointer: usize) {
+    #[cfg(feature = "leak-detection")]
+    let _ = retired().remove(&pointer);
+}
+
+/// Takes the pointers retired to `hazards` that are not freed yet, in the order of the pointers.
+#[cfg(feature = "leak-detection")]
+pub(crate) fn retired_to(hazards: &HazardBag, take: bool) -> Vec<(usize, Site)> {
+    let bag = hazards as *const HazardBag as usize;
+    let mut retired = retired();
+    let mut pointers = retired
+        .iter()
+        .filter(|(_, (hazards, _))| *hazards == bag)
+        .map(|(pointer, (_, site))| (*pointer, *site))
+        .collect::<Vec<_>>();
+    pointers.sort_unstable_by_key(|(pointer, _)| *pointer);
+    if take {
+        for (pointer, _) in &pointers {
+            let _ = retired.remove(pointer);
+        }
+    }
+    pointers
+}
+
+/// A pointer that is retired but not freed yet, reported by the `leak-detection` feature.
+#[cfg(feature = "leak-detection")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leak {
+    /// The machine representation of the pointer.
+    pub pointer: usize,
+    /// Where the pointer is retired.
+    pub retired_at: &'static Location<'static>,
+    /// Where the shields that protect the pointer are created. If empty, the pointer is only
+    /// waiting for its `RetiredSet` to be collected, or its `RetiredSet` is leaked.
+    pub pinned_by: Vec<&'static Location<'static>>,
+}
+
+#[cfg(feature = "leak-detection")]
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x} retired at {}", self.pointer, self.retired_at)?;
+        if self.pinned_by.is_empty() {
+            return write!(f, ", not pinned");
+        }
+        write!(f, ", pinned by the shields created at")?;
+        for (i, at) in self.pinned_by.iter().enumerate() {
+            write!(f, "{} {at}", if i == 0 { "" } else { "," })?;
+        }
+        Ok(())
+    }
+}
@@ -28,9 +28,12 @@ use loom::thread_local;
 use std::thread_local;
 
 mod hazard;
+mod leak;
 mod retire;
 
 pub use hazard::{HazardBag, Shield};
+#[cfg(feature = "leak-detection")]
+pub use leak::Leak;
 pub use retire::RetiredSet;
 
 #[cfg(not(feature = "check-loom"))]
@@ -40,6 +43,8 @@ pub static HAZARDS: HazardBag = HazardBag::new();
 #[cfg(feature = "check-loom")]
 // FIXME: loom does not currently provide the equivalent of Lazy:
 // https://github.com/tokio-rs/loom/issues/263
+// Nor does `crate::Lazy`, whose value would outlive the execution of the model that initialized
+// it, while `lazy_static` initializes it again in each execution.
 loom::lazy_static! {
     /// Default global bag of all hazard pointers.
     pub static ref HAZARDS: HazardBag = HazardBag::new();
@@ -56,8 +61,10 @@ thread_local! {
 ///
 /// * `pointer` must be removed from shared memory before calling this function, and must be valid.
 /// * The same `pointer` should only be retired once.
+#[track_caller]
 pub unsafe fn retire<T>(pointer: *mut T) {
-    RETIRED.with(|r| r.borrow_mut().retire(pointer));
+    let site = leak::Site::caller();
+    RETIRED.with(|r| r.borrow_mut().retire_from(pointer, site));
 }
 
 /// Frees the pointers that are `retire`d by the current thread and not `protect`ed by any other
@@ -65,3 +72,9 @@ pub unsafe fn retire<T>(pointer: *mut T) {
 pub fn collect() {
     RETIRED.with(|r| r.borrow_mut().collect());
 }
+
+/// Returns the pointers retired to `HAZARDS` that are not freed yet. See `HazardBag::leaks`.
+#[cfg(feature = "leak-detection")]
+pub fn leaks() -> Vec<Leak> {
+    HAZARDS.leaks()
+}
@@ -1,9 +1,12 @@
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, Ordering};
 use core::marker::PhantomData;
-#[cfg(not(feature = "check-loom"))]
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
 use core::sync::atomic::{fence, Ordering};
 #[cfg(feature = "check-loom")]
 use loom::sync::atomic::{fence, Ordering};
 
+use super::leak::{self, Site};
 use super::{HazardBag, HAZARDS};
 
 /// Thread-local list of retired pointers.
@@ -37,7 +40,17 @@ impl<'s> RetiredSet<'s> {
     /// * `pointer` must be removed from shared memory before calling this function, and must be
     ///   valid.
     /// * The same `pointer` should only be retired once.
+    #[track_caller]
     pub unsafe fn retire<T>(&mut self, pointer: *mut T) {
+        self.retire_from(pointer, Site::caller());
+    }
+
+    /// Retires a pointer at `site`, like `retire`.
+    ///
+    /// # Safety
+    ///
+    /// See `retire`.
+    pub(crate) unsafe fn retire_from<T>(&mut self, pointer: *mut T, site: Site) {
         /// Frees a pointer. This function is defined here instead of `collect()` as we know about
         /// the type of `pointer` only at the time of retireing it.
         ///
@@ -51,6 +64,7 @@ impl<'s> RetiredSet<'s> {
             drop(Box::from_raw(data as *mut T))
         }
 
+        leak::retire(pointer as usize, self.hazards, site);
         self.inner.push((pointer as usize, free::<T>));
         if self.inner.len() >= Self::THRESHOLD {
             self.collect();
@@ -66,6 +80,7 @@ impl<'s> RetiredSet<'s> {
         let mut new_inner_vec = Vec::<(usize, unsafe fn(usize))>::new();
         for (pointer, free) in inner_vec {
             if !hazard_bag.contains(pointer) {
+                leak::free(*pointer);
                 unsafe {
                     free(*pointer);
                 }
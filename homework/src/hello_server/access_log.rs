@@ -0,0 +1,171 @@
+//! Access log written by a dedicated thread.
+
+use crossbeam_channel::{unbounded, Receiver, Sender, TryRecvError};
+use log::warn;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Line of the access log.
+#[derive(Debug, Clone)]
+pub struct AccessLogEntry {
+    /// When the request was handled.
+    pub time: SystemTime,
+    /// Address of the client, if known.
+    pub peer: Option<SocketAddr>,
+    /// Method of the request. `None` if the request is malformed.
+    pub method: Option<String>,
+    /// Path of the request. `None` if the request is malformed.
+    pub path: Option<String>,
+    /// Status code of the response.
+    pub status: u16,
+    /// Time taken to handle the request.
+    pub duration: Duration,
+}
+
+impl fmt::Display for AccessLogEntry {
+    /// Formats the entry as `time peer method path status duration`, where `time` is in seconds
+    /// since the Unix epoch, `duration` is in milliseconds, and unknown fields are `-`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let peer = self
+            .peer
+            .map_or_else(|| "-".to_string(), |peer| peer.to_string());
+        write!(
+            f,
+            "{time:.3} {peer} {} {} {} {:.3}ms",
+            self.method.as_deref().unwrap_or("-"),
+            self.path.as_deref().unwrap_or("-"),
+            self.status,
+            self.duration.as_secs_f64() * 1e3,
+        )
+    }
+}
+
+/// Handle to an access log. Clones of it write to the same log.
+///
+/// Entries are sent to a dedicated writer thread, so that the workers never block on the disk.
+/// The writer thread finishes when all handles are dropped.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    sender: Sender<AccessLogEntry>,
+}
+
+impl AccessLog {
+    /// Opens the access log at `path` for appending, and starts its writer thread.
+    ///
+    /// When the log would exceed `max_len` bytes, it is rotated: it is renamed to `path.1`, the
+    /// previous `path.1` to `path.2`, and so on, keeping at most `max_files` old logs.
+    pub fn open<P: AsRef<Path>>(path: P, max_len: u64, max_files: usize) -> io::Result<Self> {
+        let file = RotatingFile::open(path.as_ref().to_path_buf(), max_len, max_files)?;
+        let (sender, receiver) = unbounded();
+        let _ = thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || write_entries(file, receiver))?;
+        Ok(Self { sender })
+    }
+
+    /// Appends `entry` to the log.
+    pub fn log(&self, entry: AccessLogEntry) {
+        // The writer thread only finishes when all handles are dropped.
+        self.sender.send(entry).unwrap();
+    }
+}
+
+/// Writes the entries from `receiver` to `file` until all senders are dropped. The file is flushed
+/// whenever there are no more entries to write for the moment.
+fn write_entries(mut file: RotatingFile, receiver: Receiver<AccessLogEntry>) {
+    let write = |file: &mut RotatingFile, entry: AccessLogEntry| {
+        if let Err(err) = file.write_line(&entry.to_string()) {
+            warn!("failed to write access log: {err}");
+        }
+    };
+
+    while let Ok(entry) = receiver.recv() {
+        write(&mut file, entry);
+        loop {
+            match receiver.try_recv() {
+                Ok(entry) => write(&mut file, entry),
+                Err(TryRecvError::Empty) => break,
+                // Flushed when `file` is dropped.
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        if let Err(err) = file.flush() {
+            warn!("failed to write access log: {err}");
+        }
+    }
+}
+
+/// File that is rotated when it grows too large.
+#[derive(Debug)]
+struct RotatingFile {
+    path: PathBuf,
+    max_len: u64,
+    max_files: usize,
+    file: BufWriter<File>,
+    /// Current length of the file.
+    len: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_len: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_len,
+            max_files,
+            file: BufWriter::new(file),
+            len,
+        })
+    }
+
+    /// Returns the path of the `index`-th old log.
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    /// Writes `line` followed by a newline, rotating the file first if it would grow too large.
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.len > 0 && self.len + len > self.max_len {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{line}")?;
+        self.len += len;
+        Ok(())
+    }
+
+    /// Shifts the old logs, and starts a new log.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                match fs::rename(self.rotated_path(index), self.rotated_path(index + 1)) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        *self = Self::open(self.path.clone(), self.max_len, self.max_files)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
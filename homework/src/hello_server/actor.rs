@@ -0,0 +1,205 @@
+//! Actors whose messages are handled on a [`ThreadPool`].
+
+use core::fmt;
+use log::warn;
+use std::panic::{self, AssertUnwindSafe};
+// The actors of the hello server run on its thread pool, so they are not modeled with loom.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::thread_pool::{Submitter, ThreadPool};
+use crate::mpsc::{self, Link, Linked};
+
+/// Messages handled by a job before the actor lets the other jobs run.
+const BATCH: usize = 32;
+
+/// State of an actor, which handles its messages one at a time.
+pub trait Actor: Send + 'static {
+    /// Messages sent to the actor.
+    type Message: Send + 'static;
+
+    /// Handles `message`. If it panics, the actor is replaced by a new one, and handles the next
+    /// message.
+    fn handle(&mut self, message: Self::Message);
+}
+
+/// Message in a mailbox.
+struct Envelope<M> {
+    link: Link,
+    message: M,
+}
+
+// SAFETY: The link is embedded in the envelope.
+unsafe impl<M> Linked for Envelope<M> {
+    fn link(&self) -> &Link {
+        &self.link
+    }
+}
+
+// SAFETY: The message is not accessed through a shared envelope, but only moved out of the envelope
+// once it is popped and no longer shared.
+unsafe impl<M: Send> Sync for Envelope<M> {}
+
+type Factory<A> = Box<dyn FnMut() -> A + Send>;
+
+/// What the job handling the messages of an actor uses.
+struct Running<A: Actor> {
+    mailbox: mpsc::Receiver<Envelope<A::Message>>,
+    actor: A,
+    factory: Factory<A>,
+}
+
+/// Spawned actor, with its mailbox.
+struct Cell<A: Actor> {
+    sender: mpsc::Sender<Envelope<A::Message>>,
+    /// Whether a job handling the messages is submitted and not finished. So the messages are
+    /// handled by at most one job at a time, which is the only one to lock `running`.
+    scheduled: AtomicBool,
+    running: Mutex<Running<A>>,
+    restarts: AtomicUsize,
+    submitter: Submitter,
+}
+
+impl<A: Actor> Cell<A> {
+    /// Submits a job handling the messages, after `scheduled` is set by the caller.
+    fn schedule(self: Arc<Self>) {
+        let submitter = self.submitter.clone();
+        // If the pool is dropped, the actor is dropped with the messages left in its mailbox.
+        let _ = submitter.execute(move || self.run());
+    }
+
+    /// Handles a batch of messages, and submits another job if some are left.
+    fn run(self: Arc<Self>) {
+        let mut running = self.running.lock().unwrap();
+        for _ in 0..BATCH {
+            let envelope = some_or!(running.mailbox.pop(), break);
+            let message = match Arc::try_unwrap(envelope) {
+                Ok(envelope) => envelope.message,
+                Err(_) => unreachable!("an envelope is only shared by the mailbox"),
+            };
+            let running = &mut *running;
+            if panic::catch_unwind(AssertUnwindSafe(|| running.actor.handle(message))).is_err() {
+                let restarts = self.restarts.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!("actor panicked; restarting it ({restarts} restarts so far)");
+                running.actor = (running.factory)();
+            }
+        }
+
+        // AcqRel: the next job sees the actor, and this job sees the messages whose senders saw
+        // that it was scheduled. Pairs with the swap in `send`.
+        let _ = self.scheduled.swap(false, Ordering::AcqRel);
+        let empty = running.mailbox.is_empty();
+        drop(running);
+        if !empty && !self.scheduled.swap(true, Ordering::Acquire) {
+            self.schedule();
+        }
+    }
+}
+
+/// Mailbox of an actor, whatever its type.
+trait Mailbox<M>: Send + Sync {
+    fn send(self: Arc<Self>, message: M);
+
+    fn restarts(&self) -> usize;
+}
+
+impl<A: Actor> Mailbox<A::Message> for Cell<A> {
+    fn send(self: Arc<Self>, message: A::Message) {
+        let envelope = Arc::new(Envelope {
+            link: Link::new(),
+            message,
+        });
+        if self.sender.push(envelope).is_err() {
+            unreachable!("a new envelope is not in a mailbox");
+        }
+        // AcqRel: the message is seen by the job that is scheduled, or that clears `scheduled`
+        // after this swap. Pairs with the swap in `run`.
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            self.schedule();
+        }
+    }
+
+    fn restarts(&self) -> usize {
+        self.restarts.load(Ordering::Relaxed)
+    }
+}
+
+/// Handle that sends messages to an actor.
+///
+/// The actor is dropped once all its addresses are dropped and it has handled its messages.
+pub struct Address<M> {
+    mailbox: Arc<dyn Mailbox<M>>,
+}
+
+impl<M> Clone for Address<M> {
+    fn clone(&self) -> Self {
+        Self {
+            mailbox: self.mailbox.clone(),
+        }
+    }
+}
+
+impl<M> fmt::Debug for Address<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Address").finish_non_exhaustive()
+    }
+}
+
+impl<M> Address<M> {
+    /// Sends `message` to the actor, which handles the messages in the order they are sent by each
+    /// sender.
+    ///
+    /// The message is never handled if the pool is dropped before then.
+    pub fn send(&self, message: M) {
+        self.mailbox.clone().send(message);
+    }
+
+    /// Returns the number of times the actor has been restarted after panicking.
+    pub fn restarts(&self) -> usize {
+        self.mailbox.restarts()
+    }
+}
+
+/// Spawns actors whose messages are handled by the workers of a [`ThreadPool`].
+///
+/// Like the tasks of an [`Executor`](super::Executor), an actor is submitted to the pool when a
+/// message is sent to it, unless it is already submitted, and handles its messages sequentially,
+/// a batch per job. An actor waiting for messages is not a job of the pool.
+///
+/// The actors are supervised: an actor whose handler panics is replaced by a new one, created
+/// anew by the function it was spawned with, and the message it panicked on is lost.
+#[derive(Debug, Clone)]
+pub struct ActorSystem {
+    submitter: Submitter,
+}
+
+impl ActorSystem {
+    /// Creates an actor system that runs the actors on `pool`.
+    pub fn new(pool: &ThreadPool) -> Self {
+        Self {
+            submitter: pool.submitter(),
+        }
+    }
+
+    /// Spawns the actor created by `factory`, which creates it again whenever it panics, and
+    /// returns its address.
+    pub fn spawn<A, F>(&self, mut factory: F) -> Address<A::Message>
+    where
+        A: Actor,
+        F: FnMut() -> A + Send + 'static,
+    {
+        let (sender, mailbox) = mpsc::queue();
+        let cell = Arc::new(Cell {
+            sender,
+            scheduled: AtomicBool::new(false),
+            running: Mutex::new(Running {
+                mailbox,
+                actor: factory(),
+                factory: Box::new(factory),
+            }),
+            restarts: AtomicUsize::new(0),
+            submitter: self.submitter.clone(),
+        });
+        Address { mailbox: cell }
+    }
+}
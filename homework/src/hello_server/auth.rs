@@ -0,0 +1,101 @@
+//! HTTP authentication with the Basic and Bearer schemes.
+
+use std::fmt;
+
+use super::request::Request;
+
+/// Credentials required for a protection space (realm). A request is authenticated if its
+/// `Authorization` header has any of the accepted credentials.
+#[derive(Clone)]
+pub struct Auth {
+    realm: String,
+    /// Accepted `user:password` pairs of the Basic scheme.
+    basic: Vec<String>,
+    /// Accepted tokens of the Bearer scheme.
+    bearer: Vec<String>,
+}
+
+impl fmt::Debug for Auth {
+    /// Omits the credentials, so that they do not end up in the logs.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auth")
+            .field("realm", &self.realm)
+            .field("basic", &self.basic.len())
+            .field("bearer", &self.bearer.len())
+            .finish()
+    }
+}
+
+impl Auth {
+    /// Creates an authentication for `realm` that accepts no credentials yet.
+    pub fn new(realm: &str) -> Self {
+        Self {
+            realm: realm.to_string(),
+            basic: Vec::new(),
+            bearer: Vec::new(),
+        }
+    }
+
+    /// Accepts `user` with `password` in the Basic scheme. Panics if `user` contains `:`, which
+    /// separates it from the password.
+    pub fn with_basic(mut self, user: &str, password: &str) -> Self {
+        assert!(!user.contains(':'));
+        self.basic.push(format!("{user}:{password}"));
+        self
+    }
+
+    /// Accepts `token` in the Bearer scheme. Panics if `token` is empty.
+    pub fn with_bearer(mut self, token: &str) -> Self {
+        assert!(!token.is_empty());
+        self.bearer.push(token.to_string());
+        self
+    }
+
+    /// Checks if `request` has any of the accepted credentials.
+    pub fn check(&self, request: &Request) -> bool {
+        let (scheme, credentials) = match request
+            .header("Authorization")
+            .and_then(|auth| auth.split_once(' '))
+        {
+            Some(auth) => auth,
+            None => return false,
+        };
+        let credentials = credentials.trim();
+        // The scheme is case-insensitive, unlike the credentials.
+        if scheme.eq_ignore_ascii_case("Basic") {
+            let decoded = match base64::decode(credentials) {
+                Ok(decoded) => decoded,
+                Err(_) => return false,
+            };
+            // All are compared, so that the timing does not tell which one is close.
+            self.basic.iter().fold(false, |found, accepted| {
+                constant_time_eq(&decoded, accepted.as_bytes()) | found
+            })
+        } else if scheme.eq_ignore_ascii_case("Bearer") {
+            self.bearer.iter().fold(false, |found, accepted| {
+                constant_time_eq(credentials.as_bytes(), accepted.as_bytes()) | found
+            })
+        } else {
+            false
+        }
+    }
+
+    /// Returns the `WWW-Authenticate` challenges for the accepted schemes, sent with 401.
+    pub(crate) fn challenges(&self) -> Vec<String> {
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut challenges = Vec::new();
+        if !self.basic.is_empty() {
+            challenges.push(format!("Basic realm=\"{realm}\", charset=\"UTF-8\""));
+        }
+        if !self.bearer.is_empty() {
+            challenges.push(format!("Bearer realm=\"{realm}\""));
+        }
+        challenges
+    }
+}
+
+/// Compares `a` and `b` in time independent of where they differ, so that a secret compared with
+/// a guess does not leak through the timing.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
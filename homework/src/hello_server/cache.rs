@@ -1,19 +1,309 @@
 //! Thread-safe key/value cache.
 
-use std::borrow::{Borrow, BorrowMut};
-use std::collections::hash_map::{self, HashMap};
+use either::Either;
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
 use std::hash::Hash;
-use std::sync::{Arc, RwLock};
+use std::ops::{Deref, DerefMut};
+use std::panic;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-/// Cache that remembers the result for each key.
+use super::thread_pool::ThreadPool;
+use crate::deadlock::RwLock;
+use crate::lock::BravoRwLock;
+use crate::metrics::{Counter, Registry};
+use crate::OnceCell;
+
+/// Interval between the sweeps of the expired values.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Encoding of no time to live in `Cache::ttl`.
+const NO_TTL: u64 = u64::MAX;
+
+/// Returns `ttl` in nanoseconds, cut to below `NO_TTL`, or `NO_TTL` if it is `None`.
+fn encode_ttl(ttl: Option<Duration>) -> u64 {
+    ttl.map_or(NO_TTL, |ttl| {
+        u64::try_from(ttl.as_nanos()).map_or(NO_TTL - 1, |nanos| nanos.min(NO_TTL - 1))
+    })
+}
+
+/// Value stored with the time it was computed, which is empty while it is being computed.
+type Slot<V> = Arc<OnceCell<(V, Instant)>>;
+
+/// Lock of the map of a `Cache`.
+#[derive(Debug)]
+enum MapLock<T> {
+    Std(RwLock<T>),
+    /// Lets the readers of a read-dominated cache skip the reader count of `Std`, which all of
+    /// them write to.
+    Bravo(BravoRwLock<T>),
+}
+
+impl<T: Default> Default for MapLock<T> {
+    fn default() -> Self {
+        Self::Std(RwLock::default())
+    }
+}
+
+impl<T> MapLock<T> {
+    fn read(&self) -> impl Deref<Target = T> + '_ {
+        match self {
+            Self::Std(lock) => Either::Left(lock.read().unwrap()),
+            Self::Bravo(lock) => Either::Right(lock.read().unwrap()),
+        }
+    }
+
+    fn write(&self) -> impl DerefMut<Target = T> + '_ {
+        match self {
+            Self::Std(lock) => Either::Left(lock.write().unwrap()),
+            Self::Bravo(lock) => Either::Right(lock.write().unwrap()),
+        }
+    }
+
+    fn is_poisoned(&self) -> bool {
+        match self {
+            Self::Std(lock) => lock.is_poisoned(),
+            Self::Bravo(lock) => lock.is_poisoned(),
+        }
+    }
+}
+
+/// Outcomes of the lookups of a cache.
 #[derive(Debug, Default)]
+struct Lookups {
+    /// Lookups that found a value, computed or being computed by another lookup.
+    hits: Counter,
+    /// Lookups that computed the value.
+    misses: Counter,
+}
+
+impl Lookups {
+    /// Returns the counter of the hits if `hit`, or of the misses.
+    fn counter(&self, hit: bool) -> &Counter {
+        if hit {
+            &self.hits
+        } else {
+            &self.misses
+        }
+    }
+}
+
+/// Cache that remembers the result for each key.
+#[derive(Debug)]
 pub struct Cache<K, V> {
-    // todo! This is an example cache type. Build your own cache type that satisfies the
-    // specification for `get_or_insert_with`.
-    inner: RwLock<HashMap<K, Arc<Option<V>>>>,
+    inner: MapLock<HashMap<K, Slot<V>>>,
+    /// Time to live of the values in nanoseconds, as encoded by `encode_ttl`.
+    ttl: AtomicU64,
+    lookups: Lookups,
+}
+
+impl<K, V> Default for Cache<K, V> {
+    fn default() -> Self {
+        Self {
+            inner: MapLock::default(),
+            ttl: AtomicU64::new(encode_ttl(None)),
+            lookups: Lookups::default(),
+        }
+    }
+}
+
+impl<K, V> Cache<K, V> {
+    /// Creates a cache whose values expire `ttl` after they are computed. An expired value is
+    /// recomputed by the next `get_or_insert_with`.
+    pub fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            inner: MapLock::default(),
+            ttl: AtomicU64::new(encode_ttl(Some(ttl))),
+            lookups: Lookups::default(),
+        }
+    }
+
+    /// Creates a cache like `with_ttl`, or without expiry if `ttl` is `None`, whose map is guarded
+    /// by a [`BravoRwLock`] instead of a `RwLock`.
+    ///
+    /// This suits a cache that is mostly read from many threads at once, e.g., whose keys are
+    /// computed once and then requested over and over. The lookups of fresh values then scale
+    /// with the threads, but each insertion, invalidation, and sweep gets slower.
+    pub fn read_biased(ttl: Option<Duration>) -> Self {
+        Self {
+            inner: MapLock::Bravo(BravoRwLock::default()),
+            ttl: AtomicU64::new(encode_ttl(ttl)),
+            lookups: Lookups::default(),
+        }
+    }
+
+    /// Returns the time to live of the values. If `None`, values never expire.
+    pub fn ttl(&self) -> Option<Duration> {
+        let nanos = self.ttl.load(Ordering::Relaxed);
+        (nanos != NO_TTL).then(|| Duration::from_nanos(nanos))
+    }
+
+    /// Sets the time to live of the values, including those already computed. A time to live
+    /// longer than `u64::MAX - 1` nanoseconds, about 584 years, is cut to it.
+    pub fn set_ttl(&self, ttl: Option<Duration>) {
+        self.ttl.store(encode_ttl(ttl), Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the value computed at `computed_at` has not expired.
+    fn is_fresh(&self, computed_at: Instant) -> bool {
+        match self.ttl() {
+            Some(ttl) => computed_at.elapsed() < ttl,
+            None => true,
+        }
+    }
 }
 
 impl<K: Eq + Hash + Clone, V: Clone> Cache<K, V> {
+    /// Returns `true` if the cache is usable, i.e., no thread panicked while modifying it.
+    pub fn is_available(&self) -> bool {
+        !self.inner.is_poisoned()
+    }
+
+    /// Returns the entries whose values are computed and not expired, in arbitrary order.
+    pub fn entries(&self) -> Vec<(K, V)> {
+        self.inner
+            .read()
+            .iter()
+            .filter_map(|(key, value)| match value.get() {
+                Some((v, computed_at)) if self.is_fresh(*computed_at) => {
+                    Some((key.clone(), v.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Removes the computed value for `key`, so that the next `get_or_insert_with` recomputes it.
+    /// Returns `true` if there was such a value. A value being computed is not affected.
+    pub fn invalidate<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut hash_map = self.inner.write();
+        match hash_map.get(key) {
+            Some(value) if value.get().is_some() => hash_map.remove(key).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Removes all computed values. Returns their number. Values being computed are not affected.
+    pub fn invalidate_all(&self) -> usize {
+        let mut hash_map = self.inner.write();
+        let len = hash_map.len();
+        hash_map.retain(|_, value| value.get().is_none());
+        len - hash_map.len()
+    }
+
+    /// Removes the expired values, which would otherwise be kept until their keys are requested
+    /// again. Returns their number. Values being computed are not affected.
+    pub fn sweep(&self) -> usize {
+        if self.ttl().is_none() {
+            return 0;
+        }
+        let mut hash_map = self.inner.write();
+        let len = hash_map.len();
+        hash_map.retain(|_, value| match value.get() {
+            Some((_, computed_at)) => self.is_fresh(*computed_at),
+            None => true,
+        });
+        len - hash_map.len()
+    }
+
+    /// Removes the values for which `f` returns `false`, and returns their number. Values being
+    /// computed are not affected. `f` runs with the map locked, so it must not use this cache.
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&self, mut f: F) -> usize {
+        let mut hash_map = self.inner.write();
+        let len = hash_map.len();
+        hash_map.retain(|key, value| match value.get() {
+            Some((value, _)) => f(key, value),
+            None => true,
+        });
+        len - hash_map.len()
+    }
+
+    /// Runs the sweeper on the timers of `pool`, which `sweep`s periodically until all other
+    /// references to this are dropped.
+    pub fn spawn_sweeper(self: &Arc<Self>, pool: &ThreadPool)
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        let cache = Arc::downgrade(self);
+        let _ = pool
+            .timers()
+            .schedule_repeating(SWEEP_INTERVAL, move || match cache.upgrade() {
+                Some(cache) => {
+                    let _ = cache.sweep();
+                    true
+                }
+                None => false,
+            });
+    }
+
+    /// Registers the lookups and the size of this cache into `registry`, labeled with `name`.
+    /// The series are removed once the cache is dropped.
+    pub fn register_metrics(self: &Arc<Self>, registry: &Registry, name: &str)
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        for (result, hit) in [("hit", true), ("miss", false)] {
+            let cache = Arc::downgrade(self);
+            registry.counter_fn(
+                "hello_cache_lookups_total",
+                "Lookups of the cache by result.",
+                &[("cache", name), ("result", result)],
+                move || Some(cache.upgrade()?.lookups.counter(hit).get()),
+            );
+        }
+        let cache = Arc::downgrade(self);
+        registry.gauge_fn(
+            "hello_cache_entries",
+            "Entries of the cache, including the values being computed and the expired ones.",
+            &[("cache", name)],
+            move || Some(cache.upgrade()?.inner.read().len() as i64),
+        );
+    }
+
+    /// Returns the value for `key` if it is computed and not expired, counting the lookup as a
+    /// hit if it is.
+    fn get_fresh(&self, key: &K) -> Option<V> {
+        match self.inner.read().get(key).map(|value| value.get()) {
+            Some(Some((value, computed_at))) if self.is_fresh(*computed_at) => {
+                self.lookups.hits.increment();
+                Some(value.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the slot for `key`, which is either being computed or holds a fresh value. Replaces
+    /// the slot if it holds an expired value.
+    fn slot(&self, key: &K) -> Slot<V> {
+        if let Some(slot) = self.inner.read().get(key) {
+            match slot.get() {
+                Some((_, computed_at)) if !self.is_fresh(*computed_at) => {}
+                _ => return slot.clone(),
+            }
+        }
+
+        let mut hash_map = self.inner.write();
+        if let Some(slot) = hash_map.get(key) {
+            match slot.get() {
+                Some((_, computed_at)) if !self.is_fresh(*computed_at) => {}
+                _ => return slot.clone(),
+            }
+        }
+        let slot = Slot::default();
+        let _ = hash_map.insert(key.clone(), slot.clone());
+        slot
+    }
+
     /// Retrieve the value or insert a new one created by `f`.
     ///
     /// An invocation to this function should not block another invocation with a different key.
@@ -25,64 +315,108 @@ impl<K: Eq + Hash + Clone, V: Clone> Cache<K, V> {
     /// duplicate the work. That is, `f` should be run only once for each key. Specifically, even
     /// for the concurrent invocations of `get_or_insert_with(key, f)`, `f` is called only once.
     ///
+    /// If `f` panics, the panic is propagated to the caller, and one of the invocations waiting for
+    /// the value computes it again.
+    ///
     /// Hint: the [`Entry`] API may be useful in implementing this function.
     ///
     /// [`Entry`]: https://doc.rust-lang.org/stable/std/collections/hash_map/struct.HashMap.html#method.entry
     pub fn get_or_insert_with<F: FnOnce(K) -> V>(&self, key: K, f: F) -> V {
-        let read_hash_map = self.inner.read().unwrap();
-        let value_in_map = read_hash_map.get(&key);
-        match value_in_map {
-            Some(val) => {
-                let vall = val.borrow();
-                match vall {
-                    // 값이 잘 있음
-                    Some(result) => result.clone(),
-                    // None을 넣어둠 (아직 넣는 중임)
+        match self.try_get_or_insert_with(key, |key| Ok::<_, Infallible>(f(key))) {
+            Ok(result) => result,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Retrieve the value or insert a new one created by `f`, which may fail.
+    ///
+    /// This is the same as `get_or_insert_with`, except that if `f` fails, the error is returned
+    /// without caching anything, and one of the invocations waiting for the value computes it
+    /// again, as if `f` panicked.
+    pub fn try_get_or_insert_with<E, F: FnOnce(K) -> Result<V, E>>(
+        &self,
+        key: K,
+        f: F,
+    ) -> Result<V, E> {
+        // The slot is computed by one of the invocations without any lock held, and the others
+        // wait for it. If the computation fails or panics, the slot is left empty, and one of the
+        // waiting invocations computes it with its own `f`.
+        let slot = self.slot(&key);
+        let mut computed = false;
+        let result = slot.get_or_try_init(|| {
+            computed = true;
+            f(key).map(|value| (value, Instant::now()))
+        });
+        self.lookups.counter(!computed).increment();
+        let (value, _) = result?;
+        Ok(value.clone())
+    }
+
+    /// Retrieves the values for `keys`, inserting those missing with `f`, like
+    /// `get_or_insert_with` for each key. Returns each distinct key with its value, in the order
+    /// of their first appearances in `keys`.
+    ///
+    /// The missing values are computed concurrently, each in its own thread, so that a batch takes
+    /// about as long as its slowest key. Duplicate keys are computed only once.
+    pub fn get_or_insert_many<I, F>(&self, keys: I, f: F) -> Vec<(K, V)>
+    where
+        I: IntoIterator<Item = K>,
+        F: Fn(K) -> V + Sync,
+        K: Send + Sync,
+        V: Send + Sync,
+    {
+        let results = self.try_get_or_insert_many(keys, |key| Ok::<_, Infallible>(f(key)));
+        results
+            .into_iter()
+            .map(|(key, result)| match result {
+                Ok(value) => (key, value),
+                Err(never) => match never {},
+            })
+            .collect()
+    }
+
+    /// Retrieves the values for `keys`, inserting those missing with `f`, which may fail, like
+    /// `try_get_or_insert_with` for each key. Returns each distinct key with its result, in the
+    /// order of their first appearances in `keys`.
+    ///
+    /// See `get_or_insert_many` for how the values are computed.
+    pub fn try_get_or_insert_many<I, E, F>(&self, keys: I, f: F) -> Vec<(K, Result<V, E>)>
+    where
+        I: IntoIterator<Item = K>,
+        E: Send,
+        F: Fn(K) -> Result<V, E> + Sync,
+        K: Send + Sync,
+        V: Send + Sync,
+    {
+        let mut seen = HashSet::new();
+        let distinct = keys.into_iter().filter(|key| seen.insert(key.clone()));
+
+        thread::scope(|s| {
+            let f = &f;
+            // Spawns all computations before waiting for any of them, so that they run in parallel.
+            #[allow(clippy::needless_collect)]
+            let lookups = distinct
+                .map(|key| match self.get_fresh(&key) {
+                    Some(value) => (key, Either::Left(value)),
                     None => {
-                        drop(read_hash_map);
-                        loop {
-                            let r_hash_map = self.inner.read().unwrap();
-                            if let Some(value) = r_hash_map.get(&key) {
-                                if let Some(value_final) = value.borrow() {
-                                    return value_final.clone();
-                                }
-                            }
-                            drop(r_hash_map);
-                        }
+                        let handle = s.spawn({
+                            let key = key.clone();
+                            move || self.try_get_or_insert_with(key, f)
+                        });
+                        (key, Either::Right(handle))
                     }
-                }
-            }
-            // 없어서 넣어야 함
-            None => {
-                // Read drop 후 넣을 값 더미 생성
-                drop(read_hash_map);
-                let value = Arc::new(None);
-
-                // writelock 으로 받아온 hash map 에 더미 삽입 후 write lock 해제
-                let mut write_hash_map = self.inner.write().unwrap();
-                if write_hash_map.contains_key(&key) {
-                    drop(write_hash_map);
-                    loop {
-                        let r_hash_map = self.inner.read().unwrap();
-                        if let Some(value) = r_hash_map.get(&key) {
-                            if let Some(value_final) = value.borrow() {
-                                return value_final.clone();
-                            }
-                        }
-                        drop(r_hash_map);
-                    }
-                } else {
-                    write_hash_map.insert(key.clone(), Arc::clone(&value));
-                    drop(write_hash_map);
-
-                    // Result 계산 후 더미 레퍼런스에 집어넣기
-                    let result = f(key.clone());
-                    let mut write_hash_map = self.inner.write().unwrap();
-                    *write_hash_map.get_mut(&key).unwrap() = Arc::new(Some(result.clone()));
-                    drop(write_hash_map);
-                    result
-                }
-            }
-        }
+                })
+                .collect::<Vec<_>>();
+            lookups
+                .into_iter()
+                .map(|(key, lookup)| match lookup {
+                    Either::Left(value) => (key, Ok(value)),
+                    Either::Right(handle) => match handle.join() {
+                        Ok(result) => (key, result),
+                        Err(payload) => panic::resume_unwind(payload),
+                    },
+                })
+                .collect()
+        })
     }
 }
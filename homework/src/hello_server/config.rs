@@ -0,0 +1,264 @@
+//! Tunables of the handler that can be reloaded while the server is running.
+
+use core::fmt;
+use std::fs;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::reclaim::Ebr;
+use crate::AtomicArc;
+
+/// Limit on the request rate of each client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Maximum number of requests in a burst.
+    pub burst: u32,
+    /// Number of requests per second in the long run.
+    pub per_second: f64,
+}
+
+/// Tunables of the handler.
+///
+/// The configuration file consists of `key = value` lines, where durations are in (possibly
+/// fractional) seconds and sizes in bytes. Empty lines and lines starting with `#` are ignored,
+/// and the keys that are not given take the default values.
+///
+/// ```text
+/// read_timeout = 5
+/// write_timeout = 5
+/// header_timeout = 10
+/// # Both or neither of `rate_limit_burst` and `rate_limit_per_second` should be given.
+/// rate_limit_burst = 10
+/// rate_limit_per_second = 2.5
+/// cache_ttl = 60
+/// upstream = localhost:8080
+/// admin_token = secret
+/// max_body_len = 1048576
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Timeout of each `read` from the client.
+    pub read_timeout: Duration,
+    /// Timeout of each `write` to the client.
+    pub write_timeout: Duration,
+    /// Time limit for receiving the whole request head.
+    pub header_timeout: Duration,
+    /// Per-client rate limit. If `None`, requests are not limited.
+    pub rate_limit: Option<RateLimit>,
+    /// Time to live of the cached results. If `None`, they never expire.
+    pub cache_ttl: Option<Duration>,
+    /// Upstream server to forward the requests for uncached keys to. If `None`, the results are
+    /// computed locally.
+    pub upstream: Option<SocketAddr>,
+    /// Token required in `Authorization: Bearer <token>` for the admin requests, e.g., `DELETE
+    /// /cache`. If `None`, they are not protected.
+    pub admin_token: Option<String>,
+    /// Maximum length of a request body. Requests with a longer one are answered with 413, and
+    /// the connection is closed without reading the body.
+    pub max_body_len: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            read_timeout: Self::READ_TIMEOUT,
+            write_timeout: Self::WRITE_TIMEOUT,
+            header_timeout: Self::HEADER_TIMEOUT,
+            rate_limit: None,
+            cache_ttl: None,
+            upstream: None,
+            admin_token: None,
+            max_body_len: Self::MAX_BODY_LEN,
+        }
+    }
+}
+
+impl Config {
+    /// Default timeout of each `read`.
+    pub const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Default timeout of each `write`.
+    pub const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Default time limit for receiving the request head.
+    pub const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Default maximum length of a request body.
+    pub const MAX_BODY_LEN: u64 = 1024 * 1024;
+
+    /// Parses a configuration. Fails with `InvalidData` if it is malformed.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut config = Self::default();
+        let (mut burst, mut per_second) = (None, None);
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| invalid_data(format!("line {}: {reason}", i + 1));
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| invalid("expected `key = value`"))?;
+            let (key, value) = (key.trim(), value.trim());
+            let secs = || match value.parse::<f64>() {
+                Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+                _ => Err(invalid("expected a positive number of seconds")),
+            };
+
+            match key {
+                "read_timeout" => config.read_timeout = secs()?,
+                "write_timeout" => config.write_timeout = secs()?,
+                "header_timeout" => config.header_timeout = secs()?,
+                "cache_ttl" => config.cache_ttl = Some(secs()?),
+                "upstream" => match value.to_socket_addrs().map(|mut addrs| addrs.next()) {
+                    Ok(Some(addr)) => config.upstream = Some(addr),
+                    _ => return Err(invalid("expected a resolvable `host:port`")),
+                },
+                "admin_token" if !value.is_empty() => config.admin_token = Some(value.to_string()),
+                "admin_token" => return Err(invalid("expected a non-empty token")),
+                "max_body_len" => match value.parse() {
+                    Ok(value) => config.max_body_len = value,
+                    _ => return Err(invalid("expected a number of bytes")),
+                },
+                "rate_limit_burst" => match value.parse() {
+                    Ok(value) if value > 0 => burst = Some(value),
+                    _ => return Err(invalid("expected a positive integer")),
+                },
+                "rate_limit_per_second" => match value.parse() {
+                    Ok(value) if value > 0.0 => per_second = Some(value),
+                    _ => return Err(invalid("expected a positive number")),
+                },
+                _ => return Err(invalid(&format!("unknown key `{key}`"))),
+            }
+        }
+
+        config.rate_limit = match (burst, per_second) {
+            (Some(burst), Some(per_second)) => Some(RateLimit { burst, per_second }),
+            (None, None) => None,
+            _ => {
+                return Err(invalid_data(
+                    "`rate_limit_burst` and `rate_limit_per_second` must be given together",
+                ))
+            }
+        };
+        Ok(config)
+    }
+
+    /// Reads and parses the configuration file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+/// Callback of a subscriber of a `ConfigStore`. It is unsubscribed once it returns `false`.
+type Subscriber = Box<dyn FnMut(&Arc<Config>) -> bool + Send>;
+
+/// Live configuration of the server, shared by the workers.
+///
+/// The workers read the current version on each request without locking or retrying, and keep
+/// it for as long as they like. A new version replaces the current one as a whole, so that a
+/// request never sees a mix of the old and new tunables. The old version is freed once the last
+/// worker using it drops it.
+///
+/// The versions are published one at a time, and each of them is passed to the subscribers in
+/// order, which update the state derived from the configuration.
+pub struct ConfigStore {
+    /// Read with epoch-based reclamation, which never retries.
+    current: AtomicArc<Config, Ebr>,
+    /// The configuration file, read again by `reload`.
+    path: Option<PathBuf>,
+    /// Held while a version is published, so that the subscribers see the versions in order.
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl Default for ConfigStore {
+    fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl fmt::Debug for ConfigStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConfigStore")
+            .field("current", &self.get())
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ConfigStore {
+    /// Creates a store holding `config`, which has no configuration file to reload.
+    pub fn new(config: Config) -> Self {
+        Self {
+            current: AtomicArc::new(Arc::new(config)),
+            path: None,
+            subscribers: Mutex::default(),
+        }
+    }
+
+    /// Creates a store holding the configuration in the file at `path`, which `reload` reads
+    /// again.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        Ok(Self {
+            path: Some(path.clone()),
+            ..Self::new(Config::load(path)?)
+        })
+    }
+
+    /// Returns the path of the configuration file, if any.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns the current version.
+    pub fn get(&self) -> Arc<Config> {
+        self.current.load()
+    }
+
+    /// Replaces the current version with `config`, and passes it to the subscribers. Returns the
+    /// new version.
+    pub fn publish(&self, config: Config) -> Arc<Config> {
+        self.update(|current| *current = config)
+    }
+
+    /// Publishes the current version modified by `f`, and returns the new version. Other
+    /// publications wait until it is published, so that none of them is lost.
+    pub fn update<F: FnOnce(&mut Config)>(&self, f: F) -> Arc<Config> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let mut config = Config::clone(&self.get());
+        f(&mut config);
+        let config = Arc::new(config);
+        self.current.store(config.clone());
+        subscribers.retain_mut(|subscriber| subscriber(&config));
+        config
+    }
+
+    /// Reads the configuration file again, and publishes it. Fails with `NotFound` if the store
+    /// has no configuration file, and as `Config::load` does otherwise, keeping the current
+    /// version.
+    pub fn reload(&self) -> io::Result<Arc<Config>> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no configuration file to reload")
+        })?;
+        Ok(self.publish(Config::load(path)?))
+    }
+
+    /// Calls `f` with the current version, and then with each published version until it returns
+    /// `false`.
+    pub fn subscribe<F: FnMut(&Arc<Config>) -> bool + Send + 'static>(&self, mut f: F) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if f(&self.get()) {
+            subscribers.push(Box::new(f));
+        }
+    }
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
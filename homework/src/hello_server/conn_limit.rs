@@ -0,0 +1,74 @@
+//! Limit on simultaneously handled connections.
+
+use std::sync::Arc;
+
+use crate::Semaphore;
+
+/// What to do with a new connection when the limit is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverloadPolicy {
+    /// Stop accepting until a connection finishes.
+    Wait,
+    /// Accept the connection, but reject it (e.g., with 503).
+    Reject,
+}
+
+/// Caps the number of simultaneously handled connections, independently of the pool size.
+#[derive(Debug)]
+pub struct ConnectionLimit {
+    max: usize,
+    policy: OverloadPolicy,
+    /// A permit for each connection that can still be handled.
+    permits: Semaphore,
+}
+
+/// Permission to handle a connection. The slot is released when dropped.
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    limit: Arc<ConnectionLimit>,
+}
+
+impl ConnectionLimit {
+    /// Creates a limit of `max` connections. Panics if `max` is 0.
+    pub fn new(max: usize, policy: OverloadPolicy) -> Self {
+        assert!(max > 0);
+        Self {
+            max,
+            policy,
+            permits: Semaphore::new(max),
+        }
+    }
+
+    /// Returns the overload policy.
+    pub fn policy(&self) -> OverloadPolicy {
+        self.policy
+    }
+
+    /// Returns the number of connections being handled.
+    pub fn active(&self) -> usize {
+        self.max - self.permits.available_permits()
+    }
+
+    /// Acquires a permit for a new connection.
+    ///
+    /// If the limit is reached, blocks until a permit is released under `OverloadPolicy::Wait`, and
+    /// returns `None` under `OverloadPolicy::Reject`.
+    pub fn acquire(self: &Arc<Self>) -> Option<ConnectionPermit> {
+        let permit = match self.policy {
+            OverloadPolicy::Wait => self.permits.acquire(),
+            OverloadPolicy::Reject => self.permits.try_acquire()?,
+        };
+        // The connection permit releases it instead, as it outlives the borrow of `self`.
+        permit.forget();
+
+        Some(ConnectionPermit {
+            limit: self.clone(),
+        })
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.limit.permits.release();
+    }
+}
@@ -0,0 +1,170 @@
+//! In-process event bus, on which the components of the server publish events by topic.
+
+use crossbeam_channel::{
+    bounded, Receiver, RecvError, RecvTimeoutError, Sender, TryRecvError, TrySendError,
+};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::LockedHashMap;
+
+/// Buffer of a subscriber, as seen by the publishers.
+#[derive(Debug)]
+struct Subscriber<T> {
+    sender: Sender<T>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Subscribers of a topic.
+type Topic<T> = Mutex<Vec<Subscriber<T>>>;
+
+/// Handle to an event bus. Clones of it publish to and subscribe on the same bus.
+///
+/// Each subscription has a bounded buffer of its own, to which `publish` sends a clone of the
+/// event. Publishers never wait for subscribers: an event that does not fit in the buffer of a
+/// subscription is dropped for it, and counted in [`Subscription::dropped`]. A dropped
+/// subscription is removed from its topic when an event is published to the topic next.
+///
+/// The subscriptions are closed when all handles to the bus are dropped.
+pub struct EventBus<T> {
+    topics: Arc<LockedHashMap<String, Arc<Topic<T>>>>,
+}
+
+impl<T> fmt::Debug for EventBus<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut topics = Vec::new();
+        self.topics.for_each(|topic, _| topics.push(topic.clone()));
+        f.debug_struct("EventBus")
+            .field("topics", &topics)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Clone for EventBus<T> {
+    fn clone(&self) -> Self {
+        Self {
+            topics: self.topics.clone(),
+        }
+    }
+}
+
+impl<T> Default for EventBus<T> {
+    fn default() -> Self {
+        Self {
+            topics: Arc::default(),
+        }
+    }
+}
+
+impl<T: Clone> EventBus<T> {
+    /// Creates a bus without topics.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn topic(&self, topic: &str) -> Option<Arc<Topic<T>>> {
+        self.topics.get(topic)
+    }
+
+    /// Subscribes to the events published to `topic` from now on, buffering at most `capacity`
+    /// events that are not received yet. Panics if `capacity` is 0.
+    pub fn subscribe(&self, topic: &str, capacity: usize) -> Subscription<T> {
+        assert!(capacity > 0);
+        let (sender, receiver) = bounded(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let subscriber = Subscriber {
+            sender,
+            dropped: dropped.clone(),
+        };
+        let topic_subscribers = match self.topic(topic) {
+            Some(topic) => topic,
+            None => self
+                .topics
+                .get_or_insert_with(topic.to_string(), Default::default),
+        };
+        topic_subscribers.lock().unwrap().push(subscriber);
+        Subscription {
+            topic: topic.to_string(),
+            receiver,
+            dropped,
+        }
+    }
+
+    /// Sends `event` to the subscriptions of `topic`, and returns the number of those that had
+    /// room for it.
+    pub fn publish(&self, topic: &str, event: T) -> usize {
+        let topic = match self.topic(topic) {
+            Some(topic) => topic,
+            None => return 0,
+        };
+        let mut delivered = 0;
+        topic.lock().unwrap().retain(|subscriber| {
+            match subscriber.sender.try_send(event.clone()) {
+                Ok(()) => {
+                    delivered += 1;
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    let _ = subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+        delivered
+    }
+
+    /// Returns the number of the subscriptions of `topic`, including the dropped ones that are not
+    /// removed yet.
+    pub fn subscribers(&self, topic: &str) -> usize {
+        self.topic(topic)
+            .map_or(0, |topic| topic.lock().unwrap().len())
+    }
+}
+
+/// Subscription to a topic of an [`EventBus`]. The subscriptions of a topic receive its events in
+/// the same order, even if they are published concurrently.
+///
+/// Clones of the receiver returned by [`Subscription::receiver`] share the buffer of the
+/// subscription, so each event is received by one of them.
+#[derive(Debug)]
+pub struct Subscription<T> {
+    topic: String,
+    receiver: Receiver<T>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<T> Subscription<T> {
+    /// Returns the topic subscribed to.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Blocks until an event is published. Fails if the bus is dropped and all events are
+    /// received.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.receiver.recv()
+    }
+
+    /// Receives an event if one is buffered.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    /// Blocks until an event is published or `timeout` passes.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+
+    /// Returns the receiving end of the buffer, e.g., to `select!` over several subscriptions.
+    pub fn receiver(&self) -> &Receiver<T> {
+        &self.receiver
+    }
+
+    /// Returns the number of the events dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
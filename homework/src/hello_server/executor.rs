@@ -0,0 +1,214 @@
+//! Minimal executor that runs futures on a [`ThreadPool`].
+
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+// The executor of the hello server runs on its thread pool, so it is not modeled with loom.
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
+use std::sync::{Arc, Mutex};
+use std::task::Wake;
+
+use super::thread_pool::{Submitter, ThreadPool};
+use crate::{Parker, Unparker};
+
+/// The task is waiting to be woken.
+const IDLE: u8 = 0;
+/// The task is submitted to the pool, and is not polled yet.
+const SCHEDULED: u8 = 1;
+/// The task is being polled.
+const RUNNING: u8 = 2;
+/// The task is being polled, and is woken meanwhile, so it is polled again.
+const NOTIFIED: u8 = 3;
+/// The future is finished.
+const DONE: u8 = 4;
+
+type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Spawned future, which is its own waker.
+struct Task {
+    state: AtomicU8,
+    /// Taken out when finished. Only the job polling the task locks it, so it is never contended.
+    future: Mutex<Option<BoxFuture>>,
+    submitter: Submitter,
+}
+
+impl Task {
+    /// Submits the task to the pool, after the state is changed to `SCHEDULED` by the caller.
+    fn schedule(self: Arc<Self>) {
+        let submitter = self.submitter.clone();
+        // If the pool is dropped, the task is dropped, and so is its `JoinHandle` left pending.
+        let _ = submitter.execute(move || self.run());
+    }
+
+    /// Polls the future once, which is scheduled.
+    fn run(self: Arc<Self>) {
+        // Acquire: whatever the wakers did before waking the task is seen by the poll. Pairs with
+        // the Release in `wake`.
+        let _ = self.state.swap(RUNNING, Ordering::Acquire);
+        let waker = Waker::from(self.clone());
+        let mut future = self.future.lock().unwrap();
+        let poll = match future.as_mut() {
+            Some(future) => future.as_mut().poll(&mut Context::from_waker(&waker)),
+            None => return,
+        };
+        if poll.is_ready() {
+            *future = None;
+            self.state.store(DONE, Ordering::Relaxed);
+            return;
+        }
+        drop(future);
+
+        // A wake during the poll may have been missed by the future, so it is polled again. The
+        // swap keeps the Release of the wakes since then for the next poll.
+        if self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            let _ = self.state.swap(SCHEDULED, Ordering::Relaxed);
+            self.schedule();
+        }
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            // A task already scheduled or notified is changed as well, so that the next poll
+            // synchronizes with this wake.
+            let new = match state {
+                IDLE | SCHEDULED => SCHEDULED,
+                RUNNING | NOTIFIED => NOTIFIED,
+                _ => return,
+            };
+            // Release: pairs with the Acquire in `run`.
+            match self
+                .state
+                .compare_exchange(state, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) if state == IDLE => return self.schedule(),
+                Ok(_) => return,
+                Err(current) => state = current,
+            }
+        }
+    }
+}
+
+/// Output of a task, which is handed to its `JoinHandle`.
+struct Join<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Future that resolves to the output of a spawned task.
+///
+/// Dropping the handle detaches the task, which still runs to completion.
+pub struct JoinHandle<T> {
+    join: Arc<Mutex<Join<T>>>,
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JoinHandle").finish_non_exhaustive()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut join = self.join.lock().unwrap();
+        match join.output.take() {
+            Some(output) => Poll::Ready(output),
+            None => {
+                join.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Executor that polls the spawned futures on the workers of a [`ThreadPool`].
+///
+/// Each poll of a task is a job of the pool. When the task is woken, its waker submits it to the
+/// pool again, unless it is already submitted or being polled, in which case it is polled once
+/// more after the current poll. So a task is never polled by two workers at once, and a wake is
+/// never lost.
+///
+/// A task waiting to be woken is not a job of the pool, so it is not waited for by
+/// `ThreadPool::join`. A panicking task takes its worker down, as any panicking job does.
+#[derive(Debug, Clone)]
+pub struct Executor {
+    submitter: Submitter,
+}
+
+impl Executor {
+    /// Creates an executor that runs the tasks on `pool`. The tasks are dropped without running to
+    /// completion if the pool is dropped before then.
+    pub fn new(pool: &ThreadPool) -> Self {
+        Self {
+            submitter: pool.submitter(),
+        }
+    }
+
+    /// Spawns `future` as a new task, and returns the handle to its output.
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let join = Arc::new(Mutex::new(Join {
+            output: None,
+            waker: None,
+        }));
+        let handle = JoinHandle { join: join.clone() };
+        let future = async move {
+            let output = future.await;
+            let waker = {
+                let mut join = join.lock().unwrap();
+                join.output = Some(output);
+                join.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        };
+        let task = Arc::new(Task {
+            state: AtomicU8::new(SCHEDULED),
+            future: Mutex::new(Some(Box::pin(future))),
+            submitter: self.submitter.clone(),
+        });
+        task.schedule();
+        handle
+    }
+}
+
+/// Waker of `block_on`, which unparks the blocked thread.
+struct Unpark(Unparker);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread, parking it while the future is pending.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let parker = Parker::new();
+    let waker = Waker::from(Arc::new(Unpark(parker.unparker().clone())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        // A wake since the poll leaves a token, so this returns at once.
+        parker.park();
+    }
+}
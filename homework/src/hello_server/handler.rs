@@ -1,27 +1,156 @@
 //! Request handler with a cache.
 
+use log::{debug, error, info, warn};
 use once_cell::sync::Lazy;
-use regex::bytes::Regex;
-use std::io::prelude::*;
-use std::net::TcpStream;
+use regex::Regex;
+use std::io::{self, prelude::*};
+use std::mem;
+use std::net::{SocketAddr, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
+use super::access_log::{AccessLog, AccessLogEntry};
+use super::auth::Auth;
 use super::cache::Cache;
-use super::statistics::Report;
+use super::config::{Config, ConfigStore, RateLimit};
+use super::keep_alive::KeepAlive;
+use super::metrics::Metrics;
+use super::rate_limit::RateLimiter;
+use super::request::Request;
+use super::response::Response;
+use super::statistics::{Report, RequestId};
+use super::thread_pool::{PoolLoad, ThreadPool};
+use super::upstream;
+use super::websocket;
+use crate::{AtomicArc, RadixTree};
 
 /// Computes the result for the given key. So expensive, much wow.
 fn very_expensive_computation_that_takes_a_few_seconds(key: String) -> String {
-    println!("[handler] doing computation for key: {key}");
+    info!("doing computation for key: {key}");
     thread::sleep(Duration::from_secs(3));
     format!("{key}🐕")
 }
 
+/// Fetches the result for the given key from the upstream server at `addr`.
+fn fetch_from_upstream(addr: SocketAddr, key: &str, timeout: Duration) -> io::Result<Arc<str>> {
+    let body = upstream::fetch(addr, &format!("/{key}"), timeout)?;
+    Ok(String::from_utf8_lossy(&body).into())
+}
+
+/// Returns the key requested by `path`, if it is of the form `/{key}`.
+fn key_of(path: &str) -> Option<String> {
+    static KEY_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\A/(?P<key>\w+)\z").unwrap());
+    let key = KEY_REGEX.captures(path)?.name("key")?;
+    Some(key.as_str().to_string())
+}
+
+/// Returns the ETag of a cached result, a quoted SHA-1 digest of it, so that the same result has
+/// the same ETag even after it is computed again or the server is restarted.
+fn etag_of(result: &str) -> String {
+    format!("\"{}\"", sha1_smol::Sha1::from(result).digest())
+}
+
+/// Checks if the `If-None-Match` header `if_none_match` matches `etag`. As the header only guards
+/// a GET (or HEAD), the ETags are compared weakly, i.e., ignoring the `W/` prefix.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// Reader or writer that counts the bytes passing through it.
+#[derive(Debug)]
+struct Counting<T> {
+    inner: T,
+    count: u64,
+}
+
+impl<T> Counting<T> {
+    fn new(inner: T) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<T: Read> Read for Counting<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+}
+
+impl<T: Write> Write for Counting<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.count += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Shared string that can be read with `io::Cursor`.
+#[derive(Debug)]
+struct SharedStr(Arc<str>);
+
+impl AsRef<[u8]> for SharedStr {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+/// Configuration of the handler, with the state derived from it.
+#[derive(Debug, Default)]
+struct Settings {
+    config: Arc<Config>,
+    /// Per-client rate limiter. It is kept across reloads unless the rate limit changes.
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
 /// Hello handler with a cache.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct Handler {
-    cache: Arc<Cache<String, String>>,
+    /// The results are shared with the responses being sent, so that a large result is neither
+    /// copied nor formatted into a new page for each request.
+    cache: Arc<Cache<String, Arc<str>>>,
+    /// Current tunables shared by all clones of this handler, with the state derived from them.
+    /// They are swapped as a whole on each version of `config`, so that a request never sees a
+    /// mix of old and new ones.
+    settings: Arc<AtomicArc<Settings>>,
+    config: Arc<ConfigStore>,
+    metrics: Arc<Metrics>,
+    /// Load of the pool running this handler, checked by `/readyz`.
+    pool: Option<PoolLoad>,
+    /// Idle connections kept alive for further requests. If `None`, each connection is closed
+    /// after the first response.
+    keep_alive: Option<Arc<KeepAlive>>,
+    access_log: Option<AccessLog>,
+    /// Required for the admin routes. If `None`, they are open to anyone.
+    auth: Option<Arc<Auth>>,
+}
+
+impl Default for Handler {
+    fn default() -> Self {
+        let handler = Self {
+            cache: Arc::default(),
+            settings: Arc::default(),
+            config: Arc::default(),
+            metrics: Arc::default(),
+            pool: None,
+            keep_alive: None,
+            access_log: None,
+            auth: None,
+        };
+        handler.register_cache();
+        handler.follow_config();
+        handler
+    }
 }
 
 impl Handler {
@@ -48,33 +177,935 @@ impl Handler {
   </body>
 </html>";
 
-    /// Process the request and generate report.
-    pub fn handle_conn(&self, request_id: usize, mut stream: TcpStream) -> Report {
-        let mut buf = [0; 512];
-        let _ = stream.read(&mut buf).unwrap();
-
-        static REQUEST_REGEX: Lazy<Regex> =
-            Lazy::new(|| Regex::new(r"GET /(?P<key>\w+) HTTP/1.1\r\n").unwrap());
-        let key = REQUEST_REGEX
-            .captures(&buf)
-            .and_then(|cap| cap.name("key"))
-            .map(|key| String::from_utf8_lossy(key.as_bytes()));
-
-        let resp = if let Some(ref key) = key {
-            let result = self.cache.get_or_insert_with(
-                key.to_string(),
-                very_expensive_computation_that_takes_a_few_seconds,
+    const BAD_REQUEST: &'static str = "<!DOCTYPE html>
+<html lang=\"en\">
+  <head>
+    <meta charset=\"utf-8\">
+    <title>Hello!</title>
+  </head>
+  <body>
+    <h1>Huh?</h1>
+    <p>Sorry, I can't understand your request.</p>
+  </body>
+</html>";
+
+    const METHOD_NOT_ALLOWED: &'static str = "<!DOCTYPE html>
+<html lang=\"en\">
+  <head>
+    <meta charset=\"utf-8\">
+    <title>Hello!</title>
+  </head>
+  <body>
+    <h1>Oops!</h1>
+    <p>Sorry, I can only GET things.</p>
+  </body>
+</html>";
+
+    const INTERNAL_SERVER_ERROR: &'static str = "<!DOCTYPE html>
+<html lang=\"en\">
+  <head>
+    <meta charset=\"utf-8\">
+    <title>Hello!</title>
+  </head>
+  <body>
+    <h1>Ouch!</h1>
+    <p>Something went wrong while handling your request.</p>
+  </body>
+</html>";
+
+    const TOO_MANY_REQUESTS: &'static str = "<!DOCTYPE html>
+<html lang=\"en\">
+  <head>
+    <meta charset=\"utf-8\">
+    <title>Hello!</title>
+  </head>
+  <body>
+    <h1>Slow down!</h1>
+    <p>You sent too many requests. Please try again later.</p>
+  </body>
+</html>";
+
+    const BAD_GATEWAY: &'static str = "<!DOCTYPE html>
+<html lang=\"en\">
+  <head>
+    <meta charset=\"utf-8\">
+    <title>Hello!</title>
+  </head>
+  <body>
+    <h1>Oops!</h1>
+    <p>Sorry, the upstream server failed to answer your request.</p>
+  </body>
+</html>";
+
+    const UNAUTHORIZED: &'static str = "<!DOCTYPE html>
+<html lang=\"en\">
+  <head>
+    <meta charset=\"utf-8\">
+    <title>Hello!</title>
+  </head>
+  <body>
+    <h1>Halt!</h1>
+    <p>Sorry, you are not allowed to do that.</p>
+  </body>
+</html>";
+
+    const SERVICE_UNAVAILABLE: &'static str = "<!DOCTYPE html>
+<html lang=\"en\">
+  <head>
+    <meta charset=\"utf-8\">
+    <title>Hello!</title>
+  </head>
+  <body>
+    <h1>Busy!</h1>
+    <p>The server is handling too many connections. Please try again later.</p>
+  </body>
+</html>";
+
+    const PAYLOAD_TOO_LARGE: &'static str = "<!DOCTYPE html>
+<html lang=\"en\">
+  <head>
+    <meta charset=\"utf-8\">
+    <title>Hello!</title>
+  </head>
+  <body>
+    <h1>Oops!</h1>
+    <p>Sorry, your request is too large.</p>
+  </body>
+</html>";
+
+    /// Default timeout of each `read`.
+    pub const READ_TIMEOUT: Duration = Config::READ_TIMEOUT;
+
+    /// Default timeout of each `write`.
+    pub const WRITE_TIMEOUT: Duration = Config::WRITE_TIMEOUT;
+
+    /// Default time limit for receiving the request head.
+    pub const HEADER_TIMEOUT: Duration = Config::HEADER_TIMEOUT;
+
+    /// The server is not ready if more than this many jobs are waiting for a worker.
+    const READY_QUEUE_LIMIT: usize = 64;
+
+    /// Maximum size of the request head.
+    const MAX_HEAD_LEN: usize = 8 * 1024;
+
+    /// Maximum number of keys in a `/lookup` request.
+    const MAX_LOOKUP_KEYS: usize = 16;
+
+    /// WebSocket connections idle for longer than this are closed.
+    const WEBSOCKET_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Maximum payload length of a WebSocket frame.
+    const MAX_FRAME_LEN: usize = 64 * 1024;
+
+    /// Sets the timeout of each `read` from the client. Panics if `timeout` is zero.
+    pub fn with_read_timeout(self, timeout: Duration) -> Self {
+        assert!(!timeout.is_zero());
+        self.update_config(|config| config.read_timeout = timeout);
+        self
+    }
+
+    /// Sets the timeout of each `write` to the client. Panics if `timeout` is zero.
+    pub fn with_write_timeout(self, timeout: Duration) -> Self {
+        assert!(!timeout.is_zero());
+        self.update_config(|config| config.write_timeout = timeout);
+        self
+    }
+
+    /// Sets the time limit for receiving the whole request head. Panics if `timeout` is zero.
+    pub fn with_header_timeout(self, timeout: Duration) -> Self {
+        assert!(!timeout.is_zero());
+        self.update_config(|config| config.header_timeout = timeout);
+        self
+    }
+
+    /// Limits each client (identified by its IP address) to bursts of `burst` requests, refilled at
+    /// `per_second` requests per second. Requests exceeding the limit are answered with 429.
+    /// Panics if `burst` is 0 or `per_second` is not positive.
+    pub fn with_rate_limit(self, burst: u32, per_second: f64) -> Self {
+        assert!(burst > 0);
+        assert!(per_second > 0.0);
+        self.update_config(|config| config.rate_limit = Some(RateLimit { burst, per_second }));
+        self
+    }
+
+    /// Sets all tunables at once.
+    pub fn with_config(self, config: Config) -> Self {
+        self.reload(config);
+        self
+    }
+
+    /// Takes the tunables from `store`, following the versions published to it. The tunables set
+    /// before are dropped.
+    pub fn with_config_store(mut self, store: Arc<ConfigStore>) -> Self {
+        self.config = store;
+        self.follow_config();
+        self
+    }
+
+    /// Returns the store of the tunables, to which new versions are published.
+    pub fn config_store(&self) -> &Arc<ConfigStore> {
+        &self.config
+    }
+
+    /// Returns the current tunables.
+    pub fn config(&self) -> Config {
+        Config::clone(&self.settings().config)
+    }
+
+    /// Replaces the tunables of this handler and all its clones, by publishing them to the store.
+    /// The requests being handled keep using the old ones, and the following requests use the new
+    /// ones.
+    ///
+    /// The rate limiter is reset if the rate limit changes, and the cached results are kept.
+    pub fn reload(&self, config: Config) {
+        let _ = self.config.publish(config);
+    }
+
+    /// Reloads the tunables modified by `f`.
+    fn update_config<F: FnOnce(&mut Config)>(&self, f: F) {
+        let _ = self.config.update(f);
+    }
+
+    /// Derives the settings and the TTL of the cache from each version of the tunables in the
+    /// store, until this handler and its clones are dropped.
+    fn follow_config(&self) {
+        let settings = Arc::downgrade(&self.settings);
+        let cache = Arc::downgrade(&self.cache);
+        self.config.subscribe(move |config| {
+            let (settings, cache) = match (settings.upgrade(), cache.upgrade()) {
+                (Some(settings), Some(cache)) => (settings, cache),
+                _ => return false,
+            };
+            let previous = settings.load();
+            let rate_limiter = match config.rate_limit {
+                Some(limit) if previous.config.rate_limit == Some(limit) => {
+                    previous.rate_limiter.clone()
+                }
+                Some(limit) => Some(Arc::new(RateLimiter::new(limit.burst, limit.per_second))),
+                None => None,
+            };
+            cache.set_ttl(config.cache_ttl);
+            settings.store(Arc::new(Settings {
+                config: config.clone(),
+                rate_limiter,
+            }));
+            true
+        });
+    }
+
+    /// Returns the current settings.
+    fn settings(&self) -> Arc<Settings> {
+        self.settings.load()
+    }
+
+    /// Records the metrics of this handler to `metrics`, which are also served at `/metrics`
+    /// with all the metrics of their registry.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self.register_cache();
+        self
+    }
+
+    /// Registers the metrics of the cache into the registry of the handler's metrics.
+    fn register_cache(&self) {
+        self.cache
+            .register_metrics(self.metrics.registry(), "results");
+    }
+
+    /// Reports `pool`, which runs this handler, as not ready in `/readyz` when it is saturated.
+    /// The expired results are swept from the cache on the timers of `pool`.
+    pub fn with_pool(mut self, pool: &ThreadPool) -> Self {
+        self.pool = Some(pool.load());
+        self.cache.spawn_sweeper(pool);
+        self
+    }
+
+    /// Recomputes the result for a key if it was computed more than `ttl` ago.
+    pub fn with_cache_ttl(self, ttl: Duration) -> Self {
+        self.update_config(|config| config.cache_ttl = Some(ttl));
+        self
+    }
+
+    /// Forwards the requests for uncached keys to the HTTP server at `addr`, and caches the bodies
+    /// of its responses, instead of computing the results locally. Requests for which the upstream
+    /// server fails are answered with 502, and the failures are not cached.
+    pub fn with_upstream(self, addr: SocketAddr) -> Self {
+        self.update_config(|config| config.upstream = Some(addr));
+        self
+    }
+
+    /// Requires `Authorization: Bearer <token>` for the admin requests, e.g., `DELETE /cache` and
+    /// `POST /config`, unless they have the credentials of `with_auth`. Unauthorized ones are
+    /// answered with 401. Panics if `token` is empty.
+    pub fn with_admin_token(self, token: &str) -> Self {
+        assert!(!token.is_empty());
+        self.update_config(|config| config.admin_token = Some(token.to_string()));
+        self
+    }
+
+    /// Invalidates the cached result for `key`, so that it is recomputed on the next request.
+    /// Returns `true` if there was such a result.
+    pub fn invalidate(&self, key: &str) -> bool {
+        self.cache.invalidate(key)
+    }
+
+    /// Invalidates all cached results. Returns their number.
+    pub fn invalidate_all(&self) -> usize {
+        self.cache.invalidate_all()
+    }
+
+    /// Keeps connections alive for further requests until they are idle for `idle_timeout`. The
+    /// idle connections are closed by a reaper running on the timers of `pool`.
+    pub fn with_keep_alive(mut self, idle_timeout: Duration, pool: &ThreadPool) -> Self {
+        let keep_alive = Arc::new(KeepAlive::new(idle_timeout));
+        keep_alive.spawn_reaper(pool);
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Logs each request to `access_log`.
+    pub fn with_access_log(mut self, access_log: AccessLog) -> Self {
+        self.access_log = Some(access_log);
+        self
+    }
+
+    /// Requires the credentials accepted by `auth` for the admin routes: `/metrics`, `/cache`, and
+    /// `/cache/{key}`. Other requests are answered with 401 and the challenges of `auth`.
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(Arc::new(auth));
+        self
+    }
+
+    /// Reads the next request head, i.e., until the first empty line. `buf` holds the bytes
+    /// received but not processed yet, and the bytes after the head (e.g., of pipelined requests)
+    /// are left there. Returns `None` if the client closed the connection before sending a byte.
+    ///
+    /// Fails with `TimedOut` if the head is not received within the header timeout, regardless of
+    /// how frequently the client sends bytes. This bounds how long a client that trickles bytes
+    /// (slowloris) can occupy a worker, since each byte resets the read timeout.
+    fn read_head(
+        &self,
+        settings: &Settings,
+        stream: &mut TcpStream,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let config = &settings.config;
+        let deadline = Instant::now() + config.header_timeout;
+        let mut bytes = [0; 512];
+
+        loop {
+            if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                return Ok(Some(buf.drain(..end + 4).collect()));
+            }
+            if buf.len() > Self::MAX_HEAD_LEN {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            stream.set_read_timeout(Some(remaining.min(config.read_timeout)))?;
+
+            let len = stream.read(&mut bytes)?;
+            if len == 0 {
+                // The client closed the connection before finishing the head. Process what we have.
+                return Ok((!buf.is_empty()).then(|| mem::take(buf)));
+            }
+            buf.extend_from_slice(&bytes[..len]);
+        }
+    }
+
+    /// Reads the `len`-byte body of a request after its head, taking the bytes already received
+    /// from `buf` first. Fails with `UnexpectedEof` if the client closes the connection early.
+    fn read_body(
+        &self,
+        settings: &Settings,
+        stream: &mut TcpStream,
+        buf: &mut Vec<u8>,
+        len: usize,
+    ) -> io::Result<Vec<u8>> {
+        let mut body = buf.drain(..len.min(buf.len())).collect::<Vec<_>>();
+        if body.len() < len {
+            stream.set_read_timeout(Some(settings.config.read_timeout))?;
+            let _ = stream
+                .take((len - body.len()) as u64)
+                .read_to_end(&mut body)?;
+            if body.len() < len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        Ok(body)
+    }
+
+    /// Waits without a timeout until the client sends the next request on the kept-alive
+    /// connection, or the reaper closes it. Returns `false` in the latter case.
+    fn wait_next_request(
+        &self,
+        conn_id: usize,
+        stream: &TcpStream,
+        keep_alive: &KeepAlive,
+    ) -> io::Result<bool> {
+        keep_alive.set_idle(conn_id, stream)?;
+        stream.set_read_timeout(None)?;
+        let peeked = stream.peek(&mut [0]);
+        Ok(keep_alive.set_active(conn_id) && peeked? > 0)
+    }
+
+    /// Process the requests on the connection and generate a report for each of them.
+    ///
+    /// The `index`-th request on the connection gets the id `RequestId::new(conn_id, index)`, which
+    /// is sent back in the `X-Request-Id` header.
+    ///
+    /// Unless keep-alive is enabled with `with_keep_alive`, the connection is closed after the
+    /// first response. A connection upgraded to WebSocket at `/ws` echoes messages until it is
+    /// closed or idle for a minute.
+    pub fn handle_conn(&self, conn_id: usize, mut stream: TcpStream) -> Vec<Report> {
+        let mut reports = Vec::new();
+        let mut buf = Vec::new();
+
+        for index in 0.. {
+            let id = RequestId::new(conn_id, index);
+            let accepted = Instant::now();
+            let settings = self.settings();
+            let head = match self.read_head(&settings, &mut stream, &mut buf) {
+                Ok(Some(head)) => head,
+                Ok(None) => break,
+                Err(err) => {
+                    warn!("closing connection {conn_id}: {err}");
+                    let report = Report::new(id, None)
+                        .with_bytes(buf.len() as u64, 0)
+                        .with_duration(accepted.elapsed());
+                    reports.push(report);
+                    break;
+                }
+            };
+
+            let mut request = match Request::parse(&head) {
+                Ok(request) => Some(request),
+                Err(err) => {
+                    debug!("malformed request {id}: {err}");
+                    None
+                }
+            };
+            // The body of a request that is too large is not read at all.
+            let too_large = matches!(
+                &request,
+                Some(request) if request.content_length() > settings.config.max_body_len
+            );
+            let mut read = head.len() as u64;
+            if let Some(request) = request.as_mut().filter(|_| !too_large) {
+                // Fits in memory, as it is at most `max_body_len` bytes.
+                let len = request.content_length() as usize;
+                match self.read_body(&settings, &mut stream, &mut buf, len) {
+                    Ok(body) => request.set_body(body),
+                    Err(err) => {
+                        warn!("closing connection {conn_id}: {err}");
+                        let report = Report::new(id, None)
+                            .with_bytes(read + buf.len() as u64, 0)
+                            .with_duration(accepted.elapsed());
+                        reports.push(report);
+                        break;
+                    }
+                }
+                read += len as u64;
+            }
+            // After a malformed request or an unread body, where the next request begins is
+            // unclear.
+            let keep_alive = self.keep_alive.as_deref().filter(|_| {
+                !too_large && matches!(&request, Some(request) if !request.wants_close())
+            });
+
+            let start = Instant::now();
+            // A bug in handling a request must not kill the worker or the connection.
+            let dispatched = panic::catch_unwind(AssertUnwindSafe(|| {
+                if too_large {
+                    let resp = Response::new(413, "Payload Too Large", Self::PAYLOAD_TOO_LARGE);
+                    return (resp, None);
+                }
+                self.dispatch(&settings, &stream, request.as_ref())
+            }));
+            let (resp, key) = dispatched.unwrap_or_else(|_| {
+                error!("handler panicked on request {id}");
+                let resp = Response::new(500, "Internal Server Error", Self::INTERNAL_SERVER_ERROR);
+                (resp, None)
+            });
+            let resp = resp.with_header("X-Request-Id", id);
+            let resp = if matches!(&request, Some(request) if request.method() == "HEAD") {
+                resp.omit_body()
+            } else {
+                resp
+            };
+            let resp = match keep_alive {
+                // An upgrade response already says `Connection: Upgrade`.
+                _ if resp.status() == 101 => resp,
+                Some(keep_alive) => resp.with_header("Connection", "keep-alive").with_header(
+                    "Keep-Alive",
+                    format!("timeout={}", keep_alive.idle_timeout().as_secs()),
+                ),
+                None => resp.with_header("Connection", "close"),
+            };
+            let status = resp.status();
+            let (sent, written) = self.respond(&settings, id, &mut stream, resp);
+            self.metrics.record_request(status, start.elapsed());
+            self.log_access(&stream, request.as_ref(), status, start.elapsed());
+            let mut report = Report::new(id, key)
+                .with_status(status)
+                .with_bytes(read, written)
+                .with_duration(accepted.elapsed());
+            if let Some(request) = &request {
+                report = report.with_route(Self::route(request));
+            }
+
+            if status == 101 && sent {
+                // The WebSocket session is accounted to the upgrade request.
+                let (ws_read, ws_written) =
+                    self.echo_websocket(conn_id, &stream, mem::take(&mut buf));
+                reports.push(report.with_bytes(read + ws_read, written + ws_written));
+                break;
+            }
+            reports.push(report);
+            let keep_alive = match keep_alive {
+                Some(keep_alive) if sent => keep_alive,
+                _ => break,
+            };
+            if buf.is_empty() {
+                match self.wait_next_request(conn_id, &stream, keep_alive) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(err) => {
+                        warn!("closing connection {conn_id}: {err}");
+                        break;
+                    }
+                }
+            }
+        }
+
+        reports
+    }
+
+    /// Rejects the connection with 503 without reading the request, e.g., because the server is
+    /// overloaded, and generate report.
+    pub fn reject_conn(&self, conn_id: usize, mut stream: TcpStream) -> Report {
+        let id = RequestId::new(conn_id, 0);
+        let start = Instant::now();
+        let resp = Response::new(503, "Service Unavailable", Self::SERVICE_UNAVAILABLE)
+            .with_header("Retry-After", 1)
+            .with_header("Connection", "close")
+            .with_header("X-Request-Id", id);
+        let (_, written) = self.respond(&self.settings(), id, &mut stream, resp);
+        self.metrics.record_request(503, start.elapsed());
+        self.log_access(&stream, None, 503, start.elapsed());
+
+        Report::new(id, None)
+            .with_status(503)
+            .with_bytes(0, written)
+            .with_duration(start.elapsed())
+    }
+
+    /// Computes the response to the request, which is `None` if it is malformed. Returns the
+    /// response and the requested key.
+    fn dispatch(
+        &self,
+        settings: &Settings,
+        stream: &TcpStream,
+        request: Option<&Request>,
+    ) -> (Response, Option<String>) {
+        if let Some(limiter) = &settings.rate_limiter {
+            // If the peer address is unavailable, the connection is already broken anyway.
+            if !stream
+                .peer_addr()
+                .map_or(true, |peer| limiter.check(peer.ip()))
+            {
+                let resp = Response::new(429, "Too Many Requests", Self::TOO_MANY_REQUESTS);
+                return (resp, None);
+            }
+        }
+
+        let request = match request {
+            Some(request) => request,
+            None => return (Response::new(400, "Bad Request", Self::BAD_REQUEST), None),
+        };
+        if let Some(auth) = &self.auth {
+            let is_admin = matches!(
+                Self::route(request),
+                "/metrics" | "/cache" | "/cache/{key}" | "/config"
             );
-            format!(
-                "HTTP/1.1 200 OK\r\n\r\n{}",
-                Self::OK.replace("{key}", key).replace("{result}", &result)
-            )
-        } else {
-            format!("HTTP/1.1 404 NOT FOUND\r\n\r\n{}", Self::NOT_FOUND)
+            if is_admin && !auth.check(request) {
+                let resp = auth.challenges().into_iter().fold(
+                    Response::new(401, "Unauthorized", Self::UNAUTHORIZED),
+                    |resp, challenge| resp.with_header("WWW-Authenticate", challenge),
+                );
+                return (resp, None);
+            }
+        }
+
+        let (method, path) = (request.method(), request.path());
+        let is_cache_path = path == "/cache" || path.starts_with("/cache/");
+        if method == "DELETE" && is_cache_path {
+            return (self.purge(settings, request), None);
+        }
+        if path == "/config" {
+            if method == "POST" {
+                return (self.reload_config(settings, request), None);
+            }
+            let resp = Response::new(405, "Method Not Allowed", Self::METHOD_NOT_ALLOWED)
+                .with_header("Allow", "POST");
+            return (resp, None);
+        }
+        // HEAD is handled as GET, and the body is omitted when the response is sent.
+        if !matches!(method, "GET" | "HEAD") {
+            let allow = if is_cache_path {
+                "GET, HEAD, DELETE"
+            } else {
+                "GET, HEAD"
+            };
+            let resp = Response::new(405, "Method Not Allowed", Self::METHOD_NOT_ALLOWED)
+                .with_header("Allow", allow);
+            return (resp, None);
+        }
+
+        match path {
+            "/metrics" => {
+                let resp = Response::new(200, "OK", self.metrics.render())
+                    .with_header("Content-Type", "text/plain; version=0.0.4");
+                return (resp, None);
+            }
+            "/healthz" => {
+                // Responding at all means that the server is alive.
+                let resp =
+                    Response::new(200, "OK", "ok\n").with_header("Content-Type", "text/plain");
+                return (resp, None);
+            }
+            "/readyz" => {
+                let resp = match self.check_ready() {
+                    Ok(()) => Response::new(200, "OK", "ok\n"),
+                    Err(reason) => {
+                        Response::new(503, "Service Unavailable", format!("not ready: {reason}\n"))
+                    }
+                };
+                return (resp.with_header("Content-Type", "text/plain"), None);
+            }
+            "/lookup" => return (self.lookup(settings, request), None),
+            "/ws" => return (Self::upgrade_websocket(request), None),
+            "/cache" => {
+                // The cache may be large, so stream it entry by entry.
+                let entries = self.cache.entries().into_iter();
+                let chunks = entries.map(|(key, value)| format!("{key}: {value}\n").into_bytes());
+                let resp = Response::chunked(200, "OK", chunks)
+                    .with_header("Content-Type", "text/plain; charset=utf-8");
+                return (resp, None);
+            }
+            _ => {}
+        }
+
+        let key = match key_of(path) {
+            Some(key) => key,
+            None => return (Response::new(404, "Not Found", Self::NOT_FOUND), None),
+        };
+        let resp = match settings.config.upstream {
+            Some(addr) => {
+                let timeout = settings.config.read_timeout;
+                let fetched = self.cache.try_get_or_insert_with(key.clone(), |key| {
+                    fetch_from_upstream(addr, &key, timeout)
+                });
+                match fetched {
+                    Ok(result) => Self::cached_page(request, result, Self::proxied_page),
+                    Err(err) => {
+                        warn!("failed to fetch {key} from upstream {addr}: {err}");
+                        Response::new(502, "Bad Gateway", Self::BAD_GATEWAY)
+                    }
+                }
+            }
+            None => {
+                let result = self.cache.get_or_insert_with(key.clone(), |key| {
+                    very_expensive_computation_that_takes_a_few_seconds(key).into()
+                });
+                Self::cached_page(request, result, |result| Self::ok_page(&key, result))
+            }
+        };
+        (resp, Some(key))
+    }
+
+    /// Returns the response to a WebSocket handshake request: 101 if it is valid, and 400
+    /// otherwise.
+    fn upgrade_websocket(request: &Request) -> Response {
+        let bad_request = || Response::new(400, "Bad Request", Self::BAD_REQUEST);
+        let is_upgrade = matches!(
+            request.header("Upgrade"),
+            Some(upgrade) if upgrade.eq_ignore_ascii_case("websocket")
+        );
+        let key = match request.header("Sec-WebSocket-Key") {
+            Some(key) if is_upgrade && request.method() == "GET" => key,
+            _ => return bad_request(),
         };
+        if request.header("Sec-WebSocket-Version") != Some("13") {
+            return bad_request().with_header("Sec-WebSocket-Version", 13);
+        }
+        Response::new(101, "Switching Protocols", Vec::new())
+            .with_header("Upgrade", "websocket")
+            .with_header("Connection", "Upgrade")
+            .with_header("Sec-WebSocket-Accept", websocket::accept_key(key))
+    }
+
+    /// Echoes WebSocket messages on the upgraded connection until it is closed. `buf` holds the
+    /// bytes the client sent right after the handshake. Returns the number of bytes read and
+    /// written.
+    fn echo_websocket(&self, conn_id: usize, stream: &TcpStream, buf: Vec<u8>) -> (u64, u64) {
+        let mut reader = Counting::new(io::Cursor::new(buf).chain(stream));
+        let mut writer = Counting::new(stream);
+        let result = stream
+            .set_read_timeout(Some(Self::WEBSOCKET_IDLE_TIMEOUT))
+            .and_then(|_| websocket::echo(&mut reader, &mut writer, Self::MAX_FRAME_LEN));
+        match result {
+            Ok(echoed) => debug!("websocket {conn_id} closed after echoing {echoed} frames"),
+            Err(err) => warn!("closing websocket {conn_id}: {err}"),
+        }
+        (reader.count, writer.count)
+    }
+
+    /// Returns 401 if the request does not have the admin token, if any, unless the credentials of
+    /// `auth` authorize it on their own.
+    fn check_admin_token(&self, settings: &Settings, request: &Request) -> Result<(), Response> {
+        let token = match &settings.config.admin_token {
+            Some(token) => token,
+            None => return Ok(()),
+        };
+        // Both share the `Authorization` header, so the request cannot have both credentials.
+        if self.auth.as_ref().map_or(false, |auth| auth.check(request)) {
+            return Ok(());
+        }
+        // The token may change on reload, so it is not kept in an `Auth`.
+        if !Auth::new("admin").with_bearer(token).check(request) {
+            return Err(Response::new(401, "Unauthorized", Self::UNAUTHORIZED)
+                .with_header("WWW-Authenticate", "Bearer"));
+        }
+        Ok(())
+    }
 
-        stream.write_all(resp.as_bytes()).unwrap();
+    /// Reloads the configuration file of the store for `POST /config`, if the request has the
+    /// admin token. Answers with 409 if the store has no configuration file, and with 422 if the
+    /// file cannot be loaded, keeping the current tunables.
+    fn reload_config(&self, settings: &Settings, request: &Request) -> Response {
+        if let Err(resp) = self.check_admin_token(settings, request) {
+            return resp;
+        }
+        let text = |status, reason, body: String| {
+            Response::new(status, reason, body)
+                .with_header("Content-Type", "text/plain; charset=utf-8")
+        };
+        match self.config.reload() {
+            Ok(config) => {
+                info!("reloaded configuration: {config:?}");
+                text(200, "OK", "reloaded\n".to_string())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound && self.config.path().is_none() => {
+                text(409, "Conflict", format!("{err}\n"))
+            }
+            Err(err) => {
+                warn!("cannot reload the configuration: {err}");
+                text(
+                    422,
+                    "Unprocessable Entity",
+                    format!("cannot reload: {err}\n"),
+                )
+            }
+        }
+    }
+
+    /// Invalidates the cached results for `DELETE /cache` (all of them) and `DELETE /cache/{key}`,
+    /// if the request has the admin token.
+    fn purge(&self, settings: &Settings, request: &Request) -> Response {
+        if let Err(resp) = self.check_admin_token(settings, request) {
+            return resp;
+        }
+
+        let text = |body: String| {
+            Response::new(200, "OK", body).with_header("Content-Type", "text/plain; charset=utf-8")
+        };
+        let path = request.path();
+        if path == "/cache" {
+            let count = self.invalidate_all();
+            info!("invalidated {count} cached results");
+            return text(format!("invalidated {count} results\n"));
+        }
+        match path.strip_prefix("/cache").and_then(key_of) {
+            Some(key) if self.invalidate(&key) => {
+                info!("invalidated the cached result for {key}");
+                text(format!("invalidated {key}\n"))
+            }
+            Some(key) => Response::new(404, "Not Found", format!("{key} is not cached\n"))
+                .with_header("Content-Type", "text/plain; charset=utf-8"),
+            None => Response::new(404, "Not Found", Self::NOT_FOUND),
+        }
+    }
+
+    /// Returns the results for the comma-separated keys in the `keys` parameter of the query, one
+    /// `key: result` line for each distinct key. The uncached results are computed (or fetched
+    /// from the upstream server) concurrently.
+    fn lookup(&self, settings: &Settings, request: &Request) -> Response {
+        static WORD_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\A\w+\z").unwrap());
+        let bad_request = || Response::new(400, "Bad Request", Self::BAD_REQUEST);
+
+        let keys = match request.param("keys") {
+            Some(keys) => keys.split(','),
+            None => return bad_request(),
+        };
+        let keys = keys
+            .map(|key| WORD_REGEX.is_match(key).then(|| key.to_string()))
+            .collect::<Option<Vec<_>>>();
+        let keys = match keys {
+            Some(keys) if keys.len() <= Self::MAX_LOOKUP_KEYS => keys,
+            _ => return bad_request(),
+        };
+
+        let results = match settings.config.upstream {
+            Some(addr) => {
+                let timeout = settings.config.read_timeout;
+                self.cache
+                    .try_get_or_insert_many(keys, |key| fetch_from_upstream(addr, &key, timeout))
+            }
+            None => self
+                .cache
+                .get_or_insert_many(keys, |key| {
+                    very_expensive_computation_that_takes_a_few_seconds(key).into()
+                })
+                .into_iter()
+                .map(|(key, result)| (key, Ok(result)))
+                .collect(),
+        };
+
+        let mut body = String::new();
+        for (key, result) in results {
+            match result {
+                Ok(result) => body.push_str(&format!("{key}: {result}\n")),
+                Err(err) => {
+                    warn!("failed to fetch {key} from upstream: {err}");
+                    return Response::new(502, "Bad Gateway", Self::BAD_GATEWAY);
+                }
+            }
+        }
+        Response::new(200, "OK", body).with_header("Content-Type", "text/plain; charset=utf-8")
+    }
+
+    /// Returns the route of the request for the statistics, i.e., its path, except that `{key}`
+    /// stands for the keys (as in `/{key}` and `/cache/{key}`) and `/*` for unknown paths.
+    fn route(request: &Request) -> &'static str {
+        const ROUTES: [&str; 7] = [
+            "/metrics", "/healthz", "/readyz", "/lookup", "/ws", "/cache", "/config",
+        ];
+        /// The routes with a key by the prefixes of their paths, matched by the longest one.
+        static KEY_ROUTES: Lazy<RadixTree<&str>> = Lazy::new(|| {
+            let routes = RadixTree::new();
+            for (prefix, route) in [("/", "/{key}"), ("/cache/", "/cache/{key}")] {
+                routes.insert(prefix.as_bytes(), route).unwrap();
+            }
+            routes
+        });
+        let path = request.path();
+        ROUTES
+            .into_iter()
+            .find(|route| *route == path)
+            .unwrap_or_else(|| {
+                KEY_ROUTES.longest_prefix(path.as_bytes(), |found| match found {
+                    // The rest of the path, from the last slash of the prefix, is a key.
+                    Some((len, route)) if key_of(&path[len - 1..]).is_some() => *route,
+                    _ => "/*",
+                })
+            })
+    }
+
+    /// Logs the request to the access log, if any. `request` is `None` if it is malformed.
+    fn log_access(
+        &self,
+        stream: &TcpStream,
+        request: Option<&Request>,
+        status: u16,
+        duration: Duration,
+    ) {
+        if let Some(access_log) = &self.access_log {
+            access_log.log(AccessLogEntry {
+                time: SystemTime::now(),
+                peer: stream.peer_addr().ok(),
+                method: request.map(|request| request.method().to_string()),
+                path: request.map(|request| request.target().to_string()),
+                status,
+                duration,
+            });
+        }
+    }
+
+    /// Returns the page made by `page` for the cached `result` with its ETag, or 304 without the
+    /// page if the client already has it, i.e., `If-None-Match` matches the ETag.
+    fn cached_page<F>(request: &Request, result: Arc<str>, page: F) -> Response
+    where
+        F: FnOnce(Arc<str>) -> Response,
+    {
+        let etag = etag_of(&result);
+        let resp = match request.header("If-None-Match") {
+            Some(if_none_match) if etag_matches(if_none_match, &etag) => {
+                Response::new(304, "Not Modified", Vec::new())
+            }
+            _ => page(result),
+        };
+        resp.with_header("ETag", etag)
+    }
+
+    /// Returns the page for the result of `key`, streaming `result` without copying it.
+    fn ok_page(key: &str, result: Arc<str>) -> Response {
+        let (head, tail) = Self::OK.split_once("{result}").unwrap();
+        let head = head.replace("{key}", key);
+        let len = head.len() + result.len() + tail.len();
+        let body = io::Cursor::new(head)
+            .chain(io::Cursor::new(SharedStr(result)))
+            .chain(tail.as_bytes());
+        Response::from_reader(200, "OK", body, Some(len as u64))
+    }
+
+    /// Returns the page fetched from the upstream server, streaming `result` without copying it.
+    fn proxied_page(result: Arc<str>) -> Response {
+        let len = result.len();
+        Response::from_reader(
+            200,
+            "OK",
+            io::Cursor::new(SharedStr(result)),
+            Some(len as u64),
+        )
+    }
+
+    /// Checks if the server can serve requests in a timely manner.
+    fn check_ready(&self) -> Result<(), &'static str> {
+        if !self.cache.is_available() {
+            return Err("cache is unavailable");
+        }
+        if let Some(pool) = &self.pool {
+            if pool.queued() > Self::READY_QUEUE_LIMIT {
+                return Err("thread pool is saturated");
+            }
+        }
+        Ok(())
+    }
 
-        Report::new(request_id, key.map(String::from))
+    /// Writes the response to the client. Returns whether it succeeded, and the number of bytes
+    /// written.
+    fn respond(
+        &self,
+        settings: &Settings,
+        id: RequestId,
+        stream: &mut TcpStream,
+        resp: Response,
+    ) -> (bool, u64) {
+        let mut writer = Counting::new(&mut *stream);
+        // A client that stops reading must not block the worker forever, and failing to write the
+        // response is the client's problem, not the worker's.
+        if let Err(err) = writer
+            .inner
+            .set_write_timeout(Some(settings.config.write_timeout))
+            .and_then(|_| resp.write_to(&mut writer))
+        {
+            warn!("failed to respond to request {id}: {err}");
+            return (false, writer.count);
+        }
+        (true, writer.count)
     }
 }
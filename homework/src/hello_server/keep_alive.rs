@@ -0,0 +1,93 @@
+//! Idle kept-alive connections and their reaper.
+
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::io;
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::thread_pool::ThreadPool;
+
+/// Kept-alive connections waiting for their next request.
+///
+/// A worker waiting for the next request on a kept-alive connection blocks without a timeout, so
+/// that it does not need to wake up periodically. Instead, the reaper `sweep`s the idle connections
+/// and shuts down those idle for longer than the idle timeout, which wakes up their workers.
+#[derive(Debug)]
+pub struct KeepAlive {
+    idle_timeout: Duration,
+    /// Idle connections by id, with a handle to shut them down and the time of their last activity.
+    idle: Mutex<HashMap<usize, (TcpStream, Instant)>>,
+}
+
+impl KeepAlive {
+    /// Creates an empty set of idle connections. Panics if `idle_timeout` is zero.
+    pub fn new(idle_timeout: Duration) -> Self {
+        assert!(!idle_timeout.is_zero());
+        Self {
+            idle_timeout,
+            idle: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the idle timeout.
+    pub fn idle_timeout(&self) -> Duration {
+        self.idle_timeout
+    }
+
+    /// Returns the number of idle connections.
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    /// Marks the connection `id` as idle from now on.
+    pub fn set_idle(&self, id: usize, stream: &TcpStream) -> io::Result<()> {
+        let handle = stream.try_clone()?;
+        let _ = self
+            .idle
+            .lock()
+            .unwrap()
+            .insert(id, (handle, Instant::now()));
+        Ok(())
+    }
+
+    /// Marks the connection `id` as active. Returns `false` if it was already reaped.
+    pub fn set_active(&self, id: usize) -> bool {
+        self.idle.lock().unwrap().remove(&id).is_some()
+    }
+
+    /// Shuts down the connections idle for longer than the idle timeout. Returns their number.
+    pub fn sweep(&self) -> usize {
+        let now = Instant::now();
+        let mut idle = self.idle.lock().unwrap();
+        let len = idle.len();
+        idle.retain(|id, (stream, last_activity)| {
+            if now.duration_since(*last_activity) <= self.idle_timeout {
+                return true;
+            }
+            debug!("closing idle connection {id}");
+            if let Err(err) = stream.shutdown(Shutdown::Both) {
+                warn!("failed to close idle connection {id}: {err}");
+            }
+            false
+        });
+        len - idle.len()
+    }
+
+    /// Runs the reaper on the timers of `pool`, which `sweep`s periodically until all other
+    /// references to this are dropped.
+    pub fn spawn_reaper(self: &Arc<Self>, pool: &ThreadPool) {
+        let interval = (self.idle_timeout / 2).min(Duration::from_secs(1));
+        let keep_alive = Arc::downgrade(self);
+        let _ = pool
+            .timers()
+            .schedule_repeating(interval, move || match keep_alive.upgrade() {
+                Some(keep_alive) => {
+                    let _ = keep_alive.sweep();
+                    true
+                }
+                None => false,
+            });
+    }
+}
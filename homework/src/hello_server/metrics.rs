@@ -0,0 +1,98 @@
+//! Live server metrics in the Prometheus text format.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::thread_pool::ThreadPool;
+use crate::metrics::{Counter, Histogram, Registry};
+
+/// Upper bounds (in seconds) of the request latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Status codes that the handler responds with, in increasing order. Their counters are registered
+/// up front, so that recording a response takes no lock.
+const STATUS_CODES: [u16; 14] = [
+    101, 200, 304, 400, 401, 404, 405, 409, 413, 422, 429, 500, 502, 503,
+];
+
+/// Metrics shared by all workers of a server, kept in a [`Registry`].
+///
+/// Unlike [`Statistics`](super::Statistics), which is aggregated by the reporter and only
+/// available after shutdown, these are updated by the workers themselves and can be rendered at
+/// any time, along with the metrics that the other components register into the same registry.
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Registry,
+    /// Number of responses for each status code of `STATUS_CODES`, in the same order.
+    requests: Box<[Arc<Counter>]>,
+    /// Number of responses with the other status codes.
+    other_requests: Arc<Counter>,
+    latency: Arc<Histogram>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Creates new metrics, in a registry of their own.
+    pub fn new() -> Self {
+        Self::with_registry(&Registry::new())
+    }
+
+    /// Creates new metrics that also report the load of `pool`, in a registry of their own.
+    pub fn with_pool(pool: &ThreadPool) -> Self {
+        let metrics = Self::new();
+        pool.register_metrics(&metrics.registry, "main");
+        metrics
+    }
+
+    /// Creates new metrics in `registry`, e.g., the [`global`](crate::metrics::global) one.
+    pub fn with_registry(registry: &Registry) -> Self {
+        let requests = |code: &str| {
+            registry.counter(
+                "hello_requests_total",
+                "Number of responses by status code.",
+                &[("code", code)],
+            )
+        };
+        Self {
+            registry: registry.clone(),
+            requests: STATUS_CODES
+                .iter()
+                .map(|status| requests(&status.to_string()))
+                .collect(),
+            other_requests: requests("other"),
+            latency: registry.histogram(
+                "hello_request_duration_seconds",
+                "Request latency.",
+                &[],
+                &LATENCY_BUCKETS,
+            ),
+        }
+    }
+
+    /// Returns the registry of the metrics.
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Records a response with the given status code that took `latency` to produce.
+    pub fn record_request(&self, status: u16, latency: Duration) {
+        let counter = match STATUS_CODES.binary_search(&status) {
+            Ok(index) => &self.requests[index],
+            Err(_) => &self.other_requests,
+        };
+        counter.increment();
+        self.latency.observe(latency.as_secs_f64());
+    }
+
+    /// Renders all the metrics of the registry in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        self.registry.gather().to_string()
+    }
+}
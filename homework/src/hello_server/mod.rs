@@ -1,13 +1,46 @@
 //! Hello server with a cache.
 
+mod access_log;
+mod actor;
+mod auth;
 mod cache;
+mod config;
+mod conn_limit;
+mod event_bus;
+mod executor;
 mod handler;
+mod keep_alive;
+mod metrics;
+mod rate_limit;
+mod reporter;
+mod request;
+mod response;
 mod statistics;
 mod tcp;
 mod thread_pool;
+mod timer;
+mod upstream;
+mod websocket;
 
+pub use access_log::{AccessLog, AccessLogEntry};
+pub use actor::{Actor, ActorSystem, Address};
+pub use auth::Auth;
 pub use cache::Cache;
+pub use config::{Config, ConfigStore, RateLimit};
+pub use conn_limit::{ConnectionLimit, ConnectionPermit, OverloadPolicy};
+pub use event_bus::{EventBus, Subscription};
+pub use executor::{block_on, Executor, JoinHandle};
 pub use handler::Handler;
-pub use statistics::{Report, Statistics};
-pub use tcp::CancellableTcpListener;
-pub use thread_pool::ThreadPool;
+pub use keep_alive::KeepAlive;
+pub use metrics::Metrics;
+pub use rate_limit::RateLimiter;
+pub use reporter::{
+    relaxed_report_channel, report_channel, ReportPolicy, ReportReceiver, ReportSender, Reporter,
+};
+pub use request::Request;
+pub use response::{Body, ChunkedWriter, Response};
+pub use statistics::{Histogram, LiveTraffic, Report, RequestId, Statistics, StatusClass, Traffic};
+pub use tcp::{CancellableTcpListener, ListenerOptions, ListenerStatus, PendingConnection};
+pub use thread_pool::{PoolLoad, ThreadPool};
+pub use timer::{TimerHandle, TimerWheel};
+pub use websocket::{Frame, Opcode};
@@ -0,0 +1,132 @@
+//! Per-client rate limiting.
+
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::cache::Cache;
+
+/// Interval between the sweeps of the full buckets.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Token bucket: holds at most `capacity` tokens and gains `rate` tokens per second.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64) -> Self {
+        Self {
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Refills the bucket and takes a token from it if possible.
+    fn try_take(&mut self, capacity: f64, rate: f64) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns `true` if the bucket would be full if refilled at `now`, and so is as good as a new
+    /// one.
+    fn is_full(&self, now: Instant, capacity: f64, rate: f64) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens + elapsed * rate >= capacity
+    }
+}
+
+/// Token-bucket rate limiter keyed by the peer's IP address.
+///
+/// The buckets are kept in a [`Cache`], so that the bucket for each client is created exactly once
+/// even if the client's first requests are handled concurrently by several workers, and requests
+/// from different clients only contend on the map, not on each other's buckets.
+///
+/// A bucket that is full again is as good as a new one, so the buckets of the clients idle long
+/// enough to refill them are swept every `SWEEP_INTERVAL` by the next `check`. Otherwise, a bucket
+/// would be kept for every client ever seen.
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: Cache<IpAddr, Arc<Mutex<TokenBucket>>>,
+    capacity: f64,
+    rate: f64,
+    /// Time at which `next_sweep` is counted from.
+    epoch: Instant,
+    /// Time of the next sweep, in nanoseconds since `epoch`.
+    next_sweep: AtomicU64,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter that allows bursts of `burst` requests per client, refilled at
+    /// `per_second` requests per second. Panics if `burst` is 0 or `per_second` is not positive.
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        assert!(burst > 0);
+        assert!(per_second > 0.0);
+        Self {
+            buckets: Cache::default(),
+            capacity: burst.into(),
+            rate: per_second,
+            epoch: Instant::now(),
+            next_sweep: AtomicU64::new(SWEEP_INTERVAL.as_nanos() as u64),
+        }
+    }
+
+    /// Returns `true` if a request from `ip` is allowed, consuming a token from its bucket.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        self.sweep_if_due();
+        let bucket = self.buckets.get_or_insert_with(ip, |_| {
+            Arc::new(Mutex::new(TokenBucket::new(self.capacity)))
+        });
+        let mut bucket = bucket.lock().unwrap();
+        bucket.try_take(self.capacity, self.rate)
+    }
+
+    /// Removes the buckets that are full, and returns their number. The buckets being used by a
+    /// `check` are kept, so that the tokens it takes are not lost.
+    pub fn sweep(&self) -> usize {
+        let now = Instant::now();
+        self.buckets.retain(|_, bucket| {
+            // The map is locked, so no `check` gets another reference to the bucket meanwhile.
+            Arc::strong_count(bucket) > 1
+                || !bucket
+                    .lock()
+                    .unwrap()
+                    .is_full(now, self.capacity, self.rate)
+        })
+    }
+
+    /// Sweeps the buckets if `SWEEP_INTERVAL` passed since the last sweep, unless another thread
+    /// is about to.
+    fn sweep_if_due(&self) {
+        // A run of `u64::MAX` nanoseconds is about 584 years.
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        let next_sweep = self.next_sweep.load(Ordering::Relaxed);
+        if now >= next_sweep
+            && self
+                .next_sweep
+                .compare_exchange(
+                    next_sweep,
+                    now + SWEEP_INTERVAL.as_nanos() as u64,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            let _ = self.sweep();
+        }
+    }
+}
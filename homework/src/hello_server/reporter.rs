@@ -0,0 +1,278 @@
+//! Reporter that aggregates reports from the workers.
+
+use crossbeam_channel::{bounded, never, select, tick, Receiver, SendError, Sender, TrySendError};
+use log::{debug, info};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::statistics::{LiveTraffic, Report, Statistics, StatusClass};
+// Like the live traffic, the view of the statistics is modeled with loom, so it is created only by
+// the servers opting into it.
+use crate::{ConcurrentCounter, KFifo, LeftRight};
+
+/// What a worker does with a report when the channel to the reporter is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportPolicy {
+    /// Waits until the reporter catches up.
+    #[default]
+    Block,
+    /// Drops the report, only counting it in `Statistics::dropped`.
+    Drop,
+    /// Adds the report to statistics shared by the workers, which the reporter merges into its
+    /// own from time to time. The workers never wait for the reporter, but they contend for the
+    /// shared statistics while the channel is full.
+    Coalesce,
+}
+
+/// Reports that did not fit in the channel.
+#[derive(Debug, Default)]
+struct Overflow {
+    dropped: ConcurrentCounter,
+    coalesced: Mutex<Statistics>,
+}
+
+impl Overflow {
+    /// Moves the overflown reports into `stats`.
+    fn drain_into(&self, stats: &mut Statistics) {
+        stats.add_dropped(self.dropped.take() as u64);
+        stats.merge(mem::take(&mut *self.coalesced.lock().unwrap()));
+    }
+}
+
+/// Creates a channel of reports to a reporter that holds at most `capacity` reports, handling the
+/// reports sent while it is full according to `policy`.
+pub fn report_channel(capacity: usize, policy: ReportPolicy) -> (ReportSender, ReportReceiver) {
+    let (sender, receiver) = bounded(capacity);
+    let overflow = Arc::new(Overflow::default());
+    let sender = ReportSender {
+        route: Route::Channel(sender, policy),
+        overflow: overflow.clone(),
+    };
+    let receiver = ReportReceiver {
+        receiver,
+        relaxed: None,
+        overflow,
+    };
+    (sender, receiver)
+}
+
+/// Creates an unbounded channel of reports, which reach the reporter in the order they are sent
+/// only up to a window of `k` reports. The workers rarely contend with each other when sending, and
+/// never wait for the reporter.
+///
+/// The statistics do not depend on the order of the reports, except for the interim snapshots,
+/// which may miss a report sent before one they count.
+pub fn relaxed_report_channel(k: usize) -> (ReportSender, ReportReceiver) {
+    let queue = Arc::new(KFifo::new(k));
+    // Rung after each report, so that the reporter does not poll the queue.
+    let (doorbell, ring) = bounded(1);
+    let sender = ReportSender {
+        route: Route::Relaxed(queue.clone(), doorbell),
+        overflow: Arc::default(),
+    };
+    let receiver = ReportReceiver {
+        receiver: never(),
+        relaxed: Some((queue, ring)),
+        overflow: Arc::default(),
+    };
+    (sender, receiver)
+}
+
+/// Where a sender puts the reports.
+#[derive(Debug, Clone)]
+enum Route {
+    /// A bounded channel, whose overflow is handled by the policy.
+    Channel(Sender<Report>, ReportPolicy),
+    /// A k-FIFO queue, and the doorbell of the reporter.
+    Relaxed(Arc<KFifo<Report>>, Sender<()>),
+}
+
+/// Sending end of a channel of reports. Clones of it send to the same reporter.
+#[derive(Debug, Clone)]
+pub struct ReportSender {
+    route: Route,
+    overflow: Arc<Overflow>,
+}
+
+impl ReportSender {
+    /// Sends `report` to the reporter. Fails only if the reporter is gone.
+    pub fn send(&self, report: Report) -> Result<(), SendError<Report>> {
+        let (sender, policy) = match &self.route {
+            Route::Channel(sender, policy) => (sender, policy),
+            Route::Relaxed(queue, doorbell) => {
+                queue.push(report);
+                // If the doorbell is already rung, the reporter has yet to drain the queue.
+                return match doorbell.try_send(()) {
+                    // The reporter is gone, so any report in the queue is as undelivered as this.
+                    Err(TrySendError::Disconnected(())) => Err(SendError(queue.pop().unwrap())),
+                    _ => Ok(()),
+                };
+            }
+        };
+        let report = match sender.try_send(report) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Disconnected(report)) => return Err(SendError(report)),
+            Err(TrySendError::Full(report)) => report,
+        };
+        match policy {
+            ReportPolicy::Block => return sender.send(report),
+            ReportPolicy::Drop => {
+                self.overflow.dropped.increment();
+            }
+            ReportPolicy::Coalesce => self.overflow.coalesced.lock().unwrap().add_report(report),
+        }
+        Ok(())
+    }
+}
+
+/// Receiving end of a channel of reports, consumed by `Reporter::run`.
+#[derive(Debug)]
+pub struct ReportReceiver {
+    receiver: Receiver<Report>,
+    /// The queue of a relaxed channel and its doorbell, in which case `receiver` never receives.
+    relaxed: Option<(Arc<KFifo<Report>>, Receiver<()>)>,
+    overflow: Arc<Overflow>,
+}
+
+impl From<Receiver<Report>> for ReportReceiver {
+    /// Receives from a plain channel, whose senders never drop reports.
+    fn from(receiver: Receiver<Report>) -> Self {
+        Self {
+            receiver,
+            relaxed: None,
+            overflow: Arc::default(),
+        }
+    }
+}
+
+/// Aggregates reports into statistics, periodically emitting interim snapshots.
+#[derive(Debug, Default)]
+pub struct Reporter {
+    /// Interval between interim snapshots. `None` disables them.
+    interval: Option<Duration>,
+    /// Receives the interim snapshots.
+    snapshot_sender: Option<Sender<Statistics>>,
+    /// Published to after each report.
+    live: Option<Arc<LiveTraffic>>,
+    /// Mirrors the statistics, updated after each report.
+    view: Option<Arc<LeftRight<Statistics>>>,
+}
+
+impl Reporter {
+    /// Creates a reporter that emits no interim snapshots.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Emits a snapshot of the statistics every `interval` to the log and the snapshot channel.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Sends the interim snapshots to `sender`. Snapshots are dropped if the channel is full, so
+    /// that a slow consumer does not stall the reporter.
+    pub fn with_snapshot_sender(mut self, sender: Sender<Statistics>) -> Self {
+        self.snapshot_sender = Some(sender);
+        self
+    }
+
+    /// Publishes the traffic to `live` as the reports are added, so that other threads can read
+    /// it while the reporter is running.
+    pub fn with_live_traffic(mut self, live: Arc<LiveTraffic>) -> Self {
+        self.live = Some(live);
+        self
+    }
+
+    /// Mirrors the statistics into `view` as the reports are added, so that other threads can read
+    /// all of them while the reporter is running, without ever waiting for it.
+    pub fn with_statistics_view(mut self, view: Arc<LeftRight<Statistics>>) -> Self {
+        self.view = Some(view);
+        self
+    }
+
+    /// Adds `report` to the view of the statistics, if any.
+    fn mirror_report(&self, report: &Report) {
+        if let Some(view) = &self.view {
+            view.write(|stats| stats.add_report(report.clone()));
+        }
+    }
+
+    /// Replaces the view of the statistics, if any, with `stats`.
+    fn mirror(&self, stats: &Statistics) {
+        if let Some(view) = &self.view {
+            view.write(|view| view.clone_from(stats));
+        }
+    }
+
+    /// Publishes the traffic of `classes` to the live traffic, if any.
+    fn publish(&self, stats: &Statistics, classes: &[StatusClass]) {
+        if let Some(live) = &self.live {
+            for class in classes {
+                live.publish(stats, *class);
+            }
+        }
+    }
+
+    /// Adds `report` to `stats`, and publishes it.
+    fn add_report(&self, stats: &mut Statistics, report: Report) {
+        debug!("[report] {report:?}");
+        let class = StatusClass::of(report.status());
+        self.mirror_report(&report);
+        stats.add_report(report);
+        self.publish(stats, &[class]);
+    }
+
+    /// Aggregates `reports` until all of their senders are dropped, and returns the final
+    /// statistics, including the dropped and coalesced reports.
+    pub fn run(&self, reports: impl Into<ReportReceiver>) -> Statistics {
+        let ReportReceiver {
+            receiver,
+            relaxed,
+            overflow,
+        } = reports.into();
+        let (queue, ring) = match relaxed {
+            Some((queue, ring)) => (Some(queue), ring),
+            None => (None, never()),
+        };
+        let ticker = self.interval.map_or_else(never, tick);
+        let mut stats = Statistics::default();
+
+        loop {
+            select! {
+                recv(receiver) -> report => match report {
+                    Ok(report) => self.add_report(&mut stats, report),
+                    Err(_) => break,
+                },
+                recv(ring) -> rung => {
+                    // The doorbell is rung after the reports are pushed, and this drains all of
+                    // them, including those of the senders dropped since.
+                    let queue = queue.as_ref().unwrap();
+                    while let Some(report) = queue.pop() {
+                        self.add_report(&mut stats, report);
+                    }
+                    if rung.is_err() {
+                        break;
+                    }
+                }
+                recv(ticker) -> _ => {
+                    overflow.drain_into(&mut stats);
+                    // The overflown reports may be of any class.
+                    self.publish(&stats, &StatusClass::ALL);
+                    self.mirror(&stats);
+                    info!("[interim stat] {stats:?}");
+                    if let Some(sender) = &self.snapshot_sender {
+                        let _ = sender.try_send(stats.clone());
+                    }
+                }
+            }
+        }
+
+        // The senders are all dropped, so nothing overflows any more.
+        overflow.drain_into(&mut stats);
+        self.publish(&stats, &StatusClass::ALL);
+        self.mirror(&stats);
+        stats
+    }
+}
@@ -0,0 +1,220 @@
+//! HTTP requests.
+
+use std::io;
+
+/// Head of an HTTP/1.x request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    method: String,
+    target: String,
+    version: String,
+    /// Percent-decoded path of `target`.
+    path: String,
+    /// Percent-decoded parameters in the query of `target`.
+    params: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    /// Length of the body, given by `Content-Length`.
+    content_length: u64,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Parses the head of a request, i.e., the request line and the headers, up to and including
+    /// the empty line after them. The empty line may be missing if the client closed the
+    /// connection early.
+    ///
+    /// Fails with `InvalidData` if the head is malformed: the request line must be `METHOD
+    /// /target HTTP/1.x` and end with CRLF, each header must be `Name: value`, and the
+    /// percent-encoded bytes in the target must be valid and decode to UTF-8. The body is not
+    /// part of the head, so it is empty until given by `set_body`. As only bodies of known length
+    /// are supported, `Transfer-Encoding` is rejected, and so are conflicting `Content-Length`s.
+    pub fn parse(head: &[u8]) -> io::Result<Self> {
+        let head = std::str::from_utf8(head).map_err(|_| invalid_data("head is not UTF-8"))?;
+        let (request_line, headers) = head
+            .split_once("\r\n")
+            .ok_or_else(|| invalid_data("request line is not terminated"))?;
+
+        let mut parts = request_line.split(' ');
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if parts.next().is_none() => {
+                (method, target, version)
+            }
+            _ => return Err(invalid_data("malformed request line")),
+        };
+        if method.is_empty() || !method.bytes().all(|b| b.is_ascii_uppercase()) {
+            return Err(invalid_data("malformed method"));
+        }
+        if !target.starts_with('/') || target.bytes().any(|b| b.is_ascii_control()) {
+            return Err(invalid_data("malformed request target"));
+        }
+        if !matches!(version, "HTTP/1.0" | "HTTP/1.1") {
+            return Err(invalid_data("unsupported HTTP version"));
+        }
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let path = percent_decode(path, false)?;
+        let params = query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let (name, value) = param.split_once('=').unwrap_or((param, ""));
+                Ok((percent_decode(name, true)?, percent_decode(value, true)?))
+            })
+            .collect::<io::Result<_>>()?;
+
+        let headers = headers
+            .split("\r\n")
+            .take_while(|line| !line.is_empty())
+            .map(|line| {
+                let (name, value) = line
+                    .split_once(':')
+                    .ok_or_else(|| invalid_data("malformed header"))?;
+                // Whitespace before the colon or a line folded from the previous one is rejected,
+                // as they are often used to smuggle requests.
+                if name.is_empty() || name.bytes().any(|b| !is_token(b)) {
+                    return Err(invalid_data("malformed header name"));
+                }
+                let value = value.trim_matches(|c| c == ' ' || c == '\t');
+                if value.chars().any(|c| c.is_ascii_control() && c != '\t') {
+                    return Err(invalid_data("malformed header value"));
+                }
+                Ok((name.to_string(), value.to_string()))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut content_length = None;
+        for (name, value) in &headers {
+            // Each of them would let a proxy in front of the server and the server disagree on
+            // where the body ends.
+            if name.eq_ignore_ascii_case("Transfer-Encoding") {
+                return Err(invalid_data("transfer codings are not supported"));
+            }
+            if !name.eq_ignore_ascii_case("Content-Length") {
+                continue;
+            }
+            let len = match value.parse::<u64>() {
+                Ok(len) if value.bytes().all(|b| b.is_ascii_digit()) => len,
+                _ => return Err(invalid_data("malformed content length")),
+            };
+            if matches!(content_length, Some(other) if other != len) {
+                return Err(invalid_data("conflicting content lengths"));
+            }
+            content_length = Some(len);
+        }
+
+        Ok(Self {
+            method: method.to_string(),
+            target: target.to_string(),
+            version: version.to_string(),
+            path,
+            params,
+            headers,
+            content_length: content_length.unwrap_or_default(),
+            body: Vec::new(),
+        })
+    }
+
+    /// Returns the method, e.g., `GET`.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns the request target as sent, e.g., `/lookup?keys=a%2Cb`.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Returns the HTTP version, e.g., `HTTP/1.1`.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Returns the percent-decoded path of the target, e.g., `/lookup`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the percent-decoded `name=value` parameters in the query of the target, in order.
+    /// `+` in the query stands for a space.
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// Returns the value of the first query parameter `name`, if any.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the headers, in order.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Returns the value of the first header `name`, if any. Header names are case-insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the length of the body given by `Content-Length`, or 0 if there is none.
+    pub fn content_length(&self) -> u64 {
+        self.content_length
+    }
+
+    /// Returns the body.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Sets the body, read after the head.
+    pub fn set_body(&mut self, body: Vec<u8>) {
+        self.body = body;
+    }
+
+    /// Checks if the client asks to close the connection after the response.
+    pub fn wants_close(&self) -> bool {
+        matches!(
+            self.header("Connection"),
+            Some(connection) if connection
+                .split(',')
+                .any(|option| option.trim().eq_ignore_ascii_case("close"))
+        )
+    }
+}
+
+/// Checks if `b` may appear in a token, e.g., a header name.
+fn is_token(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// Decodes the percent-encoded bytes in `s`, and `+` as a space if `plus_as_space`.
+fn percent_decode(s: &str, plus_as_space: bool) -> io::Result<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        match b {
+            b'%' => {
+                let hex = rest
+                    .get(..2)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| invalid_data("malformed percent-encoding"))?;
+                bytes.push(hex);
+                rest = &rest[2..];
+            }
+            b'+' if plus_as_space => bytes.push(b' '),
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("percent-encoded bytes are not UTF-8"))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
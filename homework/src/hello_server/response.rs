@@ -0,0 +1,234 @@
+//! HTTP responses.
+
+use once_cell::sync::Lazy;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::Pool;
+
+/// Buffers in which the heads of the responses are written, reused across the responses.
+static HEAD_BUFS: Lazy<Pool<Vec<u8>>> = Lazy::new(|| {
+    Pool::with_reset(
+        || Vec::with_capacity(HEAD_BUF_LEN),
+        |buf: &mut Vec<u8>| buf.clear(),
+    )
+});
+
+/// Initial capacity of the buffers of `HEAD_BUFS`, which fits the heads of most responses.
+const HEAD_BUF_LEN: usize = 512;
+
+/// Body of a response.
+pub enum Body {
+    /// Body of known length, sent with `Content-Length`.
+    Full(Vec<u8>),
+    /// Body of unknown length produced piece by piece, sent with `Transfer-Encoding: chunked`.
+    /// Empty pieces are skipped, since an empty chunk marks the end of the body.
+    Chunked(Box<dyn Iterator<Item = Vec<u8>> + Send>),
+    /// Body read piece by piece from a reader. It is sent with `Content-Length` if its length is
+    /// given, and with `Transfer-Encoding: chunked` otherwise.
+    Reader(Box<dyn Read + Send>, Option<u64>),
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(body) => f.debug_tuple("Full").field(&body.len()).finish(),
+            Self::Chunked(_) => f.debug_tuple("Chunked").finish(),
+            Self::Reader(_, len) => f.debug_tuple("Reader").field(len).finish(),
+        }
+    }
+}
+
+/// HTTP/1.1 response.
+#[derive(Debug)]
+pub struct Response {
+    status: u16,
+    reason: &'static str,
+    headers: Vec<(String, String)>,
+    body: Body,
+    omit_body: bool,
+}
+
+impl Response {
+    /// Creates a response with the given status and body.
+    pub fn new(status: u16, reason: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            reason,
+            headers: Vec::new(),
+            body: Body::Full(body.into()),
+            omit_body: false,
+        }
+    }
+
+    /// Creates a response whose body is the concatenation of `chunks`, sent as they are produced.
+    pub fn chunked<I>(status: u16, reason: &'static str, chunks: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        Self {
+            status,
+            reason,
+            headers: Vec::new(),
+            body: Body::Chunked(Box::new(chunks.into_iter())),
+            omit_body: false,
+        }
+    }
+
+    /// Creates a response whose body is read from `reader`, which produces `len` bytes if given.
+    ///
+    /// The body is streamed in pieces of bounded size, so that a large body need not be in memory
+    /// at once. Since each piece is written only after the previous one is accepted by the client,
+    /// a slow client slows down the reading as well.
+    pub fn from_reader<R>(status: u16, reason: &'static str, reader: R, len: Option<u64>) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        Self {
+            status,
+            reason,
+            headers: Vec::new(),
+            body: Body::Reader(Box::new(reader), len),
+            omit_body: false,
+        }
+    }
+
+    /// Adds a header.
+    pub fn with_header(mut self, name: &str, value: impl fmt::Display) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Returns the status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Omits the body when the response is written, as in the response to a HEAD request. The
+    /// headers still describe the body, e.g., with its `Content-Length`.
+    pub fn omit_body(mut self) -> Self {
+        self.omit_body = true;
+        self
+    }
+
+    /// Checks if the status allows no body: 1xx (e.g., switching to WebSocket), 204 No Content,
+    /// and 304 Not Modified responses end with their head.
+    fn is_bodiless(&self) -> bool {
+        self.status / 100 == 1 || matches!(self.status, 204 | 304)
+    }
+
+    /// Appends the status line and the headers, followed by an empty line, to `head`.
+    fn write_head(&self, head: &mut Vec<u8>) -> io::Result<()> {
+        write!(head, "HTTP/1.1 {} {}\r\n", self.status, self.reason)?;
+        for (name, value) in &self.headers {
+            write!(head, "{name}: {value}\r\n")?;
+        }
+        match &self.body {
+            _ if self.is_bodiless() => {}
+            Body::Full(body) => write!(head, "Content-Length: {}\r\n", body.len())?,
+            Body::Reader(_, Some(len)) => write!(head, "Content-Length: {len}\r\n")?,
+            Body::Chunked(_) | Body::Reader(_, None) => {
+                head.extend_from_slice(b"Transfer-Encoding: chunked\r\n")
+            }
+        }
+        head.extend_from_slice(b"\r\n");
+        Ok(())
+    }
+
+    /// Writes the response to `writer`.
+    pub fn write_to<W: Write>(self, writer: &mut W) -> io::Result<()> {
+        let mut head = HEAD_BUFS.checkout();
+        self.write_head(&mut head)?;
+        writer.write_all(&head)?;
+        // Checks the buffer back in before the body, which may take long to send.
+        drop(head);
+        if self.omit_body || self.is_bodiless() {
+            return writer.flush();
+        }
+
+        match self.body {
+            Body::Full(body) => writer.write_all(&body)?,
+            Body::Chunked(chunks) => {
+                let mut chunked = ChunkedWriter::new(&mut *writer);
+                for chunk in chunks {
+                    chunked.write_all(&chunk)?;
+                }
+                let _ = chunked.finish()?;
+            }
+            Body::Reader(reader, Some(len)) => {
+                let copied = copy(&mut reader.take(len), writer)?;
+                if copied < len {
+                    // The client would wait for the rest of the body forever.
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+            Body::Reader(mut reader, None) => {
+                let mut chunked = ChunkedWriter::new(&mut *writer);
+                let _ = copy(&mut reader, &mut chunked)?;
+                let _ = chunked.finish()?;
+            }
+        }
+
+        writer.flush()
+    }
+}
+
+/// Size of the pieces in which a `Body::Reader` is sent.
+const STREAM_BUF_LEN: usize = 8 * 1024;
+
+/// Copies `reader` to `writer` in pieces of at most `STREAM_BUF_LEN` bytes. Returns the number of
+/// bytes copied.
+///
+/// Unlike `io::copy`, this writes each piece as soon as it is read, so that each write to a
+/// `ChunkedWriter` is a chunk of bounded size.
+fn copy<R: Read + ?Sized, W: Write + ?Sized>(reader: &mut R, writer: &mut W) -> io::Result<u64> {
+    let mut buf = [0; STREAM_BUF_LEN];
+    let mut copied = 0;
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => return Ok(copied),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buf[..len])?;
+        copied += len as u64;
+    }
+}
+
+/// Writer that encodes each `write` as a chunk of the chunked transfer coding.
+#[derive(Debug)]
+pub struct ChunkedWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    /// Creates a chunked writer on top of `inner`.
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Writes the last chunk and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(b"0\r\n\r\n")?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // An empty chunk would terminate the body.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.inner, "{:x}\r\n", buf.len())?;
+        self.inner.write_all(buf)?;
+        self.inner.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
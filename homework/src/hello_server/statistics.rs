@@ -1,31 +1,586 @@
 //! Server statisics
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write as _};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+// Unlike the rest of the server, the snapshot of the live traffic is modeled with loom, so the live
+// traffic is created only by the servers opting into it.
+use crate::AtomicSnapshot;
+
+/// Unique id of a request, sent to the client in the `X-Request-Id` header.
+///
+/// It consists of the id of the connection, assigned when it is accepted, and the index of the
+/// request in the connection, as a kept-alive connection may carry several requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RequestId {
+    connection: usize,
+    index: usize,
+}
+
+impl RequestId {
+    /// Creates the id of the `index`-th request of the connection `connection`.
+    pub fn new(connection: usize, index: usize) -> Self {
+        Self { connection, index }
+    }
+
+    /// Returns the id of the connection.
+    pub fn connection(&self) -> usize {
+        self.connection
+    }
+
+    /// Returns the index of the request in the connection.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.connection, self.index)
+    }
+}
+
+/// Class of the response to a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StatusClass {
+    /// 1xx
+    Informational,
+    /// 2xx
+    Success,
+    /// 3xx
+    Redirection,
+    /// 4xx
+    ClientError,
+    /// 5xx
+    ServerError,
+    /// No response was sent, e.g., because the client timed out.
+    NoResponse,
+}
+
+impl StatusClass {
+    /// All classes, in order.
+    pub const ALL: [Self; 6] = [
+        Self::Informational,
+        Self::Success,
+        Self::Redirection,
+        Self::ClientError,
+        Self::ServerError,
+        Self::NoResponse,
+    ];
+
+    /// Returns the class of the response with the given status code, or `NoResponse` if `None`.
+    pub fn of(status: Option<u16>) -> Self {
+        match status.map(|status| status / 100) {
+            Some(1) => Self::Informational,
+            Some(2) => Self::Success,
+            Some(3) => Self::Redirection,
+            Some(4) => Self::ClientError,
+            Some(5) => Self::ServerError,
+            _ => Self::NoResponse,
+        }
+    }
+
+    /// Returns the name of the class, e.g., `2xx`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Informational => "1xx",
+            Self::Success => "2xx",
+            Self::Redirection => "3xx",
+            Self::ClientError => "4xx",
+            Self::ServerError => "5xx",
+            Self::NoResponse => "none",
+        }
+    }
+}
+
+/// Amount of traffic.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Traffic {
+    /// Number of requests.
+    pub requests: usize,
+    /// Number of bytes read from the clients.
+    pub bytes_read: u64,
+    /// Number of bytes written to the clients.
+    pub bytes_written: u64,
+}
+
+impl Traffic {
+    fn add(&mut self, report: &Report) {
+        self.requests += 1;
+        self.bytes_read += report.bytes_read;
+        self.bytes_written += report.bytes_written;
+    }
+
+    fn merge(&mut self, other: Traffic) {
+        self.requests += other.requests;
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+    }
+}
+
+/// Traffic of each status class, which the reporter publishes as it adds the reports, so that other
+/// threads can read consistent totals while the server is running.
+///
+/// The classes are the registers of an atomic snapshot, each written only by the reporter. A report
+/// changes the traffic of a single class, so a scan sees the traffic of exactly the reports added
+/// up to some point, and the classes add up to the total traffic. Only the reports coalesced on
+/// overflow, which the reporter drains all at once, are published class by class.
+#[derive(Debug, Default)]
+pub struct LiveTraffic {
+    classes: AtomicSnapshot<Traffic, { StatusClass::ALL.len() }>,
+}
+
+impl LiveTraffic {
+    /// Creates live traffic with no requests.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publishes the traffic of `class` in `stats`. Called only by the reporter.
+    pub(super) fn publish(&self, stats: &Statistics, class: StatusClass) {
+        self.classes
+            .update(class as usize, stats.class_traffic(class));
+    }
+
+    /// Returns the traffic of each class, in the order of `StatusClass::ALL`.
+    pub fn scan(&self) -> [(StatusClass, Traffic); StatusClass::ALL.len()] {
+        let classes = self.classes.scan();
+        StatusClass::ALL.map(|class| (class, classes[class as usize]))
+    }
+
+    /// Returns the total traffic.
+    pub fn total(&self) -> Traffic {
+        let mut total = Traffic::default();
+        for traffic in self.classes.scan() {
+            total.merge(traffic);
+        }
+        total
+    }
+}
 
 /// Report for each operation
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Report {
-    _id: usize,
+    id: RequestId,
     key: Option<String>, // None represents invalid request
+    /// Route of the request, e.g., `/{key}`. `None` if the request is malformed.
+    route: Option<&'static str>,
+    /// Status code of the response. `None` if no response was sent.
+    status: Option<u16>,
+    duration: Duration,
+    bytes_read: u64,
+    bytes_written: u64,
 }
 
 impl Report {
     /// Creates a new report with the given id and key.
-    pub fn new(id: usize, key: Option<String>) -> Self {
-        Report { _id: id, key }
+    pub fn new(id: RequestId, key: Option<String>) -> Self {
+        Report {
+            id,
+            key,
+            route: None,
+            status: None,
+            duration: Duration::ZERO,
+            bytes_read: 0,
+            bytes_written: 0,
+        }
+    }
+
+    /// Sets the route of the request.
+    pub fn with_route(mut self, route: &'static str) -> Self {
+        self.route = Some(route);
+        self
+    }
+
+    /// Returns the route of the request, or `None` if the request is malformed.
+    pub fn route(&self) -> Option<&'static str> {
+        self.route
+    }
+
+    /// Sets the number of bytes read from and written to the client for the operation.
+    pub fn with_bytes(mut self, read: u64, written: u64) -> Self {
+        self.bytes_read = read;
+        self.bytes_written = written;
+        self
+    }
+
+    /// Returns the number of bytes read from the client.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Returns the number of bytes written to the client.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Sets the status code of the response.
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    /// Returns the status code of the response, or `None` if no response was sent.
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    /// Returns the id of the request.
+    pub fn id(&self) -> RequestId {
+        self.id
+    }
+
+    /// Sets the time taken to handle the operation.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+}
+
+/// Histogram of durations with HDR-style log-linear buckets.
+///
+/// Durations are recorded in microseconds. Values below `SUB_BUCKETS` are recorded exactly, and
+/// each range `[2^e, 2^(e+1))` above is split into `SUB_BUCKETS / 2` equal buckets, so a value is
+/// reported with a relative error of at most `2 / SUB_BUCKETS`, no matter its magnitude.
+#[derive(Default, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    min: Option<Duration>,
+    max: Option<Duration>,
+}
+
+impl Histogram {
+    const LOG_SUB_BUCKETS: u32 = 6;
+    const SUB_BUCKETS: u64 = 1 << Self::LOG_SUB_BUCKETS;
+
+    /// Returns the index of the bucket for `micros`.
+    fn index(micros: u64) -> usize {
+        if micros < Self::SUB_BUCKETS {
+            return micros as usize;
+        }
+        let shift = (63 - micros.leading_zeros()) - (Self::LOG_SUB_BUCKETS - 1);
+        let sub = (micros >> shift) - Self::SUB_BUCKETS / 2;
+        (shift as u64 * (Self::SUB_BUCKETS / 2) + Self::SUB_BUCKETS / 2 + sub) as usize
+    }
+
+    /// Returns the smallest value in the bucket at `index`.
+    fn lower_bound(index: usize) -> u64 {
+        let index = index as u64;
+        if index < Self::SUB_BUCKETS {
+            return index;
+        }
+        let shift = index / (Self::SUB_BUCKETS / 2) - 1;
+        let sub = index % (Self::SUB_BUCKETS / 2);
+        (Self::SUB_BUCKETS / 2 + sub) << shift
+    }
+
+    /// Records a duration.
+    pub fn record(&mut self, duration: Duration) {
+        let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+        let index = Self::index(micros);
+        if self.counts.len() <= index {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.total += 1;
+        self.min = Some(self.min.map_or(duration, |min| min.min(duration)));
+        self.max = Some(self.max.map_or(duration, |max| max.max(duration)));
+    }
+
+    /// Records the durations recorded in `other`.
+    pub fn merge(&mut self, other: &Histogram) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.total += other.total;
+        self.min = self.min.into_iter().chain(other.min).min();
+        self.max = self.max.into_iter().chain(other.max).max();
+    }
+
+    /// Returns the number of recorded durations.
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the smallest recorded duration.
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    /// Returns the largest recorded duration.
+    pub fn max(&self) -> Option<Duration> {
+        self.max
+    }
+
+    /// Returns the (approximate) duration below which `quantile` of the recorded durations fall.
+    /// Panics if `quantile` is not in `[0, 1]`.
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        assert!((0.0..=1.0).contains(&quantile));
+        let (min, max) = (self.min?, self.max?);
+
+        let rank = ((quantile * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        let index = self
+            .counts
+            .iter()
+            .position(|count| {
+                seen += count;
+                seen >= rank
+            })
+            .unwrap_or(self.counts.len() - 1);
+        // Report the highest value in the bucket, as the recorded durations may be that large.
+        // For the last bucket, the bound wraps around to 0 and hence the value to `u64::MAX`.
+        let value = Duration::from_micros(Self::lower_bound(index + 1).wrapping_sub(1));
+        Some(value.clamp(min, max))
+    }
+}
+
+impl fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Histogram")
+            .field("count", &self.total)
+            .field("min", &self.min)
+            .field("p50", &self.percentile(0.5))
+            .field("p90", &self.percentile(0.9))
+            .field("p99", &self.percentile(0.99))
+            .field("max", &self.max)
+            .finish()
     }
 }
 
 /// Operation statisics
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Statistics {
     hits: HashMap<Option<String>, usize>,
+    /// Traffic of the responses in each class.
+    classes: BTreeMap<StatusClass, Traffic>,
+    latencies: Histogram,
+    /// Total traffic, including that of malformed requests.
+    traffic: Traffic,
+    /// Traffic of each route.
+    routes: BTreeMap<&'static str, Traffic>,
+    /// Number of reports dropped because the reporter fell behind.
+    dropped: u64,
 }
 
 impl Statistics {
     /// Add a report to the statisics.
     pub fn add_report(&mut self, report: Report) {
+        self.traffic.add(&report);
+        if let Some(route) = report.route {
+            self.routes.entry(route).or_default().add(&report);
+        }
+        self.classes
+            .entry(StatusClass::of(report.status))
+            .or_default()
+            .add(&report);
         let hits = self.hits.entry(report.key).or_default();
         *hits += 1;
+        self.latencies.record(report.duration);
+    }
+
+    /// Adds the statistics in `other`, as if its reports were added to `self`.
+    pub fn merge(&mut self, other: Statistics) {
+        for (key, hits) in other.hits {
+            *self.hits.entry(key).or_default() += hits;
+        }
+        for (class, traffic) in other.classes {
+            self.classes.entry(class).or_default().merge(traffic);
+        }
+        self.latencies.merge(&other.latencies);
+        self.traffic.merge(other.traffic);
+        for (route, traffic) in other.routes {
+            self.routes.entry(route).or_default().merge(traffic);
+        }
+        self.dropped += other.dropped;
+    }
+
+    /// Counts `count` reports that were dropped instead of being added.
+    pub fn add_dropped(&mut self, count: u64) {
+        self.dropped += count;
+    }
+
+    /// Returns the number of dropped reports, which the other statistics do not include.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Returns the number of responses in `class`.
+    pub fn responses(&self, class: StatusClass) -> usize {
+        self.class_traffic(class).requests
+    }
+
+    /// Returns the traffic of the responses in `class`.
+    pub fn class_traffic(&self, class: StatusClass) -> Traffic {
+        self.classes.get(&class).copied().unwrap_or_default()
+    }
+
+    /// Returns the total traffic.
+    pub fn traffic(&self) -> Traffic {
+        self.traffic
+    }
+
+    /// Returns the traffic of `route`.
+    pub fn route_traffic(&self, route: &str) -> Traffic {
+        self.routes.get(route).copied().unwrap_or_default()
+    }
+
+    /// Returns the histogram of the durations of the operations.
+    pub fn latencies(&self) -> &Histogram {
+        &self.latencies
+    }
+
+    /// Returns the number of hits for each valid key, sorted by key, and that of invalid requests.
+    fn sorted_hits(&self) -> (Vec<(&str, usize)>, usize) {
+        let mut hits = self
+            .hits
+            .iter()
+            .filter_map(|(key, hits)| Some((key.as_deref()?, *hits)))
+            .collect::<Vec<_>>();
+        hits.sort_unstable();
+        (hits, self.hits.get(&None).copied().unwrap_or_default())
+    }
+
+    /// Returns the summary of the latencies in microseconds.
+    fn latency_summary(&self) -> [(&'static str, Option<u128>); 5] {
+        let micros = |duration: Option<Duration>| duration.map(|duration| duration.as_micros());
+        [
+            ("min", micros(self.latencies.min())),
+            ("p50", micros(self.latencies.percentile(0.5))),
+            ("p90", micros(self.latencies.percentile(0.9))),
+            ("p99", micros(self.latencies.percentile(0.99))),
+            ("max", micros(self.latencies.max())),
+        ]
+    }
+
+    /// Writes the statistics to `path` in CSV.
+    ///
+    /// Each row is `metric,name,value`, where `metric` is one of `requests`, `hits` (for each key;
+    /// the name is empty for invalid requests), `responses` (for each status class),
+    /// `latency_us` (`count`, `min`, `p50`, `p90`, `p99`, and `max` in microseconds), `bytes`
+    /// (`read` and `written` in total), and `route_requests`, `route_bytes_read`, and
+    /// `route_bytes_written` (for each route), and `reports` (`dropped`). The values are empty for
+    /// latencies if there are no requests.
+    pub fn write_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        // `write!` to a `String` never fails.
+        let mut out = String::from("metric,name,value\n");
+        let _ = writeln!(out, "requests,,{}", self.latencies.count());
+        let (hits, invalid) = self.sorted_hits();
+        for (key, hits) in hits {
+            // Keys are words, which need no quoting.
+            let _ = writeln!(out, "hits,{key},{hits}");
+        }
+        let _ = writeln!(out, "hits,,{invalid}");
+        for (class, traffic) in &self.classes {
+            let _ = writeln!(out, "responses,{},{}", class.name(), traffic.requests);
+        }
+        let _ = writeln!(out, "latency_us,count,{}", self.latencies.count());
+        for (name, value) in self.latency_summary() {
+            let value = value.map_or_else(String::new, |value| value.to_string());
+            let _ = writeln!(out, "latency_us,{name},{value}");
+        }
+        let _ = writeln!(out, "bytes,read,{}", self.traffic.bytes_read);
+        let _ = writeln!(out, "bytes,written,{}", self.traffic.bytes_written);
+        for (route, traffic) in &self.routes {
+            // Routes need no quoting, either.
+            let _ = writeln!(out, "route_requests,{route},{}", traffic.requests);
+            let _ = writeln!(out, "route_bytes_read,{route},{}", traffic.bytes_read);
+            let _ = writeln!(out, "route_bytes_written,{route},{}", traffic.bytes_written);
+        }
+        let _ = writeln!(out, "reports,dropped,{}", self.dropped);
+        fs::write(path, out)
+    }
+
+    /// Writes the statistics to `path` in JSON, as an object with the following fields:
+    ///
+    /// - `requests`: number of requests.
+    /// - `hits`: object mapping each key to the number of hits.
+    /// - `invalid`: number of invalid requests.
+    /// - `responses`: object mapping each status class (e.g., `2xx`) to the number of responses.
+    /// - `latency_us`: object with `count`, `min`, `p50`, `p90`, `p99`, and `max` in microseconds.
+    ///   The values are `null` if there are no requests.
+    /// - `bytes`: object with `read` and `written` in total.
+    /// - `routes`: object mapping each route to an object with `requests`, `bytes_read`, and
+    ///   `bytes_written`.
+    /// - `dropped_reports`: number of reports dropped because the reporter fell behind.
+    pub fn write_json<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let object = |fields: Vec<(&str, String)>| {
+            let fields = fields
+                .into_iter()
+                .map(|(name, value)| format!("{}: {value}", json_string(name)))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", fields.join(", "))
+        };
+
+        let (hits, invalid) = self.sorted_hits();
+        let hits = hits
+            .into_iter()
+            .map(|(key, hits)| (key, hits.to_string()))
+            .collect();
+        let responses = self
+            .classes
+            .iter()
+            .map(|(class, traffic)| (class.name(), traffic.requests.to_string()))
+            .collect();
+        let routes = self
+            .routes
+            .iter()
+            .map(|(route, traffic)| {
+                let traffic = object(vec![
+                    ("requests", traffic.requests.to_string()),
+                    ("bytes_read", traffic.bytes_read.to_string()),
+                    ("bytes_written", traffic.bytes_written.to_string()),
+                ]);
+                (*route, traffic)
+            })
+            .collect();
+        let mut latencies = vec![("count", self.latencies.count().to_string())];
+        latencies.extend(self.latency_summary().map(|(name, value)| {
+            let value = value.map_or_else(|| "null".to_string(), |value| value.to_string());
+            (name, value)
+        }));
+
+        let json = object(vec![
+            ("requests", self.latencies.count().to_string()),
+            ("hits", object(hits)),
+            ("invalid", invalid.to_string()),
+            ("responses", object(responses)),
+            ("latency_us", object(latencies)),
+            (
+                "bytes",
+                object(vec![
+                    ("read", self.traffic.bytes_read.to_string()),
+                    ("written", self.traffic.bytes_written.to_string()),
+                ]),
+            ),
+            ("routes", object(routes)),
+            ("dropped_reports", self.dropped.to_string()),
+        ]);
+        fs::write(path, json + "\n")
+    }
+}
+
+/// Returns `s` as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
     }
+    out.push('"');
+    out
 }
@@ -1,12 +1,130 @@
 //! TcpListener that can be cancelled.
 
-use std::fmt::Error;
+use log::{debug, warn};
+use mio::net::TcpListener as MioTcpListener;
+use mio::{Events, Interest, Poll, Token, Waker};
+use socket2::{Domain, Protocol, Socket, Type};
 use std::io;
-use std::net::ToSocketAddrs;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
 use std::net::{TcpListener, TcpStream};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Token for the readiness of the listener.
+const LISTENER: Token = Token(0);
+
+/// Token for the wakeup by `cancel`.
+const WAKER: Token = Token(1);
+
+/// `CancellableTcpListener::deadline` without a scheduled cancellation.
+const NO_DEADLINE: u64 = u64::MAX;
+
+/// Options for binding a `CancellableTcpListener`.
+#[derive(Debug, Clone)]
+pub struct ListenerOptions {
+    /// Maximum number of connections waiting to be accepted.
+    pub backlog: i32,
+    /// Whether to set `SO_REUSEADDR`, which allows binding an address that still has connections
+    /// in `TIME_WAIT`, e.g., right after the previous server exits.
+    pub reuse_address: bool,
+    /// Whether to set `SO_REUSEPORT`, which allows several listeners to bind the same address.
+    /// Ignored on non-Unix platforms.
+    pub reuse_port: bool,
+    /// Whether to set `IPV6_V6ONLY` for an IPv6 address, which makes the listener accept only IPv6
+    /// connections. If `None`, the system default is used.
+    pub only_v6: Option<bool>,
+    /// Number of times to retry binding if the address is in use.
+    pub bind_retries: u32,
+    /// Time to wait before each retry.
+    pub retry_interval: Duration,
+}
+
+impl Default for ListenerOptions {
+    /// Same as `TcpListener::bind` on Unix.
+    fn default() -> Self {
+        Self {
+            backlog: 128,
+            reuse_address: true,
+            reuse_port: false,
+            only_v6: None,
+            bind_retries: 0,
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl ListenerOptions {
+    /// Creates a listener socket bound to `addr`.
+    fn bind(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_reuse_address(self.reuse_address)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(self.reuse_port)?;
+        if let (Some(only_v6), SocketAddr::V6(_)) = (self.only_v6, addr) {
+            socket.set_only_v6(only_v6)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(self.backlog)?;
+        Ok(socket.into())
+    }
+
+    /// Creates a listener socket bound to the first of `addrs` that can be bound.
+    fn bind_any(&self, addrs: &[SocketAddr]) -> io::Result<TcpListener> {
+        let mut last_err = None;
+        for addr in addrs {
+            match self.bind(*addr) {
+                Ok(listener) => return Ok(listener),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+}
+
+/// Status of a `CancellableTcpListener`, e.g., for a summary on shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenerStatus {
+    /// Whether the listener is cancelled.
+    pub canceled: bool,
+    /// Number of live `Incoming` iterators, i.e., accept loops.
+    pub accept_loops: usize,
+    /// Number of connections accepted so far.
+    pub accepted: usize,
+    /// Number of connections `track`ed and not finished yet.
+    pub pending: usize,
+}
+
+impl ListenerStatus {
+    /// Checks if the listener is cancelled and all its accept loops have exited.
+    pub fn is_stopped(&self) -> bool {
+        self.canceled && self.accept_loops == 0
+    }
+}
+
+/// Marks a connection as pending until dropped. See `CancellableTcpListener::track`.
+#[derive(Debug)]
+pub struct PendingConnection {
+    pending: Arc<AtomicUsize>,
+}
+
+impl Drop for PendingConnection {
+    fn drop(&mut self) {
+        let _ = self.pending.fetch_sub(1, Ordering::Release);
+    }
+}
 
 /// Like `std::net::tcp::TcpListener`, but `cancel`lable.
+///
+/// The listener is in non-blocking mode, and `Incoming` waits for new connections by polling it
+/// together with a waker. `cancel` sets the flag and triggers the waker, so that the blocked
+/// `Incoming` wakes up and observes the flag without relying on a bogus connection to itself.
 #[derive(Debug)]
 pub struct CancellableTcpListener {
     inner: TcpListener,
@@ -16,6 +134,27 @@ pub struct CancellableTcpListener {
     /// read the flag, use `load` method with `Ordering::Acquire`. We will discuss their precise
     /// semantics later.
     is_canceled: AtomicBool,
+    /// Time at which `deadline` is counted from.
+    epoch: Instant,
+    /// Time at which the listener is cancelled, set by `cancel_at`, in nanoseconds since `epoch`.
+    /// `NO_DEADLINE` if there is no such time.
+    deadline: AtomicU64,
+    /// Poller for the listener and the waker. Since polling requires `&mut`, it is protected by a
+    /// mutex, which also serializes `accept` so that no readiness event is lost between `accept`
+    /// and `poll` of different threads.
+    poll: Mutex<(Poll, Events)>,
+    /// Wakes up the poller.
+    waker: Waker,
+    /// Registered duplicate of `inner`. Must be kept alive while polling.
+    _source: MioTcpListener,
+    /// Number of live `Incoming` iterators.
+    active: Mutex<usize>,
+    /// Notified when `active` becomes 0.
+    inactive_condvar: Condvar,
+    /// Number of accepted connections.
+    accepted: AtomicUsize,
+    /// Number of live `PendingConnection`s. Shared with them, so that they can outlive `self`.
+    pending: Arc<AtomicUsize>,
 }
 
 /// Like `std::net::tcp::Incoming`, but stops `accept`ing connections if the listener is
@@ -28,30 +167,172 @@ pub struct Incoming<'a> {
 impl CancellableTcpListener {
     /// Wraps `TcpListener::bind`.
     pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<CancellableTcpListener> {
-        let listener = TcpListener::bind(addr)?;
+        Self::bind_with(addr, &ListenerOptions::default())
+    }
+
+    /// Like `bind`, but with the given socket options.
+    pub fn bind_with<A: ToSocketAddrs>(
+        addr: A,
+        options: &ListenerOptions,
+    ) -> io::Result<CancellableTcpListener> {
+        let addrs = addr.to_socket_addrs()?.collect::<Vec<_>>();
+        let mut retries = 0;
+        let listener = loop {
+            match options.bind_any(&addrs) {
+                Err(err)
+                    if err.kind() == io::ErrorKind::AddrInUse && retries < options.bind_retries =>
+                {
+                    retries += 1;
+                    warn!("{err}; retrying in {:?}", options.retry_interval);
+                    thread::sleep(options.retry_interval);
+                }
+                result => break result?,
+            }
+        };
+        listener.set_nonblocking(true)?;
+
+        // The duplicate refers to the same socket, so its readiness is that of `listener`.
+        let mut source = MioTcpListener::from_std(listener.try_clone()?);
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&mut source, LISTENER, Interest::READABLE)?;
+        let waker = Waker::new(poll.registry(), WAKER)?;
+
         Ok(CancellableTcpListener {
             inner: listener,
             is_canceled: AtomicBool::new(false),
+            epoch: Instant::now(),
+            deadline: AtomicU64::new(NO_DEADLINE),
+            poll: Mutex::new((poll, Events::with_capacity(8))),
+            waker,
+            _source: source,
+            active: Mutex::new(0),
+            inactive_condvar: Condvar::new(),
+            accepted: AtomicUsize::new(0),
+            pending: Arc::new(AtomicUsize::new(0)),
         })
     }
 
+    /// Returns the local address that this listener is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
     /// Signals the listener to stop accepting new connections.
+    ///
+    /// This returns without waiting for the `Incoming` iterators to observe the cancellation. Use
+    /// `cancel_and_wait` for that.
     pub fn cancel(&self) -> io::Result<()> {
-        // Set the flag first and make a bogus connection to itself to wake up the listener blocked
-        // in `accept`. Use `TcpListener::local_addr` and `TcpStream::connect`.
+        self.is_canceled.store(true, Ordering::Release);
+        self.waker.wake()
+    }
+
+    /// Binds `port` of all interfaces for both IPv4 and IPv6, whichever are available.
+    ///
+    /// If possible, a single dual-stack listener on `[::]` accepts both. Otherwise, separate
+    /// listeners are bound to `[::]` and `0.0.0.0`, and if only one of them can be bound (e.g., on
+    /// an IPv6-only network), only that one is returned. Use `local_addr` to see the actual
+    /// addresses. Note that separate listeners may get different ports if `port` is 0.
+    pub fn bind_dual_stack(port: u16, options: &ListenerOptions) -> io::Result<Vec<Self>> {
+        let v6 = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+        let v4 = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+
+        let dual_stack = ListenerOptions {
+            only_v6: Some(false),
+            ..options.clone()
+        };
+        match Self::bind_with(v6, &dual_stack) {
+            Ok(listener) => return Ok(vec![listener]),
+            Err(err) => debug!("cannot bind dual-stack {v6}: {err}"),
+        }
+
+        let v6_only = ListenerOptions {
+            only_v6: Some(true),
+            ..options.clone()
+        };
+        let mut listeners = Vec::new();
+        let mut last_err = None;
+        for result in [Self::bind_with(v6, &v6_only), Self::bind_with(v4, options)] {
+            match result {
+                Ok(listener) => listeners.push(listener),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        match last_err {
+            Some(err) if listeners.is_empty() => Err(err),
+            _ => Ok(listeners),
+        }
+    }
+
+    /// Schedules the listener to be cancelled at `deadline`. If it is already scheduled, the
+    /// earlier deadline takes effect.
+    pub fn cancel_at(&self, deadline: Instant) -> io::Result<()> {
+        // A deadline too far to count in nanoseconds is never reached.
+        let nanos = u64::try_from(deadline.saturating_duration_since(self.epoch).as_nanos())
+            .unwrap_or(NO_DEADLINE);
+        let _ = self.deadline.fetch_min(nanos, Ordering::Release);
+        // Let the `Incoming` iterators recompute how long they may wait.
+        self.waker.wake()
+    }
+
+    /// Schedules the listener to be cancelled after `timeout`. See `cancel_at`.
+    pub fn cancel_after(&self, timeout: Duration) -> io::Result<()> {
+        self.cancel_at(Instant::now() + timeout)
+    }
 
-        self.is_canceled.store(true, Ordering::Relaxed);
+    /// Returns the time remaining until the scheduled cancellation, or `None` if there is no such
+    /// schedule. If the deadline has passed, the listener is cancelled and zero is returned.
+    fn until_deadline(&self) -> Option<Duration> {
+        let nanos = self.deadline.load(Ordering::Acquire);
+        if nanos == NO_DEADLINE {
+            return None;
+        }
+        let deadline = self.epoch + Duration::from_nanos(nanos);
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            self.is_canceled.store(true, Ordering::Release);
+        }
+        Some(remaining)
+    }
+
+    /// Signals the listener to stop accepting new connections, and blocks until all `Incoming`
+    /// iterators of this listener are dropped.
+    ///
+    /// This must not be called by a thread that holds an `Incoming` of this listener.
+    pub fn cancel_and_wait(&self) -> io::Result<()> {
+        self.cancel()?;
+        let mut active = self.active.lock().unwrap();
+        while *active != 0 {
+            active = self.inactive_condvar.wait(active).unwrap();
+        }
+        Ok(())
+    }
 
-        let local_addr = self.inner.local_addr().unwrap();
-        match TcpStream::connect(local_addr) {
-            Ok(_) => Ok(()),
-            Err(error) => Err(error),
+    /// Marks a connection accepted from this listener as pending until the returned guard is
+    /// dropped, e.g., when the connection is handled, so that `status` can tell how many are left.
+    pub fn track(&self) -> PendingConnection {
+        let _ = self.pending.fetch_add(1, Ordering::Relaxed);
+        PendingConnection {
+            pending: self.pending.clone(),
+        }
+    }
+
+    /// Returns the current status of this listener.
+    pub fn status(&self) -> ListenerStatus {
+        // Applies the scheduled cancellation, if it is due.
+        let _ = self.until_deadline();
+        ListenerStatus {
+            canceled: self.is_canceled.load(Ordering::Acquire),
+            accept_loops: *self.active.lock().unwrap(),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            pending: self.pending.load(Ordering::Acquire),
         }
     }
 
     /// Returns an iterator over the connections being received on this listener.  The returned
     /// iterator will return `None` if the listener is `cancel`led.
-    pub fn incoming(&self) -> Incoming {
+    pub fn incoming(&self) -> Incoming<'_> {
+        *self.active.lock().unwrap() += 1;
         Incoming { listener: self }
     }
 }
@@ -60,12 +341,40 @@ impl<'a> Iterator for Incoming<'a> {
     type Item = io::Result<TcpStream>;
     /// Returns None if the listener is `cancel()`led.
     fn next(&mut self) -> Option<io::Result<TcpStream>> {
-        let stream: io::Result<TcpStream> = self.listener.inner.accept().map(|p| p.0);
+        let mut poll = self.listener.poll.lock().unwrap();
+        let (poll, events) = &mut *poll;
+
+        loop {
+            let timeout = self.listener.until_deadline();
+            if self.listener.is_canceled.load(Ordering::Acquire) {
+                return None;
+            }
+
+            match self.listener.inner.accept() {
+                // The accepted stream may inherit the non-blocking mode on some platforms.
+                Ok((stream, _)) => {
+                    let _ = self.listener.accepted.fetch_add(1, Ordering::Relaxed);
+                    return Some(stream.set_nonblocking(false).map(|_| stream));
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => return Some(Err(err)),
+            }
+
+            if let Err(err) = poll.poll(events, timeout) {
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
 
-        if self.listener.is_canceled.load(Ordering::Relaxed) {
-            None
-        } else {
-            Some(stream)
+impl Drop for Incoming<'_> {
+    fn drop(&mut self) {
+        let mut active = self.listener.active.lock().unwrap();
+        *active -= 1;
+        if *active == 0 {
+            self.listener.inactive_condvar.notify_all();
         }
     }
 }
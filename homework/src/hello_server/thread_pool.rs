@@ -1,10 +1,16 @@
 //! Thread pool that joins all thread when dropped.
 
-// NOTE: Crossbeam channels are MPMC, which means that you don't need to wrap the receiver in
-// Arc<Mutex<..>>. Just clone the receiver and give it to each worker thread.
-use crossbeam_channel::{unbounded, Receiver, Sender};
-use std::sync::{Arc, Condvar, Mutex};
+use log::{debug, trace};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
+
+use super::timer::{TimerHandle, TimerWheel};
+use crate::metrics::Registry;
+use crate::{qsbr, BlockingQueue, CachePadded, ConcurrentCounter, WaitGroup};
+
+/// Resolution of the delayed jobs.
+const TIMER_TICK: Duration = Duration::from_millis(1);
 
 struct Job(Box<dyn FnOnce() + Send + 'static>);
 
@@ -15,18 +21,24 @@ struct Worker {
 }
 
 impl Worker {
-    pub fn new(id: usize, receiver: Arc<Receiver<Job>>) -> Self {
+    pub fn new(id: usize, jobs: Arc<BlockingQueue<Job>>) -> Self {
         let thread = thread::spawn(move || loop {
-            let message = receiver.recv();
+            // Idle workers do not hold back the reclamation of the jobs that use QSBR.
+            let message = {
+                let _offline = qsbr::offline_scope();
+                jobs.take()
+            };
 
             match message {
-                Ok(Job(job)) => {
-                    println!("Worker {id} got a job; executing.");
+                Some(Job(job)) => {
+                    trace!("worker {id} got a job; executing");
 
                     job();
+                    // A finished job holds no pointer.
+                    qsbr::quiescent_state();
                 }
-                Err(_) => {
-                    println!("Worker {id} disconnected; shutting down.");
+                None => {
+                    debug!("worker {id} disconnected; shutting down");
                     break;
                 }
             }
@@ -53,40 +65,101 @@ impl Drop for Worker {
 /// closures via `Arc` so that the workers can report to the pool that it started/finished a job.
 #[derive(Debug, Default)]
 struct ThreadPoolInner {
-    job_count: Mutex<usize>,
-    empty_condvar: Condvar,
+    /// The jobs that are submitted but not yet finished. Both counters are updated by every job,
+    /// so they are padded apart.
+    pending: CachePadded<WaitGroup>,
+    /// Number of jobs that are submitted but not yet picked up by a worker.
+    queued: CachePadded<ConcurrentCounter>,
 }
 
 impl ThreadPoolInner {
     fn new() -> Self {
         ThreadPoolInner {
-            job_count: Mutex::new(0),
-            empty_condvar: Condvar::new(),
+            pending: CachePadded::new(WaitGroup::new()),
+            queued: CachePadded::new(ConcurrentCounter::new()),
         }
     }
 
     /// Increment the job count.
     fn start_job(&self) {
-        *self.job_count.lock().unwrap() += 1;
+        self.pending.add(1);
+        self.queued.increment();
+    }
+
+    /// Mark that a worker picked up a job.
+    fn run_job(&self) {
+        self.queued.decrement();
     }
 
     /// Decrement the job count.
     fn finish_job(&self) {
-        *self.job_count.lock().unwrap() -= 1;
+        self.pending.done();
+    }
+
+    /// Counts `f` as a new job, and returns the job that runs it.
+    fn job<F>(self: &Arc<Self>, f: F) -> Job
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let inner = self.clone();
+        self.start_job();
+        Job(Box::new(move || {
+            inner.run_job();
+            f();
+            inner.finish_job();
+        }))
     }
 
     /// Wait until the job count becomes 0.
-    ///
-    /// NOTE: We can optimize this function by adding another field to `ThreadPoolInner`, but let's
-    /// not care about that in this homework.
     fn wait_empty(&self) {
-        loop {
-            let curr_count = self.job_count.lock().unwrap();
-            if curr_count.eq(&0) {
-                break;
-            }
-            println!("Current Job Count : {}", curr_count);
+        trace!("current job count: {}", self.pending.count());
+        self.pending.wait();
+    }
+}
+
+/// Read-only view of the load of a [`ThreadPool`].
+///
+/// Unlike `Arc<ThreadPool>`, this does not keep the pool alive, so it can be freely handed to the
+/// jobs running in the pool.
+#[derive(Debug, Clone)]
+pub struct PoolLoad {
+    inner: Arc<ThreadPoolInner>,
+}
+
+impl PoolLoad {
+    /// Returns the number of jobs waiting for a worker.
+    pub fn queued(&self) -> usize {
+        // The sum may be transiently negative, see `ConcurrentCounter::sum`.
+        self.inner.queued.sum().max(0) as usize
+    }
+
+    /// Returns the number of jobs that are submitted but not finished, including the queued ones.
+    pub fn pending(&self) -> usize {
+        self.inner.pending.count()
+    }
+}
+
+/// Handle that submits jobs to a [`ThreadPool`] without keeping it alive, e.g., for the wakers of
+/// the tasks of an executor.
+#[derive(Debug, Clone)]
+pub(crate) struct Submitter {
+    jobs: Arc<BlockingQueue<Job>>,
+    inner: Arc<ThreadPoolInner>,
+}
+
+impl Submitter {
+    /// Submits `f` as a new job. Returns `false` and drops the job if the pool is dropped.
+    pub(crate) fn execute<F>(&self, f: F) -> bool
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self.jobs.put(self.inner.job(f)).is_err() {
+            // The job is never run, so it is not counted.
+            self.inner.run_job();
+            self.inner.finish_job();
+            return false;
         }
+        true
     }
 }
 
@@ -94,8 +167,11 @@ impl ThreadPoolInner {
 #[derive(Debug)]
 pub struct ThreadPool {
     _workers: Vec<Worker>,
-    job_sender: Option<Sender<Job>>,
+    /// Shared by the workers, and shut down when the pool is dropped.
+    jobs: Arc<BlockingQueue<Job>>,
     pool_inner: Arc<ThreadPoolInner>,
+    /// Submits the delayed jobs when they are due.
+    timers: TimerWheel,
 }
 
 impl ThreadPool {
@@ -103,47 +179,102 @@ impl ThreadPool {
     pub fn new(size: usize) -> Self {
         assert!(size > 0);
 
-        let (job_sender, receiver) = unbounded();
-
-        let receiver = Arc::new(receiver);
+        let jobs = Arc::new(BlockingQueue::unbounded());
 
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
+            workers.push(Worker::new(id, Arc::clone(&jobs)));
         }
 
         let pool_inner = Arc::new(ThreadPoolInner::new());
 
         ThreadPool {
             _workers: workers,
-            job_sender: Some(job_sender),
+            jobs,
             pool_inner,
+            timers: TimerWheel::new(TIMER_TICK),
         }
     }
 
     /// Execute a new job in the thread pool.
     pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = self.pool_inner.job(f);
+
+        if self.jobs.put(job).is_err() {
+            unreachable!("the jobs are shut down only when the pool is dropped");
+        }
+    }
+
+    /// Execute a new job in the thread pool after `delay`. The job can be cancelled with the
+    /// returned handle until it is due.
+    ///
+    /// The job is not counted by `join` or `load` until it is due, and is dropped without running
+    /// if the pool is dropped before then.
+    pub fn execute_after<F>(&self, delay: Duration, f: F) -> TimerHandle
     where
         F: FnOnce() + Send + 'static,
     {
         let inner_pool = self.pool_inner.clone();
-        self.pool_inner.start_job();
-        let job = Job(Box::new(move || {
-            f();
-            inner_pool.finish_job();
-        }));
+        let jobs = self.jobs.clone();
+        self.timers.schedule(delay, move || {
+            if jobs.put(inner_pool.job(f)).is_err() {
+                unreachable!("the timers are shut down before the jobs");
+            }
+        })
+    }
+
+    /// Returns the timer wheel that runs the delayed jobs, for periodic chores of the jobs.
+    pub fn timers(&self) -> &TimerWheel {
+        &self.timers
+    }
+
+    /// Returns a handle that submits jobs to this pool.
+    pub(crate) fn submitter(&self) -> Submitter {
+        Submitter {
+            jobs: self.jobs.clone(),
+            inner: self.pool_inner.clone(),
+        }
+    }
 
-        if let Some(sender) = &self.job_sender {
-            sender.send(job).unwrap();
+    /// Returns a view of the load of this pool.
+    pub fn load(&self) -> PoolLoad {
+        PoolLoad {
+            inner: self.pool_inner.clone(),
         }
     }
 
+    /// Registers the load of this pool into `registry`, labeled with `name`: the gauges of
+    /// [`PoolLoad`]. The series are removed once the pool and the views of its load are dropped.
+    pub fn register_metrics(&self, registry: &Registry, name: &str) {
+        let labels = [("pool", name)];
+        let load = {
+            let inner = Arc::downgrade(&self.pool_inner);
+            move || inner.upgrade().map(|inner| PoolLoad { inner })
+        };
+        let queued = load.clone();
+        registry.gauge_fn(
+            "hello_pool_queued_jobs",
+            "Jobs waiting for a worker.",
+            &labels,
+            move || queued().map(|load| load.queued() as i64),
+        );
+        registry.gauge_fn(
+            "hello_pool_pending_jobs",
+            "Jobs submitted but not finished.",
+            &labels,
+            move || load().map(|load| load.pending() as i64),
+        );
+    }
+
     /// Block the current thread until all jobs in the pool have been executed.
     ///
     /// NOTE: This method has nothing to do with `JoinHandle::join`.
     pub fn join(&self) {
-        println!("Start Join");
+        debug!("waiting for all jobs to finish");
         self.pool_inner.wait_empty()
     }
 }
@@ -152,10 +283,15 @@ impl Drop for ThreadPool {
     /// When dropped, all worker threads' `JoinHandle` must be `join`ed. If the thread panicked,
     /// then this function should panic too.
     fn drop(&mut self) {
-        drop(self.job_sender.take());
+        // The pending timers would put jobs after the workers are gone.
+        self.timers.shutdown();
+        // The submitters that outlive the pool fail from here on. The workers run the remaining
+        // jobs, including those submitted until here, e.g., by the wakers of an executor, and
+        // exit once they find no job left.
+        let _ = self.jobs.shutdown();
 
         for worker in &mut self._workers {
-            println!("Shutting down worker {}", worker._id);
+            debug!("shutting down worker {}", worker._id);
 
             if let Some(thread) = worker.thread.take() {
                 thread.join().unwrap();
@@ -163,3 +299,33 @@ impl Drop for ThreadPool {
         }
     }
 }
+
+#[cfg(all(test, feature = "check-loom"))]
+mod tests {
+    use super::ThreadPoolInner;
+    use loom::sync::atomic::{AtomicBool, Ordering};
+    use loom::thread;
+    use std::sync::Arc;
+
+    /// `wait_empty` returns only after the jobs counted before it are finished, and sees what
+    /// they did.
+    #[test]
+    fn wait_empty_sync() {
+        loom::model(|| {
+            let inner = Arc::new(ThreadPoolInner::new());
+            let done = Arc::new([AtomicBool::new(false), AtomicBool::new(false)]);
+            let mut jobs = (0..2).map(|i| {
+                let done = done.clone();
+                inner.job(move || done[i].store(true, Ordering::Relaxed))
+            });
+            let remote = jobs.next().unwrap();
+            let local = jobs.next().unwrap();
+            let th = thread::spawn(move || (remote.0)());
+            (local.0)();
+            inner.wait_empty();
+            assert!(done.iter().all(|done| done.load(Ordering::Relaxed)));
+            assert_eq!(inner.pending.count(), 0);
+            th.join().unwrap();
+        })
+    }
+}
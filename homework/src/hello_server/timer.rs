@@ -0,0 +1,366 @@
+//! Hierarchical timer wheel.
+
+use log::warn;
+use std::fmt;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Each level has 64 slots, indexed by a 6-bit digit of the tick.
+const SLOT_BITS: usize = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+/// Enough levels for the digits of all 64 bits of a tick, so that no deadline is out of range.
+const LEVELS: usize = 11;
+
+const PENDING: u8 = 0;
+const FIRED: u8 = 1;
+const CANCELLED: u8 = 2;
+
+enum Callback {
+    Once(Box<dyn FnOnce() + Send>),
+    /// Rescheduled `period` ticks after each run while it returns `true`.
+    Repeating {
+        period: u64,
+        f: Box<dyn FnMut() -> bool + Send>,
+    },
+}
+
+struct Timer {
+    /// Tick at which the timer is due.
+    deadline: u64,
+    state: Arc<AtomicU8>,
+    callback: Callback,
+}
+
+/// Timers by their deadlines.
+///
+/// A timer is in the level of the highest digit in which its deadline differs from `elapsed`, in
+/// the slot of that digit of the deadline. So the slots of a level that are not empty are all
+/// after the digit of `elapsed`, and when `elapsed` reaches the first tick of such a slot, its
+/// timers are due (level 0) or are moved down to lower levels (cascaded).
+struct Wheel {
+    /// Ticks processed so far.
+    elapsed: u64,
+    levels: Vec<Vec<Vec<Timer>>>,
+    /// Bitmap of the slots that are not empty, for each level.
+    occupied: [u64; LEVELS],
+    shutdown: bool,
+}
+
+impl Wheel {
+    fn new() -> Self {
+        Self {
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+                .collect(),
+            occupied: [0; LEVELS],
+            shutdown: false,
+        }
+    }
+
+    /// Inserts `timer`, whose deadline is after `elapsed`.
+    fn insert(&mut self, timer: Timer) {
+        debug_assert!(timer.deadline > self.elapsed);
+        let significant = (timer.deadline ^ self.elapsed) | (SLOTS as u64 - 1);
+        let level = (63 - significant.leading_zeros() as usize) / SLOT_BITS;
+        let slot = (timer.deadline >> (level * SLOT_BITS)) as usize % SLOTS;
+        self.levels[level][slot].push(timer);
+        self.occupied[level] |= 1 << slot;
+    }
+
+    /// Returns the level and the slot that expire next, and the tick at which they do.
+    fn next_expiration(&self) -> Option<(usize, usize, u64)> {
+        // The slots of lower levels expire before those of upper levels.
+        (0..LEVELS).find_map(|level| {
+            let shift = level * SLOT_BITS;
+            let digit = (self.elapsed >> shift) % SLOTS as u64;
+            let ahead = self.occupied[level] & (u64::MAX << digit << 1);
+            if ahead == 0 {
+                return None;
+            }
+            let slot = ahead.trailing_zeros() as usize;
+            let width = shift + SLOT_BITS;
+            let prefix = if width < 64 {
+                self.elapsed >> width << width
+            } else {
+                0
+            };
+            Some((level, slot, prefix | ((slot as u64) << shift)))
+        })
+    }
+
+    /// Advances to `now`, cascading the timers on the way. Returns the timers that are due.
+    fn advance(&mut self, now: u64) -> Vec<Timer> {
+        let mut due = Vec::new();
+        while let Some((level, slot, tick)) = self.next_expiration() {
+            if tick > now {
+                break;
+            }
+            self.elapsed = tick;
+            self.occupied[level] &= !(1 << slot);
+            for timer in mem::take(&mut self.levels[level][slot]) {
+                if timer.deadline <= tick {
+                    due.push(timer);
+                } else {
+                    self.insert(timer);
+                }
+            }
+        }
+        // The slots that are not empty are still after the digits of `now`.
+        self.elapsed = self.elapsed.max(now);
+        due
+    }
+}
+
+/// State shared with the tick thread.
+struct Shared {
+    tick: Duration,
+    start: Instant,
+    wheel: Mutex<Wheel>,
+    /// Wakes up the tick thread when a timer is scheduled or the wheel is shut down.
+    condvar: Condvar,
+}
+
+impl Shared {
+    /// Returns the number of ticks completed at `instant`.
+    fn ticks_at(&self, instant: Instant) -> u64 {
+        let ticks = instant.saturating_duration_since(self.start).as_nanos() / self.tick.as_nanos();
+        ticks.try_into().unwrap_or(u64::MAX)
+    }
+
+    /// Returns the time at which `ticks` ticks are completed, or `None` if it is out of range.
+    fn instant_of(&self, ticks: u64) -> Option<Instant> {
+        let nanos = self.tick.as_nanos().checked_mul(ticks.into())?;
+        self.start
+            .checked_add(Duration::from_nanos(nanos.try_into().ok()?))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Wheel> {
+        self.wheel.lock().unwrap()
+    }
+
+    /// Schedules `callback` at `deadline`, or at the next tick if it has passed.
+    fn schedule(&self, deadline: u64, state: Arc<AtomicU8>, callback: Callback) {
+        let mut wheel = self.lock();
+        if wheel.shutdown {
+            return;
+        }
+        let deadline = deadline.max(wheel.elapsed + 1);
+        wheel.insert(Timer {
+            deadline,
+            state,
+            callback,
+        });
+        // The tick thread may be waiting for a later deadline.
+        self.condvar.notify_one();
+    }
+
+    /// Runs the timers until the wheel is shut down.
+    fn run(&self) {
+        loop {
+            let mut wheel = self.lock();
+            let due = loop {
+                if wheel.shutdown {
+                    return;
+                }
+                let due = wheel.advance(self.ticks_at(Instant::now()));
+                if !due.is_empty() {
+                    break due;
+                }
+                let until = wheel
+                    .next_expiration()
+                    .and_then(|(_, _, tick)| self.instant_of(tick));
+                wheel = match until {
+                    Some(until) => {
+                        let timeout = until.saturating_duration_since(Instant::now());
+                        self.condvar.wait_timeout(wheel, timeout).unwrap().0
+                    }
+                    None => self.condvar.wait(wheel).unwrap(),
+                };
+            };
+            drop(wheel);
+
+            for timer in due {
+                self.fire(timer);
+            }
+        }
+    }
+
+    /// Runs the callback of `timer` unless it is cancelled, and reschedules it if it repeats.
+    fn fire(&self, timer: Timer) {
+        let Timer {
+            deadline,
+            state,
+            callback,
+        } = timer;
+        match callback {
+            Callback::Once(f) => {
+                if state
+                    .compare_exchange(PENDING, FIRED, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+                    && panic::catch_unwind(AssertUnwindSafe(f)).is_err()
+                {
+                    warn!("timer callback panicked");
+                }
+            }
+            Callback::Repeating { period, mut f } => {
+                if state.load(Ordering::Acquire) != PENDING {
+                    return;
+                }
+                let again = panic::catch_unwind(AssertUnwindSafe(&mut f)).unwrap_or_else(|_| {
+                    warn!("timer callback panicked");
+                    false
+                });
+                if !again {
+                    let _ =
+                        state.compare_exchange(PENDING, FIRED, Ordering::AcqRel, Ordering::Acquire);
+                    return;
+                }
+                let callback = Callback::Repeating { period, f };
+                self.schedule(deadline.saturating_add(period), state, callback);
+            }
+        }
+    }
+}
+
+/// Handle to a scheduled timer.
+#[derive(Debug, Clone)]
+pub struct TimerHandle {
+    state: Arc<AtomicU8>,
+}
+
+impl TimerHandle {
+    /// Cancels the timer, so that its callback does not run again. Returns `false` if it has
+    /// already fired or been cancelled.
+    ///
+    /// The callback of a repeating timer may be running meanwhile.
+    pub fn cancel(&self) -> bool {
+        self.state
+            .compare_exchange(PENDING, CANCELLED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Returns `true` if the timer has neither fired for the last time nor been cancelled.
+    pub fn is_pending(&self) -> bool {
+        self.state.load(Ordering::Acquire) == PENDING
+    }
+}
+
+/// Timer wheel that runs callbacks after delays on its own tick thread.
+///
+/// The timers are kept in a hierarchy of wheels, so that scheduling a timer, and running or
+/// cascading it to a finer wheel, take constant time however far its deadline is. The tick thread
+/// sleeps until the next slot that has timers, rather than waking up on every tick.
+///
+/// A timer fires on the first tick after its delay, so never early but up to a tick late. The
+/// callbacks run one by one on the tick thread, so they should be short, e.g., submit a job to a
+/// pool. A cancelled timer is dropped when its time comes. The timers pending when the wheel is
+/// dropped never fire.
+pub struct TimerWheel {
+    shared: Arc<Shared>,
+    ticker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl fmt::Debug for TimerWheel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimerWheel")
+            .field("tick", &self.shared.tick)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TimerWheel {
+    /// Creates a timer wheel with the resolution of `tick`, and spawns its tick thread. Panics if
+    /// `tick` is zero.
+    pub fn new(tick: Duration) -> Self {
+        assert!(!tick.is_zero());
+        let shared = Arc::new(Shared {
+            tick,
+            start: Instant::now(),
+            wheel: Mutex::new(Wheel::new()),
+            condvar: Condvar::new(),
+        });
+        let ticker = thread::spawn({
+            let shared = shared.clone();
+            move || shared.run()
+        });
+        Self {
+            shared,
+            ticker: Mutex::new(Some(ticker)),
+        }
+    }
+
+    /// Returns the resolution of the timers.
+    pub fn tick(&self) -> Duration {
+        self.shared.tick
+    }
+
+    /// Returns the tick at which a timer scheduled now after `delay` is due.
+    fn deadline(&self, delay: Duration) -> u64 {
+        let now = Instant::now();
+        let at = now
+            .checked_add(delay)
+            .map_or(u64::MAX, |at| self.shared.ticks_at(at).saturating_add(1));
+        at.max(self.shared.ticks_at(now) + 1)
+    }
+
+    /// Runs `f` on the tick thread after `delay`.
+    pub fn schedule<F>(&self, delay: Duration, f: F) -> TimerHandle
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let state = Arc::new(AtomicU8::new(PENDING));
+        let deadline = self.deadline(delay);
+        self.shared
+            .schedule(deadline, state.clone(), Callback::Once(Box::new(f)));
+        TimerHandle { state }
+    }
+
+    /// Runs `f` on the tick thread every `period`, until it returns `false` or the timer is
+    /// cancelled. Panics if `period` is zero.
+    ///
+    /// The period is counted from the tick at which `f` was due, so a slow `f` does not make the
+    /// timer drift. A late run does not make up for the runs it missed.
+    pub fn schedule_repeating<F>(&self, period: Duration, f: F) -> TimerHandle
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        assert!(!period.is_zero());
+        let state = Arc::new(AtomicU8::new(PENDING));
+        let ticks = (period.as_nanos() / self.shared.tick.as_nanos()).max(1);
+        let callback = Callback::Repeating {
+            period: ticks.try_into().unwrap_or(u64::MAX),
+            f: Box::new(f),
+        };
+        let deadline = self.deadline(period);
+        self.shared.schedule(deadline, state.clone(), callback);
+        TimerHandle { state }
+    }
+
+    /// Drops the pending timers and joins the tick thread, unless this is called from a callback.
+    pub(crate) fn shutdown(&self) {
+        let timers = {
+            let mut wheel = self.shared.lock();
+            wheel.shutdown = true;
+            mem::take(&mut wheel.levels)
+        };
+        self.shared.condvar.notify_one();
+        // The callbacks may own things that drop this wheel, so they are dropped without the lock.
+        drop(timers);
+        if let Some(ticker) = self.ticker.lock().unwrap().take() {
+            if ticker.thread().id() != thread::current().id() {
+                ticker.join().unwrap();
+            }
+        }
+    }
+}
+
+impl Drop for TimerWheel {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
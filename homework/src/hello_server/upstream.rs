@@ -0,0 +1,66 @@
+//! Minimal HTTP client for fetching from the upstream server.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+/// Maximum size of an upstream response.
+const MAX_RESPONSE_LEN: u64 = 16 * 1024 * 1024;
+
+/// Fetches `path` from the HTTP server at `addr`, and returns the body of the response.
+///
+/// `timeout` bounds connecting and each `read` and `write`. The request is sent in HTTP/1.0, so
+/// that the server closes the connection after the response, which is not chunked. Fails with
+/// `InvalidData` if the response is malformed, too large, or not 200.
+pub(crate) fn fetch(addr: SocketAddr, path: &str, timeout: Duration) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    // Sent at once, since `write!` would send each piece separately.
+    let request = format!("GET {path} HTTP/1.0\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    let mut resp = Vec::new();
+    let _ = stream.take(MAX_RESPONSE_LEN + 1).read_to_end(&mut resp)?;
+    if resp.len() as u64 > MAX_RESPONSE_LEN {
+        return Err(invalid_data("upstream response is too large"));
+    }
+
+    let end = resp
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid_data("upstream response has no head"))?;
+    let head = String::from_utf8_lossy(&resp[..end]);
+    let mut body = resp[end + 4..].to_vec();
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|status_line| {
+            let (version, rest) = status_line.split_once(' ')?;
+            version.starts_with("HTTP/1.").then_some(())?;
+            rest.get(..3)?.parse::<u16>().ok()
+        })
+        .ok_or_else(|| invalid_data("upstream response has a malformed status line"))?;
+    if status != 200 {
+        return Err(invalid_data(&format!("upstream responded with {status}")));
+    }
+
+    // Without `Content-Length`, the body ends when the connection is closed.
+    let len = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("Content-Length")
+            .then(|| value.trim().parse::<usize>())
+    });
+    match len {
+        Some(Ok(len)) if len <= body.len() => body.truncate(len),
+        Some(Ok(_)) => return Err(io::ErrorKind::UnexpectedEof.into()),
+        Some(Err(_)) => return Err(invalid_data("upstream response has a malformed length")),
+        None => {}
+    }
+    Ok(body)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,204 @@
+//! WebSocket handshake and frame codec (RFC 6455).
+
+use std::io::{self, Read, Write};
+
+/// GUID appended to the client's key to compute the accept key.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Status code of a closure due to a protocol error.
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+
+/// Returns the `Sec-WebSocket-Accept` value for the client's `Sec-WebSocket-Key`.
+pub(crate) fn accept_key(key: &str) -> String {
+    let digest = sha1_smol::Sha1::from(format!("{}{GUID}", key.trim())).digest();
+    base64::encode(digest.bytes())
+}
+
+/// Type of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    /// Continuation of a fragmented message.
+    Continuation,
+    /// (First fragment of) a text message.
+    Text,
+    /// (First fragment of) a binary message.
+    Binary,
+    /// Closes the connection.
+    Close,
+    /// Ping, to be answered with a pong with the same payload.
+    Ping,
+    /// Pong.
+    Pong,
+}
+
+impl Opcode {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0x0 => Some(Self::Continuation),
+            0x1 => Some(Self::Text),
+            0x2 => Some(Self::Binary),
+            0x8 => Some(Self::Close),
+            0x9 => Some(Self::Ping),
+            0xA => Some(Self::Pong),
+            _ => None,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Self::Continuation => 0x0,
+            Self::Text => 0x1,
+            Self::Binary => 0x2,
+            Self::Close => 0x8,
+            Self::Ping => 0x9,
+            Self::Pong => 0xA,
+        }
+    }
+
+    /// Checks if this is a control frame, which may not be fragmented.
+    pub fn is_control(self) -> bool {
+        matches!(self, Self::Close | Self::Ping | Self::Pong)
+    }
+}
+
+/// WebSocket frame. Extensions are not supported, so the reserved bits are always 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Whether this is the last fragment of a message.
+    pub fin: bool,
+    /// Type of the frame.
+    pub opcode: Opcode,
+    /// Unmasked payload.
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    /// Maximum payload length of a control frame.
+    const MAX_CONTROL_LEN: usize = 125;
+
+    /// Creates an unfragmented frame.
+    pub fn new(opcode: Opcode, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            fin: true,
+            opcode,
+            payload: payload.into(),
+        }
+    }
+
+    /// Creates a close frame with the given status code.
+    pub fn close(code: u16) -> Self {
+        Self::new(Opcode::Close, code.to_be_bytes())
+    }
+
+    /// Reads a frame sent by a client, which must be masked.
+    ///
+    /// Fails with `InvalidData` if the frame is malformed or its payload is longer than `max_len`
+    /// bytes, and with `UnexpectedEof` if the connection is closed in the middle of it.
+    pub fn read_from<R: Read>(reader: &mut R, max_len: usize) -> io::Result<Self> {
+        let mut head = [0; 2];
+        reader.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        if head[0] & 0x70 != 0 {
+            return Err(invalid_data("reserved bits are set"));
+        }
+        let opcode =
+            Opcode::from_bits(head[0] & 0x0F).ok_or_else(|| invalid_data("unknown opcode"))?;
+        if head[1] & 0x80 == 0 {
+            return Err(invalid_data("client frame is not masked"));
+        }
+
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut len = [0; 2];
+                reader.read_exact(&mut len)?;
+                u64::from(u16::from_be_bytes(len))
+            }
+            127 => {
+                let mut len = [0; 8];
+                reader.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => u64::from(len),
+        };
+        if opcode.is_control() && (!fin || len > Self::MAX_CONTROL_LEN as u64) {
+            return Err(invalid_data("control frame is fragmented or too long"));
+        }
+        if len > max_len as u64 {
+            return Err(invalid_data("frame is too long"));
+        }
+
+        let mut mask = [0; 4];
+        reader.read_exact(&mut mask)?;
+        let mut payload = vec![0; len as usize];
+        reader.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        Ok(Self {
+            fin,
+            opcode,
+            payload,
+        })
+    }
+
+    /// Writes the frame as sent by a server, i.e., unmasked.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut head = vec![u8::from(self.fin) << 7 | self.opcode.bits()];
+        let len = self.payload.len();
+        if len < 126 {
+            head.push(len as u8);
+        } else if let Ok(len) = u16::try_from(len) {
+            head.push(126);
+            head.extend_from_slice(&len.to_be_bytes());
+        } else {
+            head.push(127);
+            head.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+        writer.write_all(&head)?;
+        writer.write_all(&self.payload)?;
+        writer.flush()
+    }
+}
+
+/// Echoes the messages received from `reader` to `writer` until the client closes the connection.
+///
+/// Data frames are echoed as they are, so fragmented messages are echoed fragment by fragment,
+/// pings are answered with pongs, and pongs are ignored. If the client sends a malformed frame, the
+/// connection is closed with `CLOSE_PROTOCOL_ERROR`. Returns the number of echoed data frames.
+pub(crate) fn echo<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    max_len: usize,
+) -> io::Result<usize> {
+    let mut echoed = 0;
+    loop {
+        let frame = match Frame::read_from(reader, max_len) {
+            Ok(frame) => frame,
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                Frame::close(CLOSE_PROTOCOL_ERROR).write_to(writer)?;
+                return Err(err);
+            }
+            Err(err) => return Err(err),
+        };
+
+        match frame.opcode {
+            Opcode::Close => {
+                // Echoing the status code acknowledges the closure.
+                let code = frame.payload.get(..2).unwrap_or(&[]).to_vec();
+                Frame::new(Opcode::Close, code).write_to(writer)?;
+                return Ok(echoed);
+            }
+            Opcode::Ping => Frame::new(Opcode::Pong, frame.payload).write_to(writer)?,
+            Opcode::Pong => {}
+            Opcode::Continuation | Opcode::Text | Opcode::Binary => {
+                frame.write_to(writer)?;
+                echoed += 1;
+            }
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,255 @@
+//! k-FIFO queue, which relaxes the order of the values within a window of `k` for throughput.
+//!
+//! Based on Kirsch, Lippautz, and Payer. [Fast and Scalable, Lock-free k-FIFO
+//! Queues](https://doi.org/10.1007/978-3-642-39958-9_18). PaCT 2013.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, AtomicU8, Ordering};
+
+use crate::backoff::Backoff;
+use crate::hazard_pointer::{retire, Shield};
+use crate::utils::stripe;
+use crate::CachePadded;
+
+/// Relaxation of `KFifo::default()`.
+const DEFAULT_K: usize = 16;
+
+/// The slot was never written.
+const EMPTY: u8 = 0;
+/// A push is writing the value.
+const WRITING: u8 = 1;
+/// The slot holds a value.
+const FULL: u8 = 2;
+/// The value was popped. The slot is not written again.
+const TAKEN: u8 = 3;
+
+/// Slot of a value, which goes through the states above in order, once.
+#[derive(Debug)]
+struct Slot<T> {
+    state: AtomicU8,
+    /// Initialized from `FULL` until it is taken.
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// `k` slots that the pushes fill and the pops drain in any order.
+#[derive(Debug)]
+struct Segment<T> {
+    slots: Box<[Slot<T>]>,
+    next: AtomicPtr<Segment<T>>,
+}
+
+impl<T> Segment<T> {
+    fn new(k: usize) -> *mut Self {
+        let slots = (0..k)
+            .map(|_| Slot {
+                state: AtomicU8::new(EMPTY),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+        Box::into_raw(Box::new(Self {
+            slots,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+/// Lock-free k-FIFO queue, whose segments are reclaimed with hazard pointers.
+///
+/// The values are pushed into the `k` slots of the last segment, and popped from the slots of the
+/// first segment, in any order within a segment. So a value is popped before at most `k - 1` of
+/// the values pushed before it: the queue is FIFO up to a window of `k`. Each thread starts its
+/// search at a different slot, so that the threads rarely contend for the same slot, which is what
+/// makes the queue faster than a strict one.
+///
+/// A segment is appended once all its slots are written, and unlinked once all its values are
+/// popped. A slot is used only once, so a push never races with the pop that unlinks its segment.
+///
+/// Usable with any number of producers and consumers.
+#[derive(Debug)]
+pub struct KFifo<T> {
+    /// The first segment. Padded apart from `tail`, as poppers and pushers update them at the same
+    /// time.
+    head: CachePadded<AtomicPtr<Segment<T>>>,
+    /// The last segment or, while a segment is being appended, the one before it.
+    tail: CachePadded<AtomicPtr<Segment<T>>>,
+    k: usize,
+}
+
+// Any particular `T` should never be accessed concurrently, so no need for `T: Sync`.
+unsafe impl<T: Send> Send for KFifo<T> {}
+unsafe impl<T: Send> Sync for KFifo<T> {}
+
+impl<T> Default for KFifo<T> {
+    fn default() -> Self {
+        Self::new(DEFAULT_K)
+    }
+}
+
+impl<T> KFifo<T> {
+    /// Creates an empty queue whose order is relaxed within a window of `k`. With `k` of 1, the
+    /// queue is FIFO. Panics if `k` is zero.
+    pub fn new(k: usize) -> Self {
+        assert!(k > 0, "the relaxation must be positive");
+        let segment = Segment::new(k);
+        Self {
+            head: CachePadded::new(AtomicPtr::new(segment)),
+            tail: CachePadded::new(AtomicPtr::new(segment)),
+            k,
+        }
+    }
+
+    /// Returns the relaxation `k`.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the slot at which the current thread starts its searches.
+    fn start(&self) -> usize {
+        stripe(self.k.next_power_of_two()) % self.k
+    }
+
+    /// Adds `t` to the back of the queue.
+    pub fn push(&self, t: T) {
+        let shield = Shield::default();
+        let start = self.start();
+        let backoff = Backoff::new();
+        // The segment allocated by a failed append, for the next one.
+        let mut spare: *mut Segment<T> = ptr::null_mut();
+
+        loop {
+            let tail = shield.protect(&self.tail);
+            // SAFETY: `tail` is never null, and it is protected and validated.
+            let tail_ref = unsafe { &*tail };
+
+            for i in 0..self.k {
+                let slot = &tail_ref.slots[(start + i) % self.k];
+                if slot.state.load(Ordering::Relaxed) == EMPTY
+                    && slot
+                        .state
+                        .compare_exchange(EMPTY, WRITING, Ordering::Relaxed, Ordering::Relaxed)
+                        .is_ok()
+                {
+                    // SAFETY: The CAS above gave this thread the slot, and the segment is not
+                    // unlinked until its value is popped.
+                    unsafe { (*slot.value.get()).write(t) };
+                    // Release: the value is written before it is popped.
+                    slot.state.store(FULL, Ordering::Release);
+                    if !spare.is_null() {
+                        // SAFETY: `spare` was not published.
+                        drop(unsafe { Box::from_raw(spare) });
+                    }
+                    return;
+                }
+            }
+
+            // Every slot is written, and stays so. Append a segment, or help the push that did.
+            let next = tail_ref.next.load(Ordering::Acquire);
+            if !next.is_null() {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+            if spare.is_null() {
+                spare = Segment::new(self.k);
+            }
+            // Release: the segment is initialized before it is published.
+            if tail_ref
+                .next
+                .compare_exchange(ptr::null_mut(), spare, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, spare, Ordering::Release, Ordering::Relaxed);
+                spare = ptr::null_mut();
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Removes a value among the first `k` in the queue, or returns `None` if the queue is empty.
+    ///
+    /// A value whose push is still writing it is not popped, so the queue may look empty while a
+    /// push is in progress.
+    pub fn pop(&self) -> Option<T> {
+        let shield = Shield::default();
+        let start = self.start();
+        let backoff = Backoff::new();
+
+        loop {
+            let head = shield.protect(&self.head);
+            // SAFETY: `head` is never null, and it is protected and validated.
+            let head_ref = unsafe { &*head };
+
+            let mut drained = true;
+            for i in 0..self.k {
+                let slot = &head_ref.slots[(start + i) % self.k];
+                match slot.state.load(Ordering::Relaxed) {
+                    // Acquire: the value is written before it is read.
+                    FULL if slot
+                        .state
+                        .compare_exchange(FULL, TAKEN, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok() =>
+                    {
+                        // SAFETY: The CAS above took the value, and `head` is protected.
+                        return Some(unsafe { (*slot.value.get()).assume_init_read() });
+                    }
+                    // Taken by another pop.
+                    FULL | TAKEN => {}
+                    _ => drained = false,
+                }
+            }
+            if !drained {
+                return None;
+            }
+
+            // Every value of the segment is popped. Unlink it, unless it is the last one.
+            let next = head_ref.next.load(Ordering::Acquire);
+            if next.is_null() {
+                return None;
+            }
+            // The tail may lag behind at `head`, which is about to be retired. Move it first.
+            let _ = self
+                .tail
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed);
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                shield.clear();
+                // SAFETY: `head` is detached by the CAS above, and this thread retires it only
+                // once.
+                unsafe { retire(head) };
+            } else {
+                backoff.spin();
+            }
+        }
+    }
+}
+
+impl<T> Drop for KFifo<T> {
+    fn drop(&mut self) {
+        let mut segment = self.head.load(Ordering::Relaxed);
+        while !segment.is_null() {
+            // SAFETY: No other thread accesses the queue, and the segments in it are not retired.
+            let mut boxed = unsafe { Box::from_raw(segment) };
+            for slot in boxed.slots.iter_mut() {
+                if slot.state.load(Ordering::Relaxed) == FULL {
+                    // SAFETY: The value is written and not popped.
+                    unsafe { slot.value.get_mut().assume_init_drop() };
+                }
+            }
+            segment = boxed.next.load(Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Cell that is written once, and a value that is initialized on first access.
+
+use core::cell::{Cell, UnsafeCell};
+use core::convert::Infallible;
+use core::fmt;
+use core::mem::{self, MaybeUninit};
+use core::ops::Deref;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicU8, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicU8, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicU8, Ordering};
+
+use crate::parker::WaitList;
+
+/// No value is written, and no thread is initializing it.
+const INCOMPLETE: u8 = 0;
+/// A thread is initializing the value.
+const RUNNING: u8 = 1;
+/// The value is written.
+const COMPLETE: u8 = 2;
+
+/// Thread-safe cell that is written at most once.
+///
+/// The double-checked initialization: a reader that sees `COMPLETE` returns the value without any
+/// lock, and only the readers that miss it race to initialize it. The winner of the race runs the
+/// initializer, and the others park until it finishes. If the initializer fails or panics, the cell
+/// is left uninitialized, and one of the parked threads runs its own initializer.
+pub struct OnceCell<T> {
+    state: AtomicU8,
+    /// Written by the initializer before `COMPLETE`, and read after it.
+    value: UnsafeCell<MaybeUninit<T>>,
+    waiters: WaitList,
+}
+
+unsafe impl<T: Send> Send for OnceCell<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OnceCell").field(&self.get()).finish()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Puts the cell back to `INCOMPLETE` if the initializer fails or panics.
+struct Reset<'a, T>(&'a OnceCell<T>);
+
+impl<T> Drop for Reset<'_, T> {
+    fn drop(&mut self) {
+        self.0.state.store(INCOMPLETE, Ordering::Relaxed);
+        // The next initializer, if any.
+        self.0.waiters.notify_one();
+    }
+}
+
+impl<T> OnceCell<T> {
+    #[cfg(not(feature = "check-loom"))]
+    /// Creates an empty cell.
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(INCOMPLETE),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            waiters: WaitList::new(),
+        }
+    }
+
+    #[cfg(feature = "check-loom")]
+    /// Creates an empty cell.
+    pub fn new() -> Self {
+        Self {
+            state: AtomicU8::new(INCOMPLETE),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            waiters: WaitList::new(),
+        }
+    }
+
+    /// Returns the value if it is written.
+    pub fn get(&self) -> Option<&T> {
+        // Acquire: the value is written before `COMPLETE`. Pairs with the Release in
+        // `get_or_try_init`.
+        if self.state.load(Ordering::Acquire) == COMPLETE {
+            // SAFETY: The value is written, and is never written again.
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value mutably if it is written.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.state.load(Ordering::Relaxed) == COMPLETE {
+            // SAFETY: The value is written, and the cell is borrowed exclusively.
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Writes `value` if the cell is empty, waiting for a concurrent initializer if any. Returns
+    /// `value` back if the cell is already written.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        let _ = self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    /// Returns the value, initializing it with `f` if the cell is empty. If another thread is
+    /// initializing it, waits for that thread.
+    ///
+    /// If `f` panics, the panic is propagated to the caller, and the cell is left empty.
+    pub fn get_or_init<F>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> T,
+    {
+        match self.get_or_try_init(|| Ok::<_, Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Returns the value, initializing it with `f`, which may fail, if the cell is empty. If
+    /// another thread is initializing it, waits for that thread.
+    ///
+    /// If `f` fails, the error is returned, and the cell is left empty, as if `f` panicked.
+    pub fn get_or_try_init<E, F>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+
+        // Parks while another thread is initializing the value.
+        let won = self.waiters.wait_until(None, || {
+            // Acquire: pairs with the Release below, if the value is written meanwhile.
+            match self.state.compare_exchange(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => Some(true),
+                Err(COMPLETE) => Some(false),
+                Err(_) => None,
+            }
+        });
+        if won == Some(false) {
+            // SAFETY: The state is `COMPLETE`, as seen with Acquire above.
+            return Ok(unsafe { (*self.value.get()).assume_init_ref() });
+        }
+
+        let reset = Reset(self);
+        let value = f()?;
+        mem::forget(reset);
+        // SAFETY: This thread is the initializer, so no other thread accesses the value.
+        unsafe { (*self.value.get()).write(value) };
+        // Release: the value is written before `COMPLETE`.
+        self.state.store(COMPLETE, Ordering::Release);
+        self.waiters.notify_all();
+        // SAFETY: The value is written, and is never written again.
+        Ok(unsafe { (*self.value.get()).assume_init_ref() })
+    }
+
+    /// Returns the value if it is written, consuming the cell.
+    pub fn into_inner(mut self) -> Option<T> {
+        let value = if self.state.load(Ordering::Relaxed) == COMPLETE {
+            // SAFETY: The value is written, and the state is reset so that it is not dropped again.
+            Some(unsafe { self.value.get_mut().assume_init_read() })
+        } else {
+            None
+        };
+        self.state.store(INCOMPLETE, Ordering::Relaxed);
+        value
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.get_mut() {
+            // SAFETY: The value is written, and is not used again.
+            unsafe { core::ptr::drop_in_place(value) };
+        }
+    }
+}
+
+/// Value that is initialized by `F` on first access, e.g., in a `static`.
+///
+/// If the initializer panics, the panic is propagated to the thread that accessed the value, and
+/// all later accesses panic.
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    /// Taken out by the initializer, so that it runs only once.
+    init: Cell<Option<F>>,
+}
+
+// `init` is taken out only by the initializer of the cell, which is a single thread.
+unsafe impl<T, F: Send> Sync for Lazy<T, F> where OnceCell<T>: Sync {}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lazy")
+            .field("cell", &self.cell)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, F> Lazy<T, F> {
+    #[cfg(not(feature = "check-loom"))]
+    /// Creates a value that is initialized by `init` on first access.
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceCell::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    #[cfg(feature = "check-loom")]
+    /// Creates a value that is initialized by `init` on first access.
+    pub fn new(init: F) -> Self {
+        Self {
+            cell: OnceCell::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    /// Returns the value, initializing it if this is the first access.
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| match this.init.take() {
+            Some(init) => init(),
+            None => panic!("the initializer of the Lazy panicked"),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
@@ -0,0 +1,157 @@
+//! Left-right concurrency control.
+//!
+//! Ramalhete and Correia, "Left-Right: A Concurrency Control Technique with Wait-Free Population
+//! Oblivious Reads", 2015. The object is kept in two instances. The readers read the one the
+//! `side` points to, while the writer writes the other one, switches the readers over to it, waits
+//! for the readers of the old instance to leave, and writes the old instance as well.
+//!
+//! The readers announce themselves on one of two read indicators, chosen by `version`. The writer
+//! cannot wait on a single indicator that new readers keep arriving at. Instead, it switches the
+//! new readers to the other indicator, and waits for each indicator to empty in turn.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::Deref;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::Mutex;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::Mutex;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::Mutex;
+
+use crate::backoff::Backoff;
+
+/// An object whose reads are wait-free and never wait for the writes, for any data structure.
+///
+/// Each write is applied to both of the two instances of the object, one after the other, so it
+/// must be deterministic: applied to equal instances, it leaves them equal. The writes are
+/// serialized by a lock, and each of them waits for the readers of the instance it writes to
+/// leave, so a long-lived [`LeftRightReadGuard`] holds back the writers.
+pub struct LeftRight<T> {
+    instances: [UnsafeCell<T>; 2],
+    /// Index of the instance the readers read.
+    side: AtomicUsize,
+    /// Index of the read indicator the readers arrive at.
+    version: AtomicUsize,
+    /// Number of the readers that arrived at each read indicator and did not leave yet.
+    readers: [AtomicUsize; 2],
+    writer: Mutex<()>,
+}
+
+// The readers share the instances, and the writer on any thread writes them.
+unsafe impl<T: Send> Send for LeftRight<T> {}
+unsafe impl<T: Send + Sync> Sync for LeftRight<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for LeftRight<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LeftRight").field(&*self.read()).finish()
+    }
+}
+
+impl<T: Clone + Default> Default for LeftRight<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Clone> LeftRight<T> {
+    /// Creates an object holding `t`.
+    pub fn new(t: T) -> Self {
+        Self {
+            instances: [UnsafeCell::new(t.clone()), UnsafeCell::new(t)],
+            side: AtomicUsize::new(0),
+            version: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            writer: Mutex::new(()),
+        }
+    }
+}
+
+impl<T> LeftRight<T> {
+    /// Returns the object as of the last write that finished switching the readers over. Writes
+    /// that start while the guard is alive are not visible through it.
+    pub fn read(&self) -> LeftRightReadGuard<'_, T> {
+        let version = self.version.load(Ordering::Relaxed);
+        let _ = self.readers[version].fetch_add(1, Ordering::Relaxed);
+        // SeqCst: either the writer that switches the side sees this reader arrive, or this
+        // reader sees the new side. Pairs with the fence in `write`.
+        fence(Ordering::SeqCst);
+        // Acquire: the instance is written before the side is switched to it.
+        let side = self.side.load(Ordering::Acquire);
+        LeftRightReadGuard {
+            left_right: self,
+            version,
+            side,
+        }
+    }
+
+    /// Applies `op` to the object, and returns what it returns. `op` is called twice, once for
+    /// each instance, and must return the same and leave the instances equal both times.
+    pub fn write<R, F: FnMut(&mut T) -> R>(&self, mut op: F) -> R {
+        let _writer = self.writer.lock().unwrap();
+        // Only the writers, which hold the lock, change the side.
+        let side = self.side.load(Ordering::Relaxed);
+        // SAFETY: The readers left the other instance before the last write switched them over,
+        // and new readers do not read it until this write switches them.
+        let _ = op(unsafe { &mut *self.instances[1 - side].get() });
+        self.side.store(1 - side, Ordering::Release);
+        // SeqCst: pairs with the fence in `read`.
+        fence(Ordering::SeqCst);
+
+        // The readers that arrive from now on read the new side. Those that arrived before wait
+        // on one of the indicators, whichever `version` they loaded.
+        let version = self.version.load(Ordering::Relaxed);
+        self.wait_for_readers(1 - version);
+        self.version.store(1 - version, Ordering::Relaxed);
+        self.wait_for_readers(version);
+
+        // SAFETY: All readers that may have read the old side left.
+        op(unsafe { &mut *self.instances[side].get() })
+    }
+
+    /// Waits until all readers that arrived at the read indicator `version` leave.
+    fn wait_for_readers(&self, version: usize) {
+        let backoff = Backoff::new();
+        // Acquire: the readers that leave are done reading. Pairs with the release in
+        // `LeftRightReadGuard::drop`.
+        while self.readers[version].load(Ordering::Acquire) != 0 {
+            backoff.snooze();
+        }
+    }
+}
+
+/// Read access to a [`LeftRight`], which holds back the writers until it is dropped.
+pub struct LeftRightReadGuard<'a, T> {
+    left_right: &'a LeftRight<T>,
+    version: usize,
+    side: usize,
+}
+
+impl<T> Deref for LeftRightReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The writer does not write the instance until this reader leaves.
+        unsafe { &*self.left_right.instances[self.side].get() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LeftRightReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for LeftRightReadGuard<'_, T> {
+    fn drop(&mut self) {
+        // Release: the reads of the instance happen before the writer writes it.
+        let _ = self.left_right.readers[self.version].fetch_sub(1, Ordering::Release);
+    }
+}
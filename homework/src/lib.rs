@@ -9,27 +9,112 @@
 #![allow(unused_imports)]
 #![allow(unused_mut)]
 
+#[cfg(all(feature = "check-loom", feature = "check-sched"))]
+compile_error!("`check-loom` and `check-sched` replace the same atomics, so only one is enabled");
+
 #[macro_use]
 mod utils;
 
 mod arc;
 mod art;
+mod atomic_arc;
+mod atomic_cell;
+mod backoff;
+mod bag;
+mod barrier;
+mod bitset;
+mod blocking_queue;
+mod bplus_tree;
+pub mod broadcast;
 mod bst;
+mod cache_padded;
+pub mod counter;
+pub mod ctrie;
+mod cuckoo;
+pub mod deadlock;
+pub mod deque;
+pub mod dlist;
+pub mod ebr;
 mod elim_stack;
 mod hash_table;
 pub mod hazard_pointer;
 pub mod hello_server;
+mod kfifo;
+mod lazy;
+mod left_right;
 mod linked_list;
 mod list_set;
+pub mod lock;
+mod lock_free_deque;
+mod locked_hash_map;
+mod lru;
 mod map;
+pub mod metrics;
+pub mod mpsc;
+#[cfg(all(feature = "numa", target_os = "linux"))]
+pub mod numa;
+pub mod oneshot;
+mod parker;
+pub mod persistent;
+pub mod priority_queue;
+pub mod qsbr;
+mod queue;
+mod radix_tree;
+pub mod rcu;
+pub mod reclaim;
+pub mod registry;
+#[cfg(feature = "check-sched")]
+pub mod sched;
+mod semaphore;
+pub mod seqlock;
+pub mod skiplist;
+mod slab;
+mod snapshot;
+pub mod spsc;
+mod stack;
+pub mod stm;
+mod union_find;
+mod wait_group;
+pub mod workload;
 
-pub use arc::Arc;
+pub use arc::{Arc, Weak};
 pub use art::{Art, Entry};
+pub use atomic_arc::AtomicArc;
+pub use atomic_cell::{AtomicCell, Plain};
+pub use backoff::Backoff;
+pub use bag::{Bag, Pool, Pooled};
+pub use barrier::{Barrier, CountDownLatch};
+pub use bitset::{AtomicBitSet, GrowableBitSet};
+pub use blocking_queue::BlockingQueue;
+pub use bplus_tree::BPlusTree;
 pub use bst::Bst;
+pub use cache_padded::CachePadded;
+pub use counter::ConcurrentCounter;
+pub use ctrie::Ctrie;
+pub use cuckoo::CuckooMap;
+pub use dlist::DList;
 pub use elim_stack::ElimStack;
 pub use hash_table::{GrowableArray, SplitOrderedList};
+pub use kfifo::KFifo;
+pub use lazy::{Lazy, OnceCell};
+pub use left_right::{LeftRight, LeftRightReadGuard};
 pub use linked_list::LinkedList;
 pub use list_set::OrderedListSet;
+pub use lock_free_deque::LockFreeDeque;
+pub use locked_hash_map::LockedHashMap;
+pub use lru::ConcurrentLru;
 pub use map::{
     ConcurrentMap, NonblockingConcurrentMap, NonblockingMap, RandGen, SequentialMap, StrStringMap,
 };
+pub use parker::{Parker, Unparker};
+pub use priority_queue::PriorityQueue;
+pub use queue::Queue;
+pub use radix_tree::RadixTree;
+pub use rcu::RcuCell;
+pub use semaphore::{Semaphore, SemaphorePermit};
+pub use skiplist::{SkipMap, SkipSet};
+pub use slab::Slab;
+pub use snapshot::AtomicSnapshot;
+pub use stack::{EliminationStack, Stack, VersionedStack};
+pub use union_find::UnionFind;
+pub use wait_group::WaitGroup;
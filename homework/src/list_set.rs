@@ -1,7 +1,8 @@
 use std::cmp;
 use std::mem;
 use std::ptr;
-use std::sync::{Mutex, MutexGuard};
+
+use crate::deadlock::{Mutex, MutexGuard};
 
 #[derive(Debug)]
 struct Node<T> {
@@ -140,7 +141,7 @@ impl<'l, T> Iterator for Iter<'l, T> {
 
 impl<T> Drop for OrderedListSet<T> {
     fn drop(&mut self) {
-        let mut curr_node = *self.head.get_mut().unwrap();
+        let mut curr_node = *self.head.lock().unwrap();
         unsafe {
             loop {
                 if curr_node.is_null() {
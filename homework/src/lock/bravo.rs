@@ -0,0 +1,300 @@
+//! BRAVO, biased reader-writer locks (Dice and Kogan, 2019).
+//!
+//! A reader of `std::sync::RwLock` increments the reader count in the lock, so that the readers
+//! of a read-dominated lock on different cores keep stealing its cache line from each other,
+//! although they never wait for each other. BRAVO lets a reader skip the underlying lock while the
+//! lock is *biased* towards readers: it instead publishes itself in a slot of a global table of
+//! visible readers, picked by hashing the lock and the thread, so that different readers write to
+//! different slots.
+//!
+//! A writer revokes the bias, and waits for the visible readers of the lock to leave, which takes
+//! a scan of the table. To amortize the scan, the bias is restored by a reader only after a
+//! while, proportional to how long the revocation took. A reader whose slot is taken, or that
+//! finds the lock unbiased, acquires the underlying lock as usual.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use std::sync::{LockResult, PoisonError};
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering};
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(feature = "check-loom"))]
+use arr_macro::arr;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{fence, AtomicBool, AtomicU64, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::backoff::Backoff;
+use crate::utils::stripe;
+
+/// Number of slots of `READERS`, a power of two.
+#[cfg(not(feature = "check-loom"))]
+const SLOTS: usize = 4096;
+#[cfg(feature = "check-loom")]
+const SLOTS: usize = 4;
+
+/// How many times as long as a revocation the bias stays revoked.
+const INHIBIT: u64 = 9;
+
+#[cfg(not(feature = "check-loom"))]
+/// The visible readers of all locks: each slot holds the address of the lock that a reader holds
+/// through it, or 0 if it is free.
+static READERS: [AtomicUsize; SLOTS] = arr![AtomicUsize::new(0); 4096];
+
+#[cfg(feature = "check-loom")]
+// FIXME: loom does not currently provide the equivalent of Lazy:
+// https://github.com/tokio-rs/loom/issues/263
+loom::lazy_static! {
+    /// The visible readers of all locks: each slot holds the address of the lock that a reader
+    /// holds through it, or 0 if it is free.
+    static ref READERS: [AtomicUsize; SLOTS] = core::array::from_fn(|_| AtomicUsize::new(0));
+}
+
+/// Returns the nanoseconds since an arbitrary point in time.
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+fn now() -> u64 {
+    use once_cell::sync::Lazy;
+    use std::time::Instant;
+
+    static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+    EPOCH.elapsed().as_nanos() as u64
+}
+
+/// Returns 0, as the models must be deterministic, so the bias is restored right away.
+#[cfg(any(feature = "check-loom", feature = "check-sched"))]
+fn now() -> u64 {
+    0
+}
+
+/// Wraps the guard of a result as `Guard`, keeping it poisoned.
+fn map_result<G, T>(result: LockResult<G>, f: impl FnOnce(G) -> T) -> LockResult<T> {
+    match result {
+        Ok(guard) => Ok(f(guard)),
+        Err(e) => Err(PoisonError::new(f(e.into_inner()))),
+    }
+}
+
+/// Reader-writer lock whose readers skip the underlying `RwLock` while it is biased towards them.
+///
+/// It has the interface of `std::sync::RwLock`, including poisoning, and suits read-dominated
+/// data: reads get faster as they do not contend, but writes get slower as they scan the global
+/// table of visible readers whenever they revoke the bias.
+pub struct BravoRwLock<T> {
+    /// Whether the readers may skip `lock`. It is set only by a reader holding `lock`, and
+    /// cleared only by a writer holding it.
+    rbias: AtomicBool,
+    /// Time from which a reader may set `rbias` again.
+    inhibit_until: AtomicU64,
+    /// Excludes the writers, and the readers while the lock is not biased.
+    lock: RwLock<()>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for BravoRwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for BravoRwLock<T> {}
+
+impl<T: Default> Default for BravoRwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for BravoRwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("BravoRwLock");
+        let _ = d.field("rbias", &self.rbias.load(Ordering::Relaxed));
+        match self.lock.try_read() {
+            // SAFETY: No writer holds the lock.
+            Ok(_guard) => d.field("data", unsafe { &*self.data.get() }),
+            Err(_) => d.field("data", &format_args!("<locked>")),
+        }
+        .finish()
+    }
+}
+
+impl<T> BravoRwLock<T> {
+    /// Creates a lock protecting `t`, biased towards the readers.
+    pub fn new(t: T) -> Self {
+        Self {
+            rbias: AtomicBool::new(true),
+            inhibit_until: AtomicU64::new(0),
+            lock: RwLock::new(()),
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    /// Consumes the lock and returns its value.
+    pub fn into_inner(self) -> LockResult<T> {
+        let Self { lock, data, .. } = self;
+        let poisoned = lock.into_inner().is_err();
+        let data = data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Returns `true` if a thread panicked while holding the lock for writing.
+    pub fn is_poisoned(&self) -> bool {
+        #[cfg(not(feature = "check-loom"))]
+        let poisoned = self.lock.is_poisoned();
+        // The locks of loom are never poisoned, as a panic fails the model.
+        #[cfg(feature = "check-loom")]
+        let poisoned = false;
+        poisoned
+    }
+
+    /// Identifies the lock in `READERS`.
+    fn id(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Returns the slot of `READERS` for the current thread to read the lock through.
+    fn slot(&self) -> &'static AtomicUsize {
+        // Fibonacci hashing, like `stripe`, as the locks are aligned.
+        let hash = self.id().wrapping_mul(0x9E37_79B9) >> 16;
+        &READERS[(stripe(SLOTS) ^ hash) & (SLOTS - 1)]
+    }
+
+    /// Acquires the lock for reading, blocking until there is no writer.
+    pub fn read(&self) -> LockResult<BravoReadGuard<'_, T>> {
+        if self.rbias.load(Ordering::Relaxed) {
+            let slot = self.slot();
+            if slot
+                .compare_exchange(0, self.id(), Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                // SeqCst: pairs with the fence in `write`. Either the writer sees the slot and
+                // waits for this reader, or this reader sees the bias revoked.
+                fence(Ordering::SeqCst);
+                // Acquire: the last writer happens before the reader that restored the bias.
+                if self.rbias.load(Ordering::Acquire) {
+                    let guard = BravoReadGuard {
+                        lock: self,
+                        reader: Reader::Visible(slot),
+                    };
+                    return if self.is_poisoned() {
+                        Err(PoisonError::new(guard))
+                    } else {
+                        Ok(guard)
+                    };
+                }
+                slot.store(0, Ordering::Relaxed);
+            }
+        }
+
+        let result = self.lock.read();
+        if !self.rbias.load(Ordering::Relaxed)
+            && now() >= self.inhibit_until.load(Ordering::Relaxed)
+        {
+            // Release: the last writer happens before the readers that see the bias.
+            self.rbias.store(true, Ordering::Release);
+        }
+        map_result(result, |guard| BravoReadGuard {
+            lock: self,
+            reader: Reader::Locked(guard),
+        })
+    }
+
+    /// Acquires the lock for writing, blocking until there is no other reader or writer. Revokes
+    /// the bias if the lock is biased.
+    pub fn write(&self) -> LockResult<BravoWriteGuard<'_, T>> {
+        let result = self.lock.write();
+        if self.rbias.load(Ordering::Relaxed) {
+            self.rbias.store(false, Ordering::Relaxed);
+            // SeqCst: pairs with the fence in `read`.
+            fence(Ordering::SeqCst);
+            let start = now();
+            let id = self.id();
+            let backoff = Backoff::new();
+            for slot in READERS.iter() {
+                // Acquire: the visible reader happens before this writer.
+                while slot.load(Ordering::Acquire) == id {
+                    backoff.snooze();
+                }
+            }
+            let end = now();
+            self.inhibit_until
+                .store(end + (end - start) * INHIBIT, Ordering::Relaxed);
+        }
+        map_result(result, |guard| BravoWriteGuard {
+            lock: self,
+            _guard: guard,
+        })
+    }
+}
+
+/// How a reader holds a `BravoRwLock`.
+enum Reader<'a> {
+    /// Through its slot of `READERS`.
+    Visible(&'static AtomicUsize),
+    /// Through the underlying lock.
+    Locked(RwLockReadGuard<'a, ()>),
+}
+
+/// Read guard of a [`BravoRwLock`].
+pub struct BravoReadGuard<'a, T> {
+    lock: &'a BravoRwLock<T>,
+    reader: Reader<'a>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for BravoReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Deref for BravoReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: No writer holds the lock while a reader does.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for BravoReadGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Reader::Visible(slot) = self.reader {
+            // Release: the read happens before the next writer.
+            slot.store(0, Ordering::Release);
+        }
+    }
+}
+
+/// Write guard of a [`BravoRwLock`].
+pub struct BravoWriteGuard<'a, T> {
+    lock: &'a BravoRwLock<T>,
+    _guard: RwLockWriteGuard<'a, ()>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for BravoWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Deref for BravoWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The writer holds the lock exclusively.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for BravoWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The writer holds the lock exclusively.
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
@@ -0,0 +1,252 @@
+//! Classic mutual exclusion algorithms, which synchronize with loads and stores only.
+//!
+//! Peterson's and Dekker's algorithms are for two threads, and Lamport's bakery algorithm for `N`.
+//! They number the threads, so each lock has that many slots, which a thread claims for the
+//! duration of an acquisition. Claiming a slot is a CAS, but the mutual exclusion itself is up to
+//! the algorithms. More threads than slots wait for a slot first.
+//!
+//! The algorithms assume sequential consistency: a thread's store must be visible to the other
+//! before it loads what the other stored. This is the store-load order that acquire and release do
+//! not give, so the algorithms have `SeqCst` fences between their stores and their loads.
+//!
+//! These are for teaching, and slower than the other locks here: a waiting thread spins on the
+//! shared state of the others.
+
+use core::array;
+use cs431::lock::RawLock;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+
+use crate::backoff::Backoff;
+use crate::utils::stripe;
+
+/// Numbers of the threads that are acquiring or holding a lock.
+#[derive(Debug)]
+struct Slots<const N: usize> {
+    claimed: [AtomicBool; N],
+}
+
+impl<const N: usize> Slots<N> {
+    fn new() -> Self {
+        Self {
+            claimed: array::from_fn(|_| AtomicBool::new(false)),
+        }
+    }
+
+    /// Claims a slot, waiting for one if all are claimed.
+    fn claim(&self) -> usize {
+        let start = stripe(N.next_power_of_two()) % N;
+        let backoff = Backoff::new();
+        loop {
+            for i in (start..N).chain(0..start) {
+                // Acquire: the previous owner of the slot is done with it.
+                if !self.claimed[i].load(Ordering::Relaxed)
+                    && self.claimed[i]
+                        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok()
+                {
+                    return i;
+                }
+            }
+            backoff.snooze();
+        }
+    }
+
+    fn release(&self, i: usize) {
+        // Release: this thread is done with the slot.
+        self.claimed[i].store(false, Ordering::Release);
+    }
+}
+
+/// Peterson's lock: a thread announces that it wants to enter and gives the turn to the other,
+/// and waits while the other wants to enter and has the turn.
+#[derive(Debug)]
+pub struct PetersonLock {
+    slots: Slots<2>,
+    /// Whether the thread of each slot wants to enter.
+    flags: [AtomicBool; 2],
+    /// The slot that waits if both want to enter.
+    turn: AtomicUsize,
+}
+
+/// Token of an acquired `PetersonLock`, which is the slot of the holder.
+#[derive(Debug)]
+pub struct PetersonToken(usize);
+
+impl Default for PetersonLock {
+    fn default() -> Self {
+        Self {
+            slots: Slots::new(),
+            flags: array::from_fn(|_| AtomicBool::new(false)),
+            turn: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl RawLock for PetersonLock {
+    type Token = PetersonToken;
+
+    fn lock(&self) -> PetersonToken {
+        let me = self.slots.claim();
+        let other = 1 - me;
+        self.flags[me].store(true, Ordering::Relaxed);
+        // SeqCst: if the other thread's turn comes later, it sees this thread's flag.
+        fence(Ordering::SeqCst);
+        // Release: the previous critical section of this thread happens before the other's next
+        // one, which may start as the turn is given.
+        self.turn.store(me, Ordering::Release);
+        // SeqCst: either thread sees the other's turn.
+        fence(Ordering::SeqCst);
+        let backoff = Backoff::new();
+        // Acquire: the other's critical section happens before this thread's.
+        while self.flags[other].load(Ordering::Acquire) && self.turn.load(Ordering::Acquire) == me {
+            backoff.snooze();
+        }
+        PetersonToken(me)
+    }
+
+    unsafe fn unlock(&self, token: PetersonToken) {
+        // Release: the critical section happens before the other's.
+        self.flags[token.0].store(false, Ordering::Release);
+        self.slots.release(token.0);
+    }
+}
+
+/// Dekker's lock: a thread that wants to enter backs off while the other wants to enter and has
+/// the turn, which the holder passes on when it leaves.
+#[derive(Debug)]
+pub struct DekkerLock {
+    slots: Slots<2>,
+    /// Whether the thread of each slot wants to enter.
+    flags: [AtomicBool; 2],
+    /// The slot that enters if both want to enter.
+    turn: AtomicUsize,
+}
+
+/// Token of an acquired `DekkerLock`, which is the slot of the holder.
+#[derive(Debug)]
+pub struct DekkerToken(usize);
+
+impl Default for DekkerLock {
+    fn default() -> Self {
+        Self {
+            slots: Slots::new(),
+            flags: array::from_fn(|_| AtomicBool::new(false)),
+            turn: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl RawLock for DekkerLock {
+    type Token = DekkerToken;
+
+    fn lock(&self) -> DekkerToken {
+        let me = self.slots.claim();
+        let other = 1 - me;
+        let backoff = Backoff::new();
+        self.flags[me].store(true, Ordering::Relaxed);
+        // SeqCst: either thread sees the other's flag.
+        fence(Ordering::SeqCst);
+        // Acquire: the other's critical section happens before this thread's.
+        while self.flags[other].load(Ordering::Acquire) {
+            if self.turn.load(Ordering::Relaxed) != me {
+                self.flags[me].store(false, Ordering::Relaxed);
+                while self.turn.load(Ordering::Acquire) != me {
+                    backoff.snooze();
+                }
+                self.flags[me].store(true, Ordering::Relaxed);
+                fence(Ordering::SeqCst);
+            } else {
+                backoff.snooze();
+            }
+        }
+        DekkerToken(me)
+    }
+
+    unsafe fn unlock(&self, token: DekkerToken) {
+        let me = token.0;
+        // Release: the critical section happens before the other's.
+        self.turn.store(1 - me, Ordering::Release);
+        self.flags[me].store(false, Ordering::Release);
+        self.slots.release(me);
+    }
+}
+
+/// Lamport's bakery lock for `N` threads at once: a thread takes a number greater than those of
+/// the others, and waits for the threads with smaller numbers. Ties are broken by the slots.
+///
+/// The lock is first-come-first-served, as a thread that takes its number after another finished
+/// taking its number enters after it.
+#[derive(Debug)]
+pub struct BakeryLock<const N: usize = 8> {
+    slots: Slots<N>,
+    /// Whether the thread of each slot is taking its number.
+    choosing: [AtomicBool; N],
+    /// The number of the thread of each slot, or 0 if it does not want to enter.
+    numbers: [AtomicUsize; N],
+}
+
+/// Token of an acquired `BakeryLock`, which is the slot of the holder.
+#[derive(Debug)]
+pub struct BakeryToken(usize);
+
+impl<const N: usize> Default for BakeryLock<N> {
+    fn default() -> Self {
+        assert!(N > 0, "the bakery must have a slot");
+        Self {
+            slots: Slots::new(),
+            choosing: array::from_fn(|_| AtomicBool::new(false)),
+            numbers: array::from_fn(|_| AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl<const N: usize> RawLock for BakeryLock<N> {
+    type Token = BakeryToken;
+
+    fn lock(&self) -> BakeryToken {
+        let me = self.slots.claim();
+        self.choosing[me].store(true, Ordering::Relaxed);
+        // SeqCst: a thread that takes its number later either sees this one, or is seen choosing.
+        fence(Ordering::SeqCst);
+        let max = self
+            .numbers
+            .iter()
+            .map(|number| number.load(Ordering::Relaxed))
+            .max()
+            .unwrap_or(0);
+        let number = max + 1;
+        self.numbers[me].store(number, Ordering::Relaxed);
+        self.choosing[me].store(false, Ordering::Release);
+        // SeqCst: either thread sees the other's number.
+        fence(Ordering::SeqCst);
+
+        let backoff = Backoff::new();
+        for other in (0..N).filter(|&other| other != me) {
+            // Acquire: the number of the other thread is taken.
+            while self.choosing[other].load(Ordering::Acquire) {
+                backoff.snooze();
+            }
+            // Acquire: the other's critical section happens before this thread's.
+            loop {
+                let theirs = self.numbers[other].load(Ordering::Acquire);
+                if theirs == 0 || (number, me) < (theirs, other) {
+                    break;
+                }
+                backoff.snooze();
+            }
+        }
+        BakeryToken(me)
+    }
+
+    unsafe fn unlock(&self, token: BakeryToken) {
+        // Release: the critical section happens before the others'.
+        self.numbers[token.0].store(0, Ordering::Release);
+        self.slots.release(token.0);
+    }
+}
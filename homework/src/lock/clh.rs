@@ -0,0 +1,76 @@
+//! CLH queue lock.
+
+use cs431::lock::RawLock;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use super::node::{put_node, take_node, Node};
+use crate::backoff::Backoff;
+use crate::CachePadded;
+
+/// CLH lock: the waiting threads form an implicit queue, each spinning on the node of its
+/// predecessor until the predecessor unlocks it.
+///
+/// Unlike the MCS lock, a thread does not need to know its successor, so unlocking is a single
+/// store. The node of the predecessor is taken over by the thread that spun on it and reused for a
+/// later acquisition.
+///
+/// Use it with `cs431::lock::Lock`, whose guard unlocks the lock when dropped.
+#[derive(Debug)]
+pub struct ClhLock {
+    /// The node of the last thread in the queue, which is unlocked if the lock is free.
+    tail: AtomicPtr<CachePadded<Node>>,
+}
+
+/// Token of an acquired `ClhLock`, which is the node that the successor spins on.
+#[derive(Debug)]
+pub struct ClhToken(*mut CachePadded<Node>);
+
+impl Default for ClhLock {
+    fn default() -> Self {
+        let node = take_node();
+        // SAFETY: `node` is not published yet.
+        unsafe { &*node }.locked.store(false, Ordering::Relaxed);
+        Self {
+            tail: AtomicPtr::new(node),
+        }
+    }
+}
+
+impl RawLock for ClhLock {
+    type Token = ClhToken;
+
+    fn lock(&self) -> ClhToken {
+        let node = take_node();
+        // AcqRel: the node is initialized before the successor accesses it, and the predecessor's
+        // node is initialized before this thread accesses it.
+        let prev = self.tail.swap(node, Ordering::AcqRel);
+        // SAFETY: The predecessor's node is accessed only by this thread after the swap, besides
+        // the predecessor that unlocks it.
+        let prev_ref = unsafe { &*prev };
+        let backoff = Backoff::new();
+        while prev_ref.locked.load(Ordering::Acquire) {
+            backoff.snooze();
+        }
+        // SAFETY: The predecessor does not access its node after unlocking it.
+        unsafe { put_node(prev) };
+        ClhToken(node)
+    }
+
+    unsafe fn unlock(&self, token: ClhToken) {
+        // Release: the critical section happens before the successor's.
+        (*token.0).locked.store(false, Ordering::Release);
+    }
+}
+
+impl Drop for ClhLock {
+    fn drop(&mut self) {
+        // SAFETY: The lock is free, so no thread accesses the node at the tail.
+        unsafe { put_node(self.tail.load(Ordering::Relaxed)) };
+    }
+}
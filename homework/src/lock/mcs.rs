@@ -0,0 +1,115 @@
+//! MCS queue lock.
+
+use core::ptr;
+use cs431::lock::{RawLock, RawTryLock};
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use super::node::{put_node, take_node, Node};
+use crate::backoff::Backoff;
+use crate::CachePadded;
+
+/// MCS lock: the waiting threads form a queue of their nodes, and each spins on its own node until
+/// its predecessor hands over the lock. Unlike a test-and-set lock, the contended threads do not
+/// spin on a shared cache line.
+///
+/// The nodes are kept in a per-thread pool, so a thread allocates only as many nodes as the MCS
+/// locks it holds or waits for at once.
+#[derive(Debug)]
+pub struct McsLock {
+    /// The last node in the queue, or null if the lock is free.
+    tail: AtomicPtr<CachePadded<Node>>,
+}
+
+/// Token of an acquired `McsLock`, which is the node of the holder.
+#[derive(Debug)]
+pub struct McsToken(*mut CachePadded<Node>);
+
+impl Default for McsLock {
+    fn default() -> Self {
+        Self {
+            tail: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
+
+impl RawLock for McsLock {
+    type Token = McsToken;
+
+    fn lock(&self) -> McsToken {
+        let node = take_node();
+        // AcqRel: the node is initialized before the successor accesses it, and the predecessor's
+        // node is initialized before this thread accesses it.
+        let prev = self.tail.swap(node, Ordering::AcqRel);
+        if prev.is_null() {
+            return McsToken(node);
+        }
+
+        // SAFETY: The predecessor does not put its node back until it sees the link to this node.
+        let prev = unsafe { &*prev };
+        prev.next.store(node, Ordering::Release);
+        // SAFETY: `node` is ours, and it stays in the queue until this thread unlocks.
+        let node_ref = unsafe { &*node };
+        let backoff = Backoff::new();
+        while node_ref.locked.load(Ordering::Acquire) {
+            backoff.snooze();
+        }
+        McsToken(node)
+    }
+
+    unsafe fn unlock(&self, token: McsToken) {
+        let node = token.0;
+        let node_ref = &*node;
+        let mut next = node_ref.next.load(Ordering::Acquire);
+        if next.is_null() {
+            // Release: the critical section happens before the next `lock` that swaps `tail`.
+            if self
+                .tail
+                .compare_exchange(node, ptr::null_mut(), Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                // SAFETY: `node` is not in the queue any more, so no other thread accesses it.
+                put_node(node);
+                return;
+            }
+
+            // A successor swapped `tail` and is about to link itself.
+            let backoff = Backoff::new();
+            loop {
+                next = node_ref.next.load(Ordering::Acquire);
+                if !next.is_null() {
+                    break;
+                }
+                backoff.snooze();
+            }
+        }
+
+        // SAFETY: The successor has linked itself, so no other thread accesses `node` any more.
+        put_node(node);
+        // Release: the critical section happens before the successor's. The successor may put its
+        // node back right after this store.
+        (*next).locked.store(false, Ordering::Release);
+    }
+}
+
+impl RawTryLock for McsLock {
+    fn try_lock(&self) -> Result<McsToken, ()> {
+        let node = take_node();
+        match self
+            .tail
+            .compare_exchange(ptr::null_mut(), node, Ordering::AcqRel, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(McsToken(node)),
+            Err(_) => {
+                // SAFETY: `node` was not published.
+                unsafe { put_node(node) };
+                Err(())
+            }
+        }
+    }
+}
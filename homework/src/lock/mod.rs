@@ -0,0 +1,17 @@
+//! Spinlocks and a biased reader-writer lock, checked with loom. The mutual exclusion locks
+//! implement `cs431::lock::RawLock`, so that they can be used with `cs431::lock::Lock`.
+
+mod bravo;
+mod classic_locks;
+mod clh;
+mod mcs;
+mod node;
+mod rwspin;
+
+pub use bravo::{BravoReadGuard, BravoRwLock, BravoWriteGuard};
+pub use classic_locks::{
+    BakeryLock, BakeryToken, DekkerLock, DekkerToken, PetersonLock, PetersonToken,
+};
+pub use clh::{ClhLock, ClhToken};
+pub use mcs::{McsLock, McsToken};
+pub use rwspin::{Preference, RwSpinLock, RwSpinReadGuard, RwSpinWriteGuard};
@@ -0,0 +1,73 @@
+//! Queue nodes of the queue locks, pooled per thread.
+
+use core::cell::RefCell;
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::thread_local;
+#[cfg(not(feature = "check-loom"))]
+use std::thread_local;
+
+use crate::CachePadded;
+
+/// Queue node of a thread waiting for or holding a lock.
+#[derive(Debug)]
+pub(super) struct Node {
+    /// Set while the owner of the node waits for or holds the lock.
+    pub(super) locked: AtomicBool,
+    /// The successor, which enqueues itself after this node. Only used by the MCS lock.
+    pub(super) next: AtomicPtr<CachePadded<Node>>,
+}
+
+/// Nodes of the current thread that are not in any queue, reused across acquisitions so that the
+/// locks do not allocate on every `lock`.
+#[derive(Debug, Default)]
+struct Pool {
+    nodes: Vec<*mut CachePadded<Node>>,
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        for node in self.nodes.drain(..) {
+            // SAFETY: The nodes in the pool are not in any queue.
+            drop(unsafe { Box::from_raw(node) });
+        }
+    }
+}
+
+thread_local! {
+    static POOL: RefCell<Pool> = RefCell::new(Pool::default());
+}
+
+/// Takes a node from the current thread's pool, allocating one if it is empty. The node is locked
+/// and has no successor.
+pub(super) fn take_node() -> *mut CachePadded<Node> {
+    let node = POOL
+        .with(|pool| pool.borrow_mut().nodes.pop())
+        .unwrap_or_else(|| {
+            Box::into_raw(Box::new(CachePadded::new(Node {
+                locked: AtomicBool::new(false),
+                next: AtomicPtr::new(ptr::null_mut()),
+            })))
+        });
+    // SAFETY: The nodes in the pool are valid and not in any queue.
+    let node_ref = unsafe { &*node };
+    node_ref.locked.store(true, Ordering::Relaxed);
+    node_ref.next.store(ptr::null_mut(), Ordering::Relaxed);
+    node
+}
+
+/// Returns a node that left the queue to the current thread's pool.
+///
+/// # Safety
+///
+/// `node` must be taken by `take_node`, and no other thread may access it any more.
+pub(super) unsafe fn put_node(node: *mut CachePadded<Node>) {
+    POOL.with(|pool| pool.borrow_mut().nodes.push(node));
+}
@@ -0,0 +1,283 @@
+//! Reader-writer spinlock with a selectable preference.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicU64, Ordering};
+
+use crate::backoff::Backoff;
+
+/// Which side a `RwSpinLock` lets in first when readers and writers contend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Preference {
+    /// Readers get in whenever no writer holds the lock. Writers may starve.
+    Reader,
+    /// Readers wait while a writer holds or waits for the lock. Readers may starve.
+    Writer,
+    /// Readers and writers take turns: the readers that arrive while a writer holds or waits for
+    /// the lock all get in right after that writer, before the next one. Neither side starves.
+    #[default]
+    PhaseFair,
+}
+
+/// A writer holds the lock.
+const WRITER: u64 = 1;
+/// A writer waits for the readers to leave. Only one writer waits at a time.
+const WAITING: u64 = 1 << 1;
+/// Flipped whenever a writer unlocks, which lets in the waiting readers.
+const PHASE: u64 = 1 << 2;
+/// Unit of the number of readers holding the lock.
+const READER: u64 = 1 << 3;
+/// Unit of the number of readers waiting for the next reader phase.
+const WAITING_READER: u64 = 1 << 32;
+/// Mask of the number of readers holding the lock.
+const READERS: u64 = WAITING_READER - READER;
+
+/// Reader-writer spinlock whose whole state is a single word.
+///
+/// Unlike `std::sync::RwLock`, it never parks the threads nor gets poisoned, which suits short
+/// critical sections.
+pub struct RwSpinLock<T> {
+    /// The bits above, the number of readers holding the lock, and the number of readers waiting.
+    state: AtomicU64,
+    preference: Preference,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for RwSpinLock<T> {}
+unsafe impl<T: Send + Sync> Sync for RwSpinLock<T> {}
+
+impl<T: Default> Default for RwSpinLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwSpinLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwSpinLock");
+        let _ = d.field("preference", &self.preference);
+        match self.try_read() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        }
+        .finish()
+    }
+}
+
+impl<T> RwSpinLock<T> {
+    /// Creates a phase-fair lock protecting `t`.
+    pub fn new(t: T) -> Self {
+        Self::with_preference(t, Preference::default())
+    }
+
+    /// Creates a lock protecting `t`, with the given preference.
+    pub fn with_preference(t: T, preference: Preference) -> Self {
+        Self {
+            state: AtomicU64::new(0),
+            preference,
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    /// Returns the preference of the lock.
+    pub fn preference(&self) -> Preference {
+        self.preference
+    }
+
+    /// Returns the protected value.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Returns a mutable reference to the protected value, which needs no locking.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Returns `true` if a reader arriving at `state` may not get in right away.
+    fn blocks_reader(&self, state: u64) -> bool {
+        match self.preference {
+            Preference::Reader => state & WRITER != 0,
+            Preference::Writer | Preference::PhaseFair => state & (WRITER | WAITING) != 0,
+        }
+    }
+
+    /// Tries to acquire the lock for reading with a single CAS.
+    pub fn try_read(&self) -> Option<RwSpinReadGuard<'_, T>> {
+        let state = self.state.load(Ordering::Relaxed);
+        if self.blocks_reader(state) {
+            return None;
+        }
+        // Acquire: the last writer's critical section happens before this reader's.
+        self.state
+            .compare_exchange(state, state + READER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| RwSpinReadGuard { lock: self })
+    }
+
+    /// Acquires the lock for reading, spinning until no writer is preferred over this reader.
+    pub fn read(&self) -> RwSpinReadGuard<'_, T> {
+        let backoff = Backoff::new();
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if !self.blocks_reader(state) {
+                match self.state.compare_exchange_weak(
+                    state,
+                    state + READER,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return RwSpinReadGuard { lock: self },
+                    Err(actual) => {
+                        state = actual;
+                        backoff.spin();
+                    }
+                }
+                continue;
+            }
+
+            if self.preference != Preference::PhaseFair {
+                backoff.snooze();
+                state = self.state.load(Ordering::Relaxed);
+                continue;
+            }
+
+            // Waits for the next reader phase, whose writer counts this reader in.
+            match self.state.compare_exchange_weak(
+                state,
+                state + WAITING_READER,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    // Acquire: the writer's critical section happens before this reader's.
+                    while self.state.load(Ordering::Acquire) & PHASE == state & PHASE {
+                        backoff.snooze();
+                    }
+                    return RwSpinReadGuard { lock: self };
+                }
+                Err(actual) => state = actual,
+            }
+        }
+    }
+
+    /// Tries to acquire the lock for writing with a single CAS.
+    pub fn try_write(&self) -> Option<RwSpinWriteGuard<'_, T>> {
+        // Acquire: the previous critical sections happen before this writer's.
+        self.state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                (state & (WRITER | WAITING | READERS) == 0).then_some(state | WRITER)
+            })
+            .ok()
+            .map(|_| RwSpinWriteGuard { lock: self })
+    }
+
+    /// Acquires the lock for writing, spinning until the readers and the writer holding it leave.
+    pub fn write(&self) -> RwSpinWriteGuard<'_, T> {
+        let backoff = Backoff::new();
+        if self.preference == Preference::Reader {
+            loop {
+                if let Some(guard) = self.try_write() {
+                    return guard;
+                }
+                backoff.snooze();
+            }
+        }
+
+        // Becomes the waiting writer, which stops new readers from getting in.
+        while self
+            .state
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |state| {
+                (state & WAITING == 0).then_some(state | WAITING)
+            })
+            .is_err()
+        {
+            backoff.snooze();
+        }
+
+        // Acquire: the previous critical sections happen before this writer's.
+        while self
+            .state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                (state & (WRITER | READERS) == 0).then_some((state & !WAITING) | WRITER)
+            })
+            .is_err()
+        {
+            backoff.snooze();
+        }
+        RwSpinWriteGuard { lock: self }
+    }
+
+    /// Releases the lock held by a reader.
+    fn read_unlock(&self) {
+        // Release: the critical section happens before the next writer's.
+        let _ = self.state.fetch_sub(READER, Ordering::Release);
+    }
+
+    /// Releases the lock held by a writer, letting in the readers waiting for the next phase.
+    fn write_unlock(&self) {
+        // Release: the critical section happens before the next readers' and writer's.
+        let _ = self
+            .state
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |state| {
+                let waiting = state / WAITING_READER;
+                Some((((state % WAITING_READER) & !WRITER) ^ PHASE) + waiting * READER)
+            });
+    }
+}
+
+/// Guard of a `RwSpinLock` held for reading.
+#[derive(Debug)]
+pub struct RwSpinReadGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+}
+
+/// Guard of a `RwSpinLock` held for writing.
+#[derive(Debug)]
+pub struct RwSpinWriteGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+}
+
+impl<T> Deref for RwSpinReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: Only readers hold the lock.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwSpinReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.read_unlock();
+    }
+}
+
+impl<T> Deref for RwSpinWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: Only this writer holds the lock.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for RwSpinWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: Only this writer holds the lock.
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwSpinWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.write_unlock();
+    }
+}
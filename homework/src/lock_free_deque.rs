@@ -0,0 +1,398 @@
+//! Michael's lock-free deque with hazard pointers.
+//!
+//! Michael, "CAS-based lock-free algorithm for shared deques", Euro-Par 2003. The values are in a
+//! doubly linked list, whose two ends and status are in an *anchor*, which every operation changes
+//! with a single CAS. A push links the new node to its end, and swings the anchor to a new end
+//! that is not *stable* yet, as the outward link of the old end does not point to the new node
+//! yet. Any operation that finds the anchor unstable first completes the push, by pointing that
+//! link to the new node and marking the anchor stable again (`stabilize`). A pop only swings a
+//! stable anchor, to the node next to its end, so it never follows a link that is not set yet.
+//!
+//! The paper packs the anchor into a double word. Here, the anchor is an immutable heap object
+//! that is replaced instead, and retired with the hazard pointers like the nodes, so an anchor is
+//! never reused while a thread compares against it. A node is only retired after the anchor that
+//! removes it is installed, so a node read from the anchor is protected by checking that the
+//! anchor is still installed, like the next node of `Queue`.
+//!
+//! The outward link of an end still points to the node popped from there, until the next push
+//! is completed. A thread completing that push may read the link, stall, and CAS it after the
+//! push is completed by another thread, the new node popped, and another node pushed at the
+//! address of the first popped one, linking the end to a popped node. So the thread that pops a
+//! node marks it in the link of its neighbor, and the node is retired only once the link changes,
+//! by the thread that changes it. A node that a link points to, marked or not, is then not
+//! retired, so a thread completing a push protects the node it expects in the link before its CAS.
+
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::backoff::Backoff;
+use crate::hazard_pointer::{retire, Shield};
+
+/// Returns `true` if the link points to a popped node that is not retired yet.
+fn is_marked<T>(link: *mut T) -> bool {
+    link as usize & 1 == 1
+}
+
+fn marked<T>(link: *mut T) -> *mut T {
+    (link as usize | 1) as *mut T
+}
+
+fn unmarked<T>(link: *mut T) -> *mut T {
+    (link as usize & !1) as *mut T
+}
+
+/// An end of the deque.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Front = 0,
+    Back = 1,
+}
+
+impl Side {
+    fn opposite(self) -> Self {
+        match self {
+            Self::Front => Self::Back,
+            Self::Back => Self::Front,
+        }
+    }
+}
+
+struct Node<T> {
+    /// Taken by the thread that pops the node.
+    value: ManuallyDrop<T>,
+    /// The neighbors towards the front and the back. The inward link of an end is set before the
+    /// node is pushed. The outward one is null, or marked after a pop, or points to the node
+    /// pushed next while its push is not completed.
+    links: [AtomicPtr<Node<T>>; 2],
+}
+
+impl<T> Node<T> {
+    fn new(value: T) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            value: ManuallyDrop::new(value),
+            links: [
+                AtomicPtr::new(ptr::null_mut()),
+                AtomicPtr::new(ptr::null_mut()),
+            ],
+        }))
+    }
+
+    /// Returns the link to the neighbor towards `side`.
+    fn link(&self, side: Side) -> &AtomicPtr<Node<T>> {
+        &self.links[side as usize]
+    }
+}
+
+/// Snapshot of the ends of the deque, which is never modified once installed.
+struct Anchor<T> {
+    /// The front and the back nodes, both null if the deque is empty, and the same node if it has
+    /// a single value.
+    ends: [*mut Node<T>; 2],
+    /// The side pushed to whose old end does not link to the new one yet, if any.
+    pushing: Option<Side>,
+}
+
+impl<T> Anchor<T> {
+    fn new(ends: [*mut Node<T>; 2], pushing: Option<Side>) -> *mut Self {
+        Box::into_raw(Box::new(Self { ends, pushing }))
+    }
+
+    fn end(&self, side: Side) -> *mut Node<T> {
+        self.ends[side as usize]
+    }
+
+    /// Returns the ends with the one of `side` replaced by `node`.
+    fn with_end(&self, side: Side, node: *mut Node<T>) -> [*mut Node<T>; 2] {
+        let mut ends = self.ends;
+        ends[side as usize] = node;
+        ends
+    }
+}
+
+/// Lock-free deque, which any thread pushes to and pops from at both ends.
+///
+/// Unlike the Chase-Lev [`Worker`](crate::deque::Worker), the two ends are symmetric, and unlike
+/// the [`DList`](crate::DList), a pop moves the value out. Every operation changes the same
+/// anchor, so the operations do not run in parallel even at different ends, but a stalled thread
+/// never blocks the others.
+pub struct LockFreeDeque<T> {
+    anchor: AtomicPtr<Anchor<T>>,
+}
+
+// Any particular `T` should never be accessed concurrently, so no need for `T: Sync`.
+unsafe impl<T: Send> Send for LockFreeDeque<T> {}
+unsafe impl<T: Send> Sync for LockFreeDeque<T> {}
+
+impl<T> fmt::Debug for LockFreeDeque<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockFreeDeque")
+            .field("is_empty", &self.is_empty())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Default for LockFreeDeque<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> LockFreeDeque<T> {
+    /// Creates an empty deque.
+    pub fn new() -> Self {
+        Self {
+            anchor: AtomicPtr::new(Anchor::new([ptr::null_mut(); 2], None)),
+        }
+    }
+
+    /// Returns `true` if the deque is empty.
+    pub fn is_empty(&self) -> bool {
+        let shield = Shield::default();
+        let anchor = shield.protect(&self.anchor);
+        // SAFETY: `anchor` is never null, and it is protected and validated.
+        unsafe { (*anchor).end(Side::Front).is_null() }
+    }
+
+    /// Adds `value` to the front of the deque.
+    pub fn push_front(&self, value: T) {
+        self.push(Side::Front, value);
+    }
+
+    /// Adds `value` to the back of the deque.
+    pub fn push_back(&self, value: T) {
+        self.push(Side::Back, value);
+    }
+
+    /// Removes the front value, or returns `None` if the deque is empty.
+    pub fn pop_front(&self) -> Option<T> {
+        self.pop(Side::Front)
+    }
+
+    /// Removes the back value, or returns `None` if the deque is empty.
+    pub fn pop_back(&self) -> Option<T> {
+        self.pop(Side::Back)
+    }
+
+    /// Replaces `current` by a new anchor. Retires `current` on success, and frees the new anchor
+    /// on failure.
+    fn swing(&self, current: *mut Anchor<T>, new: *mut Anchor<T>) -> bool {
+        // Release: the nodes of the new anchor are initialized and linked before it is installed.
+        if self
+            .anchor
+            .compare_exchange(current, new, Ordering::Release, Ordering::Relaxed)
+            .is_ok()
+        {
+            // SAFETY: `current` is detached by the CAS above, and only this thread detached it.
+            unsafe { retire(current) };
+            true
+        } else {
+            // SAFETY: `new` was not published.
+            drop(unsafe { Box::from_raw(new) });
+            false
+        }
+    }
+
+    fn push(&self, side: Side, value: T) {
+        let node = Node::new(value);
+        let anchor_shield = Shield::default();
+        let shields = Default::default();
+        let backoff = Backoff::new();
+        loop {
+            let anchor = anchor_shield.protect(&self.anchor);
+            // SAFETY: `anchor` is never null, and it is protected and validated.
+            let anchor_ref = unsafe { &*anchor };
+            let end = anchor_ref.end(side);
+            if end.is_null() {
+                if self.swing(anchor, Anchor::new([node, node], None)) {
+                    return;
+                }
+            } else if anchor_ref.pushing.is_none() {
+                // SAFETY: `node` is not published yet.
+                unsafe { &*node }
+                    .link(side.opposite())
+                    .store(end, Ordering::Relaxed);
+                let new = Anchor::new(anchor_ref.with_end(side, node), Some(side));
+                if self.swing(anchor, new) {
+                    // Another thread may have completed the push already.
+                    if anchor_shield.try_protect(new, &self.anchor).is_ok() {
+                        self.stabilize(new, &shields);
+                    }
+                    return;
+                }
+            } else {
+                self.stabilize(anchor, &shields);
+                continue;
+            }
+            backoff.spin();
+        }
+    }
+
+    fn pop(&self, side: Side) -> Option<T> {
+        let anchor_shield = Shield::default();
+        let shields: [Shield<Node<T>>; 3] = Default::default();
+        let backoff = Backoff::new();
+        loop {
+            let anchor = anchor_shield.protect(&self.anchor);
+            // SAFETY: `anchor` is never null, and it is protected and validated.
+            let anchor_ref = unsafe { &*anchor };
+            let end = anchor_ref.end(side);
+            if end.is_null() {
+                return None;
+            }
+            if anchor_ref.pushing.is_some() {
+                self.stabilize(anchor, &shields);
+                continue;
+            }
+
+            let inner = if end == anchor_ref.end(side.opposite()) {
+                ptr::null_mut()
+            } else {
+                // The nodes in the deque are not retired while the anchor is still installed.
+                shields[0].set(end);
+                if Shield::validate(anchor, &self.anchor).is_err() {
+                    continue;
+                }
+                // SAFETY: `end` is protected and validated.
+                let inner = unsafe { &*end }
+                    .link(side.opposite())
+                    .load(Ordering::Acquire);
+                shields[1].set(inner);
+                if Shield::validate(anchor, &self.anchor).is_err() {
+                    continue;
+                }
+                inner
+            };
+            let ends = if inner.is_null() {
+                [ptr::null_mut(); 2]
+            } else {
+                anchor_ref.with_end(side, inner)
+            };
+            if self.swing(anchor, Anchor::new(ends, None)) {
+                // SAFETY: The CAS in `swing` removed `end` from the deque, so no other thread
+                // takes its value, and `inner` is protected.
+                unsafe {
+                    let value = ManuallyDrop::take(&mut (*end).value);
+                    Self::unlink(end, inner, side);
+                    return Some(value);
+                }
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Releases the links of `end`, just popped from `side`, and marks it in the link of `inner`,
+    /// its neighbor if any. Retires `end` if it is not marked.
+    ///
+    /// # Safety
+    ///
+    /// `end` must be just removed from the deque by the current thread, and `inner` must be
+    /// protected.
+    unsafe fn unlink(end: *mut Node<T>, inner: *mut Node<T>, side: Side) {
+        for link in &(*end).links {
+            // Acquire: the popped node is taken before it is retired.
+            let pending = link.swap(ptr::null_mut(), Ordering::Acquire);
+            if is_marked(pending) {
+                retire(unmarked(pending));
+            }
+        }
+        // Release: the value is taken before the node is retired by the thread changing the link.
+        if inner.is_null()
+            || (*inner)
+                .link(side)
+                .compare_exchange(end, marked(end), Ordering::Release, Ordering::Relaxed)
+                .is_err()
+        {
+            // The link points to the node pushed next.
+            retire(end);
+        }
+    }
+
+    /// Completes the push that left `anchor` unstable, protecting the nodes with `shields`. Gives
+    /// up if the anchor is no longer installed, as it means another thread completed it.
+    fn stabilize(&self, anchor: *mut Anchor<T>, shields: &[Shield<Node<T>>; 3]) {
+        // SAFETY: `anchor` is protected by the caller.
+        let anchor_ref = unsafe { &*anchor };
+        // The address of the anchor installed by `push` may be reused by a stable one.
+        let side = some_or!(anchor_ref.pushing, return);
+
+        // The nodes of the anchor are not popped while it is unstable, so they are not retired
+        // while it is installed.
+        let new = anchor_ref.end(side);
+        shields[0].set(new);
+        if Shield::validate(anchor, &self.anchor).is_err() {
+            return;
+        }
+        // SAFETY: `new` is protected and validated.
+        let prev = unsafe { &*new }
+            .link(side.opposite())
+            .load(Ordering::Acquire);
+        shields[1].set(prev);
+        if Shield::validate(anchor, &self.anchor).is_err() {
+            return;
+        }
+
+        // SAFETY: `prev` is protected and validated.
+        let outward = unsafe { &*prev }.link(side);
+        let next = outward.load(Ordering::Acquire);
+        if next != new {
+            // A popped node, which is not retired while the link points to it.
+            if !next.is_null() {
+                shields[2].set(unmarked(next));
+                if Shield::validate(next, outward).is_err() {
+                    return;
+                }
+            }
+            if Shield::validate(anchor, &self.anchor).is_err() {
+                return;
+            }
+            // Acquire: the popped node is taken before it is retired.
+            if outward
+                .compare_exchange(next, new, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+            {
+                return;
+            }
+            if is_marked(next) {
+                // SAFETY: The link was the last to point to the popped node.
+                unsafe { retire(unmarked(next)) };
+            }
+        }
+        let _ = self.swing(anchor, Anchor::new(anchor_ref.ends, None));
+    }
+}
+
+impl<T> Drop for LockFreeDeque<T> {
+    fn drop(&mut self) {
+        // SAFETY: No other thread accesses the deque, and the anchor in it is not retired.
+        let anchor = unsafe { Box::from_raw(self.anchor.load(Ordering::Relaxed)) };
+        // The inward links are all set, except the one of the old end while a push is not
+        // completed, so the nodes are visited from the side pushed to.
+        let side = anchor.pushing.unwrap_or(Side::Back);
+        let last = anchor.end(side.opposite());
+        let mut node = anchor.end(side);
+        while !node.is_null() {
+            // SAFETY: Same as above. The nodes in the deque hold values that are not popped, and
+            // the marked ones are popped and not retired.
+            let mut boxed = unsafe { Box::from_raw(node) };
+            unsafe { ManuallyDrop::drop(&mut boxed.value) };
+            for link in &boxed.links {
+                let link = link.load(Ordering::Relaxed);
+                if is_marked(link) {
+                    drop(unsafe { Box::from_raw(unmarked(link)) });
+                }
+            }
+            if node == last {
+                break;
+            }
+            node = boxed.link(side.opposite()).load(Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Concurrent hash map with a lock per bucket, which grows incrementally.
+//!
+//! Each bucket chains its entries in a vector behind a mutex of its own, so operations on keys in
+//! different buckets never wait for each other. When the entries outnumber `LOAD_FACTOR` times
+//! the buckets, the table is replaced by one with twice as many buckets, and the old one is kept
+//! until its entries are moved. Instead of a single thread moving them all at once, every
+//! operation meanwhile moves a few buckets before its own, so the cost of growing is spread over
+//! the operations.
+//!
+//! An old bucket is moved with its lock and the locks of its two new buckets held, and is marked
+//! as moved. An operation locks the bucket of its key in the old table first, and only if it is
+//! moved, the one in the new table. As an operation holds at most one bucket lock at a time, and
+//! a move locks an old bucket before new ones, the locks are never taken in a cycle. The tables as
+//! a whole are behind a lock that the operations take for reading, and that starting and finishing
+//! growing takes for writing.
+
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+use core::mem;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::{Mutex, MutexGuard, RwLock};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::{Mutex, MutexGuard, RwLock};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::{Mutex, MutexGuard, RwLock};
+
+use crate::utils::{hash_of, HashBuilder};
+
+/// Number of the buckets of `LockedHashMap::new`.
+const DEFAULT_BUCKETS: usize = 16;
+
+/// Average number of the entries of a bucket above which the table grows.
+const LOAD_FACTOR: usize = 2;
+
+/// Number of the old buckets an operation moves while the table grows.
+const MOVES_PER_OPERATION: usize = 2;
+
+struct Slot<K, V> {
+    /// Kept to find the new bucket of the entry without hashing the key again.
+    hash: u64,
+    key: K,
+    value: V,
+}
+
+struct Bucket<K, V> {
+    slots: Vec<Slot<K, V>>,
+    /// Whether the entries moved to the new table. Only set in the old table.
+    moved: bool,
+}
+
+impl<K, V> Default for Bucket<K, V> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            moved: false,
+        }
+    }
+}
+
+struct Table<K, V> {
+    /// The number of the buckets is a power of two.
+    buckets: Box<[Mutex<Bucket<K, V>>]>,
+}
+
+impl<K, V> Table<K, V> {
+    fn new(buckets: usize) -> Self {
+        debug_assert!(buckets.is_power_of_two());
+        Self {
+            buckets: (0..buckets)
+                .map(|_| Mutex::new(Bucket::default()))
+                .collect(),
+        }
+    }
+
+    fn lock(&self, hash: u64) -> MutexGuard<'_, Bucket<K, V>> {
+        self.buckets[hash as usize & (self.buckets.len() - 1)]
+            .lock()
+            .unwrap()
+    }
+}
+
+/// The old table of a table that is growing.
+struct Growth<K, V> {
+    old: Table<K, V>,
+    /// The next old bucket to move.
+    next: AtomicUsize,
+    /// The number of the old buckets moved.
+    moved: AtomicUsize,
+}
+
+struct Tables<K, V> {
+    table: Table<K, V>,
+    growth: Option<Growth<K, V>>,
+}
+
+impl<K, V> Tables<K, V> {
+    /// Moves up to `MOVES_PER_OPERATION` old buckets to the table. Returns `true` if the last one
+    /// is moved by this call.
+    fn help(&self) -> bool {
+        let growth = some_or!(&self.growth, return false);
+        let len = growth.old.buckets.len();
+        let mut finished = false;
+        for _ in 0..MOVES_PER_OPERATION {
+            let index = growth.next.fetch_add(1, Ordering::Relaxed);
+            if index >= len {
+                break;
+            }
+            let mut old = growth.old.buckets[index].lock().unwrap();
+            let mut low = self.table.buckets[index].lock().unwrap();
+            let mut high = self.table.buckets[index + len].lock().unwrap();
+            for slot in mem::take(&mut old.slots) {
+                if slot.hash as usize & len == 0 {
+                    low.slots.push(slot);
+                } else {
+                    high.slots.push(slot);
+                }
+            }
+            old.moved = true;
+            finished = growth.moved.fetch_add(1, Ordering::Relaxed) + 1 == len;
+        }
+        finished
+    }
+
+    /// Locks the bucket of the keys hashed to `hash`, in the old table if it is not moved yet.
+    fn lock(&self, hash: u64) -> MutexGuard<'_, Bucket<K, V>> {
+        if let Some(growth) = &self.growth {
+            let bucket = growth.old.lock(hash);
+            if !bucket.moved {
+                return bucket;
+            }
+        }
+        self.table.lock(hash)
+    }
+}
+
+/// A concurrent hash map that chains the entries of each bucket behind a lock of the bucket.
+///
+/// It is the lock-based baseline of the concurrent maps: unlike the
+/// [`SplitOrderedList`](crate::SplitOrderedList) it blocks, and unlike the
+/// [`CuckooMap`](crate::CuckooMap) a lookup takes a single lock and growing blocks no one, but a
+/// lookup searches a chain, and readers of the same bucket exclude each other. The values are
+/// accessed in place under the bucket locks.
+pub struct LockedHashMap<K, V> {
+    tables: RwLock<Tables<K, V>>,
+    len: AtomicUsize,
+    hash_builder: HashBuilder,
+}
+
+impl<K, V> fmt::Debug for LockedHashMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LockedHashMap")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V> Default for LockedHashMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> LockedHashMap<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::with_buckets(DEFAULT_BUCKETS)
+    }
+
+    /// Creates an empty map that holds at least `capacity` entries without growing.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_buckets(((capacity.max(1) - 1) / LOAD_FACTOR + 1).next_power_of_two())
+    }
+
+    fn with_buckets(buckets: usize) -> Self {
+        Self {
+            tables: RwLock::new(Tables {
+                table: Table::new(buckets),
+                growth: None,
+            }),
+            len: AtomicUsize::new(0),
+            hash_builder: HashBuilder::default(),
+        }
+    }
+
+    /// Returns the number of the entries. It may be stale if other threads insert or remove
+    /// entries meanwhile.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of the entries the map holds before it grows.
+    pub fn capacity(&self) -> usize {
+        self.tables.read().unwrap().table.buckets.len() * LOAD_FACTOR
+    }
+
+    /// Returns `true` if the table is growing, i.e., some old buckets are not moved yet.
+    pub fn is_growing(&self) -> bool {
+        self.tables.read().unwrap().growth.is_some()
+    }
+
+    /// Calls `f` with each entry, locking a bucket at a time. An entry inserted or removed
+    /// meanwhile may or may not be seen, but an entry that is there all along is seen once.
+    pub fn for_each<F: FnMut(&K, &V)>(&self, mut f: F) {
+        let tables = self.tables.read().unwrap();
+        let mut visit = |bucket: &Bucket<K, V>| {
+            for slot in &bucket.slots {
+                f(&slot.key, &slot.value);
+            }
+        };
+        let growth = some_or!(&tables.growth, {
+            for bucket in tables.table.buckets.iter() {
+                visit(&bucket.lock().unwrap());
+            }
+            return;
+        });
+        // An old bucket is locked until its entries are seen, in either table, so that they are
+        // not moved meanwhile.
+        let len = growth.old.buckets.len();
+        for (index, old) in growth.old.buckets.iter().enumerate() {
+            let old = old.lock().unwrap();
+            if !old.moved {
+                visit(&old);
+                continue;
+            }
+            visit(&tables.table.buckets[index].lock().unwrap());
+            visit(&tables.table.buckets[index + len].lock().unwrap());
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> LockedHashMap<K, V> {
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        hash_of(&self.hash_builder, key)
+    }
+
+    /// Calls `f` with the bucket of the keys hashed to `hash`, after helping the table grow.
+    fn with_bucket<R>(&self, hash: u64, f: impl FnOnce(&mut Bucket<K, V>) -> R) -> R {
+        let tables = self.tables.read().unwrap();
+        let finished = tables.help();
+        let result = f(&mut tables.lock(hash));
+        drop(tables);
+        if finished {
+            self.tables.write().unwrap().growth = None;
+        }
+        result
+    }
+
+    /// Looks up `key`, and calls `f` with its value, which stays locked until `f` returns.
+    pub fn lookup<Q, F, R>(&self, key: &Q, f: F) -> R
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(Option<&V>) -> R,
+    {
+        let hash = self.hash(key);
+        self.with_bucket(hash, |bucket| {
+            let value = bucket
+                .slots
+                .iter()
+                .find(|slot| slot.hash == hash && slot.key.borrow() == key)
+                .map(|slot| &slot.value);
+            f(value)
+        })
+    }
+
+    /// Returns a clone of the value of `key`.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.lookup(key, |value| value.cloned())
+    }
+
+    /// Returns `true` if the map has an entry for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lookup(key, |value| value.is_some())
+    }
+
+    /// Returns a clone of the value of `key`, inserting the one `default` returns if it has none.
+    /// `default` is called with the bucket of the key locked, so at most once per key.
+    pub fn get_or_insert_with<F>(&self, key: K, default: F) -> V
+    where
+        F: FnOnce() -> V,
+        V: Clone,
+    {
+        let hash = self.hash(&key);
+        let (value, inserted) = self.with_bucket(hash, |bucket| {
+            if let Some(slot) = bucket
+                .slots
+                .iter()
+                .find(|slot| slot.hash == hash && slot.key == key)
+            {
+                return (slot.value.clone(), false);
+            }
+            let value = default();
+            bucket.slots.push(Slot {
+                hash,
+                key,
+                value: value.clone(),
+            });
+            (value, true)
+        });
+        if inserted {
+            self.inserted();
+        }
+        value
+    }
+
+    /// Inserts the entry, replacing the value of the key if it has one, which is returned.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        let hash = self.hash(&key);
+        let old = self.with_bucket(hash, |bucket| {
+            if let Some(slot) = bucket
+                .slots
+                .iter_mut()
+                .find(|slot| slot.hash == hash && slot.key == key)
+            {
+                return Some(mem::replace(&mut slot.value, value));
+            }
+            bucket.slots.push(Slot { hash, key, value });
+            None
+        });
+        if old.is_none() {
+            self.inserted();
+        }
+        old
+    }
+
+    /// Removes the entry of `key`, and returns its value.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash(key);
+        let value = self.with_bucket(hash, |bucket| {
+            let index = bucket
+                .slots
+                .iter()
+                .position(|slot| slot.hash == hash && slot.key.borrow() == key)?;
+            Some(bucket.slots.swap_remove(index).value)
+        })?;
+        let _ = self.len.fetch_sub(1, Ordering::Relaxed);
+        Some(value)
+    }
+
+    /// Counts an inserted entry, and starts growing the table if it is overloaded and not growing
+    /// already.
+    fn inserted(&self) {
+        let len = self.len.fetch_add(1, Ordering::Relaxed) + 1;
+        {
+            let tables = self.tables.read().unwrap();
+            if tables.growth.is_some() || len <= tables.table.buckets.len() * LOAD_FACTOR {
+                return;
+            }
+        }
+        let mut tables = self.tables.write().unwrap();
+        let buckets = tables.table.buckets.len();
+        if tables.growth.is_some() || self.len() <= buckets * LOAD_FACTOR {
+            return;
+        }
+        let old = mem::replace(&mut tables.table, Table::new(buckets * 2));
+        tables.growth = Some(Growth {
+            old,
+            next: AtomicUsize::new(0),
+            moved: AtomicUsize::new(0),
+        });
+    }
+}
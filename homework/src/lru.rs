@@ -0,0 +1,303 @@
+//! Concurrent LRU cache with a sharded index and a single recency list.
+
+use core::borrow::Borrow;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hash::Hash;
+use core::ptr;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::{Mutex, RwLock};
+#[cfg(feature = "check-loom")]
+use loom::sync::{Mutex, RwLock};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::{Mutex, RwLock};
+
+use crate::utils::{hash_of, HashBuilder};
+
+/// Number of the shards of `ConcurrentLru::new`.
+const DEFAULT_SHARDS: usize = 16;
+
+/// Links of a node in the recency list.
+struct Links<K, V> {
+    /// The more recently used node, or null if this is the head.
+    prev: *const Node<K, V>,
+    /// The less recently used node, or null if this is the tail.
+    next: *const Node<K, V>,
+    /// Whether the node is in the list. A node is never linked again once unlinked.
+    linked: bool,
+}
+
+impl<K, V> Links<K, V> {
+    fn unlinked() -> Self {
+        Self {
+            prev: ptr::null(),
+            next: ptr::null(),
+            linked: false,
+        }
+    }
+}
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    /// Accessed only with the list locked.
+    links: UnsafeCell<Links<K, V>>,
+}
+
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for Node<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for Node<K, V> {}
+
+/// Intrusive doubly linked list of the nodes, the most recently used first. Owns a reference to
+/// each node in it.
+struct List<K, V> {
+    head: *const Node<K, V>,
+    tail: *const Node<K, V>,
+    len: usize,
+    capacity: usize,
+}
+
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for List<K, V> {}
+
+impl<K, V> List<K, V> {
+    /// Returns the links of `node`.
+    ///
+    /// # Safety
+    ///
+    /// `node` is alive. The links are accessed only through the list, which is locked as `&mut`
+    /// witnesses.
+    unsafe fn links(&mut self, node: *const Node<K, V>) -> &mut Links<K, V> {
+        // SAFETY: Guaranteed by the caller.
+        unsafe { &mut *(*node).links.get() }
+    }
+
+    /// Links `node`, which was never linked, as the most recently used.
+    fn push_front(&mut self, node: Arc<Node<K, V>>) {
+        let node = Arc::into_raw(node);
+        let head = self.head;
+        // SAFETY: The list owns a reference to `node` and `head`.
+        unsafe {
+            *self.links(node) = Links {
+                prev: ptr::null(),
+                next: head,
+                linked: true,
+            };
+            if head.is_null() {
+                self.tail = node;
+            } else {
+                self.links(head).prev = node;
+            }
+        }
+        self.head = node;
+        self.len += 1;
+    }
+
+    /// Unlinks `node` if it is in the list, and returns the reference the list owned.
+    fn unlink(&mut self, node: &Node<K, V>) -> Option<Arc<Node<K, V>>> {
+        let node = node as *const Node<K, V>;
+        // SAFETY: `node` is borrowed, and its neighbors are in the list.
+        unsafe {
+            let links = self.links(node);
+            if !links.linked {
+                return None;
+            }
+            let (prev, next) = (links.prev, links.next);
+            *links = Links::unlinked();
+            if prev.is_null() {
+                self.head = next;
+            } else {
+                self.links(prev).next = next;
+            }
+            if next.is_null() {
+                self.tail = prev;
+            } else {
+                self.links(next).prev = prev;
+            }
+            self.len -= 1;
+            Some(Arc::from_raw(node))
+        }
+    }
+
+    /// Unlinks the least recently used node.
+    fn pop_back(&mut self) -> Option<Arc<Node<K, V>>> {
+        if self.tail.is_null() {
+            return None;
+        }
+        // SAFETY: The list owns a reference to the tail.
+        self.unlink(unsafe { &*self.tail })
+    }
+
+    /// Unlinks the least recently used nodes until the list is within its capacity.
+    fn shrink(&mut self) -> Vec<Arc<Node<K, V>>> {
+        let excess = self.len.saturating_sub(self.capacity);
+        (0..excess).filter_map(|_| self.pop_back()).collect()
+    }
+}
+
+impl<K, V> Drop for List<K, V> {
+    fn drop(&mut self) {
+        while self.pop_back().is_some() {}
+    }
+}
+
+type Shard<K, V> = RwLock<HashMap<K, Arc<Node<K, V>>>>;
+
+/// Concurrent cache that evicts the least recently used entries beyond its capacity.
+///
+/// The entries are indexed by a hash map divided into shards, each behind its own lock, so that
+/// lookups of different keys mostly do not contend. The recency of the entries is kept in an
+/// intrusive doubly linked list of the nodes behind a lock of its own, which `get` takes to move
+/// the node to the front.
+///
+/// A shard is locked before the list, and never after it. So an eviction unlinks the node first,
+/// and removes it from its shard after the list is unlocked, unless the key is mapped to another
+/// node meanwhile. Until then, the node is in the index but not in the list, which counts as
+/// evicted.
+pub struct ConcurrentLru<K, V> {
+    /// The number of the shards is a power of two.
+    shards: Box<[Shard<K, V>]>,
+    list: Mutex<List<K, V>>,
+    hash_builder: HashBuilder,
+}
+
+impl<K, V> fmt::Debug for ConcurrentLru<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = self.list.lock().unwrap();
+        f.debug_struct("ConcurrentLru")
+            .field("len", &list.len)
+            .field("capacity", &list.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V> ConcurrentLru<K, V> {
+    /// Creates a cache of `capacity` entries. Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        Self::with_shards(capacity, DEFAULT_SHARDS)
+    }
+
+    /// Creates a cache of `capacity` entries, whose index is divided into `shards` shards, rounded
+    /// up to a power of two. Panics if `capacity` is 0.
+    pub fn with_shards(capacity: usize, shards: usize) -> Self {
+        assert!(capacity > 0);
+        Self {
+            shards: (0..shards.max(1).next_power_of_two())
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            list: Mutex::new(List {
+                head: ptr::null(),
+                tail: ptr::null(),
+                len: 0,
+                capacity,
+            }),
+            hash_builder: HashBuilder::default(),
+        }
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.list.lock().unwrap().len
+    }
+
+    /// Returns `true` if the cache has no entry.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of entries.
+    pub fn capacity(&self) -> usize {
+        self.list.lock().unwrap().capacity
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> ConcurrentLru<K, V> {
+    fn shard<Q: Hash + ?Sized>(&self, key: &Q) -> &Shard<K, V> {
+        &self.shards[hash_of(&self.hash_builder, key) as usize & (self.shards.len() - 1)]
+    }
+
+    /// Removes the nodes unlinked by an eviction from the index, unless their keys are mapped to
+    /// other nodes meanwhile.
+    fn remove_evicted(&self, nodes: &[Arc<Node<K, V>>]) {
+        for node in nodes {
+            let mut shard = self.shard(&node.key).write().unwrap();
+            if matches!(shard.get(&node.key), Some(current) if Arc::ptr_eq(current, node)) {
+                let _ = shard.remove(&node.key);
+            }
+        }
+    }
+
+    /// Returns the value for `key`, and marks it as the most recently used.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        V: Clone,
+    {
+        let node = self.shard(key).read().unwrap().get(key)?.clone();
+        let mut list = self.list.lock().unwrap();
+        // An evicted node is not linked again.
+        let linked = list.unlink(&node)?;
+        list.push_front(linked);
+        Some(node.value.clone())
+    }
+
+    /// Inserts `value` for `key` as the most recently used, replacing the old value if any, and
+    /// evicts the least recently used entry if the cache is full.
+    pub fn put(&self, key: K, value: V) {
+        let node = Arc::new(Node {
+            key: key.clone(),
+            value,
+            links: UnsafeCell::new(Links::unlinked()),
+        });
+        let evicted = {
+            let mut shard = self.shard(&key).write().unwrap();
+            let old = shard.insert(key, node.clone());
+            let mut list = self.list.lock().unwrap();
+            if let Some(old) = old {
+                // Unless the old node is being evicted.
+                drop(list.unlink(&old));
+            }
+            list.push_front(node);
+            list.shrink()
+        };
+        self.remove_evicted(&evicted);
+    }
+
+    /// Removes the entry for `key`, and returns its value.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        V: Clone,
+    {
+        let mut shard = self.shard(key).write().unwrap();
+        let node = shard.remove(key)?;
+        // Unless the node is being evicted, in which case the evicting thread returns it.
+        let _linked = self.list.lock().unwrap().unlink(&node)?;
+        Some(node.value.clone())
+    }
+
+    /// Evicts the least recently used entry, and returns it.
+    pub fn pop_lru(&self) -> Option<(K, V)>
+    where
+        V: Clone,
+    {
+        let node = self.list.lock().unwrap().pop_back()?;
+        self.remove_evicted(core::slice::from_ref(&node));
+        Some((node.key.clone(), node.value.clone()))
+    }
+
+    /// Sets the maximum number of entries, evicting the least recently used entries beyond it.
+    /// Panics if `capacity` is 0.
+    pub fn set_capacity(&self, capacity: usize) {
+        assert!(capacity > 0);
+        let evicted = {
+            let mut list = self.list.lock().unwrap();
+            list.capacity = capacity;
+            list.shrink()
+        };
+        self.remove_evicted(&evicted);
+    }
+}
@@ -0,0 +1,622 @@
+//! Registry of named metrics, gathered in the Prometheus text format.
+//!
+//! A [`Registry`] holds families of metrics, each with a name, a help text and a kind, and a
+//! series per set of labels: [`Counter`]s, [`Gauge`]s and [`Histogram`]s, which are all built on
+//! [`ConcurrentCounter`]s so that the threads updating them do not contend, and counters and
+//! gauges computed by a function when gathered, which read the state of a component. The
+//! components register their metrics into a registry, usually [`global`], and
+//! [`Registry::gather`] takes a [`Snapshot`] of all of them, which is rendered for a `/metrics`
+//! endpoint or inspected by tests.
+//!
+//! A registry does not keep its metrics alive: the series of a metric is removed once the metric
+//! is dropped by the component that registered it, or once its function returns `None`.
+
+use core::fmt;
+use once_cell::sync::Lazy;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+
+use crate::ConcurrentCounter;
+
+/// The sum of a histogram is kept in millionths of the unit of its values.
+const SUM_SCALE: f64 = 1e6;
+
+/// Counter that only goes up.
+#[derive(Debug, Default)]
+pub struct Counter(ConcurrentCounter);
+
+impl Counter {
+    /// Creates a counter at 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `n` to the counter.
+    pub fn add(&self, n: u64) {
+        self.0.add(i64::try_from(n).unwrap_or(i64::MAX));
+    }
+
+    /// Adds 1 to the counter.
+    pub fn increment(&self) {
+        self.0.increment();
+    }
+
+    /// Returns the value of the counter.
+    pub fn get(&self) -> u64 {
+        self.0.sum().max(0) as u64
+    }
+}
+
+/// Value that goes up and down.
+///
+/// Being striped, a gauge is only updated by deltas, and is never set. A value that is only known
+/// as a whole is registered with [`Registry::gauge_fn`] instead.
+#[derive(Debug, Default)]
+pub struct Gauge(ConcurrentCounter);
+
+impl Gauge {
+    /// Creates a gauge at 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `delta` to the gauge.
+    pub fn add(&self, delta: i64) {
+        self.0.add(delta);
+    }
+
+    /// Adds 1 to the gauge.
+    pub fn increment(&self) {
+        self.0.increment();
+    }
+
+    /// Subtracts 1 from the gauge.
+    pub fn decrement(&self) {
+        self.0.decrement();
+    }
+
+    /// Returns the value of the gauge, which may be transiently off while it is updated. See
+    /// `ConcurrentCounter::sum`.
+    pub fn get(&self) -> i64 {
+        self.0.sum()
+    }
+}
+
+/// Distribution of observed values over fixed buckets.
+#[derive(Debug)]
+pub struct Histogram {
+    /// Upper bounds of the buckets, in increasing order. The last bucket, for `+Inf`, has none.
+    bounds: Box<[f64]>,
+    /// Number of the values in each bucket, not cumulated.
+    buckets: Box<[ConcurrentCounter]>,
+    /// Sum of the values, scaled by `SUM_SCALE`.
+    sum: ConcurrentCounter,
+}
+
+impl Histogram {
+    /// Creates a histogram whose buckets have the upper bounds `bounds`, plus one for `+Inf`.
+    /// Panics if the bounds are not increasing.
+    pub fn new(bounds: &[f64]) -> Self {
+        assert!(
+            bounds.windows(2).all(|pair| pair[0] < pair[1]),
+            "the bounds of a histogram are increasing"
+        );
+        Self {
+            bounds: bounds.into(),
+            buckets: (0..=bounds.len())
+                .map(|_| ConcurrentCounter::new())
+                .collect(),
+            sum: ConcurrentCounter::new(),
+        }
+    }
+
+    /// Records `value`.
+    pub fn observe(&self, value: f64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.buckets[bucket].increment();
+        self.sum.add((value * SUM_SCALE) as i64);
+    }
+
+    /// Returns the recorded values, with the counts of the buckets cumulated.
+    pub fn get(&self) -> HistogramValue {
+        let mut count = 0;
+        let buckets = self
+            .bounds
+            .iter()
+            .copied()
+            .chain([f64::INFINITY])
+            .zip(self.buckets.iter())
+            .map(|(bound, bucket)| {
+                count += bucket.sum().max(0) as u64;
+                (bound, count)
+            })
+            .collect();
+        HistogramValue {
+            buckets,
+            sum: self.sum.sum() as f64 / SUM_SCALE,
+            count,
+        }
+    }
+}
+
+/// Values recorded by a [`Histogram`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramValue {
+    /// Upper bound of each bucket, the last one being infinite, with the number of the values up
+    /// to it.
+    pub buckets: Vec<(f64, u64)>,
+    /// Sum of the values.
+    pub sum: f64,
+    /// Number of the values.
+    pub count: u64,
+}
+
+/// Kind of the metrics of a family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Made of [`Counter`]s, or of functions.
+    Counter,
+    /// Made of [`Gauge`]s, or of functions.
+    Gauge,
+    /// Made of [`Histogram`]s.
+    Histogram,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Histogram => "histogram",
+        }
+    }
+}
+
+/// Value of a series when gathered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Value of a [`Counter`], or of a counter function.
+    Counter(u64),
+    /// Value of a [`Gauge`], or of a gauge function.
+    Gauge(i64),
+    /// Values of a [`Histogram`].
+    Histogram(HistogramValue),
+}
+
+type ValueFn = Box<dyn Fn() -> Option<Value> + Send + Sync>;
+
+/// Where the value of a series is read from.
+enum Source {
+    Counter(Weak<Counter>),
+    Gauge(Weak<Gauge>),
+    Histogram(Weak<Histogram>),
+    Fn(ValueFn),
+}
+
+impl Source {
+    /// Returns the value, or `None` if the series is gone.
+    fn value(&self) -> Option<Value> {
+        match self {
+            Self::Counter(counter) => counter.upgrade().map(|c| Value::Counter(c.get())),
+            Self::Gauge(gauge) => gauge.upgrade().map(|g| Value::Gauge(g.get())),
+            Self::Histogram(histogram) => histogram.upgrade().map(|h| Value::Histogram(h.get())),
+            Self::Fn(f) => f(),
+        }
+    }
+}
+
+type Labels = Vec<(&'static str, String)>;
+
+struct Series {
+    labels: Labels,
+    source: Source,
+}
+
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: Kind,
+    series: Vec<Series>,
+}
+
+impl fmt::Debug for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Family")
+            .field("name", &self.name)
+            .field("kind", &self.kind)
+            .field("series", &self.series.len())
+            .finish()
+    }
+}
+
+/// Families of metrics, shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    families: Arc<Mutex<Vec<Family>>>,
+}
+
+static GLOBAL: Lazy<Registry> = Lazy::new(Registry::new);
+
+/// Returns the registry shared by the whole process.
+pub fn global() -> &'static Registry {
+    &GLOBAL
+}
+
+/// Gathers the metrics of the [`global`] registry.
+pub fn gather() -> Snapshot {
+    GLOBAL.gather()
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Family>> {
+        // The families are consistent even if a thread panicked while holding them, e.g., in a
+        // gauge function.
+        self.families.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the family `name`, adding it if it is not registered. Panics if it is registered
+    /// with another kind.
+    fn family<'a>(
+        families: &'a mut Vec<Family>,
+        name: &'static str,
+        help: &'static str,
+        kind: Kind,
+    ) -> &'a mut Family {
+        let index = match families.iter().position(|family| family.name == name) {
+            Some(index) => index,
+            None => {
+                families.push(Family {
+                    name,
+                    help,
+                    kind,
+                    series: Vec::new(),
+                });
+                families.len() - 1
+            }
+        };
+        let family = &mut families[index];
+        assert_eq!(
+            family.kind, kind,
+            "metric {name} is registered with another kind"
+        );
+        family
+    }
+
+    /// Returns the series of `labels` that is still alive, created by `create` if there is none,
+    /// as by `get` of its source.
+    fn series<T>(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        kind: Kind,
+        labels: &[(&'static str, &str)],
+        get: impl Fn(&Source) -> Option<Arc<T>>,
+        create: impl FnOnce() -> (Arc<T>, Source),
+    ) -> Arc<T> {
+        let mut families = self.lock();
+        let family = Self::family(&mut families, name, help, kind);
+        let labels = to_labels(labels);
+        if let Some(index) = family
+            .series
+            .iter()
+            .position(|series| series.labels == labels)
+        {
+            if let Some(existing) = get(&family.series[index].source) {
+                return existing;
+            }
+            // The metric of the series is dropped.
+            let _ = family.series.remove(index);
+        }
+        let (metric, source) = create();
+        family.series.push(Series { labels, source });
+        metric
+    }
+
+    /// Returns the counter `name` with `labels`, registering a new one unless it is alive. Panics
+    /// if the series is a counter function.
+    pub fn counter(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+    ) -> Arc<Counter> {
+        self.series(
+            name,
+            help,
+            Kind::Counter,
+            labels,
+            |source| match source {
+                Source::Counter(counter) => counter.upgrade(),
+                _ => panic!("counter {name} is registered as a function"),
+            },
+            || {
+                let counter = Arc::new(Counter::new());
+                let source = Source::Counter(Arc::downgrade(&counter));
+                (counter, source)
+            },
+        )
+    }
+
+    /// Returns the gauge `name` with `labels`, registering a new one unless it is alive. Panics if
+    /// the series is a gauge function.
+    pub fn gauge(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+    ) -> Arc<Gauge> {
+        self.series(
+            name,
+            help,
+            Kind::Gauge,
+            labels,
+            |source| match source {
+                Source::Gauge(gauge) => gauge.upgrade(),
+                _ => panic!("gauge {name} is registered as a function"),
+            },
+            || {
+                let gauge = Arc::new(Gauge::new());
+                let source = Source::Gauge(Arc::downgrade(&gauge));
+                (gauge, source)
+            },
+        )
+    }
+
+    /// Returns the histogram `name` with `labels`, registering a new one with the upper bounds
+    /// `bounds` unless it is alive. Panics if the histogram that is alive has other bounds.
+    pub fn histogram(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        bounds: &[f64],
+    ) -> Arc<Histogram> {
+        self.series(
+            name,
+            help,
+            Kind::Histogram,
+            labels,
+            |source| match source {
+                Source::Histogram(histogram) => {
+                    let histogram = histogram.upgrade()?;
+                    assert!(
+                        *histogram.bounds == *bounds,
+                        "histogram {name} is registered with other bounds"
+                    );
+                    Some(histogram)
+                }
+                _ => unreachable!("histograms are not registered as functions"),
+            },
+            || {
+                let histogram = Arc::new(Histogram::new(bounds));
+                let source = Source::Histogram(Arc::downgrade(&histogram));
+                (histogram, source)
+            },
+        )
+    }
+
+    /// Registers the series of `labels` in the family `name`, computed by `f`, replacing the
+    /// series with the same labels.
+    fn register_fn(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        kind: Kind,
+        labels: &[(&'static str, &str)],
+        f: ValueFn,
+    ) {
+        let mut families = self.lock();
+        let family = Self::family(&mut families, name, help, kind);
+        let labels = to_labels(labels);
+        family.series.retain(|series| series.labels != labels);
+        family.series.push(Series {
+            labels,
+            source: Source::Fn(f),
+        });
+    }
+
+    /// Registers the counter `name` with `labels` whose value is computed by `f` when gathered,
+    /// replacing the series with the same labels. The series is removed once `f` returns `None`,
+    /// e.g., once the component it reads is dropped.
+    ///
+    /// `f` runs with the registry locked, so it must not register metrics.
+    pub fn counter_fn<F>(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        f: F,
+    ) where
+        F: Fn() -> Option<u64> + Send + Sync + 'static,
+    {
+        let f = Box::new(move || f().map(Value::Counter));
+        self.register_fn(name, help, Kind::Counter, labels, f);
+    }
+
+    /// Registers the gauge `name` with `labels` whose value is computed by `f` when gathered, like
+    /// [`counter_fn`](Self::counter_fn).
+    pub fn gauge_fn<F>(
+        &self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&'static str, &str)],
+        f: F,
+    ) where
+        F: Fn() -> Option<i64> + Send + Sync + 'static,
+    {
+        let f = Box::new(move || f().map(Value::Gauge));
+        self.register_fn(name, help, Kind::Gauge, labels, f);
+    }
+
+    /// Reads all the metrics, and removes the series that are gone.
+    ///
+    /// The families are sorted by name, and the series of each family by labels.
+    pub fn gather(&self) -> Snapshot {
+        let mut families = self.lock();
+        let mut gathered = Vec::with_capacity(families.len());
+        for family in families.iter_mut() {
+            let mut samples = Vec::with_capacity(family.series.len());
+            family.series.retain(|series| match series.source.value() {
+                Some(value) => {
+                    samples.push(Sample {
+                        labels: series.labels.clone(),
+                        value,
+                    });
+                    true
+                }
+                None => false,
+            });
+            if samples.is_empty() {
+                continue;
+            }
+            samples.sort_by(|a, b| a.labels.cmp(&b.labels));
+            gathered.push(MetricFamily {
+                name: family.name,
+                help: family.help,
+                kind: family.kind,
+                samples,
+            });
+        }
+        families.retain(|family| !family.series.is_empty());
+        gathered.sort_by_key(|family| family.name);
+        Snapshot { families: gathered }
+    }
+}
+
+fn to_labels(labels: &[(&'static str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(name, value)| (*name, value.to_string()))
+        .collect()
+}
+
+/// Value of a series, with its labels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// Name and value of each label.
+    pub labels: Vec<(&'static str, String)>,
+    /// Value of the series.
+    pub value: Value,
+}
+
+/// Family of metrics when gathered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricFamily {
+    /// Name of the metrics.
+    pub name: &'static str,
+    /// What the metrics measure.
+    pub help: &'static str,
+    /// Kind of the metrics.
+    pub kind: Kind,
+    /// Value of each series.
+    pub samples: Vec<Sample>,
+}
+
+/// Metrics of a registry, read by [`Registry::gather`].
+///
+/// Displayed in the Prometheus text exposition format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    families: Vec<MetricFamily>,
+}
+
+impl Snapshot {
+    /// Returns the families, sorted by name.
+    pub fn families(&self) -> &[MetricFamily] {
+        &self.families
+    }
+
+    /// Returns the value of the series `name` with exactly `labels`, in any order.
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> Option<&Value> {
+        let family = self.families.iter().find(|family| family.name == name)?;
+        family
+            .samples
+            .iter()
+            .find(|sample| {
+                sample.labels.len() == labels.len()
+                    && labels.iter().all(|(name, value)| {
+                        sample.labels.iter().any(|(n, v)| n == name && v == value)
+                    })
+            })
+            .map(|sample| &sample.value)
+    }
+}
+
+/// Writes `labels`, and `extra` after them, in braces unless there are none.
+fn write_labels(
+    f: &mut fmt::Formatter<'_>,
+    labels: &[(&'static str, String)],
+    extra: Option<(&str, &str)>,
+) -> fmt::Result {
+    let mut labels = labels
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .chain(extra)
+        .peekable();
+    if labels.peek().is_none() {
+        return Ok(());
+    }
+    write!(f, "{{")?;
+    for (i, (name, value)) in labels.enumerate() {
+        if i > 0 {
+            write!(f, ",")?;
+        }
+        let value = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        write!(f, "{name}=\"{value}\"")?;
+    }
+    write!(f, "}}")
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for family in &self.families {
+            let name = family.name;
+            writeln!(f, "# HELP {name} {}", family.help)?;
+            writeln!(f, "# TYPE {name} {}", family.kind.as_str())?;
+            for sample in &family.samples {
+                match &sample.value {
+                    Value::Counter(value) => {
+                        write!(f, "{name}")?;
+                        write_labels(f, &sample.labels, None)?;
+                        writeln!(f, " {value}")?;
+                    }
+                    Value::Gauge(value) => {
+                        write!(f, "{name}")?;
+                        write_labels(f, &sample.labels, None)?;
+                        writeln!(f, " {value}")?;
+                    }
+                    Value::Histogram(histogram) => {
+                        for (bound, count) in &histogram.buckets {
+                            let bound = if bound.is_infinite() {
+                                "+Inf".to_string()
+                            } else {
+                                bound.to_string()
+                            };
+                            write!(f, "{name}_bucket")?;
+                            write_labels(f, &sample.labels, Some(("le", &bound)))?;
+                            writeln!(f, " {count}")?;
+                        }
+                        write!(f, "{name}_sum")?;
+                        write_labels(f, &sample.labels, None)?;
+                        writeln!(f, " {}", histogram.sum)?;
+                        write!(f, "{name}_count")?;
+                        write_labels(f, &sample.labels, None)?;
+                        writeln!(f, " {}", histogram.count)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! Intrusive multi-producer single-consumer queue.
+//!
+//! The values are linked into the queue through a [`Link`] embedded in them, so that sending a
+//! value does not allocate. A value is sent as an `Arc`, which the queue keeps until the consumer
+//! pops it, and which the sender may send again afterwards, e.g., a preallocated message or the
+//! task of an executor that is woken up over and over.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ptr;
+use std::sync::Arc;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+use crate::{Backoff, CachePadded};
+
+/// Link embedded in the values sent through a queue.
+#[derive(Debug)]
+pub struct Link {
+    next: AtomicPtr<Link>,
+    /// Whether the value is in a queue, so that it is not linked twice.
+    queued: AtomicBool,
+    /// The value that embeds this link, as returned by `Arc::into_raw`, while it is in a queue.
+    owner: AtomicPtr<()>,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Link {
+    /// Creates a link that is not in a queue.
+    pub fn new() -> Self {
+        Self {
+            next: AtomicPtr::new(ptr::null_mut()),
+            queued: AtomicBool::new(false),
+            owner: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Returns `true` if the value is in a queue, i.e., it is sent and not yet popped.
+    pub fn is_queued(&self) -> bool {
+        self.queued.load(Ordering::Acquire)
+    }
+}
+
+/// Values that embed a [`Link`], so that they can be sent through a queue.
+///
+/// # Safety
+///
+/// `link` returns the same link each time, which lives as long as `self` and is not used by any
+/// other value.
+pub unsafe trait Linked {
+    /// Returns the link embedded in `self`.
+    fn link(&self) -> &Link;
+}
+
+/// Queue shared by the senders and the receiver.
+///
+/// Vyukov's intrusive queue: the senders swap themselves into `head` and then link the previous
+/// head to themselves, and the receiver pops from `tail`. The queue always holds a link, so that
+/// the receiver never pops the last value while a sender links to it: when `tail` is the only
+/// value left, the receiver first pushes `stub` behind it.
+///
+/// A sender that has swapped `head` but not yet linked the previous head hides its value and
+/// those sent after it until it does, meanwhile the receiver waits for it.
+struct Inner<T> {
+    head: CachePadded<AtomicPtr<Link>>,
+    /// Only accessed by the receiver.
+    tail: CachePadded<AtomicPtr<Link>>,
+    stub: Box<Link>,
+    _marker: PhantomData<Arc<T>>,
+}
+
+impl<T> Inner<T> {
+    fn stub(&self) -> *mut Link {
+        &*self.stub as *const Link as *mut Link
+    }
+
+    /// Links `link` behind the head.
+    fn push_link(&self, link: *mut Link) {
+        // SAFETY: `link` is the stub or the link of a value that the queue keeps alive.
+        unsafe { (*link).next.store(ptr::null_mut(), Ordering::Relaxed) };
+        let prev = self.head.swap(link, Ordering::AcqRel);
+        // SAFETY: The receiver does not pop `prev` until its `next` is set below, or another link
+        // is swapped into `head`, which is not before this swap.
+        unsafe { (*prev).next.store(link, Ordering::Release) };
+    }
+
+    /// Pops the value at the tail. Returns `None` if the queue is empty, and waits for the senders
+    /// that are linking their values.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread pops at a time.
+    unsafe fn pop(&self) -> Option<Arc<T>> {
+        let stub = self.stub();
+        let backoff = Backoff::new();
+        loop {
+            let tail = self.tail.load(Ordering::Relaxed);
+            // SAFETY: The links from `tail` on are not popped.
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+            if tail == stub {
+                if !next.is_null() {
+                    self.tail.store(next, Ordering::Relaxed);
+                } else if self.head.load(Ordering::Acquire) == stub {
+                    return None;
+                } else {
+                    // A sender swapped itself in after the stub, and is linking the stub to itself.
+                    backoff.snooze();
+                }
+                continue;
+            }
+
+            if !next.is_null() {
+                self.tail.store(next, Ordering::Relaxed);
+                // SAFETY: `tail` is not the stub and is now popped.
+                return Some(unsafe { Self::take(tail) });
+            }
+            if self.head.load(Ordering::Acquire) != tail {
+                // A sender swapped itself in after `tail`, and is linking `tail` to itself.
+                backoff.snooze();
+                continue;
+            }
+            // `tail` is the last value. The next iteration pops it once the stub, or a value sent
+            // meanwhile, is linked behind it.
+            self.push_link(stub);
+        }
+    }
+
+    /// Returns the value of `link`, which is popped, and lets it be sent again.
+    ///
+    /// # Safety
+    ///
+    /// `link` is the link of a value that was sent, and is popped.
+    unsafe fn take(link: *mut Link) -> Arc<T> {
+        // SAFETY: The value is kept alive until it is taken.
+        let link = unsafe { &*link };
+        let owner = link.owner.load(Ordering::Relaxed);
+        link.queued.store(false, Ordering::Release);
+        // SAFETY: The sender put the value in the queue with `Arc::into_raw`.
+        unsafe { Arc::from_raw(owner as *const T) }
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        // SAFETY: No sender is linking its value, and no other thread pops.
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
+
+/// Creates an empty queue, returning its sending and receiving halves.
+pub fn queue<T: Linked>() -> (Sender<T>, Receiver<T>) {
+    let stub = Box::new(Link::new());
+    let stub_ptr = &*stub as *const Link as *mut Link;
+    let inner = Arc::new(Inner {
+        head: CachePadded::new(AtomicPtr::new(stub_ptr)),
+        tail: CachePadded::new(AtomicPtr::new(stub_ptr)),
+        stub,
+        _marker: PhantomData,
+    });
+    let sender = Sender {
+        inner: inner.clone(),
+    };
+    (sender, Receiver { inner })
+}
+
+/// Sending half of the queue, which can be cloned for each sender.
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T: Linked> Sender<T> {
+    /// Sends `value` without allocating. Returns it back if it is already in a queue.
+    ///
+    /// Lock-free, except that the receiver waits for the value to be linked.
+    pub fn push(&self, value: Arc<T>) -> Result<(), Arc<T>> {
+        if value.link().queued.swap(true, Ordering::Acquire) {
+            return Err(value);
+        }
+        let owner = Arc::into_raw(value);
+        // SAFETY: The queue keeps the value alive until it is popped.
+        let link = unsafe { (*owner).link() };
+        link.owner.store(owner as *mut (), Ordering::Relaxed);
+        self.inner.push_link(link as *const Link as *mut Link);
+        Ok(())
+    }
+}
+
+/// Receiving half of the queue.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Pops the value sent first. Returns `None` if the queue is empty.
+    ///
+    /// Waits for the senders that are linking their values, so that a value sent before is popped
+    /// even if a sender is preempted while sending another one.
+    pub fn pop(&mut self) -> Option<Arc<T>> {
+        // SAFETY: The receiver is not shared, and `pop` takes it mutably.
+        unsafe { self.inner.pop() }
+    }
+
+    /// Returns `true` if no value is in the queue. A value being linked by its sender counts as in
+    /// the queue.
+    pub fn is_empty(&self) -> bool {
+        let stub = self.inner.stub();
+        self.inner.tail.load(Ordering::Relaxed) == stub
+            && self.inner.head.load(Ordering::Acquire) == stub
+    }
+
+    /// Pops the values until the queue is empty, in the order they are sent.
+    pub fn drain(&mut self) -> impl Iterator<Item = Arc<T>> + '_ {
+        core::iter::from_fn(move || self.pop())
+    }
+}
@@ -0,0 +1,335 @@
+//! NUMA-aware allocation, with the `numa` feature on Linux.
+//!
+//! On a machine with several NUMA nodes, memory is attached to one of them, and the cores of the
+//! other nodes reach it over the interconnect. Data touched mostly by one thread, such as its
+//! hazard slot or the buffer of its work-stealing deque, is best allocated on the node of that
+//! thread.
+//!
+//! Each thread is assigned a node the first time it asks for one: the node of the CPU it runs on
+//! then, or the one it sets with [`set_current_node`], e.g., after pinning itself to a CPU. The
+//! arena of each node allocates from chunks bound to the node with `mbind`, and keeps the freed
+//! blocks for the allocations of the same layout. The chunks are never returned to the system.
+
+use core::alloc::Layout;
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use once_cell::sync::Lazy;
+use std::alloc::handle_alloc_error;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+
+/// Length of the chunks, unless an allocation needs a larger one.
+const CHUNK: usize = 1 << 20;
+
+/// Memory policy that prefers the given node, falling back to the others when it is full.
+const MPOL_PREFERRED: libc::c_int = 1;
+
+/// The arena of each node.
+static ARENAS: Lazy<Box<[Arena]>> =
+    Lazy::new(|| (0..node_count()).map(|_| Arena::default()).collect());
+
+thread_local! {
+    /// The node of the current thread, once assigned.
+    static NODE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Returns the number of the NUMA nodes, which is 1 if they cannot be determined.
+pub fn node_count() -> usize {
+    static COUNT: Lazy<usize> = Lazy::new(|| {
+        let nodes = fs::read_dir("/sys/devices/system/node").map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| {
+                    let name = entry.file_name();
+                    let name = name.to_string_lossy();
+                    matches!(name.strip_prefix("node"), Some(id) if id.parse::<usize>().is_ok())
+                })
+                .count()
+        });
+        nodes.unwrap_or(0).max(1)
+    });
+    *COUNT
+}
+
+/// Returns the node of the current thread, which is the node of the CPU it runs on the first
+/// time it is called, unless set with [`set_current_node`].
+pub fn current_node() -> usize {
+    NODE.with(|node| match node.get() {
+        Some(node) => node,
+        None => {
+            let current = cpu_node().min(node_count() - 1);
+            node.set(Some(current));
+            current
+        }
+    })
+}
+
+/// Sets the node of the current thread, on which its later allocations are. Panics if there is
+/// no such node.
+pub fn set_current_node(node: usize) {
+    assert!(node < node_count(), "no NUMA node {node}");
+    NODE.with(|current| current.set(Some(node)));
+}
+
+/// Returns the node of the CPU the current thread runs on, or 0 if it cannot be determined.
+fn cpu_node() -> usize {
+    let mut cpu: libc::c_uint = 0;
+    let mut node: libc::c_uint = 0;
+    // SAFETY: `getcpu` writes the CPU and the node to the given pointers, and ignores the last.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_getcpu,
+            &mut cpu as *mut libc::c_uint,
+            &mut node as *mut libc::c_uint,
+            ptr::null_mut::<libc::c_void>(),
+        )
+    };
+    if result == 0 {
+        node as usize
+    } else {
+        0
+    }
+}
+
+/// Allocator of the memory on a node.
+#[derive(Debug, Default)]
+struct Arena {
+    inner: Mutex<ArenaInner>,
+}
+
+#[derive(Debug, Default)]
+struct ArenaInner {
+    /// The rest of the last chunk, as its address and length.
+    free: usize,
+    len: usize,
+    /// The chunks, as their addresses and lengths, to tell the node of a block.
+    chunks: Vec<(usize, usize)>,
+    /// The freed blocks of each layout.
+    blocks: HashMap<Layout, Vec<usize>>,
+}
+
+impl Arena {
+    /// Allocates a block of `layout`, of nonzero size, on `node`.
+    fn alloc(&self, node: usize, layout: Layout) -> NonNull<u8> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(block) = inner.blocks.get_mut(&layout).and_then(Vec::pop) {
+            // SAFETY: The freed blocks are not null.
+            return unsafe { NonNull::new_unchecked(block as *mut u8) };
+        }
+
+        let mut start = (inner.free + layout.align() - 1) & !(layout.align() - 1);
+        if inner.len == 0 || start + layout.size() > inner.free + inner.len {
+            let len = CHUNK.max(layout.size() + layout.align());
+            let chunk = map(node, len).unwrap_or_else(|| handle_alloc_error(layout));
+            inner.chunks.push((chunk, len));
+            inner.free = chunk;
+            inner.len = len;
+            start = (chunk + layout.align() - 1) & !(layout.align() - 1);
+        }
+        let end = start + layout.size();
+        inner.len -= end - inner.free;
+        inner.free = end;
+        // SAFETY: The chunks are not null.
+        unsafe { NonNull::new_unchecked(start as *mut u8) }
+    }
+
+    /// Returns whether `block` is in a chunk of this arena.
+    fn owns(&self, block: usize) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .chunks
+            .iter()
+            .any(|&(chunk, len)| (chunk..chunk + len).contains(&block))
+    }
+
+    /// Keeps `block` of `layout` for a later allocation.
+    fn dealloc(&self, block: usize, layout: Layout) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.blocks.entry(layout).or_default().push(block);
+    }
+}
+
+/// Maps `len` bytes of memory that prefers `node`, and returns its address.
+fn map(node: usize, len: usize) -> Option<usize> {
+    // SAFETY: An anonymous private mapping does not alias any memory.
+    let addr = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    if addr == libc::MAP_FAILED {
+        return None;
+    }
+
+    let bits = libc::c_ulong::BITS as usize;
+    let mut mask = vec![0 as libc::c_ulong; node / bits + 1];
+    mask[node / bits] |= 1 << (node % bits);
+    // SAFETY: The range is the mapping above, and `mask` has `mask.len() * bits` bits, one fewer
+    // than passed as the kernel expects. The pages are not touched yet, so they are allocated on
+    // the node when they are. If it fails, e.g., without NUMA support in the kernel, the memory
+    // is allocated as usual.
+    let _ = unsafe {
+        libc::syscall(
+            libc::SYS_mbind,
+            addr,
+            len,
+            MPOL_PREFERRED,
+            mask.as_ptr(),
+            mask.len() * bits + 1,
+            0 as libc::c_uint,
+        )
+    };
+    Some(addr as usize)
+}
+
+/// Allocates a block of `layout` on `node`.
+fn alloc(node: usize, layout: Layout) -> NonNull<u8> {
+    if layout.size() == 0 {
+        // SAFETY: The alignment is not zero.
+        return unsafe { NonNull::new_unchecked(layout.align() as *mut u8) };
+    }
+    ARENAS[node].alloc(node, layout)
+}
+
+/// Frees `block` of `layout`, allocated with `alloc`.
+fn dealloc(block: NonNull<u8>, layout: Layout) {
+    if layout.size() == 0 {
+        return;
+    }
+    let block = block.as_ptr() as usize;
+    let node = node_of(block).expect("the block is not allocated by an arena");
+    ARENAS[node].dealloc(block, layout);
+}
+
+/// Returns the node of the block at `addr`, allocated with `alloc`.
+fn node_of(addr: usize) -> Option<usize> {
+    ARENAS.iter().position(|arena| arena.owns(addr))
+}
+
+/// Box whose value is allocated on a NUMA node.
+pub struct NodeBox<T: ?Sized> {
+    ptr: NonNull<T>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for NodeBox<T> {}
+unsafe impl<T: ?Sized + Sync> Sync for NodeBox<T> {}
+
+impl<T> NodeBox<T> {
+    /// Allocates `value` on the node of the current thread.
+    pub fn new(value: T) -> Self {
+        Self::new_on(current_node(), value)
+    }
+
+    /// Allocates `value` on `node`. Panics if there is no such node.
+    pub fn new_on(node: usize, value: T) -> Self {
+        assert!(node < node_count(), "no NUMA node {node}");
+        let ptr = alloc(node, Layout::new::<T>()).cast::<T>();
+        // SAFETY: `ptr` is allocated for a `T`.
+        unsafe { ptr.as_ptr().write(value) };
+        Self {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> NodeBox<[T]> {
+    /// Allocates a slice of `len` values on the node of the current thread, where the `i`-th
+    /// value is `f(i)`.
+    pub fn from_fn<F: FnMut(usize) -> T>(len: usize, mut f: F) -> Self {
+        let layout = Layout::array::<T>(len).expect("the slice is too long");
+        let data = alloc(current_node(), layout).cast::<T>();
+        for i in 0..len {
+            // SAFETY: `data` is allocated for `len` values. If `f` panics, the block and the
+            // values written so far are leaked.
+            unsafe { data.as_ptr().add(i).write(f(i)) };
+        }
+        let slice = ptr::slice_from_raw_parts_mut(data.as_ptr(), len);
+        Self {
+            // SAFETY: `data` is not null.
+            ptr: unsafe { NonNull::new_unchecked(slice) },
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> FromIterator<T> for NodeBox<[T]> {
+    /// Collects the values on the node of the current thread.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let values = iter.into_iter().collect::<Vec<_>>();
+        let len = values.len();
+        let mut values = values.into_iter();
+        Self::from_fn(len, |_| values.next().unwrap())
+    }
+}
+
+impl<T: ?Sized> NodeBox<T> {
+    /// Returns the node of the value.
+    pub fn node(this: &Self) -> usize {
+        // A zero-sized value is not in any arena.
+        node_of(this.ptr.as_ptr() as *mut u8 as usize).unwrap_or(0)
+    }
+
+    /// Consumes the box, and returns the pointer to the value, which is freed by
+    /// [`NodeBox::from_raw`].
+    pub fn into_raw(this: Self) -> *mut T {
+        let ptr = this.ptr.as_ptr();
+        core::mem::forget(this);
+        ptr
+    }
+
+    /// Takes the ownership of the value from a pointer returned by [`NodeBox::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be returned by `into_raw`, and not taken by another `from_raw`.
+    pub unsafe fn from_raw(ptr: *mut T) -> Self {
+        Self {
+            ptr: NonNull::new_unchecked(ptr),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for NodeBox<T> {
+    fn drop(&mut self) {
+        // SAFETY: The value is owned by the box, and the layout is the one it is allocated with.
+        unsafe {
+            let layout = Layout::for_value(self.ptr.as_ref());
+            ptr::drop_in_place(self.ptr.as_ptr());
+            dealloc(self.ptr.cast(), layout);
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for NodeBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: The value is owned by the box.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for NodeBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: The value is owned by the box.
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for NodeBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
@@ -0,0 +1,234 @@
+//! Channel for sending a single value.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::{ManuallyDrop, MaybeUninit};
+use core::ptr;
+use std::error::Error;
+use std::sync::Arc;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::parker::{Parker, Unparker};
+
+/// No value is sent yet.
+const EMPTY: usize = 0;
+/// No value is sent yet, and the receiver is parked on the parker of `waiter`.
+const WAITING: usize = 1;
+/// The value is sent and not received.
+const SENT: usize = 2;
+/// The other half is dropped, or the value is received.
+const CLOSED: usize = 3;
+
+/// State shared by the sender and the receiver.
+struct Inner<T> {
+    state: AtomicUsize,
+    /// Written by the sender before `SENT`, and read by the receiver after it.
+    value: UnsafeCell<MaybeUninit<T>>,
+    /// Written by the receiver before `WAITING`, and read by the sender after it.
+    waiter: UnsafeCell<Option<Unparker>>,
+}
+
+impl<T> Inner<T> {
+    /// Takes the value out.
+    ///
+    /// # Safety
+    ///
+    /// The state must have been `SENT`, as seen by the caller, who then owns the value.
+    unsafe fn take(&self) -> T {
+        (*self.value.get()).assume_init_read()
+    }
+
+    /// Unparks the receiver, after the state is changed from `WAITING` by the caller.
+    fn wake(&self) {
+        // SAFETY: The receiver wrote the waiter before `WAITING`, and does not write it again.
+        if let Some(waiter) = unsafe { &*self.waiter.get() } {
+            waiter.unpark();
+        }
+    }
+}
+
+/// Creates a channel for sending a single value, returning its sender and receiver halves.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        state: AtomicUsize::new(EMPTY),
+        value: UnsafeCell::new(MaybeUninit::uninit()),
+        waiter: UnsafeCell::new(None),
+    });
+    (
+        Sender {
+            inner: inner.clone(),
+        },
+        Receiver { inner },
+    )
+}
+
+/// Sending half of a oneshot channel. Dropping it without sending disconnects the receiver.
+pub struct Sender<T> {
+    inner: Arc<Inner<T>>,
+}
+
+/// Receiving half of a oneshot channel.
+pub struct Receiver<T> {
+    inner: Arc<Inner<T>>,
+}
+
+unsafe impl<T: Send> Send for Sender<T> {}
+unsafe impl<T: Send> Send for Receiver<T> {}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+/// Error returned by [`Receiver::recv`] when the sender is dropped without sending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("receiving on a closed oneshot channel")
+    }
+}
+
+impl Error for RecvError {}
+
+/// Error returned by [`Receiver::try_recv`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    /// The value is not sent yet.
+    Empty,
+    /// The sender is dropped without sending, or the value is already received.
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty oneshot channel"),
+            TryRecvError::Disconnected => f.write_str("receiving on a closed oneshot channel"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+impl<T> Sender<T> {
+    /// Sends `value` without blocking. Returns it back if the receiver is dropped.
+    pub fn send(self, value: T) -> Result<(), T> {
+        // The drop of `self` would close the channel.
+        let this = ManuallyDrop::new(self);
+        // SAFETY: `this` is not used or dropped afterwards.
+        let inner = unsafe { ptr::read(&this.inner) };
+
+        // SAFETY: Only the sender writes the value, and the receiver does not read it before
+        // `SENT`.
+        unsafe { (*inner.value.get()).write(value) };
+        let mut state = inner.state.load(Ordering::Relaxed);
+        loop {
+            if state == CLOSED {
+                // SAFETY: The receiver is dropped, so the value is still the sender's.
+                return Err(unsafe { inner.take() });
+            }
+            // Release: the value is written before it is received. Acquire: the waiter is written
+            // before it is unparked.
+            match inner
+                .state
+                .compare_exchange(state, SENT, Ordering::AcqRel, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => state = current,
+            }
+        }
+        if state == WAITING {
+            inner.wake();
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the receiver is dropped, so that a value sent would be returned back.
+    pub fn is_abandoned(&self) -> bool {
+        self.inner.state.load(Ordering::Relaxed) == CLOSED
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Acquire: the waiter is written before it is unparked.
+        if self.inner.state.swap(CLOSED, Ordering::Acquire) == WAITING {
+            self.inner.wake();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives the value if the state is `SENT` or `CLOSED`.
+    fn receive(&self, state: usize) -> Result<T, TryRecvError> {
+        match state {
+            SENT => {
+                // The value is taken, so that it is not dropped again.
+                self.inner.state.store(CLOSED, Ordering::Relaxed);
+                // SAFETY: The state was `SENT`, and the sender is gone.
+                Ok(unsafe { self.inner.take() })
+            }
+            CLOSED => Err(TryRecvError::Disconnected),
+            _ => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Receives the value without blocking.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        // Acquire: the value is written before it is received.
+        self.receive(self.inner.state.load(Ordering::Acquire))
+    }
+
+    /// Blocks until the value is sent, and receives it. Returns an error if the sender is dropped
+    /// without sending.
+    pub fn recv(self) -> Result<T, RecvError> {
+        let mut state = self.inner.state.load(Ordering::Acquire);
+        if state == EMPTY {
+            let parker = Parker::new();
+            // SAFETY: The sender does not read the waiter before `WAITING`.
+            unsafe { *self.inner.waiter.get() = Some(parker.unparker().clone()) };
+            // Release: the waiter is written before it is unparked. Acquire: the value is written
+            // before it is received.
+            state = match self.inner.state.compare_exchange(
+                EMPTY,
+                WAITING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => WAITING,
+                Err(current) => current,
+            };
+            // Parking may wake up spuriously.
+            while state == WAITING {
+                parker.park();
+                state = self.inner.state.load(Ordering::Acquire);
+            }
+        }
+        self.receive(state).map_err(|_| RecvError)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // Acquire: the value is written before it is dropped.
+        if self.inner.state.swap(CLOSED, Ordering::Acquire) == SENT {
+            // SAFETY: The state was `SENT`, and the sender is gone.
+            drop(unsafe { self.inner.take() });
+        }
+    }
+}
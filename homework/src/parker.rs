@@ -0,0 +1,441 @@
+//! Thread parker on a futex, and a list of parked waiters built on it.
+
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::Mutex;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::Mutex;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::Mutex;
+
+/// Blocks the thread that owns it until its [`Unparker`] is called, like `std::thread::park` but
+/// without being tied to a thread handle.
+///
+/// An `unpark` that comes before `park` is not lost: the parker keeps a single token, which
+/// `unpark` sets and `park` consumes. `park` may also return spuriously, so the caller checks its
+/// condition again after it returns.
+///
+/// On Linux, the parker sleeps on a futex, so that parking and unparking take a system call only
+/// if the other side is actually asleep. Elsewhere, and with loom, which does not model futexes,
+/// it falls back to a mutex and a condition variable. Loom does not time out the waits, so a
+/// timed `park` returns only when unparked.
+pub struct Parker {
+    unparker: Unparker,
+    /// Only the owning thread parks, which the implementation relies on.
+    _marker: PhantomData<Cell<()>>,
+}
+
+/// Wakes up the thread blocked in [`Parker::park`], or makes its next `park` return at once.
+#[derive(Clone)]
+pub struct Unparker {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for Parker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Parker").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for Unparker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Unparker").finish_non_exhaustive()
+    }
+}
+
+impl Default for Parker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parker {
+    /// Creates a parker without the token.
+    pub fn new() -> Self {
+        Self {
+            unparker: Unparker {
+                inner: Arc::new(Inner::new()),
+            },
+            _marker: PhantomData,
+        }
+    }
+
+    /// Blocks until the token is set, and consumes it.
+    pub fn park(&self) {
+        self.unparker.inner.park(None);
+    }
+
+    /// Blocks until the token is set for at most `timeout`, and consumes it if set.
+    pub fn park_timeout(&self, timeout: Duration) {
+        self.unparker.inner.park(Some(timeout));
+    }
+
+    /// Blocks until the token is set, or until `deadline`, and consumes it if set.
+    pub fn park_deadline(&self, deadline: Instant) {
+        self.park_timeout(deadline.saturating_duration_since(Instant::now()));
+    }
+
+    /// Returns the unparker of this parker, which may be cloned and sent to other threads.
+    pub fn unparker(&self) -> &Unparker {
+        &self.unparker
+    }
+}
+
+impl Unparker {
+    /// Sets the token, waking up the parked thread if any.
+    pub fn unpark(&self) {
+        self.inner.unpark();
+    }
+
+    /// Returns `true` if `self` and `other` unpark the same parker.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+/// The token is not set, and the owner is not parked.
+const EMPTY: u32 = 0;
+/// The token is set.
+const NOTIFIED: u32 = 1;
+/// The owner is parked or about to park.
+const PARKED: u32 = u32::MAX;
+
+#[cfg(any(
+    not(target_os = "linux"),
+    feature = "check-loom",
+    feature = "check-sched"
+))]
+use fallback::Inner;
+#[cfg(all(
+    target_os = "linux",
+    not(any(feature = "check-loom", feature = "check-sched"))
+))]
+use futex::Inner;
+
+#[cfg(all(
+    target_os = "linux",
+    not(any(feature = "check-loom", feature = "check-sched"))
+))]
+mod futex {
+    use core::sync::atomic::{AtomicU32, Ordering};
+    use core::{ptr, time::Duration};
+
+    use super::{EMPTY, NOTIFIED, PARKED};
+
+    /// Blocks while `futex` holds `expected`, for at most `timeout` if any. May return spuriously.
+    fn wait(futex: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+        let timespec = timeout.map(|timeout| libc::timespec {
+            tv_sec: timeout.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+            tv_nsec: timeout.subsec_nanos() as _,
+        });
+        let timespec = timespec
+            .as_ref()
+            .map_or(ptr::null(), |timespec| timespec as *const libc::timespec);
+        // SAFETY: `futex` points to a live `u32`, and `timespec` is null or points to a live
+        // `timespec`. The error on a timeout, on a signal, or on `futex` not holding `expected` is
+        // just a spurious wakeup for the caller.
+        let _ = unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                futex as *const AtomicU32,
+                libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+                expected,
+                timespec,
+            )
+        };
+    }
+
+    /// Wakes up a thread blocked in `wait` on `futex`, if any.
+    fn wake_one(futex: &AtomicU32) {
+        // SAFETY: `futex` points to a live `u32`.
+        let _ = unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                futex as *const AtomicU32,
+                libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+                1,
+            )
+        };
+    }
+
+    pub(super) struct Inner {
+        /// `EMPTY`, `NOTIFIED` or `PARKED`, which is also the futex.
+        state: AtomicU32,
+    }
+
+    impl Inner {
+        pub(super) fn new() -> Self {
+            Self {
+                state: AtomicU32::new(EMPTY),
+            }
+        }
+
+        pub(super) fn park(&self, timeout: Option<Duration>) {
+            // Consumes the token, or goes from `EMPTY` to `PARKED`, as `PARKED` is `EMPTY - 1`.
+            // Acquire: the work before `unpark` happens before `park` returns. Pairs with the
+            // Release in `unpark`.
+            if self.state.fetch_sub(1, Ordering::Acquire) == NOTIFIED {
+                return;
+            }
+            match timeout {
+                None => loop {
+                    wait(&self.state, PARKED, None);
+                    // Acquire: pairs with the Release in `unpark`.
+                    if self
+                        .state
+                        .compare_exchange(NOTIFIED, EMPTY, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok()
+                    {
+                        return;
+                    }
+                },
+                Some(timeout) => {
+                    wait(&self.state, PARKED, Some(timeout));
+                    // Consumes the token if set, whether woken up or timed out. Acquire: pairs
+                    // with the Release in `unpark`.
+                    let _ = self.state.swap(EMPTY, Ordering::Acquire);
+                }
+            }
+        }
+
+        pub(super) fn unpark(&self) {
+            // Release: pairs with the Acquire in `park`.
+            if self.state.swap(NOTIFIED, Ordering::Release) == PARKED {
+                wake_one(&self.state);
+            }
+        }
+    }
+}
+
+#[cfg(any(
+    not(target_os = "linux"),
+    feature = "check-loom",
+    feature = "check-sched"
+))]
+mod fallback {
+    use core::time::Duration;
+
+    #[cfg(feature = "check-sched")]
+    use crate::sched::sync::atomic::{AtomicU32, Ordering};
+    #[cfg(feature = "check-sched")]
+    use crate::sched::sync::{Condvar, Mutex};
+    #[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+    use core::sync::atomic::{AtomicU32, Ordering};
+    #[cfg(feature = "check-loom")]
+    use loom::sync::atomic::{AtomicU32, Ordering};
+    #[cfg(feature = "check-loom")]
+    use loom::sync::{Condvar, Mutex};
+    #[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+    use std::sync::{Condvar, Mutex};
+
+    use super::{EMPTY, NOTIFIED, PARKED};
+
+    pub(super) struct Inner {
+        /// `EMPTY`, `NOTIFIED` or `PARKED`.
+        state: AtomicU32,
+        /// Held by the owner from going `PARKED` until it waits, so that `unpark` does not notify
+        /// in between.
+        lock: Mutex<()>,
+        condvar: Condvar,
+    }
+
+    impl Inner {
+        pub(super) fn new() -> Self {
+            Self {
+                state: AtomicU32::new(EMPTY),
+                lock: Mutex::new(()),
+                condvar: Condvar::new(),
+            }
+        }
+
+        pub(super) fn park(&self, timeout: Option<Duration>) {
+            // Acquire: the work before `unpark` happens before `park` returns. Pairs with the
+            // Release in `unpark`.
+            if self
+                .state
+                .compare_exchange(NOTIFIED, EMPTY, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+
+            let mut guard = self.lock.lock().unwrap();
+            if let Err(state) =
+                self.state
+                    .compare_exchange(EMPTY, PARKED, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                // Only `unpark` changed the state since the check above.
+                debug_assert_eq!(state, NOTIFIED);
+                // Acquire: pairs with the Release in `unpark`.
+                let _ = self.state.swap(EMPTY, Ordering::Acquire);
+                return;
+            }
+            match timeout {
+                None => loop {
+                    guard = self.condvar.wait(guard).unwrap();
+                    // Acquire: pairs with the Release in `unpark`.
+                    if self
+                        .state
+                        .compare_exchange(NOTIFIED, EMPTY, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok()
+                    {
+                        return;
+                    }
+                },
+                Some(timeout) => {
+                    drop(self.condvar.wait_timeout(guard, timeout).unwrap());
+                    // Acquire: pairs with the Release in `unpark`.
+                    let _ = self.state.swap(EMPTY, Ordering::Acquire);
+                }
+            }
+        }
+
+        pub(super) fn unpark(&self) {
+            // Release: pairs with the Acquire in `park`.
+            if self.state.swap(NOTIFIED, Ordering::Release) == PARKED {
+                // Waits until the owner waits, so that the notification is not lost.
+                drop(self.lock.lock().unwrap());
+                self.condvar.notify_one();
+            }
+        }
+    }
+}
+
+/// Threads parked until a condition holds, which are woken up by the threads that make it hold.
+///
+/// A waiter enqueues its unparker and then checks the condition once more before it parks, and a
+/// notifier changes the condition before it dequeues a waiter, so that either the waiter sees the
+/// change or the notifier sees the waiter. The notifiers check the number of waiters without the
+/// lock, so that they take it only if there is any.
+pub(crate) struct WaitList {
+    /// Number of the waiters in `waiters`.
+    len: AtomicUsize,
+    /// The waiters, oldest first.
+    waiters: Mutex<Vec<Unparker>>,
+}
+
+impl fmt::Debug for WaitList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WaitList")
+            .field("len", &self.len.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl WaitList {
+    #[cfg(not(feature = "check-loom"))]
+    pub(crate) const fn new() -> Self {
+        Self {
+            len: AtomicUsize::new(0),
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[cfg(feature = "check-loom")]
+    pub(crate) fn new() -> Self {
+        Self {
+            len: AtomicUsize::new(0),
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Calls `poll` until it returns `Some`, parking in between, and returns its result. Gives up
+    /// and returns `None` at `deadline`, if any.
+    ///
+    /// `poll` is called with no lock held, and may also consume what it waits for, e.g., take a
+    /// permit.
+    pub(crate) fn wait_until<R, F>(&self, deadline: Option<Instant>, mut poll: F) -> Option<R>
+    where
+        F: FnMut() -> Option<R>,
+    {
+        if let Some(result) = poll() {
+            return Some(result);
+        }
+
+        let parker = Parker::new();
+        loop {
+            if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+                return None;
+            }
+
+            {
+                let mut waiters = self.waiters.lock().unwrap();
+                waiters.push(parker.unparker().clone());
+                self.len.store(waiters.len(), Ordering::Relaxed);
+            }
+            // SeqCst: either `poll` sees the change made before a notification, or the notifier
+            // sees this waiter. Pairs with the fence in `notify`.
+            fence(Ordering::SeqCst);
+            if let Some(result) = poll() {
+                if !self.remove(parker.unparker()) {
+                    // The notification is for another waiter, as this one did not need it.
+                    self.notify_one();
+                }
+                return Some(result);
+            }
+
+            match deadline {
+                None => parker.park(),
+                Some(deadline) => parker.park_deadline(deadline),
+            }
+            // Woken up spuriously or timed out, unless a notifier took this waiter out.
+            let _ = self.remove(parker.unparker());
+            if let Some(result) = poll() {
+                return Some(result);
+            }
+        }
+    }
+
+    /// Takes `unparker` out of the list. Returns `false` if a notifier already took it out.
+    fn remove(&self, unparker: &Unparker) -> bool {
+        let mut waiters = self.waiters.lock().unwrap();
+        let index = some_or!(
+            waiters.iter().position(|waiter| waiter.ptr_eq(unparker)),
+            return false
+        );
+        let _ = waiters.remove(index);
+        self.len.store(waiters.len(), Ordering::Relaxed);
+        true
+    }
+
+    /// Wakes up at most `n` waiters, the ones that waited the longest.
+    fn notify(&self, n: usize) {
+        // SeqCst: pairs with the fence in `wait_until`.
+        fence(Ordering::SeqCst);
+        if self.len.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let woken = {
+            let mut waiters = self.waiters.lock().unwrap();
+            let n = n.min(waiters.len());
+            let woken = waiters.drain(..n).collect::<Vec<_>>();
+            self.len.store(waiters.len(), Ordering::Relaxed);
+            woken
+        };
+        for waiter in woken {
+            waiter.unpark();
+        }
+    }
+
+    /// Wakes up the waiter that waited the longest, if any, after the caller changed the
+    /// condition.
+    pub(crate) fn notify_one(&self) {
+        self.notify(1);
+    }
+
+    /// Wakes up all waiters, after the caller changed the condition.
+    pub(crate) fn notify_all(&self) {
+        self.notify(usize::MAX);
+    }
+}
@@ -0,0 +1,170 @@
+use core::fmt;
+use core::iter::FromIterator;
+
+use crate::Arc;
+
+struct Node<T> {
+    value: T,
+    next: List<T>,
+}
+
+/// Persistent singly linked list.
+///
+/// Pushing a value returns a new list whose tail is this one, and taking the tail returns the list
+/// below the front. Both take constant time, and neither changes the list they are called on, so
+/// the lists share their tails.
+pub struct List<T> {
+    head: Option<Arc<Node<T>>>,
+    len: usize,
+}
+
+impl<T> Clone for List<T> {
+    fn clone(&self) -> Self {
+        Self {
+            head: self.head.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T> List<T> {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        Self { head: None, len: 0 }
+    }
+
+    /// Returns the number of the values.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the front value.
+    pub fn front(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    /// Returns a list of `t` in front of the values of this list.
+    pub fn push_front(&self, t: T) -> Self {
+        Self {
+            head: Some(Arc::new(Node {
+                value: t,
+                next: self.clone(),
+            })),
+            len: self.len + 1,
+        }
+    }
+
+    /// Returns the list of the values after the front one, or `None` if the list is empty.
+    pub fn tail(&self) -> Option<Self> {
+        self.head.as_ref().map(|node| node.next.clone())
+    }
+
+    /// Returns `true` if the lists are the same version, i.e., share all their nodes.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.head, &other.head) {
+            (Some(this), Some(other)) => Arc::ptr_eq(this, other),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Iterates over the values, from the front.
+    pub fn iter(&self) -> ListIter<'_, T> {
+        ListIter {
+            next: self.head.as_deref(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        // Drops the nodes no other list shares one by one, rather than recursively, which would
+        // overflow the stack for long lists.
+        let mut head = self.head.take();
+        while let Some(node) = head {
+            head = match Arc::try_unwrap(node) {
+                Ok(mut node) => node.next.head.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    /// Creates a list of the values in the order of the iterator.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        // The list is built from its back, and `I` need not be double-ended.
+        #[allow(clippy::needless_collect)]
+        let values = iter.into_iter().collect::<Vec<_>>();
+        values
+            .into_iter()
+            .rev()
+            .fold(Self::new(), |list, value| list.push_front(value))
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = ListIter<'a, T>;
+
+    fn into_iter(self) -> ListIter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterator over the values of a [`List`].
+pub struct ListIter<'a, T> {
+    next: Option<&'a Node<T>>,
+    len: usize,
+}
+
+impl<T> fmt::Debug for ListIter<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ListIter")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, T> Iterator for ListIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.next?;
+        self.next = node.next.head.as_deref();
+        self.len -= 1;
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> ExactSizeIterator for ListIter<'_, T> {}
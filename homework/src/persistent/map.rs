@@ -0,0 +1,381 @@
+use core::borrow::Borrow;
+use core::fmt;
+use core::hash::Hash;
+use core::iter::FromIterator;
+use core::slice;
+
+use crate::utils::{hash_of, HashBuilder};
+use crate::Arc;
+
+/// Number of the bits of a hash that index the children of a branch.
+const W: u32 = 6;
+const MASK: u64 = (1 << W) - 1;
+
+enum Node<K, V> {
+    /// The children whose bits are set in `bitmap`, in the order of the bits.
+    Branch {
+        bitmap: u64,
+        children: Box<[Arc<Node<K, V>>]>,
+    },
+    /// The entries of the keys with the same hash, of which there is usually one.
+    Leaf { hash: u64, entries: Box<[(K, V)]> },
+}
+
+impl<K, V> Node<K, V> {
+    /// Returns the bit of `hash` at the level `shift`, and the index of its child in a branch.
+    fn flag_pos(bitmap: u64, hash: u64, shift: u32) -> (u64, usize) {
+        let flag = 1 << ((hash >> shift) & MASK);
+        (flag, (bitmap & (flag - 1)).count_ones() as usize)
+    }
+
+    fn get<Q>(&self, hash: u64, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let mut node = self;
+        let mut shift = 0;
+        loop {
+            match node {
+                Node::Branch { bitmap, children } => {
+                    let (flag, pos) = Self::flag_pos(*bitmap, hash, shift);
+                    if bitmap & flag == 0 {
+                        return None;
+                    }
+                    node = &children[pos];
+                    shift += W;
+                }
+                Node::Leaf {
+                    hash: leaf_hash,
+                    entries,
+                } => {
+                    if *leaf_hash != hash {
+                        return None;
+                    }
+                    return entries
+                        .iter()
+                        .find(|(k, _)| k.borrow() == key)
+                        .map(|(_, v)| v);
+                }
+            }
+        }
+    }
+}
+
+impl<K: Eq + Clone, V: Clone> Node<K, V> {
+    /// Returns a copy of `node` at the level `shift` with the entry inserted, and whether the key
+    /// is new. Only the nodes on the way down to the entry are copied.
+    fn insert(node: &Arc<Self>, shift: u32, hash: u64, key: K, value: V) -> (Self, bool) {
+        match &**node {
+            Node::Branch { bitmap, children } => {
+                let (flag, pos) = Self::flag_pos(*bitmap, hash, shift);
+                let mut children = children.to_vec();
+                let new = if bitmap & flag == 0 {
+                    children.insert(
+                        pos,
+                        Arc::new(Node::Leaf {
+                            hash,
+                            entries: Box::new([(key, value)]),
+                        }),
+                    );
+                    true
+                } else {
+                    let (child, new) = Self::insert(&children[pos], shift + W, hash, key, value);
+                    children[pos] = Arc::new(child);
+                    new
+                };
+                let branch = Node::Branch {
+                    bitmap: bitmap | flag,
+                    children: children.into_boxed_slice(),
+                };
+                (branch, new)
+            }
+            Node::Leaf {
+                hash: leaf_hash,
+                entries,
+            } if *leaf_hash == hash => {
+                let mut entries = entries.to_vec();
+                let new = match entries.iter_mut().find(|(k, _)| *k == key) {
+                    Some(entry) => {
+                        entry.1 = value;
+                        false
+                    }
+                    None => {
+                        entries.push((key, value));
+                        true
+                    }
+                };
+                let leaf = Node::Leaf {
+                    hash,
+                    entries: entries.into_boxed_slice(),
+                };
+                (leaf, new)
+            }
+            Node::Leaf {
+                hash: leaf_hash, ..
+            } => {
+                // Push the leaf down into a branch, where the hashes eventually differ.
+                let (flag, _) = Self::flag_pos(0, *leaf_hash, shift);
+                let branch = Arc::new(Node::Branch {
+                    bitmap: flag,
+                    children: Box::new([node.clone()]),
+                });
+                Self::insert(&branch, shift, hash, key, value)
+            }
+        }
+    }
+
+    /// Returns a copy of `node` at the level `shift` without the entry of `key`, or `None` in
+    /// place of the copy if it is empty. Returns `None` if there is no such entry.
+    fn remove<Q>(node: &Arc<Self>, shift: u32, hash: u64, key: &Q) -> Option<Option<Arc<Self>>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        match &**node {
+            Node::Branch { bitmap, children } => {
+                let (flag, pos) = Self::flag_pos(*bitmap, hash, shift);
+                if bitmap & flag == 0 {
+                    return None;
+                }
+                let child = Self::remove(&children[pos], shift + W, hash, key)?;
+                let mut children = children.to_vec();
+                let mut bitmap = *bitmap;
+                match child {
+                    Some(child) => children[pos] = child,
+                    None => {
+                        let _ = children.remove(pos);
+                        bitmap &= !flag;
+                    }
+                }
+                // A branch of a single leaf is replaced with the leaf, so that the trie is as
+                // shallow as if the entry had never been inserted.
+                match children.as_slice() {
+                    [] => Some(None),
+                    [child] if matches!(**child, Node::Leaf { .. }) => Some(Some(child.clone())),
+                    _ => Some(Some(Arc::new(Node::Branch {
+                        bitmap,
+                        children: children.into_boxed_slice(),
+                    }))),
+                }
+            }
+            Node::Leaf {
+                hash: leaf_hash,
+                entries,
+            } => {
+                if *leaf_hash != hash {
+                    return None;
+                }
+                let pos = entries.iter().position(|(k, _)| k.borrow() == key)?;
+                if entries.len() == 1 {
+                    return Some(None);
+                }
+                let mut entries = entries.to_vec();
+                let _ = entries.remove(pos);
+                Some(Some(Arc::new(Node::Leaf {
+                    hash,
+                    entries: entries.into_boxed_slice(),
+                })))
+            }
+        }
+    }
+}
+
+/// Persistent hash map, a hash array mapped trie.
+///
+/// Inserting or removing an entry returns a new map, which shares with this one all nodes but
+/// those on the way down to the entry, and leaves this one as it was. Both take time logarithmic
+/// in the number of the entries, and so does a lookup. The maps derived from one another hash the
+/// keys the same way, but unrelated maps do not.
+pub struct Map<K, V> {
+    root: Option<Arc<Node<K, V>>>,
+    len: usize,
+    hash_builder: HashBuilder,
+}
+
+impl<K, V> Clone for Map<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            len: self.len,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+}
+
+impl<K, V> Default for Map<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for Map<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> Map<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self {
+            root: None,
+            len: 0,
+            hash_builder: HashBuilder::default(),
+        }
+    }
+
+    /// Returns the number of the entries.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns `true` if the maps are the same version, i.e., share all their nodes.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.root, &other.root) {
+            (Some(this), Some(other)) => Arc::ptr_eq(this, other),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Iterates over the entries, in no particular order.
+    pub fn iter(&self) -> MapIter<'_, K, V> {
+        MapIter {
+            stack: self.root.iter().map(|root| &**root).collect(),
+            entries: [].iter(),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Map<K, V> {
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        hash_of(&self.hash_builder, key)
+    }
+
+    /// Returns the value of `key`.
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.root.as_ref()?.get(self.hash(key), key)
+    }
+
+    /// Returns `true` if the map has an entry for `key`.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Map<K, V> {
+    /// Returns a map with the entry inserted, replacing the value of the key if it has one.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let hash = self.hash(&key);
+        let (root, new) = match &self.root {
+            Some(root) => {
+                let (root, new) = Node::insert(root, 0, hash, key, value);
+                (Arc::new(root), new)
+            }
+            None => {
+                let leaf = Node::Leaf {
+                    hash,
+                    entries: Box::new([(key, value)]),
+                };
+                (Arc::new(leaf), true)
+            }
+        };
+        Self {
+            root: Some(root),
+            len: self.len + usize::from(new),
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+
+    /// Returns a map without the entry of `key`. If there is no such entry, the map is the same
+    /// version as this one.
+    pub fn remove<Q>(&self, key: &Q) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let root = some_or!(self.root.as_ref(), return self.clone());
+        let root = some_or!(
+            Node::remove(root, 0, self.hash(key), key),
+            return self.clone()
+        );
+        Self {
+            root,
+            len: self.len - 1,
+            hash_builder: self.hash_builder.clone(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> FromIterator<(K, V)> for Map<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::new(), |map, (key, value)| map.insert(key, value))
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a Map<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = MapIter<'a, K, V>;
+
+    fn into_iter(self) -> MapIter<'a, K, V> {
+        self.iter()
+    }
+}
+
+/// Iterator over the entries of a [`Map`].
+pub struct MapIter<'a, K, V> {
+    /// The nodes yet to visit.
+    stack: Vec<&'a Node<K, V>>,
+    /// The rest of the entries of the current leaf.
+    entries: slice::Iter<'a, (K, V)>,
+    len: usize,
+}
+
+impl<K, V> fmt::Debug for MapIter<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapIter")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, K, V> Iterator for MapIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((key, value)) = self.entries.next() {
+                self.len -= 1;
+                return Some((key, value));
+            }
+            match self.stack.pop()? {
+                Node::Branch { children, .. } => self
+                    .stack
+                    .extend(children.iter().rev().map(|child| &**child)),
+                Node::Leaf { entries, .. } => self.entries = entries.iter(),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for MapIter<'_, K, V> {}
@@ -0,0 +1,72 @@
+//! Persistent collections, whose updates return new versions that share structure with the old.
+//!
+//! A version is never modified once created, so the threads share versions without any
+//! synchronization of their own, and a reader keeps its version consistent for as long as it
+//! likes. The nodes are reference counted with [`Arc`], and a node is freed with the last version
+//! that shares it. [`Published`] holds the latest version of a collection for the threads to load
+//! and replace, which suits read-mostly data updated by a few writers.
+
+use core::fmt;
+
+use crate::{Arc, RcuCell};
+
+mod list;
+mod map;
+
+pub use list::{List, ListIter};
+pub use map::{Map, MapIter};
+
+/// The latest version of a value, usually a persistent collection.
+///
+/// [`load`](Self::load) returns the version as an [`Arc`], which the reader keeps for as long as
+/// it likes without holding back the writers or the reclamation of the other versions.
+/// [`update`](Self::update) derives a new version from the latest one, and publishes it unless
+/// another version was published meanwhile, in which case it derives one again.
+pub struct Published<T> {
+    /// Only the `Arc` of the latest version is reclaimed with EBR. The version itself is freed
+    /// when the last `Arc` to it is dropped.
+    cell: RcuCell<Arc<T>>,
+}
+
+impl<T: fmt::Debug> fmt::Debug for Published<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Published").field(&*self.load()).finish()
+    }
+}
+
+impl<T: Default> Default for Published<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> Published<T> {
+    /// Publishes `t` as the first version.
+    pub fn new(t: T) -> Self {
+        Self {
+            cell: RcuCell::new(Arc::new(t)),
+        }
+    }
+
+    /// Returns the latest version.
+    pub fn load(&self) -> Arc<T> {
+        self.cell.read().clone()
+    }
+
+    /// Publishes `t` as the latest version.
+    pub fn store(&self, t: T) {
+        self.cell.store(Arc::new(t));
+    }
+
+    /// Publishes `f` applied to the latest version, and returns what it published. `f` may be
+    /// called more than once if other versions are published meanwhile.
+    pub fn update<F: FnMut(&T) -> T>(&self, mut f: F) -> Arc<T> {
+        let mut published = None;
+        self.cell.update(|current| {
+            let new = Arc::new(f(current));
+            published = Some(new.clone());
+            new
+        });
+        published.unwrap()
+    }
+}
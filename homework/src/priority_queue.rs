@@ -0,0 +1,86 @@
+//! Lock-free priority queue based on a skiplist.
+//!
+//! Lotan and Shavit, "Skiplist-Based Concurrent Priority Queues", IPDPS 2000. The items are kept
+//! in a `SkipMap` ordered by their priorities, and the minimum is removed by walking the bottom
+//! level until the thread succeeds in marking a node removed.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicU64, Ordering};
+
+use crate::skiplist::SkipMap;
+
+/// Item, which is taken out by the thread that removes its node.
+struct Slot<T>(UnsafeCell<Option<T>>);
+
+// Only the thread that removes the node accesses the item.
+unsafe impl<T: Send> Sync for Slot<T> {}
+
+/// Lock-free priority queue, whose nodes are reclaimed with hazard pointers.
+///
+/// A pop that races with a push of an item with a lower priority may return an item other than the
+/// minimum, as in the original algorithm, so the queue is quiescently consistent rather than
+/// linearizable. Once the pushes are done, the items with equal priorities are popped in the order
+/// they are pushed.
+pub struct PriorityQueue<P, T> {
+    /// Keyed by the priority and the order of the push, so that the keys are unique.
+    map: SkipMap<(P, u64), Slot<T>>,
+    seq: AtomicU64,
+}
+
+impl<P, T> Default for PriorityQueue<P, T> {
+    fn default() -> Self {
+        Self {
+            map: SkipMap::new(),
+            seq: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<P, T> fmt::Debug for PriorityQueue<P, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PriorityQueue").finish_non_exhaustive()
+    }
+}
+
+impl<P, T> PriorityQueue<P, T> {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<P: Ord, T> PriorityQueue<P, T> {
+    /// Pushes `item` with `priority`.
+    pub fn push(&self, priority: P, item: T) {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let slot = Slot(UnsafeCell::new(Some(item)));
+        // The keys are unique, so the insertion succeeds.
+        if self.map.insert((priority, seq), slot).is_err() {
+            unreachable!("the sequence numbers are unique")
+        }
+    }
+
+    /// Pops the item with the lowest priority, or returns `None` if the queue is empty.
+    pub fn try_pop_min(&self) -> Option<(P, T)>
+    where
+        P: Clone,
+    {
+        self.map.pop_first_with(|(priority, _), slot| {
+            // SAFETY: Only the thread that removed the node accesses the item.
+            let item = unsafe { (*slot.0.get()).take() };
+            (priority.clone(), item.expect("the item is taken once"))
+        })
+    }
+
+    /// Returns `true` if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
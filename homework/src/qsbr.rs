@@ -0,0 +1,384 @@
+//! Quiescent-state-based reclamation (QSBR).
+//!
+//! Reading is free: a thread does not announce anything when it reads shared memory. Instead, it
+//! announces a *quiescent state* with `quiescent_state` once in a while, at a point where it holds
+//! no pointer loaded from shared memory, e.g., between the iterations of a worker loop. A pointer
+//! retired in grace period `g` is freed once every online thread has announced a quiescent state
+//! in `g` or later.
+//!
+//! The catch is that an online thread that does not announce quiescent states holds back the
+//! reclamation of every thread. A thread that blocks for long, e.g., waiting for jobs, should go
+//! offline meanwhile with `offline_scope`, and does not hold anything back while offline.
+//!
+//! # Example
+//!
+//! ```
+//! use std::ptr;
+//! use std::sync::atomic::{AtomicPtr, Ordering};
+//! use cs431_homework::qsbr::{collect, quiescent_state, read_lock, retire};
+//!
+//! let atomic = AtomicPtr::new(Box::leak(Box::new(1usize)));
+//! let guard = read_lock();
+//! let pointer = atomic.load(Ordering::Acquire);
+//! assert_eq!(unsafe { *pointer }, 1);
+//!
+//! // unlink the block and retire it
+//! atomic.store(ptr::null_mut(), Ordering::Relaxed);
+//! unsafe { retire(pointer) };
+//! drop(guard);
+//!
+//! // the thread holds no pointer any more, so the block may be freed
+//! quiescent_state();
+//! collect();
+//! ```
+
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use core::mem;
+use std::rc::Rc;
+use std::sync::Mutex;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::thread_local;
+#[cfg(not(feature = "check-loom"))]
+use std::thread_local;
+
+use crate::registry::{self, PerThread, Registration};
+use crate::CachePadded;
+
+/// Grace period announced by an offline thread. The grace periods start at 1.
+const OFFLINE: usize = 0;
+
+/// Pointer retired to be freed, with its destructor.
+type Garbage = (usize, unsafe fn(usize));
+
+/// Retired pointers that may be freed once every online thread announces a quiescent state in
+/// `.0` or later.
+type Batch = (usize, Vec<Garbage>);
+
+/// Global grace period and the ones announced by the threads.
+#[derive(Debug)]
+struct Domain {
+    period: AtomicUsize,
+    /// The grace period in which each thread of `REGISTRY` announced its last quiescent state, or
+    /// `OFFLINE`. Padded, as each thread writes its own on every quiescent state.
+    periods: PerThread<CachePadded<AtomicUsize>>,
+    /// Batches left by the threads that exited before they could free them.
+    orphans: Mutex<Vec<Batch>>,
+}
+
+impl Domain {
+    #[cfg(not(feature = "check-loom"))]
+    const fn new() -> Self {
+        Self {
+            period: AtomicUsize::new(1),
+            periods: PerThread::new(),
+            orphans: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[cfg(feature = "check-loom")]
+    fn new() -> Self {
+        Self {
+            period: AtomicUsize::new(1),
+            periods: PerThread::new(),
+            orphans: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the earliest grace period announced by the online threads, so that the batches of
+    /// the grace periods up to it may be freed.
+    fn completed(&self) -> usize {
+        // SeqCst: pairs with the fence in `online`. Either this thread sees a thread that went
+        // online, or that thread sees the pointers unlinked before the grace period started.
+        fence(Ordering::SeqCst);
+        self.periods
+            .iter()
+            // Acquire: the accesses of the thread before its quiescent state happen before the
+            // batches are freed.
+            .map(|(_, period)| period.load(Ordering::Acquire))
+            .filter(|period| *period != OFFLINE)
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+}
+
+#[cfg(not(feature = "check-loom"))]
+/// The domain of all threads.
+static DOMAIN: Domain = Domain::new();
+
+#[cfg(feature = "check-loom")]
+// FIXME: loom does not currently provide the equivalent of Lazy:
+// https://github.com/tokio-rs/loom/issues/263
+loom::lazy_static! {
+    /// The domain of all threads.
+    static ref DOMAIN: Domain = Domain::new();
+}
+
+/// Thread-local state of a thread in `DOMAIN`.
+#[derive(Debug)]
+struct Local {
+    /// Keeps the id of the thread, and so `period`, until this is dropped.
+    registration: Rc<Registration<'static>>,
+    /// The grace period of the thread in `DOMAIN.periods`.
+    period: &'static AtomicUsize,
+    /// Number of the guards of this thread that are alive.
+    guards: usize,
+    /// Number of the `Offline`s of this thread that are alive.
+    offline: usize,
+    /// Retired pointers that are not in a batch yet.
+    pending: Vec<Garbage>,
+    batches: Vec<Batch>,
+}
+
+impl Local {
+    /// The pending pointers are batched and collected when there are `THRESHOLD` of them.
+    const THRESHOLD: usize = 64;
+
+    /// Brings the current thread online.
+    fn new() -> Self {
+        let registration = registry::current();
+        let period = DOMAIN.periods.get(registration.id());
+        online(period);
+        Self {
+            registration,
+            period,
+            guards: 0,
+            offline: 0,
+            pending: Vec::new(),
+            batches: Vec::new(),
+        }
+    }
+
+    /// Batches the pending pointers, to be freed once the grace period that starts now completes.
+    fn seal(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        // Release: the pointers are unlinked before a thread announces a quiescent state in the
+        // new grace period, so that it does not load them again.
+        let period = DOMAIN.period.fetch_add(1, Ordering::AcqRel) + 1;
+        self.batches.push((period, mem::take(&mut self.pending)));
+    }
+
+    /// Removes the batches whose grace periods are completed.
+    fn take_expired(&mut self, completed: usize) -> Vec<Batch> {
+        let (expired, batches) = mem::take(&mut self.batches)
+            .into_iter()
+            .partition(|(period, _)| *period <= completed);
+        self.batches = batches;
+        expired
+    }
+}
+
+// Like the one of `ebr::Local`, this triggers a loom internal bug.
+#[cfg(not(feature = "check-loom"))]
+impl Drop for Local {
+    /// Leaves the remaining batches to the other threads, as some of them may not announce a
+    /// quiescent state until long after this thread exits.
+    fn drop(&mut self) {
+        self.seal();
+        // The id is released after this, so that the next thread with it starts offline.
+        offline(self.period);
+        if !self.batches.is_empty() {
+            DOMAIN.orphans.lock().unwrap().append(&mut self.batches);
+        }
+    }
+}
+
+thread_local! {
+    /// State of the current thread in `DOMAIN`.
+    static LOCAL: RefCell<Local> = RefCell::new(Local::new());
+}
+
+/// Announces that the thread of `period` reads shared memory again.
+fn online(period: &AtomicUsize) {
+    period.store(DOMAIN.period.load(Ordering::Acquire), Ordering::Relaxed);
+    // SeqCst: pairs with the fence in `Domain::completed`.
+    fence(Ordering::SeqCst);
+}
+
+/// Announces that the thread of `period` does not read shared memory until it goes online.
+fn offline(period: &AtomicUsize) {
+    // Release: the accesses while online happen before the batches are freed.
+    period.store(OFFLINE, Ordering::Release);
+}
+
+/// Frees the pointers of `batches`.
+fn free(batches: Vec<Batch>) {
+    for (_, garbage) in batches {
+        for (pointer, free) in garbage {
+            // SAFETY: No thread accesses the pointer since its grace period completed.
+            unsafe { free(pointer) };
+        }
+    }
+}
+
+/// Announces that the current thread holds no pointer loaded from shared memory, and frees the
+/// pointers retired by it whose grace periods are completed. Does nothing while the thread is
+/// offline.
+///
+/// This is meant to be called often, e.g., once per iteration of a worker loop, and is cheap: it
+/// reads the global grace period and writes it to the thread's own slot.
+///
+/// # Panics
+///
+/// Panics if a guard of the current thread is alive, as it may still hold pointers.
+pub fn quiescent_state() {
+    let expired = LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        assert_eq!(local.guards, 0, "quiescent state announced while reading");
+        if local.offline > 0 {
+            return Vec::new();
+        }
+        // Acquire: the pointers retired before the grace period started are unlinked, so they are
+        // not loaded again. Release: the accesses so far happen before the batches are freed.
+        let period = DOMAIN.period.load(Ordering::Acquire);
+        local.period.store(period, Ordering::Release);
+        if local.batches.is_empty() {
+            return Vec::new();
+        }
+        local.take_expired(DOMAIN.completed())
+    });
+    // The destructors may retire pointers again, so they run outside of the borrow.
+    free(expired);
+}
+
+/// Batches the pointers retired by the current thread, and frees the retired pointers whose grace
+/// periods are completed, including those left by the threads that exited.
+///
+/// Unlike `quiescent_state`, this does not announce anything, so the pointers retired by the
+/// current thread since its last quiescent state are not freed.
+pub fn collect() {
+    let expired = LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        local.seal();
+        local.take_expired(DOMAIN.completed())
+    });
+    free(expired);
+
+    let completed = DOMAIN.completed();
+    let expired = match DOMAIN.orphans.try_lock() {
+        Ok(mut orphans) if !orphans.is_empty() => {
+            let (expired, orphans_left) = mem::take(&mut *orphans)
+                .into_iter()
+                .partition(|(period, _)| *period <= completed);
+            *orphans = orphans_left;
+            expired
+        }
+        _ => Vec::new(),
+    };
+    free(expired);
+}
+
+/// Retires `pointer`, to be freed once every thread online now announces a quiescent state.
+///
+/// # Safety
+///
+/// * `pointer` must be removed from shared memory before calling this function, and must be
+///   valid.
+/// * The same `pointer` should only be retired once.
+pub unsafe fn retire<T>(pointer: *mut T) {
+    /// Frees a pointer of the type known only here.
+    unsafe fn free<T>(data: usize) {
+        drop(Box::from_raw(data as *mut T))
+    }
+
+    let collect_now = LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        local.pending.push((pointer as usize, free::<T>));
+        local.pending.len() >= Local::THRESHOLD
+    });
+    if collect_now {
+        collect();
+    }
+}
+
+/// Marks a read-side critical section, in which the pointers loaded from shared memory stay valid.
+///
+/// This costs nothing but a thread-local counter: an online thread keeps its pointers until its
+/// next quiescent state anyway, which the guard only checks against. An offline thread goes online
+/// until the guard is dropped. Guards are reentrant.
+pub fn read_lock() -> Guard {
+    LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        if local.guards == 0 && local.offline > 0 {
+            online(local.period);
+        }
+        local.guards += 1;
+    });
+    Guard {
+        _marker: PhantomData,
+    }
+}
+
+/// A witness that the current thread is in a read-side critical section.
+#[derive(Debug)]
+pub struct Guard {
+    _marker: PhantomData<*const ()>, // !Send + !Sync
+}
+
+impl Default for Guard {
+    fn default() -> Self {
+        read_lock()
+    }
+}
+
+impl Drop for Guard {
+    /// Ends the critical section if this is the last guard of the thread, going offline again if
+    /// the thread was.
+    fn drop(&mut self) {
+        LOCAL.with(|local| {
+            let mut local = local.borrow_mut();
+            local.guards -= 1;
+            if local.guards == 0 && local.offline > 0 {
+                offline(local.period);
+            }
+        });
+    }
+}
+
+/// Takes the current thread offline until the returned value is dropped, so that it does not hold
+/// back the reclamation, e.g., while it blocks. Reentrant.
+///
+/// # Panics
+///
+/// Panics if a guard of the current thread is alive.
+pub fn offline_scope() -> Offline {
+    LOCAL.with(|local| {
+        let mut local = local.borrow_mut();
+        assert_eq!(local.guards, 0, "went offline while reading");
+        if local.offline == 0 {
+            offline(local.period);
+        }
+        local.offline += 1;
+    });
+    Offline {
+        _marker: PhantomData,
+    }
+}
+
+/// A witness that the current thread is offline.
+#[derive(Debug)]
+pub struct Offline {
+    _marker: PhantomData<*const ()>, // !Send + !Sync
+}
+
+impl Drop for Offline {
+    /// Brings the current thread back online if this is its last `Offline`.
+    fn drop(&mut self) {
+        LOCAL.with(|local| {
+            let mut local = local.borrow_mut();
+            local.offline -= 1;
+            if local.offline == 0 {
+                online(local.period);
+            }
+        });
+    }
+}
@@ -0,0 +1,247 @@
+//! Michael-Scott lock-free queue with hazard pointers.
+
+use core::mem::MaybeUninit;
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::{Condvar, Mutex};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{fence, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{fence, AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::{Condvar, Mutex};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::{Condvar, Mutex};
+
+use crate::backoff::Backoff;
+use crate::hazard_pointer::{retire, Shield};
+use crate::CachePadded;
+
+#[derive(Debug)]
+struct Node<T> {
+    /// Initialized for the nodes made by `push`, until the node becomes the sentinel. The sentinel
+    /// never holds a value.
+    data: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(data: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Self {
+            data,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+/// Michael-Scott lock-free queue, whose nodes are reclaimed with hazard pointers.
+///
+/// Usable with any number of producers and consumers. `pop` blocks until a value is pushed, first
+/// spinning for a while and then parking the thread.
+#[derive(Debug)]
+pub struct Queue<T> {
+    /// The sentinel node, followed by the nodes of the values in the queue. Padded apart from
+    /// `tail`, as poppers and pushers update them at the same time.
+    head: CachePadded<AtomicPtr<Node<T>>>,
+    /// The last node or, while a push is in progress, the one before it.
+    tail: CachePadded<AtomicPtr<Node<T>>>,
+    /// Number of threads parked or about to park in `pop`.
+    sleepers: AtomicUsize,
+    /// Held by a popper from checking the queue for the last time until it parks, so that a
+    /// pusher does not notify in between.
+    lock: Mutex<()>,
+    condvar: Condvar,
+}
+
+// Any particular `T` should never be accessed concurrently, so no need for `T: Sync`.
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        let sentinel = Node::new(MaybeUninit::uninit());
+        Self {
+            head: CachePadded::new(AtomicPtr::new(sentinel)),
+            tail: CachePadded::new(AtomicPtr::new(sentinel)),
+            sleepers: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            condvar: Condvar::new(),
+        }
+    }
+}
+
+impl<T> Queue<T> {
+    /// Adds `t` to the back of the queue, waking up a thread blocked in `pop` if any.
+    pub fn push(&self, t: T) {
+        let new = Node::new(MaybeUninit::new(t));
+        let shield = Shield::default();
+        let backoff = Backoff::new();
+
+        loop {
+            let tail = shield.protect(&self.tail);
+            // SAFETY: `tail` is never null, and it is protected and validated.
+            let tail_ref = unsafe { &*tail };
+
+            let next = tail_ref.next.load(Ordering::Acquire);
+            // `tail` lags behind the last node. Help the push that lags it move the tail.
+            if !next.is_null() {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+
+            // Release: the node is initialized before it is published.
+            if tail_ref
+                .next
+                .compare_exchange(ptr::null_mut(), new, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                let _ = self
+                    .tail
+                    .compare_exchange(tail, new, Ordering::Release, Ordering::Relaxed);
+                break;
+            }
+            backoff.spin();
+        }
+        drop(shield);
+
+        // SeqCst: either this thread sees a sleeper that is about to park, or the sleeper sees the
+        // node pushed above. Pairs with the fence in `pop`.
+        fence(Ordering::SeqCst);
+        if self.sleepers.load(Ordering::Relaxed) > 0 {
+            // Waits until the sleeper parks, so that the notification is not lost.
+            drop(self.lock.lock().unwrap());
+            self.condvar.notify_one();
+        }
+    }
+
+    /// Tries to pop the front value with a single CAS, protecting the front nodes with the
+    /// shields, which are cleared afterwards. See `try_pop`.
+    fn try_pop_protected(
+        &self,
+        head_shield: &Shield<Node<T>>,
+        next_shield: &Shield<Node<T>>,
+    ) -> Result<Option<T>, ()> {
+        let head = head_shield.protect(&self.head);
+        // SAFETY: `head` is never null, and it is protected and validated.
+        let head_ref = unsafe { &*head };
+
+        let next = head_ref.next.load(Ordering::Acquire);
+        if next.is_null() {
+            head_shield.clear();
+            return Ok(None);
+        }
+        next_shield.set(next);
+        // `next` is not retired while `head` is still the head, since `head` is retired first.
+        if Shield::validate(head, &self.head).is_err() {
+            head_shield.clear();
+            next_shield.clear();
+            return Err(());
+        }
+
+        // The tail may lag behind at `head`, which is about to be retired. Move it first.
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == head {
+            let _ = self
+                .tail
+                .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+        }
+
+        let result = if self
+            .head
+            .compare_exchange(head, next, Ordering::Release, Ordering::Relaxed)
+            .is_ok()
+        {
+            // SAFETY: `next` was pushed, so its value is initialized, and the CAS above made it
+            // the sentinel, so no other thread takes its value.
+            let t = unsafe { (*next).data.assume_init_read() };
+            // SAFETY: `head` is detached by the CAS above, and this thread retires it only once.
+            unsafe { retire(head) };
+            Ok(Some(t))
+        } else {
+            Err(())
+        };
+        head_shield.clear();
+        next_shield.clear();
+        result
+    }
+
+    /// Tries to pop the front value with a single CAS.
+    ///
+    /// Returns `Ok(Some(v))` if `v` is popped, `Ok(None)` if the queue is empty, and `Err(())` if
+    /// the CAS failed due to contention.
+    pub fn try_pop(&self) -> Result<Option<T>, ()> {
+        self.try_pop_protected(&Shield::default(), &Shield::default())
+    }
+
+    /// Pops the front value with the shields, retrying on contention. Returns `None` if the queue
+    /// is empty.
+    fn pop_protected(
+        &self,
+        head_shield: &Shield<Node<T>>,
+        next_shield: &Shield<Node<T>>,
+    ) -> Option<T> {
+        let backoff = Backoff::new();
+        loop {
+            if let Ok(result) = self.try_pop_protected(head_shield, next_shield) {
+                return result;
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Pops the front value, blocking until one is pushed if the queue is empty.
+    pub fn pop(&self) -> T {
+        let head_shield = Shield::default();
+        let next_shield = Shield::default();
+
+        // Retries until the backoff tells it to park.
+        let backoff = Backoff::new();
+        while !backoff.is_completed() {
+            if let Some(t) = self.pop_protected(&head_shield, &next_shield) {
+                return t;
+            }
+            backoff.snooze();
+        }
+
+        let mut guard = self.lock.lock().unwrap();
+        let _ = self.sleepers.fetch_add(1, Ordering::Relaxed);
+        loop {
+            // SeqCst: pairs with the fence in `push`.
+            fence(Ordering::SeqCst);
+            if let Some(t) = self.pop_protected(&head_shield, &next_shield) {
+                let _ = self.sleepers.fetch_sub(1, Ordering::Relaxed);
+                return t;
+            }
+            guard = self.condvar.wait(guard).unwrap();
+        }
+    }
+
+    /// Returns `true` if the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        let shield = Shield::default();
+        let head = shield.protect(&self.head);
+        // SAFETY: `head` is never null, and it is protected and validated.
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+}
+
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        let sentinel = self.head.load(Ordering::Relaxed);
+        // SAFETY: No other thread accesses the queue, and the nodes in it are not retired.
+        let mut node = unsafe { Box::from_raw(sentinel) }
+            .next
+            .load(Ordering::Relaxed);
+        while !node.is_null() {
+            // SAFETY: Same as above. The nodes after the sentinel hold values that are not popped.
+            let mut boxed = unsafe { Box::from_raw(node) };
+            node = boxed.next.load(Ordering::Relaxed);
+            unsafe { boxed.data.assume_init_drop() };
+        }
+    }
+}
@@ -0,0 +1,344 @@
+//! Concurrent radix tree with lock coupling.
+//!
+//! Each node is latched with a reader-writer lock, and the operations descend the tree by read
+//! lock coupling, like those of [`BPlusTree`](crate::BPlusTree). An update write-latches only the
+//! node it changes, while holding the read latch of its parent.
+//!
+//! A node is split in place: the node keeps the common part of its label, and its entry and
+//! children move to a new child. So a node keeps its path from the root, and an operation that
+//! latches a node after reading the pointer from its parent finds the node where it expected it.
+//! Conversely, the parent of a node is never split while it is latched, so the node does not move
+//! under a child latched by coupling.
+//!
+//! Nodes are never merged. A removal only takes the value out of its node, so that the nodes live
+//! until the tree is dropped, and a node reached from the tree can always be latched.
+
+use core::fmt;
+use core::mem;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(feature = "check-loom")]
+use loom::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+type Latch<V> = RwLock<Node<V>>;
+
+#[derive(Debug)]
+struct Node<V> {
+    /// The bytes of the keys from the parent to this node, which are empty only for the root.
+    label: Vec<u8>,
+    /// The value of the key that ends at this node.
+    value: Option<V>,
+    /// The children sorted by the first bytes of their labels, which differ.
+    children: Vec<(u8, *mut Latch<V>)>,
+}
+
+impl<V> Node<V> {
+    fn new(label: Vec<u8>, value: Option<V>) -> *mut Latch<V> {
+        Box::into_raw(Box::new(RwLock::new(Self {
+            label,
+            value,
+            children: Vec::new(),
+        })))
+    }
+
+    /// Returns the child of the node whose label starts with `byte`, if any.
+    fn child(&self, byte: u8) -> Option<*mut Latch<V>> {
+        let index = self
+            .children
+            .binary_search_by_key(&byte, |(b, _)| *b)
+            .ok()?;
+        Some(self.children[index].1)
+    }
+
+    /// Returns the child to descend to for `rest`, the key from the parent, and the length of the
+    /// label that it skips. Returns `None` if `rest` ends at or diverges from the node, or the
+    /// node has no child for it.
+    fn step(&self, rest: &[u8]) -> Option<(*mut Latch<V>, usize)> {
+        let len = self.label.len();
+        if rest.len() <= len || !rest.starts_with(&self.label) {
+            return None;
+        }
+        Some((self.child(rest[len])?, len))
+    }
+
+    /// Splits the label of the node at `at`, moving the rest of the label, the value, and the
+    /// children to a new child.
+    fn split(&mut self, at: usize) {
+        let label = self.label.split_off(at);
+        let byte = label[0];
+        let child = Box::into_raw(Box::new(RwLock::new(Self {
+            label,
+            value: self.value.take(),
+            children: mem::take(&mut self.children),
+        })));
+        self.children.push((byte, child));
+    }
+}
+
+/// Returns the length of the common prefix of `a` and `b`.
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
+}
+
+/// Concurrent map from byte strings, as a radix tree whose nodes are latched one by one.
+///
+/// The keys sharing a prefix share the path of the prefix, so that the tree answers prefix
+/// queries: the entries under a prefix, and the longest key that is a prefix of a string, e.g., to
+/// match a path against routes.
+pub struct RadixTree<V> {
+    /// Has an empty label, and so is never split.
+    root: *mut Latch<V>,
+}
+
+unsafe impl<V: Send + Sync> Send for RadixTree<V> {}
+unsafe impl<V: Send + Sync> Sync for RadixTree<V> {}
+
+impl<V> fmt::Debug for RadixTree<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RadixTree").finish_non_exhaustive()
+    }
+}
+
+impl<V> Default for RadixTree<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> RadixTree<V> {
+    /// Creates an empty tree.
+    pub fn new() -> Self {
+        Self {
+            root: Node::new(Vec::new(), None),
+        }
+    }
+
+    /// Returns the latch of `node`.
+    fn latch(&self, node: *mut Latch<V>) -> &Latch<V> {
+        // SAFETY: The nodes are freed only when the tree is dropped.
+        unsafe { &*node }
+    }
+
+    /// Descends along `key` by read lock coupling to the node that an update of `key` changes,
+    /// i.e., where `key` ends, diverges from the label, or has no child to go on. Returns the
+    /// write-latched node and the length of the key above its label.
+    fn write_node(&self, key: &[u8]) -> (RwLockWriteGuard<'_, Node<V>>, usize) {
+        'restart: loop {
+            let mut parent: Option<RwLockReadGuard<'_, Node<V>>> = None;
+            let mut node = self.root;
+            let mut depth = 0;
+            loop {
+                let guard = self.latch(node).read().unwrap();
+                if let Some((child, len)) = guard.step(&key[depth..]) {
+                    parent = Some(guard);
+                    node = child;
+                    depth += len;
+                    continue;
+                }
+                drop(guard);
+                // The parent stays read-latched, so the node stays where it is.
+                let guard = self.latch(node).write().unwrap();
+                if guard.step(&key[depth..]).is_some() {
+                    // A child was inserted meanwhile.
+                    drop((guard, parent));
+                    continue 'restart;
+                }
+                return (guard, depth);
+            }
+        }
+    }
+
+    /// Descends along `key` by read lock coupling, calling `visit` on each node whose path is a
+    /// prefix of `key` with the length of the path, until it returns `false`.
+    fn descend<'a, F>(&'a self, key: &[u8], mut visit: F)
+    where
+        F: FnMut(RwLockReadGuard<'a, Node<V>>, usize) -> bool,
+    {
+        let mut guard = self.latch(self.root).read().unwrap();
+        let mut depth = 0;
+        loop {
+            let rest = &key[depth..];
+            if !rest.starts_with(&guard.label) {
+                return;
+            }
+            let child = guard.step(rest);
+            depth += guard.label.len();
+            let next = child.map(|(child, _)| self.latch(child).read().unwrap());
+            if !visit(guard, depth) {
+                return;
+            }
+            guard = some_or!(next, return);
+        }
+    }
+
+    /// Looks up `key`, calling `f` on its value if any, while the node holding it is read-latched.
+    pub fn lookup<F, R>(&self, key: &[u8], f: F) -> R
+    where
+        F: FnOnce(Option<&V>) -> R,
+    {
+        let mut found = None;
+        self.descend(key, |guard, depth| {
+            if depth == key.len() {
+                found = Some(guard);
+                return false;
+            }
+            true
+        });
+        f(found.as_ref().and_then(|guard| guard.value.as_ref()))
+    }
+
+    /// Returns `true` if the tree contains `key`.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.lookup(key, |value| value.is_some())
+    }
+
+    /// Finds the longest key in the tree that is a prefix of `key`, calling `f` on its length and
+    /// value if any, while the node holding it is read-latched.
+    pub fn longest_prefix<F, R>(&self, key: &[u8], f: F) -> R
+    where
+        F: FnOnce(Option<(usize, &V)>) -> R,
+    {
+        // The deepest node with a value so far, which stays latched above the current one.
+        let mut best = None;
+        self.descend(key, |guard, depth| {
+            if guard.value.is_some() {
+                best = Some((guard, depth));
+            }
+            true
+        });
+        f(best
+            .as_ref()
+            .and_then(|(guard, depth)| Some((*depth, guard.value.as_ref()?))))
+    }
+
+    /// Calls `f` on the entries under the node of `guard`, whose path is `key`, in ascending order
+    /// of the keys, until it returns `false`. Returns `false` if it did.
+    fn visit<F>(&self, guard: &RwLockReadGuard<'_, Node<V>>, key: &mut Vec<u8>, f: &mut F) -> bool
+    where
+        F: FnMut(&[u8], &V) -> bool,
+    {
+        if let Some(value) = &guard.value {
+            if !f(key, value) {
+                return false;
+            }
+        }
+        for (_, child) in &guard.children {
+            // The ancestors stay read-latched, so that the keys are built from the current labels.
+            let child = self.latch(*child).read().unwrap();
+            let len = key.len();
+            key.extend_from_slice(&child.label);
+            let more = self.visit(&child, key, f);
+            key.truncate(len);
+            if !more {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Calls `f` on the entries whose keys start with `prefix`, until it returns `false`.
+    fn scan<F>(&self, prefix: &[u8], mut f: F)
+    where
+        F: FnMut(&[u8], &V) -> bool,
+    {
+        let mut guard = self.latch(self.root).read().unwrap();
+        let mut depth = 0;
+        loop {
+            let rest = &prefix[depth..];
+            if rest.len() <= guard.label.len() {
+                // The prefix ends in the label of the node.
+                if !guard.label.starts_with(rest) {
+                    return;
+                }
+                let mut key = prefix[..depth].to_vec();
+                key.extend_from_slice(&guard.label);
+                let _ = self.visit(&guard, &mut key, &mut f);
+                return;
+            }
+            let (child, len) = some_or!(guard.step(rest), return);
+            depth += len;
+            guard = self.latch(child).read().unwrap();
+        }
+    }
+
+    /// Calls `f` on the entries whose keys start with `prefix`, in ascending order of the keys.
+    ///
+    /// The scan read-latches the path to each entry while it visits the entry, so it is not a
+    /// snapshot: it sees the updates to the subtrees it has not reached yet, but not to those it
+    /// has passed.
+    pub fn prefix<F>(&self, prefix: &[u8], mut f: F)
+    where
+        F: FnMut(&[u8], &V),
+    {
+        self.scan(prefix, |key, value| {
+            f(key, value);
+            true
+        });
+    }
+
+    /// Returns `true` if the tree is empty.
+    pub fn is_empty(&self) -> bool {
+        let mut empty = true;
+        self.scan(&[], |_, _| {
+            empty = false;
+            false
+        });
+        empty
+    }
+
+    /// Inserts `key` with `value`. Returns the value back if the key is already in the tree.
+    pub fn insert(&self, key: &[u8], value: V) -> Result<(), V> {
+        let (mut guard, depth) = self.write_node(key);
+        let rest = &key[depth..];
+        let common = common_prefix(&guard.label, rest);
+        if common < guard.label.len() {
+            guard.split(common);
+        }
+        if rest.len() == common {
+            if guard.value.is_some() {
+                return Err(value);
+            }
+            guard.value = Some(value);
+            return Ok(());
+        }
+        // The node has no child for the next byte, as `write_node` would have descended to it.
+        let byte = rest[common];
+        let index = guard.children.partition_point(|(b, _)| *b < byte);
+        let leaf = Node::new(rest[common..].to_vec(), Some(value));
+        guard.children.insert(index, (byte, leaf));
+        Ok(())
+    }
+
+    /// Removes `key`, returning its value if any.
+    pub fn remove(&self, key: &[u8]) -> Option<V> {
+        let (mut guard, depth) = self.write_node(key);
+        if guard.label.as_slice() != &key[depth..] {
+            return None;
+        }
+        guard.value.take()
+    }
+}
+
+impl<V> Drop for RadixTree<V> {
+    fn drop(&mut self) {
+        /// Frees `node` and its descendants.
+        ///
+        /// # Safety
+        ///
+        /// No other thread accesses the nodes, and they are freed only once.
+        unsafe fn free<V>(node: *mut Latch<V>) {
+            // SAFETY: Guaranteed by the caller.
+            let node = unsafe { Box::from_raw(node) }.into_inner().unwrap();
+            for (_, child) in node.children {
+                // SAFETY: Each node is the child of only one node.
+                unsafe { free(child) };
+            }
+        }
+
+        // SAFETY: No other thread accesses the tree, and the tree is the only owner of the root.
+        unsafe { free(self.root) };
+    }
+}
@@ -0,0 +1,141 @@
+//! Read-copy-update cell, whose old versions are reclaimed with EBR.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::backoff::Backoff;
+use crate::ebr::{pin, Guard};
+
+/// A shared value that is read without locking and updated by replacing it with a modified copy.
+///
+/// Suited for read-mostly state. Readers see a consistent version for as long as they hold the
+/// `RcuReadGuard`, and an old version is freed after a grace period, i.e. once all readers that
+/// may see it are gone.
+pub struct RcuCell<T> {
+    /// Never null. Replaced only as a whole, so that readers never see a partial update.
+    ptr: AtomicPtr<T>,
+}
+
+// Readers on any thread share the current version, and the thread that reclaims an old version
+// drops it.
+unsafe impl<T: Send + Sync> Send for RcuCell<T> {}
+unsafe impl<T: Send + Sync> Sync for RcuCell<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for RcuCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RcuCell").field(&*self.read()).finish()
+    }
+}
+
+impl<T: Default> Default for RcuCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> RcuCell<T> {
+    /// Creates a cell holding `t`.
+    pub fn new(t: T) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Box::into_raw(Box::new(t))),
+        }
+    }
+
+    /// Enters a read-side critical section, returning the current version. Updates made while the
+    /// guard is alive are not visible through it.
+    pub fn read(&self) -> RcuReadGuard<'_, T> {
+        let guard = pin();
+        // Acquire: the version is initialized before it is published.
+        let ptr = self.ptr.load(Ordering::Acquire);
+        RcuReadGuard {
+            _guard: guard,
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Replaces the current version with `f` applied to it. `f` may be called more than once if
+    /// other updates race with this one.
+    pub fn update<F: FnMut(&T) -> T>(&self, mut f: F) {
+        let guard = pin();
+        let backoff = Backoff::new();
+        let mut current = self.ptr.load(Ordering::Acquire);
+        loop {
+            // SAFETY: `current` is loaded while pinned, so it is not freed until `guard` is
+            // dropped.
+            let new = Box::into_raw(Box::new(f(unsafe { &*current })));
+            // Release: the new version is initialized before it is published. Acquire: so is the
+            // version that replaced `current` if the CAS fails.
+            match self
+                .ptr
+                .compare_exchange(current, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => {
+                    // SAFETY: `current` is replaced by the CAS above, and only this thread
+                    // replaced it.
+                    unsafe { guard.defer_destroy(current) };
+                    return;
+                }
+                Err(actual) => {
+                    // SAFETY: `new` was not published.
+                    drop(unsafe { Box::from_raw(new) });
+                    current = actual;
+                    backoff.spin();
+                }
+            }
+        }
+    }
+
+    /// Replaces the current version with `t`.
+    pub fn store(&self, t: T) {
+        let guard = pin();
+        let old = self.ptr.swap(Box::into_raw(Box::new(t)), Ordering::AcqRel);
+        // SAFETY: `old` is replaced by the swap above, and only this thread replaced it.
+        unsafe { guard.defer_destroy(old) };
+    }
+
+    /// Returns the current version.
+    pub fn into_inner(self) -> T {
+        let ptr = self.ptr.load(Ordering::Relaxed);
+        core::mem::forget(self);
+        // SAFETY: No other thread accesses the cell, and the current version is not deferred.
+        *unsafe { Box::from_raw(ptr) }
+    }
+}
+
+impl<T> Drop for RcuCell<T> {
+    fn drop(&mut self) {
+        // SAFETY: No other thread accesses the cell, and the current version is not deferred.
+        drop(unsafe { Box::from_raw(self.ptr.load(Ordering::Relaxed)) });
+    }
+}
+
+/// Read-side critical section of an `RcuCell`, through which a version of its value is read.
+pub struct RcuReadGuard<'a, T> {
+    _guard: Guard,
+    ptr: *mut T,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<T> Deref for RcuReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: `ptr` is loaded while pinned, so it is not freed until `_guard` is dropped.
+        unsafe { &*self.ptr }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RcuReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
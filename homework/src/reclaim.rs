@@ -0,0 +1,120 @@
+//! Memory reclamation schemes that the lock-free data structures can be instantiated with.
+//!
+//! A structure generic over `R: Reclaimer` protects the nodes it reads with `R::Shield` and retires
+//! the nodes it unlinks with `R::retire`, so that the same code runs with hazard pointers (`Hp`),
+//! epoch-based reclamation (`Ebr`), and quiescent-state-based reclamation (`Qsbr`).
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::{ebr, hazard_pointer, qsbr};
+
+/// Protection of the pointers loaded from shared memory from being freed.
+pub trait Protect<T>: Default {
+    /// Loads a pointer from `src` and protects it, until `clear` is called or `self` is dropped.
+    fn protect(&self, src: &AtomicPtr<T>) -> *mut T;
+
+    /// Gives up the protection.
+    fn clear(&self);
+}
+
+/// Memory reclamation scheme.
+pub trait Reclaimer {
+    /// Protects a pointer to `T`.
+    type Shield<T>: Protect<T>;
+
+    /// Frees `pointer` once no shield protects it.
+    ///
+    /// # Safety
+    ///
+    /// * `pointer` must be removed from shared memory before calling this function, and must be
+    ///   valid.
+    /// * The same `pointer` should only be retired once.
+    unsafe fn retire<T>(pointer: *mut T);
+
+    /// Frees the pointers retired by the current thread that are no longer protected.
+    fn collect();
+}
+
+/// Hazard pointers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hp;
+
+/// Epoch-based reclamation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ebr;
+
+/// Quiescent-state-based reclamation. The threads using it must call `qsbr::quiescent_state`
+/// regularly, or go offline, for the retired pointers to be freed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Qsbr;
+
+impl<T> Protect<T> for hazard_pointer::Shield<T> {
+    fn protect(&self, src: &AtomicPtr<T>) -> *mut T {
+        hazard_pointer::Shield::protect(self, src)
+    }
+
+    fn clear(&self) {
+        hazard_pointer::Shield::clear(self)
+    }
+}
+
+impl Reclaimer for Hp {
+    type Shield<T> = hazard_pointer::Shield<T>;
+
+    unsafe fn retire<T>(pointer: *mut T) {
+        hazard_pointer::retire(pointer)
+    }
+
+    fn collect() {
+        hazard_pointer::collect()
+    }
+}
+
+/// A guard protects every pointer loaded while it is alive, so there is nothing to clear.
+impl<T> Protect<T> for ebr::Guard {
+    fn protect(&self, src: &AtomicPtr<T>) -> *mut T {
+        src.load(Ordering::Acquire)
+    }
+
+    fn clear(&self) {}
+}
+
+impl Reclaimer for Ebr {
+    type Shield<T> = ebr::Guard;
+
+    unsafe fn retire<T>(pointer: *mut T) {
+        ebr::pin().defer_destroy(pointer)
+    }
+
+    fn collect() {
+        ebr::collect()
+    }
+}
+
+/// An online thread keeps every pointer it loads until its next quiescent state, so there is
+/// nothing to protect or clear.
+impl<T> Protect<T> for qsbr::Guard {
+    fn protect(&self, src: &AtomicPtr<T>) -> *mut T {
+        src.load(Ordering::Acquire)
+    }
+
+    fn clear(&self) {}
+}
+
+impl Reclaimer for Qsbr {
+    type Shield<T> = qsbr::Guard;
+
+    unsafe fn retire<T>(pointer: *mut T) {
+        qsbr::retire(pointer)
+    }
+
+    /// Only frees the pointers retired before the last quiescent state of the current thread.
+    fn collect() {
+        qsbr::collect()
+    }
+}
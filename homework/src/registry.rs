@@ -0,0 +1,398 @@
+//! Registry of the threads, which numbers them densely.
+//!
+//! A thread registers once, and gets an id that it keeps until it exits. The id of an exited
+//! thread is given to the next thread that registers, so that the ids are smaller than the
+//! largest number of threads that were ever registered at once. The state of each thread can then
+//! be kept in a [`PerThread`] indexed by the ids, instead of a list of its own, and found by
+//! iterating over the live threads. A thread may also be called back when it exits, before its id
+//! is given to another thread.
+//!
+//! [`REGISTRY`] registers each thread that asks for [`current_id`]. It numbers the slot hints of
+//! the hazard pointers, the stripes of [`ConcurrentCounter`](crate::ConcurrentCounter), and the
+//! grace periods announced to [`qsbr`](crate::qsbr).
+
+use core::cell::RefCell;
+use core::fmt;
+use core::iter;
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+use std::rc::Rc;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(not(feature = "check-loom"))]
+use arr_macro::arr;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::thread_local;
+#[cfg(not(feature = "check-loom"))]
+use std::thread_local;
+
+use crate::backoff::Backoff;
+
+/// Id of a thread in a `ThreadRegistry`.
+#[derive(Debug)]
+struct Entry {
+    /// Whether the id is held by a registration.
+    active: AtomicBool,
+    id: usize,
+    /// Immutable pointer to the entry of the previous id.
+    next: *const Entry,
+}
+
+/// Registry that hands out dense ids to the threads.
+///
+/// Like the participants of `ebr::Collector`, the entries of the ids form a grow-only list, whose
+/// entries are recycled. The list is in the descending order of the ids, so a new entry takes the
+/// id after the one of the head.
+#[derive(Debug)]
+pub struct ThreadRegistry {
+    head: AtomicPtr<Entry>,
+}
+
+// The entries are only accessed through atomics.
+unsafe impl Send for ThreadRegistry {}
+unsafe impl Sync for ThreadRegistry {}
+
+impl Default for ThreadRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ThreadRegistry {
+    #[cfg(not(feature = "check-loom"))]
+    /// Creates an empty registry.
+    pub const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    #[cfg(feature = "check-loom")]
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Registers the current thread, which holds the id until the registration is dropped.
+    pub fn register(&self) -> Registration<'_> {
+        Registration {
+            entry: self.claim(),
+            exits: RefCell::new(Vec::new()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Claims an id, either by recycling an inactive entry or pushing a new one.
+    fn claim(&self) -> &Entry {
+        let mut node: *const Entry = self.head.load(Ordering::Acquire);
+        // SAFETY: Entries are never freed while the registry is alive.
+        while let Some(entry) = unsafe { node.as_ref() } {
+            // Acquire: the previous holder of the id is done with it.
+            if entry
+                .active
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return entry;
+            }
+            node = entry.next;
+        }
+
+        let mut entry = Box::new(Entry {
+            active: AtomicBool::new(true),
+            id: 0,
+            next: ptr::null(),
+        });
+        let backoff = Backoff::new();
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            entry.next = head;
+            // SAFETY: Entries are never freed while the registry is alive.
+            entry.id = unsafe { head.as_ref() }.map_or(0, |head| head.id + 1);
+            let new = Box::into_raw(entry);
+            match self
+                .head
+                .compare_exchange(head, new, Ordering::Release, Ordering::Relaxed)
+            {
+                // SAFETY: Entries are never freed while the registry is alive.
+                Ok(_) => return unsafe { &*new },
+                Err(_) => {
+                    // SAFETY: `new` was not published.
+                    entry = unsafe { Box::from_raw(new) };
+                    backoff.spin();
+                }
+            }
+        }
+    }
+
+    /// Returns the ids of the registered threads, in descending order. A thread that registers or
+    /// exits meanwhile may or may not be seen.
+    pub fn live(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut node: *const Entry = self.head.load(Ordering::Acquire);
+        iter::from_fn(move || {
+            // SAFETY: Entries are never freed while the registry is alive.
+            while let Some(entry) = unsafe { node.as_ref() } {
+                node = entry.next;
+                // Acquire: the registration happens before the iteration goes on.
+                if entry.active.load(Ordering::Acquire) {
+                    return Some(entry.id);
+                }
+            }
+            None
+        })
+    }
+
+    /// Returns the number of ids handed out so far, which all ids are smaller than.
+    pub fn capacity(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        // SAFETY: Entries are never freed while the registry is alive.
+        unsafe { head.as_ref() }.map_or(0, |head| head.id + 1)
+    }
+}
+
+impl Drop for ThreadRegistry {
+    /// Frees all entries.
+    fn drop(&mut self) {
+        let mut node = self.head.load(Ordering::Relaxed);
+        while !node.is_null() {
+            // SAFETY: No thread is registered any more, so no one accesses the entries.
+            let entry = unsafe { Box::from_raw(node) };
+            node = entry.next as *mut Entry;
+        }
+    }
+}
+
+/// Callback called with the id of a thread when it exits.
+type Exit = Box<dyn FnOnce(usize)>;
+
+/// Registration of the current thread in a `ThreadRegistry`, which holds its id.
+pub struct Registration<'r> {
+    entry: &'r Entry,
+    /// Called back with the id when the registration is dropped.
+    exits: RefCell<Vec<Exit>>,
+    _marker: PhantomData<*const ()>, // !Send + !Sync
+}
+
+impl fmt::Debug for Registration<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registration")
+            .field("id", &self.id())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Registration<'_> {
+    /// Returns the id of the thread.
+    pub fn id(&self) -> usize {
+        self.entry.id
+    }
+
+    /// Calls `f` with the id when the registration is dropped, before the id is given to another
+    /// thread. The callbacks are called in the reverse order of their registrations.
+    pub fn at_exit<F: FnOnce(usize) + 'static>(&self, f: F) {
+        self.exits.borrow_mut().push(Box::new(f));
+    }
+}
+
+// Like the one of `ebr::Local`, this triggers a loom internal bug when the registration is
+// thread-local.
+#[cfg(not(feature = "check-loom"))]
+impl Drop for Registration<'_> {
+    /// Calls the exit callbacks, and releases the id.
+    fn drop(&mut self) {
+        for exit in mem::take(self.exits.get_mut()).into_iter().rev() {
+            exit(self.id());
+        }
+        // Release: the accesses of this thread to its state happen before the next holder's.
+        self.entry.active.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(not(feature = "check-loom"))]
+/// The registry of all threads.
+pub static REGISTRY: ThreadRegistry = ThreadRegistry::new();
+
+#[cfg(feature = "check-loom")]
+// FIXME: loom does not currently provide the equivalent of Lazy:
+// https://github.com/tokio-rs/loom/issues/263
+loom::lazy_static! {
+    /// The registry of all threads.
+    pub static ref REGISTRY: ThreadRegistry = ThreadRegistry::new();
+}
+
+thread_local! {
+    /// Registration of the current thread in `REGISTRY`.
+    static CURRENT: Rc<Registration<'static>> = Rc::new(REGISTRY.register());
+}
+
+/// Returns the registration of the current thread in `REGISTRY`, registering it on the first
+/// call. The thread keeps its id while the returned value is alive, even after its thread-locals
+/// are destroyed, so that a thread-local destructor can clean up the state of the id.
+///
+/// # Panics
+///
+/// Panics if the thread-local registration is destroyed, i.e., if this is called from a
+/// thread-local destructor that runs after it.
+pub fn current() -> Rc<Registration<'static>> {
+    CURRENT.with(Rc::clone)
+}
+
+/// Returns the id of the current thread in `REGISTRY`, registering it on the first call.
+///
+/// # Panics
+///
+/// Panics like `current`.
+pub fn current_id() -> usize {
+    CURRENT.with(|registration| registration.id())
+}
+
+/// Returns the id of the current thread like `current_id`, or `None` if the thread-local
+/// registration is destroyed.
+pub fn try_current_id() -> Option<usize> {
+    CURRENT.try_with(|registration| registration.id()).ok()
+}
+
+/// Calls `f` with the id of the current thread in `REGISTRY` when the thread exits, before the id
+/// is given to another thread. See `Registration::at_exit`.
+///
+/// `f` runs in a thread-local destructor, so it must not use the thread-locals that may be
+/// destroyed before, including `current_id`.
+///
+/// # Panics
+///
+/// Panics like `current`.
+pub fn at_thread_exit<F: FnOnce(usize) + 'static>(f: F) {
+    CURRENT.with(|registration| registration.at_exit(f));
+}
+
+/// Number of the buckets of a `PerThread`.
+#[cfg(not(feature = "check-loom"))]
+const BUCKETS: usize = 64;
+#[cfg(feature = "check-loom")]
+const BUCKETS: usize = 8;
+
+/// Returns the bucket of `id` and its index in the bucket. Bucket `b` holds the values of the ids
+/// from `2^b - 1` to `2^(b + 1) - 2`, so that the buckets double in length.
+fn locate(id: usize) -> (usize, usize) {
+    let n = id + 1;
+    let bucket = (usize::BITS - 1 - n.leading_zeros()) as usize;
+    (bucket, n - (1 << bucket))
+}
+
+/// Value for each thread, indexed by the ids of `REGISTRY`.
+///
+/// The values are allocated in buckets, on the first access to an id of each bucket, and stay
+/// until the `PerThread` is dropped. The value of an id is used by the thread that holds the id,
+/// and then by the next one, so a thread should reset what it leaves in its value before it exits.
+pub struct PerThread<T> {
+    buckets: [AtomicPtr<T>; BUCKETS],
+}
+
+unsafe impl<T: Send> Send for PerThread<T> {}
+unsafe impl<T: Send + Sync> Sync for PerThread<T> {}
+
+impl<T> fmt::Debug for PerThread<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PerThread").finish_non_exhaustive()
+    }
+}
+
+impl<T: Default> Default for PerThread<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Default> PerThread<T> {
+    #[cfg(not(feature = "check-loom"))]
+    /// Creates a `PerThread` whose values are created with `T::default` on demand.
+    pub const fn new() -> Self {
+        Self {
+            buckets: arr![AtomicPtr::new(ptr::null_mut()); 64],
+        }
+    }
+
+    #[cfg(feature = "check-loom")]
+    /// Creates a `PerThread` whose values are created with `T::default` on demand.
+    pub fn new() -> Self {
+        Self {
+            buckets: core::array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+        }
+    }
+
+    /// Returns the value of `id`.
+    pub fn get(&self, id: usize) -> &T {
+        let (bucket, index) = locate(id);
+        // Acquire: the values of the bucket are initialized.
+        let mut values = self.buckets[bucket].load(Ordering::Acquire);
+        if values.is_null() {
+            let new = Box::into_raw((0..1 << bucket).map(|_| T::default()).collect::<Box<[T]>>())
+                as *mut T;
+            match self.buckets[bucket].compare_exchange(
+                ptr::null_mut(),
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => values = new,
+                Err(current) => {
+                    // SAFETY: `new` was not published.
+                    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(new, 1 << bucket)) });
+                    values = current;
+                }
+            }
+        }
+        // SAFETY: The bucket holds `2^bucket` values, and is freed only when `self` is dropped.
+        unsafe { &*values.add(index) }
+    }
+
+    /// Returns the value of the current thread.
+    pub fn local(&self) -> &T {
+        self.get(current_id())
+    }
+
+    /// Returns the value of the current thread, or `None` if the thread-local registration is
+    /// destroyed. See `try_current_id`.
+    pub fn try_local(&self) -> Option<&T> {
+        Some(self.get(try_current_id()?))
+    }
+}
+
+impl<T> PerThread<T> {
+    /// Returns the value of `id`, if it is allocated.
+    fn try_get(&self, id: usize) -> Option<&T> {
+        let (bucket, index) = locate(id);
+        let values = self.buckets[bucket].load(Ordering::Acquire);
+        // SAFETY: As in `get`.
+        (!values.is_null()).then(|| unsafe { &*values.add(index) })
+    }
+
+    /// Returns the values of the live threads of `REGISTRY` with their ids, skipping those that
+    /// are not allocated, as in `ThreadRegistry::live`.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        REGISTRY
+            .live()
+            .filter_map(|id| Some((id, self.try_get(id)?)))
+    }
+}
+
+impl<T> Drop for PerThread<T> {
+    /// Frees all buckets.
+    fn drop(&mut self) {
+        for (bucket, values) in self.buckets.iter().enumerate() {
+            let values = values.load(Ordering::Relaxed);
+            if !values.is_null() {
+                // SAFETY: No other thread accesses the values any more.
+                drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(values, 1 << bucket)) });
+            }
+        }
+    }
+}
@@ -0,0 +1,950 @@
+//! Deterministic scheduler for reproducible concurrency tests.
+//!
+//! Under the `check-sched` feature, the structures that opt into it use the atomics of
+//! [`sync::atomic`] instead of those of `core`, the same way they use those of loom under
+//! `check-loom`, and the tests use [`thread`] and [`model`]. Each iteration of [`model`] runs the
+//! threads of the model one at a time, as virtual threads, and switches between them only at the
+//! scheduling points: the operations on the atomics, and spawning, joining and yielding a thread.
+//! At each of them, the thread that runs next is picked at random from the seed of the iteration,
+//! so an interleaving is reproduced by running its seed again.
+//!
+//! Unlike loom, the scheduler samples the interleavings instead of exploring all of them, so it
+//! runs models that are too large for loom, and the longer it runs, the more preemptions the
+//! interleavings it finds may need. It runs only the sequentially consistent executions, though,
+//! so it does not check the orderings of the atomics.
+//!
+//! `SCHED_ITERATIONS` is the number of iterations, 1000 by default. A failing iteration reports its
+//! seed, and `SCHED_SEED` runs only the iteration of that seed. An iteration is reproducible only
+//! if the model is deterministic apart from its threads, e.g., it does not seed its hashers at
+//! random. A thread of the model may block only by joining another one, or on the locks, condition
+//! variables and parking of [`sync`] and [`thread`], which let the other threads run while it
+//! waits: a thread that blocks otherwise, e.g., on a lock of `std`, blocks the whole model. An
+//! iteration whose threads take more than a million steps fails, since they are probably waiting
+//! for a wakeup that never comes.
+
+use core::cell::RefCell;
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+/// Iterations of a model, unless `SCHED_ITERATIONS` is set.
+const DEFAULT_ITERATIONS: u64 = 1000;
+
+/// Scheduling points of an iteration after which it fails.
+const MAX_STEPS: usize = 1_000_000;
+
+/// Xorshift generator, which is enough to pick the threads and is the same on every platform.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // SplitMix64 of the seed, so that consecutive seeds start far apart.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Runnable,
+    /// Waiting for the thread to finish.
+    Joining(usize),
+    Finished,
+}
+
+#[derive(Debug)]
+struct State {
+    /// The thread that runs.
+    current: usize,
+    /// Status of each thread, the thread of the model first.
+    threads: Vec<Status>,
+    rng: Rng,
+    /// Scheduling points so far.
+    steps: usize,
+    /// Why the scheduler failed the iteration, if it did.
+    failure: Option<&'static str>,
+    /// Whether the iteration failed, so that the threads waiting for their turn unwind.
+    aborted: bool,
+}
+
+impl State {
+    /// Picks the thread that runs next at random.
+    fn pick(&mut self) {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            self.failure =
+                Some("the threads of the model take too many steps, and may never finish");
+            self.aborted = true;
+            return;
+        }
+        let runnable = (0..self.threads.len())
+            .filter(|&t| match self.threads[t] {
+                Status::Runnable => true,
+                Status::Joining(other) => self.threads[other] == Status::Finished,
+                Status::Finished => false,
+            })
+            .collect::<Vec<_>>();
+        if runnable.is_empty() {
+            if self
+                .threads
+                .iter()
+                .any(|&status| status != Status::Finished)
+            {
+                self.failure = Some("all the threads of the model are blocked");
+                self.aborted = true;
+            }
+            return;
+        }
+        let next = runnable[self.rng.below(runnable.len())];
+        self.threads[next] = Status::Runnable;
+        self.current = next;
+    }
+
+    fn is_finished(&self) -> bool {
+        self.threads
+            .iter()
+            .all(|&status| status == Status::Finished)
+    }
+}
+
+/// Payload of the threads that unwind because the iteration failed.
+struct Aborted;
+
+/// Iteration of a model.
+#[derive(Debug)]
+struct Execution {
+    state: Mutex<State>,
+    /// Notified when `current` changes, or the iteration finishes or fails.
+    turn: Condvar,
+    /// OS threads running the virtual threads, joined at the end of the iteration.
+    os_threads: Mutex<Vec<std::thread::JoinHandle<()>>>,
+}
+
+impl Execution {
+    fn new(seed: u64) -> Self {
+        Self {
+            state: Mutex::new(State {
+                current: 0,
+                threads: vec![Status::Runnable],
+                rng: Rng::new(seed),
+                steps: 0,
+                failure: None,
+                aborted: false,
+            }),
+            turn: Condvar::new(),
+            os_threads: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The state is consistent even if a thread panics, which it does not while holding it.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits until it is the turn of `me`. Unwinds if the iteration fails meanwhile.
+    fn wait_turn(&self, mut state: MutexGuard<'_, State>, me: usize) {
+        while state.current != me && !state.aborted {
+            state = self
+                .turn
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        if state.aborted {
+            drop(state);
+            panic::resume_unwind(Box::new(Aborted));
+        }
+    }
+
+    /// Lets the next thread run, and waits until it is the turn of `me` again.
+    fn switch(&self, mut state: MutexGuard<'_, State>, me: usize) {
+        state.pick();
+        self.turn.notify_all();
+        self.wait_turn(state, me);
+    }
+
+    /// Marks `me` as finished, and lets the next thread run.
+    fn finish(&self, me: usize) {
+        let mut state = self.lock();
+        state.threads[me] = Status::Finished;
+        state.pick();
+        self.turn.notify_all();
+    }
+
+    /// Fails the iteration.
+    fn abort(&self) {
+        self.lock().aborted = true;
+        self.turn.notify_all();
+    }
+}
+
+thread_local! {
+    /// The iteration the current thread is a virtual thread of, and its index.
+    static CONTEXT: RefCell<Option<(Arc<Execution>, usize)>> = const { RefCell::new(None) };
+}
+
+fn context() -> Option<(Arc<Execution>, usize)> {
+    CONTEXT.with(|context| context.borrow().clone())
+}
+
+fn set_context(context: Option<(Arc<Execution>, usize)>) {
+    CONTEXT.with(|cell| *cell.borrow_mut() = context);
+}
+
+/// Scheduling point: lets the scheduler pick the thread that runs next. Does nothing outside a
+/// model.
+fn yield_point() {
+    if let Some((execution, me)) = context() {
+        execution.switch(execution.lock(), me);
+    }
+}
+
+/// Runs `f` for `SCHED_ITERATIONS` iterations, each with its own interleaving of the threads it
+/// spawns, or only for the iteration of `SCHED_SEED` if it is set. Panics if an iteration fails,
+/// reporting its seed.
+pub fn model<F: Fn()>(f: F) {
+    let seeds = match env::var("SCHED_SEED") {
+        Ok(seed) => {
+            let seed = seed.parse().expect("SCHED_SEED is not a number");
+            seed..seed + 1
+        }
+        Err(_) => {
+            let iterations = env::var("SCHED_ITERATIONS").map_or(DEFAULT_ITERATIONS, |n| {
+                n.parse().expect("SCHED_ITERATIONS is not a number")
+            });
+            0..iterations
+        }
+    };
+    for seed in seeds {
+        if let Err(payload) = run(seed, &f) {
+            eprintln!("model failed with SCHED_SEED={seed}");
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+/// Runs `f` once, with the interleaving of `seed`, e.g., to reproduce an iteration that failed.
+pub fn replay<F: Fn()>(seed: u64, f: F) {
+    if let Err(payload) = run(seed, &f) {
+        panic::resume_unwind(payload);
+    }
+}
+
+/// Runs an iteration, and waits for all its threads.
+fn run(seed: u64, f: &dyn Fn()) -> std::thread::Result<()> {
+    assert!(context().is_none(), "models are not nested");
+    let execution = Arc::new(Execution::new(seed));
+    set_context(Some((execution.clone(), 0)));
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).and_then(|()| {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            execution.finish(0);
+            let mut state = execution.lock();
+            while !state.is_finished() && !state.aborted {
+                state = execution
+                    .turn
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        }))
+    });
+    set_context(None);
+
+    if result.is_err() {
+        execution.abort();
+    }
+    let os_threads = core::mem::take(&mut *execution.os_threads.lock().unwrap());
+    for os_thread in os_threads {
+        os_thread.join().unwrap();
+    }
+    if let Some(failure) = execution.lock().failure {
+        return Err(Box::new(failure));
+    }
+    result
+}
+
+pub mod thread {
+    //! Threads of a model, like `std::thread` otherwise.
+
+    use core::time::Duration;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex};
+
+    use super::{context, set_context, yield_point, Aborted, Execution, Status};
+
+    pub use std::thread::*;
+
+    type Output<T> = Arc<Mutex<Option<Result<T>>>>;
+
+    enum Handle<T> {
+        Std(std::thread::JoinHandle<T>),
+        Virtual {
+            execution: Arc<Execution>,
+            id: usize,
+            output: Output<T>,
+        },
+    }
+
+    /// Handle to join a thread.
+    pub struct JoinHandle<T>(Handle<T>);
+
+    impl<T> core::fmt::Debug for JoinHandle<T> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.debug_struct("JoinHandle").finish_non_exhaustive()
+        }
+    }
+
+    impl<T> JoinHandle<T> {
+        /// Waits for the thread to finish, and returns its output, or the payload of its panic.
+        /// A scheduling point in a model.
+        pub fn join(self) -> Result<T> {
+            let (execution, id, output) = match self.0 {
+                Handle::Std(handle) => return handle.join(),
+                Handle::Virtual {
+                    execution,
+                    id,
+                    output,
+                } => (execution, id, output),
+            };
+            let (_, me) = context().expect("a thread of a model is joined outside the model");
+            let mut state = execution.lock();
+            if state.threads[id] != Status::Finished {
+                state.threads[me] = Status::Joining(id);
+                execution.switch(state, me);
+            } else {
+                drop(state);
+            }
+            let output = output.lock().unwrap().take();
+            output.expect("a finished thread has its output")
+        }
+    }
+
+    /// Spawns a thread, which is a virtual thread of the model if called in one. A scheduling
+    /// point in a model.
+    pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (execution, me) = match context() {
+            Some(context) => context,
+            None => return JoinHandle(Handle::Std(std::thread::spawn(f))),
+        };
+        let mut state = execution.lock();
+        let id = state.threads.len();
+        state.threads.push(Status::Runnable);
+        let output = Output::default();
+        let os_thread = std::thread::spawn({
+            let execution = execution.clone();
+            let output = output.clone();
+            move || {
+                set_context(Some((execution.clone(), id)));
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    execution.wait_turn(execution.lock(), id);
+                    f()
+                }));
+                set_context(None);
+                if let Err(payload) = &result {
+                    if payload.is::<Aborted>() {
+                        return;
+                    }
+                }
+                *output.lock().unwrap() = Some(result);
+                execution.finish(id);
+            }
+        });
+        execution.os_threads.lock().unwrap().push(os_thread);
+        execution.switch(state, me);
+        JoinHandle(Handle::Virtual {
+            execution,
+            id,
+            output,
+        })
+    }
+
+    /// Lets another thread run. A scheduling point in a model.
+    pub fn yield_now() {
+        if context().is_some() {
+            yield_point();
+        } else {
+            std::thread::yield_now();
+        }
+    }
+
+    /// Blocks until the thread is unparked. In a model, lets another thread run and returns, as if
+    /// woken up spuriously, so the caller checks its condition again.
+    pub fn park() {
+        if context().is_some() {
+            yield_point();
+        } else {
+            std::thread::park();
+        }
+    }
+
+    /// Blocks until the thread is unparked or `dur` elapses. In a model, like [`park`].
+    pub fn park_timeout(dur: Duration) {
+        if context().is_some() {
+            yield_point();
+        } else {
+            std::thread::park_timeout(dur);
+        }
+    }
+}
+
+pub mod sync {
+    //! Synchronization primitives of a model, like `std::sync` except for the atomics, locks and
+    //! condition variables.
+    //!
+    //! Acquiring a lock is a scheduling point, and a thread that waits for a lock lets the other
+    //! threads run until it is released. Waiting on a condition variable releases the lock and lets
+    //! the other threads run, and returns as if woken up spuriously, so the callers that check
+    //! their condition again in a loop, as they should, wait until it holds.
+
+    use core::fmt;
+    use core::ops::{Deref, DerefMut};
+    use core::time::Duration;
+
+    use super::{context, yield_point};
+
+    pub use std::sync::*;
+
+    /// Maps the guard of a lock result.
+    fn map_result<G, H>(result: LockResult<G>, f: impl FnOnce(G) -> H) -> LockResult<H> {
+        match result {
+            Ok(guard) => Ok(f(guard)),
+            Err(error) => Err(PoisonError::new(f(error.into_inner()))),
+        }
+    }
+
+    /// Acquires a lock with `try_lock`, letting the other threads run until it succeeds in a
+    /// model, or with `lock` otherwise.
+    fn acquire<G>(
+        try_lock: impl Fn() -> TryLockResult<G>,
+        lock: impl FnOnce() -> LockResult<G>,
+    ) -> LockResult<G> {
+        if context().is_none() {
+            return lock();
+        }
+        loop {
+            yield_point();
+            match try_lock() {
+                Ok(guard) => return Ok(guard),
+                Err(TryLockError::Poisoned(error)) => return Err(error),
+                Err(TryLockError::WouldBlock) => {}
+            }
+        }
+    }
+
+    /// Maps the guard of a `try_lock` result.
+    fn map_try_result<G, H>(result: TryLockResult<G>, f: impl FnOnce(G) -> H) -> TryLockResult<H> {
+        match result {
+            Ok(guard) => Ok(f(guard)),
+            Err(TryLockError::Poisoned(error)) => Err(TryLockError::Poisoned(PoisonError::new(f(
+                error.into_inner(),
+            )))),
+            Err(TryLockError::WouldBlock) => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// `Mutex` whose acquisitions are scheduling points.
+    #[derive(Debug, Default)]
+    pub struct Mutex<T: ?Sized> {
+        inner: std::sync::Mutex<T>,
+    }
+
+    /// Guard of a [`Mutex`].
+    pub struct MutexGuard<'a, T: ?Sized> {
+        lock: &'a Mutex<T>,
+        guard: std::sync::MutexGuard<'a, T>,
+    }
+
+    impl<T> Mutex<T> {
+        /// Creates an unlocked mutex holding `value`.
+        pub const fn new(value: T) -> Self {
+            Self {
+                inner: std::sync::Mutex::new(value),
+            }
+        }
+
+        /// Returns the value.
+        pub fn into_inner(self) -> LockResult<T> {
+            self.inner.into_inner()
+        }
+    }
+
+    impl<T: ?Sized> Mutex<T> {
+        /// Acquires the mutex. A scheduling point in a model.
+        pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+            let result = acquire(|| self.inner.try_lock(), || self.inner.lock());
+            map_result(result, |guard| MutexGuard { lock: self, guard })
+        }
+
+        /// Acquires the mutex if it is not locked. A scheduling point in a model.
+        pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+            yield_point();
+            map_try_result(self.inner.try_lock(), |guard| MutexGuard {
+                lock: self,
+                guard,
+            })
+        }
+
+        /// Returns whether a thread panicked while holding the mutex.
+        pub fn is_poisoned(&self) -> bool {
+            self.inner.is_poisoned()
+        }
+
+        /// Returns a mutable reference to the value.
+        pub fn get_mut(&mut self) -> LockResult<&mut T> {
+            self.inner.get_mut()
+        }
+    }
+
+    impl<T> From<T> for Mutex<T> {
+        fn from(value: T) -> Self {
+            Self::new(value)
+        }
+    }
+
+    impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.guard
+        }
+    }
+
+    impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            &mut self.guard
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.guard.fmt(f)
+        }
+    }
+
+    /// Whether a wait on a [`Condvar`] timed out.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct WaitTimeoutResult(bool);
+
+    impl WaitTimeoutResult {
+        /// Returns whether the wait timed out.
+        pub fn timed_out(&self) -> bool {
+            self.0
+        }
+    }
+
+    /// `Condvar` whose waits let the other threads of a model run.
+    #[derive(Debug, Default)]
+    pub struct Condvar {
+        inner: std::sync::Condvar,
+    }
+
+    impl Condvar {
+        /// Creates a condition variable.
+        pub const fn new() -> Self {
+            Self {
+                inner: std::sync::Condvar::new(),
+            }
+        }
+
+        /// Releases the lock of `guard`, waits for a notification and acquires the lock again. In
+        /// a model, lets the other threads run and returns, as if woken up spuriously.
+        pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+            let lock = guard.lock;
+            if context().is_some() {
+                drop(guard);
+                return lock.lock();
+            }
+            map_result(self.inner.wait(guard.guard), |guard| MutexGuard {
+                lock,
+                guard,
+            })
+        }
+
+        /// Like [`wait`](Self::wait), but gives up once `dur` elapses. A wait in a model does not
+        /// time out.
+        pub fn wait_timeout<'a, T>(
+            &self,
+            guard: MutexGuard<'a, T>,
+            dur: Duration,
+        ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+            if context().is_some() {
+                return map_result(self.wait(guard), |guard| (guard, WaitTimeoutResult(false)));
+            }
+            let lock = guard.lock;
+            map_result(
+                self.inner.wait_timeout(guard.guard, dur),
+                |(guard, result)| {
+                    (
+                        MutexGuard { lock, guard },
+                        WaitTimeoutResult(result.timed_out()),
+                    )
+                },
+            )
+        }
+
+        /// Wakes up a waiting thread.
+        pub fn notify_one(&self) {
+            self.inner.notify_one();
+        }
+
+        /// Wakes up all the waiting threads.
+        pub fn notify_all(&self) {
+            self.inner.notify_all();
+        }
+    }
+
+    /// `RwLock` whose acquisitions are scheduling points.
+    #[derive(Debug, Default)]
+    pub struct RwLock<T: ?Sized> {
+        inner: std::sync::RwLock<T>,
+    }
+
+    /// Shared guard of an [`RwLock`].
+    pub struct RwLockReadGuard<'a, T: ?Sized>(std::sync::RwLockReadGuard<'a, T>);
+
+    /// Exclusive guard of an [`RwLock`].
+    pub struct RwLockWriteGuard<'a, T: ?Sized>(std::sync::RwLockWriteGuard<'a, T>);
+
+    impl<T> RwLock<T> {
+        /// Creates an unlocked lock holding `value`.
+        pub const fn new(value: T) -> Self {
+            Self {
+                inner: std::sync::RwLock::new(value),
+            }
+        }
+
+        /// Returns the value.
+        pub fn into_inner(self) -> LockResult<T> {
+            self.inner.into_inner()
+        }
+    }
+
+    impl<T: ?Sized> RwLock<T> {
+        /// Acquires the lock shared. A scheduling point in a model.
+        pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+            let result = acquire(|| self.inner.try_read(), || self.inner.read());
+            map_result(result, RwLockReadGuard)
+        }
+
+        /// Acquires the lock shared if it is not held exclusively. A scheduling point in a model.
+        pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+            yield_point();
+            map_try_result(self.inner.try_read(), RwLockReadGuard)
+        }
+
+        /// Acquires the lock exclusively. A scheduling point in a model.
+        pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+            let result = acquire(|| self.inner.try_write(), || self.inner.write());
+            map_result(result, RwLockWriteGuard)
+        }
+
+        /// Acquires the lock exclusively if it is not held. A scheduling point in a model.
+        pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+            yield_point();
+            map_try_result(self.inner.try_write(), RwLockWriteGuard)
+        }
+
+        /// Returns whether a thread panicked while holding the lock exclusively.
+        pub fn is_poisoned(&self) -> bool {
+            self.inner.is_poisoned()
+        }
+
+        /// Returns a mutable reference to the value.
+        pub fn get_mut(&mut self) -> LockResult<&mut T> {
+            self.inner.get_mut()
+        }
+    }
+
+    impl<T> From<T> for RwLock<T> {
+        fn from(value: T) -> Self {
+            Self::new(value)
+        }
+    }
+
+    impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.0
+        }
+    }
+
+    impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            &self.0
+        }
+    }
+
+    impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            &mut self.0
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            (**self).fmt(f)
+        }
+    }
+
+    impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            (**self).fmt(f)
+        }
+    }
+
+    pub mod atomic {
+        //! Atomics whose operations are scheduling points.
+
+        use super::super::yield_point;
+
+        pub use core::sync::atomic::{compiler_fence, Ordering};
+
+        /// Fence, after a scheduling point.
+        pub fn fence(order: Ordering) {
+            yield_point();
+            core::sync::atomic::fence(order);
+        }
+
+        macro_rules! atomic {
+            ($name:ident, $value:ty $(, $op:ident)*) => {
+                #[doc = concat!(
+                    "`", stringify!($name), "` whose operations are scheduling points."
+                )]
+                #[derive(Debug, Default)]
+                pub struct $name(core::sync::atomic::$name);
+
+                impl $name {
+                    /// Creates an atomic holding `value`.
+                    pub const fn new(value: $value) -> Self {
+                        Self(core::sync::atomic::$name::new(value))
+                    }
+
+                    /// Returns a mutable reference to the value, which is not a scheduling point.
+                    pub fn get_mut(&mut self) -> &mut $value {
+                        self.0.get_mut()
+                    }
+
+                    /// Returns the value.
+                    pub fn into_inner(self) -> $value {
+                        self.0.into_inner()
+                    }
+
+                    /// Loads the value.
+                    pub fn load(&self, order: Ordering) -> $value {
+                        yield_point();
+                        self.0.load(order)
+                    }
+
+                    /// Stores `value`.
+                    pub fn store(&self, value: $value, order: Ordering) {
+                        yield_point();
+                        self.0.store(value, order)
+                    }
+
+                    /// Stores `value`, and returns the previous value.
+                    pub fn swap(&self, value: $value, order: Ordering) -> $value {
+                        yield_point();
+                        self.0.swap(value, order)
+                    }
+
+                    /// Stores `new` if the value is `current`.
+                    pub fn compare_exchange(
+                        &self,
+                        current: $value,
+                        new: $value,
+                        success: Ordering,
+                        failure: Ordering,
+                    ) -> Result<$value, $value> {
+                        yield_point();
+                        self.0.compare_exchange(current, new, success, failure)
+                    }
+
+                    /// Stores `new` if the value is `current`, and may fail spuriously.
+                    pub fn compare_exchange_weak(
+                        &self,
+                        current: $value,
+                        new: $value,
+                        success: Ordering,
+                        failure: Ordering,
+                    ) -> Result<$value, $value> {
+                        yield_point();
+                        self.0.compare_exchange_weak(current, new, success, failure)
+                    }
+
+                    /// Updates the value with `f` until it succeeds or `f` returns `None`.
+                    pub fn fetch_update<F>(
+                        &self,
+                        set_order: Ordering,
+                        fetch_order: Ordering,
+                        f: F,
+                    ) -> Result<$value, $value>
+                    where
+                        F: FnMut($value) -> Option<$value>,
+                    {
+                        yield_point();
+                        self.0.fetch_update(set_order, fetch_order, f)
+                    }
+
+                    $(
+                        #[doc = concat!("Like `", stringify!($op), "` of `core`.")]
+                        pub fn $op(&self, value: $value, order: Ordering) -> $value {
+                            yield_point();
+                            self.0.$op(value, order)
+                        }
+                    )*
+                }
+
+                impl From<$value> for $name {
+                    fn from(value: $value) -> Self {
+                        Self::new(value)
+                    }
+                }
+            };
+        }
+
+        atomic!(AtomicBool, bool, fetch_and, fetch_nand, fetch_or, fetch_xor);
+        atomic!(
+            AtomicU8, u8, fetch_add, fetch_sub, fetch_and, fetch_or, fetch_xor, fetch_max,
+            fetch_min
+        );
+        atomic!(
+            AtomicU32, u32, fetch_add, fetch_sub, fetch_and, fetch_or, fetch_xor, fetch_max,
+            fetch_min
+        );
+        atomic!(
+            AtomicU64, u64, fetch_add, fetch_sub, fetch_and, fetch_or, fetch_xor, fetch_max,
+            fetch_min
+        );
+        atomic!(
+            AtomicUsize,
+            usize,
+            fetch_add,
+            fetch_sub,
+            fetch_and,
+            fetch_or,
+            fetch_xor,
+            fetch_max,
+            fetch_min
+        );
+        atomic!(
+            AtomicI64, i64, fetch_add, fetch_sub, fetch_and, fetch_or, fetch_xor, fetch_max,
+            fetch_min
+        );
+        atomic!(
+            AtomicIsize,
+            isize,
+            fetch_add,
+            fetch_sub,
+            fetch_and,
+            fetch_or,
+            fetch_xor,
+            fetch_max,
+            fetch_min
+        );
+
+        /// `AtomicPtr` whose operations are scheduling points.
+        #[derive(Debug)]
+        pub struct AtomicPtr<T>(core::sync::atomic::AtomicPtr<T>);
+
+        impl<T> Default for AtomicPtr<T> {
+            fn default() -> Self {
+                Self(core::sync::atomic::AtomicPtr::default())
+            }
+        }
+
+        impl<T> AtomicPtr<T> {
+            /// Creates an atomic holding `value`.
+            pub const fn new(value: *mut T) -> Self {
+                Self(core::sync::atomic::AtomicPtr::new(value))
+            }
+
+            /// Returns a mutable reference to the pointer, which is not a scheduling point.
+            pub fn get_mut(&mut self) -> &mut *mut T {
+                self.0.get_mut()
+            }
+
+            /// Returns the pointer.
+            pub fn into_inner(self) -> *mut T {
+                self.0.into_inner()
+            }
+
+            /// Loads the pointer.
+            pub fn load(&self, order: Ordering) -> *mut T {
+                yield_point();
+                self.0.load(order)
+            }
+
+            /// Stores `value`.
+            pub fn store(&self, value: *mut T, order: Ordering) {
+                yield_point();
+                self.0.store(value, order)
+            }
+
+            /// Stores `value`, and returns the previous pointer.
+            pub fn swap(&self, value: *mut T, order: Ordering) -> *mut T {
+                yield_point();
+                self.0.swap(value, order)
+            }
+
+            /// Stores `new` if the pointer is `current`.
+            pub fn compare_exchange(
+                &self,
+                current: *mut T,
+                new: *mut T,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<*mut T, *mut T> {
+                yield_point();
+                self.0.compare_exchange(current, new, success, failure)
+            }
+
+            /// Stores `new` if the pointer is `current`, and may fail spuriously.
+            pub fn compare_exchange_weak(
+                &self,
+                current: *mut T,
+                new: *mut T,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<*mut T, *mut T> {
+                yield_point();
+                self.0.compare_exchange_weak(current, new, success, failure)
+            }
+
+            /// Updates the pointer with `f` until it succeeds or `f` returns `None`.
+            pub fn fetch_update<F>(
+                &self,
+                set_order: Ordering,
+                fetch_order: Ordering,
+                f: F,
+            ) -> Result<*mut T, *mut T>
+            where
+                F: FnMut(*mut T) -> Option<*mut T>,
+            {
+                yield_point();
+                self.0.fetch_update(set_order, fetch_order, f)
+            }
+        }
+
+        impl<T> From<*mut T> for AtomicPtr<T> {
+            fn from(value: *mut T) -> Self {
+                Self::new(value)
+            }
+        }
+    }
+}
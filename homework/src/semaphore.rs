@@ -0,0 +1,119 @@
+//! Counting semaphore with atomics and parking.
+
+use core::fmt;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::parker::WaitList;
+
+/// Counting semaphore, which hands out at most a given number of permits at a time.
+///
+/// The permits are counted with an atomic, so that acquiring and releasing an available permit
+/// takes a single CAS. Only the threads that find no permit park, and a release takes the lock of
+/// the parked threads to wake one of them up only if there is any.
+pub struct Semaphore {
+    /// Number of available permits.
+    permits: AtomicUsize,
+    /// The threads parked in `acquire`.
+    waiters: WaitList,
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A permit acquired from a [`Semaphore`]. It is released when dropped.
+#[derive(Debug)]
+pub struct SemaphorePermit<'s> {
+    semaphore: &'s Semaphore,
+}
+
+impl Semaphore {
+    /// Creates a semaphore with `permits` available permits.
+    pub fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+            waiters: WaitList::new(),
+        }
+    }
+
+    /// Takes an available permit, if any.
+    fn take(&self) -> bool {
+        // Acquire: the work done under the permit happens after its previous holder's.
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |permits| {
+                permits.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Takes a permit, waiting for one until `deadline` if any.
+    fn take_until(&self, deadline: Option<Instant>) -> bool {
+        self.waiters
+            .wait_until(deadline, || self.take().then_some(()))
+            .is_some()
+    }
+
+    /// Acquires a permit, blocking until one is available.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        let _ = self.take_until(None);
+        SemaphorePermit { semaphore: self }
+    }
+
+    /// Acquires a permit, blocking until one is available for at most `timeout`. Returns `None` if
+    /// none became available.
+    pub fn acquire_timeout(&self, timeout: Duration) -> Option<SemaphorePermit<'_>> {
+        if self.take_until(Some(Instant::now() + timeout)) {
+            Some(SemaphorePermit { semaphore: self })
+        } else {
+            None
+        }
+    }
+
+    /// Acquires a permit without blocking. Returns `None` if none is available.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        if self.take() {
+            Some(SemaphorePermit { semaphore: self })
+        } else {
+            None
+        }
+    }
+
+    /// Adds a permit, waking up a thread blocked in `acquire` if any.
+    ///
+    /// Pairs with [`SemaphorePermit::forget`] to hold a permit beyond the lifetime of a guard.
+    pub fn release(&self) {
+        // Release: pairs with the Acquire in `take`.
+        let _ = self.permits.fetch_add(1, Ordering::Release);
+        self.waiters.notify_one();
+    }
+
+    /// Returns the number of available permits.
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+}
+
+impl SemaphorePermit<'_> {
+    /// Consumes the permit without releasing it. It is up to the caller to
+    /// [`release`](Semaphore::release) it later, if ever.
+    pub fn forget(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.release();
+    }
+}
@@ -0,0 +1,210 @@
+//! Sequence lock for plain values.
+//!
+//! A writer makes the sequence number odd while it writes, and readers retry until they read the
+//! value without a write in between. Readers never block the writers or each other, so this suits
+//! read-mostly values such as statistics counters.
+//!
+//! A reader may read the value while it is being written, so it must copy the value out and use
+//! the copy only after validating it: the copy may be torn, i.e. mix the bytes of several writes,
+//! and a reference to the value would observe the writes as they happen. The value is copied word
+//! by word through atomics, so that racing with a write is not a data race. The words are stored
+//! as integers, so the values are [`Plain`], whose bytes are all initialized.
+
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{fence, AtomicUsize, Ordering};
+
+use crate::backoff::Backoff;
+use crate::Plain;
+
+/// Sequence lock that protects a value of type `T`.
+#[derive(Debug)]
+pub struct SeqLock<T> {
+    /// Odd while a writer holds the lock. Incremented by 2 on each write.
+    seq: AtomicUsize,
+    /// The bytes of the value, the last word padded with zeroes.
+    words: Box<[AtomicUsize]>,
+    _marker: PhantomData<T>,
+}
+
+// The values are copied between threads.
+unsafe impl<T: Plain + Send> Send for SeqLock<T> {}
+unsafe impl<T: Plain + Send> Sync for SeqLock<T> {}
+
+const WORD: usize = mem::size_of::<usize>();
+
+impl<T: Plain + Default> Default for SeqLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Plain> SeqLock<T> {
+    /// Creates a sequence lock holding `t`.
+    pub fn new(t: T) -> Self {
+        let lock = Self {
+            seq: AtomicUsize::new(0),
+            words: (0..mem::size_of::<T>())
+                .step_by(WORD)
+                .map(|_| AtomicUsize::new(0))
+                .collect(),
+            _marker: PhantomData,
+        };
+        lock.store(t);
+        lock
+    }
+
+    /// Returns the number of the bytes of `T` in the `index`-th word.
+    fn bytes(index: usize) -> usize {
+        (mem::size_of::<T>() - index * WORD).min(WORD)
+    }
+
+    /// Copies `t` into the words.
+    fn store(&self, t: T) {
+        let src = &t as *const T as *const u8;
+        for (index, word) in self.words.iter().enumerate() {
+            let mut bytes = 0usize;
+            // SAFETY: The range is within `t` and `bytes`, and the bytes of `t` are all
+            // initialized.
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    src.add(index * WORD),
+                    &mut bytes as *mut usize as *mut u8,
+                    Self::bytes(index),
+                )
+            };
+            word.store(bytes, Ordering::Relaxed);
+        }
+    }
+
+    /// Copies the words out, without validating the copy.
+    fn load(&self) -> MaybeUninit<T> {
+        let mut t = MaybeUninit::<T>::uninit();
+        let dst = t.as_mut_ptr() as *mut u8;
+        for (index, word) in self.words.iter().enumerate() {
+            let bytes = word.load(Ordering::Relaxed);
+            // SAFETY: The range is within `bytes` and `t`.
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    &bytes as *const usize as *const u8,
+                    dst.add(index * WORD),
+                    Self::bytes(index),
+                )
+            };
+        }
+        t
+    }
+
+    /// Reads the value with a single attempt, and returns it with the sequence number it was read
+    /// at. Returns `None` if a write raced with the read.
+    fn try_read_seq(&self) -> Option<(usize, T)> {
+        // Acquire: the previous write happens before the copy.
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq & 1 == 1 {
+            return None;
+        }
+        let t = self.load();
+        // Acquire: the copy happens before the sequence number is checked again, so that a write
+        // that the copy saw is seen here as well.
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) != seq {
+            return None;
+        }
+        // SAFETY: No write raced with the copy, so it is a copy of a value written as a whole.
+        Some((seq, unsafe { t.assume_init() }))
+    }
+
+    /// Reads the value with a single attempt. Returns `None` if a write raced with the read.
+    pub fn try_read(&self) -> Option<T> {
+        self.try_read_seq().map(|(_, t)| t)
+    }
+
+    /// Reads the value, retrying while writes race with the read.
+    pub fn read(&self) -> T {
+        let backoff = Backoff::new();
+        loop {
+            if let Some(t) = self.try_read() {
+                return t;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Reads the value without validating it against the writes.
+    ///
+    /// # Safety
+    ///
+    /// The result may be torn, i.e. consist of the bytes of different writes. Every mix of the
+    /// bytes of values of `T` must be a valid `T`.
+    pub unsafe fn read_unvalidated(&self) -> T {
+        self.load().assume_init()
+    }
+
+    /// Acquires the writer's lock if the sequence number is still `seq`, which is even.
+    fn try_write_lock(&self, seq: usize) -> bool {
+        if self
+            .seq
+            .compare_exchange(
+                seq,
+                seq.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return false;
+        }
+        // Release: readers that see the writes below see the odd sequence number as well.
+        fence(Ordering::Release);
+        true
+    }
+
+    /// Acquires the writer's lock, returning the sequence number before it.
+    fn write_lock(&self) -> usize {
+        let backoff = Backoff::new();
+        loop {
+            let seq = self.seq.load(Ordering::Relaxed);
+            if seq & 1 == 0 && self.try_write_lock(seq) {
+                return seq;
+            }
+            backoff.snooze();
+        }
+    }
+
+    /// Replaces the value with `t`.
+    pub fn write(&self, t: T) {
+        let seq = self.write_lock();
+        self.store(t);
+        // Release: the writes happen before the next readers' copies.
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    /// Replaces the value with `f` applied to it, retrying if another writer changes it
+    /// meanwhile. Returns the new value.
+    ///
+    /// `f` runs on a copy without the lock, so it may run several times, and the lock is not left
+    /// held if it panics.
+    pub fn update<F: FnMut(T) -> T>(&self, mut f: F) -> T {
+        let backoff = Backoff::new();
+        loop {
+            if let Some((seq, t)) = self.try_read_seq() {
+                let t = f(t);
+                // No write happened since the copy if the sequence number is still the same.
+                if self.try_write_lock(seq) {
+                    self.store(t);
+                    // Release: the writes happen before the next readers' copies.
+                    self.seq.store(seq.wrapping_add(2), Ordering::Release);
+                    return t;
+                }
+            }
+            backoff.snooze();
+        }
+    }
+}
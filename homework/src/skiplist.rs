@@ -0,0 +1,537 @@
+//! Lock-free skiplist with hazard pointers.
+//!
+//! Based on the lock-free skiplist of Herlihy and Shavit, "The Art of Multiprocessor Programming",
+//! Section 14.4. A node is removed by marking its next pointers from the top level down, and the
+//! marked nodes are unlinked ("snipped") by the traversals that pass through them.
+
+use core::marker::PhantomData;
+use core::mem;
+use core::ptr;
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use rand::{thread_rng, Rng};
+use std::collections::HashSet;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::hazard_pointer::{retire, Shield};
+
+/// Maximum number of levels.
+#[cfg(not(feature = "check-loom"))]
+const MAX_HEIGHT: usize = 16;
+/// Loom explores every access to every level, so it only checks a couple of them.
+#[cfg(feature = "check-loom")]
+const MAX_HEIGHT: usize = 2;
+
+/// Returns `true` if the node that has `next` is removed at its level.
+fn is_marked<T>(next: *mut T) -> bool {
+    next as usize & 1 == 1
+}
+
+fn marked<T>(next: *mut T) -> *mut T {
+    (next as usize | 1) as *mut T
+}
+
+fn unmarked<T>(next: *mut T) -> *mut T {
+    (next as usize & !1) as *mut T
+}
+
+/// Returns a random height, where each level is taken with probability 1/2.
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+fn random_height() -> usize {
+    let height = 1 + thread_rng().gen::<u32>().trailing_ones() as usize;
+    height.min(MAX_HEIGHT)
+}
+
+/// Loom and the scheduler require the execution to be deterministic, so every node takes all
+/// levels.
+#[cfg(any(feature = "check-loom", feature = "check-sched"))]
+fn random_height() -> usize {
+    MAX_HEIGHT
+}
+
+#[derive(Debug)]
+struct Node<K, V> {
+    key: K,
+    value: V,
+    /// Number of levels the node is linked or is yet to be linked at, plus one while it is being
+    /// inserted. The thread that drops it to zero retires the node.
+    refs: AtomicUsize,
+    /// Next nodes at each level. Marked when the node is removed, after which it never changes.
+    tower: Box<[AtomicPtr<Node<K, V>>]>,
+}
+
+impl<K, V> Node<K, V> {
+    /// Drops `count` references, retiring the node if none is left.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be valid, and the references must be owned by the caller.
+    unsafe fn release(node: *mut Self, count: usize) {
+        if (*node).refs.fetch_sub(count, Ordering::AcqRel) == count {
+            retire(node);
+        }
+    }
+
+    /// Marks the node removed at `level`. Returns `false` if it is already marked.
+    fn mark(&self, level: usize) -> bool {
+        let mut next = self.tower[level].load(Ordering::Relaxed);
+        loop {
+            if is_marked(next) {
+                return false;
+            }
+            match self.tower[level].compare_exchange(
+                next,
+                marked(next),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => next = current,
+            }
+        }
+    }
+}
+
+/// Result of `SkipMap::find`: for each level, the link of the last node with a smaller key (or
+/// the head) and the node after it, both protected by the shields.
+struct Position<K, V> {
+    preds: [*mut Node<K, V>; MAX_HEIGHT],
+    links: [*const AtomicPtr<Node<K, V>>; MAX_HEIGHT],
+    succs: [*mut Node<K, V>; MAX_HEIGHT],
+    pred_shields: [Shield<Node<K, V>>; MAX_HEIGHT],
+    succ_shields: [Shield<Node<K, V>>; MAX_HEIGHT],
+}
+
+impl<K, V> Position<K, V> {
+    fn new() -> Self {
+        Self {
+            preds: [ptr::null_mut(); MAX_HEIGHT],
+            links: [ptr::null(); MAX_HEIGHT],
+            succs: [ptr::null_mut(); MAX_HEIGHT],
+            pred_shields: [(); MAX_HEIGHT].map(|_| Shield::default()),
+            succ_shields: [(); MAX_HEIGHT].map(|_| Shield::default()),
+        }
+    }
+}
+
+/// Lock-free ordered map based on a skiplist, whose nodes are reclaimed with hazard pointers.
+#[derive(Debug)]
+pub struct SkipMap<K, V> {
+    head: [AtomicPtr<Node<K, V>>; MAX_HEIGHT],
+}
+
+// The keys and values are shared with the readers, and dropped by whichever thread frees the node.
+unsafe impl<K: Send + Sync, V: Send + Sync> Send for SkipMap<K, V> {}
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for SkipMap<K, V> {}
+
+impl<K, V> Default for SkipMap<K, V> {
+    fn default() -> Self {
+        Self {
+            head: [(); MAX_HEIGHT].map(|_| AtomicPtr::new(ptr::null_mut())),
+        }
+    }
+}
+
+impl<K, V> SkipMap<K, V> {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the link at `level` of `node`, or of the head if `node` is null.
+    ///
+    /// # Safety
+    ///
+    /// `node` must be null or protected.
+    unsafe fn link(&self, node: *mut Node<K, V>, level: usize) -> &AtomicPtr<Node<K, V>> {
+        if node.is_null() {
+            &self.head[level]
+        } else {
+            &(*node).tower[level]
+        }
+    }
+
+    /// Returns an iterator over the entries in the ascending order of the keys.
+    ///
+    /// The iterator does not see a consistent snapshot: entries inserted or removed during the
+    /// iteration may or may not be visited. The keys it yields are strictly increasing though.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            map: self,
+            last: ptr::null_mut(),
+            last_shield: Shield::default(),
+            cursor_shield: Shield::default(),
+            next_shield: Shield::default(),
+        }
+    }
+}
+
+impl<K: Ord, V> SkipMap<K, V> {
+    /// Finds the position of `key`, snipping the removed nodes on the way. Returns `true` if
+    /// `pos.succs[0]` has `key`.
+    fn find(&self, key: &K, pos: &mut Position<K, V>) -> bool {
+        'retry: loop {
+            let mut pred = ptr::null_mut();
+            for level in (0..MAX_HEIGHT).rev() {
+                // `pred` is already protected by the shield of the upper level.
+                pos.pred_shields[level].set(pred);
+                // SAFETY: `pred` is null or protected.
+                let mut link = unsafe { self.link(pred, level) };
+                let mut curr = link.load(Ordering::Acquire);
+                loop {
+                    // `pred` is removed, so `curr` may be reclaimed.
+                    if is_marked(curr) {
+                        continue 'retry;
+                    }
+                    if curr.is_null() {
+                        break;
+                    }
+                    // `pred` is not removed at this level, so neither is `curr` while it is linked.
+                    if pos.succ_shields[level].try_protect(curr, link).is_err() {
+                        continue 'retry;
+                    }
+                    // SAFETY: `curr` is protected and validated.
+                    let curr_ref = unsafe { &*curr };
+                    let succ = curr_ref.tower[level].load(Ordering::Acquire);
+                    if is_marked(succ) {
+                        if link
+                            .compare_exchange(
+                                curr,
+                                unmarked(succ),
+                                Ordering::Release,
+                                Ordering::Relaxed,
+                            )
+                            .is_err()
+                        {
+                            continue 'retry;
+                        }
+                        // SAFETY: The CAS above unlinked `curr` at this level, which owned a
+                        // reference.
+                        unsafe { Node::release(curr, 1) };
+                        curr = unmarked(succ);
+                        continue;
+                    }
+                    if curr_ref.key >= *key {
+                        break;
+                    }
+                    pred = curr;
+                    pos.pred_shields[level].set(pred);
+                    link = &curr_ref.tower[level];
+                    curr = succ;
+                }
+                pos.preds[level] = pred;
+                pos.links[level] = link;
+                pos.succs[level] = curr;
+            }
+            // SAFETY: `succs[0]` is null or protected.
+            return matches!(unsafe { pos.succs[0].as_ref() }, Some(succ) if succ.key == *key);
+        }
+    }
+
+    /// Inserts `key` with `value`. Returns them back if the map already has `key`.
+    pub fn insert(&self, key: K, value: V) -> Result<(), (K, V)> {
+        let mut pos = Position::new();
+        if self.find(&key, &mut pos) {
+            return Err((key, value));
+        }
+
+        let height = random_height();
+        let node = Box::into_raw(Box::new(Node {
+            key,
+            value,
+            refs: AtomicUsize::new(height + 1),
+            tower: (0..height)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+        }));
+        // SAFETY: The inserting thread owns a reference to the node.
+        let node_ref = unsafe { &*node };
+
+        loop {
+            for level in 0..height {
+                node_ref.tower[level].store(pos.succs[level], Ordering::Relaxed);
+            }
+            // SAFETY: The link is in the head or in a protected node.
+            let link = unsafe { &*pos.links[0] };
+            // Release: the node is initialized before it is published.
+            if link
+                .compare_exchange(pos.succs[0], node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                break;
+            }
+            if self.find(&node_ref.key, &mut pos) {
+                // SAFETY: The node was not published.
+                let Node { key, value, .. } = *unsafe { Box::from_raw(node) };
+                return Err((key, value));
+            }
+        }
+
+        // Links the upper levels, unless the node is removed meanwhile.
+        let mut linked = 1;
+        'levels: for level in 1..height {
+            loop {
+                let next = node_ref.tower[level].load(Ordering::Acquire);
+                if is_marked(next) {
+                    break 'levels;
+                }
+                let succ = pos.succs[level];
+                if next != succ
+                    && node_ref.tower[level]
+                        .compare_exchange(next, succ, Ordering::Release, Ordering::Relaxed)
+                        .is_err()
+                {
+                    continue;
+                }
+                // SAFETY: The link is in the head or in a protected node.
+                let link = unsafe { &*pos.links[level] };
+                if link
+                    .compare_exchange(succ, node, Ordering::Release, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    linked += 1;
+                    continue 'levels;
+                }
+                let _ = self.find(&node_ref.key, &mut pos);
+            }
+        }
+
+        // The node may have been removed while the upper levels were linked, before any traversal
+        // could snip them.
+        if is_marked(node_ref.tower[0].load(Ordering::Acquire)) {
+            let _ = self.find(&node_ref.key, &mut pos);
+        }
+        // SAFETY: The levels that are not linked and the inserting thread owned the references.
+        unsafe { Node::release(node, height - linked + 1) };
+        Ok(())
+    }
+
+    /// Calls `f` with the value of `key`, or with `None` if the map does not have `key`. The value
+    /// is not reclaimed until `f` returns.
+    pub fn lookup<F, R>(&self, key: &K, f: F) -> R
+    where
+        F: FnOnce(Option<&V>) -> R,
+    {
+        let mut pos = Position::new();
+        if self.find(key, &mut pos) {
+            // SAFETY: `succs[0]` is protected.
+            f(Some(unsafe { &(*pos.succs[0]).value }))
+        } else {
+            f(None)
+        }
+    }
+
+    /// Returns `true` if the map has `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key, &mut Position::new())
+    }
+
+    /// Removes `key`. Returns `false` if the map does not have `key`. The key and the value are
+    /// dropped when the node is reclaimed.
+    pub fn remove(&self, key: &K) -> bool {
+        let mut pos = Position::new();
+        if !self.find(key, &mut pos) {
+            return false;
+        }
+        // SAFETY: `succs[0]` is protected.
+        let node_ref = unsafe { &*pos.succs[0] };
+        self.remove_node(node_ref, &mut pos)
+    }
+
+    /// Removes `node_ref`. Returns `false` if another thread removes it first.
+    fn remove_node(&self, node_ref: &Node<K, V>, pos: &mut Position<K, V>) -> bool {
+        for level in (1..node_ref.tower.len()).rev() {
+            let _ = node_ref.mark(level);
+        }
+        // The thread that marks the bottom level removes the key.
+        if !node_ref.mark(0) {
+            return false;
+        }
+        let _ = self.find(&node_ref.key, pos);
+        true
+    }
+
+    /// Removes the first entry and calls `f` with it. Returns `None` if the map is empty.
+    ///
+    /// Only the thread that removes an entry calls `f` with it. An entry inserted before the
+    /// first one while this walks past it may be left in the map.
+    pub(crate) fn pop_first_with<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&K, &V) -> R,
+    {
+        let mut pos = Position::new();
+        for entry in self.iter() {
+            // SAFETY: The node is protected by `entry`.
+            let node_ref = unsafe { &*entry.node };
+            if self.remove_node(node_ref, &mut pos) {
+                return Some(f(&node_ref.key, &node_ref.value));
+            }
+        }
+        None
+    }
+
+    /// Returns `true` if the map is empty.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+impl<K, V> Drop for SkipMap<K, V> {
+    fn drop(&mut self) {
+        // A removed node may still be linked at some levels but not at the bottom one.
+        let mut nodes = HashSet::new();
+        for level in 0..MAX_HEIGHT {
+            let mut node = self.head[level].load(Ordering::Relaxed);
+            while !node.is_null() {
+                let _ = nodes.insert(node);
+                // SAFETY: No other thread accesses the map, and the linked nodes are not retired.
+                node = unmarked(unsafe { &*node }.tower[level].load(Ordering::Relaxed));
+            }
+        }
+        for node in nodes {
+            // SAFETY: Same as above.
+            drop(unsafe { Box::from_raw(node) });
+        }
+    }
+}
+
+/// Entry of a skiplist, which is not reclaimed while the entry is alive.
+#[derive(Debug)]
+pub struct Entry<'a, K, V> {
+    node: *mut Node<K, V>,
+    _shield: Shield<Node<K, V>>,
+    _marker: PhantomData<&'a SkipMap<K, V>>,
+}
+
+impl<K, V> Entry<'_, K, V> {
+    /// Returns the key.
+    pub fn key(&self) -> &K {
+        // SAFETY: The node is protected.
+        unsafe { &(*self.node).key }
+    }
+
+    /// Returns the value.
+    pub fn value(&self) -> &V {
+        // SAFETY: The node is protected.
+        unsafe { &(*self.node).value }
+    }
+}
+
+/// Iterator over the entries of a skiplist. See `SkipMap::iter`.
+#[derive(Debug)]
+pub struct Iter<'a, K, V> {
+    map: &'a SkipMap<K, V>,
+    /// The last node yielded, protected by `last_shield`. Null at the start.
+    last: *mut Node<K, V>,
+    last_shield: Shield<Node<K, V>>,
+    cursor_shield: Shield<Node<K, V>>,
+    next_shield: Shield<Node<K, V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for Iter<'a, K, V> {
+    type Item = Entry<'a, K, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Walks the bottom level from the last node yielded, skipping the removed nodes.
+        let mut cursor = self.last;
+        self.cursor_shield.set(cursor);
+        loop {
+            // SAFETY: `cursor` is null or protected.
+            let link = unsafe { self.map.link(cursor, 0) };
+            let next = link.load(Ordering::Acquire);
+            if is_marked(next) {
+                // `cursor` is removed, so `next` may be reclaimed. Snips `cursor` and starts over
+                // from the node before it, or from the head. Starting over from the last node
+                // yielded would not snip `cursor` if it is after that node, and would spin until
+                // the thread removing it does.
+                let mut pos = Position::new();
+                // SAFETY: `cursor` is protected, and is not the head, which is never removed.
+                let _ = self.map.find(unsafe { &(*cursor).key }, &mut pos);
+                cursor = pos.preds[0];
+                // `cursor` is protected by `pos` until then.
+                self.cursor_shield.set(cursor);
+                continue;
+            }
+            if next.is_null() {
+                return None;
+            }
+            if self.next_shield.try_protect(next, link).is_err() {
+                continue;
+            }
+            // SAFETY: `next` is protected and validated.
+            let next_ref = unsafe { &*next };
+            let removed = is_marked(next_ref.tower[0].load(Ordering::Acquire));
+            // SAFETY: `last` is protected.
+            let visited =
+                matches!(unsafe { self.last.as_ref() }, Some(last) if next_ref.key <= last.key);
+            mem::swap(&mut self.cursor_shield, &mut self.next_shield);
+            cursor = next;
+            if removed || visited {
+                continue;
+            }
+
+            let shield = Shield::default();
+            shield.set(next);
+            self.last = next;
+            self.last_shield.set(next);
+            return Some(Entry {
+                node: next,
+                _shield: shield,
+                _marker: PhantomData,
+            });
+        }
+    }
+}
+
+/// Lock-free ordered set based on a skiplist. See `SkipMap`.
+#[derive(Debug)]
+pub struct SkipSet<T> {
+    map: SkipMap<T, ()>,
+}
+
+impl<T> Default for SkipSet<T> {
+    fn default() -> Self {
+        Self {
+            map: SkipMap::default(),
+        }
+    }
+}
+
+impl<T> SkipSet<T> {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an iterator over the keys in the ascending order. See `SkipMap::iter`.
+    pub fn iter(&self) -> Iter<'_, T, ()> {
+        self.map.iter()
+    }
+}
+
+impl<T: Ord> SkipSet<T> {
+    /// Inserts `key`. Returns it back if the set already has it.
+    pub fn insert(&self, key: T) -> Result<(), T> {
+        self.map.insert(key, ()).map_err(|(key, ())| key)
+    }
+
+    /// Returns `true` if the set has `key`.
+    pub fn contains(&self, key: &T) -> bool {
+        self.map.contains_key(key)
+    }
+
+    /// Removes `key`. Returns `false` if the set does not have `key`.
+    pub fn remove(&self, key: &T) -> bool {
+        self.map.remove(key)
+    }
+
+    /// Returns `true` if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
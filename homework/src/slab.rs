@@ -0,0 +1,246 @@
+//! Concurrent slab allocator with stable indices.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, AtomicU32, AtomicU64, Ordering};
+
+use crate::backoff::Backoff;
+
+/// Log of the number of slots in the first chunk.
+const FIRST_CHUNK_LOG: u32 = 5;
+/// Number of the chunks, each twice as large as the one before, which cover all `u32` indices
+/// below `NIL`.
+const CHUNKS: usize = (u32::BITS - FIRST_CHUNK_LOG) as usize;
+/// Index that no slot has, which ends the free list.
+const NIL: u32 = u32::MAX;
+
+struct Slot<T> {
+    /// The next slot in the free list, if this slot is in it.
+    next_free: AtomicU32,
+    /// Initialized while the slot is allocated.
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Typed arena from which the nodes of a concurrent data structure can be allocated, and which
+/// names them by `u32` indices.
+///
+/// The slots are never moved nor returned to the system allocator until the slab is dropped, so
+/// the index of a slot is as good as a pointer to it. Freed slots are recycled through a lock-free
+/// free list, which saves the system allocator from the churn of the nodes.
+///
+/// Since a freed slot stays valid memory, a data structure that links its nodes by indices can
+/// read a node that is concurrently freed without undefined behavior, as long as it only reads the
+/// atomics in it. An index is also small enough to be packed with a version tag into an
+/// `AtomicU64`, so that a CAS on a link fails if the node was freed and reallocated in between,
+/// i.e., the ABA problem. The free list itself is such a tagged link.
+///
+/// The slab does not know when a slot can be freed. A data structure freeing its nodes still needs
+/// to make sure that no other thread reads their values, e.g., with hazard pointers.
+pub struct Slab<T> {
+    /// Chunk `i` is null or has `FIRST_CHUNK << i` slots. Allocated chunks stay until the slab is
+    /// dropped.
+    chunks: [AtomicPtr<Slot<T>>; CHUNKS],
+    /// The index of the top of the free list, or `NIL` if it is empty, in the lower half, and
+    /// the version tag in the upper half, which is incremented on each change.
+    free: AtomicU64,
+    /// Number of slots ever allocated. The slots from here on are not used yet.
+    next: AtomicU32,
+}
+
+unsafe impl<T: Send> Send for Slab<T> {}
+unsafe impl<T: Send + Sync> Sync for Slab<T> {}
+
+impl<T> fmt::Debug for Slab<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Slab")
+            .field("next", &self.next.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Default for Slab<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the chunk of `index` and the offset of it in the chunk.
+fn locate(index: u32) -> (usize, usize) {
+    let n = index as u64 + (1 << FIRST_CHUNK_LOG);
+    let chunk = u64::BITS - 1 - n.leading_zeros() - FIRST_CHUNK_LOG;
+    (
+        chunk as usize,
+        (n - (1 << (chunk + FIRST_CHUNK_LOG))) as usize,
+    )
+}
+
+/// Returns the number of slots in chunk `chunk`.
+fn chunk_len(chunk: usize) -> usize {
+    1 << (chunk as u32 + FIRST_CHUNK_LOG)
+}
+
+/// Frees chunk `chunk` at `slots`, without dropping the values in it.
+///
+/// # Safety
+///
+/// `slots` is allocated by `Slab::alloc_chunk` for chunk `chunk`, and no other thread accesses it.
+unsafe fn free_chunk<T>(slots: *mut Slot<T>, chunk: usize) {
+    // SAFETY: Guaranteed by the caller. The chunk has `chunk_len(chunk)` slots.
+    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(slots, chunk_len(chunk))) });
+}
+
+impl<T> Slab<T> {
+    /// Creates an empty slab. No chunk is allocated until the first slot is.
+    pub fn new() -> Self {
+        Self {
+            chunks: [(); CHUNKS].map(|_| AtomicPtr::new(ptr::null_mut())),
+            free: AtomicU64::new(NIL as u64),
+            next: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns the slot of `index`, which is allocated at least once.
+    fn slot(&self, index: u32) -> &Slot<T> {
+        let (chunk, offset) = locate(index);
+        // Acquire: the slots of the chunk are initialized before it is published. Pairs with the
+        // Release in `alloc_chunk`.
+        let slots = self.chunks[chunk].load(Ordering::Acquire);
+        assert!(!slots.is_null(), "slot {index} is never allocated");
+        // SAFETY: The chunk has `chunk_len(chunk)` slots, more than `offset`, and it is not freed
+        // until the slab is dropped.
+        unsafe { &*slots.add(offset) }
+    }
+
+    /// Allocates chunk `chunk` if no other thread has.
+    fn alloc_chunk(&self, chunk: usize) {
+        if !self.chunks[chunk].load(Ordering::Acquire).is_null() {
+            return;
+        }
+        let slots = (0..chunk_len(chunk))
+            .map(|_| Slot {
+                next_free: AtomicU32::new(NIL),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect::<Box<[Slot<T>]>>();
+        let slots = Box::into_raw(slots) as *mut Slot<T>;
+        // Release: the slots are initialized before the chunk is published.
+        if self.chunks[chunk]
+            .compare_exchange(ptr::null_mut(), slots, Ordering::Release, Ordering::Acquire)
+            .is_err()
+        {
+            // SAFETY: Another thread published its chunk first, so `slots` is never shared.
+            unsafe { free_chunk(slots, chunk) };
+        }
+    }
+
+    /// Takes a slot from the free list, or a slot never used if the list is empty.
+    fn take_slot(&self) -> u32 {
+        let backoff = Backoff::new();
+        // Acquire: the value of the slot was read out before it was freed. Pairs with the Release
+        // in `free`.
+        let mut free = self.free.load(Ordering::Acquire);
+        loop {
+            let index = free as u32;
+            if index == NIL {
+                break;
+            }
+            // The slot may be taken and freed again by other threads meanwhile, in which case the
+            // tag has changed, and the CAS below fails.
+            let next = self.slot(index).next_free.load(Ordering::Relaxed);
+            let new = ((free >> 32) + 1) << 32 | next as u64;
+            match self
+                .free
+                .compare_exchange(free, new, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => return index,
+                Err(current) => free = current,
+            }
+            backoff.spin();
+        }
+
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        assert!(index != NIL, "the slab is full");
+        self.alloc_chunk(locate(index).0);
+        index
+    }
+
+    /// Allocates a slot holding `value`, and returns its index.
+    pub fn alloc(&self, value: T) -> u32 {
+        let index = self.take_slot();
+        // SAFETY: This thread took the slot, so no other thread accesses its value.
+        unsafe { (*self.slot(index).value.get()).write(value) };
+        index
+    }
+
+    /// Returns the value in the slot of `index`.
+    ///
+    /// # Safety
+    ///
+    /// The slot is allocated, and is not freed while the returned reference is alive.
+    pub unsafe fn get(&self, index: u32) -> &T {
+        // SAFETY: Guaranteed by the caller.
+        unsafe { (*self.slot(index).value.get()).assume_init_ref() }
+    }
+
+    /// Frees the slot of `index`, and returns its value.
+    ///
+    /// # Safety
+    ///
+    /// The slot is allocated, is freed only once, and no other thread accesses its value.
+    pub unsafe fn free(&self, index: u32) -> T {
+        let slot = self.slot(index);
+        // SAFETY: Guaranteed by the caller.
+        let value = unsafe { (*slot.value.get()).assume_init_read() };
+
+        let backoff = Backoff::new();
+        let mut free = self.free.load(Ordering::Relaxed);
+        loop {
+            slot.next_free.store(free as u32, Ordering::Relaxed);
+            let new = ((free >> 32) + 1) << 32 | index as u64;
+            // Release: the value is read out before the slot is reused.
+            match self
+                .free
+                .compare_exchange(free, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return value,
+                Err(current) => free = current,
+            }
+            backoff.spin();
+        }
+    }
+}
+
+impl<T> Drop for Slab<T> {
+    fn drop(&mut self) {
+        // The slots allocated at least once and not in the free list hold values.
+        let next = self.next.load(Ordering::Relaxed);
+        let mut live = vec![true; next as usize];
+        let mut index = self.free.load(Ordering::Relaxed) as u32;
+        while index != NIL {
+            live[index as usize] = false;
+            index = self.slot(index).next_free.load(Ordering::Relaxed);
+        }
+        for (index, _) in live.into_iter().enumerate().filter(|(_, live)| *live) {
+            let slot = self.slot(index as u32);
+            // SAFETY: No other thread accesses the slab, and the slot is allocated.
+            unsafe { (*slot.value.get()).assume_init_drop() };
+        }
+
+        for (chunk, slots) in self.chunks.iter().enumerate() {
+            let slots = slots.load(Ordering::Relaxed);
+            if !slots.is_null() {
+                // SAFETY: No other thread accesses the slab, and the values in the chunk are
+                // dropped above.
+                unsafe { free_chunk(slots, chunk) };
+            }
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Wait-free atomic snapshot of single-writer registers.
+//!
+//! Afek et al., "Atomic Snapshots of Shared Memory", JACM 1993. A scan reads all registers twice
+//! in a row ("double collect"), and returns the values if no register changed in between. Each
+//! update embeds a scan of its own into the register, so a scan that sees a register change twice
+//! returns the scan embedded by the second change, which started and finished within the scan.
+//! Either way, a scan finishes after at most `N + 1` double collects.
+
+use core::array;
+use core::fmt;
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::ebr::{pin, Guard};
+
+/// Value of a register, which is immutable once written.
+struct Record<V, const N: usize> {
+    value: V,
+    /// The scan taken by the update that wrote the value, before it wrote it.
+    view: [V; N],
+}
+
+/// Array of `N` registers, whose values can be read all at once atomically.
+///
+/// Each register has a single writer: the updates of a register must not be concurrent with each
+/// other, e.g., each thread updates its own register. The updates and the scans are wait-free.
+///
+/// A register points to its current record, which an update replaces with a new one. The records
+/// are reclaimed with EBR, so that a scan may compare them by their addresses.
+pub struct AtomicSnapshot<V, const N: usize> {
+    registers: [AtomicPtr<Record<V, N>>; N],
+}
+
+unsafe impl<V: Send, const N: usize> Send for AtomicSnapshot<V, N> {}
+unsafe impl<V: Send + Sync, const N: usize> Sync for AtomicSnapshot<V, N> {}
+
+impl<V: Clone + fmt::Debug, const N: usize> fmt::Debug for AtomicSnapshot<V, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicSnapshot").field(&self.scan()).finish()
+    }
+}
+
+impl<V: Clone + Default, const N: usize> Default for AtomicSnapshot<V, N> {
+    fn default() -> Self {
+        Self::new(array::from_fn(|_| V::default()))
+    }
+}
+
+impl<V: Clone, const N: usize> AtomicSnapshot<V, N> {
+    /// Creates registers holding `values`.
+    pub fn new(values: [V; N]) -> Self {
+        let registers = values.clone().map(|value| {
+            AtomicPtr::new(Box::into_raw(Box::new(Record {
+                value,
+                view: values.clone(),
+            })))
+        });
+        Self { registers }
+    }
+
+    /// Reads the current records of the registers, which are not reclaimed while `guard` is
+    /// alive.
+    fn collect<'g>(&self, _guard: &'g Guard) -> [&'g Record<V, N>; N] {
+        array::from_fn(|i| {
+            // SeqCst: the scans agree on the order of the updates of different registers, which
+            // Acquire alone does not ensure. Pairs with the swap in `update`.
+            let record = self.registers[i].load(Ordering::SeqCst);
+            // SAFETY: The record is retired only after it is replaced, and the guard was pinned
+            // before it is loaded.
+            unsafe { &*record }
+        })
+    }
+
+    fn scan_pinned(&self, guard: &Guard) -> [V; N] {
+        let mut moved = [false; N];
+        let mut old = self.collect(guard);
+        loop {
+            let new = self.collect(guard);
+            let mut clean = true;
+            for i in 0..N {
+                // The records are not reclaimed while pinned, so a new record is never at the
+                // address of an old one.
+                if ptr::eq(old[i], new[i]) {
+                    continue;
+                }
+                // The second change is written by an update that started after the first change,
+                // and hence after this scan started.
+                if moved[i] {
+                    return new[i].view.clone();
+                }
+                moved[i] = true;
+                clean = false;
+            }
+            if clean {
+                return new.map(|record| record.value.clone());
+            }
+            old = new;
+        }
+    }
+
+    /// Returns the values of all registers at a single point in time.
+    pub fn scan(&self) -> [V; N] {
+        self.scan_pinned(&pin())
+    }
+
+    /// Writes `value` to register `i`. Panics if `i` is out of bounds.
+    ///
+    /// The updates of register `i` must not be concurrent with each other. Otherwise, a scan may
+    /// return values that were not in the registers at any single point in time.
+    pub fn update(&self, i: usize, value: V) {
+        let register = &self.registers[i];
+        let guard = pin();
+        let view = self.scan_pinned(&guard);
+        let record = Box::into_raw(Box::new(Record { value, view }));
+        // SeqCst: pairs with the loads in `collect`.
+        let old = register.swap(record, Ordering::SeqCst);
+        // SAFETY: The old record is unlinked, and only this update, the single writer, retires it.
+        unsafe { guard.defer_destroy(old) };
+    }
+}
+
+impl<V, const N: usize> Drop for AtomicSnapshot<V, N> {
+    fn drop(&mut self) {
+        for register in &self.registers {
+            // SAFETY: No other thread accesses the registers, and the current records are not
+            // retired.
+            drop(unsafe { Box::from_raw(register.load(Ordering::Relaxed)) });
+        }
+    }
+}
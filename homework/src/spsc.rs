@@ -0,0 +1,203 @@
+//! Wait-free single-producer single-consumer ring buffer.
+
+use core::cell::{Cell, UnsafeCell};
+use core::mem::MaybeUninit;
+use std::sync::Arc;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::CachePadded;
+
+/// Buffer shared by the producer and the consumer.
+///
+/// `head` and `tail` count the values popped and pushed so far, so the values in the buffer are
+/// at the indices `head..tail`, modulo the capacity. Only the consumer writes `head` and only the
+/// producer writes `tail`.
+#[derive(Debug)]
+struct Buffer<T> {
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the slot at `index`, modulo the capacity.
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.slots[index % self.capacity()].get()
+    }
+}
+
+impl<T> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        for index in head..tail {
+            // SAFETY: The values at `head..tail` are pushed and not popped.
+            unsafe { (*self.slot(index)).assume_init_drop() };
+        }
+    }
+}
+
+/// Creates a ring buffer that holds at most `capacity` values, returning its producer and consumer
+/// halves. Panics if `capacity` is 0.
+pub fn ring_buffer<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0);
+    let buffer = Arc::new(Buffer {
+        head: CachePadded::new(AtomicUsize::new(0)),
+        tail: CachePadded::new(AtomicUsize::new(0)),
+        slots: (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+    });
+    let producer = Producer {
+        buffer: buffer.clone(),
+        head: Cell::new(0),
+    };
+    let consumer = Consumer {
+        buffer,
+        tail: Cell::new(0),
+    };
+    (producer, consumer)
+}
+
+/// Pushing half of the ring buffer.
+#[derive(Debug)]
+pub struct Producer<T> {
+    buffer: Arc<Buffer<T>>,
+    /// `head` as last seen, so that the producer reads the consumer's index only when the buffer
+    /// looks full.
+    head: Cell<usize>,
+}
+
+/// Popping half of the ring buffer.
+#[derive(Debug)]
+pub struct Consumer<T> {
+    buffer: Arc<Buffer<T>>,
+    /// `tail` as last seen, so that the consumer reads the producer's index only when the buffer
+    /// looks empty.
+    tail: Cell<usize>,
+}
+
+// Each half accesses the slots that the other half has handed over, one at a time.
+unsafe impl<T: Send> Send for Producer<T> {}
+unsafe impl<T: Send> Send for Consumer<T> {}
+
+impl<T> Producer<T> {
+    /// Returns the number of slots that can be pushed to, reading the consumer's index only if
+    /// fewer than `wanted` slots are known to be free.
+    fn free(&self, tail: usize, wanted: usize) -> usize {
+        let capacity = self.buffer.capacity();
+        if self.head.get() + capacity - tail < wanted {
+            // Acquire: the consumer has moved out of the slots before it handed them over.
+            self.head.set(self.buffer.head.load(Ordering::Acquire));
+        }
+        self.head.get() + capacity - tail
+    }
+
+    /// Pushes `t` to the back of the buffer. Returns `t` back if the buffer is full.
+    pub fn push(&mut self, t: T) -> Result<(), T> {
+        let tail = self.buffer.tail.load(Ordering::Relaxed);
+        if self.free(tail, 1) == 0 {
+            return Err(t);
+        }
+        // SAFETY: The slot is free, and the consumer does not access it until `tail` is moved.
+        unsafe { (*self.buffer.slot(tail)).write(t) };
+        // Release: the value is written before it is handed over.
+        self.buffer.tail.store(tail + 1, Ordering::Release);
+        Ok(())
+    }
+
+    /// Pushes values from `values` until it runs out or the buffer is full, handing them over to
+    /// the consumer all at once. Returns the number of values pushed. Values that do not fit are
+    /// left in `values`.
+    pub fn push_batch(&mut self, values: &mut impl Iterator<Item = T>) -> usize {
+        let tail = self.buffer.tail.load(Ordering::Relaxed);
+        let free = self.free(tail, self.buffer.capacity());
+        let mut pushed = 0;
+        for t in values.take(free) {
+            // SAFETY: Same as in `push`.
+            unsafe { (*self.buffer.slot(tail + pushed)).write(t) };
+            pushed += 1;
+        }
+        if pushed > 0 {
+            self.buffer.tail.store(tail + pushed, Ordering::Release);
+        }
+        pushed
+    }
+
+    /// Returns the number of values the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Returns `true` if the consumer is dropped, so that pushed values are never popped.
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.buffer) == 1
+    }
+}
+
+impl<T> Consumer<T> {
+    /// Returns the number of values that can be popped, reading the producer's index only if fewer
+    /// than `wanted` values are known to be pushed.
+    fn available(&self, head: usize, wanted: usize) -> usize {
+        if self.tail.get() - head < wanted {
+            // Acquire: the producer has written the values before it handed them over.
+            self.tail.set(self.buffer.tail.load(Ordering::Acquire));
+        }
+        self.tail.get() - head
+    }
+
+    /// Pops the front value. Returns `None` if the buffer is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.buffer.head.load(Ordering::Relaxed);
+        if self.available(head, 1) == 0 {
+            return None;
+        }
+        // SAFETY: The value is pushed, and the producer does not access the slot until `head` is
+        // moved.
+        let t = unsafe { (*self.buffer.slot(head)).assume_init_read() };
+        // Release: the value is moved out before the slot is handed over.
+        self.buffer.head.store(head + 1, Ordering::Release);
+        Some(t)
+    }
+
+    /// Pops at most `max` values into `out`, handing the slots back to the producer all at once.
+    /// Returns the number of values popped.
+    pub fn pop_batch(&mut self, out: &mut impl Extend<T>, max: usize) -> usize {
+        let head = self.buffer.head.load(Ordering::Relaxed);
+        let popped = self.available(head, max).min(max);
+        out.extend((head..head + popped).map(|index| {
+            // SAFETY: Same as in `pop`.
+            unsafe { (*self.buffer.slot(index)).assume_init_read() }
+        }));
+        if popped > 0 {
+            self.buffer.head.store(head + popped, Ordering::Release);
+        }
+        popped
+    }
+
+    /// Returns the number of values in the buffer.
+    pub fn len(&self) -> usize {
+        let head = self.buffer.head.load(Ordering::Relaxed);
+        self.buffer.tail.load(Ordering::Acquire) - head
+    }
+
+    /// Returns `true` if the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the producer is dropped, so that no more values are pushed.
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.buffer) == 1
+    }
+}
@@ -0,0 +1,200 @@
+//! Elimination-backoff stack on top of Treiber's stack.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use rand::{thread_rng, Rng};
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{Node, Stack};
+use crate::backoff::Backoff;
+use crate::hazard_pointer::Shield;
+
+/// Slot of the elimination array, where a pusher offers its value to a popper.
+///
+/// The state goes `EMPTY` → `BUSY` (the pusher writes the value) → `OFFERED`, and then either
+/// → `BUSY` (a popper reads the value) → `TAKEN` → `EMPTY` (the pusher notices), or → `BUSY` (the
+/// pusher withdraws the value) → `EMPTY`. Only the thread that set `BUSY` accesses the value.
+#[derive(Debug)]
+struct Slot<T> {
+    state: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    const EMPTY: usize = 0;
+    const BUSY: usize = 1;
+    const OFFERED: usize = 2;
+    const TAKEN: usize = 3;
+
+    fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(Self::EMPTY),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Offers `t` to a popper, waiting for `spins` iterations. Returns `t` back if the slot is
+    /// occupied or no popper takes it in time.
+    fn offer(&self, t: T, spins: usize) -> Result<(), T> {
+        if self
+            .state
+            .compare_exchange(
+                Self::EMPTY,
+                Self::BUSY,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return Err(t);
+        }
+        // SAFETY: This thread set `BUSY`.
+        unsafe { (*self.value.get()).write(t) };
+        self.state.store(Self::OFFERED, Ordering::Release);
+
+        let backoff = Backoff::new();
+        for _ in 0..spins {
+            if self.state.load(Ordering::Acquire) == Self::TAKEN {
+                self.state.store(Self::EMPTY, Ordering::Release);
+                return Ok(());
+            }
+            backoff.snooze();
+        }
+
+        match self.state.compare_exchange(
+            Self::OFFERED,
+            Self::BUSY,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => {
+                // SAFETY: This thread set `BUSY`, and the value written above is still there.
+                let t = unsafe { (*self.value.get()).assume_init_read() };
+                self.state.store(Self::EMPTY, Ordering::Release);
+                Err(t)
+            }
+            Err(_) => {
+                // A popper is taking the value, which takes only a moment.
+                while self.state.load(Ordering::Acquire) != Self::TAKEN {
+                    backoff.snooze();
+                }
+                self.state.store(Self::EMPTY, Ordering::Release);
+                Ok(())
+            }
+        }
+    }
+
+    /// Takes the value offered in the slot, if any.
+    fn take(&self) -> Option<T> {
+        self.state
+            .compare_exchange(
+                Self::OFFERED,
+                Self::BUSY,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()?;
+        // SAFETY: This thread set `BUSY`, and the value was offered.
+        let t = unsafe { (*self.value.get()).assume_init_read() };
+        self.state.store(Self::TAKEN, Ordering::Release);
+        Some(t)
+    }
+}
+
+/// Elimination-backoff stack.
+///
+/// When a push or a pop fails due to contention on the top of the underlying Treiber's stack, it
+/// backs off to a random slot of the elimination array instead of retrying immediately. There, a
+/// push waits for a while for a pop that takes its value, so that the pair completes without
+/// touching the top at all.
+#[derive(Debug)]
+pub struct EliminationStack<T> {
+    inner: Stack<T>,
+    slots: Box<[Slot<T>]>,
+    /// Number of spins a push waits in a slot.
+    backoff: usize,
+}
+
+// The values are moved between threads through the slots, each accessed by one thread at a time.
+unsafe impl<T: Send> Send for EliminationStack<T> {}
+unsafe impl<T: Send> Sync for EliminationStack<T> {}
+
+impl<T> Default for EliminationStack<T> {
+    fn default() -> Self {
+        Self::new(Self::SLOTS, Self::BACKOFF)
+    }
+}
+
+impl<T> EliminationStack<T> {
+    /// Default number of slots in the elimination array.
+    pub const SLOTS: usize = 16;
+
+    /// Default number of spins a push waits in a slot.
+    pub const BACKOFF: usize = 256;
+
+    /// Creates a stack with `slots` slots in the elimination array, where a push waits for
+    /// `backoff` spins. More slots make collisions of pushes less likely but also make a push and
+    /// a pop less likely to meet. Panics if `slots` is 0.
+    pub fn new(slots: usize, backoff: usize) -> Self {
+        assert!(slots > 0);
+        Self {
+            inner: Stack::default(),
+            slots: (0..slots).map(|_| Slot::new()).collect(),
+            backoff,
+        }
+    }
+
+    /// Returns a random slot of the elimination array.
+    fn random_slot(&self) -> &Slot<T> {
+        &self.slots[thread_rng().gen_range(0..self.slots.len())]
+    }
+
+    /// Pushes a value on top of the stack.
+    pub fn push(&self, t: T) {
+        let mut node = Node::new(t);
+        loop {
+            node = match self.inner.try_push_node(node) {
+                Ok(()) => return,
+                Err(node) => node,
+            };
+            match self.random_slot().offer((*node).into_inner(), self.backoff) {
+                Ok(()) => return,
+                Err(t) => node = Node::new(t),
+            }
+        }
+    }
+
+    /// Pops the top value.
+    ///
+    /// Returns `None` if the stack is empty.
+    pub fn pop(&self) -> Option<T> {
+        let shield = Shield::default();
+        loop {
+            if let Ok(result) = self.inner.try_pop_protected(&shield) {
+                return result;
+            }
+            if let Some(t) = self.random_slot().take() {
+                return Some(t);
+            }
+        }
+    }
+
+    /// Returns `true` if the stack is empty.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+impl<T: Copy + Sync> EliminationStack<T> {
+    /// Returns a copy of the top value without popping it, or `None` if the stack is empty. See
+    /// `Stack::peek`.
+    pub fn peek(&self) -> Option<T> {
+        self.inner.peek()
+    }
+}
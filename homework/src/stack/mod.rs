@@ -0,0 +1,181 @@
+//! Treiber's lock-free stack, generic over the memory reclamation scheme.
+
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::backoff::Backoff;
+use crate::reclaim::{Hp, Protect, Reclaimer};
+
+mod elim;
+mod versioned;
+
+pub use elim::EliminationStack;
+pub use versioned::VersionedStack;
+
+#[derive(Debug)]
+struct Node<T> {
+    /// Taken by the thread that pops the node. Other threads may still read it through their
+    /// shields until then, which is why `peek` needs `T: Copy`.
+    data: ManuallyDrop<T>,
+    /// Immutable once the node is pushed.
+    next: *mut Node<T>,
+}
+
+impl<T> Node<T> {
+    fn new(t: T) -> Box<Self> {
+        Box::new(Self {
+            data: ManuallyDrop::new(t),
+            next: ptr::null_mut(),
+        })
+    }
+
+    /// Returns the value of a node that was never pushed.
+    fn into_inner(self) -> T {
+        ManuallyDrop::into_inner(self.data)
+    }
+}
+
+/// Treiber's lock-free stack, whose nodes are reclaimed with `R`, hazard pointers by default.
+///
+/// Usable with any number of producers and consumers.
+#[derive(Debug)]
+pub struct Stack<T, R = Hp> {
+    head: AtomicPtr<Node<T>>,
+    _marker: PhantomData<R>,
+}
+
+// The values are moved between threads, but never shared except through `peek`, which copies them.
+unsafe impl<T: Send, R> Send for Stack<T, R> {}
+unsafe impl<T: Send, R> Sync for Stack<T, R> {}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, R: Reclaimer> Stack<T, R> {
+    /// Creates an empty stack. `Stack::default()` creates one with hazard pointers.
+    pub fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Pushes a value on top of the stack.
+    pub fn push(&self, t: T) {
+        let mut node = Node::new(t);
+        let backoff = Backoff::new();
+        while let Err(returned) = self.try_push_node(node) {
+            node = returned;
+            backoff.spin();
+        }
+    }
+
+    /// Tries to push `node` on top of the stack with a single CAS. Returns the node back if the
+    /// CAS failed due to contention.
+    fn try_push_node(&self, mut node: Box<Node<T>>) -> Result<(), Box<Node<T>>> {
+        let head = self.head.load(Ordering::Relaxed);
+        node.next = head;
+        let new = Box::into_raw(node);
+        // Release: the node is initialized before it is published.
+        match self
+            .head
+            .compare_exchange(head, new, Ordering::Release, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(()),
+            // SAFETY: `new` was not published.
+            Err(_) => Err(unsafe { Box::from_raw(new) }),
+        }
+    }
+
+    /// Tries to pop the top value with a single CAS, protecting the top node with `shield`, which
+    /// is cleared afterwards. See `try_pop`.
+    fn try_pop_protected(&self, shield: &R::Shield<Node<T>>) -> Result<Option<T>, ()> {
+        let head = shield.protect(&self.head);
+        // SAFETY: `head` is protected and validated, so it is not reclaimed while `shield` is set.
+        let head_ref = some_or!(unsafe { head.as_ref() }, return Ok(None));
+
+        // ABA is impossible: `head` is protected, so it is not reused until the shield is cleared.
+        if self
+            .head
+            .compare_exchange(head, head_ref.next, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            shield.clear();
+            return Err(());
+        }
+
+        // SAFETY: The node is detached by the CAS above, so no other thread takes its value, and
+        // no other thread retires it.
+        unsafe {
+            let data = ptr::read(&head_ref.data);
+            shield.clear();
+            R::retire(head);
+            Ok(Some(ManuallyDrop::into_inner(data)))
+        }
+    }
+
+    /// Tries to pop the top value with a single CAS.
+    ///
+    /// Returns `Ok(Some(v))` if `v` is popped, `Ok(None)` if the stack is empty, and `Err(())` if
+    /// the CAS failed due to contention.
+    pub fn try_pop(&self) -> Result<Option<T>, ()> {
+        self.try_pop_protected(&Default::default())
+    }
+
+    /// Pops the top value, retrying on contention.
+    ///
+    /// Returns `None` if the stack is empty.
+    pub fn pop(&self) -> Option<T> {
+        let shield = R::Shield::default();
+        let backoff = Backoff::new();
+        loop {
+            if let Ok(result) = self.try_pop_protected(&shield) {
+                return result;
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Returns `true` if the stack is empty.
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<T: Copy + Sync, R: Reclaimer> Stack<T, R> {
+    /// Returns a copy of the top value without popping it, or `None` if the stack is empty.
+    ///
+    /// Only `Copy` values can be peeked: another thread may pop the value while it is read, and
+    /// then mutate or drop it, which a bitwise copy does not race with.
+    pub fn peek(&self) -> Option<T> {
+        let shield = R::Shield::default();
+        let head = shield.protect(&self.head);
+        // SAFETY: `head` is protected and validated, and `T: Copy` has no interior mutability or
+        // drop glue, so reading the value is sound even if it is popped concurrently.
+        unsafe { head.as_ref().map(|head| *head.data) }
+    }
+}
+
+impl<T, R> Drop for Stack<T, R> {
+    fn drop(&mut self) {
+        let mut node = self.head.load(Ordering::Relaxed);
+        while !node.is_null() {
+            // SAFETY: No other thread accesses the stack, and the nodes in it are not retired.
+            let mut boxed = unsafe { Box::from_raw(node) };
+            node = boxed.next;
+            // SAFETY: The value was not popped.
+            unsafe { ManuallyDrop::drop(&mut boxed.data) };
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Treiber's stack whose nodes are recycled instead of freed, so that it needs no memory
+//! reclamation.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+
+use crate::backoff::Backoff;
+
+/// Number of the low bits of a tagged pointer that hold the address, which is enough for the heap
+/// addresses on the 64-bit platforms in use. The version takes the remaining high bits.
+const ADDRESS_BITS: u32 = 48;
+const ADDRESS_MASK: u64 = (1 << ADDRESS_BITS) - 1;
+
+struct Node<T> {
+    /// Initialized while the node is in the stack.
+    value: UnsafeCell<MaybeUninit<T>>,
+    /// The node below in the stack, or in the free list. Atomic, because a thread that loses the
+    /// race to pop the node reads it while the winner recycles the node.
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn alloc() -> *mut Self {
+        let node = Box::into_raw(Box::new(Self {
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }));
+        assert!(
+            node as u64 & !ADDRESS_MASK == 0,
+            "the address of a node does not fit in a tagged pointer"
+        );
+        node
+    }
+}
+
+fn address<T>(tagged: u64) -> *mut Node<T> {
+    (tagged & ADDRESS_MASK) as *mut Node<T>
+}
+
+/// Returns `node` tagged with the version after that of `tagged`, which wraps around.
+fn next_version<T>(tagged: u64, node: *mut Node<T>) -> u64 {
+    (tagged & !ADDRESS_MASK).wrapping_add(1 << ADDRESS_BITS) | node as u64
+}
+
+/// Treiber's stack of nodes, whose top is tagged with a version that each push and pop
+/// increments.
+///
+/// A node popped and pushed back meanwhile is at the top again with a different version, so a CAS
+/// with the old top fails: the ABA problem is avoided without protecting the top, as long as the
+/// version does not wrap around while a thread is between its load and CAS of the top.
+struct TaggedList<T> {
+    top: AtomicU64,
+    _marker: PhantomData<*mut Node<T>>,
+}
+
+impl<T> TaggedList<T> {
+    fn new() -> Self {
+        Self {
+            top: AtomicU64::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Pushes `node`, which this thread owns.
+    fn push(&self, node: *mut Node<T>) {
+        let backoff = Backoff::new();
+        let mut top = self.top.load(Ordering::Relaxed);
+        loop {
+            // SAFETY: The node is never freed while the stack is alive.
+            unsafe { &*node }
+                .next
+                .store(address(top), Ordering::Relaxed);
+            // Release: the value is written or read out before the node is published.
+            match self.top.compare_exchange(
+                top,
+                next_version(top, node),
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(current) => top = current,
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Pops a node, which this thread owns from then on. Returns `None` if the list is empty.
+    fn pop(&self) -> Option<*mut Node<T>> {
+        let backoff = Backoff::new();
+        // Acquire: see `push`.
+        let mut top = self.top.load(Ordering::Acquire);
+        loop {
+            let node = address::<T>(top);
+            if node.is_null() {
+                return None;
+            }
+            // SAFETY: The node is never freed while the stack is alive. Other threads may pop and
+            // push it meanwhile, but then the version has changed, and the CAS below fails.
+            let next = unsafe { &*node }.next.load(Ordering::Relaxed);
+            match self.top.compare_exchange(
+                top,
+                next_version(top, next),
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(node),
+                Err(current) => top = current,
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Iterates over the nodes, which no other thread accesses.
+    fn nodes(&self) -> impl Iterator<Item = *mut Node<T>> {
+        let mut node = address(self.top.load(Ordering::Relaxed));
+        core::iter::from_fn(move || {
+            if node.is_null() {
+                return None;
+            }
+            let current = node;
+            // SAFETY: No other thread accesses the nodes.
+            node = unsafe { &*node }.next.load(Ordering::Relaxed);
+            Some(current)
+        })
+    }
+}
+
+/// Treiber's stack whose nodes are never freed until it is dropped, but recycled through a free
+/// list of its own.
+///
+/// The tops of the stack and of the free list are pointers packed with version tags into
+/// `AtomicU64`s, so that a node popped and pushed back does not fool a CAS (the ABA problem), and
+/// a node is read only while it is valid memory. So, unlike [`Stack`](super::Stack), the threads
+/// neither protect the nodes nor defer their reclamation. In exchange, the stack holds on to as
+/// many nodes as it ever had values at once.
+///
+/// The versions have 16 bits. A thread stalled between its load and CAS of a top while the top
+/// changes exactly a multiple of 65536 times may still be fooled.
+pub struct VersionedStack<T> {
+    stack: TaggedList<T>,
+    free: TaggedList<T>,
+}
+
+// The values are moved between threads, but never shared.
+unsafe impl<T: Send> Send for VersionedStack<T> {}
+unsafe impl<T: Send> Sync for VersionedStack<T> {}
+
+impl<T> fmt::Debug for VersionedStack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionedStack")
+            .field("is_empty", &self.is_empty())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Default for VersionedStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> VersionedStack<T> {
+    /// Creates an empty stack, without any node.
+    pub fn new() -> Self {
+        Self {
+            stack: TaggedList::new(),
+            free: TaggedList::new(),
+        }
+    }
+
+    /// Pushes a value on top of the stack, in a recycled node if there is one.
+    pub fn push(&self, t: T) {
+        let node = self.free.pop().unwrap_or_else(Node::alloc);
+        // SAFETY: This thread owns the node, whose value is not initialized.
+        unsafe { (*(*node).value.get()).write(t) };
+        self.stack.push(node);
+    }
+
+    /// Pops the top value, and recycles its node. Returns `None` if the stack is empty.
+    pub fn pop(&self) -> Option<T> {
+        let node = self.stack.pop()?;
+        // SAFETY: This thread owns the node, whose value is initialized.
+        let t = unsafe { (*(*node).value.get()).assume_init_read() };
+        self.free.push(node);
+        Some(t)
+    }
+
+    /// Returns `true` if the stack is empty.
+    pub fn is_empty(&self) -> bool {
+        address::<T>(self.stack.top.load(Ordering::Acquire)).is_null()
+    }
+}
+
+impl<T> Drop for VersionedStack<T> {
+    fn drop(&mut self) {
+        for node in self.stack.nodes() {
+            // SAFETY: No other thread accesses the stack, and the nodes in it hold values.
+            let mut node = unsafe { Box::from_raw(node) };
+            // SAFETY: Same as above.
+            unsafe { node.value.get_mut().assume_init_drop() };
+        }
+        for node in self.free.nodes() {
+            // SAFETY: No other thread accesses the stack, and the nodes in the free list hold no
+            // values.
+            drop(unsafe { Box::from_raw(node) });
+        }
+    }
+}
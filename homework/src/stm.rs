@@ -0,0 +1,325 @@
+//! Software transactional memory.
+//!
+//! Dice et al., "Transactional Locking II", DISC 2006. A global clock counts the commits. Each
+//! variable has a versioned lock: the clock at the commit that last wrote it, and a bit for
+//! whether a commit is writing it. A transaction reads the clock when it starts, and a read of a
+//! variable written since then, or being written, aborts it, so that it only ever sees the
+//! variables as of its start. Writes are buffered until the commit, which locks the written
+//! variables, ticks the clock, checks that no variable it read has been written since its start,
+//! and publishes the writes.
+//!
+//! The values are immutable once published, and a write replaces the value of a variable with a
+//! new one, so that reading a value that is being replaced is not a data race. The old values are
+//! reclaimed with EBR.
+
+use core::any::Any;
+use core::fmt;
+use core::marker::PhantomData;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::backoff::Backoff;
+use crate::ebr::{pin, Guard};
+
+/// The bit of a versioned lock that is set while a commit writes the variable. The version takes
+/// the remaining high bits.
+const LOCKED: usize = 1;
+
+#[cfg(not(feature = "check-loom"))]
+/// Number of the commits that wrote any variable.
+static CLOCK: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "check-loom")]
+// FIXME: loom does not currently provide the equivalent of Lazy:
+// https://github.com/tokio-rs/loom/issues/263
+loom::lazy_static! {
+    /// Number of the commits that wrote any variable.
+    static ref CLOCK: AtomicUsize = AtomicUsize::new(0);
+}
+
+/// A transaction conflicted with another one, and is run again. A transaction returns it by
+/// propagating the errors of [`Transaction::read`] with `?`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict;
+
+/// Result of a step of a transaction.
+pub type StmResult<T> = Result<T, Conflict>;
+
+/// A variable that transactions read and write.
+pub struct TVar<T> {
+    /// The version of `value`, shifted by one bit, and the `LOCKED` bit.
+    lock: AtomicUsize,
+    value: AtomicPtr<T>,
+    _marker: PhantomData<*mut T>,
+}
+
+// The transactions on any thread clone the values, and drop those they replace.
+unsafe impl<T: Send> Send for TVar<T> {}
+unsafe impl<T: Send + Sync> Sync for TVar<T> {}
+
+impl<T: Clone + Send + Sync + fmt::Debug + 'static> fmt::Debug for TVar<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TVar").field(&self.load()).finish()
+    }
+}
+
+impl<T: Default> Default for TVar<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> TVar<T> {
+    /// Creates a variable holding `t`.
+    pub fn new(t: T) -> Self {
+        Self {
+            lock: AtomicUsize::new(0),
+            value: AtomicPtr::new(Box::into_raw(Box::new(t))),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the value.
+    pub fn into_inner(self) -> T {
+        let value = self.value.load(Ordering::Relaxed);
+        core::mem::forget(self);
+        // SAFETY: No transaction accesses the variable, and the current value is not deferred.
+        *unsafe { Box::from_raw(value) }
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> TVar<T> {
+    /// Returns a clone of the value, read in a transaction of its own.
+    pub fn load(&self) -> T {
+        atomically(|tx| tx.read(self))
+    }
+}
+
+impl<T> Drop for TVar<T> {
+    fn drop(&mut self) {
+        // SAFETY: No transaction accesses the variable, and the current value is not deferred.
+        drop(unsafe { Box::from_raw(self.value.load(Ordering::Relaxed)) });
+    }
+}
+
+/// A buffered write of a variable, whose type is erased, so that the writes of variables of
+/// different types are kept together.
+trait Write {
+    /// Returns the versioned lock of the variable, whose address identifies it.
+    fn lock(&self) -> &AtomicUsize;
+
+    /// Returns the value to be written.
+    fn value(&mut self) -> &mut dyn Any;
+
+    /// Replaces the value of the variable with the written one.
+    ///
+    /// # Safety
+    ///
+    /// The variable must be locked by this transaction, and the value must not be published yet.
+    unsafe fn publish(&mut self, guard: &Guard);
+}
+
+struct Pending<'a, T> {
+    var: &'a TVar<T>,
+    /// Taken when published.
+    value: Option<T>,
+}
+
+impl<T: Send + Sync + 'static> Write for Pending<'_, T> {
+    fn lock(&self) -> &AtomicUsize {
+        &self.var.lock
+    }
+
+    fn value(&mut self) -> &mut dyn Any {
+        self.value.as_mut().unwrap()
+    }
+
+    unsafe fn publish(&mut self, guard: &Guard) {
+        let value = Box::into_raw(Box::new(self.value.take().unwrap()));
+        // Release: the value is initialized before it is published. Pairs with the Acquire in
+        // `Transaction::read`.
+        let old = self.var.value.swap(value, Ordering::AcqRel);
+        // SAFETY: `old` is replaced by the swap above, and only this transaction, which holds the
+        // lock, replaced it.
+        unsafe { guard.defer_destroy(old) };
+    }
+}
+
+/// A run of a transaction, which reads the variables as of its start, and buffers its writes.
+pub struct Transaction<'a> {
+    guard: Guard,
+    /// The clock when the transaction started.
+    read_version: usize,
+    /// The locks of the variables read, not counting those written first.
+    reads: Vec<&'a AtomicUsize>,
+    /// Searched linearly, as transactions write few variables.
+    writes: Vec<Box<dyn Write + 'a>>,
+}
+
+impl fmt::Debug for Transaction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("read_version", &self.read_version)
+            .field("reads", &self.reads.len())
+            .field("writes", &self.writes.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> Transaction<'a> {
+    fn new() -> Self {
+        let guard = pin();
+        // Acquire: the commits counted by the clock locked the variables they write before they
+        // ticked it, so that a read of such a variable sees either the lock or the write. Pairs
+        // with the `fetch_add` in `commit`.
+        let read_version = CLOCK.load(Ordering::Acquire);
+        Self {
+            guard,
+            read_version,
+            reads: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
+    fn write_of(&mut self, lock: &AtomicUsize) -> Option<&mut Box<dyn Write + 'a>> {
+        self.writes
+            .iter_mut()
+            .find(|write| core::ptr::eq(write.lock(), lock))
+    }
+
+    /// Returns a clone of the value of `var` as of the start of the transaction, or as written by
+    /// the transaction. Returns `Conflict` if another transaction wrote it since then.
+    pub fn read<T: Clone + Send + Sync + 'static>(&mut self, var: &'a TVar<T>) -> StmResult<T> {
+        if let Some(write) = self.write_of(&var.lock) {
+            return Ok(write.value().downcast_ref::<T>().unwrap().clone());
+        }
+
+        // Acquire: see `new`.
+        let before = var.lock.load(Ordering::Acquire);
+        // Acquire: see `Pending::publish`. A value published after `before` is loaded is seen only
+        // after the lock of its commit.
+        let value = var.value.load(Ordering::Acquire);
+        // SAFETY: `value` is loaded while pinned, so it is not freed until the guard is dropped.
+        let t = unsafe { &*value }.clone();
+        let after = var.lock.load(Ordering::Acquire);
+        if before & LOCKED != 0 || before != after || before >> 1 > self.read_version {
+            return Err(Conflict);
+        }
+        self.reads.push(&var.lock);
+        Ok(t)
+    }
+
+    /// Writes `t` to `var` when the transaction commits.
+    pub fn write<T: Send + Sync + 'static>(&mut self, var: &'a TVar<T>, t: T) {
+        match self.write_of(&var.lock) {
+            Some(write) => *write.value().downcast_mut::<T>().unwrap() = t,
+            None => self.writes.push(Box::new(Pending {
+                var,
+                value: Some(t),
+            })),
+        }
+    }
+
+    /// Writes `f` applied to the value of `var`, and returns what it wrote.
+    pub fn modify<T, F>(&mut self, var: &'a TVar<T>, f: F) -> StmResult<T>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce(T) -> T,
+    {
+        let t = f(self.read(var)?);
+        self.write(var, t.clone());
+        Ok(t)
+    }
+
+    /// Publishes the writes if no variable read was written since the start of the transaction.
+    fn commit(mut self) -> StmResult<()> {
+        if self.writes.is_empty() {
+            // Each read already checked that the variable had not been written since the start.
+            return Ok(());
+        }
+
+        // The variables are only tried to be locked, so that the order they are locked in does
+        // not matter. The old lock words are kept to be restored if the commit fails.
+        let mut words = Vec::with_capacity(self.writes.len());
+        for write in &self.writes {
+            let lock = write.lock();
+            let word = lock.load(Ordering::Relaxed);
+            // Acquire: the value this commit replaces is published before. Pairs with the release
+            // in the store below.
+            if word & LOCKED != 0
+                || lock
+                    .compare_exchange(word, word | LOCKED, Ordering::Acquire, Ordering::Relaxed)
+                    .is_err()
+            {
+                self.unlock(&words);
+                return Err(Conflict);
+            }
+            words.push(word);
+        }
+
+        // AcqRel: see `new`, and the commits that tick the clock before this one hold the locks
+        // they took before they ticked it.
+        let write_version = CLOCK.fetch_add(1, Ordering::AcqRel) + 1;
+        // If no commit ticked the clock since the start, no variable read was written since then.
+        if write_version != self.read_version + 1 {
+            for lock in &self.reads {
+                let word = lock.load(Ordering::Acquire);
+                let written = word >> 1 > self.read_version;
+                let locked = word & LOCKED != 0
+                    && !self
+                        .writes
+                        .iter()
+                        .any(|write| core::ptr::eq(write.lock(), *lock));
+                if written || locked {
+                    self.unlock(&words);
+                    return Err(Conflict);
+                }
+            }
+        }
+
+        for write in &mut self.writes {
+            // SAFETY: This transaction locked the variable above, and publishes each write once.
+            unsafe { write.publish(&self.guard) };
+            // Release: the value is published before the variable is unlocked.
+            write.lock().store(write_version << 1, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    /// Restores the lock words of the first variables written, which this transaction locked.
+    fn unlock(&self, words: &[usize]) {
+        for (write, word) in self.writes.iter().zip(words) {
+            // Release: passes on the release of the commit that wrote the old word, which the
+            // lock acquired.
+            write.lock().store(*word, Ordering::Release);
+        }
+    }
+}
+
+/// Runs `f` as a transaction until it commits, and returns what it returns.
+///
+/// `f` is run again, after backing off, if it returns `Conflict` or if its commit finds that a
+/// variable it read was written since it started. So it should have no side effects other than
+/// through the transaction. The values it reads are consistent with each other even in a run that
+/// does not commit.
+pub fn atomically<'a, R, F>(mut f: F) -> R
+where
+    F: FnMut(&mut Transaction<'a>) -> StmResult<R>,
+{
+    let backoff = Backoff::new();
+    loop {
+        let mut tx = Transaction::new();
+        if let Ok(r) = f(&mut tx) {
+            if tx.commit().is_ok() {
+                return r;
+            }
+        }
+        // The transactions that conflict back off, so that one of them commits.
+        backoff.snooze();
+    }
+}
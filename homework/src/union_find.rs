@@ -0,0 +1,181 @@
+//! Lock-free disjoint-set forest.
+//!
+//! Anderson and Woll, "Wait-free Parallel Algorithms for the Union-Find Problem", STOC 1991. Each
+//! element has a word holding its parent and its rank, so that a root is linked only if neither
+//! changed since they were read. An element is linked below another only if it is smaller by
+//! `(rank, index)`, and the ranks of the roots only grow, so the elements get larger along the
+//! parents, and the forest never has a cycle.
+
+use core::fmt;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicU64, Ordering};
+
+/// Number of the low bits of a word that hold the parent. The rank takes the remaining high bits.
+const PARENT_BITS: u32 = 32;
+const PARENT_MASK: u64 = (1 << PARENT_BITS) - 1;
+
+fn word(parent: usize, rank: u64) -> u64 {
+    rank << PARENT_BITS | parent as u64
+}
+
+fn parent(word: u64) -> usize {
+    (word & PARENT_MASK) as usize
+}
+
+fn rank(word: u64) -> u64 {
+    word >> PARENT_BITS
+}
+
+/// Disjoint sets of the elements `0..len`, merged concurrently.
+///
+/// [`union`](Self::union) links the roots by rank with a CAS, and retries if another thread linked
+/// either of them first. [`find`](Self::find) halves the paths it follows, and never retries: the
+/// elements it visits get larger, so it takes at most `len` steps whatever the other threads do.
+/// All operations are linearizable, except that which element `find` returns as the root may be
+/// out of date as soon as it returns.
+pub struct UnionFind {
+    words: Box<[AtomicU64]>,
+}
+
+impl fmt::Debug for UnionFind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnionFind")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl UnionFind {
+    /// Creates `len` singleton sets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is larger than `2^32`.
+    pub fn new(len: usize) -> Self {
+        assert!(
+            len as u64 <= 1 << PARENT_BITS,
+            "too many elements for a union-find"
+        );
+        Self {
+            words: (0..len).map(|x| AtomicU64::new(word(x, 0))).collect(),
+        }
+    }
+
+    /// Returns the number of the elements.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns `true` if there are no elements.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Returns the root of the set of `x`, which identifies the set until it is merged with
+    /// another one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `x` is out of bounds.
+    pub fn find(&self, mut x: usize) -> usize {
+        // Acquire: a find that follows a link sees the parent at least as linked as the union that
+        // made the link did. Pairs with the CAS in `union`.
+        let mut current = self.words[x].load(Ordering::Acquire);
+        loop {
+            let p = parent(current);
+            if p == x {
+                return x;
+            }
+            let above = self.words[p].load(Ordering::Acquire);
+            let grandparent = parent(above);
+            if grandparent == p {
+                return p;
+            }
+            // Path halving: link `x` to its grandparent, which is larger than its parent. Only the
+            // parent of `x` changes, and if another thread changed it first, theirs is as good.
+            let _ = self.words[x].compare_exchange(
+                current,
+                word(grandparent, rank(current)),
+                Ordering::Release,
+                Ordering::Relaxed,
+            );
+            x = grandparent;
+            current = self.words[x].load(Ordering::Acquire);
+        }
+    }
+
+    /// Merges the sets of `a` and `b`. Returns `false` if they were already the same set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` is out of bounds.
+    pub fn union(&self, a: usize, b: usize) -> bool {
+        loop {
+            let (x, y) = (self.find(a), self.find(b));
+            if x == y {
+                return false;
+            }
+            let (x_word, y_word) = (
+                self.words[x].load(Ordering::Acquire),
+                self.words[y].load(Ordering::Acquire),
+            );
+            if parent(x_word) != x || parent(y_word) != y {
+                continue;
+            }
+
+            // Link the smaller root below the larger one.
+            let ((x, x_word), (y, y_word)) = if (rank(x_word), x) < (rank(y_word), y) {
+                ((x, x_word), (y, y_word))
+            } else {
+                ((y, y_word), (x, x_word))
+            };
+            // Release: see `find`.
+            if self.words[x]
+                .compare_exchange(
+                    x_word,
+                    word(y, rank(x_word)),
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                continue;
+            }
+            if rank(x_word) == rank(y_word) {
+                // If `y` was linked or ranked up meanwhile, it stays larger than `x` anyway. The
+                // ranks only keep the trees shallow.
+                let _ = self.words[y].compare_exchange(
+                    y_word,
+                    word(y, rank(y_word) + 1),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+            }
+            return true;
+        }
+    }
+
+    /// Returns `true` if `a` and `b` are in the same set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` is out of bounds.
+    pub fn same_set(&self, a: usize, b: usize) -> bool {
+        loop {
+            let (x, y) = (self.find(a), self.find(b));
+            if x == y {
+                return true;
+            }
+            // A root that is linked is never a root again. If `x` is still a root, it was one when
+            // `y` was found to be one, and the sets were different then.
+            if parent(self.words[x].load(Ordering::Acquire)) == x {
+                return false;
+            }
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use core::hash::{BuildHasher, Hash, Hasher};
+
 #[macro_export]
 /// Ok or executing the given expression.
 macro_rules! ok_or {
@@ -19,3 +21,33 @@ macro_rules! some_or {
         }
     }};
 }
+
+thread_local! {
+    /// Its address tells the threads apart, so that they tend to pick different stripes.
+    static HINT: u8 = const { 0 };
+}
+
+/// Returns the stripe of the current thread among `stripes` stripes, a power of two. Different
+/// threads tend to get different stripes, and a thread always gets the same one.
+pub(crate) fn stripe(stripes: usize) -> usize {
+    debug_assert!(stripes.is_power_of_two());
+    let hint = HINT.with(|hint| hint as *const u8 as usize);
+    // Fibonacci hashing, as the addresses of the thread-locals share their low bits.
+    (hint.wrapping_mul(0x9E37_79B9) >> 16) & (stripes - 1)
+}
+
+/// Builds the hashers of the hash maps.
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+pub(crate) type HashBuilder = std::collections::hash_map::RandomState;
+/// Loom and the scheduler require the execution to be deterministic, so the hashes are not
+/// randomized.
+#[cfg(any(feature = "check-loom", feature = "check-sched"))]
+pub(crate) type HashBuilder =
+    core::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+/// Returns the hash of `key` with a hasher built by `hash_builder`.
+pub(crate) fn hash_of<S: BuildHasher, Q: Hash + ?Sized>(hash_builder: &S, key: &Q) -> u64 {
+    let mut hasher = hash_builder.build_hasher();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,83 @@
+//! Go-style wait group with atomics and parking.
+
+use core::fmt;
+
+#[cfg(feature = "check-sched")]
+use crate::sched::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "check-loom")]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::parker::WaitList;
+
+/// Counter of outstanding tasks, which blocks `wait` until all of them are done.
+///
+/// Each task is counted with `add` before it starts, e.g., before the thread running it is
+/// spawned, and uncounted with `done` when it finishes. Unlike a
+/// [`CountDownLatch`](crate::CountDownLatch), the number of tasks need not be known upfront, and
+/// the group can be reused once the count drops to zero.
+pub struct WaitGroup {
+    /// Number of the tasks added but not done.
+    count: AtomicUsize,
+    /// The threads parked in `wait`.
+    waiters: WaitList,
+}
+
+impl fmt::Debug for WaitGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WaitGroup")
+            .field("count", &self.count())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for WaitGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitGroup {
+    /// Creates a wait group without tasks.
+    pub fn new() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+            waiters: WaitList::new(),
+        }
+    }
+
+    /// Counts `n` more tasks.
+    pub fn add(&self, n: usize) {
+        let _ = self.count.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Marks a task as done, waking up the threads blocked in `wait` if it was the last one.
+    /// Panics if there is no task left.
+    pub fn done(&self) {
+        // Release: the work of the task happens before `wait` returns. Pairs with the Acquire in
+        // `wait`.
+        let prev = self
+            .count
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            })
+            .expect("`done` called more times than `add`");
+        if prev == 1 {
+            self.waiters.notify_all();
+        }
+    }
+
+    /// Blocks until all tasks added so far are done.
+    pub fn wait(&self) {
+        // Acquire: pairs with the Release in `done`.
+        let _ = self.waiters.wait_until(None, || {
+            (self.count.load(Ordering::Acquire) == 0).then_some(())
+        });
+    }
+
+    /// Returns the number of the tasks added but not done.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
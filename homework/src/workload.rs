@@ -0,0 +1,291 @@
+//! Workloads for benchmarking the concurrent data structures.
+//!
+//! A workload is the keys the operations pick ([`KeyDist`]), the proportions of the kinds of
+//! operations ([`Mix`]), and the number of operations each thread runs. It is run by as many
+//! threads as the level of [`Contention`] asks for. The operations of each thread are generated
+//! from the seed of the workload and the index of the thread before the threads start, so that a
+//! workload is the same in every run, and generating it is not measured.
+
+use core::fmt;
+use core::time::Duration;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::num::NonZeroUsize;
+use std::sync::Barrier;
+use std::thread;
+use std::time::Instant;
+
+/// Seed of the workloads unless given another one.
+const DEFAULT_SEED: u64 = 0x5EED;
+
+/// Distribution of the keys of the operations, which are in `0..keys`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyDist {
+    /// Every key is equally likely.
+    Uniform {
+        /// Number of the keys.
+        keys: u64,
+    },
+    /// The `i`-th key is picked with probability proportional to `1 / (i + 1)^exponent`, so that a
+    /// few keys take most of the operations.
+    Zipf {
+        /// Number of the keys.
+        keys: u64,
+        /// Skew of the distribution, usually around 1.
+        exponent: f64,
+    },
+    /// The first `hot` keys take `ratio` of the operations, and the rest take the others.
+    Hotspot {
+        /// Number of the keys.
+        keys: u64,
+        /// Number of the hot keys.
+        hot: u64,
+        /// Fraction of the operations on the hot keys, in `[0, 1]`.
+        ratio: f64,
+    },
+}
+
+impl KeyDist {
+    /// Returns the number of the keys.
+    pub fn keys(&self) -> u64 {
+        match *self {
+            Self::Uniform { keys } | Self::Zipf { keys, .. } | Self::Hotspot { keys, .. } => keys,
+        }
+    }
+
+    fn sampler(&self) -> KeySampler {
+        match *self {
+            Self::Uniform { keys } => KeySampler::Uniform(keys),
+            Self::Zipf { keys, exponent } => {
+                let weights = (1..=keys).map(|rank| (rank as f64).powf(-exponent));
+                KeySampler::Weighted(WeightedIndex::new(weights).unwrap())
+            }
+            Self::Hotspot { keys, hot, ratio } => {
+                assert!(0 < hot && hot <= keys && (0.0..=1.0).contains(&ratio));
+                KeySampler::Hotspot { keys, hot, ratio }
+            }
+        }
+    }
+}
+
+impl fmt::Display for KeyDist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Uniform { keys } => write!(f, "uniform({keys})"),
+            Self::Zipf { keys, exponent } => write!(f, "zipf({keys},{exponent})"),
+            Self::Hotspot { keys, hot, ratio } => write!(f, "hotspot({keys},{hot},{ratio})"),
+        }
+    }
+}
+
+/// [`KeyDist`] prepared for sampling.
+enum KeySampler {
+    Uniform(u64),
+    /// The cumulative weights of the keys are computed once.
+    Weighted(WeightedIndex<f64>),
+    Hotspot {
+        keys: u64,
+        hot: u64,
+        ratio: f64,
+    },
+}
+
+impl KeySampler {
+    fn sample<R: Rng>(&self, rng: &mut R) -> u64 {
+        match self {
+            Self::Uniform(keys) => rng.gen_range(0..*keys),
+            Self::Weighted(index) => index.sample(rng) as u64,
+            Self::Hotspot { keys, hot, ratio } => {
+                if *hot == *keys || rng.gen_bool(*ratio) {
+                    rng.gen_range(0..*hot)
+                } else {
+                    rng.gen_range(*hot..*keys)
+                }
+            }
+        }
+    }
+}
+
+/// Relative weights of the kinds of operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    /// Weight of the reads.
+    pub read: u32,
+    /// Weight of the insertions.
+    pub insert: u32,
+    /// Weight of the removals.
+    pub remove: u32,
+}
+
+impl Mix {
+    /// Reads only.
+    pub const READ_ONLY: Self = Self::new(100, 0, 0);
+    /// Mostly reads, and as many insertions as removals, so that the size stays about the same.
+    pub const READ_MOSTLY: Self = Self::new(90, 5, 5);
+    /// As many reads as writes.
+    pub const BALANCED: Self = Self::new(50, 25, 25);
+    /// Writes only.
+    pub const WRITE_ONLY: Self = Self::new(0, 50, 50);
+
+    /// Creates a mix of the given weights, which must not all be zero.
+    pub const fn new(read: u32, insert: u32, remove: u32) -> Self {
+        Self {
+            read,
+            insert,
+            remove,
+        }
+    }
+}
+
+impl fmt::Display for Mix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "r{}/i{}/d{}", self.read, self.insert, self.remove)
+    }
+}
+
+/// Operation of a workload on a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    /// Looks up the key.
+    Read(u64),
+    /// Inserts the key.
+    Insert(u64),
+    /// Removes the key.
+    Remove(u64),
+}
+
+/// Number of threads running a workload, relative to the hardware threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Contention {
+    /// A single thread.
+    None,
+    /// Two threads.
+    Low,
+    /// A thread for each hardware thread.
+    High,
+    /// Two threads for each hardware thread, so that threads are preempted while they hold locks
+    /// or are in the middle of their operations.
+    Oversubscribed,
+}
+
+impl Contention {
+    /// All the levels, from the lowest.
+    pub const ALL: [Self; 4] = [Self::None, Self::Low, Self::High, Self::Oversubscribed];
+
+    /// Returns the number of the threads.
+    pub fn threads(self) -> usize {
+        let parallelism = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        match self {
+            Self::None => 1,
+            Self::Low => 2,
+            Self::High => parallelism,
+            Self::Oversubscribed => 2 * parallelism,
+        }
+    }
+}
+
+impl fmt::Display for Contention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}x{}", self, self.threads())
+    }
+}
+
+/// Operations on keys drawn from a distribution, in a mix of kinds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Workload {
+    keys: KeyDist,
+    mix: Mix,
+    /// Number of the operations of each thread.
+    ops: usize,
+    seed: u64,
+}
+
+impl fmt::Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.keys, self.mix)
+    }
+}
+
+impl Workload {
+    /// Creates a workload of `ops` operations for each thread.
+    pub fn new(keys: KeyDist, mix: Mix, ops: usize) -> Self {
+        Self {
+            keys,
+            mix,
+            ops,
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    /// Returns the workload with the operations generated from `seed`.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Returns the distribution of the keys.
+    pub fn keys(&self) -> KeyDist {
+        self.keys
+    }
+
+    /// Returns the mix of the operations.
+    pub fn mix(&self) -> Mix {
+        self.mix
+    }
+
+    /// Returns the number of the operations of each thread.
+    pub fn ops_per_thread(&self) -> usize {
+        self.ops
+    }
+
+    /// Returns every other key, with which a structure is filled before a run, so that about half
+    /// of the reads and of the insertions find their keys, as do the removals.
+    pub fn prefill(&self) -> impl Iterator<Item = u64> {
+        (0..self.keys.keys()).step_by(2)
+    }
+
+    /// Returns the operations of the `thread`-th thread.
+    pub fn ops(&self, thread: usize) -> Vec<Op> {
+        let mut rng = StdRng::seed_from_u64(self.seed ^ (thread as u64).wrapping_mul(0x9E37_79B9));
+        let keys = self.keys.sampler();
+        let kinds = WeightedIndex::new([self.mix.read, self.mix.insert, self.mix.remove]).unwrap();
+        (0..self.ops)
+            .map(|_| {
+                let key = keys.sample(&mut rng);
+                match kinds.sample(&mut rng) {
+                    0 => Op::Read(key),
+                    1 => Op::Insert(key),
+                    _ => Op::Remove(key),
+                }
+            })
+            .collect()
+    }
+
+    /// Runs the workload on `threads` threads, and returns how long they took. Each thread calls
+    /// `setup` with its index before the threads start, and then the function it returns on each
+    /// of its operations.
+    pub fn run<S, F>(&self, threads: usize, setup: S) -> Duration
+    where
+        S: Fn(usize) -> F + Sync,
+        F: FnMut(Op),
+    {
+        let ops = (0..threads).map(|t| self.ops(t)).collect::<Vec<_>>();
+        // The main thread starts the clock when all threads are ready.
+        let start = Barrier::new(threads + 1);
+        thread::scope(|s| {
+            for (t, ops) in ops.into_iter().enumerate() {
+                let start = &start;
+                let setup = &setup;
+                let _ = s.spawn(move || {
+                    let mut f = setup(t);
+                    let _ = start.wait();
+                    ops.into_iter().for_each(&mut f);
+                });
+            }
+            let _ = start.wait();
+            // The scope joins the threads before it returns.
+            Instant::now()
+        })
+        .elapsed()
+    }
+}
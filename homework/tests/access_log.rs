@@ -0,0 +1,49 @@
+use cs431_homework::hello_server::{AccessLog, AccessLogEntry};
+use std::fs;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::thread::sleep;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+#[test]
+fn access_log_rotate() {
+    let dir = std::env::temp_dir().join(format!("access_log_rotate_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("access.log");
+
+    let entry = |status| AccessLogEntry {
+        time: UNIX_EPOCH + Duration::from_secs(1),
+        peer: Some(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1234))),
+        method: Some("GET".to_string()),
+        path: Some("/alice".to_string()),
+        status,
+        duration: Duration::from_micros(1500),
+    };
+    let line = "1.000 127.0.0.1:1234 GET /alice 200 1.500ms\n";
+    assert_eq!(format!("{}\n", entry(200)), line);
+
+    // Each file holds at most two lines, and at most two old files are kept.
+    let log = AccessLog::open(&path, 2 * line.len() as u64, 2).unwrap();
+    for status in [200, 200, 200, 200, 200, 404, 404] {
+        log.log(entry(status));
+    }
+    drop(log);
+
+    let deadline = Instant::now() + Duration::from_secs(3);
+    let last = line.replace("200", "404");
+    while fs::read_to_string(&path).unwrap() != last {
+        assert!(Instant::now() < deadline, "the log is not written");
+        sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        fs::read_to_string(dir.join("access.log.1")).unwrap(),
+        line.to_string() + &line.replace("200", "404"),
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("access.log.2")).unwrap(),
+        line.repeat(2)
+    );
+    assert!(!dir.join("access.log.3").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
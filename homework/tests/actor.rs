@@ -0,0 +1,167 @@
+// The actors run on the thread pool, which is modeled with loom, so these run only without it.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::hello_server::{Actor, ActorSystem, ThreadPool};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const NUM_THREADS: usize = 4;
+
+/// Records the messages it handles, and panics on `None`.
+struct Recorder {
+    handled: Arc<Mutex<Vec<usize>>>,
+}
+
+impl Actor for Recorder {
+    type Message = Option<usize>;
+
+    fn handle(&mut self, message: Option<usize>) {
+        let message = message.expect("told to panic");
+        self.handled.lock().unwrap().push(message);
+    }
+}
+
+/// Counts the actors alive.
+struct Counted(Arc<AtomicUsize>);
+
+impl Counted {
+    fn new(alive: &Arc<AtomicUsize>) -> Self {
+        let _ = alive.fetch_add(1, Ordering::Relaxed);
+        Self(alive.clone())
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        let _ = self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Actor for Counted {
+    type Message = ();
+
+    fn handle(&mut self, _: ()) {}
+}
+
+#[test]
+fn handle_in_order() {
+    let pool = ThreadPool::new(NUM_THREADS);
+    let actors = ActorSystem::new(&pool);
+    let handled = Arc::new(Mutex::new(Vec::new()));
+    let address = {
+        let handled = handled.clone();
+        actors.spawn(move || Recorder {
+            handled: handled.clone(),
+        })
+    };
+    for i in 0..1000 {
+        address.send(Some(i));
+    }
+    pool.join();
+    assert_eq!(*handled.lock().unwrap(), (0..1000).collect::<Vec<_>>());
+}
+
+/// The messages of concurrent senders are each handled once, in the order of each sender, one at
+/// a time.
+#[test]
+fn concurrent_senders() {
+    const SENDERS: usize = 4;
+    const MESSAGES: usize = 1024 * 4;
+
+    let pool = ThreadPool::new(NUM_THREADS);
+    let actors = ActorSystem::new(&pool);
+    let handled = Arc::new(Mutex::new(Vec::new()));
+    let address = {
+        let handled = handled.clone();
+        actors.spawn(move || Recorder {
+            handled: handled.clone(),
+        })
+    };
+    let senders = (0..SENDERS)
+        .map(|t| {
+            let address = address.clone();
+            thread::spawn(move || {
+                for i in 0..MESSAGES {
+                    address.send(Some(t * MESSAGES + i));
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for sender in senders {
+        sender.join().unwrap();
+    }
+    pool.join();
+
+    let handled = handled.lock().unwrap();
+    assert_eq!(handled.len(), SENDERS * MESSAGES);
+    let mut last = [None; SENDERS];
+    for &message in handled.iter() {
+        let (t, i) = (message / MESSAGES, message % MESSAGES);
+        assert!(last[t] < Some(i));
+        last[t] = Some(i);
+    }
+}
+
+/// An actor that panics is replaced by a new one, which handles the next messages.
+#[test]
+fn restart_on_panic() {
+    let pool = ThreadPool::new(NUM_THREADS);
+    let actors = ActorSystem::new(&pool);
+    let handled = Arc::new(Mutex::new(Vec::new()));
+    let created = Arc::new(AtomicUsize::new(0));
+    let address = {
+        let handled = handled.clone();
+        let created = created.clone();
+        actors.spawn(move || {
+            let _ = created.fetch_add(1, Ordering::Relaxed);
+            Recorder {
+                handled: handled.clone(),
+            }
+        })
+    };
+    address.send(Some(0));
+    address.send(None);
+    address.send(Some(1));
+    address.send(None);
+    address.send(Some(2));
+    pool.join();
+
+    assert_eq!(*handled.lock().unwrap(), [0, 1, 2]);
+    assert_eq!(address.restarts(), 2);
+    assert_eq!(created.load(Ordering::Relaxed), 3);
+    // The workers survive the panics.
+    pool.execute(|| {});
+    pool.join();
+}
+
+/// An actor is dropped once its addresses are dropped and its messages are handled, or once the
+/// pool is dropped.
+#[test]
+fn drop_actor() {
+    let alive = Arc::new(AtomicUsize::new(0));
+    let pool = ThreadPool::new(NUM_THREADS);
+    let actors = ActorSystem::new(&pool);
+
+    let address = actors.spawn({
+        let alive = alive.clone();
+        move || Counted::new(&alive)
+    });
+    for _ in 0..100 {
+        address.send(());
+    }
+    drop(address);
+    pool.join();
+    assert_eq!(alive.load(Ordering::Relaxed), 0);
+
+    let address = actors.spawn({
+        let alive = alive.clone();
+        move || Counted::new(&alive)
+    });
+    address.send(());
+    drop(pool);
+    address.send(());
+    assert_eq!(alive.load(Ordering::Relaxed), 1);
+    drop(address);
+    assert_eq!(alive.load(Ordering::Relaxed), 0);
+}
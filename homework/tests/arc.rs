@@ -18,7 +18,7 @@ impl Drop for Canary {
 
 #[cfg(not(feature = "check-loom"))]
 mod basic {
-    use cs431_homework::Arc;
+    use cs431_homework::{Arc, Weak};
 
     use super::mock::sync::atomic::{AtomicUsize, Ordering::Relaxed};
     use super::mock::sync::mpsc::channel;
@@ -126,6 +126,48 @@ mod basic {
         assert!(canary.load(Relaxed) == 1);
     }
 
+    #[test]
+    fn test_weak() {
+        let canary = AtomicUsize::new(0);
+        let x = Arc::new(Canary(&canary as *const AtomicUsize));
+        let weak = Arc::downgrade(&x);
+        let weak2 = weak.clone();
+        assert_eq!(Arc::weak_count(&x), 2);
+        assert_eq!(weak.strong_count(), 1);
+        assert!(Weak::ptr_eq(&weak, &weak2));
+
+        let y = weak.upgrade().unwrap();
+        assert!(Arc::ptr_eq(&x, &y));
+        assert_eq!(Arc::count(&x), 2);
+        drop(x);
+        drop(y);
+        // The data is dropped while the `Weak`s keep the allocation alive.
+        assert_eq!(canary.load(Relaxed), 1);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+        assert_eq!(weak.weak_count(), 0);
+        drop(weak);
+        drop(weak2);
+        assert_eq!(canary.load(Relaxed), 1);
+    }
+
+    #[test]
+    fn test_weak_unique() {
+        let mut x = Arc::new(5);
+        let weak = Arc::downgrade(&x);
+        assert!(Arc::get_mut(&mut x).is_none());
+        // Clones the data, as `weak` may upgrade. The old data is dropped with the only `Arc`.
+        *Arc::make_mut(&mut x) += 1;
+        assert_eq!(*x, 6);
+        assert!(weak.upgrade().is_none());
+        assert!(Arc::get_mut(&mut x).is_some());
+
+        let x = Arc::new(7);
+        let weak = Arc::downgrade(&x);
+        assert_eq!(Arc::try_unwrap(x).unwrap(), 7);
+        assert!(weak.upgrade().is_none());
+    }
+
     #[test]
     fn test_stress() {
         let count = Arc::new(AtomicUsize::new(0));
@@ -245,4 +287,58 @@ mod correctness {
             assert_eq!(canary.load(Relaxed), 1);
         })
     }
+
+    #[test]
+    /// value:=123 → drop upgraded `Arc` → count:=1 → get_mut success
+    fn weak_get_mut_sync() {
+        model(|| {
+            let mut value = Arc::new(AtomicUsize::new(0));
+            {
+                let weak = Arc::downgrade(&value);
+                thread::spawn(move || {
+                    let value = weak.upgrade();
+                    drop(weak);
+                    if let Some(value) = value {
+                        value.store(123, Relaxed);
+                    }
+                });
+            }
+            if let Some(val) = Arc::get_mut(&mut value) {
+                assert_eq!(val.load(Relaxed), 123);
+            }
+        })
+    }
+
+    #[test]
+    /// Accesses through an upgraded `Arc` → data drop, and the data is dropped exactly once.
+    fn upgrade_drop_sync() {
+        struct Counter(AtomicUsize, *const AtomicUsize);
+
+        unsafe impl Send for Counter {}
+        unsafe impl Sync for Counter {}
+
+        impl Drop for Counter {
+            fn drop(&mut self) {
+                unsafe {
+                    (*self.1).fetch_add(self.0.load(Relaxed) + 1, Relaxed);
+                }
+            }
+        }
+
+        model(|| {
+            let dropped = Arc::new(AtomicUsize::new(0));
+            let counter = Arc::new(Counter(AtomicUsize::new(0), &*dropped));
+            let weak = Arc::downgrade(&counter);
+            let handle = thread::spawn(move || match weak.upgrade() {
+                Some(counter) => {
+                    counter.0.fetch_add(1, Relaxed);
+                    true
+                }
+                None => false,
+            });
+            drop(counter);
+            let upgraded = handle.join().unwrap();
+            assert_eq!(dropped.load(Relaxed), 1 + upgraded as usize);
+        })
+    }
 }
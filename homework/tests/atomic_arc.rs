@@ -0,0 +1,151 @@
+mod canary;
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::reclaim::{Ebr, Hp, Reclaimer};
+    use cs431_homework::AtomicArc;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::sync::Arc;
+    use std::thread::scope;
+
+    #[test]
+    fn load_store() {
+        let cell = AtomicArc::<_>::new(Arc::new(1));
+        let old = cell.load();
+        assert_eq!(*old, 1);
+        cell.store(Arc::new(2));
+        assert_eq!(*old, 1);
+        assert_eq!(*cell.load(), 2);
+        assert_eq!(*cell.swap(Arc::new(3)), 2);
+        assert_eq!(format!("{cell:?}"), "AtomicArc(3)");
+        assert_eq!(*cell.into_inner(), 3);
+    }
+
+    #[test]
+    fn compare_and_swap() {
+        let cell = AtomicArc::<_>::new(Arc::new(1));
+        let current = cell.load();
+        // Only the same `Arc` matches, not an equal value.
+        assert_eq!(
+            cell.compare_and_swap(&Arc::new(1), Arc::new(2)),
+            Err(Arc::new(1))
+        );
+        let previous = cell.compare_and_swap(&current, Arc::new(2)).unwrap();
+        assert!(Arc::ptr_eq(&previous, &current));
+        assert_eq!(
+            *cell.compare_and_swap(&current, Arc::new(3)).unwrap_err(),
+            2
+        );
+        assert_eq!(*cell.update(|v| Arc::new(**v * 10)), 2);
+        assert_eq!(*cell.load(), 20);
+    }
+
+    /// Updates are not lost, and readers see the versions in order.
+    fn stress<R: Reclaimer>() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+
+        let cell = AtomicArc::<_, R>::new(Arc::new((0, 0)));
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    for _ in 0..ITER {
+                        // Both fields are updated at once.
+                        let _ = cell.update(|v| Arc::new((v.0 + 1, v.1 + 1)));
+                    }
+                });
+                let _ = s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..ITER {
+                        let version = cell.load();
+                        assert_eq!(version.0, version.1);
+                        assert!(last <= version.0);
+                        last = version.0;
+                    }
+                });
+            }
+        });
+        assert_eq!(*cell.load(), (THREADS * ITER, THREADS * ITER));
+    }
+
+    #[test]
+    fn stress_hp() {
+        stress::<Hp>();
+    }
+
+    #[test]
+    fn stress_ebr() {
+        stress::<Ebr>();
+    }
+
+    /// Every version is dropped exactly once, when its last `Arc` is dropped.
+    #[test]
+    fn drop_values() {
+        // The retired versions may outlive the test, so the count does too.
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        let cell = AtomicArc::<_>::new(Arc::new(Canary(&DROPPED)));
+        let first = cell.load();
+        for _ in 0..9 {
+            cell.store(Arc::new(Canary(&DROPPED)));
+        }
+        drop(cell);
+        // The replaced boxes are freed once no thread protects them.
+        collect();
+        assert_eq!(DROPPED.load(Relaxed), 9);
+        drop(first);
+        assert_eq!(DROPPED.load(Relaxed), 10);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::AtomicArc;
+
+    /// A reader sees either version in whole, and the old version is not freed while it is read.
+    #[test]
+    fn load_store_sync() {
+        model(|| {
+            let cell = Arc::new(AtomicArc::<_>::new(std::sync::Arc::new(Box::new(1))));
+            let th = {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    let version = cell.load();
+                    assert!(matches!(**version, 1 | 2));
+                })
+            };
+            cell.store(std::sync::Arc::new(Box::new(2)));
+            collect();
+            th.join().unwrap();
+            assert_eq!(**cell.load(), 2);
+        })
+    }
+
+    /// Of two racing `compare_and_swap`s from the same version, exactly one succeeds.
+    #[test]
+    fn compare_and_swap_sync() {
+        model(|| {
+            let cell = Arc::new(AtomicArc::<_>::new(std::sync::Arc::new(0)));
+            let current = cell.load();
+            let th = {
+                let cell = cell.clone();
+                let current = current.clone();
+                thread::spawn(move || cell.compare_and_swap(&current, std::sync::Arc::new(1)))
+            };
+            let mine = cell.compare_and_swap(&current, std::sync::Arc::new(2));
+            let theirs = th.join().unwrap();
+            let value = |result: Result<std::sync::Arc<i32>, std::sync::Arc<i32>>| {
+                result.map(|v| *v).map_err(|v| *v)
+            };
+            let (mine, theirs) = (value(mine), value(theirs));
+            assert!(matches!((mine, theirs), (Ok(0), Err(2)) | (Err(1), Ok(0))));
+            assert_eq!(*cell.load(), if mine.is_ok() { 2 } else { 1 });
+            collect();
+        })
+    }
+}
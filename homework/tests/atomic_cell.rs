@@ -0,0 +1,148 @@
+// `AtomicCell` uses the atomics of `core`, which loom does not model.
+#![cfg(not(feature = "check-loom"))]
+
+use core::num::NonZeroU64;
+use cs431_homework::AtomicCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread::scope;
+
+#[test]
+fn is_lock_free() {
+    assert!(AtomicCell::<u8>::is_lock_free());
+    assert!(AtomicCell::<u32>::is_lock_free());
+    assert!(AtomicCell::<Option<core::num::NonZeroU32>>::is_lock_free());
+    assert!(AtomicCell::<usize>::is_lock_free());
+    // Too little aligned for a `u32`.
+    assert!(!AtomicCell::<[u8; 4]>::is_lock_free());
+    assert!(!AtomicCell::<[u8; 3]>::is_lock_free());
+    assert!(!AtomicCell::<[u64; 2]>::is_lock_free());
+    assert!(!AtomicCell::<()>::is_lock_free());
+}
+
+#[test]
+fn native() {
+    let cell = AtomicCell::new(1u32);
+    assert_eq!(cell.load(), 1);
+    cell.store(2);
+    assert_eq!(cell.swap(3), 2);
+    assert_eq!(cell.compare_exchange(2, 4), Err(3));
+    assert_eq!(cell.compare_exchange(3, 4), Ok(3));
+    assert_eq!(cell.fetch_update(|x| (x < 5).then_some(x + 1)), Ok(4));
+    assert_eq!(cell.fetch_update(|x| (x < 5).then_some(x + 1)), Err(5));
+    assert_eq!(format!("{cell:?}"), "AtomicCell(5)");
+    assert_eq!(cell.into_inner(), 5);
+
+    let flag = AtomicCell::new(false);
+    assert_eq!(flag.compare_exchange(false, true), Ok(false));
+    assert!(flag.load());
+}
+
+#[test]
+fn fallback() {
+    let cell = AtomicCell::new([1u8, 2, 3]);
+    assert_eq!(cell.load(), [1, 2, 3]);
+    assert_eq!(cell.swap([4, 5, 6]), [1, 2, 3]);
+    assert_eq!(cell.compare_exchange([1, 2, 3], [0; 3]), Err([4, 5, 6]));
+    assert_eq!(cell.compare_exchange([4, 5, 6], [7, 8, 9]), Ok([4, 5, 6]));
+
+    let pair = AtomicCell::<[Option<NonZeroU64>; 2]>::default();
+    assert_eq!(pair.load(), [None; 2]);
+    pair.store([NonZeroU64::new(1500), None]);
+    assert_eq!(pair.load(), [NonZeroU64::new(1500), None]);
+    let halved =
+        pair.fetch_update(|pair| Some(pair.map(|x| x.and_then(|x| NonZeroU64::new(x.get() / 2)))));
+    assert_eq!(halved, Ok([NonZeroU64::new(1500), None]));
+    assert_eq!(pair.load(), [NonZeroU64::new(750), None]);
+    assert_eq!(
+        pair.compare_exchange([None; 2], [None; 2]),
+        Err([NonZeroU64::new(750), None])
+    );
+
+    let unit = AtomicCell::new(());
+    unit.store(());
+    assert_eq!(unit.compare_exchange((), ()), Ok(()));
+}
+
+/// `fetch_update` runs its closure without the lock of the cell, which is shared with other cells,
+/// so the closure may use the cell, and the cell is still usable after the closure panics.
+#[test]
+fn fallback_closure() {
+    let cell = AtomicCell::new([1u8, 2, 3]);
+    let updated = cell.fetch_update(|value| {
+        assert_eq!(cell.load(), value);
+        Some([4, 5, 6])
+    });
+    assert_eq!(updated, Ok([1, 2, 3]));
+
+    let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+        cell.fetch_update(|_| -> Option<[u8; 3]> { panic!("update failed") })
+    }));
+    assert!(panicked.is_err());
+    cell.store([7, 8, 9]);
+    assert_eq!(cell.load(), [7, 8, 9]);
+}
+
+/// Readers never see a value that is partially written, and updates are not lost, whether the
+/// value fits a native atomic or not.
+#[test]
+fn stress() {
+    const THREADS: usize = 4;
+    const ITER: usize = 1024 * 16;
+
+    let native = AtomicCell::new(0u64);
+    let locked = AtomicCell::new([0u64; 4]);
+    scope(|s| {
+        for _ in 0..THREADS {
+            let _ = s.spawn(|| {
+                for _ in 0..ITER {
+                    let _ = native.fetch_update(|x| Some(x + 1));
+                    let _ = locked.fetch_update(|value| Some(value.map(|x| x + 1)));
+                }
+            });
+            let _ = s.spawn(|| {
+                let mut last = 0;
+                for _ in 0..ITER {
+                    let value = locked.load();
+                    assert!(value.iter().all(|&x| x == value[0]), "torn read {value:?}");
+                    assert!(value[0] >= last);
+                    last = value[0];
+                }
+            });
+        }
+    });
+    assert_eq!(native.load(), (THREADS * ITER) as u64);
+    assert_eq!(locked.load(), [(THREADS * ITER) as u64; 4]);
+}
+
+/// Concurrent `compare_exchange`s on a value without a native atomic succeed once for each value.
+#[test]
+fn compare_exchange_race() {
+    const THREADS: usize = 4;
+    const ITER: u32 = 1024 * 4;
+
+    let cell = AtomicCell::new([0u32; 3]);
+    let wins = scope(|s| {
+        // Spawns all threads before joining any of them, so that they race.
+        #[allow(clippy::needless_collect)]
+        let handles = (0..THREADS)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut wins = 0;
+                    for i in 0..ITER {
+                        if cell.compare_exchange([i; 3], [i + 1; 3]).is_ok() {
+                            wins += 1;
+                        }
+                    }
+                    wins
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum::<u32>()
+    });
+    let last = cell.load()[0];
+    assert_eq!(cell.load(), [last; 3]);
+    assert_eq!(wins, last);
+}
@@ -0,0 +1,97 @@
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use cs431_homework::Backoff;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread::scope;
+
+    #[test]
+    fn completes() {
+        let backoff = Backoff::new();
+        // Spinning alone never tells the caller to park.
+        for _ in 0..100 {
+            backoff.spin();
+        }
+        assert!(!backoff.is_completed());
+
+        let mut snoozes = 0;
+        while !backoff.is_completed() {
+            backoff.snooze();
+            snoozes += 1;
+        }
+        assert!(snoozes > 1);
+
+        backoff.reset();
+        assert!(!backoff.is_completed());
+    }
+
+    /// Threads incrementing a counter with CAS loops backing off on failure lose no increments.
+    #[test]
+    fn cas_loop() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+
+        let counter = AtomicUsize::new(0);
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    for _ in 0..ITER {
+                        let backoff = Backoff::new();
+                        let mut current = counter.load(Ordering::Relaxed);
+                        while let Err(actual) = counter.compare_exchange_weak(
+                            current,
+                            current + 1,
+                            Ordering::Relaxed,
+                            Ordering::Relaxed,
+                        ) {
+                            current = actual;
+                            backoff.spin();
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.load(Ordering::Relaxed), THREADS * ITER);
+    }
+
+    /// A thread snoozing until a flag is set yields to the thread that sets it.
+    #[test]
+    fn snooze_wait() {
+        let flag = AtomicBool::new(false);
+        scope(|s| {
+            let _ = s.spawn(|| {
+                let backoff = Backoff::new();
+                while !flag.load(Ordering::Acquire) {
+                    backoff.snooze();
+                }
+            });
+            flag.store(true, Ordering::Release);
+        });
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::atomic::{AtomicBool, Ordering};
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::Backoff;
+
+    /// Snoozing yields to the model checker, so that the wait for another thread terminates.
+    #[test]
+    fn snooze_wait() {
+        model(|| {
+            let flag = Arc::new(AtomicBool::new(false));
+            let th = {
+                let flag = flag.clone();
+                thread::spawn(move || flag.store(true, Ordering::Release))
+            };
+            let backoff = Backoff::new();
+            while !flag.load(Ordering::Acquire) {
+                backoff.snooze();
+            }
+            th.join().unwrap();
+        })
+    }
+}
@@ -0,0 +1,117 @@
+mod canary;
+
+use canary::Canary;
+use cs431_homework::{Bag, Pool, Pooled};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{scope, yield_now};
+
+#[test]
+fn put_take() {
+    let bag = Bag::with_segments(2, 2);
+    assert_eq!(bag.capacity(), 4);
+    assert!(bag.is_empty());
+    for i in 0..4 {
+        assert_eq!(bag.put(i), Ok(()));
+    }
+    assert_eq!(bag.put(4), Err(4));
+    assert_eq!(bag.len(), 4);
+
+    let mut taken = (0..4).map(|_| bag.take().unwrap()).collect::<Vec<_>>();
+    taken.sort_unstable();
+    assert_eq!(taken, [0, 1, 2, 3]);
+    assert_eq!(bag.take(), None);
+}
+
+/// Each value is taken exactly once, whether it is taken from the segment it was put into or
+/// stolen from another.
+#[test]
+fn stress() {
+    const THREADS: usize = 8;
+    const ITER: usize = 1024 * 16;
+
+    let bag = Bag::with_segments(4, 4);
+    let sum = AtomicUsize::new(0);
+    let taken = AtomicUsize::new(0);
+    scope(|s| {
+        for t in 0..THREADS {
+            let (bag, sum, taken) = (&bag, &sum, &taken);
+            let _ = s.spawn(move || {
+                // Half of the threads only put, so that the other half steals.
+                if t % 2 == 0 {
+                    for i in 0..ITER {
+                        let mut value = t * ITER + i;
+                        while let Err(v) = bag.put(value) {
+                            value = v;
+                            yield_now();
+                        }
+                    }
+                    return;
+                }
+                while taken.load(Ordering::Relaxed) < THREADS / 2 * ITER {
+                    match bag.take() {
+                        Some(value) => {
+                            let _ = sum.fetch_add(value, Ordering::Relaxed);
+                            let _ = taken.fetch_add(1, Ordering::Relaxed);
+                        }
+                        None => yield_now(),
+                    }
+                }
+            });
+        }
+    });
+    assert!(bag.is_empty());
+    let expected = (0..THREADS)
+        .step_by(2)
+        .flat_map(|t| (0..ITER).map(move |i| t * ITER + i))
+        .sum::<usize>();
+    assert_eq!(sum.load(Ordering::Relaxed), expected);
+}
+
+/// The values left in the bag are dropped with it.
+#[test]
+fn drop_values() {
+    let dropped = AtomicUsize::new(0);
+    let bag = Bag::with_segments(1, 4);
+    for _ in 0..4 {
+        assert!(bag.put(Canary(&dropped)).is_ok());
+    }
+    drop(bag.put(Canary(&dropped)));
+    drop(bag.take());
+    assert_eq!(dropped.load(Ordering::Relaxed), 2);
+    drop(bag);
+    assert_eq!(dropped.load(Ordering::Relaxed), 5);
+}
+
+/// The objects checked in are reset and reused, and new ones are made only when the pool is
+/// empty.
+#[test]
+fn pool() {
+    let made = Arc::new(AtomicUsize::new(0));
+    let pool = {
+        let made = made.clone();
+        Pool::with_bag(
+            Bag::with_segments(1, 2),
+            move || {
+                let _ = made.fetch_add(1, Ordering::Relaxed);
+                Vec::new()
+            },
+            Vec::clear,
+        )
+    };
+    let mut buf = pool.checkout();
+    buf.extend_from_slice(b"hello");
+    let ptr = buf.as_ptr();
+    drop(buf);
+    assert_eq!(pool.len(), 1);
+
+    let buf = pool.checkout();
+    assert!(buf.is_empty());
+    assert!(buf.capacity() >= 5);
+    assert_eq!(buf.as_ptr(), ptr);
+    let other = pool.checkout();
+    assert_eq!(made.load(Ordering::Relaxed), 2);
+    assert_eq!(Pooled::detach(other), Vec::<u8>::new());
+    drop(buf);
+    assert_eq!(pool.len(), 1);
+}
@@ -0,0 +1,74 @@
+use cs431_homework::{Barrier, CountDownLatch};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{scope, sleep};
+use std::time::{Duration, Instant};
+
+/// No thread passes a round before all threads arrive, and exactly one thread leads each round.
+#[test]
+fn barrier_rounds() {
+    const THREADS: usize = 8;
+    const ROUNDS: usize = 1024;
+
+    let barrier = Barrier::new(THREADS);
+    assert_eq!(barrier.parties(), THREADS);
+    let arrived = AtomicUsize::new(0);
+    let leaders = AtomicUsize::new(0);
+    scope(|s| {
+        for _ in 0..THREADS {
+            let _ = s.spawn(|| {
+                for round in 0..ROUNDS {
+                    let _ = arrived.fetch_add(1, Ordering::Relaxed);
+                    if barrier.wait() {
+                        let _ = leaders.fetch_add(1, Ordering::Relaxed);
+                    }
+                    assert!(arrived.load(Ordering::Relaxed) >= (round + 1) * THREADS);
+                    // Keeps the threads of the next round from arriving before all threads check.
+                    let _ = barrier.wait();
+                }
+            });
+        }
+    });
+    assert_eq!(leaders.load(Ordering::Relaxed), ROUNDS);
+}
+
+/// The latch opens after as many count downs as its count, and stays open.
+#[test]
+fn latch_count_down() {
+    let latch = CountDownLatch::new(2);
+    latch.count_down();
+    assert_eq!(latch.count(), 1);
+    let start = Instant::now();
+    assert!(!latch.wait_timeout(Duration::from_millis(100)));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    latch.count_down();
+    latch.count_down();
+    assert_eq!(latch.count(), 0);
+    latch.wait();
+    assert!(latch.wait_timeout(Duration::ZERO));
+
+    CountDownLatch::new(0).wait();
+}
+
+/// All threads waiting on the latch are woken up when it opens.
+#[test]
+fn latch_wakes_all() {
+    const THREADS: usize = 4;
+
+    let latch = CountDownLatch::new(THREADS);
+    let woken = AtomicUsize::new(0);
+    scope(|s| {
+        for _ in 0..THREADS {
+            let _ = s.spawn(|| {
+                latch.wait();
+                let _ = woken.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        sleep(Duration::from_millis(100));
+        assert_eq!(woken.load(Ordering::Relaxed), 0);
+        for _ in 0..THREADS {
+            let _ = s.spawn(|| latch.count_down());
+        }
+    });
+    assert_eq!(woken.load(Ordering::Relaxed), THREADS);
+}
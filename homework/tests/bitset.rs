@@ -0,0 +1,181 @@
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::threads::run;
+    use cs431_homework::{AtomicBitSet, GrowableBitSet};
+    use rand::prelude::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn smoke() {
+        let bits = AtomicBitSet::new(100);
+        assert_eq!(bits.len(), 100);
+        assert!(!bits.test(3));
+        bits.set(3);
+        assert!(bits.test(3));
+        assert!(bits.test_and_set(3));
+        assert!(!bits.test_and_set(64));
+        assert!(bits.test_and_clear(64));
+        assert!(!bits.test_and_clear(64));
+        bits.set(99);
+        assert_eq!(bits.iter().collect::<Vec<_>>(), [3, 99]);
+        assert_eq!(bits.count(), 2);
+        assert_eq!(format!("{bits:?}"), "{3, 99}");
+        bits.clear_all();
+        assert_eq!(bits.iter().next(), None);
+
+        let bits = GrowableBitSet::new();
+        assert!(!bits.test(1000));
+        assert!(!bits.test_and_clear(1000));
+        bits.set(1000);
+        bits.set(5);
+        assert!(bits.test(1000));
+        assert_eq!(bits.iter().collect::<Vec<_>>(), [5, 1000]);
+        bits.clear(5);
+        assert_eq!(bits.count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn out_of_bounds() {
+        let bits = AtomicBitSet::new(100);
+        bits.set(100);
+    }
+
+    /// The lowest clear bit is handed out, and never one past the end.
+    #[test]
+    fn set_first_clear() {
+        let bits = AtomicBitSet::new(70);
+        for i in 0..70 {
+            assert_eq!(bits.set_first_clear(), Some(i));
+        }
+        assert_eq!(bits.set_first_clear(), None);
+        bits.clear(65);
+        bits.clear(7);
+        assert_eq!(bits.set_first_clear(), Some(7));
+        assert_eq!(bits.set_first_clear(), Some(65));
+
+        let bits = GrowableBitSet::new();
+        for i in 0..1000 {
+            assert_eq!(bits.set_first_clear(), i);
+        }
+        bits.clear(500);
+        assert_eq!(bits.set_first_clear(), 500);
+        assert_eq!(bits.set_first_clear(), 1000);
+    }
+
+    /// Random operations agree with `BTreeSet`.
+    #[test]
+    fn sequential() {
+        const ITER: usize = 1024 * 16;
+        const LEN: usize = 1000;
+
+        let bits = AtomicBitSet::new(LEN);
+        let growable = GrowableBitSet::new();
+        let mut reference = BTreeSet::new();
+        let mut rng = thread_rng();
+        for _ in 0..ITER {
+            let index = rng.gen_range(0..LEN);
+            if rng.gen() {
+                let present = !reference.insert(index);
+                assert_eq!(bits.test_and_set(index), present);
+                assert_eq!(growable.test_and_set(index * 7), present);
+            } else {
+                let present = reference.remove(&index);
+                assert_eq!(bits.test_and_clear(index), present);
+                assert_eq!(growable.test_and_clear(index * 7), present);
+            }
+        }
+        assert!(bits.iter().eq(reference.iter().copied()));
+        assert!(growable.iter().eq(reference.iter().map(|index| index * 7)));
+        assert_eq!(growable.count(), reference.len());
+    }
+
+    /// The threads take slots and give them back. No slot is held by two threads at once.
+    #[test]
+    fn slots() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+        const HELD: usize = 8;
+
+        let bits = AtomicBitSet::new(THREADS * HELD);
+        let growable = GrowableBitSet::new();
+        let _ = run(THREADS, |_| {
+            let mut held = Vec::new();
+            let mut held_growable = Vec::new();
+            for i in 0..ITER {
+                if i % (HELD * 2) < HELD {
+                    held.push(bits.set_first_clear().unwrap());
+                    held_growable.push(growable.set_first_clear());
+                } else {
+                    assert!(bits.test_and_clear(held.pop().unwrap()));
+                    assert!(growable.test_and_clear(held_growable.pop().unwrap()));
+                }
+            }
+        });
+        assert_eq!(bits.count(), 0);
+        assert_eq!(growable.count(), 0);
+        assert_eq!(growable.set_first_clear(), 0);
+    }
+
+    /// Bits set concurrently in the same words, and in segments allocated concurrently, are all
+    /// kept.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+
+        let bits = AtomicBitSet::new(THREADS * ITER);
+        let growable = GrowableBitSet::new();
+        let _ = run(THREADS, |t| {
+            for i in 0..ITER {
+                bits.set(i * THREADS + t);
+                growable.set(i * THREADS + t);
+            }
+        });
+        assert_eq!(bits.count(), THREADS * ITER);
+        assert!(bits.iter().eq(0..THREADS * ITER));
+        assert!(growable.iter().eq(0..THREADS * ITER));
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::{AtomicBitSet, GrowableBitSet};
+
+    /// Threads racing for the last clear bits of a word take different ones.
+    #[test]
+    fn set_first_clear_sync() {
+        model(|| {
+            let bits = Arc::new(AtomicBitSet::new(2));
+            let th = {
+                let bits = bits.clone();
+                thread::spawn(move || bits.set_first_clear())
+            };
+            let mine = bits.set_first_clear();
+            let theirs = th.join().unwrap();
+            assert_ne!(mine, theirs);
+            assert_eq!(bits.set_first_clear(), None);
+        })
+    }
+
+    /// Threads growing the set at once allocate the same segment only once, and keep each other's
+    /// bits.
+    #[test]
+    fn grow_sync() {
+        model(|| {
+            let bits = Arc::new(GrowableBitSet::new());
+            let th = {
+                let bits = bits.clone();
+                thread::spawn(move || bits.set(1))
+            };
+            bits.set(2);
+            th.join().unwrap();
+            assert_eq!(bits.iter().collect::<Vec<_>>(), [1, 2]);
+        })
+    }
+}
@@ -0,0 +1,100 @@
+// The queue parks on wait lists, which are modeled with loom, so these run only without it.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::BlockingQueue;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{scope, sleep};
+use std::time::{Duration, Instant};
+
+#[test]
+fn put_take() {
+    let queue = BlockingQueue::new(2);
+    assert!(queue.is_empty());
+    assert_eq!(queue.put(1), Ok(()));
+    assert_eq!(queue.try_put(2), Ok(()));
+    assert_eq!(queue.try_put(3), Err(3));
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue.take(), Some(1));
+    assert_eq!(queue.try_take(), Some(2));
+    assert_eq!(queue.try_take(), None);
+}
+
+/// The timed variants give up after their timeouts.
+#[test]
+fn timeout() {
+    let queue = BlockingQueue::new(1);
+    let start = Instant::now();
+    assert_eq!(queue.take_timeout(Duration::from_millis(100)), None);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    queue.put(1).unwrap();
+    let start = Instant::now();
+    assert_eq!(queue.put_timeout(2, Duration::from_millis(100)), Err(2));
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    scope(|s| {
+        let _ = s.spawn(|| {
+            sleep(Duration::from_millis(100));
+            assert_eq!(queue.take(), Some(1));
+        });
+        assert_eq!(queue.put_timeout(2, Duration::from_secs(10)), Ok(()));
+    });
+    assert_eq!(queue.take_timeout(Duration::from_secs(10)), Some(2));
+}
+
+/// Shutting down wakes up the blocked threads. The remaining items can still be taken.
+#[test]
+fn shutdown() {
+    let queue = BlockingQueue::new(1);
+    scope(|s| {
+        let _ = s.spawn(|| assert_eq!(queue.take(), None));
+        sleep(Duration::from_millis(100));
+        assert!(queue.shutdown());
+    });
+    assert!(!queue.shutdown());
+    assert!(queue.is_shutdown());
+    assert_eq!(queue.put(1), Err(1));
+
+    let queue = BlockingQueue::new(1);
+    queue.put(1).unwrap();
+    scope(|s| {
+        let _ = s.spawn(|| assert_eq!(queue.put(2), Err(2)));
+        sleep(Duration::from_millis(100));
+        let _ = queue.shutdown();
+    });
+    assert_eq!(queue.take(), Some(1));
+    assert_eq!(queue.take(), None);
+}
+
+/// Each item is taken exactly once, in the order of each producer, through a small buffer.
+#[test]
+fn stress() {
+    const THREADS: usize = 4;
+    const ITEMS: usize = 1024 * 16;
+
+    let queue = BlockingQueue::new(4);
+    let taken = AtomicUsize::new(0);
+    scope(|s| {
+        for t in 0..THREADS {
+            let queue = &queue;
+            let _ = s.spawn(move || {
+                for i in 0..ITEMS {
+                    queue.put((t, i)).unwrap();
+                }
+            });
+        }
+        for _ in 0..THREADS {
+            let _ = s.spawn(|| {
+                let mut last = [None; THREADS];
+                while let Some((t, i)) = queue.take() {
+                    assert!(last[t] < Some(i));
+                    last[t] = Some(i);
+                    if taken.fetch_add(1, Ordering::Relaxed) + 1 == THREADS * ITEMS {
+                        let _ = queue.shutdown();
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(taken.load(Ordering::Relaxed), THREADS * ITEMS);
+}
@@ -0,0 +1,210 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable_by, MapOp, MapRet, MapSpec, Recorder};
+    use super::threads::{run, scope};
+    use cs431_homework::BPlusTree;
+    use rand::prelude::*;
+    use std::collections::BTreeMap;
+    use std::ops::Bound;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    fn collect<R: std::ops::RangeBounds<usize>>(
+        tree: &BPlusTree<usize, usize>,
+        range: R,
+    ) -> Vec<(usize, usize)> {
+        let mut entries = Vec::new();
+        tree.range(range, |key, value| entries.push((*key, *value)));
+        entries
+    }
+
+    #[test]
+    fn smoke() {
+        let tree = BPlusTree::with_fanout(3);
+        assert!(tree.is_empty());
+        for i in [3, 1, 4, 5, 9, 2, 6] {
+            assert_eq!(tree.insert(i, i * 10), Ok(()));
+        }
+        assert_eq!(tree.insert(4, 0), Err((4, 0)));
+        assert!(tree.contains_key(&9));
+        assert!(!tree.contains_key(&7));
+        assert_eq!(tree.lookup(&5, |value| value.copied()), Some(50));
+        assert_eq!(tree.remove(&4), Some(40));
+        assert_eq!(tree.remove(&4), None);
+        assert_eq!(
+            collect(&tree, ..),
+            [(1, 10), (2, 20), (3, 30), (5, 50), (6, 60), (9, 90)]
+        );
+        assert_eq!(collect(&tree, 2..6), [(2, 20), (3, 30), (5, 50)]);
+        assert_eq!(
+            collect(&tree, (Bound::Excluded(3), Bound::Included(9))),
+            [(5, 50), (6, 60), (9, 90)]
+        );
+
+        for i in [1, 2, 3, 5, 6, 9] {
+            assert!(tree.remove(&i).is_some());
+        }
+        assert!(tree.is_empty());
+    }
+
+    /// Random operations, including range scans, agree with `BTreeMap`.
+    #[test]
+    fn sequential() {
+        const ITER: usize = 1024 * 16;
+
+        for fanout in [3, 4, 32] {
+            let tree = BPlusTree::with_fanout(fanout);
+            let mut reference = BTreeMap::new();
+            let mut rng = thread_rng();
+            for _ in 0..ITER {
+                let key = rng.gen_range(0..256);
+                match rng.gen_range(0..4) {
+                    0 => assert_eq!(
+                        tree.insert(key, key * 2).is_ok(),
+                        reference.insert(key, key * 2).is_none()
+                    ),
+                    1 => assert_eq!(tree.remove(&key), reference.remove(&key)),
+                    2 => assert_eq!(
+                        tree.lookup(&key, |value| value.copied()),
+                        reference.get(&key).copied()
+                    ),
+                    _ => {
+                        let end = key + rng.gen_range(0..32);
+                        assert!(collect(&tree, key..end)
+                            .into_iter()
+                            .eq(reference.range(key..end).map(|(k, v)| (*k, *v))));
+                    }
+                }
+            }
+            assert!(collect(&tree, ..).into_iter().eq(reference.into_iter()));
+        }
+    }
+
+    /// Each thread owns the keys of its residue, so it knows exactly which of them are in the
+    /// tree, while the others insert and remove around them and the nodes split.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+        const KEYS: usize = 1024;
+
+        let tree = BPlusTree::with_fanout(4);
+        scope(|s| {
+            for t in 0..THREADS {
+                let tree = &tree;
+                let _ = s.spawn(move || {
+                    let mut rng = thread_rng();
+                    let mut mine = vec![false; KEYS];
+                    for _ in 0..ITER {
+                        let index = rng.gen_range(0..KEYS);
+                        let key = index * THREADS + t;
+                        if rng.gen() {
+                            assert_eq!(tree.insert(key, t).is_ok(), !mine[index]);
+                            mine[index] = true;
+                        } else {
+                            assert_eq!(tree.remove(&key).is_some(), mine[index]);
+                            mine[index] = false;
+                        }
+
+                        // A scan sees the keys in order, and sees exactly the keys of this thread
+                        // that are in the tree.
+                        let mut last = None;
+                        let mut seen = 0;
+                        tree.range(key..key + 16 * THREADS, |key, value| {
+                            assert!(last < Some(*key));
+                            last = Some(*key);
+                            if *value == t {
+                                assert!(mine[key / THREADS]);
+                                seen += 1;
+                            }
+                        });
+                        let expected = (index..KEYS.min(index + 16)).filter(|i| mine[*i]).count();
+                        assert_eq!(seen, expected);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Keys and values are dropped exactly once, whether removed or left in the tree.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let tree = BPlusTree::with_fanout(4);
+        for i in 0..100 {
+            assert!(tree.insert(i, Canary(&dropped)).is_ok());
+        }
+        for i in 0..50 {
+            drop(tree.remove(&i));
+        }
+        assert_eq!(dropped.load(Relaxed), 50);
+        drop(tree);
+        assert_eq!(dropped.load(Relaxed), 100);
+    }
+
+    /// Histories of random operations on a few keys are linearizable.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024;
+
+        let tree = BPlusTree::with_fanout(3);
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let key = rng.gen_range(0..8);
+                let op = match rng.gen_range(0..3) {
+                    0 => MapOp::Insert(key, t * ITER + i),
+                    1 => MapOp::Remove(key),
+                    _ => MapOp::Lookup(key),
+                };
+                recorder.record(&mut history, op, |op| match *op {
+                    MapOp::Insert(key, value) => MapRet::Done(tree.insert(key, value).is_ok()),
+                    MapOp::Remove(key) => MapRet::Done(tree.remove(&key).is_some()),
+                    MapOp::Lookup(key) => MapRet::Value(tree.lookup(&key, |value| value.copied())),
+                    MapOp::Upsert(..) => unreachable!(),
+                });
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable_by(&MapSpec::default(), &history, |op| *op.key());
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::BPlusTree;
+
+    /// An insertion that splits the root does not lose the key of a concurrent insertion, nor hide
+    /// the keys from a concurrent lookup or scan.
+    #[test]
+    fn split_sync() {
+        model(|| {
+            let tree = Arc::new(BPlusTree::with_fanout(3));
+            tree.insert(1, ()).unwrap();
+            let th = {
+                let tree = tree.clone();
+                thread::spawn(move || tree.insert(2, ()).unwrap())
+            };
+            tree.insert(3, ()).unwrap();
+            assert!(tree.contains_key(&3));
+            let mut keys = Vec::new();
+            tree.range(.., |key, _| keys.push(*key));
+            assert!(keys == [1, 3] || keys == [1, 2, 3]);
+            th.join().unwrap();
+            assert!((1..=3).all(|key| tree.contains_key(&key)));
+        })
+    }
+}
@@ -0,0 +1,115 @@
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use cs431_homework::broadcast::{channel, LagPolicy, RecvError, TryRecvError};
+    use std::thread::scope;
+
+    #[test]
+    fn smoke() {
+        let (sender, mut first) = channel(4, LagPolicy::Block);
+        assert_eq!(first.try_recv(), Err(TryRecvError::Empty));
+        sender.send(1).unwrap();
+        let mut second = sender.subscribe();
+        let mut third = first.clone();
+        assert_eq!(sender.receiver_count(), 3);
+        sender.send(2).unwrap();
+
+        assert_eq!(first.recv(), Ok(1));
+        assert_eq!(first.recv(), Ok(2));
+        assert_eq!(second.recv(), Ok(2));
+        assert_eq!(third.try_recv(), Ok(1));
+        drop(sender);
+        assert_eq!(first.recv(), Err(RecvError::Closed));
+        assert_eq!(third.try_recv(), Ok(2));
+        assert_eq!(third.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    /// A message sent without receivers is returned back.
+    #[test]
+    fn no_receivers() {
+        let (sender, receiver) = channel(1, LagPolicy::Block);
+        drop(receiver);
+        assert_eq!(sender.send(1), Err(1));
+        let mut receiver = sender.subscribe();
+        assert_eq!(sender.send(2), Ok(()));
+        assert_eq!(receiver.recv(), Ok(2));
+    }
+
+    /// The receivers lagging behind miss the oldest messages, silently or not.
+    #[test]
+    fn lagging() {
+        let (sender, mut silent) = channel(2, LagPolicy::DropOldest);
+        for i in 0..5 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(silent.recv(), Ok(3));
+        assert_eq!(silent.recv(), Ok(4));
+
+        let (sender, mut loud) = channel(2, LagPolicy::Error);
+        for i in 0..5 {
+            sender.send(i).unwrap();
+        }
+        assert_eq!(loud.recv(), Err(RecvError::Lagged(3)));
+        assert_eq!(loud.recv(), Ok(3));
+        assert_eq!(loud.try_recv(), Ok(4));
+        assert_eq!(loud.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    /// With `Block`, every receiver receives every message of every sender, in the order of each
+    /// sender, however far behind it is.
+    #[test]
+    fn block_stress() {
+        const SENDERS: usize = 4;
+        const RECEIVERS: usize = 4;
+        const MESSAGES: usize = 1024 * 4;
+
+        let (sender, receiver) = channel(8, LagPolicy::Block);
+        scope(|s| {
+            for _ in 0..RECEIVERS {
+                let mut receiver = receiver.clone();
+                let _ = s.spawn(move || {
+                    let mut last = [None; SENDERS];
+                    for _ in 0..SENDERS * MESSAGES {
+                        let (t, i) = receiver.recv().unwrap();
+                        assert!(last[t] < Some(i));
+                        last[t] = Some(i);
+                    }
+                    assert_eq!(receiver.recv(), Err(RecvError::Closed));
+                });
+            }
+            drop(receiver);
+            for t in 0..SENDERS {
+                let sender = sender.clone();
+                let _ = s.spawn(move || {
+                    for i in 0..MESSAGES {
+                        sender.send((t, i)).unwrap();
+                    }
+                });
+            }
+            drop(sender);
+        });
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::thread;
+    use cs431_homework::broadcast::{channel, LagPolicy};
+
+    /// A sender blocked on a full channel is woken up by the receiver catching up, or dropped.
+    #[test]
+    fn block_sync() {
+        model(|| {
+            let (sender, first) = channel(1, LagPolicy::Block);
+            let mut second = first.clone();
+            drop(first);
+            let th = thread::spawn(move || {
+                assert_eq!(second.recv(), Ok(1));
+            });
+            sender.send(1).unwrap();
+            assert!(matches!(sender.send(2), Ok(()) | Err(2)));
+            th.join().unwrap();
+        })
+    }
+}
@@ -1,112 +1,324 @@
-use crossbeam_channel::bounded;
-use cs431_homework::hello_server::Cache;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Barrier;
-use std::thread::scope;
-use std::time::Duration;
-
-const NUM_THREADS: usize = 8;
-const NUM_KEYS: usize = 128;
-
-#[test]
-fn cache_no_duplicate_sequential() {
-    let cache = Cache::default();
-    cache.get_or_insert_with(1, |_| 1);
-    cache.get_or_insert_with(2, |_| 2);
-    cache.get_or_insert_with(3, |_| 3);
-    assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 1);
-    assert_eq!(cache.get_or_insert_with(2, |_| panic!()), 2);
-    assert_eq!(cache.get_or_insert_with(3, |_| panic!()), 3);
-}
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use cs431_homework::hello_server::Cache;
+    use cs431_homework::{Barrier, CountDownLatch};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread::{scope, sleep};
+    use std::time::{Duration, Instant};
 
-#[test]
-fn cache_no_duplicate_concurrent() {
-    for _ in 0..8 {
+    const NUM_THREADS: usize = 8;
+    const NUM_KEYS: usize = 128;
+
+    #[test]
+    fn cache_no_duplicate_sequential() {
         let cache = Cache::default();
-        let barrier = Barrier::new(NUM_THREADS);
-        // Count the number of times the computation is run.
+        cache.get_or_insert_with(1, |_| 1);
+        cache.get_or_insert_with(2, |_| 2);
+        cache.get_or_insert_with(3, |_| 3);
+        assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 1);
+        assert_eq!(cache.get_or_insert_with(2, |_| panic!()), 2);
+        assert_eq!(cache.get_or_insert_with(3, |_| panic!()), 3);
+    }
+
+    #[test]
+    fn cache_no_duplicate_concurrent() {
+        for _ in 0..8 {
+            let cache = Cache::default();
+            let barrier = Barrier::new(NUM_THREADS);
+            // Count the number of times the computation is run.
+            let num_compute = AtomicUsize::new(0);
+            scope(|s| {
+                for _ in 0..NUM_THREADS {
+                    s.spawn(|| {
+                        let _ = barrier.wait();
+                        for key in 0..NUM_KEYS {
+                            cache.get_or_insert_with(key, |k| {
+                                num_compute.fetch_add(1, Ordering::Relaxed);
+                                k
+                            });
+                        }
+                    });
+                }
+            });
+            assert_eq!(num_compute.load(Ordering::Relaxed), NUM_KEYS);
+        }
+    }
+
+    #[test]
+    fn cache_no_block_disjoint() {
+        let cache = &Cache::default();
+        let t1_quit = &CountDownLatch::new(1);
+        let t2_done = &CountDownLatch::new(1);
+
+        scope(|s| {
+            // T1 blocks while inserting 1.
+            s.spawn(move || {
+                cache.get_or_insert_with(1, |k| {
+                    // block T1
+                    t1_quit.wait();
+                    k
+                });
+            });
+
+            // T2 must not be blocked by T1 when inserting 2.
+            s.spawn(move || {
+                cache.get_or_insert_with(2, |k| k);
+                t2_done.count_down();
+            });
+
+            // If T2 is blocked, then this will time out.
+            assert!(
+                t2_done.wait_timeout(Duration::from_secs(3)),
+                "Inserting a different key should not block"
+            );
+
+            // clean up
+            t1_quit.count_down();
+        });
+    }
+
+    #[test]
+    fn cache_no_reader_block() {
+        let cache = &Cache::default();
+        let t1_quit = &CountDownLatch::new(1);
+        let t3_done = &CountDownLatch::new(1);
+
+        scope(|s| {
+            // T1 blocks while inserting 1.
+            s.spawn(move || {
+                cache.get_or_insert_with(1, |k| {
+                    // T2 is blocked by T1 when reading 1
+                    s.spawn(move || cache.get_or_insert_with(1, |_| panic!()));
+
+                    // T3 should not be blocked when inserting 3.
+                    s.spawn(move || {
+                        cache.get_or_insert_with(3, |k| k);
+                        t3_done.count_down();
+                    });
+
+                    // block T1
+                    t1_quit.wait();
+                    k
+                });
+            });
+
+            // If T3 is blocked, then this will time out.
+            assert!(
+                t3_done.wait_timeout(Duration::from_secs(3)),
+                "Inserting a different key should not block"
+            );
+
+            // clean up
+            t1_quit.count_down();
+        });
+    }
+
+    /// A cache whose map lock is biased towards the readers computes each value once, while the
+    /// insertions keep revoking the bias.
+    #[test]
+    fn cache_read_biased() {
+        let cache = Cache::read_biased(None);
         let num_compute = AtomicUsize::new(0);
         scope(|s| {
             for _ in 0..NUM_THREADS {
                 s.spawn(|| {
-                    barrier.wait();
-                    for key in 0..NUM_KEYS {
-                        cache.get_or_insert_with(key, |k| {
-                            num_compute.fetch_add(1, Ordering::Relaxed);
-                            k
-                        });
+                    for _ in 0..8 {
+                        for key in 0..NUM_KEYS {
+                            assert_eq!(
+                                cache.get_or_insert_with(key, |k| {
+                                    num_compute.fetch_add(1, Ordering::Relaxed);
+                                    k
+                                }),
+                                key
+                            );
+                        }
                     }
                 });
             }
         });
         assert_eq!(num_compute.load(Ordering::Relaxed), NUM_KEYS);
+
+        assert!(cache.invalidate(&0));
+        assert_eq!(cache.get_or_insert_with(0, |_| 10), 10);
+        assert_eq!(cache.entries().len(), NUM_KEYS);
+        assert!(cache.is_available());
     }
-}
 
-#[test]
-fn cache_no_block_disjoint() {
-    let cache = &Cache::default();
-
-    scope(|s| {
-        // T1 blocks while inserting 1.
-        let (t1_quit_sender, t1_quit_receiver) = bounded(0);
-        s.spawn(move || {
-            cache.get_or_insert_with(1, |k| {
-                // block T1
-                t1_quit_receiver.recv().unwrap();
-                k
-            });
-        });
+    #[test]
+    fn cache_ttl() {
+        let cache = Cache::with_ttl(Duration::from_millis(100));
+        assert_eq!(cache.get_or_insert_with(1, |_| 1), 1);
+        assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 1);
+        assert_eq!(cache.entries(), [(1, 1)]);
 
-        // T2 must not be blocked by T1 when inserting 2.
-        let (t2_done_sender, t2_done_receiver) = bounded(0);
-        s.spawn(move || {
-            cache.get_or_insert_with(2, |k| k);
-            t2_done_sender.send(()).unwrap();
-        });
+        // The expired value is recomputed.
+        sleep(Duration::from_millis(200));
+        assert!(cache.entries().is_empty());
+        assert_eq!(cache.get_or_insert_with(1, |_| 2), 2);
+        assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 2);
+    }
 
-        // If T2 is blocked, then this will time out.
-        t2_done_receiver
-            .recv_timeout(Duration::from_secs(3))
-            .expect("Inserting a different key should not block");
+    /// `sweep` removes the expired values, but not the fresh ones.
+    #[test]
+    fn cache_sweep() {
+        let cache = Cache::with_ttl(Duration::from_millis(100));
+        for key in 0..2 {
+            cache.get_or_insert_with(key, |k| k);
+        }
+        sleep(Duration::from_millis(200));
+        cache.get_or_insert_with(2, |k| k);
+        assert_eq!(cache.sweep(), 2);
+        assert_eq!(cache.entries(), [(2, 2)]);
+        assert_eq!(cache.get_or_insert_with(0, |_| 10), 10);
 
-        // clean up
-        t1_quit_sender.send(()).unwrap();
-    });
-}
+        cache.set_ttl(None);
+        assert_eq!(cache.sweep(), 0);
+    }
+
+    /// `retain` removes the values for which the predicate returns `false`.
+    #[test]
+    fn cache_retain() {
+        let cache = Cache::default();
+        for key in 0..10 {
+            cache.get_or_insert_with(key, |k| k * 10);
+        }
+        assert_eq!(cache.retain(|key, value| key % 2 == 0 && *value < 50), 7);
+        let mut entries = cache.entries();
+        entries.sort();
+        assert_eq!(entries, [(0, 0), (2, 20), (4, 40)]);
+    }
 
-#[test]
-fn cache_no_reader_block() {
-    let cache = &Cache::default();
+    #[test]
+    fn cache_invalidate() {
+        let cache = Cache::default();
+        for key in 0..3 {
+            cache.get_or_insert_with(key, |k| k);
+        }
 
-    scope(|s| {
-        let (t1_quit_sender, t1_quit_receiver) = bounded(0);
-        let (t3_done_sender, t3_done_receiver) = bounded(0);
+        assert!(cache.invalidate(&0));
+        assert!(!cache.invalidate(&0));
+        assert_eq!(cache.get_or_insert_with(0, |_| 10), 10);
 
-        // T1 blocks while inserting 1.
-        s.spawn(move || {
-            cache.get_or_insert_with(1, |k| {
-                // T2 is blocked by T1 when reading 1
-                s.spawn(move || cache.get_or_insert_with(1, |_| panic!()));
+        assert_eq!(cache.invalidate_all(), 3);
+        assert!(cache.entries().is_empty());
+        assert_eq!(cache.get_or_insert_with(1, |_| 11), 11);
+    }
 
-                // T3 should not be blocked when inserting 3.
-                s.spawn(move || {
-                    cache.get_or_insert_with(3, |k| k);
-                    t3_done_sender.send(()).unwrap();
-                });
+    #[test]
+    fn cache_panic() {
+        let cache = Cache::default();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cache.get_or_insert_with(1, |_| panic!("computation failed"))
+        }));
+        assert!(result.is_err());
 
-                // block T1
-                t1_quit_receiver.recv().unwrap();
-                k
-            });
+        // The failed computation is retried.
+        assert_eq!(cache.get_or_insert_with(1, |k| k), 1);
+    }
+
+    #[test]
+    fn cache_try_get_or_insert_with() {
+        let cache = Cache::default();
+        assert_eq!(
+            cache.try_get_or_insert_with(1, |_| Err("failed")),
+            Err("failed")
+        );
+        // The failure is not cached.
+        assert_eq!(cache.try_get_or_insert_with(1, |_| Ok::<_, ()>(1)), Ok(1));
+        assert_eq!(cache.try_get_or_insert_with(1, |_| Err(())), Ok(1));
+        assert_eq!(cache.get_or_insert_with(1, |_| panic!()), 1);
+    }
+
+    #[test]
+    fn cache_get_or_insert_many() {
+        let cache = Cache::default();
+        let _ = cache.get_or_insert_with(1, |_| 10);
+
+        let num_compute = AtomicUsize::new(0);
+        let start = Instant::now();
+        let results = cache.get_or_insert_many([3, 1, 2, 3], |key| {
+            let _ = num_compute.fetch_add(1, Ordering::Relaxed);
+            sleep(Duration::from_millis(500));
+            key * 10
         });
+        assert_eq!(results, [(3, 30), (1, 10), (2, 20)]);
+        // The missing values are computed once each, concurrently.
+        assert_eq!(num_compute.load(Ordering::Relaxed), 2);
+        assert!(start.elapsed() < Duration::from_millis(900));
 
-        // If T3 is blocked, then this will time out.
-        t3_done_receiver
-            .recv_timeout(Duration::from_secs(3))
-            .expect("Inserting a different key should not block");
+        let results = cache.try_get_or_insert_many([2, 4], Err);
+        assert_eq!(results, [(2, Ok(20)), (4, Err(4))]);
+        assert_eq!(cache.get_or_insert_with(4, |_| 40), 40);
+    }
+}
 
-        // clean up
-        t1_quit_sender.send(()).unwrap();
-    });
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::hello_server::Cache;
+
+    /// Of two lookups of a missing key, one computes the value and the other waits for it.
+    #[test]
+    fn get_or_insert_sync() {
+        model(|| {
+            let cache = Arc::new(Cache::default());
+            let calls = Arc::new(AtomicUsize::new(0));
+            let th = {
+                let cache = cache.clone();
+                let calls = calls.clone();
+                thread::spawn(move || {
+                    cache.get_or_insert_with(1, |_| {
+                        let _ = calls.fetch_add(1, Relaxed);
+                        2
+                    })
+                })
+            };
+            let value = cache.get_or_insert_with(1, |_| {
+                let _ = calls.fetch_add(1, Relaxed);
+                2
+            });
+            assert_eq!(value, 2);
+            assert_eq!(th.join().unwrap(), 2);
+            assert_eq!(calls.load(Relaxed), 1);
+        })
+    }
+
+    /// A failed computation caches nothing, and a lookup waiting for it computes the value
+    /// itself.
+    #[test]
+    fn try_get_or_insert_sync() {
+        model(|| {
+            let cache = Arc::new(Cache::default());
+            let th = {
+                let cache = cache.clone();
+                thread::spawn(move || cache.try_get_or_insert_with(1, |_| Err(())))
+            };
+            assert_eq!(cache.get_or_insert_with(1, |_| 2), 2);
+            // The failure is seen only if it comes first.
+            assert!(matches!(th.join().unwrap(), Ok(2) | Err(())));
+            assert_eq!(cache.entries(), [(1, 2)]);
+        })
+    }
+
+    /// A value invalidated while it is looked up is either returned or computed again.
+    #[test]
+    fn invalidate_sync() {
+        model(|| {
+            let cache = Arc::new(Cache::default());
+            assert_eq!(cache.get_or_insert_with(1, |_| 1), 1);
+            let th = {
+                let cache = cache.clone();
+                thread::spawn(move || assert!(cache.invalidate(&1)))
+            };
+            let value = cache.get_or_insert_with(1, |_| 2);
+            th.join().unwrap();
+            assert!(value == 1 || value == 2);
+            assert_eq!(cache.entries().len(), usize::from(value == 2));
+        })
+    }
 }
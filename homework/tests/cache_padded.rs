@@ -0,0 +1,32 @@
+use core::mem;
+use cs431_homework::CachePadded;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn smoke() {
+    let mut padded = CachePadded::new(1);
+    *padded += 1;
+    assert_eq!(*padded, 2);
+    assert_eq!(format!("{padded:?}"), "CachePadded(2)");
+    assert_eq!(padded.into_inner(), 2);
+}
+
+/// Adjacent padded values are on different cache lines.
+#[test]
+fn layout() {
+    let align = mem::align_of::<CachePadded<u8>>();
+    assert!(align >= 64 && align.is_power_of_two());
+    assert_eq!(mem::size_of::<CachePadded<u8>>(), align);
+    assert_eq!(mem::size_of::<CachePadded<[u8; 65]>>() % align, 0);
+
+    let counters = [
+        CachePadded::new(AtomicUsize::new(0)),
+        CachePadded::new(AtomicUsize::new(0)),
+    ];
+    let first = &*counters[0] as *const _ as usize;
+    let second = &*counters[1] as *const _ as usize;
+    assert_eq!(first % align, 0);
+    assert!(second - first >= align);
+    let _ = counters[1].fetch_add(1, Ordering::Relaxed);
+    assert_eq!(counters[0].load(Ordering::Relaxed), 0);
+}
@@ -0,0 +1,18 @@
+//! Values that count their drops, for the tests that check that a structure drops each of its
+//! values exactly once.
+
+// Each test crate uses a part of it.
+#![allow(dead_code)]
+
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+/// Value that increments the counter it borrows when it is dropped. A clone is another value,
+/// which is dropped on its own.
+#[derive(Debug, Clone)]
+pub struct Canary<'a>(pub &'a AtomicUsize);
+
+impl Drop for Canary<'_> {
+    fn drop(&mut self) {
+        let _ = self.0.fetch_add(1, Relaxed);
+    }
+}
@@ -0,0 +1,113 @@
+use cs431_homework::hello_server::{Config, ConfigStore, RateLimit};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[test]
+fn config_parse() {
+    let config = Config::parse(
+        "# comment\n\
+         read_timeout = 0.5\n\
+         \n\
+         rate_limit_burst = 10\n\
+         rate_limit_per_second = 2.5\n\
+         cache_ttl = 60\n\
+         upstream = 127.0.0.1:8080\n\
+         admin_token = secret\n\
+         max_body_len = 0\n",
+    )
+    .unwrap();
+    assert_eq!(
+        config,
+        Config {
+            read_timeout: Duration::from_millis(500),
+            rate_limit: Some(RateLimit {
+                burst: 10,
+                per_second: 2.5
+            }),
+            cache_ttl: Some(Duration::from_secs(60)),
+            upstream: Some(SocketAddr::from(([127, 0, 0, 1], 8080))),
+            admin_token: Some("secret".to_string()),
+            max_body_len: 0,
+            ..Config::default()
+        }
+    );
+
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+}
+
+#[test]
+fn config_parse_invalid() {
+    for text in [
+        "read_timeout",
+        "read_timeout = 0",
+        "read_timeout = -1",
+        "unknown = 1",
+        "rate_limit_burst = 10",
+        "upstream = 127.0.0.1",
+        "admin_token =",
+        "max_body_len = -1",
+        "max_body_len = 1k",
+        "rate_limit_burst = 0\nrate_limit_per_second = 1",
+    ] {
+        assert!(Config::parse(text).is_err(), "{text}");
+    }
+}
+
+#[test]
+fn config_store() {
+    let store = ConfigStore::default();
+    let first = store.get();
+    assert_eq!(*first, Config::default());
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    {
+        let seen = seen.clone();
+        store.subscribe(move |config| {
+            seen.lock().unwrap().push(config.max_body_len);
+            config.max_body_len != 2
+        });
+    }
+    let published = store.publish(Config {
+        max_body_len: 1,
+        ..Config::default()
+    });
+    assert!(Arc::ptr_eq(&published, &store.get()));
+    let _ = store.update(|config| config.max_body_len += 1);
+    let _ = store.update(|config| config.max_body_len += 1);
+    assert_eq!(store.get().max_body_len, 3);
+    // The subscriber sees the current version first, and is dropped once it returns `false`.
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [Config::default().max_body_len, 1, 2]
+    );
+    // Versions read before stay as they were.
+    assert_eq!(*first, Config::default());
+}
+
+#[test]
+fn config_store_reload() {
+    let path = std::env::temp_dir().join(format!("config_store_{}", std::process::id()));
+    fs::write(&path, "cache_ttl = 60\n").unwrap();
+    let store = ConfigStore::open(&path).unwrap();
+    assert_eq!(store.path(), Some(path.as_path()));
+    assert_eq!(store.get().cache_ttl, Some(Duration::from_secs(60)));
+
+    fs::write(&path, "cache_ttl = 30\n").unwrap();
+    assert_eq!(
+        store.reload().unwrap().cache_ttl,
+        Some(Duration::from_secs(30))
+    );
+    // An invalid file keeps the current version.
+    fs::write(&path, "cache_ttl = soon\n").unwrap();
+    assert!(store.reload().is_err());
+    assert_eq!(store.get().cache_ttl, Some(Duration::from_secs(30)));
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        ConfigStore::default().reload().unwrap_err().kind(),
+        io::ErrorKind::NotFound
+    );
+}
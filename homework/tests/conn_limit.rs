@@ -0,0 +1,39 @@
+// The connection limit runs on a semaphore, which is modeled with loom, so these run only without
+// it.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::hello_server::{ConnectionLimit, OverloadPolicy};
+use cs431_homework::CountDownLatch;
+use std::sync::Arc;
+use std::thread::scope;
+use std::time::Duration;
+
+#[test]
+fn conn_limit_reject() {
+    let limit = Arc::new(ConnectionLimit::new(2, OverloadPolicy::Reject));
+    let p1 = limit.acquire().unwrap();
+    let _p2 = limit.acquire().unwrap();
+    assert!(limit.acquire().is_none());
+    drop(p1);
+    assert!(limit.acquire().is_some());
+}
+
+#[test]
+fn conn_limit_wait() {
+    let limit = Arc::new(ConnectionLimit::new(1, OverloadPolicy::Wait));
+    let permit = limit.acquire().unwrap();
+
+    let done = CountDownLatch::new(1);
+    scope(|s| {
+        s.spawn(|| {
+            let _permit = limit.acquire().unwrap();
+            done.count_down();
+        });
+
+        // Blocked while the permit is held.
+        assert!(!done.wait_timeout(Duration::from_millis(100)));
+        drop(permit);
+        assert!(done.wait_timeout(Duration::from_secs(3)));
+    });
+    assert_eq!(limit.active(), 0);
+}
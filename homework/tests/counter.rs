@@ -0,0 +1,36 @@
+use cs431_homework::ConcurrentCounter;
+use std::thread::scope;
+
+#[test]
+fn add_sum() {
+    let counter = ConcurrentCounter::with_stripes(3);
+    assert_eq!(counter.sum(), 0);
+    counter.increment();
+    counter.add(10);
+    counter.decrement();
+    assert_eq!(counter.sum(), 10);
+    assert_eq!(counter.take(), 10);
+    assert_eq!(counter.sum(), 0);
+}
+
+/// No update is lost, and each is taken exactly once.
+#[test]
+fn stress() {
+    const THREADS: i64 = 8;
+    const ITER: i64 = 1024 * 64;
+
+    let counter = ConcurrentCounter::new();
+    let taken = scope(|s| {
+        for _ in 0..THREADS {
+            let _ = s.spawn(|| {
+                for i in 0..ITER {
+                    counter.add(i);
+                }
+            });
+        }
+        s.spawn(|| (0..1024).map(|_| counter.take()).sum::<i64>())
+            .join()
+            .unwrap()
+    });
+    assert_eq!(taken + counter.take(), THREADS * ITER * (ITER - 1) / 2);
+}
@@ -0,0 +1,312 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable_by, MapOp, MapRet, MapSpec, Recorder};
+    use super::threads::{run, scope};
+    use cs431_homework::ebr::collect;
+    use cs431_homework::Ctrie;
+    use rand::prelude::*;
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn smoke() {
+        let map = Ctrie::new();
+        assert!(map.is_empty());
+        map.insert("a", 1);
+        map.insert("b", 2);
+        map.insert("a", 3);
+        assert_eq!(map.get("a"), Some(3));
+        assert_eq!(map.lookup("b", |value| value.copied()), Some(2));
+        assert!(!map.contains_key("c"));
+        assert_eq!(map.len(), 2);
+        assert!(map.remove("a"));
+        assert!(!map.remove("a"));
+        assert_eq!(map.len(), 1);
+    }
+
+    /// Random operations agree with `HashMap`.
+    #[test]
+    fn sequential() {
+        const ITER: usize = 1024 * 16;
+
+        let map = Ctrie::new();
+        let mut reference = HashMap::new();
+        let mut rng = thread_rng();
+        for _ in 0..ITER {
+            let key = rng.gen_range(0..1024);
+            match rng.gen_range(0..3) {
+                0 => {
+                    map.insert(key, key * 2);
+                    let _ = reference.insert(key, key * 2);
+                }
+                1 => assert_eq!(map.remove(&key), reference.remove(&key).is_some()),
+                _ => assert_eq!(map.get(&key), reference.get(&key).copied()),
+            }
+        }
+        let mut entries = map
+            .iter()
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect::<Vec<_>>();
+        let mut expected = reference.into_iter().collect::<Vec<_>>();
+        entries.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(entries, expected);
+    }
+
+    /// Hashes equal in all levels, so that the keys end up in a list.
+    #[derive(Debug, Clone, Copy, Eq)]
+    struct Collide(u32);
+
+    impl PartialEq for Collide {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl Hash for Collide {
+        fn hash<H: Hasher>(&self, _: &mut H) {}
+    }
+
+    #[test]
+    fn collisions() {
+        let map = Ctrie::new();
+        for i in 0..8 {
+            map.insert(Collide(i), i);
+        }
+        map.insert(Collide(3), 33);
+        assert_eq!(map.get(&Collide(3)), Some(33));
+        assert_eq!(map.len(), 8);
+        for i in 0..8 {
+            assert!(map.remove(&Collide(i)));
+            assert!(!map.contains_key(&Collide(i)));
+            assert_eq!(map.len(), 7 - i as usize);
+        }
+        map.insert(Collide(0), 0);
+        assert_eq!(map.get(&Collide(0)), Some(0));
+    }
+
+    /// A snapshot and the map it is taken from are updated independently.
+    #[test]
+    fn snapshot() {
+        let map = Ctrie::new();
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+        let snapshot = map.snapshot();
+        for i in 0..500 {
+            assert!(map.remove(&i));
+            snapshot.insert(i, i + 1);
+        }
+        map.insert(1000, 1000);
+        assert_eq!(map.len(), 501);
+        assert_eq!(snapshot.len(), 1000);
+        assert_eq!(map.get(&0), None);
+        assert_eq!(snapshot.get(&0), Some(1));
+        assert_eq!(snapshot.get(&999), Some(999));
+        assert_eq!(snapshot.get(&1000), None);
+
+        let again = snapshot.snapshot();
+        snapshot.insert(0, 0);
+        assert_eq!(again.get(&0), Some(1));
+    }
+
+    /// Iterators see the keys inserted in order as a prefix, and the keys removed in order as a
+    /// suffix, however the writers race with them.
+    #[test]
+    fn iter_consistent() {
+        const KEYS: usize = 1024 * 4;
+
+        let map = Ctrie::new();
+        let done = AtomicBool::new(false);
+        scope(|s| {
+            let _ = s.spawn(|| {
+                for i in 0..KEYS {
+                    map.insert(i, i);
+                }
+                for i in 0..KEYS {
+                    assert!(map.remove(&i));
+                }
+                done.store(true, Relaxed);
+            });
+            for _ in 0..2 {
+                let _ = s.spawn(|| {
+                    while !done.load(Relaxed) {
+                        let mut keys = map.iter().map(|entry| *entry.key()).collect::<Vec<_>>();
+                        keys.sort_unstable();
+                        if let (Some(&first), Some(&last)) = (keys.first(), keys.last()) {
+                            assert!(keys.iter().copied().eq(first..=last));
+                            assert!(first == 0 || last == KEYS - 1);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(map.is_empty());
+    }
+
+    /// Each thread owns the keys of its residue, so it knows exactly which of them are in the map,
+    /// while the others insert, remove and take snapshots around them.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 8;
+        const KEYS: usize = 512;
+
+        let map = Ctrie::new();
+        scope(|s| {
+            for t in 0..THREADS {
+                let map = &map;
+                let _ = s.spawn(move || {
+                    let mut rng = thread_rng();
+                    let mut mine = vec![None; KEYS];
+                    for i in 0..ITER {
+                        let index = rng.gen_range(0..KEYS);
+                        let key = index * THREADS + t;
+                        match rng.gen_range(0..3) {
+                            0 => {
+                                map.insert(key, i);
+                                mine[index] = Some(i);
+                            }
+                            1 => assert_eq!(map.remove(&key), mine[index].take().is_some()),
+                            _ => assert_eq!(map.get(&key), mine[index]),
+                        }
+                        if i % 256 == 0 {
+                            let snapshot = map.snapshot();
+                            for (index, value) in mine.iter().enumerate() {
+                                assert_eq!(snapshot.get(&(index * THREADS + t)), *value);
+                            }
+                        }
+                    }
+                    for (index, value) in mine.into_iter().enumerate() {
+                        assert_eq!(map.get(&(index * THREADS + t)), value);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Keys and values are dropped exactly once, whether removed, replaced or left in the map and
+    /// its snapshots.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let map = Ctrie::new();
+        for i in 0..100 {
+            map.insert(i, Canary(&dropped));
+        }
+        let snapshot = map.snapshot();
+        for i in 0..50 {
+            map.insert(i, Canary(&dropped));
+            assert!(snapshot.remove(&i));
+        }
+        drop(map);
+        drop(snapshot);
+        // Other tests may be pinned meanwhile, which only delays the reclamation.
+        while dropped.load(Relaxed) < 150 {
+            collect();
+        }
+        assert_eq!(dropped.load(Relaxed), 150);
+    }
+
+    /// Histories of random operations on a few keys are linearizable, snapshots included: a
+    /// snapshot looks up the key of each operation as the map had it at some point during it.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024;
+
+        let map = Ctrie::new();
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let key = rng.gen_range(0..8);
+                let op = match rng.gen_range(0..3) {
+                    0 => MapOp::Upsert(key, t * ITER + i),
+                    1 => MapOp::Remove(key),
+                    _ => MapOp::Lookup(key),
+                };
+                let snapshot = rng.gen_ratio(1, 8);
+                recorder.record(&mut history, op, |op| match *op {
+                    MapOp::Upsert(key, value) => {
+                        map.insert(key, value);
+                        MapRet::Unit
+                    }
+                    MapOp::Remove(key) => MapRet::Done(map.remove(&key)),
+                    MapOp::Lookup(key) if snapshot => MapRet::Value(map.snapshot().get(&key)),
+                    MapOp::Lookup(key) => MapRet::Value(map.get(&key)),
+                    MapOp::Insert(..) => unreachable!(),
+                });
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable_by(&MapSpec::default(), &history, |op| *op.key());
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::ebr::collect;
+    use cs431_homework::Ctrie;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    /// Returns keys in the same branch of the root, so that their updates race with a snapshot
+    /// in an I-node that the map shares with it. Loom does not randomize the hashes.
+    fn keys() -> Vec<u64> {
+        let hash = |key: &u64| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+        (0..)
+            .filter(|key| hash(key) % 64 == hash(&0) % 64)
+            .take(4)
+            .collect()
+    }
+
+    /// An insertion racing with a snapshot is either in the snapshot or not, but always in the
+    /// map, even if the map copies the I-node it is inserted in meanwhile.
+    ///
+    /// The full model is too large to explore. Run with `LOOM_MAX_PREEMPTIONS=2`.
+    #[test]
+    fn insert_snapshot_sync() {
+        model(|| {
+            let keys = keys();
+            let map = Arc::new(Ctrie::new());
+            map.insert(keys[0], 0);
+            map.insert(keys[1], 1);
+            let th = {
+                let map = map.clone();
+                let key = keys[2];
+                thread::spawn(move || {
+                    map.insert(key, 2);
+                    collect();
+                })
+            };
+            let snapshot = map.snapshot();
+            map.insert(keys[3], 3);
+            th.join().unwrap();
+            collect();
+            for (value, key) in keys.iter().enumerate() {
+                assert_eq!(map.get(key), Some(value));
+            }
+            assert_eq!(snapshot.get(&keys[1]), Some(1));
+            assert!(matches!(snapshot.get(&keys[2]), None | Some(2)));
+            assert_eq!(snapshot.get(&keys[3]), None);
+        })
+    }
+}
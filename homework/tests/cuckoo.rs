@@ -0,0 +1,261 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod stress;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable_by, MapOp, MapRet, MapSpec, Recorder};
+    use super::stress::{Op, Stress};
+    use super::threads::{run, scope};
+    use cs431_homework::CuckooMap;
+    use rand::prelude::*;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn smoke() {
+        let map = CuckooMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert("a", 1), None);
+        assert_eq!(map.insert("b", 2), None);
+        assert_eq!(map.insert("a", 3), Some(1));
+        assert_eq!(map.get("a"), Some(3));
+        assert_eq!(map.lookup("b", |value| value.copied()), Some(2));
+        assert!(!map.contains_key("c"));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.remove("a"), Some(3));
+        assert_eq!(map.remove("a"), None);
+        assert_eq!(map.len(), 1);
+    }
+
+    /// Random operations agree with `HashMap`, while the table grows from a single bucket.
+    #[test]
+    fn sequential() {
+        const ITER: usize = 1024 * 16;
+
+        let map = CuckooMap::with_capacity(1);
+        let mut reference = HashMap::new();
+        let mut rng = thread_rng();
+        for i in 0..ITER {
+            let key = rng.gen_range(0..1024);
+            match rng.gen_range(0..3) {
+                0 => assert_eq!(map.insert(key, i), reference.insert(key, i)),
+                1 => assert_eq!(map.remove(&key), reference.remove(&key)),
+                _ => assert_eq!(map.get(&key), reference.get(&key).copied()),
+            }
+        }
+        assert_eq!(map.len(), reference.len());
+        assert!(reference
+            .iter()
+            .all(|(key, value)| map.get(key) == Some(*value)));
+    }
+
+    /// The table fills up to a high load factor before it grows.
+    #[test]
+    fn load_factor() {
+        const CAPACITY: usize = 1024 * 4;
+
+        let map = CuckooMap::with_capacity(CAPACITY);
+        assert_eq!(map.capacity(), CAPACITY);
+        let mut len = 0;
+        while map.capacity() == CAPACITY {
+            assert_eq!(map.insert(len, ()), None);
+            len += 1;
+        }
+        assert!(len > CAPACITY * 9 / 10, "grew at {len} of {CAPACITY}");
+        assert!((0..len).all(|key| map.contains_key(&key)));
+    }
+
+    /// Keys inserted concurrently, while the table grows and the entries move, are all kept, and
+    /// the keys that stay in the map are always found.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+        const STAYING: usize = 64;
+
+        let map = CuckooMap::with_capacity(1);
+        for key in 0..STAYING {
+            let _ = map.insert(key, key);
+        }
+        let done = AtomicBool::new(false);
+        let found = AtomicUsize::new(0);
+        scope(|s| {
+            let _ = s.spawn(|| {
+                while !done.load(Relaxed) {
+                    for key in 0..STAYING {
+                        assert_eq!(map.get(&key), Some(key));
+                    }
+                    let _ = found.fetch_add(1, Relaxed);
+                }
+            });
+            let _ = s.run(THREADS, |t| {
+                for i in 0..ITER {
+                    let key = STAYING + i * THREADS + t;
+                    assert_eq!(map.insert(key, key), None);
+                    if i % 2 == 1 {
+                        assert_eq!(map.remove(&(key - THREADS)), Some(key - THREADS));
+                    }
+                }
+            });
+            done.store(true, Relaxed);
+        });
+        assert!(found.load(Relaxed) > 0);
+        assert_eq!(map.len(), STAYING + THREADS * ITER / 2);
+        for i in 0..ITER {
+            for t in 0..THREADS {
+                let key = STAYING + i * THREADS + t;
+                assert_eq!(map.contains_key(&key), i % 2 == 1);
+            }
+        }
+    }
+
+    /// The values left in the map, and those replaced, are dropped.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        {
+            let map = CuckooMap::with_capacity(1);
+            for key in 0..100 {
+                let _ = map.insert(key, Canary(&dropped));
+            }
+            for key in 0..50 {
+                drop(map.insert(key, Canary(&dropped)));
+            }
+            assert_eq!(dropped.load(Relaxed), 50);
+        }
+        assert_eq!(dropped.load(Relaxed), 150);
+    }
+
+    /// Histories of random operations on a few keys are linearizable.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024;
+
+        let map = CuckooMap::with_capacity(1);
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let key = rng.gen_range(0..16);
+                let op = match rng.gen_range(0..3) {
+                    0 => MapOp::Upsert(key, t * ITER + i),
+                    1 => MapOp::Remove(key),
+                    _ => MapOp::Lookup(key),
+                };
+                recorder.record(&mut history, op, |op| match *op {
+                    MapOp::Upsert(key, value) => {
+                        let _ = map.insert(key, value);
+                        MapRet::Unit
+                    }
+                    MapOp::Remove(key) => MapRet::Done(map.remove(&key).is_some()),
+                    MapOp::Lookup(key) => MapRet::Value(map.get(&key)),
+                    MapOp::Insert(..) => unreachable!(),
+                });
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable_by(&MapSpec::default(), &history, |op| *op.key());
+    }
+
+    impl super::stress::Subject for CuckooMap<u64, u64> {
+        type Model = HashMap<u64, u64>;
+
+        fn apply(&self, op: Op) -> Option<u64> {
+            match op {
+                Op::Insert(key, value) => self.insert(key, value),
+                Op::Remove(key) => self.remove(&key),
+                Op::Get(key) => self.get(&key),
+            }
+        }
+
+        fn check(&self) {
+            assert!(self.len() <= self.capacity());
+        }
+    }
+
+    #[test]
+    fn model_disjoint() {
+        Stress::new(4, 1024 * 8, 1024).disjoint::<CuckooMap<u64, u64>>();
+    }
+
+    #[test]
+    fn model_shared() {
+        Stress::new(4, 1024 * 8, 64).shared::<CuckooMap<u64, u64>>();
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::CuckooMap;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    /// Returns keys whose two buckets in a table of two buckets are `first` and `second`. Loom
+    /// does not randomize the hashes.
+    fn keys(first: u64, second: u64) -> impl Iterator<Item = u64> {
+        let hash = |key: &u64| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+        (0..).filter(move |key| {
+            let hash = hash(key);
+            hash & 1 == first && (hash >> 32) & 1 == second
+        })
+    }
+
+    /// A key moved to its other bucket to make room for another one is always found.
+    #[test]
+    fn displace_sync() {
+        model(|| {
+            let map = Arc::new(CuckooMap::with_capacity(8));
+            assert_eq!(map.capacity(), 8);
+            let mut stuck = keys(0, 0);
+            let moving = keys(0, 1).next().unwrap();
+            for _ in 0..3 {
+                let _ = map.insert(stuck.next().unwrap(), ());
+            }
+            // The fourth slot of the first bucket.
+            let _ = map.insert(moving, ());
+            let th = {
+                let map = map.clone();
+                let key = stuck.next().unwrap();
+                thread::spawn(move || map.insert(key, ()))
+            };
+            assert!(map.contains_key(&moving));
+            th.join().unwrap();
+            assert_eq!(map.capacity(), 8);
+            assert_eq!(map.len(), 5);
+        })
+    }
+
+    /// A key is always found while the table grows.
+    #[test]
+    fn grow_sync() {
+        model(|| {
+            let map = Arc::new(CuckooMap::with_capacity(1));
+            for key in 0..4 {
+                let _ = map.insert(key, key);
+            }
+            let th = {
+                let map = map.clone();
+                thread::spawn(move || map.insert(4, 4))
+            };
+            assert_eq!(map.get(&0), Some(0));
+            th.join().unwrap();
+            assert!(map.capacity() > 4);
+            assert!((0..5).all(|key| map.get(&key) == Some(key)));
+        })
+    }
+}
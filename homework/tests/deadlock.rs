@@ -0,0 +1,136 @@
+// The locks are those of loom with `check-loom`, so these run only without it.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::deadlock::{Mutex, RwLock};
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+#[test]
+fn smoke() {
+    let mutex = Mutex::new(1);
+    *mutex.lock().unwrap() += 1;
+    let lock = RwLock::new(String::from("a"));
+    {
+        let a = lock.read().unwrap();
+        assert_eq!(*a, "a");
+    }
+    lock.write().unwrap().push('b');
+    assert_eq!(format!("{:?}", mutex.lock().unwrap()), "2");
+    assert_eq!(mutex.into_inner().unwrap(), 2);
+    assert_eq!(lock.into_inner().unwrap(), "ab");
+}
+
+/// A thread that panics while holding a lock poisons it, and the guard is still returned.
+#[test]
+fn poison() {
+    let mutex = Mutex::new(0);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut guard = mutex.lock().unwrap();
+        *guard = 1;
+        panic!();
+    }));
+    assert!(result.is_err());
+    assert!(mutex.is_poisoned());
+    assert_eq!(*mutex.lock().unwrap_err().into_inner(), 1);
+}
+
+/// Locks acquired in the same order by all threads, including hand over hand, are not reported.
+#[test]
+fn consistent_order() {
+    let locks = (0..4).map(Mutex::new).collect::<Vec<_>>();
+    thread::scope(|s| {
+        for _ in 0..4 {
+            let _ = s.spawn(|| {
+                for _ in 0..1024 {
+                    let mut prev = locks[0].lock().unwrap();
+                    for lock in &locks[1..] {
+                        let next = lock.lock().unwrap();
+                        prev = next;
+                    }
+                    drop(prev);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(feature = "deadlock-detection")]
+mod detection {
+    use super::*;
+
+    fn panic_message(f: impl FnOnce() + Send) -> String {
+        let payload = thread::scope(|s| s.spawn(f).join().unwrap_err());
+        match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => String::from(*payload.downcast::<&str>().unwrap()),
+        }
+    }
+
+    /// Acquiring two locks in the opposite order of another thread is reported, even though the
+    /// threads do not run at the same time.
+    #[test]
+    fn inversion() {
+        let a = Mutex::new(());
+        let b = RwLock::new(());
+        thread::scope(|s| {
+            let _ = s.spawn(|| {
+                let _a = a.lock().unwrap();
+                let _b = b.write().unwrap();
+            });
+        });
+
+        let message = panic_message(|| {
+            // Reading does not poison the lock.
+            let _b = b.read().unwrap();
+            let _a = a.lock().unwrap();
+        });
+        assert!(message.contains("lock order cycle"), "{message}");
+        assert!(message.contains("tests/deadlock.rs"), "{message}");
+        assert_eq!(message.lines().count(), 3, "{message}");
+
+        // The locks are usable after the report.
+        let _a = a.lock().unwrap();
+        let _b = b.write().unwrap();
+    }
+
+    /// A cycle through several locks, none of which is acquired in both orders, is reported.
+    #[test]
+    fn cycle() {
+        let locks = [Mutex::new(()), Mutex::new(()), Mutex::new(())];
+        for i in 0..2 {
+            let _first = locks[i].lock().unwrap();
+            let _second = locks[i + 1].lock().unwrap();
+        }
+        let message = panic_message(|| {
+            let _last = locks[2].lock().unwrap();
+            let _first = locks[0].lock().unwrap();
+        });
+        assert!(message.contains("lock order cycle"), "{message}");
+        assert_eq!(message.lines().count(), 4, "{message}");
+    }
+
+    /// Acquiring a lock the thread already holds is reported.
+    #[test]
+    fn reacquire() {
+        let lock = RwLock::new(());
+        let message = panic_message(|| {
+            let _read = lock.read().unwrap();
+            let _write = lock.write().unwrap();
+        });
+        assert!(message.contains("already held"), "{message}");
+    }
+
+    /// The order of a dropped lock is forgotten, so that a lock created in its place starts anew.
+    #[test]
+    fn dropped() {
+        let a = Mutex::new(());
+        {
+            let b = Mutex::new(());
+            let _b = b.lock().unwrap();
+            let _a = a.lock().unwrap();
+        }
+        let b = Mutex::new(());
+        let _a = a.lock().unwrap();
+        let _b = b.lock().unwrap();
+    }
+}
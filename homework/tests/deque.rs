@@ -0,0 +1,165 @@
+mod canary;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::threads::{scope, Handle};
+    use cs431_homework::deque::{Steal, Worker};
+    use cs431_homework::hazard_pointer::collect;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn push_pop_steal() {
+        let worker = Worker::with_capacity(2);
+        let stealer = worker.stealer();
+        assert!(worker.is_empty());
+        assert_eq!(worker.pop(), None);
+        assert_eq!(stealer.steal(), Steal::Empty);
+
+        // Grows the buffer a few times.
+        for i in 0..10 {
+            worker.push(i);
+        }
+        assert_eq!(worker.len(), 10);
+        assert_eq!(worker.pop(), Some(9));
+        assert_eq!(stealer.steal(), Steal::Success(0));
+        let other = stealer.clone();
+        assert_eq!(other.steal(), Steal::Success(1));
+        for i in (2..9).rev() {
+            assert_eq!(worker.pop(), Some(i));
+        }
+        assert!(stealer.is_empty());
+        assert!(other.is_empty());
+        assert_eq!(worker.pop(), None);
+        assert_eq!(stealer.steal(), Steal::Empty);
+    }
+
+    /// Every pushed value is taken exactly once, by the worker or by one of the thieves, while the
+    /// buffer grows.
+    #[test]
+    fn stress() {
+        const THIEVES: usize = 4;
+        const ITER: usize = 1024 * 64;
+
+        let worker = Worker::with_capacity(1);
+        let done = AtomicBool::new(false);
+        let (mine, stolen) = scope(|s| {
+            // Spawns all thieves before the worker starts pushing, so that they steal while it pushes.
+            #[allow(clippy::needless_collect)]
+            let handles = (0..THIEVES)
+                .map(|_| {
+                    let stealer = worker.stealer();
+                    let done = &done;
+                    s.spawn(move || {
+                        let mut stolen = Vec::new();
+                        while !done.load(Relaxed) || !stealer.is_empty() {
+                            if let Steal::Success(value) = stealer.steal() {
+                                stolen.push(value);
+                            }
+                            collect();
+                        }
+                        stolen
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut mine = Vec::new();
+            for i in 0..ITER {
+                worker.push(i);
+                if i % 3 == 0 {
+                    mine.extend(worker.pop());
+                }
+                collect();
+            }
+            done.store(true, Relaxed);
+            let stolen = handles
+                .into_iter()
+                .flat_map(Handle::join)
+                .collect::<Vec<_>>();
+            (mine, stolen)
+        });
+
+        assert!(worker.is_empty());
+        let taken = mine.len() + stolen.len();
+        let values = mine.into_iter().chain(stolen).collect::<HashSet<_>>();
+        assert_eq!(taken, ITER);
+        assert_eq!(values.len(), ITER);
+    }
+
+    /// Values left in the deque are dropped with it, and taken values are not dropped again, even
+    /// after the buffer grows.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let worker = Worker::with_capacity(2);
+        let stealer = worker.stealer();
+        for _ in 0..10 {
+            worker.push(Canary(&dropped));
+        }
+        drop(worker.pop());
+        drop(stealer.steal());
+        collect();
+        assert_eq!(dropped.load(Relaxed), 2);
+        drop(worker);
+        assert_eq!(dropped.load(Relaxed), 2);
+        drop(stealer);
+        assert_eq!(dropped.load(Relaxed), 10);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::thread;
+    use cs431_homework::deque::{Steal, Worker};
+    use cs431_homework::hazard_pointer::collect;
+
+    /// The last value is taken by exactly one of the worker and the thief.
+    #[test]
+    fn pop_steal_sync() {
+        model(|| {
+            let worker = Worker::with_capacity(1);
+            let stealer = worker.stealer();
+            worker.push(Box::new(1));
+            let th = thread::spawn(move || match stealer.steal() {
+                Steal::Success(value) => Some(value),
+                Steal::Empty | Steal::Retry => None,
+            });
+            let mine = worker.pop();
+            let theirs = th.join().unwrap();
+            assert!(matches!((mine, theirs), (Some(_), None) | (None, Some(_))));
+        })
+    }
+
+    /// A thief reading the old buffer while the worker grows it gets a pushed value, and the old
+    /// buffer is not freed under it.
+    #[test]
+    fn grow_steal_sync() {
+        model(|| {
+            let worker = Worker::with_capacity(1);
+            let stealer = worker.stealer();
+            worker.push(1);
+            let th = thread::spawn(move || {
+                let value = stealer.steal();
+                collect();
+                value
+            });
+            worker.push(2);
+            collect();
+            match th.join().unwrap() {
+                Steal::Success(value) => {
+                    assert_eq!(value, 1);
+                    assert_eq!(worker.pop(), Some(2));
+                }
+                stolen => {
+                    assert_eq!(stolen, Steal::Empty);
+                    assert_eq!(worker.pop(), Some(2));
+                    assert_eq!(worker.pop(), Some(1));
+                }
+            }
+            assert_eq!(worker.pop(), None);
+        })
+    }
+}
@@ -0,0 +1,274 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable, QueueOp, QueueSpec, Recorder};
+    use super::threads::{run, scope};
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::DList;
+    use rand::prelude::*;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn smoke() {
+        let list = DList::new();
+        assert!(list.is_empty());
+        assert!(list.pop_front().is_none());
+        assert!(list.pop_back().is_none());
+        list.push_back(2);
+        list.push_front(1);
+        list.push_back(3);
+        assert!(!list.is_empty());
+        assert_eq!(list.pop_front().map(|entry| *entry.value()), Some(1));
+        assert_eq!(list.pop_back().map(|entry| *entry.value()), Some(3));
+        assert_eq!(list.pop_back().map(|entry| *entry.value()), Some(2));
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn cursor() {
+        let list = DList::new();
+        for i in [1, 3, 5] {
+            list.push_back(i);
+        }
+        let mut cursor = list.cursor_front();
+        assert_eq!(cursor.get(), None);
+        assert!(!cursor.move_prev());
+        assert!(cursor.move_next());
+        assert_eq!(cursor.get(), Some(&1));
+        assert!(cursor.move_next());
+        assert_eq!(cursor.get(), Some(&3));
+        assert_eq!(cursor.insert_before(2), Ok(()));
+        assert_eq!(cursor.insert_after(4), Ok(()));
+        assert_eq!(cursor.get(), Some(&3));
+
+        // The cursor stays on the removed node, and can still move away from it.
+        assert!(cursor.remove());
+        assert!(!cursor.remove());
+        assert_eq!(cursor.get(), None);
+        assert_eq!(cursor.insert_after(6), Err(6));
+        assert!(cursor.move_next());
+        assert_eq!(cursor.get(), Some(&4));
+        assert!(cursor.move_prev());
+        assert_eq!(cursor.get(), Some(&2));
+
+        // Inserting at an end inserts into the list.
+        let back = list.cursor_back();
+        assert!(!back.remove());
+        assert_eq!(back.insert_after(6), Ok(()));
+        let mut values = Vec::new();
+        let mut cursor = list.cursor_front();
+        while cursor.move_next() {
+            values.push(*cursor.get().unwrap());
+        }
+        assert_eq!(values, [1, 2, 4, 5, 6]);
+        assert!(!cursor.move_next());
+        values.clear();
+        while cursor.move_prev() {
+            values.push(*cursor.get().unwrap());
+        }
+        assert_eq!(values, [6, 5, 4, 2, 1]);
+    }
+
+    /// Random operations agree with `VecDeque`.
+    #[test]
+    fn sequential() {
+        const ITER: usize = 1024 * 16;
+
+        let list = DList::new();
+        let mut reference = VecDeque::new();
+        let mut rng = thread_rng();
+        for i in 0..ITER {
+            match rng.gen_range(0..4) {
+                0 => {
+                    list.push_front(i);
+                    reference.push_front(i);
+                }
+                1 => {
+                    list.push_back(i);
+                    reference.push_back(i);
+                }
+                2 => assert_eq!(
+                    list.pop_front().map(|entry| *entry.value()),
+                    reference.pop_front()
+                ),
+                _ => assert_eq!(
+                    list.pop_back().map(|entry| *entry.value()),
+                    reference.pop_back()
+                ),
+            }
+        }
+        let mut cursor = list.cursor_front();
+        for value in reference {
+            assert!(cursor.move_next());
+            assert_eq!(cursor.get(), Some(&value));
+        }
+        assert!(!cursor.move_next());
+    }
+
+    /// Each value pushed at either end is popped exactly once, from either end, while cursors
+    /// walk, insert and remove in between.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 8;
+
+        let list = DList::new();
+        let results = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let (mut pushed, mut popped) = (Vec::new(), Vec::new());
+            for i in 0..ITER {
+                let value = t * ITER + i;
+                let action = rng.gen_range(0..5);
+                if action != 2 && action != 3 {
+                    pushed.push(value);
+                }
+                match action {
+                    0 => list.push_front(value),
+                    1 => list.push_back(value),
+                    2 => popped.extend(list.pop_front().map(|entry| *entry.value())),
+                    3 => popped.extend(list.pop_back().map(|entry| *entry.value())),
+                    _ => {
+                        // Replaces a value near an end with this one.
+                        let mut cursor = if rng.gen() {
+                            list.cursor_front()
+                        } else {
+                            list.cursor_back()
+                        };
+                        for _ in 0..rng.gen_range(1..4) {
+                            let _ = if rng.gen() {
+                                cursor.move_next()
+                            } else {
+                                cursor.move_prev()
+                            };
+                        }
+                        if let Some(&removed) = cursor.get() {
+                            if cursor.remove() {
+                                popped.push(removed);
+                            }
+                        }
+                        if cursor.insert_after(value).is_err() {
+                            list.push_back(value);
+                        }
+                    }
+                }
+                collect();
+            }
+            (pushed, popped)
+        });
+        let (pushed, popped): (Vec<_>, Vec<_>) = results.into_iter().unzip();
+        let mut pushed = pushed.into_iter().flatten().collect::<Vec<_>>();
+        let mut popped = popped.into_iter().flatten().collect::<Vec<_>>();
+        while let Some(entry) = list.pop_front() {
+            popped.push(*entry.value());
+        }
+        pushed.sort_unstable();
+        popped.sort_unstable();
+        assert_eq!(popped, pushed);
+    }
+
+    /// Values are dropped exactly once, whether removed or left in the list, and the removed
+    /// nodes do not keep each other alive.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let list = DList::new();
+        for _ in 0..100 {
+            list.push_back(Canary(&dropped));
+        }
+        scope(|s| {
+            s.run(2, |t| {
+                for _ in 0..20 {
+                    if t == 0 {
+                        assert!(list.pop_front().is_some());
+                    } else {
+                        assert!(list.pop_back().is_some());
+                    }
+                }
+                collect();
+            });
+        });
+        let mut cursor = list.cursor_front();
+        for _ in 0..10 {
+            assert!(cursor.move_next());
+            assert!(cursor.remove());
+        }
+        drop(cursor);
+        collect();
+        assert_eq!(dropped.load(Relaxed), 50);
+        drop(list);
+        assert_eq!(dropped.load(Relaxed), 100);
+    }
+
+    /// Histories of pushes to the back and pops from the front are linearizable as a queue.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 4;
+        const ITER: usize = 256;
+
+        let list = DList::new();
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let op = if rng.gen() {
+                    QueueOp::Push(t * ITER + i)
+                } else {
+                    QueueOp::Pop
+                };
+                recorder.record(&mut history, op, |op| match *op {
+                    QueueOp::Push(value) => {
+                        list.push_back(value);
+                        None
+                    }
+                    QueueOp::Pop => list.pop_front().map(|entry| *entry.value()),
+                });
+                collect();
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable(&QueueSpec::default(), &history);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::DList;
+
+    /// The last value is popped from exactly one end, and its node is not accessed after the
+    /// thread that popped it reclaims it.
+    ///
+    /// The full model is too large to explore. Run with `LOOM_MAX_PREEMPTIONS=2`.
+    #[test]
+    fn pop_both_ends_sync() {
+        model(|| {
+            let list = Arc::new(DList::new());
+            list.push_back(1);
+            let th = {
+                let list = list.clone();
+                thread::spawn(move || {
+                    let popped = list.pop_front().map(|entry| *entry.value());
+                    collect();
+                    popped
+                })
+            };
+            let popped = list.pop_back().map(|entry| *entry.value());
+            collect();
+            let other = th.join().unwrap();
+            assert_eq!(popped.xor(other), Some(1));
+            assert!(list.is_empty());
+        })
+    }
+}
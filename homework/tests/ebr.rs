@@ -0,0 +1,163 @@
+mod canary;
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use core::ptr;
+    use cs431_homework::ebr::{collect, pin};
+    use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering::*};
+    use std::sync::Barrier;
+    use std::thread::scope;
+
+    #[test]
+    fn counter() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+
+        let count = AtomicPtr::new(Box::leak(Box::new(0usize)));
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    for _ in 0..ITER {
+                        let guard = pin();
+                        let mut new = Box::new(0);
+                        loop {
+                            let cur_ptr = count.load(Acquire);
+                            *new = unsafe { *cur_ptr } + 1;
+                            let new_ptr = Box::into_raw(new);
+                            if count
+                                .compare_exchange(cur_ptr, new_ptr, AcqRel, Acquire)
+                                .is_ok()
+                            {
+                                unsafe { guard.defer_destroy(cur_ptr) };
+                                break;
+                            }
+                            new = unsafe { Box::from_raw(new_ptr) };
+                        }
+                    }
+                });
+            }
+        });
+        let cur = count.load(Acquire);
+        assert_eq!(unsafe { *cur }, THREADS * ITER);
+        unsafe { drop(Box::from_raw(cur)) };
+    }
+
+    /// A deferred pointer is freed once the current thread is unpinned.
+    #[test]
+    fn defer_collect() {
+        let dropped = AtomicUsize::new(0);
+        let atomic = AtomicPtr::new(Box::into_raw(Box::new(Canary(&dropped))));
+
+        let guard = pin();
+        let local = atomic.swap(ptr::null_mut(), AcqRel);
+        unsafe { guard.defer_destroy(local) };
+        collect();
+        assert_eq!(dropped.load(Relaxed), 0);
+        drop(guard);
+
+        // Other tests may be pinned meanwhile, which only delays the reclamation.
+        while dropped.load(Relaxed) == 0 {
+            collect();
+        }
+        assert_eq!(dropped.load(Relaxed), 1);
+    }
+
+    /// A deferred pointer is not freed while another thread that may have loaded it is pinned.
+    #[test]
+    fn pinned_blocks_collect() {
+        let dropped = AtomicUsize::new(0);
+        let atomic = AtomicPtr::new(Box::into_raw(Box::new(Canary(&dropped))));
+        let loaded = Barrier::new(2);
+        let retired = Barrier::new(2);
+
+        scope(|s| {
+            let _ = s.spawn(|| {
+                let guard = pin();
+                let local = atomic.load(Acquire);
+                let _ = loaded.wait();
+                let _ = retired.wait();
+                assert_eq!(unsafe { (*local).0.load(Relaxed) }, 0);
+                drop(guard);
+            });
+
+            let _ = loaded.wait();
+            let guard = pin();
+            let local = atomic.swap(ptr::null_mut(), AcqRel);
+            unsafe { guard.defer_destroy(local) };
+            drop(guard);
+            for _ in 0..16 {
+                collect();
+            }
+            assert_eq!(dropped.load(Relaxed), 0);
+            let _ = retired.wait();
+        });
+
+        while dropped.load(Relaxed) == 0 {
+            collect();
+        }
+    }
+
+    /// Pinning is reentrant: dropping the inner guard does not unpin the thread.
+    #[test]
+    fn nested_pin() {
+        let dropped = AtomicUsize::new(0);
+        let local = Box::into_raw(Box::new(Canary(&dropped)));
+
+        let outer = pin();
+        let inner = pin();
+        unsafe { inner.defer_destroy(local) };
+        drop(inner);
+        for _ in 0..16 {
+            collect();
+        }
+        assert_eq!(dropped.load(Relaxed), 0);
+        drop(outer);
+
+        while dropped.load(Relaxed) == 0 {
+            collect();
+        }
+    }
+}
+
+mod sync {
+    use super::mock::model;
+    use super::mock::sync::atomic::{AtomicPtr, Ordering::*};
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use core::ptr;
+    use cs431_homework::ebr::*;
+
+    #[test]
+    fn pin_collect_sync() {
+        model(|| {
+            let atomic = Arc::new(AtomicPtr::new(ptr::null_mut::<usize>()));
+
+            let th = {
+                let atomic = atomic.clone();
+                thread::spawn(move || {
+                    let guard = pin();
+                    let local = atomic.load(Acquire);
+                    if !local.is_null() {
+                        // safe to deref a pointer loaded while pinned
+                        assert_eq!(unsafe { *local }, 123);
+                    }
+                    drop(guard);
+                })
+            };
+
+            // link
+            let local = Box::into_raw(Box::new(123));
+            atomic.store(local, Release);
+
+            // unlink, defer, and advance the epoch twice
+            atomic.store(ptr::null_mut(), Relaxed);
+            unsafe { pin().defer_destroy(local) };
+            collect();
+            collect();
+
+            th.join().unwrap();
+        })
+    }
+}
@@ -0,0 +1,92 @@
+// The topics of the bus are behind the locks of loom, which only work in a model.
+#![cfg(not(feature = "check-loom"))]
+
+mod threads;
+
+use crossbeam_channel::{RecvError, TryRecvError};
+use cs431_homework::hello_server::EventBus;
+use threads::scope;
+
+#[test]
+fn event_bus_fan_out() {
+    let bus = EventBus::new();
+    let first = bus.subscribe("stats", 4);
+    let second = bus.subscribe("stats", 4);
+    let admin = bus.subscribe("admin", 4);
+    assert_eq!(bus.publish("stats", 1), 2);
+    assert_eq!(bus.publish("admin", 2), 1);
+    assert_eq!(bus.publish("nobody", 3), 0);
+
+    assert_eq!(first.recv(), Ok(1));
+    assert_eq!(second.recv(), Ok(1));
+    assert_eq!(admin.recv(), Ok(2));
+    assert_eq!(first.try_recv(), Err(TryRecvError::Empty));
+    assert_eq!(admin.topic(), "admin");
+
+    // A subscription only receives the events published after it subscribed.
+    let late = bus.subscribe("stats", 4);
+    assert_eq!(late.try_recv(), Err(TryRecvError::Empty));
+}
+
+/// A full subscription misses events without holding back the publisher or the others.
+#[test]
+fn event_bus_full() {
+    let bus = EventBus::new();
+    let slow = bus.subscribe("stats", 2);
+    let fast = bus.subscribe("stats", 8);
+    for i in 0..4 {
+        let delivered = bus.publish("stats", i);
+        assert_eq!(delivered, if i < 2 { 2 } else { 1 });
+        assert_eq!(fast.recv(), Ok(i));
+    }
+    assert_eq!(slow.dropped(), 2);
+    assert_eq!(fast.dropped(), 0);
+    assert_eq!(slow.receiver().try_iter().collect::<Vec<_>>(), [0, 1]);
+}
+
+/// Dropped subscriptions are removed, and the remaining ones are closed with the bus.
+#[test]
+fn event_bus_close() {
+    let bus = EventBus::new();
+    let kept = bus.subscribe("stats", 4);
+    drop(bus.subscribe("stats", 4));
+    assert_eq!(bus.subscribers("stats"), 2);
+    assert_eq!(bus.publish("stats", 1), 1);
+    assert_eq!(bus.subscribers("stats"), 1);
+
+    drop(bus.clone());
+    assert_eq!(bus.publish("stats", 2), 1);
+    drop(bus);
+    assert_eq!(kept.recv(), Ok(1));
+    assert_eq!(kept.recv(), Ok(2));
+    assert_eq!(kept.recv(), Err(RecvError));
+}
+
+/// The subscriptions receive the events of concurrent publishers in the same order.
+#[test]
+fn event_bus_order() {
+    const PUBLISHERS: usize = 4;
+    const ITER: usize = 1024;
+
+    let bus = EventBus::new();
+    let subscriptions = [(); 2].map(|_| bus.subscribe("stats", PUBLISHERS * ITER));
+    scope(|s| {
+        for t in 0..PUBLISHERS {
+            let bus = bus.clone();
+            let _ = s.spawn(move || {
+                for i in 0..ITER {
+                    assert_eq!(bus.publish("stats", (t, i)), 2);
+                }
+            });
+        }
+    });
+    let [first, second] =
+        subscriptions.map(|subscription| subscription.receiver().try_iter().collect::<Vec<_>>());
+    assert_eq!(first.len(), PUBLISHERS * ITER);
+    assert_eq!(first, second);
+    // The events of each publisher are in order.
+    for t in 0..PUBLISHERS {
+        let events = first.iter().filter(|(publisher, _)| *publisher == t);
+        assert!(events.map(|(_, i)| *i).eq(0..ITER));
+    }
+}
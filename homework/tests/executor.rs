@@ -0,0 +1,172 @@
+// The executor parks and runs on the thread pool, which are modeled with loom, so these run only
+// without it.
+#![cfg(not(feature = "check-loom"))]
+
+mod canary;
+
+use canary::Canary;
+use cs431_homework::hello_server::{block_on, Executor, ThreadPool};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+const NUM_THREADS: usize = 4;
+
+/// Future that is pending the first `times` polls, and wakes itself each time.
+struct YieldNow {
+    times: usize,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.times == 0 {
+            return Poll::Ready(());
+        }
+        self.times -= 1;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Future that is ready once the flag is set by another thread.
+#[derive(Clone, Default)]
+struct Flag {
+    inner: Arc<Mutex<(bool, Option<Waker>)>>,
+}
+
+impl Flag {
+    fn set(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.0 = true;
+        if let Some(waker) = inner.1.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Future for Flag {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.0 {
+            return Poll::Ready(());
+        }
+        inner.1 = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[test]
+fn block_on_ready() {
+    assert_eq!(block_on(async { 42 }), 42);
+    block_on(YieldNow { times: 16 });
+}
+
+/// `block_on` parks until the future is woken by another thread.
+#[test]
+fn block_on_wake() {
+    let flag = Flag::default();
+    let setter = {
+        let flag = flag.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            flag.set();
+        })
+    };
+    block_on(flag);
+    setter.join().unwrap();
+}
+
+#[test]
+fn spawn_join() {
+    let pool = ThreadPool::new(NUM_THREADS);
+    let executor = Executor::new(&pool);
+    // Spawns all futures before blocking on any of them, so that they run concurrently.
+    #[allow(clippy::needless_collect)]
+    let handles = (0..1024)
+        .map(|i| executor.spawn(async move { i * i }))
+        .collect::<Vec<_>>();
+    for (i, handle) in handles.into_iter().enumerate() {
+        assert_eq!(block_on(handle), i * i);
+    }
+}
+
+/// A task that wakes itself while it is being polled is polled again.
+#[test]
+fn spawn_yield() {
+    let pool = ThreadPool::new(NUM_THREADS);
+    let executor = Executor::new(&pool);
+    let polls = Arc::new(AtomicUsize::new(0));
+    let handles = (0..64)
+        .map(|_| {
+            let polls = polls.clone();
+            executor.spawn(async move {
+                for _ in 0..64 {
+                    YieldNow { times: 1 }.await;
+                    polls.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    block_on(async {
+        for handle in handles {
+            handle.await;
+        }
+    });
+    assert_eq!(polls.load(Ordering::Relaxed), 64 * 64);
+}
+
+/// Tasks awaiting each other, and a task woken from outside the pool.
+#[test]
+fn spawn_chain() {
+    let pool = ThreadPool::new(NUM_THREADS);
+    let executor = Executor::new(&pool);
+    let flag = Flag::default();
+    let mut last = {
+        let flag = flag.clone();
+        executor.spawn(async move {
+            flag.await;
+            0
+        })
+    };
+    for _ in 0..100 {
+        let prev = last;
+        last = executor.spawn(async move { prev.await + 1 });
+    }
+    // Only the first task is waiting for the flag, so the pool is idle.
+    thread::sleep(Duration::from_millis(50));
+    pool.join();
+    flag.set();
+    assert_eq!(block_on(last), 100);
+}
+
+/// The tasks are dropped with the pool, even if they wake themselves while it is dropped.
+#[test]
+fn drop_pool() {
+    const TASKS: usize = 16;
+
+    // A leaked task would outlive the test, so the count does too.
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    for round in 1..=16 {
+        let pool = ThreadPool::new(NUM_THREADS);
+        let executor = Executor::new(&pool);
+        for _ in 0..TASKS {
+            let canary = Canary(&DROPPED);
+            drop(executor.spawn(async move {
+                let _canary = canary;
+                loop {
+                    YieldNow { times: 1 }.await;
+                }
+            }));
+        }
+        drop(pool);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), round * TASKS);
+    }
+}
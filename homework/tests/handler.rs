@@ -0,0 +1,850 @@
+// The handler runs on the thread pool and the cache, which are modeled with loom, so these run only
+// without it.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::hello_server::{
+    Auth, Config, ConfigStore, Handler, RateLimit, Response, ThreadPool,
+};
+use std::fs;
+use std::io::{prelude::*, Cursor};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread::{scope, sleep};
+use std::time::{Duration, Instant};
+
+fn bind() -> (SocketAddr, TcpListener) {
+    let mut port = 24567;
+    loop {
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port));
+        if let Ok(listener) = TcpListener::bind(addr) {
+            break (addr, listener);
+        }
+        port += 1;
+    }
+}
+
+#[test]
+fn handler_not_found() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+
+    scope(|s| {
+        s.spawn(|| {
+            let (stream, _) = listener.accept().unwrap();
+            handler.handle_conn(0, stream);
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut resp = String::new();
+        let _ = stream.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 404"));
+    });
+}
+
+#[test]
+fn handler_status_codes() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+    let requests = [
+        ("GET /metrics HTTP/1.1\r\n\r\n", "200"),
+        ("GET /not/found HTTP/1.1\r\n\r\n", "404"),
+        ("POST /healthz HTTP/1.1\r\n\r\n", "405"),
+        ("HEAD /healthz HTTP/1.1\r\n\r\n", "200"),
+        ("GET /healthz\r\n\r\n", "400"),
+        ("garbage\r\n\r\n", "400"),
+        ("GET /%ZZ HTTP/1.1\r\n\r\n", "400"),
+        ("GET /%68ealthz HTTP/1.1\r\n\r\n", "200"),
+        ("GET /healthz HTTP/1.1\r\nHost localhost\r\n\r\n", "400"),
+    ];
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(requests.len()).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        for (request, status) in requests {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            assert_eq!(&resp[9..12], status, "{request:?}");
+        }
+    });
+}
+
+/// A client that trickles bytes must be disconnected after the header timeout.
+#[test]
+fn handler_slowloris() {
+    let (addr, listener) = bind();
+    let handler = Handler::default()
+        .with_read_timeout(Duration::from_millis(500))
+        .with_header_timeout(Duration::from_secs(1));
+
+    scope(|s| {
+        let server = s.spawn(|| {
+            let (stream, _) = listener.accept().unwrap();
+            let start = Instant::now();
+            handler.handle_conn(0, stream);
+            start.elapsed()
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        for byte in b"GET /slowloris HTTP/1.1\r\n" {
+            if stream.write_all(&[*byte]).is_err() {
+                break;
+            }
+            sleep(Duration::from_millis(100));
+        }
+
+        let elapsed = server.join().unwrap();
+        assert!(elapsed < Duration::from_secs(2), "took {elapsed:?}");
+    });
+}
+
+#[test]
+fn handler_rate_limit() {
+    let (addr, listener) = bind();
+    let handler = Handler::default().with_rate_limit(2, 0.001);
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(3).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        let statuses = (0..3)
+            .map(|_| {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+                let mut resp = String::new();
+                let _ = stream.read_to_string(&mut resp).unwrap();
+                resp[9..12].to_string()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, ["404", "404", "429"]);
+    });
+}
+
+#[test]
+fn handler_reload() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+    assert_eq!(handler.config(), Config::default());
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(3).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        let get = || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            resp[9..12].to_string()
+        };
+
+        assert_eq!(get(), "404");
+        // A clone shares the configuration.
+        handler.clone().reload(Config {
+            rate_limit: Some(RateLimit {
+                burst: 1,
+                per_second: 0.001,
+            }),
+            ..Config::default()
+        });
+        assert_eq!(get(), "404");
+        assert_eq!(get(), "429");
+    });
+}
+
+#[test]
+fn handler_metrics() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(2).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\n\r\n").unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            resp
+        };
+
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+        let resp = get("/metrics");
+        assert!(resp.starts_with("HTTP/1.1 200"));
+        assert!(resp.contains("hello_requests_total{code=\"404\"} 1"));
+        assert!(resp.contains("hello_request_duration_seconds_count 1"));
+        // The cache registers its own metrics into the same registry.
+        assert!(resp.contains("hello_cache_entries{cache=\"results\"} 0"));
+    });
+}
+
+#[test]
+fn response_chunked() {
+    let chunks = vec![b"hello".to_vec(), vec![], b", world!".to_vec()];
+    let mut out = Vec::new();
+    Response::chunked(200, "OK", chunks)
+        .write_to(&mut out)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+         5\r\nhello\r\n8\r\n, world!\r\n0\r\n\r\n"
+    );
+}
+
+#[test]
+fn response_reader() {
+    let body = vec![b'a'; 20000];
+
+    let mut out = Vec::new();
+    Response::from_reader(200, "OK", Cursor::new(body.clone()), Some(20000))
+        .write_to(&mut out)
+        .unwrap();
+    let head = "HTTP/1.1 200 OK\r\nContent-Length: 20000\r\n\r\n";
+    assert_eq!(&out[..head.len()], head.as_bytes());
+    assert_eq!(&out[head.len()..], body);
+
+    // Without the length, the body is sent in chunks of bounded size.
+    let mut out = Vec::new();
+    Response::from_reader(200, "OK", Cursor::new(body), None)
+        .write_to(&mut out)
+        .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.starts_with("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2000\r\n"));
+    assert!(out.ends_with("\r\n0\r\n\r\n"));
+
+    // A reader shorter than the given length is an error.
+    let resp = Response::from_reader(200, "OK", Cursor::new(b"short"), Some(10));
+    assert!(resp.write_to(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn handler_head() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(2).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        let request = |method: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "{method} /healthz HTTP/1.1\r\n\r\n").unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            resp
+        };
+
+        let get = request("GET");
+        let head = request("HEAD");
+        let (get_head, get_body) = get.split_once("\r\n\r\n").unwrap();
+        assert_eq!(get_body, "ok\n");
+        assert!(get_head.lines().any(|line| line == "Content-Length: 3"));
+        // Same headers except for the request id, and no body.
+        fn without_id(resp: &str) -> Vec<&str> {
+            resp.lines()
+                .filter(|line| !line.starts_with("X-Request-Id"))
+                .collect()
+        }
+        assert!(head.ends_with("\r\n\r\n"));
+        assert_eq!(
+            without_id(&head),
+            without_id(&format!("{get_head}\r\n\r\n"))
+        );
+    });
+}
+
+#[test]
+fn handler_health() {
+    let (addr, listener) = bind();
+    let pool = ThreadPool::new(1);
+    let handler = Handler::default().with_pool(&pool);
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(2).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        for path in ["/healthz", "/readyz"] {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\n\r\n").unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            assert!(resp.starts_with("HTTP/1.1 200"), "{path}: {resp}");
+        }
+    });
+}
+
+#[test]
+fn handler_keep_alive() {
+    let (addr, listener) = bind();
+    let pool = ThreadPool::new(1);
+    let handler = Handler::default().with_keep_alive(Duration::from_millis(500), &pool);
+
+    scope(|s| {
+        let server = s.spawn(|| {
+            let (stream, _) = listener.accept().unwrap();
+            let start = Instant::now();
+            let reports = handler.handle_conn(0, stream);
+            (reports.len(), start.elapsed())
+        });
+
+        // Two pipelined requests and another one later on the same connection.
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /healthz HTTP/1.1\r\n\r\nGET /healthz HTTP/1.1\r\n\r\n")
+            .unwrap();
+        sleep(Duration::from_millis(100));
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+
+        // The idle connection is closed by the reaper.
+        let mut resp = String::new();
+        let _ = stream.read_to_string(&mut resp).unwrap();
+        assert_eq!(resp.matches("HTTP/1.1 200").count(), 2);
+        assert_eq!(resp.matches("HTTP/1.1 404").count(), 1);
+        assert!(resp.contains("Connection: keep-alive"));
+        for index in 0..3 {
+            assert!(resp.contains(&format!("X-Request-Id: 0-{index}\r\n")));
+        }
+
+        let (requests, elapsed) = server.join().unwrap();
+        assert_eq!(requests, 3);
+        assert!(elapsed < Duration::from_secs(3), "took {elapsed:?}");
+    });
+}
+
+#[test]
+fn handler_websocket() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+
+    /// Sends a masked client frame.
+    fn send(stream: &mut TcpStream, opcode: u8, payload: &[u8]) {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        stream.write_all(&frame).unwrap();
+    }
+
+    /// Receives a short server frame.
+    fn recv(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let mut head = [0; 2];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(head[1] & 0x80, 0, "server frames must not be masked");
+        let mut payload = vec![0; usize::from(head[1])];
+        stream.read_exact(&mut payload).unwrap();
+        (head[0], payload)
+    }
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(2).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        // Not a handshake.
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /ws HTTP/1.1\r\n\r\n").unwrap();
+        let mut resp = String::new();
+        let _ = stream.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 400"));
+
+        // The example handshake of RFC 6455, followed by a frame in the same packet.
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut request = b"GET /ws HTTP/1.1\r\n\
+            Host: localhost\r\n\
+            upgrade: WebSocket\r\n\
+            Connection: Upgrade\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+            Sec-WebSocket-Version: 13\r\n\r\n"
+            .to_vec();
+        request.extend_from_slice(&[0x81, 0x80, 1, 2, 3, 4]);
+        stream.write_all(&request).unwrap();
+
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101"));
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert!(!head.contains("Content-Length"));
+
+        assert_eq!(recv(&mut stream), (0x81, Vec::new()));
+        send(&mut stream, 0x1, b"hello");
+        assert_eq!(recv(&mut stream), (0x81, b"hello".to_vec()));
+        send(&mut stream, 0x2, &[0, 1, 2]);
+        assert_eq!(recv(&mut stream), (0x82, vec![0, 1, 2]));
+        send(&mut stream, 0x9, b"ping");
+        assert_eq!(recv(&mut stream), (0x8A, b"ping".to_vec()));
+        send(&mut stream, 0x8, &1000u16.to_be_bytes());
+        assert_eq!(recv(&mut stream), (0x88, 1000u16.to_be_bytes().to_vec()));
+        assert_eq!(stream.read(&mut [0]).unwrap(), 0);
+    });
+}
+
+#[test]
+fn handler_upstream() {
+    let (upstream_addr, upstream) = bind();
+    let (addr, listener) = bind();
+    let handler = Handler::default().with_upstream(upstream_addr);
+
+    let get = |path: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\n\r\n").unwrap();
+        let mut resp = String::new();
+        let _ = stream.read_to_string(&mut resp).unwrap();
+        resp
+    };
+
+    scope(|s| {
+        let upstream = s.spawn(|| {
+            let mut paths = Vec::new();
+            for stream in upstream.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0];
+                    stream.read_exact(&mut byte).unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                let path = request.split(' ').nth(1).unwrap().to_string();
+                let resp = if path == "/alice" {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+                };
+                stream.write_all(resp.as_bytes()).unwrap();
+                paths.push(path);
+            }
+            paths
+        });
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(4).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        // The second request is answered from the cache.
+        for _ in 0..2 {
+            let resp = get("/alice");
+            assert!(resp.starts_with("HTTP/1.1 200"));
+            assert!(resp.ends_with("\r\n\r\nhello"));
+        }
+        // Failures are not cached.
+        for _ in 0..2 {
+            assert!(get("/bob").starts_with("HTTP/1.1 502"));
+        }
+        assert_eq!(upstream.join().unwrap(), ["/alice", "/bob", "/bob"]);
+    });
+}
+
+#[test]
+fn handler_lookup() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+    let requests = [
+        ("/lookup", "400"),
+        ("/lookup?keys=a,b-c", "400"),
+        ("/lookup?foo&keys=a,b,a", "200"),
+    ];
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(requests.len()).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        for (path, status) in requests {
+            let start = Instant::now();
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\n\r\n").unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            assert_eq!(&resp[9..12], status, "{path}");
+            if status == "200" {
+                assert!(resp.ends_with("\r\n\r\na: a🐕\nb: b🐕\n"));
+                // The keys are computed concurrently.
+                assert!(start.elapsed() < Duration::from_secs(5));
+            }
+        }
+    });
+}
+
+#[test]
+fn handler_traffic() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+    let request = "GET /healthz?verbose HTTP/1.1\r\n\r\n";
+
+    scope(|s| {
+        let server = s.spawn(|| {
+            let (stream, _) = listener.accept().unwrap();
+            handler.handle_conn(0, stream)
+        });
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut resp = String::new();
+        let _ = stream.read_to_string(&mut resp).unwrap();
+
+        let reports = server.join().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].route(), Some("/healthz"));
+        assert_eq!(reports[0].bytes_read(), request.len() as u64);
+        assert_eq!(reports[0].bytes_written(), resp.len() as u64);
+    });
+}
+
+#[test]
+fn handler_routes() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+    let routes = [
+        ("/metrics", "/metrics"),
+        ("/alice", "/{key}"),
+        ("/cache/alice", "/cache/{key}"),
+        ("/cache", "/cache"),
+        ("/cachealice", "/{key}"),
+        ("/cache/", "/*"),
+        ("/alice/bob", "/*"),
+        ("/", "/*"),
+    ];
+
+    scope(|s| {
+        for (path, route) in routes {
+            let server = s.spawn(|| {
+                let (stream, _) = listener.accept().unwrap();
+                handler.handle_conn(0, stream)
+            });
+
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\n\r\n").unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+
+            let reports = server.join().unwrap();
+            assert_eq!(reports[0].route(), Some(route), "{path}");
+        }
+    });
+}
+
+#[test]
+fn handler_purge() {
+    let (addr, listener) = bind();
+    let handler = Handler::default().with_admin_token("secret");
+    let requests = [
+        ("GET /alice HTTP/1.1\r\n\r\n", "200"),
+        ("DELETE /cache/alice HTTP/1.1\r\n\r\n", "401"),
+        (
+            "DELETE /cache/alice HTTP/1.1\r\nAuthorization: Bearer guess\r\n\r\n",
+            "401",
+        ),
+        (
+            "DELETE /cache/alice HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n",
+            "200",
+        ),
+        (
+            "DELETE /cache/alice HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n",
+            "404",
+        ),
+        (
+            "DELETE /cache HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n",
+            "200",
+        ),
+        (
+            "DELETE /alice HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n",
+            "405",
+        ),
+    ];
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(requests.len()).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        for (request, status) in requests {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            assert_eq!(&resp[9..12], status, "{request:?}");
+        }
+    });
+}
+
+#[test]
+fn handler_reload_config() {
+    let (addr, listener) = bind();
+    let path = std::env::temp_dir().join(format!("handler_config_{}", std::process::id()));
+    fs::write(&path, "admin_token = secret\n").unwrap();
+    let store = Arc::new(ConfigStore::open(&path).unwrap());
+    let handler = Handler::default().with_config_store(store.clone());
+    let post = |token| format!("POST /config HTTP/1.1\r\nAuthorization: Bearer {token}\r\n\r\n");
+    let requests = [
+        ("GET /config HTTP/1.1\r\n\r\n".to_string(), "405"),
+        ("POST /config HTTP/1.1\r\n\r\n".to_string(), "401"),
+        (post("secret"), "200"),
+        // The reloaded file changed the token.
+        (post("secret"), "401"),
+        (post("other"), "422"),
+    ];
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(requests.len()).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        for (i, (request, status)) in requests.iter().enumerate() {
+            if i == 2 {
+                fs::write(&path, "admin_token = other\n").unwrap();
+            } else if i == 4 {
+                fs::write(&path, "admin_token =\n").unwrap();
+            }
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            assert_eq!(&resp[9..12], *status, "{request:?}");
+        }
+    });
+    fs::remove_file(&path).unwrap();
+    // The invalid file kept the reloaded tunables.
+    assert_eq!(store.get().admin_token.as_deref(), Some("other"));
+}
+
+#[test]
+fn handler_auth() {
+    let (addr, listener) = bind();
+    let auth = Auth::new("admin")
+        .with_basic("alice", "pw")
+        .with_bearer("token");
+    let handler = Handler::default().with_auth(auth);
+    let requests = [
+        ("GET /healthz HTTP/1.1\r\n\r\n", "200"),
+        ("GET /metrics HTTP/1.1\r\n\r\n", "401"),
+        (
+            "GET /metrics HTTP/1.1\r\nAuthorization: Basic YWxpY2U6cHc=\r\n\r\n",
+            "200",
+        ),
+        (
+            "GET /metrics HTTP/1.1\r\nAuthorization: Basic YWxpY2U6d3Jvbmc=\r\n\r\n",
+            "401",
+        ),
+        (
+            "GET /metrics HTTP/1.1\r\nAuthorization: Bearer guess\r\n\r\n",
+            "401",
+        ),
+        (
+            "GET /cache HTTP/1.1\r\nAuthorization: Bearer token\r\n\r\n",
+            "200",
+        ),
+        (
+            "DELETE /cache/alice HTTP/1.1\r\nAuthorization: bearer token\r\n\r\n",
+            "404",
+        ),
+    ];
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(requests.len()).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        for (request, status) in requests {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            assert_eq!(&resp[9..12], status, "{request:?}");
+            if status == "401" {
+                assert!(resp.contains(
+                    "\r\nWWW-Authenticate: Basic realm=\"admin\", charset=\"UTF-8\"\r\n"
+                ));
+                assert!(resp.contains("\r\nWWW-Authenticate: Bearer realm=\"admin\"\r\n"));
+            }
+        }
+    });
+}
+
+/// With both, the credentials of `Auth` authorize the admin requests without the admin token.
+#[test]
+fn handler_auth_admin_token() {
+    let (addr, listener) = bind();
+    let auth = Auth::new("admin").with_basic("alice", "pw");
+    let handler = Handler::default()
+        .with_auth(auth)
+        .with_admin_token("secret");
+    let requests = [
+        ("GET /alice HTTP/1.1\r\n\r\n", "200"),
+        (
+            "DELETE /cache/alice HTTP/1.1\r\nAuthorization: Basic YWxpY2U6cHc=\r\n\r\n",
+            "200",
+        ),
+        (
+            "DELETE /cache/alice HTTP/1.1\r\nAuthorization: Basic YWxpY2U6d3Jvbmc=\r\n\r\n",
+            "401",
+        ),
+        (
+            "DELETE /cache HTTP/1.1\r\nAuthorization: bearer secret\r\n\r\n",
+            "401",
+        ),
+        (
+            "POST /config HTTP/1.1\r\nAuthorization: Basic YWxpY2U6cHc=\r\n\r\n",
+            "409",
+        ),
+    ];
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(requests.len()).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        for (request, status) in requests {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            assert_eq!(&resp[9..12], status, "{request:?}");
+        }
+    });
+}
+
+#[test]
+fn handler_body_limit() {
+    let (addr, listener) = bind();
+    let handler = Handler::default().with_config(Config {
+        max_body_len: 5,
+        ..Config::default()
+    });
+    let requests = [
+        (
+            "GET /healthz HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello",
+            "200",
+        ),
+        (
+            "POST /healthz HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc",
+            "405",
+        ),
+        // Rejected without waiting for the body.
+        (
+            "POST /healthz HTTP/1.1\r\nContent-Length: 1000000\r\n\r\n",
+            "413",
+        ),
+        (
+            "POST /healthz HTTP/1.1\r\nContent-Length: 3x\r\n\r\nabc",
+            "400",
+        ),
+        (
+            "POST /healthz HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            "400",
+        ),
+    ];
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(requests.len()).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        for (request, status) in requests {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            assert_eq!(&resp[9..12], status, "{request:?}");
+            if status == "413" {
+                assert!(resp.contains("\r\nConnection: close\r\n"));
+            }
+        }
+    });
+}
+
+#[test]
+fn handler_etag() {
+    let (addr, listener) = bind();
+    let handler = Handler::default();
+
+    scope(|s| {
+        s.spawn(|| {
+            for (id, stream) in listener.incoming().take(5).enumerate() {
+                handler.handle_conn(id, stream.unwrap());
+            }
+        });
+
+        let get = |if_none_match: Option<&str>| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut request = "GET /alice HTTP/1.1\r\n".to_string();
+            if let Some(if_none_match) = if_none_match {
+                request.push_str(&format!("If-None-Match: {if_none_match}\r\n"));
+            }
+            request.push_str("\r\n");
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut resp = String::new();
+            let _ = stream.read_to_string(&mut resp).unwrap();
+            resp
+        };
+        let etag_of = |resp: &str| {
+            resp.lines()
+                .find_map(|line| line.strip_prefix("ETag: "))
+                .unwrap()
+                .to_string()
+        };
+
+        let resp = get(None);
+        assert!(resp.starts_with("HTTP/1.1 200"));
+        let etag = etag_of(&resp);
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+
+        for if_none_match in [
+            etag.clone(),
+            format!("\"other\", W/{etag}"),
+            "*".to_string(),
+        ] {
+            let resp = get(Some(&if_none_match));
+            assert!(resp.starts_with("HTTP/1.1 304"), "{if_none_match}");
+            assert_eq!(etag_of(&resp), etag);
+            assert!(!resp.contains("Content-Length"));
+            assert!(resp.ends_with("\r\n\r\n"));
+        }
+
+        let resp = get(Some("\"other\""));
+        assert!(resp.starts_with("HTTP/1.1 200"));
+        assert!(resp.contains("alice🐕"));
+    });
+}
@@ -1,8 +1,10 @@
 use std::thread::sleep;
 use std::time::Duration;
 
-#[cfg(not(feature = "check-loom"))]
+#[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
 use core::sync::atomic::{AtomicPtr, Ordering::*};
+#[cfg(feature = "check-sched")]
+use cs431_homework::sched::sync::atomic::{AtomicPtr, Ordering::*};
 #[cfg(feature = "check-loom")]
 use loom::sync::atomic::{AtomicPtr, Ordering::*};
 
@@ -161,6 +163,41 @@ fn stack_queue() {
     assert!(stack.try_pop().is_none());
 }
 
+/// A shield is created by the destructor of a thread-local that runs after the registration of
+/// the thread is destroyed.
+#[cfg(not(feature = "check-loom"))]
+#[test]
+fn shield_in_thread_local_destructor() {
+    use core::sync::atomic::AtomicBool;
+    use cs431_homework::registry;
+
+    static PROTECTED: AtomicBool = AtomicBool::new(false);
+
+    struct Protector;
+
+    impl Drop for Protector {
+        fn drop(&mut self) {
+            let mut value = 0;
+            let shield = Shield::default();
+            shield.set(&mut value);
+            PROTECTED.store(registry::try_current_id().is_none(), Relaxed);
+        }
+    }
+
+    thread_local! {
+        static PROTECTOR: Protector = Protector;
+    }
+
+    std::thread::spawn(|| {
+        // Initialized before the registration of the thread, so destroyed after it.
+        PROTECTOR.with(|_| ());
+        drop(Shield::<usize>::default());
+    })
+    .join()
+    .unwrap();
+    assert!(PROTECTED.load(Relaxed));
+}
+
 mod mock;
 
 mod sync {
@@ -264,8 +301,10 @@ mod stack {
     use core::mem::ManuallyDrop;
     use core::ptr;
 
-    #[cfg(not(feature = "check-loom"))]
+    #[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
     use core::sync::atomic::{AtomicPtr, Ordering::*};
+    #[cfg(feature = "check-sched")]
+    use cs431_homework::sched::sync::atomic::{AtomicPtr, Ordering::*};
     #[cfg(feature = "check-loom")]
     use loom::sync::atomic::{AtomicPtr, Ordering::*};
 
@@ -354,8 +393,10 @@ mod queue {
     use core::mem::MaybeUninit;
     use core::ptr;
 
-    #[cfg(not(feature = "check-loom"))]
+    #[cfg(not(any(feature = "check-loom", feature = "check-sched")))]
     use core::sync::atomic::{AtomicPtr, Ordering::*};
+    #[cfg(feature = "check-sched")]
+    use cs431_homework::sched::sync::atomic::{AtomicPtr, Ordering::*};
     #[cfg(feature = "check-loom")]
     use loom::sync::atomic::{AtomicPtr, Ordering::*};
 
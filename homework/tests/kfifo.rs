@@ -0,0 +1,143 @@
+mod canary;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::threads::scope;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::KFifo;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn push_pop() {
+        let queue = KFifo::new(1);
+        assert_eq!(queue.k(), 1);
+        assert_eq!(queue.pop(), None);
+        for i in 0..10 {
+            queue.push(i);
+        }
+        // Without relaxation, the queue is FIFO.
+        for i in 0..10 {
+            assert_eq!(queue.pop(), Some(i));
+        }
+        assert_eq!(queue.pop(), None);
+    }
+
+    /// A value is popped within `k` of its position in the order of the pushes.
+    #[test]
+    fn relaxation() {
+        const K: usize = 4;
+
+        let queue = KFifo::new(K);
+        for i in 0..10 * K + 1 {
+            queue.push(i);
+        }
+        let mut popped = Vec::new();
+        while let Some(i) = queue.pop() {
+            assert_eq!(i / K, popped.len() / K);
+            popped.push(i);
+        }
+        popped.sort_unstable();
+        assert_eq!(popped, (0..10 * K + 1).collect::<Vec<_>>());
+    }
+
+    /// Every pushed value is popped exactly once.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+
+        let queue = KFifo::new(8);
+        let popped = scope(|s| {
+            for t in 0..THREADS {
+                let queue = &queue;
+                let _ = s.spawn(move || {
+                    for i in 0..ITER {
+                        queue.push(t * ITER + i);
+                        collect();
+                    }
+                });
+            }
+            s.run(THREADS, |_| {
+                let mut popped = Vec::new();
+                while popped.len() < ITER {
+                    if let Some(value) = queue.pop() {
+                        popped.push(value);
+                        collect();
+                    }
+                }
+                popped
+            })
+        });
+
+        assert_eq!(queue.pop(), None);
+        let popped = popped.into_iter().flatten().collect::<HashSet<_>>();
+        assert_eq!(popped, (0..THREADS * ITER).collect());
+    }
+
+    /// Values left in the queue are dropped with it, and popped values are not dropped again.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let queue = KFifo::new(3);
+        for _ in 0..10 {
+            queue.push(Canary(&dropped));
+        }
+        drop(queue.pop());
+        assert_eq!(dropped.load(Relaxed), 1);
+        drop(queue);
+        assert_eq!(dropped.load(Relaxed), 10);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::KFifo;
+
+    /// value pushed → value popped → value seen, across a segment boundary.
+    #[test]
+    fn push_pop_sync() {
+        model(|| {
+            let queue = Arc::new(KFifo::new(1));
+            queue.push(Box::new(1));
+            let th = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.push(Box::new(2)))
+            };
+            assert_eq!(queue.pop().map(|v| *v), Some(1));
+            th.join().unwrap();
+            assert_eq!(queue.pop().map(|v| *v), Some(2));
+        })
+    }
+
+    /// The segment unlinked by a popping thread is not freed while another thread pops from it,
+    /// and each value is popped once.
+    #[test]
+    fn pop_pop_sync() {
+        model(|| {
+            let queue = Arc::new(KFifo::new(1));
+            queue.push(0);
+            queue.push(1);
+            let th = {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let value = queue.pop();
+                    collect();
+                    value
+                })
+            };
+            let mine = queue.pop();
+            collect();
+            let theirs = th.join().unwrap();
+            let mut popped = [mine, theirs];
+            popped.sort_unstable();
+            assert_eq!(popped, [Some(0), Some(1)]);
+        })
+    }
+}
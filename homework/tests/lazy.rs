@@ -0,0 +1,175 @@
+mod canary;
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use cs431_homework::{Lazy, OnceCell};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread::{scope, sleep};
+    use std::time::Duration;
+
+    #[test]
+    fn get_set() {
+        let cell = OnceCell::new();
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.set(1), Ok(()));
+        assert_eq!(cell.set(2), Err(2));
+        assert_eq!(cell.get(), Some(&1));
+        assert_eq!(cell.get_or_init(|| unreachable!()), &1);
+        assert_eq!(cell.into_inner(), Some(1));
+        assert_eq!(OnceCell::<usize>::new().into_inner(), None);
+    }
+
+    /// A failed or panicking initializer leaves the cell empty for the next one.
+    #[test]
+    fn init_fails() {
+        let cell = OnceCell::new();
+        assert_eq!(cell.get_or_try_init(|| Err(())), Err(()));
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!()))).is_err());
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.get_or_try_init(|| Ok::<_, ()>(3)), Ok(&3));
+    }
+
+    /// Concurrent initializers run once, and the others wait for the winner.
+    #[test]
+    fn init_once() {
+        const THREADS: usize = 8;
+
+        for _ in 0..64 {
+            let cell = OnceCell::new();
+            let inits = AtomicUsize::new(0);
+            scope(|s| {
+                for t in 0..THREADS {
+                    let (cell, inits) = (&cell, &inits);
+                    let _ = s.spawn(move || {
+                        let value = cell.get_or_init(|| {
+                            let _ = inits.fetch_add(1, Ordering::Relaxed);
+                            sleep(Duration::from_millis(1));
+                            t
+                        });
+                        assert_eq!(cell.get(), Some(value));
+                    });
+                }
+            });
+            assert_eq!(inits.load(Ordering::Relaxed), 1);
+        }
+    }
+
+    /// If the initializer fails, one of the waiting threads initializes the cell instead.
+    #[test]
+    fn init_retry() {
+        const THREADS: usize = 8;
+
+        let cell = OnceCell::new();
+        let inits = AtomicUsize::new(0);
+        let failed = AtomicUsize::new(0);
+        scope(|s| {
+            for t in 0..THREADS {
+                let (cell, inits, failed) = (&cell, &inits, &failed);
+                let _ = s.spawn(move || {
+                    let result = cell.get_or_try_init(|| {
+                        sleep(Duration::from_millis(10));
+                        // The first initializer fails.
+                        match inits.fetch_add(1, Ordering::Relaxed) {
+                            0 => Err(()),
+                            _ => Ok(t),
+                        }
+                    });
+                    if result.is_err() {
+                        let _ = failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        assert!(cell.get().is_some());
+        assert_eq!(inits.load(Ordering::Relaxed), 2);
+        assert_eq!(failed.load(Ordering::Relaxed), 1);
+    }
+
+    /// The value is dropped exactly once, whether the cell is dropped or consumed.
+    #[test]
+    fn drop_value() {
+        let dropped = AtomicUsize::new(0);
+        let cell = OnceCell::new();
+        assert!(cell.set(Canary(&dropped)).is_ok());
+        drop(cell.set(Canary(&dropped)));
+        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+        drop(cell);
+        assert_eq!(dropped.load(Ordering::Relaxed), 2);
+
+        let cell = OnceCell::new();
+        assert!(cell.set(Canary(&dropped)).is_ok());
+        drop(cell.into_inner());
+        assert_eq!(dropped.load(Ordering::Relaxed), 3);
+    }
+
+    static INITS: AtomicUsize = AtomicUsize::new(0);
+    static LAZY: Lazy<Vec<usize>> = Lazy::new(|| {
+        let _ = INITS.fetch_add(1, Ordering::Relaxed);
+        (0..16).collect()
+    });
+
+    #[test]
+    fn lazy_static() {
+        scope(|s| {
+            for _ in 0..8 {
+                let _ = s.spawn(|| assert_eq!(LAZY.len(), 16));
+            }
+        });
+        assert_eq!(LAZY[15], 15);
+        assert_eq!(INITS.load(Ordering::Relaxed), 1);
+    }
+
+    /// A panicking initializer poisons the `Lazy`.
+    #[test]
+    fn lazy_poisoned() {
+        let lazy = Lazy::<usize, _>::new(|| panic!());
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| *lazy)).is_err());
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| *lazy)).is_err());
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::OnceCell;
+
+    /// Of two initializations, one runs and the other waits for it.
+    #[test]
+    fn get_or_init_sync() {
+        model(|| {
+            let cell = Arc::new(OnceCell::new());
+            let inits = Arc::new(AtomicUsize::new(0));
+            let init = |inits: &AtomicUsize| {
+                let _ = inits.fetch_add(1, Relaxed);
+                1
+            };
+            let th = {
+                let cell = cell.clone();
+                let inits = inits.clone();
+                thread::spawn(move || *cell.get_or_init(|| init(&inits)))
+            };
+            assert_eq!(*cell.get_or_init(|| init(&inits)), 1);
+            assert_eq!(th.join().unwrap(), 1);
+            assert_eq!(inits.load(Relaxed), 1);
+        })
+    }
+
+    /// A failed initialization leaves the cell empty for the one waiting for it.
+    #[test]
+    fn get_or_try_init_sync() {
+        model(|| {
+            let cell = Arc::new(OnceCell::new());
+            let th = {
+                let cell = cell.clone();
+                thread::spawn(move || cell.get_or_try_init(|| Err(())).copied())
+            };
+            assert_eq!(cell.get_or_init(|| 1), &1);
+            assert!(matches!(th.join().unwrap(), Ok(1) | Err(())));
+        })
+    }
+}
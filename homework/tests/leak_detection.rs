@@ -0,0 +1,81 @@
+// The reports are only made with the `leak-detection` feature, and the pointers of loom are not
+// freed in the same way.
+#![cfg(all(feature = "leak-detection", not(feature = "check-loom")))]
+
+use cs431_homework::hazard_pointer::{collect, leaks, retire, HazardBag, RetiredSet, Shield};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// A pointer pinned by a shield is reported with where it is retired and where the shield is
+/// created, until the shield is dropped.
+#[test]
+fn pinned() {
+    let hazards = HazardBag::new();
+    let mut retired = RetiredSet::new(&hazards);
+    let atomic = AtomicPtr::new(Box::into_raw(Box::new(1)));
+
+    let (shield, created) = (Shield::new(&hazards), line!());
+    let pointer = shield.protect(&atomic);
+    atomic.store(ptr::null_mut(), Ordering::Relaxed);
+    let retired_at = line!() + 1;
+    unsafe { retired.retire(pointer) };
+    retired.collect();
+
+    let leaks = hazards.leaks();
+    assert_eq!(leaks.len(), 1);
+    assert_eq!(leaks[0].pointer, pointer as usize);
+    assert_eq!(leaks[0].retired_at.file(), file!());
+    assert_eq!(leaks[0].retired_at.line(), retired_at);
+    assert_eq!(leaks[0].pinned_by.len(), 1);
+    assert_eq!(leaks[0].pinned_by[0].line(), created);
+    assert!(leaks[0]
+        .to_string()
+        .contains("pinned by the shields created at"));
+
+    drop(shield);
+    assert!(hazards.leaks()[0].pinned_by.is_empty());
+    retired.collect();
+    assert!(hazards.leaks().is_empty());
+}
+
+/// The retire sites go through the thread-local retired set of `retire`.
+#[test]
+fn global() {
+    let pointer = Box::into_raw(Box::new(1));
+    let (shield, created) = (Shield::default(), line!());
+    shield.set(pointer);
+    let retired_at = line!() + 1;
+    unsafe { retire(pointer) };
+    collect();
+
+    // The other tests may have their own pointers retired meanwhile.
+    let leak = leaks()
+        .into_iter()
+        .find(|leak| leak.pointer == pointer as usize)
+        .unwrap();
+    assert_eq!(leak.retired_at.line(), retired_at);
+    assert_eq!(leak.pinned_by.len(), 1);
+    assert_eq!(leak.pinned_by[0].line(), created);
+
+    drop(shield);
+    collect();
+    assert!(leaks().iter().all(|leak| leak.pointer != pointer as usize));
+}
+
+/// The pointers left in a leaked retired set are reported when their bag is dropped.
+#[test]
+fn dropped_bag() {
+    // Boxed, as the bag is told by its address, and moves into the closure.
+    let hazards = Box::new(HazardBag::new());
+    let mut retired = RetiredSet::new(&hazards);
+    unsafe { retired.retire(Box::into_raw(Box::new(1))) };
+    mem::forget(retired);
+
+    let payload = panic::catch_unwind(AssertUnwindSafe(move || drop(hazards))).unwrap_err();
+    let message = payload.downcast::<String>().unwrap();
+    assert!(message.contains("never freed"), "{message}");
+    assert!(message.contains(file!()), "{message}");
+    assert!(message.contains("not pinned"), "{message}");
+}
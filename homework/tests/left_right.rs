@@ -0,0 +1,153 @@
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::threads::scope;
+    use crossbeam_channel::unbounded;
+    use cs431_homework::hello_server::{Report, Reporter, RequestId, Statistics};
+    use cs431_homework::LeftRight;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn smoke() {
+        let numbers = LeftRight::new(vec![1, 2]);
+        assert_eq!(*numbers.read(), [1, 2]);
+        numbers.write(|numbers| numbers.push(3));
+        assert_eq!(numbers.write(|numbers| numbers.len()), 3);
+        {
+            let guard = numbers.read();
+            let other = numbers.read();
+            assert_eq!(*guard, *other);
+        }
+        numbers.write(|numbers| numbers.retain(|number| number % 2 == 1));
+        assert_eq!(*numbers.read(), [1, 3]);
+        assert_eq!(format!("{numbers:?}"), "LeftRight([1, 3])");
+    }
+
+    /// The readers see each write whole, and the writes in order.
+    #[test]
+    fn stress() {
+        const READERS: usize = 3;
+        const ITER: usize = 1024 * 2;
+
+        let numbers = LeftRight::new(Vec::new());
+        let done = AtomicBool::new(false);
+        scope(|s| {
+            let _ = s.spawn(|| {
+                for i in 0..ITER {
+                    numbers.write(|numbers| {
+                        numbers.push(i);
+                        if numbers.len() > 16 {
+                            let _ = numbers.remove(0);
+                        }
+                    });
+                }
+                done.store(true, Ordering::Relaxed);
+            });
+            s.run(READERS, |_| {
+                let mut last = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let numbers = numbers.read();
+                    assert!(numbers.windows(2).all(|pair| pair[0] + 1 == pair[1]));
+                    let newest = numbers.last().map_or(0, |newest| newest + 1);
+                    assert!(newest >= last);
+                    last = newest;
+                }
+            });
+        });
+        assert_eq!(numbers.read().last(), Some(&(ITER - 1)));
+    }
+
+    /// The view of the statistics is consistent while the reports are coming in, and matches the
+    /// statistics in the end.
+    #[test]
+    fn statistics_view() {
+        const REPORTS: usize = 1024;
+
+        let view = Arc::new(LeftRight::<Statistics>::default());
+        let (report_sender, report_receiver) = unbounded();
+        let stats = scope(|s| {
+            let reporter = s.spawn(|| {
+                Reporter::new()
+                    .with_statistics_view(view.clone())
+                    .run(report_receiver)
+            });
+            let mut last = 0;
+            for id in 0..REPORTS {
+                let report = Report::new(RequestId::new(id, 0), None)
+                    .with_route("/{key}")
+                    .with_bytes(1, 10);
+                report_sender.send(report).unwrap();
+
+                let stats = view.read();
+                let traffic = stats.traffic();
+                assert_eq!(stats.route_traffic("/{key}"), traffic);
+                assert_eq!(traffic.bytes_read as usize, traffic.requests);
+                assert_eq!(stats.latencies().count() as usize, traffic.requests);
+                assert!(traffic.requests >= last);
+                last = traffic.requests;
+            }
+            drop(report_sender);
+            reporter.join()
+        });
+
+        let view = view.read();
+        assert_eq!(view.traffic(), stats.traffic());
+        assert_eq!(view.traffic().requests, REPORTS);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::atomic::{AtomicBool, Ordering};
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::LeftRight;
+
+    /// Instance that tells whether a write is in progress on it.
+    #[derive(Debug, Default)]
+    struct Instance {
+        writing: AtomicBool,
+        value: usize,
+    }
+
+    impl Clone for Instance {
+        fn clone(&self) -> Self {
+            Self {
+                writing: AtomicBool::new(false),
+                value: self.value,
+            }
+        }
+    }
+
+    /// A reader never reads the instance the writer is writing, and sees the writes in order.
+    #[test]
+    fn read_write_sync() {
+        model(|| {
+            let left_right = Arc::new(LeftRight::<Instance>::default());
+            let th = {
+                let left_right = left_right.clone();
+                thread::spawn(move || {
+                    let mut last = 0;
+                    for _ in 0..2 {
+                        let instance = left_right.read();
+                        assert!(!instance.writing.load(Ordering::SeqCst));
+                        assert!(instance.value >= last);
+                        last = instance.value;
+                    }
+                })
+            };
+            for _ in 0..2 {
+                left_right.write(|instance| {
+                    instance.writing.store(true, Ordering::SeqCst);
+                    instance.value += 1;
+                    instance.writing.store(false, Ordering::SeqCst);
+                });
+            }
+            th.join().unwrap();
+            assert_eq!(left_right.read().value, 2);
+        })
+    }
+}
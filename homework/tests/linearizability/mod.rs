@@ -0,0 +1,344 @@
+//! Linearizability checker for the histories of concurrent objects.
+//!
+//! The threads record each operation on the object with the times it is called and returns, and
+//! the checker searches for a linearization: an order of the operations that respects their real
+//! time order and in which the sequential specification returns what the object returned. The
+//! search is the one of Wing and Gong, with the memoization of Lowe, "Testing for
+//! Linearizability", 2017: a pair of the set of linearized operations and the state of the
+//! specification is explored only once.
+//!
+//! Operations on different keys of a set or map are independent, so the history of each key is
+//! checked on its own (P-compositionality, Horn and Kroening, 2015), which keeps the search small.
+
+// Each test crate uses a part of it.
+#![allow(dead_code)]
+
+use core::fmt;
+use core::hash::Hash;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Sequential specification of an object.
+pub trait Spec: Clone + Eq + Hash {
+    /// Operation, with its arguments.
+    type Op: Clone + fmt::Debug;
+    /// Return value of an operation.
+    type Ret: Clone + PartialEq + fmt::Debug;
+
+    /// Applies `op` to the object, returning what it should return.
+    fn apply(&mut self, op: &Self::Op) -> Self::Ret;
+}
+
+/// A completed operation.
+#[derive(Debug, Clone)]
+pub struct Operation<O, R> {
+    pub op: O,
+    pub ret: R,
+    /// Time of the call.
+    pub call: usize,
+    /// Time of the return, after `call`.
+    pub end: usize,
+}
+
+/// Clock that orders the calls and returns of the operations of all threads.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    clock: AtomicUsize,
+}
+
+impl Recorder {
+    /// Calls `f`, which performs `op` on the object, and pushes the operation to `history`.
+    pub fn record<O, R, F>(&self, history: &mut Vec<Operation<O, R>>, op: O, f: F)
+    where
+        F: FnOnce(&O) -> R,
+    {
+        // SeqCst: the times are ordered as the operations are in real time.
+        let call = self.clock.fetch_add(1, Ordering::SeqCst);
+        let ret = f(&op);
+        let end = self.clock.fetch_add(1, Ordering::SeqCst);
+        history.push(Operation { op, ret, call, end });
+    }
+}
+
+/// Event in the history: the call or the return of an operation.
+#[derive(Debug, Clone, Copy)]
+struct Event {
+    op: usize,
+    is_call: bool,
+    /// Index of the other event of the operation.
+    other: usize,
+    prev: usize,
+    next: usize,
+}
+
+/// Returns a linearization of `history` starting from `init`, or `None` if there is none.
+pub fn linearize<S: Spec>(init: &S, history: &[Operation<S::Op, S::Ret>]) -> Option<Vec<usize>> {
+    // The events in time order, linked in a list after the sentinel at 0.
+    let mut times = Vec::with_capacity(history.len() * 2);
+    for (index, operation) in history.iter().enumerate() {
+        assert!(operation.call < operation.end);
+        times.push((operation.call, index, true));
+        times.push((operation.end, index, false));
+    }
+    times.sort_unstable();
+    let mut call_of = vec![0; history.len()];
+    let mut events = vec![Event {
+        op: usize::MAX,
+        is_call: false,
+        other: 0,
+        prev: 0,
+        next: 1,
+    }];
+    for (index, &(_, op, is_call)) in times.iter().enumerate() {
+        let index = index + 1;
+        let other = if is_call {
+            call_of[op] = index;
+            0
+        } else {
+            events[call_of[op]].other = index;
+            call_of[op]
+        };
+        events.push(Event {
+            op,
+            is_call,
+            other,
+            prev: index - 1,
+            next: index + 1,
+        });
+    }
+    let end = events.len();
+
+    // Unlinks the events of an operation, which are relinked in the reverse order.
+    fn lift(events: &mut [Event], call: usize) {
+        for index in [call, events[call].other] {
+            let Event { prev, next, .. } = events[index];
+            events[prev].next = next;
+            if let Some(event) = events.get_mut(next) {
+                event.prev = prev;
+            }
+        }
+    }
+    fn unlift(events: &mut [Event], call: usize) {
+        for index in [events[call].other, call] {
+            let Event { prev, next, .. } = events[index];
+            events[prev].next = index;
+            if let Some(event) = events.get_mut(next) {
+                event.prev = index;
+            }
+        }
+    }
+
+    let mut linearized = vec![0u64; history.len() / 64 + 1];
+    let mut explored = HashSet::new();
+    let mut state = init.clone();
+    // The calls linearized so far, with the states before them.
+    let mut stack: Vec<(usize, S)> = Vec::new();
+    let mut current = events[0].next;
+    while events[0].next != end {
+        let event = events[current];
+        if event.is_call {
+            let operation = &history[event.op];
+            let mut next = state.clone();
+            if next.apply(&operation.op) == operation.ret {
+                linearized[event.op / 64] |= 1 << (event.op % 64);
+                if explored.insert((linearized.clone(), next.clone())) {
+                    stack.push((current, state));
+                    state = next;
+                    lift(&mut events, current);
+                    current = events[0].next;
+                    continue;
+                }
+                linearized[event.op / 64] &= !(1 << (event.op % 64));
+            }
+            current = event.next;
+        } else {
+            // An operation returned before any pending one could be linearized, so the last
+            // linearized operation is taken back.
+            let (call, prev) = stack.pop()?;
+            let op = events[call].op;
+            linearized[op / 64] &= !(1 << (op % 64));
+            state = prev;
+            unlift(&mut events, call);
+            current = events[call].next;
+        }
+    }
+    Some(stack.into_iter().map(|(call, _)| events[call].op).collect())
+}
+
+/// Panics if `history` is not linearizable from `init`.
+pub fn assert_linearizable<S: Spec>(init: &S, history: &[Operation<S::Op, S::Ret>]) {
+    if linearize(init, history).is_none() {
+        let mut history = history.to_vec();
+        history.sort_by_key(|operation| operation.call);
+        panic!("history is not linearizable: {:#?}", history);
+    }
+}
+
+/// Panics if the history of any partition given by `key` is not linearizable from `init`.
+pub fn assert_linearizable_by<S, P, F>(init: &S, history: &[Operation<S::Op, S::Ret>], key: F)
+where
+    S: Spec,
+    P: Hash + Eq,
+    F: Fn(&S::Op) -> P,
+{
+    let mut partitions = HashMap::<_, Vec<_>>::new();
+    for operation in history {
+        partitions
+            .entry(key(&operation.op))
+            .or_default()
+            .push(operation.clone());
+    }
+    for partition in partitions.values() {
+        assert_linearizable(init, partition);
+    }
+}
+
+/// Operation on a FIFO queue.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum QueueOp<T> {
+    Push(T),
+    Pop,
+}
+
+/// FIFO queue, whose `Pop` returns the front value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct QueueSpec<T>(pub VecDeque<T>);
+
+impl<T: Clone + Eq + Hash + fmt::Debug> Spec for QueueSpec<T> {
+    type Op = QueueOp<T>;
+    type Ret = Option<T>;
+
+    fn apply(&mut self, op: &QueueOp<T>) -> Option<T> {
+        match op {
+            QueueOp::Push(t) => {
+                self.0.push_back(t.clone());
+                None
+            }
+            QueueOp::Pop => self.0.pop_front(),
+        }
+    }
+}
+
+/// Operation on a double-ended queue.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DequeOp<T> {
+    PushFront(T),
+    PushBack(T),
+    PopFront,
+    PopBack,
+}
+
+/// Double-ended queue, whose pops return the value at their ends.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DequeSpec<T>(pub VecDeque<T>);
+
+impl<T: Clone + Eq + Hash + fmt::Debug> Spec for DequeSpec<T> {
+    type Op = DequeOp<T>;
+    type Ret = Option<T>;
+
+    fn apply(&mut self, op: &DequeOp<T>) -> Option<T> {
+        match op {
+            DequeOp::PushFront(t) => {
+                self.0.push_front(t.clone());
+                None
+            }
+            DequeOp::PushBack(t) => {
+                self.0.push_back(t.clone());
+                None
+            }
+            DequeOp::PopFront => self.0.pop_front(),
+            DequeOp::PopBack => self.0.pop_back(),
+        }
+    }
+}
+
+/// Operation on a LIFO stack.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum StackOp<T> {
+    Push(T),
+    Pop,
+}
+
+/// LIFO stack, whose `Pop` returns the top value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct StackSpec<T>(pub Vec<T>);
+
+impl<T: Clone + Eq + Hash + fmt::Debug> Spec for StackSpec<T> {
+    type Op = StackOp<T>;
+    type Ret = Option<T>;
+
+    fn apply(&mut self, op: &StackOp<T>) -> Option<T> {
+        match op {
+            StackOp::Push(t) => {
+                self.0.push(t.clone());
+                None
+            }
+            StackOp::Pop => self.0.pop(),
+        }
+    }
+}
+
+/// Operation on a map.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapOp<K, V> {
+    /// Inserts the entry if there is none for the key.
+    Insert(K, V),
+    /// Inserts the entry, replacing the one for the key.
+    Upsert(K, V),
+    Remove(K),
+    Lookup(K),
+}
+
+impl<K, V> MapOp<K, V> {
+    /// Returns the key, by which the history of a map is partitioned.
+    pub fn key(&self) -> &K {
+        match self {
+            MapOp::Insert(key, _)
+            | MapOp::Upsert(key, _)
+            | MapOp::Remove(key)
+            | MapOp::Lookup(key) => key,
+        }
+    }
+}
+
+/// Return value of an operation on a map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapRet<V> {
+    /// Whether `Insert` inserted, or whether `Remove` removed.
+    Done(bool),
+    /// Returned by `Upsert`.
+    Unit,
+    /// Returned by `Lookup`.
+    Value(Option<V>),
+}
+
+/// Map, or a set whose values are `()`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct MapSpec<K, V>(pub BTreeMap<K, V>);
+
+impl<K, V> Spec for MapSpec<K, V>
+where
+    K: Clone + Ord + Hash + fmt::Debug,
+    V: Clone + Eq + Hash + fmt::Debug,
+{
+    type Op = MapOp<K, V>;
+    type Ret = MapRet<V>;
+
+    fn apply(&mut self, op: &MapOp<K, V>) -> MapRet<V> {
+        match op {
+            MapOp::Insert(key, value) => {
+                let absent = !self.0.contains_key(key);
+                if absent {
+                    let _ = self.0.insert(key.clone(), value.clone());
+                }
+                MapRet::Done(absent)
+            }
+            MapOp::Upsert(key, value) => {
+                let _ = self.0.insert(key.clone(), value.clone());
+                MapRet::Unit
+            }
+            MapOp::Remove(key) => MapRet::Done(self.0.remove(key).is_some()),
+            MapOp::Lookup(key) => MapRet::Value(self.0.get(key).cloned()),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Tests of the linearizability checker on histories written by hand.
+
+mod linearizability;
+
+use linearizability::*;
+
+fn operation<O, R>(op: O, ret: R, call: usize, end: usize) -> Operation<O, R> {
+    Operation { op, ret, call, end }
+}
+
+#[test]
+fn sequential() {
+    let history = [
+        operation(QueueOp::Push(1), None, 0, 1),
+        operation(QueueOp::Push(2), None, 2, 3),
+        operation(QueueOp::Pop, Some(1), 4, 5),
+        operation(QueueOp::Pop, Some(2), 6, 7),
+        operation(QueueOp::Pop, None, 8, 9),
+    ];
+    assert_eq!(
+        linearize(&QueueSpec::default(), &history),
+        Some(vec![0, 1, 2, 3, 4])
+    );
+}
+
+/// Overlapping operations may take effect in either order, but not against the real time order.
+#[test]
+fn overlapping() {
+    let history = [
+        operation(QueueOp::Push(1), None, 0, 3),
+        operation(QueueOp::Push(2), None, 1, 2),
+        operation(QueueOp::Pop, Some(2), 4, 5),
+    ];
+    assert_eq!(
+        linearize(&QueueSpec::default(), &history),
+        Some(vec![1, 0, 2])
+    );
+
+    let history = [
+        operation(QueueOp::Push(1), None, 0, 1),
+        operation(QueueOp::Push(2), None, 2, 3),
+        operation(QueueOp::Pop, Some(2), 4, 5),
+    ];
+    assert_eq!(linearize(&QueueSpec::default(), &history), None);
+}
+
+/// The search backtracks over the pending operations linearized too early.
+#[test]
+fn backtrack() {
+    let history = [
+        operation(StackOp::Push(1), None, 0, 10),
+        operation(StackOp::Push(2), None, 1, 9),
+        operation(StackOp::Pop, Some(1), 2, 3),
+        operation(StackOp::Pop, Some(2), 4, 5),
+        operation(StackOp::Pop, None, 11, 12),
+    ];
+    assert_eq!(
+        linearize(&StackSpec::default(), &history),
+        Some(vec![0, 2, 1, 3, 4])
+    );
+}
+
+#[test]
+#[should_panic(expected = "not linearizable")]
+fn stale_lookup() {
+    let history = [
+        operation(MapOp::Insert(1, 'a'), MapRet::Done(true), 0, 1),
+        operation(MapOp::Insert(2, 'b'), MapRet::Done(true), 0, 1),
+        operation(MapOp::Remove(1), MapRet::Done(true), 2, 3),
+        operation(MapOp::Lookup(2), MapRet::Value(Some('b')), 4, 5),
+        operation(MapOp::Lookup(1), MapRet::Value(Some('a')), 4, 5),
+    ];
+    assert_linearizable_by(&MapSpec::default(), &history, |op| *op.key());
+}
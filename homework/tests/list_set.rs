@@ -1,312 +1,417 @@
-use rand::distributions::Alphanumeric;
-use rand::prelude::*;
-use std::collections::{HashMap, HashSet};
-use std::sync::atomic::{
-    AtomicBool,
-    Ordering::{Acquire, Release},
-};
-use std::thread;
-
-use cs431_homework::OrderedListSet;
-
-#[test]
-fn smoke() {
-    let set = OrderedListSet::new();
-    set.insert(1).unwrap();
-    set.insert(2).unwrap();
-    set.insert(3).unwrap();
-    assert_eq!(set.remove(&2), Ok(2));
-    for i in set.iter() {
-        println!("{i}");
+mod mock;
+mod stress;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use rand::distributions::Alphanumeric;
+    use rand::prelude::*;
+    use std::collections::{BTreeSet, HashMap, HashSet};
+    use std::sync::atomic::{
+        AtomicBool,
+        Ordering::{Acquire, Release},
+    };
+    use std::thread;
+
+    use super::stress::{Op, Stress};
+    use cs431_homework::OrderedListSet;
+
+    #[test]
+    fn smoke() {
+        let set = OrderedListSet::new();
+        set.insert(1).unwrap();
+        set.insert(2).unwrap();
+        set.insert(3).unwrap();
+        assert_eq!(set.remove(&2), Ok(2));
+        for i in set.iter() {
+            println!("{i}");
+        }
+        assert_eq!(set.remove(&3), Ok(3));
     }
-    assert_eq!(set.remove(&3), Ok(3));
-}
 
-#[test]
-fn parallel_iter_end() {
-    let set = OrderedListSet::new();
-    set.insert(1).unwrap();
-    set.insert(2).unwrap();
-    let mut iter = set.iter();
-    iter.next();
-    iter.next();
-    iter.next();
-    thread::scope(|s| {
-        s.spawn(|| {
-            // this shouldn't block
-            let _ = set.iter().collect::<Vec<_>>();
+    #[test]
+    fn parallel_iter_end() {
+        let set = OrderedListSet::new();
+        set.insert(1).unwrap();
+        set.insert(2).unwrap();
+        let mut iter = set.iter();
+        iter.next();
+        iter.next();
+        iter.next();
+        thread::scope(|s| {
+            s.spawn(|| {
+                // this shouldn't block
+                let _ = set.iter().collect::<Vec<_>>();
+            });
         });
-    });
-    drop(iter);
-}
-
-#[test]
-fn stress_sequential() {
-    #[derive(Debug)]
-    enum Ops {
-        ContainsSome,
-        ContainsNone,
-        Insert,
-        RemoveSome,
-        RemoveNone,
-        Iterate,
+        drop(iter);
     }
 
-    let ops = [
-        Ops::ContainsSome,
-        Ops::ContainsNone,
-        Ops::Insert,
-        Ops::RemoveSome,
-        Ops::RemoveNone,
-        Ops::Iterate,
-    ];
-    let mut rng = thread_rng();
-    let set = OrderedListSet::default();
-    let mut hashset = HashSet::<String>::new();
-
-    const OPS: usize = 4096;
-
-    for i in 0..OPS {
-        let op = ops.choose(&mut rng).unwrap();
-
-        match op {
-            Ops::ContainsSome => {
-                if let Some(key) = hashset.iter().choose(&mut rng) {
-                    println!("iteration {i}: contains({key:?}) (existing)");
-                    assert_eq!(set.contains(key), hashset.contains(key));
+    #[test]
+    fn stress_sequential() {
+        #[derive(Debug)]
+        enum Ops {
+            ContainsSome,
+            ContainsNone,
+            Insert,
+            RemoveSome,
+            RemoveNone,
+            Iterate,
+        }
+
+        let ops = [
+            Ops::ContainsSome,
+            Ops::ContainsNone,
+            Ops::Insert,
+            Ops::RemoveSome,
+            Ops::RemoveNone,
+            Ops::Iterate,
+        ];
+        let mut rng = thread_rng();
+        let set = OrderedListSet::default();
+        let mut hashset = HashSet::<String>::new();
+
+        const OPS: usize = 4096;
+
+        for i in 0..OPS {
+            let op = ops.choose(&mut rng).unwrap();
+
+            match op {
+                Ops::ContainsSome => {
+                    if let Some(key) = hashset.iter().choose(&mut rng) {
+                        println!("iteration {i}: contains({key:?}) (existing)");
+                        assert_eq!(set.contains(key), hashset.contains(key));
+                    }
                 }
-            }
-            Ops::ContainsNone => {
-                let key = generate_random_string(&mut rng);
-                println!("iteration {i}: contains({key:?}) (non-existing)");
-                assert_eq!(set.contains(&key), hashset.contains(&key));
-            }
-            Ops::Insert => {
-                let key = generate_random_string(&mut rng);
-                println!("iteration {i}: insert({key:?})");
-                assert_eq!(set.insert(key.clone()).is_ok(), hashset.insert(key));
-            }
-            Ops::RemoveSome => {
-                let key = hashset.iter().choose(&mut rng).map(Clone::clone);
-                if let Some(key) = key {
-                    println!("iteration {i}: remove({key:?}) (existing)");
+                Ops::ContainsNone => {
+                    let key = generate_random_string(&mut rng);
+                    println!("iteration {i}: contains({key:?}) (non-existing)");
+                    assert_eq!(set.contains(&key), hashset.contains(&key));
+                }
+                Ops::Insert => {
+                    let key = generate_random_string(&mut rng);
+                    println!("iteration {i}: insert({key:?})");
+                    assert_eq!(set.insert(key.clone()).is_ok(), hashset.insert(key));
+                }
+                Ops::RemoveSome => {
+                    let key = hashset.iter().choose(&mut rng).map(Clone::clone);
+                    if let Some(key) = key {
+                        println!("iteration {i}: remove({key:?}) (existing)");
+                        assert_eq!(set.remove(&key).is_ok(), hashset.remove(&key));
+                    }
+                }
+                Ops::RemoveNone => {
+                    let key = generate_random_string(&mut rng);
+                    println!("iteration {i}: remove({key:?}) (non-existing)");
                     assert_eq!(set.remove(&key).is_ok(), hashset.remove(&key));
                 }
-            }
-            Ops::RemoveNone => {
-                let key = generate_random_string(&mut rng);
-                println!("iteration {i}: remove({key:?}) (non-existing)");
-                assert_eq!(set.remove(&key).is_ok(), hashset.remove(&key));
-            }
-            Ops::Iterate => {
-                let result = set.iter().map(Clone::clone).collect::<HashSet<_>>();
-                println!("iteration {i}: iter() → {result:?}");
-                assert_eq!(result, hashset);
+                Ops::Iterate => {
+                    let result = set.iter().map(Clone::clone).collect::<HashSet<_>>();
+                    println!("iteration {i}: iter() → {result:?}");
+                    assert_eq!(result, hashset);
+                }
             }
         }
     }
-}
 
-const THREADS: usize = 16;
-const STEPS: usize = 4096 * 8;
+    const THREADS: usize = 16;
+    const STEPS: usize = 4096 * 8;
 
-fn generate_random_string(rng: &mut ThreadRng) -> String {
-    rng.sample_iter(&Alphanumeric)
-        .take(1)
-        .map(|x| x as char)
-        .collect()
-}
+    fn generate_random_string(rng: &mut ThreadRng) -> String {
+        rng.sample_iter(&Alphanumeric)
+            .take(1)
+            .map(|x| x as char)
+            .collect()
+    }
 
-#[derive(Debug, Clone, Copy)]
-enum Ops {
-    Contains,
-    Insert,
-    Remove,
-}
+    #[derive(Debug, Clone, Copy)]
+    enum Ops {
+        Contains,
+        Insert,
+        Remove,
+    }
 
-#[derive(Debug, Clone)]
-enum Log {
-    Contains { key: String, result: bool },
-    Insert { key: String, result: bool },
-    Remove { key: String, result: bool },
-}
+    #[derive(Debug, Clone)]
+    enum Log {
+        Contains { key: String, result: bool },
+        Insert { key: String, result: bool },
+        Remove { key: String, result: bool },
+    }
 
-impl Log {
-    fn key(&self) -> &String {
-        match self {
-            Self::Contains { key, .. } => key,
-            Self::Insert { key, .. } => key,
-            Self::Remove { key, .. } => key,
+    impl Log {
+        fn key(&self) -> &String {
+            match self {
+                Self::Contains { key, .. } => key,
+                Self::Insert { key, .. } => key,
+                Self::Remove { key, .. } => key,
+            }
         }
     }
-}
-
-#[test]
-fn stress_concurrent() {
-    let ops = [Ops::Contains, Ops::Insert, Ops::Remove];
-
-    let set = OrderedListSet::new();
 
-    thread::scope(|s| {
-        for _ in 0..THREADS {
-            s.spawn(|| {
-                let mut rng = thread_rng();
-                for _ in 0..STEPS {
-                    let op = ops.choose(&mut rng).unwrap();
-
-                    match op {
-                        Ops::Contains => {
-                            let value = generate_random_string(&mut rng);
-                            let _ = set.contains(&value);
-                        }
-                        Ops::Insert => {
-                            let value = generate_random_string(&mut rng);
-                            let _ = set.insert(value);
-                        }
-                        Ops::Remove => {
-                            let value = generate_random_string(&mut rng);
-                            let _ = set.remove(&value);
+    #[test]
+    fn stress_concurrent() {
+        let ops = [Ops::Contains, Ops::Insert, Ops::Remove];
+
+        let set = OrderedListSet::new();
+
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    let mut rng = thread_rng();
+                    for _ in 0..STEPS {
+                        let op = ops.choose(&mut rng).unwrap();
+
+                        match op {
+                            Ops::Contains => {
+                                let value = generate_random_string(&mut rng);
+                                let _ = set.contains(&value);
+                            }
+                            Ops::Insert => {
+                                let value = generate_random_string(&mut rng);
+                                let _ = set.insert(value);
+                            }
+                            Ops::Remove => {
+                                let value = generate_random_string(&mut rng);
+                                let _ = set.remove(&value);
+                            }
                         }
                     }
-                }
-            });
-        }
-    });
-}
+                });
+            }
+        });
+    }
 
-fn assert_logs_consistent(logs: &Vec<Vec<Log>>) {
-    let mut per_key_logs = HashMap::<String, Vec<Log>>::new();
-    for ls in logs {
-        for l in ls {
-            per_key_logs
-                .entry(l.key().clone())
-                .or_insert_with(Vec::new)
-                .push(l.clone());
+    fn assert_logs_consistent(logs: &Vec<Vec<Log>>) {
+        let mut per_key_logs = HashMap::<String, Vec<Log>>::new();
+        for ls in logs {
+            for l in ls {
+                per_key_logs
+                    .entry(l.key().clone())
+                    .or_insert_with(Vec::new)
+                    .push(l.clone());
+            }
         }
-    }
 
-    for (k, logs) in &per_key_logs {
-        let mut inserts = HashMap::<String, usize>::new();
-        let mut deletes = HashMap::<String, usize>::new();
+        for (k, logs) in &per_key_logs {
+            let mut inserts = HashMap::<String, usize>::new();
+            let mut deletes = HashMap::<String, usize>::new();
 
-        for l in logs {
-            match l {
-                Log::Insert { result: true, .. } => *inserts.entry(k.clone()).or_insert(0) += 1,
-                Log::Remove { result: true, .. } => *deletes.entry(k.clone()).or_insert(0) += 1,
-                _ => (),
+            for l in logs {
+                match l {
+                    Log::Insert { result: true, .. } => *inserts.entry(k.clone()).or_insert(0) += 1,
+                    Log::Remove { result: true, .. } => *deletes.entry(k.clone()).or_insert(0) += 1,
+                    _ => (),
+                }
             }
-        }
 
-        for l in logs {
-            if let Log::Contains { key, result: true } = l {
-                assert!(inserts.contains_key(key))
+            for l in logs {
+                if let Log::Contains { key, result: true } = l {
+                    assert!(inserts.contains_key(key))
+                }
             }
-        }
 
-        for (k, v) in &deletes {
-            assert!(inserts.get(k).unwrap() >= v);
+            for (k, v) in &deletes {
+                assert!(inserts.get(k).unwrap() >= v);
+            }
         }
     }
-}
-
-#[test]
-fn log_concurrent() {
-    let ops = [Ops::Contains, Ops::Insert, Ops::Remove];
 
-    const THREADS: usize = 16;
-    const STEPS: usize = 4096 * 12;
-
-    let set = OrderedListSet::new();
-
-    let logs = thread::scope(|s| {
-        let mut handles = Vec::new();
-        for _ in 0..THREADS {
-            let handle = s.spawn(|| {
-                let mut rng = thread_rng();
-                let mut logs = Vec::new();
-                for _ in 0..STEPS {
-                    let op = ops.choose(&mut rng).unwrap();
-
-                    match op {
-                        Ops::Contains => {
-                            let key = generate_random_string(&mut rng);
-                            let result = set.contains(&key);
-                            logs.push(Log::Contains {
-                                key: key.clone(),
-                                result,
-                            });
+    #[test]
+    fn log_concurrent() {
+        let ops = [Ops::Contains, Ops::Insert, Ops::Remove];
+
+        const THREADS: usize = 16;
+        const STEPS: usize = 4096 * 12;
+
+        let set = OrderedListSet::new();
+
+        let logs = thread::scope(|s| {
+            let mut handles = Vec::new();
+            for _ in 0..THREADS {
+                let handle = s.spawn(|| {
+                    let mut rng = thread_rng();
+                    let mut logs = Vec::new();
+                    for _ in 0..STEPS {
+                        let op = ops.choose(&mut rng).unwrap();
+
+                        match op {
+                            Ops::Contains => {
+                                let key = generate_random_string(&mut rng);
+                                let result = set.contains(&key);
+                                logs.push(Log::Contains {
+                                    key: key.clone(),
+                                    result,
+                                });
+                            }
+                            Ops::Insert => {
+                                let key = generate_random_string(&mut rng);
+                                let result = set.insert(key.clone());
+                                logs.push(Log::Insert {
+                                    key,
+                                    result: result.is_ok(),
+                                });
+                            }
+                            Ops::Remove => {
+                                let key = generate_random_string(&mut rng);
+                                let result = set.remove(&key);
+                                logs.push(Log::Remove {
+                                    key: key.clone(),
+                                    result: result.is_ok(),
+                                });
+                            }
                         }
-                        Ops::Insert => {
-                            let key = generate_random_string(&mut rng);
-                            let result = set.insert(key.clone());
-                            logs.push(Log::Insert {
-                                key,
-                                result: result.is_ok(),
-                            });
-                        }
-                        Ops::Remove => {
-                            let key = generate_random_string(&mut rng);
-                            let result = set.remove(&key);
-                            logs.push(Log::Remove {
-                                key: key.clone(),
-                                result: result.is_ok(),
-                            });
+                    }
+                    logs
+                });
+                handles.push(handle);
+            }
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert_logs_consistent(&logs);
+    }
+
+    #[test]
+    fn iter_consistent() {
+        const THREADS: usize = 15;
+        const STEPS: usize = 4096 * 12;
+
+        let set = OrderedListSet::new();
+
+        // pre-fill with even numbers
+        for i in (0..100).step_by(2).rev() {
+            let _ = set.insert(i);
+        }
+        let evens = set.iter().copied().collect::<HashSet<_>>();
+
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            // insert or remove odd numbers
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    let mut rng = thread_rng();
+                    for _ in 0..STEPS {
+                        let key = 2 * rng.gen_range(0..50) + 1;
+                        if rng.gen() {
+                            let _ = set.insert(key);
+                        } else {
+                            let _ = set.remove(&key);
                         }
                     }
+                    done.store(true, Release);
+                });
+            }
+            // iterator consistency check
+            s.spawn(|| {
+                while !done.load(Acquire) {
+                    let snapshot = set.iter().copied().collect::<Vec<_>>();
+                    // sorted
+                    assert!(snapshot.windows(2).all(|k| k[0] <= k[1]));
+                    // even numbers are not touched
+                    let snapshot = snapshot.into_iter().collect::<HashSet<_>>();
+                    assert!(evens.is_subset(&snapshot));
                 }
-                logs
             });
-            handles.push(handle);
+        });
+    }
+
+    impl super::stress::Subject for OrderedListSet<u64> {
+        type Model = BTreeSet<u64>;
+
+        fn apply(&self, op: Op) -> Option<u64> {
+            match op {
+                Op::Insert(key, _) => self.insert(key).err(),
+                Op::Remove(key) => self.remove(&key).ok(),
+                Op::Get(key) => self.contains(&key).then_some(key),
+            }
         }
-        handles
-            .into_iter()
-            .map(|h| h.join().unwrap())
-            .collect::<Vec<_>>()
-    });
 
-    assert_logs_consistent(&logs);
-}
+        fn state(&self, _: u64) -> Self::Model {
+            self.iter().copied().collect()
+        }
 
-#[test]
-fn iter_consistent() {
-    const THREADS: usize = 15;
-    const STEPS: usize = 4096 * 12;
+        /// The keys are sorted, even while they are inserted and removed.
+        fn check(&self) {
+            let keys = self.iter().copied().collect::<Vec<_>>();
+            assert!(keys.windows(2).all(|pair| pair[0] < pair[1]), "{keys:?}");
+        }
+    }
 
-    let set = OrderedListSet::new();
+    #[test]
+    fn model_disjoint() {
+        Stress::new(4, 1024 * 4, 256).disjoint::<OrderedListSet<u64>>();
+    }
 
-    // pre-fill with even numbers
-    for i in (0..100).step_by(2).rev() {
-        let _ = set.insert(i);
+    #[test]
+    fn model_shared() {
+        Stress::new(4, 1024 * 4, 64).shared::<OrderedListSet<u64>>();
     }
-    let evens = set.iter().copied().collect::<HashSet<_>>();
+}
 
-    let done = AtomicBool::new(false);
-    thread::scope(|s| {
-        // insert or remove odd numbers
-        for _ in 0..THREADS {
-            s.spawn(|| {
-                let mut rng = thread_rng();
-                for _ in 0..STEPS {
-                    let key = 2 * rng.gen_range(0..50) + 1;
-                    if rng.gen() {
-                        let _ = set.insert(key);
-                    } else {
-                        let _ = set.remove(&key);
-                    }
-                }
-                done.store(true, Release);
-            });
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::OrderedListSet;
+
+    fn set_of(keys: &[i32]) -> Arc<OrderedListSet<i32>> {
+        let set = OrderedListSet::new();
+        for key in keys {
+            set.insert(*key).unwrap();
         }
-        // iterator consistency check
-        s.spawn(|| {
-            while !done.load(Acquire) {
-                let snapshot = set.iter().copied().collect::<Vec<_>>();
-                // sorted
-                assert!(snapshot.windows(2).all(|k| k[0] <= k[1]));
-                // even numbers are not touched
-                let snapshot = snapshot.into_iter().collect::<HashSet<_>>();
-                assert!(evens.is_subset(&snapshot));
-            }
-        });
-    });
+        Arc::new(set)
+    }
+
+    /// An insertion and a removal next to each other both take effect.
+    #[test]
+    fn insert_remove_sync() {
+        model(|| {
+            let set = set_of(&[1, 3]);
+            let th = {
+                let set = set.clone();
+                thread::spawn(move || set.insert(2))
+            };
+            assert_eq!(set.remove(&3), Ok(3));
+            assert_eq!(th.join().unwrap(), Ok(()));
+            assert_eq!(set.iter().copied().collect::<Vec<_>>(), [1, 2]);
+        })
+    }
+
+    /// Of two removals of the same key, exactly one succeeds.
+    #[test]
+    fn remove_sync() {
+        model(|| {
+            let set = set_of(&[1, 2]);
+            let th = {
+                let set = set.clone();
+                thread::spawn(move || set.remove(&1).is_ok())
+            };
+            let removed = set.remove(&1).is_ok();
+            assert!(removed != th.join().unwrap());
+            assert!(!set.contains(&1));
+            assert!(set.contains(&2));
+        })
+    }
+
+    /// An iteration sees the keys in order, and sees a key that is neither inserted nor removed
+    /// meanwhile.
+    #[test]
+    fn iter_sync() {
+        model(|| {
+            let set = set_of(&[1, 3]);
+            let th = {
+                let set = set.clone();
+                thread::spawn(move || {
+                    let keys = set.iter().copied().collect::<Vec<_>>();
+                    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+                    assert!(keys.contains(&3));
+                })
+            };
+            assert_eq!(set.insert(2), Ok(()));
+            assert_eq!(set.remove(&1), Ok(1));
+            th.join().unwrap();
+        })
+    }
 }
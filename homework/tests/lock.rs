@@ -0,0 +1,334 @@
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use cs431::lock::{Lock, RawLock, RawTryLock};
+    use cs431_homework::lock::{
+        BakeryLock, BravoRwLock, ClhLock, DekkerLock, McsLock, PetersonLock, Preference, RwSpinLock,
+    };
+    use std::panic::{self, AssertUnwindSafe};
+    use std::thread::scope;
+
+    /// Every thread's push is in the vector exactly once.
+    fn smoke<L: RawLock>() {
+        const THREADS: usize = 8;
+        const ITER: usize = 1024;
+
+        let lock = Lock::<L, Vec<usize>>::new(Vec::new());
+        scope(|s| {
+            for t in 0..THREADS {
+                let lock = &lock;
+                let _ = s.spawn(move || {
+                    for i in 0..ITER {
+                        lock.lock().push(t * ITER + i);
+                    }
+                });
+            }
+        });
+
+        let mut values = lock.into_inner();
+        values.sort_unstable();
+        assert_eq!(values, (0..THREADS * ITER).collect::<Vec<_>>());
+    }
+
+    fn try_lock<L: RawTryLock>() {
+        let lock = Lock::<L, usize>::new(0);
+        let mut guard = lock.try_lock().unwrap();
+        *guard += 1;
+        assert!(lock.try_lock().is_err());
+        drop(guard);
+        *lock.try_lock().unwrap() += 1;
+        assert_eq!(*lock.lock(), 2);
+    }
+
+    /// A thread holds two locks of the same kind at once.
+    fn nested<L: RawLock>() {
+        let outer = Lock::<L, usize>::new(0);
+        let inner = Lock::<L, usize>::new(0);
+        scope(|s| {
+            for _ in 0..4 {
+                let _ = s.spawn(|| {
+                    for _ in 0..1024 {
+                        let mut outer = outer.lock();
+                        let mut inner = inner.lock();
+                        *outer += 1;
+                        *inner += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*outer.lock(), 4 * 1024);
+        assert_eq!(*inner.lock(), 4 * 1024);
+    }
+
+    #[test]
+    fn mcs() {
+        smoke::<McsLock>();
+        try_lock::<McsLock>();
+        nested::<McsLock>();
+    }
+
+    #[test]
+    fn clh() {
+        smoke::<ClhLock>();
+        nested::<ClhLock>();
+    }
+
+    /// More threads than the slots of the classic locks wait for a slot.
+    #[test]
+    fn classic() {
+        smoke::<PetersonLock>();
+        nested::<PetersonLock>();
+        smoke::<DekkerLock>();
+        nested::<DekkerLock>();
+        smoke::<BakeryLock>();
+        nested::<BakeryLock>();
+        smoke::<BakeryLock<3>>();
+    }
+
+    const PREFERENCES: [Preference; 3] = [
+        Preference::Reader,
+        Preference::Writer,
+        Preference::PhaseFair,
+    ];
+
+    #[test]
+    fn rw_spin() {
+        for preference in PREFERENCES {
+            let lock = RwSpinLock::with_preference(0, preference);
+            let first = lock.read();
+            let second = lock.try_read().unwrap();
+            assert_eq!(*first + *second, 0);
+            assert!(lock.try_write().is_none());
+            drop((first, second));
+
+            let mut guard = lock.write();
+            *guard += 1;
+            assert!(lock.try_read().is_none());
+            assert!(lock.try_write().is_none());
+            drop(guard);
+            *lock.try_write().unwrap() += 1;
+            assert_eq!(*lock.read(), 2);
+            assert_eq!(lock.into_inner(), 2);
+        }
+    }
+
+    /// Readers never see a half-done write, and writes are not lost.
+    #[test]
+    fn rw_spin_stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+
+        for preference in PREFERENCES {
+            let lock = RwSpinLock::with_preference((0, 0), preference);
+            scope(|s| {
+                for _ in 0..THREADS {
+                    let _ = s.spawn(|| {
+                        for _ in 0..ITER {
+                            let mut guard = lock.write();
+                            guard.0 += 1;
+                            guard.1 += 1;
+                        }
+                    });
+                    let _ = s.spawn(|| {
+                        for _ in 0..ITER {
+                            let guard = lock.read();
+                            assert_eq!(guard.0, guard.1);
+                        }
+                    });
+                }
+            });
+            assert_eq!(lock.into_inner(), (THREADS * ITER, THREADS * ITER));
+        }
+    }
+
+    #[test]
+    fn bravo() {
+        let lock = BravoRwLock::new(0);
+        // The first reader goes through its slot, and the second one, whose slot is taken,
+        // through the underlying lock.
+        let first = lock.read().unwrap();
+        let second = lock.read().unwrap();
+        assert_eq!(*first + *second, 0);
+        drop((first, second));
+
+        // The writer revokes the bias, and the readers after it restore it.
+        *lock.write().unwrap() += 1;
+        assert_eq!(*lock.read().unwrap(), 1);
+        *lock.write().unwrap() += 1;
+        assert_eq!(*lock.read().unwrap(), 2);
+
+        // A writer that panics poisons the lock for the readers through the slots too.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _guard = lock.write().unwrap();
+            panic!("write failed");
+        }));
+        assert!(result.is_err());
+        assert!(lock.is_poisoned());
+        for _ in 0..2 {
+            assert_eq!(*lock.read().unwrap_err().into_inner(), 2);
+        }
+        assert_eq!(lock.into_inner().unwrap_err().into_inner(), 2);
+    }
+
+    /// Readers never see a half-done write, and writes are not lost, while the writers keep
+    /// revoking the bias the readers restore.
+    #[test]
+    fn bravo_stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+
+        let lock = BravoRwLock::new((0, 0));
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    for _ in 0..ITER / 16 {
+                        for _ in 0..16 {
+                            let guard = lock.read().unwrap();
+                            assert_eq!(guard.0, guard.1);
+                        }
+                        let mut guard = lock.write().unwrap();
+                        guard.0 += 1;
+                        guard.1 += 1;
+                    }
+                });
+                let _ = s.spawn(|| {
+                    for _ in 0..ITER {
+                        let guard = lock.read().unwrap();
+                        assert_eq!(guard.0, guard.1);
+                    }
+                });
+            }
+        });
+        assert_eq!(
+            lock.into_inner().unwrap(),
+            (THREADS * ITER / 16, THREADS * ITER / 16)
+        );
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431::lock::RawLock;
+    use cs431_homework::lock::{
+        BakeryLock, BravoRwLock, ClhLock, DekkerLock, McsLock, PetersonLock, Preference, RwSpinLock,
+    };
+
+    /// Increments a counter non-atomically in the critical sections of two threads. An update is
+    /// lost if the critical sections overlap or are not ordered by the lock.
+    fn mutual_exclusion<L: RawLock + 'static>() {
+        model(|| {
+            let lock = Arc::new(L::default());
+            let count = Arc::new(AtomicUsize::new(0));
+            let increment = {
+                let count = count.clone();
+                move || {
+                    let token = lock.lock();
+                    count.store(count.load(Relaxed) + 1, Relaxed);
+                    // SAFETY: `token` is from `lock` above.
+                    unsafe { lock.unlock(token) };
+                }
+            };
+            let th = thread::spawn(increment.clone());
+            increment();
+            th.join().unwrap();
+            assert_eq!(count.load(Relaxed), 2);
+        })
+    }
+
+    #[test]
+    fn mcs_sync() {
+        mutual_exclusion::<McsLock>();
+    }
+
+    #[test]
+    fn clh_sync() {
+        mutual_exclusion::<ClhLock>();
+    }
+
+    #[test]
+    fn peterson_sync() {
+        mutual_exclusion::<PetersonLock>();
+    }
+
+    #[test]
+    fn dekker_sync() {
+        mutual_exclusion::<DekkerLock>();
+    }
+
+    #[test]
+    fn bakery_sync() {
+        mutual_exclusion::<BakeryLock<2>>();
+    }
+
+    /// A reader sees both or neither of the stores of a writer, and the writers' increments are
+    /// not lost.
+    fn reader_writer(preference: Preference) {
+        model(move || {
+            let lock = Arc::new(RwSpinLock::with_preference(
+                [AtomicUsize::new(0), AtomicUsize::new(0)],
+                preference,
+            ));
+            let writer = {
+                let lock = lock.clone();
+                move || {
+                    let guard = lock.write();
+                    for count in guard.iter() {
+                        count.store(count.load(Relaxed) + 1, Relaxed);
+                    }
+                }
+            };
+            let th = thread::spawn(writer.clone());
+            {
+                let guard = lock.read();
+                assert_eq!(guard[0].load(Relaxed), guard[1].load(Relaxed));
+            }
+            writer();
+            th.join().unwrap();
+            assert_eq!(lock.read()[1].load(Relaxed), 2);
+        })
+    }
+
+    #[test]
+    fn rw_spin_reader_sync() {
+        reader_writer(Preference::Reader);
+    }
+
+    #[test]
+    fn rw_spin_writer_sync() {
+        reader_writer(Preference::Writer);
+    }
+
+    #[test]
+    fn rw_spin_phase_fair_sync() {
+        reader_writer(Preference::PhaseFair);
+    }
+
+    /// A reader through a slot sees both or neither of the stores of a writer that revokes the
+    /// bias, and the writer's stores are seen by the reader that restores it.
+    #[test]
+    fn bravo_sync() {
+        model(|| {
+            let lock = Arc::new(BravoRwLock::new([AtomicUsize::new(0), AtomicUsize::new(0)]));
+            let th = {
+                let lock = lock.clone();
+                thread::spawn(move || {
+                    let guard = lock.write().unwrap();
+                    for count in guard.iter() {
+                        count.store(count.load(Relaxed) + 1, Relaxed);
+                    }
+                })
+            };
+            for _ in 0..2 {
+                let guard = lock.read().unwrap();
+                assert_eq!(guard[0].load(Relaxed), guard[1].load(Relaxed));
+            }
+            th.join().unwrap();
+            let guard = lock.read().unwrap();
+            assert_eq!(guard[1].load(Relaxed), 1);
+        })
+    }
+}
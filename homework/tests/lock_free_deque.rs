@@ -0,0 +1,292 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable, DequeOp, DequeSpec, Recorder};
+    use super::threads::{run, scope};
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::LockFreeDeque;
+    use rand::prelude::*;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn smoke() {
+        let deque = LockFreeDeque::new();
+        assert!(deque.is_empty());
+        assert_eq!(deque.pop_front(), None);
+        assert_eq!(deque.pop_back(), None);
+
+        deque.push_back(2);
+        deque.push_front(1);
+        deque.push_back(3);
+        assert!(!deque.is_empty());
+        assert_eq!(deque.pop_front(), Some(1));
+        assert_eq!(deque.pop_back(), Some(3));
+        assert_eq!(deque.pop_back(), Some(2));
+        assert_eq!(deque.pop_front(), None);
+        assert!(deque.is_empty());
+    }
+
+    /// Random operations agree with `VecDeque`.
+    #[test]
+    fn sequential() {
+        const ITER: usize = 1024 * 16;
+
+        let deque = LockFreeDeque::new();
+        let mut reference = VecDeque::new();
+        let mut rng = thread_rng();
+        for i in 0..ITER {
+            match rng.gen_range(0..4) {
+                0 => {
+                    deque.push_front(i);
+                    reference.push_front(i);
+                }
+                1 => {
+                    deque.push_back(i);
+                    reference.push_back(i);
+                }
+                2 => assert_eq!(deque.pop_front(), reference.pop_front()),
+                _ => assert_eq!(deque.pop_back(), reference.pop_back()),
+            }
+        }
+        while let Some(value) = reference.pop_front() {
+            assert_eq!(deque.pop_front(), Some(value));
+        }
+        assert!(deque.is_empty());
+    }
+
+    /// Every value pushed at either end is popped exactly once, at either end.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+
+        let deque = LockFreeDeque::new();
+        let done = AtomicBool::new(false);
+        let mut popped = scope(|s| {
+            // Spawns all poppers before pushing, so that they pop while the values are pushed.
+            #[allow(clippy::needless_collect)]
+            let poppers = (0..THREADS)
+                .map(|t| {
+                    let deque = &deque;
+                    let done = &done;
+                    s.spawn(move || {
+                        let mut popped = Vec::new();
+                        loop {
+                            let value = if t % 2 == 0 {
+                                deque.pop_front()
+                            } else {
+                                deque.pop_back()
+                            };
+                            match value {
+                                Some(value) => popped.push(value),
+                                None if done.load(Relaxed) => break,
+                                None => {}
+                            }
+                            collect();
+                        }
+                        popped
+                    })
+                })
+                .collect::<Vec<_>>();
+            let _ = s.run(THREADS, |t| {
+                for i in 0..ITER {
+                    let value = (t, i);
+                    if pushes_front(t, i) {
+                        deque.push_front(value);
+                    } else {
+                        deque.push_back(value);
+                    }
+                }
+            });
+            done.store(true, Relaxed);
+            poppers
+                .into_iter()
+                .flat_map(|popper| popper.join())
+                .collect::<Vec<_>>()
+        });
+
+        assert!(deque.is_empty());
+        popped.sort_unstable();
+        let pushed = (0..THREADS)
+            .flat_map(|t| (0..ITER).map(move |i| (t, i)))
+            .collect::<Vec<_>>();
+        assert_eq!(popped, pushed);
+    }
+
+    /// Which end the `i`-th value of thread `t` is pushed to.
+    fn pushes_front(t: usize, i: usize) -> bool {
+        (t.wrapping_mul(0x9E37_79B9) ^ i.wrapping_mul(0x85EB_CA6B)).count_ones() & 1 == 0
+    }
+
+    /// Values are dropped exactly once, whether popped or left in the deque.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let deque = LockFreeDeque::new();
+        for i in 0..100 {
+            if i % 2 == 0 {
+                deque.push_front(Canary(&dropped));
+            } else {
+                deque.push_back(Canary(&dropped));
+            }
+        }
+        scope(|s| {
+            s.run(2, |t| {
+                for _ in 0..25 {
+                    let popped = if t == 0 {
+                        deque.pop_front()
+                    } else {
+                        deque.pop_back()
+                    };
+                    assert!(popped.is_some());
+                }
+                collect();
+            });
+        });
+        assert_eq!(dropped.load(Relaxed), 50);
+        drop(deque);
+        assert_eq!(dropped.load(Relaxed), 100);
+    }
+
+    /// Histories of random operations at both ends are linearizable.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 4;
+        const ITER: usize = 256;
+
+        let deque = LockFreeDeque::new();
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let value = t * ITER + i;
+                let op = match rng.gen_range(0..4) {
+                    0 => DequeOp::PushFront(value),
+                    1 => DequeOp::PushBack(value),
+                    2 => DequeOp::PopFront,
+                    _ => DequeOp::PopBack,
+                };
+                recorder.record(&mut history, op, |op| match *op {
+                    DequeOp::PushFront(value) => {
+                        deque.push_front(value);
+                        None
+                    }
+                    DequeOp::PushBack(value) => {
+                        deque.push_back(value);
+                        None
+                    }
+                    DequeOp::PopFront => deque.pop_front(),
+                    DequeOp::PopBack => deque.pop_back(),
+                });
+                collect();
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable(&DequeSpec::default(), &history);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::LockFreeDeque;
+
+    /// The last value is popped from exactly one end, and its node is not accessed after the
+    /// thread that popped it reclaims it.
+    ///
+    /// The full model is too large to explore. Run with `LOOM_MAX_PREEMPTIONS=3`, or sample it with
+    /// `check-sched`.
+    #[test]
+    fn pop_both_ends_sync() {
+        model(|| {
+            let deque = Arc::new(LockFreeDeque::new());
+            deque.push_back(1);
+            let th = {
+                let deque = deque.clone();
+                thread::spawn(move || {
+                    let popped = deque.pop_front();
+                    collect();
+                    popped
+                })
+            };
+            let popped = deque.pop_back();
+            collect();
+            let other = th.join().unwrap();
+            assert_eq!(popped.xor(other), Some(1));
+            assert!(deque.is_empty());
+        })
+    }
+
+    /// A pop at the other end helps a push that is not completed, and gets the old value.
+    ///
+    /// The full model is too large to explore. Run with `LOOM_MAX_PREEMPTIONS=3`, or sample it with
+    /// `check-sched`.
+    #[test]
+    fn push_pop_sync() {
+        model(|| {
+            let deque = Arc::new(LockFreeDeque::new());
+            deque.push_back(1);
+            let th = {
+                let deque = deque.clone();
+                thread::spawn(move || {
+                    deque.push_back(2);
+                    collect();
+                })
+            };
+            assert_eq!(deque.pop_front(), Some(1));
+            collect();
+            th.join().unwrap();
+            assert_eq!(deque.pop_front(), Some(2));
+            assert!(deque.is_empty());
+        })
+    }
+
+    /// Every value is popped exactly once while three threads push and pop at both ends.
+    ///
+    /// Far too large for loom, so it is only sampled by the scheduler of `check-sched`.
+    #[cfg(feature = "check-sched")]
+    #[test]
+    fn both_ends_sampled() {
+        model(|| {
+            let deque = Arc::new(LockFreeDeque::new());
+            deque.push_back(0);
+            let ths = (1..3)
+                .map(|t| {
+                    let deque = deque.clone();
+                    thread::spawn(move || {
+                        let popped = if t == 1 {
+                            deque.push_front(1);
+                            deque.pop_back()
+                        } else {
+                            deque.push_back(2);
+                            deque.pop_front()
+                        };
+                        collect();
+                        popped
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut popped = vec![deque.pop_back()];
+            collect();
+            popped.extend(ths.into_iter().map(|th| th.join().unwrap()));
+            let mut popped = popped.into_iter().flatten().collect::<Vec<_>>();
+            while let Some(value) = deque.pop_front() {
+                popped.push(value);
+            }
+            popped.sort_unstable();
+            assert_eq!(popped, [0, 1, 2]);
+        })
+    }
+}
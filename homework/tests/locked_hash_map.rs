@@ -0,0 +1,266 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod stress;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable_by, MapOp, MapRet, MapSpec, Recorder};
+    use super::stress::{Op, Stress};
+    use super::threads::{run, scope};
+    use cs431_homework::LockedHashMap;
+    use rand::prelude::*;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn smoke() {
+        let map = LockedHashMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert("a", 1), None);
+        assert_eq!(map.insert("b", 2), None);
+        assert_eq!(map.insert("a", 3), Some(1));
+        assert_eq!(map.get("a"), Some(3));
+        assert_eq!(map.lookup("b", |value| value.copied()), Some(2));
+        assert_eq!(map.get_or_insert_with("b", || unreachable!()), 2);
+        assert_eq!(map.get_or_insert_with("c", || 4), 4);
+        assert!(map.contains_key("c"));
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.remove("a"), Some(3));
+        assert_eq!(map.remove("a"), None);
+        assert_eq!(map.len(), 2);
+    }
+
+    /// Random operations agree with `HashMap`, while the table grows from a single bucket.
+    #[test]
+    fn sequential() {
+        const ITER: usize = 1024 * 16;
+
+        let map = LockedHashMap::with_capacity(1);
+        let mut reference = HashMap::new();
+        let mut rng = thread_rng();
+        for i in 0..ITER {
+            let key = rng.gen_range(0..1024);
+            match rng.gen_range(0..3) {
+                0 => assert_eq!(map.insert(key, i), reference.insert(key, i)),
+                1 => assert_eq!(map.remove(&key), reference.remove(&key)),
+                _ => assert_eq!(map.get(&key), reference.get(&key).copied()),
+            }
+        }
+        assert_eq!(map.len(), reference.len());
+        assert!(reference
+            .iter()
+            .all(|(key, value)| map.get(key) == Some(*value)));
+    }
+
+    /// The table grows once it is overloaded, and the operations move the old buckets until it is
+    /// done, while each entry is seen once.
+    #[test]
+    fn incremental_growth() {
+        const CAPACITY: usize = 64;
+
+        let map = LockedHashMap::with_capacity(CAPACITY);
+        assert_eq!(map.capacity(), CAPACITY);
+        for key in 0..=CAPACITY {
+            assert_eq!(map.insert(key, key), None);
+        }
+        assert_eq!(map.capacity(), CAPACITY * 2);
+        assert!(map.is_growing());
+
+        let mut seen = Vec::new();
+        map.for_each(|&key, &value| {
+            assert_eq!(key, value);
+            seen.push(key);
+        });
+        seen.sort_unstable();
+        assert_eq!(seen, (0..=CAPACITY).collect::<Vec<_>>());
+
+        let mut lookups = 0;
+        while map.is_growing() {
+            assert_eq!(map.get(&lookups), Some(lookups));
+            lookups += 1;
+        }
+        assert!(lookups < CAPACITY, "grew in {lookups} lookups");
+        assert!((0..=CAPACITY).all(|key| map.get(&key) == Some(key)));
+    }
+
+    /// Keys inserted concurrently, while the table grows and the entries move, are all kept, and
+    /// the keys that stay in the map are always found.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+        const STAYING: usize = 64;
+
+        let map = LockedHashMap::with_capacity(1);
+        for key in 0..STAYING {
+            let _ = map.insert(key, key);
+        }
+        let done = AtomicBool::new(false);
+        let found = AtomicUsize::new(0);
+        scope(|s| {
+            let _ = s.spawn(|| {
+                while !done.load(Relaxed) {
+                    for key in 0..STAYING {
+                        assert_eq!(map.get(&key), Some(key));
+                    }
+                    let mut staying = 0;
+                    map.for_each(|&key, _| staying += usize::from(key < STAYING));
+                    assert_eq!(staying, STAYING);
+                    let _ = found.fetch_add(1, Relaxed);
+                }
+            });
+            let _ = s.run(THREADS, |t| {
+                for i in 0..ITER {
+                    let key = STAYING + i * THREADS + t;
+                    assert_eq!(map.insert(key, key), None);
+                    if i % 2 == 1 {
+                        assert_eq!(map.remove(&(key - THREADS)), Some(key - THREADS));
+                    }
+                }
+            });
+            done.store(true, Relaxed);
+        });
+        assert!(found.load(Relaxed) > 0);
+        assert_eq!(map.len(), STAYING + THREADS * ITER / 2);
+        for i in 0..ITER {
+            for t in 0..THREADS {
+                let key = STAYING + i * THREADS + t;
+                assert_eq!(map.contains_key(&key), i % 2 == 1);
+            }
+        }
+    }
+
+    /// Threads racing to insert the same keys insert each of them once.
+    #[test]
+    fn get_or_insert_with() {
+        const THREADS: usize = 4;
+        const KEYS: usize = 1024;
+
+        let map = LockedHashMap::with_capacity(1);
+        let inserted = AtomicUsize::new(0);
+        let _ = run(THREADS, |t| {
+            for key in 0..KEYS {
+                let value = map.get_or_insert_with(key, || {
+                    let _ = inserted.fetch_add(1, Relaxed);
+                    (key, t)
+                });
+                assert_eq!(value.0, key);
+            }
+        });
+        assert_eq!(inserted.load(Relaxed), KEYS);
+        assert_eq!(map.len(), KEYS);
+    }
+
+    /// The values left in the map, and those replaced, are dropped.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        {
+            let map = LockedHashMap::with_capacity(1);
+            for key in 0..100 {
+                let _ = map.insert(key, Canary(&dropped));
+            }
+            for key in 0..50 {
+                drop(map.insert(key, Canary(&dropped)));
+            }
+            assert_eq!(dropped.load(Relaxed), 50);
+        }
+        assert_eq!(dropped.load(Relaxed), 150);
+    }
+
+    /// Histories of random operations on a few keys are linearizable.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024;
+
+        let map = LockedHashMap::with_capacity(1);
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let key = rng.gen_range(0..16);
+                let op = match rng.gen_range(0..3) {
+                    0 => MapOp::Upsert(key, t * ITER + i),
+                    1 => MapOp::Remove(key),
+                    _ => MapOp::Lookup(key),
+                };
+                recorder.record(&mut history, op, |op| match *op {
+                    MapOp::Upsert(key, value) => {
+                        let _ = map.insert(key, value);
+                        MapRet::Unit
+                    }
+                    MapOp::Remove(key) => MapRet::Done(map.remove(&key).is_some()),
+                    MapOp::Lookup(key) => MapRet::Value(map.get(&key)),
+                    MapOp::Insert(..) => unreachable!(),
+                });
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable_by(&MapSpec::default(), &history, |op| *op.key());
+    }
+
+    impl super::stress::Subject for LockedHashMap<u64, u64> {
+        type Model = HashMap<u64, u64>;
+
+        fn apply(&self, op: Op) -> Option<u64> {
+            match op {
+                Op::Insert(key, value) => self.insert(key, value),
+                Op::Remove(key) => self.remove(&key),
+                Op::Get(key) => self.get(&key),
+            }
+        }
+
+        fn check(&self) {
+            let mut keys = HashSet::new();
+            self.for_each(|&key, _| assert!(keys.insert(key), "{key} seen twice"));
+        }
+    }
+
+    #[test]
+    fn model_disjoint() {
+        Stress::new(4, 1024 * 8, 1024).disjoint::<LockedHashMap<u64, u64>>();
+    }
+
+    #[test]
+    fn model_shared() {
+        Stress::new(4, 1024 * 8, 64).shared::<LockedHashMap<u64, u64>>();
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::LockedHashMap;
+
+    /// A key is always found while another thread grows the table and moves its bucket.
+    #[test]
+    fn grow_sync() {
+        model(|| {
+            let map = Arc::new(LockedHashMap::with_capacity(2));
+            for key in 0..2 {
+                let _ = map.insert(key, key);
+            }
+            let th = {
+                let map = map.clone();
+                thread::spawn(move || {
+                    let _ = map.insert(2, 2);
+                    let _ = map.get(&2);
+                })
+            };
+            assert_eq!(map.get(&0), Some(0));
+            assert_eq!(map.get(&1), Some(1));
+            th.join().unwrap();
+            assert_eq!(map.capacity(), 4);
+            assert!((0..3).all(|key| map.get(&key) == Some(key)));
+        })
+    }
+}
@@ -0,0 +1,172 @@
+mod canary;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::threads::run;
+    use cs431_homework::ConcurrentLru;
+    use rand::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn smoke() {
+        let lru = ConcurrentLru::new(3);
+        assert!(lru.is_empty());
+        for i in 0..3 {
+            lru.put(i, i * 10);
+        }
+        assert_eq!(lru.get(&0), Some(0));
+        // 1 is the least recently used.
+        lru.put(3, 30);
+        assert_eq!(lru.get(&1), None);
+        assert_eq!(lru.len(), 3);
+
+        // Replacing a value makes it the most recently used.
+        lru.put(2, 21);
+        assert_eq!(lru.pop_lru(), Some((0, 0)));
+        assert_eq!(lru.remove(&2), Some(21));
+        assert_eq!(lru.remove(&2), None);
+        assert_eq!(lru.pop_lru(), Some((3, 30)));
+        assert_eq!(lru.pop_lru(), None);
+    }
+
+    #[test]
+    fn set_capacity() {
+        let lru = ConcurrentLru::new(8);
+        for i in 0..8 {
+            lru.put(i, ());
+        }
+        lru.set_capacity(3);
+        assert_eq!(lru.capacity(), 3);
+        assert_eq!(lru.len(), 3);
+        assert!((0..5).all(|i| lru.get(&i).is_none()));
+        assert!((5..8).all(|i| lru.get(&i).is_some()));
+    }
+
+    /// Random operations agree with a naive LRU cache.
+    #[test]
+    fn sequential() {
+        const CAPACITY: usize = 16;
+        const ITER: usize = 1024 * 16;
+
+        let lru = ConcurrentLru::with_shards(CAPACITY, 4);
+        // The most recently used last.
+        let mut reference = Vec::<(usize, usize)>::new();
+        let mut rng = thread_rng();
+        for i in 0..ITER {
+            let key = rng.gen_range(0..CAPACITY * 2);
+            let position = reference.iter().position(|(k, _)| *k == key);
+            match rng.gen_range(0..4) {
+                0 => {
+                    lru.put(key, i);
+                    if let Some(position) = position {
+                        let _ = reference.remove(position);
+                    } else if reference.len() == CAPACITY {
+                        let _ = reference.remove(0);
+                    }
+                    reference.push((key, i));
+                }
+                1 => assert_eq!(
+                    lru.remove(&key),
+                    position.map(|position| reference.remove(position).1)
+                ),
+                2 => assert_eq!(
+                    lru.pop_lru(),
+                    (!reference.is_empty()).then(|| reference.remove(0))
+                ),
+                _ => {
+                    let expected = position.map(|position| {
+                        let entry = reference.remove(position);
+                        reference.push(entry);
+                        entry.1
+                    });
+                    assert_eq!(lru.get(&key), expected);
+                }
+            }
+            assert_eq!(lru.len(), reference.len());
+        }
+    }
+
+    /// Every entry put is either still in the cache, or evicted or removed exactly once.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+        const KEYS: usize = 256;
+
+        let lru = ConcurrentLru::with_shards(64, 8);
+        let taken = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut taken = Vec::new();
+            for i in 0..ITER {
+                let key = rng.gen_range(0..KEYS);
+                match rng.gen_range(0..4) {
+                    0 => lru.put(key, (t, i)),
+                    1 => taken.extend(lru.remove(&key)),
+                    2 => taken.extend(lru.pop_lru().map(|(_, value)| value)),
+                    _ => {
+                        let _ = lru.get(&key);
+                    }
+                }
+            }
+            taken
+        });
+        assert!(lru.len() <= 64);
+
+        let mut taken = taken.into_iter().flatten().collect::<Vec<_>>();
+        taken.extend(std::iter::from_fn(|| lru.pop_lru().map(|(_, value)| value)));
+        let len = taken.len();
+        taken.sort_unstable();
+        taken.dedup();
+        assert_eq!(taken.len(), len);
+    }
+
+    /// The keys and values are dropped exactly once, whether evicted, removed or left in the cache.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let lru = ConcurrentLru::new(4);
+        for i in 0..8 {
+            lru.put(i, Canary(&dropped));
+        }
+        assert_eq!(dropped.load(Relaxed), 4);
+        drop(lru.remove(&7));
+        assert_eq!(dropped.load(Relaxed), 6);
+        drop(lru);
+        assert_eq!(dropped.load(Relaxed), 9);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::ConcurrentLru;
+
+    /// An entry evicted concurrently with its removal is returned by exactly one of them, and a
+    /// concurrent `get` does not bring it back.
+    #[test]
+    fn evict_remove_sync() {
+        model(|| {
+            let lru = Arc::new(ConcurrentLru::with_shards(2, 2));
+            lru.put(1, 10);
+            lru.put(2, 20);
+            let th = {
+                let lru = lru.clone();
+                thread::spawn(move || lru.remove(&1))
+            };
+            let popped = lru.pop_lru();
+            let got = lru.get(&1);
+            let removed = th.join().unwrap();
+            match popped {
+                Some((1, 10)) => assert_eq!(removed, None),
+                Some((2, 20)) => assert_eq!(removed, Some(10)),
+                _ => panic!("{popped:?}"),
+            }
+            assert_eq!(got, None);
+            assert_eq!(lru.len(), 1 - usize::from(popped == Some((2, 20))));
+        })
+    }
+}
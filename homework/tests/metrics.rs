@@ -0,0 +1,228 @@
+// The metrics are read by the components of the hello server, which are not modeled with loom.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::hazard_pointer::{Shield, HAZARDS};
+use cs431_homework::hello_server::{Cache, ThreadPool};
+use cs431_homework::metrics::{HistogramValue, Kind, Registry, Value};
+use std::sync::Arc;
+use std::thread::scope;
+
+#[test]
+fn counter_gauge_histogram() {
+    let registry = Registry::new();
+    let counter = registry.counter("requests_total", "Requests.", &[("code", "200")]);
+    let gauge = registry.gauge("in_flight", "Requests in flight.", &[]);
+    let histogram = registry.histogram("latency_seconds", "Latency.", &[], &[0.1, 1.0]);
+
+    counter.add(2);
+    counter.increment();
+    gauge.increment();
+    gauge.add(-3);
+    histogram.observe(0.05);
+    histogram.observe(0.5);
+    histogram.observe(5.0);
+
+    let snapshot = registry.gather();
+    let names = snapshot
+        .families()
+        .iter()
+        .map(|family| (family.name, family.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            ("in_flight", Kind::Gauge),
+            ("latency_seconds", Kind::Histogram),
+            ("requests_total", Kind::Counter),
+        ]
+    );
+    assert_eq!(
+        snapshot.get("requests_total", &[("code", "200")]),
+        Some(&Value::Counter(3))
+    );
+    assert_eq!(snapshot.get("requests_total", &[]), None);
+    assert_eq!(snapshot.get("in_flight", &[]), Some(&Value::Gauge(-2)));
+    assert_eq!(
+        snapshot.get("latency_seconds", &[]),
+        Some(&Value::Histogram(HistogramValue {
+            buckets: vec![(0.1, 1), (1.0, 2), (f64::INFINITY, 3)],
+            sum: 5.55,
+            count: 3,
+        }))
+    );
+}
+
+/// A series is shared by those that register it, until it is dropped.
+#[test]
+fn shared_until_dropped() {
+    let registry = Registry::new();
+    let first = registry.counter("hits_total", "Hits.", &[("shard", "0")]);
+    let again = registry.counter("hits_total", "Hits.", &[("shard", "0")]);
+    let other = registry.counter("hits_total", "Hits.", &[("shard", "1")]);
+    assert!(Arc::ptr_eq(&first, &again));
+    assert!(!Arc::ptr_eq(&first, &other));
+
+    first.increment();
+    drop(first);
+    assert_eq!(
+        registry.gather().get("hits_total", &[("shard", "0")]),
+        Some(&Value::Counter(1))
+    );
+    drop(again);
+    let snapshot = registry.gather();
+    assert_eq!(snapshot.get("hits_total", &[("shard", "0")]), None);
+    assert_eq!(
+        snapshot.get("hits_total", &[("shard", "1")]),
+        Some(&Value::Counter(0))
+    );
+
+    // A new series starts over.
+    let first = registry.counter("hits_total", "Hits.", &[("shard", "0")]);
+    assert_eq!(first.get(), 0);
+    drop((first, other));
+    assert!(registry.gather().families().is_empty());
+}
+
+#[test]
+#[should_panic(expected = "another kind")]
+fn kind_mismatch() {
+    let registry = Registry::new();
+    let _counter = registry.counter("value", "A value.", &[]);
+    let _gauge = registry.gauge("value", "A value.", &[]);
+}
+
+/// A function series is read when gathered, and removed once it returns `None`.
+#[test]
+fn functions() {
+    let registry = Registry::new();
+    let source = Arc::new(7);
+    let weak = Arc::downgrade(&source);
+    registry.gauge_fn("answer", "The answer.", &[], move || {
+        weak.upgrade().map(|value| *value)
+    });
+    registry.counter_fn("constant_total", "Always 1.", &[], || Some(1));
+    let snapshot = registry.gather();
+    assert_eq!(snapshot.get("answer", &[]), Some(&Value::Gauge(7)));
+    assert_eq!(
+        snapshot.get("constant_total", &[]),
+        Some(&Value::Counter(1))
+    );
+
+    drop(source);
+    let snapshot = registry.gather();
+    assert_eq!(snapshot.get("answer", &[]), None);
+    assert_eq!(snapshot.families().len(), 1);
+}
+
+#[test]
+fn render() {
+    let registry = Registry::new();
+    let counter = registry.counter("hits_total", "Hits.", &[("path", "/a\"b")]);
+    let histogram = registry.histogram("size_bytes", "Size.", &[("kind", "x")], &[10.0]);
+    counter.increment();
+    histogram.observe(4.0);
+    histogram.observe(12.5);
+    assert_eq!(
+        registry.gather().to_string(),
+        "# HELP hits_total Hits.\n\
+         # TYPE hits_total counter\n\
+         hits_total{path=\"/a\\\"b\"} 1\n\
+         # HELP size_bytes Size.\n\
+         # TYPE size_bytes histogram\n\
+         size_bytes_bucket{kind=\"x\",le=\"10\"} 1\n\
+         size_bytes_bucket{kind=\"x\",le=\"+Inf\"} 2\n\
+         size_bytes_sum{kind=\"x\"} 16.5\n\
+         size_bytes_count{kind=\"x\"} 2\n"
+    );
+}
+
+/// No update from concurrent threads is lost.
+#[test]
+fn concurrent_updates() {
+    const THREADS: u64 = 8;
+    const ITER: u64 = 1024 * 16;
+
+    let registry = Registry::new();
+    let counter = registry.counter("ops_total", "Operations.", &[]);
+    let histogram = registry.histogram("op_seconds", "Operations.", &[], &[0.5]);
+    scope(|s| {
+        for _ in 0..THREADS {
+            let _ = s.spawn(|| {
+                let counter = registry.counter("ops_total", "Operations.", &[]);
+                let histogram = registry.histogram("op_seconds", "Operations.", &[], &[0.5]);
+                for i in 0..ITER {
+                    counter.increment();
+                    histogram.observe((i % 2) as f64);
+                }
+            });
+        }
+    });
+    assert_eq!(counter.get(), THREADS * ITER);
+    assert_eq!(
+        histogram.get(),
+        HistogramValue {
+            buckets: vec![(0.5, THREADS * ITER / 2), (f64::INFINITY, THREADS * ITER)],
+            sum: (THREADS * ITER / 2) as f64,
+            count: THREADS * ITER,
+        }
+    );
+}
+
+/// The thread pool, a cache and a hazard domain report into the same registry.
+#[test]
+fn components() {
+    let registry = Registry::new();
+
+    let pool = ThreadPool::new(2);
+    pool.register_metrics(&registry, "test");
+    pool.join();
+    let snapshot = registry.gather();
+    assert_eq!(
+        snapshot.get("hello_pool_queued_jobs", &[("pool", "test")]),
+        Some(&Value::Gauge(0))
+    );
+    assert_eq!(
+        snapshot.get("hello_pool_pending_jobs", &[("pool", "test")]),
+        Some(&Value::Gauge(0))
+    );
+
+    let cache = Arc::new(Cache::default());
+    cache.register_metrics(&registry, "squares");
+    assert_eq!(cache.get_or_insert_with(3, |x: usize| x * x), 9);
+    assert_eq!(cache.get_or_insert_with(3, |_| unreachable!()), 9);
+    assert_eq!(cache.get_or_insert_with(4, |x| x * x), 16);
+    let snapshot = registry.gather();
+    let lookups = |result| {
+        snapshot.get(
+            "hello_cache_lookups_total",
+            &[("cache", "squares"), ("result", result)],
+        )
+    };
+    assert_eq!(lookups("hit"), Some(&Value::Counter(1)));
+    assert_eq!(lookups("miss"), Some(&Value::Counter(2)));
+    assert_eq!(
+        snapshot.get("hello_cache_entries", &[("cache", "squares")]),
+        Some(&Value::Gauge(2))
+    );
+
+    HAZARDS.register_metrics(&registry, "global");
+    let shield = Shield::<usize>::default();
+    let shields = match registry
+        .gather()
+        .get("hazard_shields", &[("domain", "global")])
+    {
+        Some(&Value::Gauge(shields)) => shields,
+        value => panic!("unexpected {value:?}"),
+    };
+    assert!(shields >= 1);
+    drop(shield);
+
+    drop((pool, cache));
+    let snapshot = registry.gather();
+    let names = snapshot
+        .families()
+        .iter()
+        .map(|family| family.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["hazard_shields", "hazard_slots"]);
+}
@@ -1,14 +1,24 @@
 #[cfg(not(feature = "check-loom"))]
+#[cfg_attr(feature = "check-sched", allow(unused_imports))]
 pub use std::*;
 
 #[cfg(feature = "check-loom")]
 pub use loom::*;
 
-/// Run `f` with `loom::model` if compiled with `check-loom` feature.
+// The deterministic scheduler replaces the threads, the atomics and the locks, and keeps the rest
+// of `std`.
+#[cfg(feature = "check-sched")]
+#[allow(unused_imports)]
+pub use cs431_homework::sched::{sync, thread};
+
+/// Run `f` with `loom::model` if compiled with `check-loom` feature, or with `sched::model` if
+/// compiled with `check-sched` feature.
 pub fn model<F: Fn() + Sync + Send + 'static>(f: F) {
     cfg_if::cfg_if! {
         if #[cfg(feature = "check-loom")] {
             loom::model(f)
+        } else if #[cfg(feature = "check-sched")] {
+            cs431_homework::sched::model(f)
         } else {
             f()
         }
@@ -0,0 +1,168 @@
+mod canary;
+mod mock;
+mod threads;
+
+use cs431_homework::mpsc::{Link, Linked};
+
+/// Message embedding its link.
+#[derive(Debug, Default)]
+struct Message {
+    link: Link,
+    value: usize,
+}
+
+impl Message {
+    fn new(value: usize) -> Self {
+        Self {
+            link: Link::new(),
+            value,
+        }
+    }
+}
+
+unsafe impl Linked for Message {
+    fn link(&self) -> &Link {
+        &self.link
+    }
+}
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::threads::scope;
+    use super::Message;
+    use cs431_homework::mpsc::{self, Link, Linked};
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::sync::Arc;
+
+    #[test]
+    fn smoke() {
+        let (sender, mut receiver) = mpsc::queue();
+        assert!(receiver.is_empty());
+        assert!(receiver.pop().is_none());
+
+        let messages = (0..3)
+            .map(|i| Arc::new(Message::new(i)))
+            .collect::<Vec<_>>();
+        for message in &messages {
+            sender.push(message.clone()).unwrap();
+            assert!(message.link.is_queued());
+            assert!(!receiver.is_empty());
+        }
+        let popped = receiver.drain().map(|m| m.value).collect::<Vec<_>>();
+        assert_eq!(popped, [0, 1, 2]);
+        assert!(messages.iter().all(|message| !message.link.is_queued()));
+        assert!(receiver.is_empty());
+        assert!(receiver.pop().is_none());
+    }
+
+    /// A value is not linked twice, and is sent again once it is popped.
+    #[test]
+    fn resend() {
+        let (sender, mut receiver) = mpsc::queue();
+        let message = Arc::new(Message::new(7));
+        for _ in 0..3 {
+            sender.push(message.clone()).unwrap();
+            assert!(sender.push(message.clone()).is_err());
+            assert_eq!(receiver.pop().unwrap().value, 7);
+            assert!(receiver.pop().is_none());
+        }
+        assert_eq!(Arc::strong_count(&message), 1);
+    }
+
+    /// The values of each sender are popped in the order they are sent, each exactly once.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+
+        let (sender, mut receiver) = mpsc::queue();
+        let received = scope(|s| {
+            // Spawns all senders before receiving, so that they push concurrently.
+            #[allow(clippy::needless_collect)]
+            let senders = (0..THREADS)
+                .map(|t| {
+                    let sender = sender.clone();
+                    s.spawn(move || {
+                        for i in 0..ITER {
+                            sender.push(Arc::new(Message::new(t * ITER + i))).unwrap();
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+            let mut last = [None; THREADS];
+            let mut received = 0;
+            while received < THREADS * ITER {
+                for message in receiver.drain() {
+                    let (t, i) = (message.value / ITER, message.value % ITER);
+                    assert!(last[t] < Some(i));
+                    last[t] = Some(i);
+                    received += 1;
+                }
+            }
+            senders.into_iter().for_each(|sender| sender.join());
+            received
+        });
+        assert_eq!(received, THREADS * ITER);
+        assert!(receiver.pop().is_none());
+    }
+
+    /// The values left in the queue are dropped with it.
+    #[test]
+    fn drop_values() {
+        #[derive(Debug)]
+        struct Node<'a> {
+            link: Link,
+            _canary: Canary<'a>,
+        }
+
+        unsafe impl Linked for Node<'_> {
+            fn link(&self) -> &Link {
+                &self.link
+            }
+        }
+
+        let dropped = AtomicUsize::new(0);
+        let (sender, mut receiver) = mpsc::queue();
+        for _ in 0..10 {
+            let node = Node {
+                link: Link::new(),
+                _canary: Canary(&dropped),
+            };
+            sender.push(Arc::new(node)).unwrap();
+        }
+        drop(receiver.pop());
+        assert_eq!(dropped.load(Relaxed), 1);
+        drop(receiver);
+        assert_eq!(dropped.load(Relaxed), 1);
+        drop(sender);
+        assert_eq!(dropped.load(Relaxed), 10);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::thread;
+    use super::Message;
+    use cs431_homework::mpsc;
+    use std::sync::Arc;
+
+    /// The values of two senders are both popped, while the receiver pushes the stub behind the
+    /// last one.
+    #[test]
+    fn push_pop_sync() {
+        model(|| {
+            let (sender, mut receiver) = mpsc::queue();
+            let th = {
+                let sender = sender.clone();
+                thread::spawn(move || sender.push(Arc::new(Message::new(1))).unwrap())
+            };
+            sender.push(Arc::new(Message::new(0))).unwrap();
+            let mut popped = receiver.drain().map(|m| m.value).collect::<Vec<_>>();
+            th.join().unwrap();
+            popped.extend(receiver.drain().map(|m| m.value));
+            popped.sort_unstable();
+            assert_eq!(popped, [0, 1]);
+        })
+    }
+}
@@ -0,0 +1,75 @@
+#![cfg(all(feature = "numa", target_os = "linux"))]
+
+mod canary;
+
+use canary::Canary;
+use cs431_homework::numa::{current_node, node_count, set_current_node, NodeBox};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::thread;
+
+#[test]
+fn smoke() {
+    assert!(current_node() < node_count());
+    let mut value = NodeBox::new(String::from("a"));
+    value.push('b');
+    assert_eq!(*value, "ab");
+    assert_eq!(NodeBox::node(&value), current_node());
+    assert_eq!(format!("{value:?}"), r#""ab""#);
+
+    let slice = (0..100).collect::<NodeBox<[usize]>>();
+    assert_eq!(slice.iter().sum::<usize>(), 4950);
+    let empty = NodeBox::<[u64]>::from_fn(0, |_| unreachable!());
+    assert!(empty.is_empty());
+}
+
+/// A thread allocates on the node it is set to.
+#[test]
+fn nodes() {
+    let last = node_count() - 1;
+    thread::spawn(move || {
+        set_current_node(last);
+        let value = NodeBox::new(0u64);
+        assert_eq!(NodeBox::node(&value), last);
+        let other = NodeBox::new_on(0, [0u8; 3]);
+        assert_eq!(NodeBox::node(&other), 0);
+    })
+    .join()
+    .unwrap();
+}
+
+/// The values are dropped once, and the memory of a freed value is reused.
+#[test]
+fn drop_reuse() {
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+    let canaries = NodeBox::<[Canary]>::from_fn(10, |_| Canary(&DROPPED));
+    drop(canaries);
+    assert_eq!(DROPPED.load(Relaxed), 10);
+
+    let first = NodeBox::into_raw(NodeBox::new([7u64; 5]));
+    // SAFETY: `first` is returned by `into_raw` above.
+    drop(unsafe { NodeBox::from_raw(first) });
+    let second = NodeBox::new([8u64; 5]);
+    assert_eq!(&*second as *const _, first as *const _);
+    assert_eq!(*second, [8; 5]);
+}
+
+/// Values allocated and freed by many threads do not overlap.
+#[test]
+fn concurrent() {
+    thread::scope(|s| {
+        for t in 0..4 {
+            let _ = s.spawn(move || {
+                let mut boxes = Vec::new();
+                for i in 0..1024 {
+                    boxes.push(NodeBox::new([t, i]));
+                    if i % 3 == 0 {
+                        let _ = boxes.swap_remove(i % boxes.len());
+                    }
+                }
+                for b in &boxes {
+                    assert_eq!(b[0], t);
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,119 @@
+mod canary;
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use cs431_homework::oneshot::{channel, RecvError, TryRecvError};
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::thread::{scope, sleep};
+    use std::time::Duration;
+
+    #[test]
+    fn send_recv() {
+        let (sender, receiver) = channel();
+        assert!(!sender.is_abandoned());
+        assert_eq!(sender.send(1), Ok(()));
+        assert_eq!(receiver.recv(), Ok(1));
+
+        let (sender, mut receiver) = channel();
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(sender.send(2), Ok(()));
+        assert_eq!(receiver.try_recv(), Ok(2));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(receiver.recv(), Err(RecvError));
+    }
+
+    /// Dropping one half is signaled to the other.
+    #[test]
+    fn disconnect() {
+        let (sender, mut receiver) = channel::<i32>();
+        drop(sender);
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(receiver.recv(), Err(RecvError));
+
+        let (sender, receiver) = channel();
+        drop(receiver);
+        assert!(sender.is_abandoned());
+        assert_eq!(sender.send(1), Err(1));
+    }
+
+    /// `recv` blocks until the value is sent, or until the sender is dropped.
+    #[test]
+    fn recv_blocks() {
+        for send in [true, false] {
+            let (sender, receiver) = channel();
+            scope(|s| {
+                let _ = s.spawn(move || {
+                    sleep(Duration::from_millis(100));
+                    if send {
+                        sender.send(1).unwrap();
+                    }
+                });
+                let expected = if send { Ok(1) } else { Err(RecvError) };
+                assert_eq!(receiver.recv(), expected);
+            });
+        }
+    }
+
+    /// The value is dropped exactly once, whether received, left in the channel, or returned by
+    /// `send`.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let (sender, receiver) = channel();
+        assert!(sender.send(Canary(&dropped)).is_ok());
+        drop(receiver.recv());
+        assert_eq!(dropped.load(Relaxed), 1);
+
+        let (sender, receiver) = channel();
+        assert!(sender.send(Canary(&dropped)).is_ok());
+        assert_eq!(dropped.load(Relaxed), 1);
+        drop(receiver);
+        assert_eq!(dropped.load(Relaxed), 2);
+
+        let (sender, receiver) = channel();
+        drop(receiver);
+        drop(sender.send(Canary(&dropped)));
+        assert_eq!(dropped.load(Relaxed), 3);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::thread;
+    use cs431_homework::oneshot::{channel, RecvError};
+
+    /// The value sent is received, whether the receiver parks before it is sent or not.
+    #[test]
+    fn send_recv_sync() {
+        model(|| {
+            let (sender, receiver) = channel();
+            let th = thread::spawn(move || sender.send(1).unwrap());
+            assert_eq!(receiver.recv(), Ok(1));
+            th.join().unwrap();
+        })
+    }
+
+    /// The receiver is woken up when the sender is dropped without sending.
+    #[test]
+    fn drop_sender_sync() {
+        model(|| {
+            let (sender, receiver) = channel::<usize>();
+            let th = thread::spawn(move || drop(sender));
+            assert_eq!(receiver.recv(), Err(RecvError));
+            th.join().unwrap();
+        })
+    }
+
+    /// A value sent while the receiver is dropped is either dropped with the channel or returned.
+    #[test]
+    fn drop_receiver_sync() {
+        model(|| {
+            let (sender, receiver) = channel();
+            let th = thread::spawn(move || drop(receiver));
+            let _ = sender.send(Box::new(1));
+            th.join().unwrap();
+        })
+    }
+}
@@ -0,0 +1,88 @@
+// The parker falls back to the mutex and the condition variable of loom with `check-loom`, so these
+// run only without it.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::Parker;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{scope, sleep};
+use std::time::{Duration, Instant};
+
+#[test]
+fn token() {
+    let parker = Parker::new();
+    // An unpark before the park is not lost.
+    parker.unparker().unpark();
+    parker.park();
+
+    // The tokens do not add up.
+    parker.unparker().unpark();
+    parker.unparker().unpark();
+    parker.park();
+    let start = Instant::now();
+    parker.park_timeout(Duration::from_millis(50));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn deadline() {
+    let parker = Parker::new();
+    let deadline = Instant::now() + Duration::from_millis(50);
+    // Parking may return spuriously, but not before the deadline without a token.
+    while Instant::now() < deadline {
+        parker.park_deadline(deadline);
+    }
+    // A deadline in the past returns at once.
+    parker.park_deadline(deadline);
+}
+
+/// An unpark from another thread wakes up the parked thread, which sees the writes before it.
+#[test]
+fn unpark() {
+    let parker = Parker::new();
+    let unparker = parker.unparker().clone();
+    assert!(unparker.ptr_eq(parker.unparker()));
+    assert!(!unparker.ptr_eq(Parker::new().unparker()));
+
+    let ready = AtomicBool::new(false);
+    scope(|s| {
+        let _ = s.spawn(|| {
+            sleep(Duration::from_millis(50));
+            ready.store(true, Ordering::Relaxed);
+            unparker.unpark();
+        });
+        while !ready.load(Ordering::Relaxed) {
+            parker.park();
+        }
+    });
+}
+
+/// Each round, the main thread unparks the worker, which unparks it back, so that a lost unpark
+/// would hang the test.
+#[test]
+fn ping_pong() {
+    const ROUNDS: usize = 1024 * 4;
+
+    let (ping, pong) = (Parker::new(), Parker::new());
+    let (pinged, ponged) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    scope(|s| {
+        let ping_unparker = ping.unparker().clone();
+        let pong_unparker = pong.unparker().clone();
+        let (pinged, ponged) = (&pinged, &ponged);
+        let _ = s.spawn(move || {
+            for round in 1..=ROUNDS {
+                while pinged.load(Ordering::Acquire) < round {
+                    pong.park();
+                }
+                ponged.store(round, Ordering::Release);
+                ping_unparker.unpark();
+            }
+        });
+        for round in 1..=ROUNDS {
+            pinged.store(round, Ordering::Release);
+            pong_unparker.unpark();
+            while ponged.load(Ordering::Acquire) < round {
+                ping.park();
+            }
+        }
+    });
+}
@@ -0,0 +1,219 @@
+mod canary;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::threads::scope;
+    use cs431_homework::persistent::{List, Map, Published};
+    use rand::prelude::*;
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn list() {
+        let empty = List::new();
+        let one = empty.push_front(1);
+        let two = one.push_front(2);
+        let other = one.push_front(3);
+        assert!(empty.is_empty());
+        assert_eq!(two.len(), 2);
+        assert_eq!(two.front(), Some(&2));
+        assert_eq!(two.iter().copied().collect::<Vec<_>>(), [2, 1]);
+        assert_eq!(format!("{other:?}"), "[3, 1]");
+        assert!(two.tail().unwrap().ptr_eq(&one));
+        assert!(other.tail().unwrap().ptr_eq(&one));
+        assert_eq!(one.tail(), Some(empty.clone()));
+        assert_eq!(empty.tail(), None);
+        assert_eq!(
+            (1..4).collect::<List<_>>(),
+            List::new().push_front(3).push_front(2).push_front(1)
+        );
+    }
+
+    /// A long list is dropped without overflowing the stack, but not the tail another list shares.
+    #[test]
+    fn list_drop_long() {
+        const LEN: usize = 1024 * 1024;
+
+        let shared = (0..LEN).collect::<List<_>>();
+        let list = (0..LEN).fold(shared.clone(), |list, i| list.push_front(i));
+        drop(list);
+        assert_eq!(shared.len(), LEN);
+        assert_eq!(shared.iter().nth(LEN - 1), Some(&(LEN - 1)));
+    }
+
+    #[test]
+    fn map() {
+        let empty = Map::new();
+        let one = empty.insert("a", 1);
+        let two = one.insert("b", 2);
+        let replaced = two.insert("a", 3);
+        assert!(empty.is_empty());
+        assert_eq!(one.get("a"), Some(&1));
+        assert_eq!(two.len(), 2);
+        assert_eq!(replaced.get("a"), Some(&3));
+        assert_eq!(replaced.len(), 2);
+        assert_eq!(two.get("a"), Some(&1));
+        let removed = replaced.remove("b");
+        assert!(!removed.contains_key("b"));
+        assert!(replaced.contains_key("b"));
+        assert!(removed.remove("b").ptr_eq(&removed));
+        assert_eq!(format!("{removed:?}"), r#"{"a": 3}"#);
+    }
+
+    /// Random operations agree with `HashMap`, and every version keeps its entries.
+    #[test]
+    fn map_sequential() {
+        const ITER: usize = 1024 * 16;
+
+        let mut map = Map::new();
+        let mut reference = HashMap::new();
+        let mut versions = Vec::new();
+        let mut rng = thread_rng();
+        for i in 0..ITER {
+            let key = rng.gen_range(0..1024);
+            if rng.gen() {
+                map = map.insert(key, i);
+                let _ = reference.insert(key, i);
+            } else {
+                map = map.remove(&key);
+                let _ = reference.remove(&key);
+            }
+            assert_eq!(map.len(), reference.len());
+            if i % 1024 == 0 {
+                versions.push((map.clone(), reference.clone()));
+            }
+        }
+        versions.push((map, reference));
+        for (map, reference) in versions {
+            let mut entries = map.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+            let mut expected = reference.into_iter().collect::<Vec<_>>();
+            entries.sort_unstable();
+            expected.sort_unstable();
+            assert_eq!(entries, expected);
+        }
+    }
+
+    /// Hashes equal in all levels, so that the keys end up in a single leaf.
+    #[derive(Debug, Clone, Copy, Eq)]
+    struct Collide(u32);
+
+    impl PartialEq for Collide {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+
+    impl Hash for Collide {
+        fn hash<H: Hasher>(&self, _: &mut H) {}
+    }
+
+    #[test]
+    fn map_collisions() {
+        let mut map = (0..8).map(|i| (Collide(i), i)).collect::<Map<_, _>>();
+        map = map.insert(Collide(3), 33);
+        assert_eq!(map.get(&Collide(3)), Some(&33));
+        assert_eq!(map.len(), 8);
+        for i in 0..8 {
+            map = map.remove(&Collide(i));
+            assert!(!map.contains_key(&Collide(i)));
+            assert_eq!(map.len(), 7 - i as usize);
+        }
+        assert!(map.is_empty());
+    }
+
+    /// A value is dropped with the last version that has it.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let map = (0..100)
+            .map(|i| (i, Canary(&dropped)))
+            .collect::<Map<_, _>>();
+        assert_eq!(dropped.load(Relaxed), 0);
+        let removed = (0..50).fold(map.clone(), |map, i| map.remove(&i));
+        drop(map);
+        assert_eq!(dropped.load(Relaxed), 50);
+        drop(removed);
+        assert_eq!(dropped.load(Relaxed), 100);
+
+        let list = (0..100).map(|_| Canary(&dropped)).collect::<List<_>>();
+        let tail = list.tail().unwrap();
+        drop(list);
+        assert_eq!(dropped.load(Relaxed), 101);
+        drop(tail);
+        assert_eq!(dropped.load(Relaxed), 200);
+    }
+
+    /// Updates of a published map are not lost, and readers see the versions in order, each of
+    /// them whole.
+    #[test]
+    fn published() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024;
+
+        let published = Published::new(Map::new());
+        let done = AtomicBool::new(false);
+        scope(|s| {
+            let _ = s.spawn(|| {
+                let mut last = 0;
+                while !done.load(Relaxed) {
+                    let map = published.load();
+                    // Each thread inserts its keys in order.
+                    for t in 0..THREADS {
+                        let count = map.iter().filter(|(key, _)| *key % THREADS == t).count();
+                        assert!((0..count).all(|i| map.contains_key(&(i * THREADS + t))));
+                    }
+                    assert!(map.len() >= last);
+                    last = map.len();
+                }
+            });
+            s.run(THREADS, |t| {
+                for i in 0..ITER {
+                    let key = i * THREADS + t;
+                    let map = published.update(|map| map.insert(key, t));
+                    assert_eq!(map.get(&key), Some(&t));
+                }
+            });
+            done.store(true, Relaxed);
+        });
+        assert_eq!(published.load().len(), THREADS * ITER);
+
+        published.store(Map::new());
+        assert!(published.load().is_empty());
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::ebr::collect;
+    use cs431_homework::persistent::{Map, Published};
+
+    /// A reader keeps the version it loaded whole while a new one is published, and the old
+    /// version is freed once neither the reader nor the holder has it.
+    #[test]
+    fn publish_sync() {
+        model(|| {
+            let published = Arc::new(Published::new(Map::new().insert(0, 0)));
+            let th = {
+                let published = published.clone();
+                thread::spawn(move || {
+                    let map = published.load();
+                    collect();
+                    match map.len() {
+                        1 => assert_eq!(map.get(&0), Some(&0)),
+                        _ => assert_eq!(map.get(&1), Some(&1)),
+                    }
+                })
+            };
+            let _ = published.update(|map| map.insert(1, 1));
+            collect();
+            th.join().unwrap();
+            assert_eq!(published.load().len(), 2);
+        })
+    }
+}
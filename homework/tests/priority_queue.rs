@@ -0,0 +1,151 @@
+mod canary;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::threads::scope;
+    use cs431_homework::PriorityQueue;
+    use rand::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn smoke() {
+        let queue = PriorityQueue::new();
+        assert!(queue.is_empty());
+        assert_eq!(queue.try_pop_min(), None);
+        for (priority, item) in [(3, "c"), (1, "a"), (2, "b"), (1, "a2"), (3, "c2")] {
+            queue.push(priority, item);
+        }
+        assert!(!queue.is_empty());
+        let mut popped = Vec::new();
+        while let Some(entry) = queue.try_pop_min() {
+            popped.push(entry);
+        }
+        assert_eq!(popped, [(1, "a"), (1, "a2"), (2, "b"), (3, "c"), (3, "c2")]);
+        assert!(queue.is_empty());
+    }
+
+    /// Each item is popped exactly once, and the items of a producer with equal priorities are
+    /// popped in the order they are pushed.
+    #[test]
+    fn stress() {
+        const PRODUCERS: usize = 4;
+        const CONSUMERS: usize = 4;
+        const ITEMS: usize = 1024 * 8;
+
+        let queue = PriorityQueue::new();
+        let popped = AtomicUsize::new(0);
+        let items = scope(|s| {
+            for t in 0..PRODUCERS {
+                let queue = &queue;
+                let _ = s.spawn(move || {
+                    let mut rng = thread_rng();
+                    for i in 0..ITEMS {
+                        queue.push(rng.gen_range(0..16), (t, i));
+                    }
+                });
+            }
+            s.run(CONSUMERS, |_| {
+                let mut items = Vec::new();
+                while popped.load(Relaxed) < PRODUCERS * ITEMS {
+                    if let Some(entry) = queue.try_pop_min() {
+                        let _ = popped.fetch_add(1, Relaxed);
+                        items.push(entry);
+                    }
+                }
+                items
+            })
+        });
+
+        let mut seen = vec![vec![false; ITEMS]; PRODUCERS];
+        // A pop may walk past the position of an item just before it is pushed, and take a later
+        // item of the same producer, so the items are checked only for being popped exactly once.
+        for &(_, (t, i)) in items.iter().flatten() {
+            assert!(!seen[t][i]);
+            seen[t][i] = true;
+        }
+        assert!(seen.iter().flatten().all(|&seen| seen));
+        assert!(queue.is_empty());
+    }
+
+    /// Once the pushes are done, the items are popped in the order of their priorities.
+    #[test]
+    fn quiescent_order() {
+        const THREADS: usize = 4;
+        const ITEMS: usize = 1024 * 4;
+
+        let queue = PriorityQueue::new();
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    let mut rng = thread_rng();
+                    for _ in 0..ITEMS {
+                        queue.push(rng.gen::<u32>(), ());
+                    }
+                });
+            }
+        });
+        let mut last = 0;
+        for _ in 0..THREADS * ITEMS {
+            let (priority, ()) = queue.try_pop_min().unwrap();
+            assert!(last <= priority);
+            last = priority;
+        }
+        assert_eq!(queue.try_pop_min(), None);
+    }
+
+    /// Items are dropped exactly once, whether popped or left in the queue.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let queue = PriorityQueue::new();
+        for i in 0..100 {
+            queue.push(i % 10, Canary(&dropped));
+        }
+        for _ in 0..50 {
+            drop(queue.try_pop_min().unwrap());
+        }
+        // A popped item is moved out of its node, which is reclaimed later.
+        assert_eq!(dropped.load(Relaxed), 50);
+        drop(queue);
+        assert_eq!(dropped.load(Relaxed), 100);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::PriorityQueue;
+
+    /// Two threads racing to pop the minimum take different items, even if one of them is pushed
+    /// meanwhile.
+    ///
+    /// Each execution is long, and the full model is too large to explore. Run with
+    /// `LOOM_MAX_PREEMPTIONS=1 LOOM_MAX_BRANCHES=2000`.
+    #[test]
+    fn push_pop_sync() {
+        model(|| {
+            let queue = Arc::new(PriorityQueue::new());
+            queue.push(1, 1);
+            let th = {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let popped = queue.try_pop_min();
+                    collect();
+                    popped
+                })
+            };
+            queue.push(0, 0);
+            let mine = queue.try_pop_min().unwrap();
+            collect();
+            let theirs = th.join().unwrap().unwrap();
+            assert_ne!(mine, theirs);
+            assert!(mine.0 + theirs.0 == 1);
+            assert_eq!(queue.try_pop_min(), None);
+        })
+    }
+}
@@ -0,0 +1,214 @@
+mod canary;
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use core::ptr;
+    use cs431_homework::hello_server::ThreadPool;
+    use cs431_homework::qsbr::{collect, offline_scope, quiescent_state, read_lock, retire};
+    use cs431_homework::reclaim::Qsbr;
+    use cs431_homework::AtomicArc;
+    use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering::*};
+    use std::sync::{Arc, Barrier};
+    use std::thread::scope;
+
+    /// Announces quiescent states until `dropped` reaches `count`.
+    fn wait_dropped(dropped: &AtomicUsize, count: usize) {
+        // Other tests may be online meanwhile, which only delays the reclamation.
+        while dropped.load(Relaxed) < count {
+            quiescent_state();
+            collect();
+        }
+        assert_eq!(dropped.load(Relaxed), count);
+    }
+
+    /// A retired pointer is not freed until the retiring thread announces a quiescent state.
+    #[test]
+    fn retire_quiescent() {
+        let dropped = AtomicUsize::new(0);
+        let atomic = AtomicPtr::new(Box::into_raw(Box::new(Canary(&dropped))));
+
+        let guard = read_lock();
+        let local = atomic.swap(ptr::null_mut(), AcqRel);
+        unsafe { retire(local) };
+        drop(guard);
+        for _ in 0..16 {
+            collect();
+        }
+        assert_eq!(dropped.load(Relaxed), 0);
+
+        wait_dropped(&dropped, 1);
+    }
+
+    /// A retired pointer is not freed while another online thread may have loaded it.
+    #[test]
+    fn online_blocks_collect() {
+        let dropped = AtomicUsize::new(0);
+        let atomic = AtomicPtr::new(Box::into_raw(Box::new(Canary(&dropped))));
+        let loaded = Barrier::new(2);
+        let retired = Barrier::new(2);
+
+        scope(|s| {
+            let _ = s.spawn(|| {
+                let guard = read_lock();
+                let local = atomic.load(Acquire);
+                let _ = loaded.wait();
+                let _ = retired.wait();
+                assert_eq!(unsafe { (*local).0.load(Relaxed) }, 0);
+                drop(guard);
+                quiescent_state();
+            });
+
+            let _ = loaded.wait();
+            let local = atomic.swap(ptr::null_mut(), AcqRel);
+            unsafe { retire(local) };
+            for _ in 0..16 {
+                quiescent_state();
+                collect();
+            }
+            assert_eq!(dropped.load(Relaxed), 0);
+            let _ = retired.wait();
+        });
+
+        wait_dropped(&dropped, 1);
+    }
+
+    /// An offline thread does not hold back the reclamation, and a guard brings it online.
+    #[test]
+    fn offline_scope_does_not_block() {
+        let dropped = AtomicUsize::new(0);
+        let registered = Barrier::new(2);
+        let freed = Barrier::new(2);
+
+        scope(|s| {
+            let _ = s.spawn(|| {
+                let offline = offline_scope();
+                let _ = registered.wait();
+                let _ = freed.wait();
+                // Reading while offline goes online for the guard.
+                let guard = read_lock();
+                drop(guard);
+                drop(offline);
+            });
+
+            let _ = registered.wait();
+            unsafe { retire(Box::into_raw(Box::new(Canary(&dropped)))) };
+            wait_dropped(&dropped, 1);
+            let _ = freed.wait();
+        });
+    }
+
+    /// Announcing a quiescent state while reading is a bug.
+    #[test]
+    #[should_panic(expected = "quiescent state announced while reading")]
+    fn quiescent_while_reading() {
+        let _guard = read_lock();
+        quiescent_state();
+    }
+
+    /// Updates are not lost, and every replaced version is freed.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+
+        // The retired versions may outlive the test, so the count does too.
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        let created = AtomicUsize::new(1);
+        let cell = AtomicArc::<_, Qsbr>::new(Arc::new((0, Canary(&DROPPED))));
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    for _ in 0..ITER {
+                        // A version may be created and dropped by a failed attempt.
+                        let _ = cell.update(|v| {
+                            let _ = created.fetch_add(1, Relaxed);
+                            Arc::new((v.0 + 1, Canary(&DROPPED)))
+                        });
+                        quiescent_state();
+                    }
+                    collect();
+                });
+            }
+        });
+        assert_eq!(cell.load().0, THREADS * ITER);
+
+        drop(cell);
+        wait_dropped(&DROPPED, created.load(Relaxed));
+    }
+
+    /// The workers of a thread pool announce a quiescent state after each job, and do not hold
+    /// back the reclamation while idle.
+    #[test]
+    fn thread_pool() {
+        const JOBS: usize = 64;
+
+        // The jobs outlive the test, so the count does too.
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        let pool = ThreadPool::new(2);
+        let cell = Arc::new(AtomicArc::<_, Qsbr>::new(Arc::new(0)));
+        for _ in 0..JOBS {
+            let cell = cell.clone();
+            pool.execute(move || {
+                let _ = cell.update(|v| Arc::new(**v + 1));
+                unsafe { retire(Box::into_raw(Box::new(Canary(&DROPPED)))) };
+                collect();
+            });
+        }
+        pool.join();
+        assert_eq!(*cell.load(), JOBS);
+
+        // The workers are idle, and offline, so they do not hold back this thread's pointers.
+        let dropped = AtomicUsize::new(0);
+        unsafe { retire(Box::into_raw(Box::new(Canary(&dropped)))) };
+        wait_dropped(&dropped, 1);
+
+        // A worker frees its own batches only once it runs again, or leaves them when it exits.
+        drop(pool);
+        wait_dropped(&DROPPED, JOBS);
+    }
+}
+
+mod sync {
+    use super::mock::model;
+    use super::mock::sync::atomic::{AtomicPtr, Ordering::*};
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use core::ptr;
+    use cs431_homework::qsbr::*;
+
+    #[test]
+    fn read_quiescent_sync() {
+        model(|| {
+            let atomic = Arc::new(AtomicPtr::new(ptr::null_mut::<usize>()));
+
+            let th = {
+                let atomic = atomic.clone();
+                thread::spawn(move || {
+                    let guard = read_lock();
+                    let local = atomic.load(Acquire);
+                    if !local.is_null() {
+                        // safe to deref a pointer loaded while online
+                        assert_eq!(unsafe { *local }, 123);
+                    }
+                    drop(guard);
+                    quiescent_state();
+                })
+            };
+
+            // link
+            let local = Box::into_raw(Box::new(123));
+            atomic.store(local, Release);
+
+            // unlink, retire, and free it if the reader is done with it
+            atomic.store(ptr::null_mut(), Relaxed);
+            unsafe { retire(local) };
+            collect();
+            quiescent_state();
+            collect();
+
+            th.join().unwrap();
+        })
+    }
+}
@@ -0,0 +1,178 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable, QueueOp, QueueSpec, Recorder};
+    use super::threads::{run, scope};
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::Queue;
+    use rand::prelude::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    #[test]
+    fn push_pop() {
+        let queue = Queue::default();
+        assert!(queue.is_empty());
+        assert_eq!(queue.try_pop(), Ok(None));
+
+        for i in 0..10 {
+            queue.push(i);
+        }
+        assert!(!queue.is_empty());
+        for i in 0..10 {
+            assert_eq!(queue.pop(), i);
+        }
+        assert!(queue.is_empty());
+        assert_eq!(queue.try_pop(), Ok(None));
+    }
+
+    /// Every pushed value is popped exactly once, in the order of each producer.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+
+        let queue = Queue::default();
+        let popped = scope(|s| {
+            for t in 0..THREADS {
+                let queue = &queue;
+                let _ = s.spawn(move || {
+                    for i in 0..ITER {
+                        queue.push((t, i));
+                        collect();
+                    }
+                });
+            }
+            s.run(THREADS, |_| {
+                let mut last = [None; THREADS];
+                for _ in 0..ITER {
+                    let (t, i) = queue.pop();
+                    assert!(last[t] < Some(i));
+                    last[t] = Some(i);
+                    collect();
+                }
+                last
+            })
+        });
+
+        assert!(queue.is_empty());
+        let last = (0..THREADS)
+            .map(|t| popped.iter().filter_map(|last| last[t]).max())
+            .collect::<HashSet<_>>();
+        assert_eq!(last, HashSet::from([Some(ITER - 1)]));
+    }
+
+    /// `pop` blocks until a value is pushed, even after it parks.
+    #[test]
+    fn pop_blocks() {
+        let queue = Queue::default();
+        scope(|s| {
+            let handle = s.spawn(|| queue.pop());
+            sleep(Duration::from_millis(100));
+            assert!(!handle.is_finished());
+            queue.push(1);
+            assert_eq!(handle.join(), 1);
+        });
+    }
+
+    /// Values left in the queue are dropped with it, and popped values are not dropped again.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let queue = Queue::default();
+        for _ in 0..10 {
+            queue.push(Canary(&dropped));
+        }
+        drop(queue.pop());
+        assert_eq!(dropped.load(Relaxed), 1);
+        drop(queue);
+        assert_eq!(dropped.load(Relaxed), 10);
+    }
+
+    /// Histories of random operations are linearizable.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 3;
+        const ITER: usize = 256;
+
+        let queue = Queue::default();
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let op = if rng.gen() {
+                    QueueOp::Push(t * ITER + i)
+                } else {
+                    QueueOp::Pop
+                };
+                recorder.record(&mut history, op, |op| match *op {
+                    QueueOp::Push(value) => {
+                        queue.push(value);
+                        None
+                    }
+                    QueueOp::Pop => loop {
+                        if let Ok(value) = queue.try_pop() {
+                            break value;
+                        }
+                    },
+                });
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable(&QueueSpec::default(), &history);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::Queue;
+
+    /// value pushed → value popped → value seen, and a blocked pop is woken up by the push.
+    #[test]
+    fn push_pop_sync() {
+        model(|| {
+            let queue = Arc::new(Queue::default());
+            let th = {
+                let queue = queue.clone();
+                thread::spawn(move || queue.push(Box::new(123)))
+            };
+            assert_eq!(*queue.pop(), 123);
+            th.join().unwrap();
+        })
+    }
+
+    /// The node freed by a popping thread is not accessed by a thread reading the queue.
+    #[test]
+    fn pop_read_sync() {
+        model(|| {
+            let queue = Arc::new(Queue::default());
+            queue.push(1);
+            let th = {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let value = queue.try_pop();
+                    collect();
+                    value
+                })
+            };
+            let _ = queue.is_empty();
+            collect();
+            assert_eq!(th.join().unwrap(), Ok(Some(1)));
+            assert!(queue.is_empty());
+        })
+    }
+}
@@ -0,0 +1,248 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable_by, MapOp, MapRet, MapSpec, Recorder};
+    use super::threads::{run, scope};
+    use cs431_homework::RadixTree;
+    use rand::prelude::*;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    fn collect(tree: &RadixTree<usize>, prefix: &[u8]) -> Vec<(Vec<u8>, usize)> {
+        let mut entries = Vec::new();
+        tree.prefix(prefix, |key, value| entries.push((key.to_vec(), *value)));
+        entries
+    }
+
+    fn longest_prefix(tree: &RadixTree<usize>, key: &[u8]) -> Option<(usize, usize)> {
+        tree.longest_prefix(key, |found| found.map(|(len, value)| (len, *value)))
+    }
+
+    #[test]
+    fn smoke() {
+        let tree = RadixTree::new();
+        assert!(tree.is_empty());
+        for (i, key) in [
+            "romane", "romanus", "romulus", "rubens", "ruber", "rubicon", "rom",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            assert_eq!(tree.insert(key.as_bytes(), i), Ok(()));
+        }
+        assert_eq!(tree.insert(b"romulus", 0), Err(0));
+        assert!(tree.contains_key(b"rom"));
+        assert!(!tree.contains_key(b"roma"));
+        assert!(!tree.contains_key(b"r"));
+        assert_eq!(tree.lookup(b"ruber", |value| value.copied()), Some(4));
+        assert_eq!(tree.remove(b"romanus"), Some(1));
+        assert_eq!(tree.remove(b"romanus"), None);
+        assert_eq!(tree.remove(b"roman"), None);
+
+        assert_eq!(
+            collect(&tree, b"rom"),
+            [
+                (b"rom".to_vec(), 6),
+                (b"romane".to_vec(), 0),
+                (b"romulus".to_vec(), 2),
+            ]
+        );
+        // The prefix may end in the middle of a label.
+        assert_eq!(
+            collect(&tree, b"rube"),
+            [(b"rubens".to_vec(), 3), (b"ruber".to_vec(), 4)]
+        );
+        assert_eq!(collect(&tree, b"rx"), []);
+        assert_eq!(collect(&tree, b"").len(), 6);
+
+        assert_eq!(longest_prefix(&tree, b"romanesque"), Some((6, 0)));
+        assert_eq!(longest_prefix(&tree, b"romanus"), Some((3, 6)));
+        assert_eq!(longest_prefix(&tree, b"ro"), None);
+
+        // The empty key is a key like the others.
+        assert_eq!(tree.insert(b"", 7), Ok(()));
+        assert_eq!(longest_prefix(&tree, b"ro"), Some((0, 7)));
+
+        for key in ["", "romane", "romulus", "rubens", "ruber", "rubicon", "rom"] {
+            assert!(tree.remove(key.as_bytes()).is_some());
+        }
+        assert!(tree.is_empty());
+    }
+
+    /// Random operations, including prefix queries, agree with `BTreeMap`.
+    #[test]
+    fn sequential() {
+        const ITER: usize = 1024 * 16;
+
+        let tree = RadixTree::new();
+        let mut reference = BTreeMap::new();
+        let mut rng = thread_rng();
+        for _ in 0..ITER {
+            // Short keys of few letters, which share prefixes.
+            let len = rng.gen_range(0..6);
+            let key = (0..len)
+                .map(|_| b"abc"[rng.gen_range(0..3)])
+                .collect::<Vec<_>>();
+            match rng.gen_range(0..5) {
+                0 | 1 => assert_eq!(
+                    tree.insert(&key, key.len()).is_ok(),
+                    reference.insert(key.clone(), key.len()).is_none()
+                ),
+                2 => assert_eq!(tree.remove(&key), reference.remove(&key)),
+                3 => assert_eq!(
+                    longest_prefix(&tree, &key),
+                    (0..=key.len())
+                        .rev()
+                        .find_map(|len| reference.get(&key[..len]).map(|value| (len, *value)))
+                ),
+                _ => assert!(collect(&tree, &key).into_iter().eq(reference
+                    .range(key.clone()..)
+                    .take_while(|(k, _)| k.starts_with(&key))
+                    .map(|(k, v)| (k.clone(), *v)))),
+            }
+        }
+        assert!(collect(&tree, b"").into_iter().eq(reference.into_iter()));
+    }
+
+    /// Each thread owns the keys of its suffix, so it knows exactly which of them are in the tree,
+    /// while the others insert and remove around them and the nodes split.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+        const KEYS: usize = 256;
+
+        let tree = RadixTree::new();
+        scope(|s| {
+            for t in 0..THREADS {
+                let tree = &tree;
+                let _ = s.spawn(move || {
+                    let mut rng = thread_rng();
+                    let mut mine = vec![false; KEYS];
+                    for _ in 0..ITER {
+                        let index = rng.gen_range(0..KEYS);
+                        let key = format!("{index:x}/{t}");
+                        if rng.gen() {
+                            assert_eq!(tree.insert(key.as_bytes(), t).is_ok(), !mine[index]);
+                            mine[index] = true;
+                        } else {
+                            assert_eq!(tree.remove(key.as_bytes()).is_some(), mine[index]);
+                            mine[index] = false;
+                        }
+
+                        // A prefix query sees the keys in order, and sees exactly the keys of this
+                        // thread that are in the tree.
+                        let prefix = format!("{:x}", index / 16);
+                        let mut last = None;
+                        let mut seen = 0;
+                        tree.prefix(prefix.as_bytes(), |key, value| {
+                            assert!(last.as_deref() < Some(key));
+                            last = Some(key.to_vec());
+                            if *value == t {
+                                let key = std::str::from_utf8(key).unwrap();
+                                let index = key.split('/').next().unwrap();
+                                assert!(mine[usize::from_str_radix(index, 16).unwrap()]);
+                                seen += 1;
+                            }
+                        });
+                        let expected = (0..KEYS)
+                            .filter(|i| mine[*i] && format!("{i:x}").starts_with(&prefix))
+                            .count();
+                        assert_eq!(seen, expected);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Values are dropped exactly once, whether removed or left in the tree.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let tree = RadixTree::new();
+        for i in 0..100 {
+            assert!(tree
+                .insert(i.to_string().as_bytes(), Canary(&dropped))
+                .is_ok());
+        }
+        for i in 0..50 {
+            drop(tree.remove(i.to_string().as_bytes()));
+        }
+        assert_eq!(dropped.load(Relaxed), 50);
+        drop(tree);
+        assert_eq!(dropped.load(Relaxed), 100);
+    }
+
+    /// Histories of random operations on a few keys that share prefixes are linearizable.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024;
+        const KEYS: [&[u8]; 6] = [b"", b"a", b"ab", b"abc", b"abd", b"b"];
+
+        let tree = RadixTree::new();
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let key = rng.gen_range(0..KEYS.len());
+                let op = match rng.gen_range(0..3) {
+                    0 => MapOp::Insert(key, t * ITER + i),
+                    1 => MapOp::Remove(key),
+                    _ => MapOp::Lookup(key),
+                };
+                recorder.record(&mut history, op, |op| match *op {
+                    MapOp::Insert(key, value) => {
+                        MapRet::Done(tree.insert(KEYS[key], value).is_ok())
+                    }
+                    MapOp::Remove(key) => MapRet::Done(tree.remove(KEYS[key]).is_some()),
+                    MapOp::Lookup(key) => {
+                        MapRet::Value(tree.lookup(KEYS[key], |value| value.copied()))
+                    }
+                    MapOp::Upsert(..) => unreachable!(),
+                });
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable_by(&MapSpec::default(), &history, |op| *op.key());
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::RadixTree;
+
+    /// An insertion that splits a node does not lose the key of a concurrent insertion below it,
+    /// nor hide the keys from a concurrent lookup or prefix query.
+    #[test]
+    fn split_sync() {
+        model(|| {
+            let tree = Arc::new(RadixTree::new());
+            tree.insert(b"abc", ()).unwrap();
+            let th = {
+                let tree = tree.clone();
+                thread::spawn(move || tree.insert(b"abcd", ()).unwrap())
+            };
+            tree.insert(b"ab", ()).unwrap();
+            assert!(tree.contains_key(b"abc"));
+            let mut keys = Vec::new();
+            tree.prefix(b"a", |key, _| keys.push(key.to_vec()));
+            assert!(keys == [&b"ab"[..], b"abc"] || keys == [&b"ab"[..], b"abc", b"abcd"]);
+            th.join().unwrap();
+            assert!(["ab", "abc", "abcd"]
+                .iter()
+                .all(|key| tree.contains_key(key.as_bytes())));
+        })
+    }
+}
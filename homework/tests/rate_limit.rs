@@ -0,0 +1,51 @@
+// The rate limiter keeps its buckets in the cache, which is modeled with loom, so these run only
+// without it.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::hello_server::RateLimiter;
+use std::net::{IpAddr, Ipv4Addr};
+use std::thread::sleep;
+use std::time::Duration;
+
+fn client(i: u8) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(10, 0, 0, i))
+}
+
+#[test]
+fn rate_limit() {
+    // A token is refilled every 100ms.
+    let limiter = RateLimiter::new(2, 10.0);
+    assert!(limiter.check(client(0)));
+    assert!(limiter.check(client(0)));
+    assert!(!limiter.check(client(0)));
+    // Each client has its own bucket.
+    assert!(limiter.check(client(1)));
+
+    sleep(Duration::from_millis(150));
+    assert!(limiter.check(client(0)));
+    assert!(!limiter.check(client(0)));
+}
+
+/// `sweep` removes the buckets that are full again, but not those still being refilled, and the
+/// clients of the removed ones start with full buckets.
+#[test]
+fn rate_limit_sweep() {
+    // A token is refilled every 50ms.
+    let limiter = RateLimiter::new(2, 20.0);
+    for i in 0..100 {
+        assert!(limiter.check(client(i)));
+    }
+    assert!(limiter.check(client(0)));
+    assert!(!limiter.check(client(0)));
+    assert_eq!(limiter.sweep(), 0);
+
+    // The other clients took one token, and client 0 two.
+    sleep(Duration::from_millis(75));
+    assert_eq!(limiter.sweep(), 99);
+    sleep(Duration::from_millis(50));
+    assert_eq!(limiter.sweep(), 1);
+
+    assert!(limiter.check(client(0)));
+    assert!(limiter.check(client(0)));
+    assert!(!limiter.check(client(0)));
+}
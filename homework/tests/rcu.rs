@@ -0,0 +1,102 @@
+mod canary;
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use cs431_homework::ebr::collect;
+    use cs431_homework::RcuCell;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::thread::scope;
+
+    #[test]
+    fn read_update() {
+        let cell = RcuCell::new(vec![1]);
+        let old = cell.read();
+        cell.update(|v| {
+            let mut v = v.clone();
+            v.push(2);
+            v
+        });
+        assert_eq!(*old, [1]);
+        assert_eq!(*cell.read(), [1, 2]);
+        drop(old);
+
+        cell.store(vec![3]);
+        assert_eq!(*cell.read(), [3]);
+        assert_eq!(cell.into_inner(), [3]);
+    }
+
+    /// Updates are not lost, and readers see the versions in order.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+
+        let cell = RcuCell::new((0, 0));
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    for _ in 0..ITER {
+                        // Both fields are updated at once.
+                        cell.update(|&(a, b)| (a + 1, b + 1));
+                    }
+                });
+                let _ = s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..ITER {
+                        let version = cell.read();
+                        assert_eq!(version.0, version.1);
+                        assert!(last <= version.0);
+                        last = version.0;
+                    }
+                });
+            }
+        });
+        assert_eq!(*cell.read(), (THREADS * ITER, THREADS * ITER));
+    }
+
+    /// Every version is dropped exactly once, the old ones after a grace period.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let cell = RcuCell::new(Canary(&dropped));
+        for _ in 0..9 {
+            cell.update(|canary| Canary(canary.0));
+        }
+        drop(cell);
+        // Other tests may be pinned meanwhile, which only delays the reclamation.
+        while dropped.load(Relaxed) < 10 {
+            collect();
+        }
+        assert_eq!(dropped.load(Relaxed), 10);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::ebr::collect;
+    use cs431_homework::RcuCell;
+
+    /// A reader sees either version in whole, and the old version is not freed while it is read.
+    #[test]
+    fn read_update_sync() {
+        model(|| {
+            let cell = Arc::new(RcuCell::new(Box::new(1)));
+            let th = {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    let version = cell.read();
+                    assert!(matches!(**version, 1 | 2));
+                })
+            };
+            cell.update(|v| Box::new(**v + 1));
+            collect();
+            collect();
+            th.join().unwrap();
+            assert_eq!(**cell.read(), 2);
+        })
+    }
+}
@@ -0,0 +1,144 @@
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use cs431_homework::registry::{self, PerThread, ThreadRegistry, REGISTRY};
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::thread::{self, scope};
+
+    /// The ids are dense, and the id of a dropped registration is given to the next one.
+    #[test]
+    fn dense_ids() {
+        let registry = ThreadRegistry::new();
+        let registrations = (0..4).map(|_| registry.register()).collect::<Vec<_>>();
+        let ids = registrations.iter().map(|r| r.id()).collect::<Vec<_>>();
+        assert_eq!(ids, [0, 1, 2, 3]);
+        assert_eq!(registry.live().collect::<Vec<_>>(), [3, 2, 1, 0]);
+
+        let mut registrations = registrations.into_iter();
+        let first = registrations.next().unwrap();
+        drop(registrations.next());
+        assert_eq!(registry.live().collect::<Vec<_>>(), [3, 2, 0]);
+        assert_eq!(registry.register().id(), 1);
+        drop(first);
+        assert_eq!(registry.capacity(), 4);
+    }
+
+    /// The exit callbacks are called in reverse order, before the id is given to another thread.
+    #[test]
+    fn at_exit() {
+        let registry = ThreadRegistry::new();
+        let called = Arc::new(Mutex::new(Vec::new()));
+        let registration = registry.register();
+        for i in 0..3 {
+            let called = called.clone();
+            registration.at_exit(move |id| called.lock().unwrap().push((id, i)));
+        }
+        assert!(called.lock().unwrap().is_empty());
+        drop(registration);
+        assert_eq!(*called.lock().unwrap(), [(0, 2), (0, 1), (0, 0)]);
+
+        // The callbacks of a thread run when it exits.
+        let exited = Arc::new(AtomicUsize::new(usize::MAX));
+        let id = thread::spawn({
+            let exited = exited.clone();
+            move || {
+                registry::at_thread_exit(move |id| exited.store(id, Relaxed));
+                registry::current_id()
+            }
+        })
+        .join()
+        .unwrap();
+        assert_eq!(exited.load(Relaxed), id);
+    }
+
+    /// The threads alive at once have distinct ids, and see each other as live.
+    #[test]
+    fn concurrent_ids() {
+        const THREADS: usize = 8;
+
+        let barrier = Barrier::new(THREADS);
+        let ids = Mutex::new(HashSet::new());
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    let id = registry::current_id();
+                    assert!(ids.lock().unwrap().insert(id));
+                    let _ = barrier.wait();
+                    let live = REGISTRY.live().collect::<HashSet<_>>();
+                    assert!(live.is_superset(&ids.lock().unwrap()));
+                    assert!(id < REGISTRY.capacity());
+                    let _ = barrier.wait();
+                });
+            }
+        });
+    }
+
+    /// Each thread has its own value, which the next thread with the id gets.
+    #[test]
+    fn per_thread() {
+        const THREADS: usize = 8;
+
+        let values = PerThread::<AtomicUsize>::new();
+        let barrier = Barrier::new(THREADS);
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    let _ = values.local().fetch_add(1, Relaxed);
+                    let _ = barrier.wait();
+                    let mine = registry::current_id();
+                    assert!(std::ptr::eq(values.local(), values.get(mine)));
+                    assert!(values.iter().any(|(id, _)| id == mine));
+                    let _ = barrier.wait();
+                });
+            }
+        });
+
+        // An id that no thread has had yet, in a bucket of its own.
+        assert_eq!(values.get(REGISTRY.capacity() + 1000).load(Relaxed), 0);
+        let total = (0..REGISTRY.capacity())
+            .map(|id| values.get(id).load(Relaxed))
+            .sum::<usize>();
+        assert_eq!(total, THREADS);
+    }
+}
+
+mod sync {
+    use super::mock::model;
+    use super::mock::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::registry::{PerThread, ThreadRegistry};
+    use std::mem;
+
+    /// Two threads that register at once get distinct ids, and the value of an id that both
+    /// allocate at once is the same.
+    #[test]
+    fn register_sync() {
+        model(|| {
+            let registry = Arc::new(ThreadRegistry::new());
+            let values = Arc::new(PerThread::<AtomicUsize>::new());
+            let th = {
+                let registry = registry.clone();
+                let values = values.clone();
+                thread::spawn(move || {
+                    let registration = registry.register();
+                    let _ = values.get(0).fetch_add(1, Relaxed);
+                    let id = registration.id();
+                    // Keeps the id, as the thread may exit before the other registers.
+                    mem::forget(registration);
+                    id
+                })
+            };
+            let registration = registry.register();
+            let id = registration.id();
+            let _ = values.get(0).fetch_add(1, Relaxed);
+            let other = th.join().unwrap();
+            assert_eq!(id + other, 1);
+            assert_eq!(values.get(0).load(Relaxed), 2);
+            drop(registration);
+        })
+    }
+}
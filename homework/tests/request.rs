@@ -0,0 +1,61 @@
+use cs431_homework::hello_server::Request;
+
+#[test]
+fn request_parse() {
+    let head = b"GET /caf%C3%A9?keys=a,b&q=x+y%26z&flag HTTP/1.1\r\n\
+        host: localhost\r\n\
+        Connection:  keep-alive, Close \r\n\
+        \r\n";
+    let request = Request::parse(head).unwrap();
+    assert_eq!(request.method(), "GET");
+    assert_eq!(request.target(), "/caf%C3%A9?keys=a,b&q=x+y%26z&flag");
+    assert_eq!(request.version(), "HTTP/1.1");
+    assert_eq!(request.path(), "/café");
+    assert_eq!(request.param("keys"), Some("a,b"));
+    assert_eq!(request.param("q"), Some("x y&z"));
+    assert_eq!(request.param("flag"), Some(""));
+    assert_eq!(request.param("missing"), None);
+    assert_eq!(request.params().len(), 3);
+    assert_eq!(request.header("Host"), Some("localhost"));
+    assert_eq!(request.header("CONNECTION"), Some("keep-alive, Close"));
+    assert!(request.wants_close());
+    assert_eq!(request.headers().len(), 2);
+
+    let request = Request::parse(b"HEAD / HTTP/1.0\r\n\r\n").unwrap();
+    assert_eq!(request.path(), "/");
+    assert!(request.params().is_empty());
+    assert!(!request.wants_close());
+    assert_eq!(request.content_length(), 0);
+
+    let mut request =
+        Request::parse(b"POST / HTTP/1.1\r\ncontent-length: 5\r\nContent-Length: 5\r\n\r\n")
+            .unwrap();
+    assert_eq!(request.content_length(), 5);
+    assert!(request.body().is_empty());
+    request.set_body(b"hello".to_vec());
+    assert_eq!(request.body(), b"hello");
+}
+
+#[test]
+fn request_malformed() {
+    let heads: [&[u8]; 15] = [
+        b"",
+        b"GET / HTTP/1.1",
+        b"GET /\r\n\r\n",
+        b"GET  / HTTP/1.1\r\n\r\n",
+        b"get / HTTP/1.1\r\n\r\n",
+        b"GET foo HTTP/1.1\r\n\r\n",
+        b"GET / HTTP/2.0\r\n\r\n",
+        b"GET /%G0 HTTP/1.1\r\n\r\n",
+        b"GET /%FF HTTP/1.1\r\n\r\n",
+        b"GET / HTTP/1.1\r\nHost : localhost\r\n\r\n",
+        b"GET / HTTP/1.1\r\nHost: localhost\r\n folded\r\n\r\n",
+        b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+        b"POST / HTTP/1.1\r\nContent-Length: +1\r\n\r\n",
+        b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+    ];
+    for head in heads {
+        assert!(Request::parse(head).is_err(), "{head:?}");
+    }
+}
@@ -0,0 +1,97 @@
+// Checks the deterministic scheduler itself, which exists only with `check-sched`.
+#![cfg(feature = "check-sched")]
+
+use cs431_homework::sched::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use cs431_homework::sched::sync::{Arc, Mutex};
+use cs431_homework::sched::{model, replay, thread};
+use std::panic;
+
+/// Runs two threads that record the steps they take, with the interleaving of `seed`.
+fn interleaving(seed: u64) -> Vec<usize> {
+    let steps = Arc::new(Mutex::new(Vec::new()));
+    let counter = Arc::new(AtomicUsize::new(0));
+    replay(seed, || {
+        steps.lock().unwrap().clear();
+        let ths = (0..2)
+            .map(|t| {
+                let steps = steps.clone();
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    for _ in 0..4 {
+                        let _ = counter.fetch_add(1, SeqCst);
+                        steps.lock().unwrap().push(t);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for th in ths {
+            th.join().unwrap();
+        }
+    });
+    let steps = steps.lock().unwrap().clone();
+    steps
+}
+
+/// A seed always gives the same interleaving, and the seeds give different ones.
+#[test]
+fn reproducible() {
+    for seed in 0..16 {
+        assert_eq!(interleaving(seed), interleaving(seed));
+    }
+    let distinct = (0..16)
+        .map(interleaving)
+        .collect::<std::collections::HashSet<_>>();
+    assert!(distinct.len() > 1);
+}
+
+/// The iterations find an interleaving that loses an update of a counter that is not incremented
+/// atomically.
+#[test]
+fn find_lost_update() {
+    let result = panic::catch_unwind(|| {
+        model(|| {
+            let counter = Arc::new(AtomicUsize::new(0));
+            let th = {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    let value = counter.load(SeqCst);
+                    counter.store(value + 1, SeqCst);
+                })
+            };
+            let value = counter.load(SeqCst);
+            counter.store(value + 1, SeqCst);
+            th.join().unwrap();
+            assert_eq!(counter.load(SeqCst), 2);
+        })
+    });
+    assert!(result.is_err());
+}
+
+/// The panic of a thread of the model is returned by `join`, and the model goes on.
+#[test]
+fn join_panicked() {
+    model(|| {
+        let th = thread::spawn(|| panic!("expected"));
+        assert!(th.join().is_err());
+    });
+}
+
+/// The threads that are not joined are waited for at the end of the iteration.
+#[test]
+fn detached() {
+    let iterations = Arc::new(AtomicUsize::new(0));
+    let finished = Arc::new(AtomicUsize::new(0));
+    model({
+        let iterations = iterations.clone();
+        let finished = finished.clone();
+        move || {
+            let _ = iterations.fetch_add(1, SeqCst);
+            let finished = finished.clone();
+            let _ = thread::spawn(move || {
+                thread::yield_now();
+                let _ = finished.fetch_add(1, SeqCst);
+            });
+        }
+    });
+    assert_eq!(finished.load(SeqCst), iterations.load(SeqCst));
+}
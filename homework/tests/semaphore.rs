@@ -0,0 +1,92 @@
+// The semaphore is modeled with loom, so these run only without it.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::Semaphore;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{scope, sleep};
+use std::time::{Duration, Instant};
+
+#[test]
+fn acquire_release() {
+    let semaphore = Semaphore::new(2);
+    let first = semaphore.acquire();
+    let second = semaphore.try_acquire().unwrap();
+    assert_eq!(semaphore.available_permits(), 0);
+    assert!(semaphore.try_acquire().is_none());
+    drop(first);
+    assert_eq!(semaphore.available_permits(), 1);
+    let _third = semaphore.try_acquire().unwrap();
+
+    second.forget();
+    assert_eq!(semaphore.available_permits(), 0);
+    semaphore.release();
+    assert!(semaphore.try_acquire().is_some());
+}
+
+/// `acquire_timeout` gives up after its timeout, and otherwise takes the permit released.
+#[test]
+fn timeout() {
+    let semaphore = Semaphore::new(0);
+    let start = Instant::now();
+    assert!(semaphore
+        .acquire_timeout(Duration::from_millis(100))
+        .is_none());
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    scope(|s| {
+        let _ = s.spawn(|| {
+            sleep(Duration::from_millis(100));
+            semaphore.release();
+        });
+        assert!(semaphore.acquire_timeout(Duration::from_secs(10)).is_some());
+    });
+}
+
+/// A thread blocked in `acquire` is woken up when a permit is released.
+#[test]
+fn acquire_blocks() {
+    let semaphore = Semaphore::new(1);
+    let permit = semaphore.acquire();
+    scope(|s| {
+        let waiter = s.spawn(|| drop(semaphore.acquire()));
+        sleep(Duration::from_millis(100));
+        assert!(!waiter.is_finished());
+        drop(permit);
+    });
+    assert_eq!(semaphore.available_permits(), 1);
+}
+
+/// At most as many threads as the permits hold them at a time, and none of them is left blocked.
+#[test]
+fn stress() {
+    const PERMITS: usize = 3;
+    const THREADS: usize = 8;
+    const ITERS: usize = 1024 * 16;
+
+    let semaphore = Semaphore::new(PERMITS);
+    let holders = AtomicUsize::new(0);
+    scope(|s| {
+        for t in 0..THREADS {
+            let semaphore = &semaphore;
+            let holders = &holders;
+            let _ = s.spawn(move || {
+                for i in 0..ITERS {
+                    let _permit = match (t + i) % 3 {
+                        0 => semaphore.acquire(),
+                        1 => match semaphore.try_acquire() {
+                            Some(permit) => permit,
+                            None => continue,
+                        },
+                        _ => match semaphore.acquire_timeout(Duration::from_micros(10)) {
+                            Some(permit) => permit,
+                            None => continue,
+                        },
+                    };
+                    assert!(holders.fetch_add(1, Ordering::Relaxed) < PERMITS);
+                    let _ = holders.fetch_sub(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    assert_eq!(semaphore.available_permits(), PERMITS);
+}
@@ -0,0 +1,129 @@
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use cs431_homework::seqlock::SeqLock;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::thread::scope;
+
+    #[test]
+    fn read_write() {
+        // The last word is partly used.
+        let lock = SeqLock::new([1u16, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(lock.read(), [1, 2, 3, 4, 5, 6, 7]);
+        lock.write([4; 7]);
+        assert_eq!(lock.try_read(), Some([4; 7]));
+        assert_eq!(lock.update(|value| value.map(|x| x + 1)), [5; 7]);
+        assert_eq!(lock.read(), [5; 7]);
+
+        let unit = SeqLock::new(());
+        unit.write(());
+        unit.read();
+        assert_eq!(unit.try_read(), Some(()));
+    }
+
+    /// `update` runs its closure without the lock, so the closure may read the value, and the lock
+    /// is still usable after the closure panics.
+    #[test]
+    fn update_closure() {
+        let lock = SeqLock::new([1usize; 3]);
+        let value = lock.update(|value| {
+            assert_eq!(lock.read(), value);
+            value.map(|x| x * 2)
+        });
+        assert_eq!(value, [2; 3]);
+
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            lock.update(|_| -> [usize; 3] { panic!("update failed") })
+        }));
+        assert!(panicked.is_err());
+        lock.write([3; 3]);
+        assert_eq!(lock.try_read(), Some([3; 3]));
+    }
+
+    /// Readers never see a value that is partially written, and updates are not lost.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+
+        let lock = SeqLock::new([0usize; 4]);
+        scope(|s| {
+            for _ in 0..THREADS {
+                let _ = s.spawn(|| {
+                    for _ in 0..ITER {
+                        let _ = lock.update(|value| value.map(|x| x + 1));
+                    }
+                });
+                let _ = s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..ITER {
+                        let value = lock.read();
+                        assert!(value.iter().all(|&x| x == value[0]));
+                        assert!(last <= value[0]);
+                        last = value[0];
+                    }
+                });
+            }
+        });
+        assert_eq!(lock.read(), [THREADS * ITER; 4]);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::seqlock::SeqLock;
+
+    /// A validated read sees a value written as a whole.
+    #[test]
+    fn read_write_sync() {
+        model(|| {
+            let lock = Arc::new(SeqLock::new([0usize; 2]));
+            let th = {
+                let lock = lock.clone();
+                thread::spawn(move || lock.write([1, 1]))
+            };
+            if let Some(value) = lock.try_read() {
+                assert!(matches!(value, [0, 0] | [1, 1]));
+            }
+            th.join().unwrap();
+            assert_eq!(lock.read(), [1, 1]);
+        })
+    }
+
+    /// Racing updates are applied one after the other, so neither is lost.
+    #[test]
+    fn update_sync() {
+        model(|| {
+            let lock = Arc::new(SeqLock::new([0usize; 2]));
+            let th = {
+                let lock = lock.clone();
+                thread::spawn(move || lock.update(|value| value.map(|x| x + 1)))
+            };
+            let _ = lock.update(|value| value.map(|x| x + 1));
+            let _ = th.join().unwrap();
+            assert_eq!(lock.read(), [2, 2]);
+        })
+    }
+
+    /// Without the validation, a read racing with a write may see a torn value. This is why the
+    /// reader copies the value and uses the copy only after validating it, instead of reading it
+    /// in place. Loom finds the torn read, and so does the scheduler.
+    #[test]
+    #[cfg_attr(any(feature = "check-loom", feature = "check-sched"), should_panic)]
+    fn torn_read_sync() {
+        model(|| {
+            let lock = Arc::new(SeqLock::new([0usize; 2]));
+            let th = {
+                let lock = lock.clone();
+                thread::spawn(move || lock.write([1, 1]))
+            };
+            // SAFETY: Any mix of `usize`s is a valid `[usize; 2]`.
+            let value = unsafe { lock.read_unvalidated() };
+            assert!(matches!(value, [0, 0] | [1, 1]));
+            th.join().unwrap();
+        })
+    }
+}
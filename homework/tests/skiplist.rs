@@ -0,0 +1,229 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod stress;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable_by, MapOp, MapRet, MapSpec, Recorder};
+    use super::stress::{Op, Stress};
+    use super::threads::{run, scope};
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::{SkipMap, SkipSet};
+    use rand::prelude::*;
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn smoke() {
+        let set = SkipSet::new();
+        assert!(set.is_empty());
+        for i in [3, 1, 4, 5, 9, 2, 6] {
+            assert_eq!(set.insert(i), Ok(()));
+        }
+        assert_eq!(set.insert(4), Err(4));
+        assert!(set.contains(&9));
+        assert!(!set.contains(&7));
+        assert!(set.remove(&4));
+        assert!(!set.remove(&4));
+        assert_eq!(
+            set.iter().map(|entry| *entry.key()).collect::<Vec<_>>(),
+            [1, 2, 3, 5, 6, 9]
+        );
+
+        let map = SkipMap::new();
+        assert_eq!(map.insert("a", 1), Ok(()));
+        assert_eq!(map.insert("a", 2), Err(("a", 2)));
+        assert_eq!(map.lookup(&"a", |value| value.copied()), Some(1));
+        assert_eq!(map.lookup(&"b", |value| value.copied()), None);
+        assert!(map.remove(&"a"));
+        assert!(map.is_empty());
+    }
+
+    /// Random operations agree with `BTreeMap`.
+    #[test]
+    fn sequential() {
+        const ITER: usize = 1024 * 16;
+
+        let map = SkipMap::new();
+        let mut reference = BTreeMap::new();
+        let mut rng = thread_rng();
+        for _ in 0..ITER {
+            let key = rng.gen_range(0..256);
+            match rng.gen_range(0..3) {
+                0 => assert_eq!(
+                    map.insert(key, key * 2).is_ok(),
+                    reference.insert(key, key * 2).is_none()
+                ),
+                1 => assert_eq!(map.remove(&key), reference.remove(&key).is_some()),
+                _ => assert_eq!(
+                    map.lookup(&key, |value| value.copied()),
+                    reference.get(&key).copied()
+                ),
+            }
+        }
+        assert!(map
+            .iter()
+            .map(|entry| (*entry.key(), *entry.value()))
+            .eq(reference.into_iter()));
+    }
+
+    /// Each thread owns the keys of its residue, so it knows exactly which of them are in the set,
+    /// while the others insert and remove around them.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 8;
+        const KEYS: usize = 512;
+
+        let set = SkipSet::new();
+        scope(|s| {
+            for t in 0..THREADS {
+                let set = &set;
+                let _ = s.spawn(move || {
+                    let mut rng = thread_rng();
+                    let mut mine = vec![false; KEYS];
+                    for _ in 0..ITER {
+                        let index = rng.gen_range(0..KEYS);
+                        let key = index * THREADS + t;
+                        if rng.gen() {
+                            assert_eq!(set.insert(key).is_ok(), !mine[index]);
+                            mine[index] = true;
+                        } else {
+                            assert_eq!(set.remove(&key), mine[index]);
+                            mine[index] = false;
+                        }
+                        assert!(set
+                            .iter()
+                            .take(8)
+                            .map(|entry| *entry.key())
+                            .collect::<Vec<_>>()
+                            .windows(2)
+                            .all(|pair| pair[0] < pair[1]));
+                        collect();
+                    }
+                    for (index, present) in mine.into_iter().enumerate() {
+                        assert_eq!(set.contains(&(index * THREADS + t)), present);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Keys and values are dropped exactly once, whether removed or left in the map.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let map = SkipMap::new();
+        for i in 0..100 {
+            assert!(map.insert(i, Canary(&dropped)).is_ok());
+        }
+        for i in 0..50 {
+            assert!(map.remove(&i));
+        }
+        collect();
+        assert_eq!(dropped.load(Relaxed), 50);
+        drop(map);
+        assert_eq!(dropped.load(Relaxed), 100);
+    }
+
+    /// Histories of random operations on a few keys are linearizable.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024;
+
+        let map = SkipMap::new();
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let key = rng.gen_range(0..8);
+                let op = match rng.gen_range(0..3) {
+                    0 => MapOp::Insert(key, t * ITER + i),
+                    1 => MapOp::Remove(key),
+                    _ => MapOp::Lookup(key),
+                };
+                recorder.record(&mut history, op, |op| match *op {
+                    MapOp::Insert(key, value) => MapRet::Done(map.insert(key, value).is_ok()),
+                    MapOp::Remove(key) => MapRet::Done(map.remove(&key)),
+                    MapOp::Lookup(key) => MapRet::Value(map.lookup(&key, |value| value.copied())),
+                    MapOp::Upsert(..) => unreachable!(),
+                });
+                collect();
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable_by(&MapSpec::default(), &history, |op| *op.key());
+    }
+
+    impl super::stress::Subject for SkipSet<u64> {
+        type Model = BTreeSet<u64>;
+
+        fn apply(&self, op: Op) -> Option<u64> {
+            let result = match op {
+                Op::Insert(key, _) => self.insert(key).err(),
+                Op::Remove(key) => self.remove(&key).then_some(key),
+                Op::Get(key) => self.contains(&key).then_some(key),
+            };
+            collect();
+            result
+        }
+
+        fn state(&self, _: u64) -> Self::Model {
+            self.iter().map(|entry| *entry.key()).collect()
+        }
+
+        /// The keys are sorted, even while they are inserted and removed.
+        fn check(&self) {
+            let keys = self.iter().map(|entry| *entry.key()).collect::<Vec<_>>();
+            assert!(keys.windows(2).all(|pair| pair[0] < pair[1]), "{keys:?}");
+        }
+    }
+
+    #[test]
+    fn model_disjoint() {
+        Stress::new(4, 1024 * 8, 512).disjoint::<SkipSet<u64>>();
+    }
+
+    #[test]
+    fn model_shared() {
+        Stress::new(4, 1024 * 8, 64).shared::<SkipSet<u64>>();
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::SkipSet;
+
+    /// A node removed and reclaimed by one thread is not accessed by another thread looking it up.
+    ///
+    /// The full model is too large to explore. Run with `LOOM_MAX_PREEMPTIONS=3`.
+    #[test]
+    fn remove_lookup_sync() {
+        model(|| {
+            let set = Arc::new(SkipSet::new());
+            set.insert(1).unwrap();
+            let th = {
+                let set = set.clone();
+                thread::spawn(move || {
+                    assert!(set.remove(&1));
+                    collect();
+                })
+            };
+            let _ = set.contains(&1);
+            collect();
+            th.join().unwrap();
+            assert!(!set.contains(&1));
+        })
+    }
+}
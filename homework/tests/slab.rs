@@ -0,0 +1,213 @@
+mod canary;
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use cs431_homework::Slab;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::*};
+    use std::thread::scope;
+
+    #[test]
+    fn alloc_free() {
+        let slab = Slab::new();
+        let a = slab.alloc(String::from("a"));
+        let b = slab.alloc(String::from("b"));
+        assert_ne!(a, b);
+        // SAFETY: `a` and `b` are allocated and not freed.
+        unsafe {
+            assert_eq!(slab.get(a), "a");
+            assert_eq!(slab.get(b), "b");
+            assert_eq!(slab.free(a), "a");
+        }
+        // The freed slot is reused.
+        assert_eq!(slab.alloc(String::from("c")), a);
+        // SAFETY: `a` is allocated again.
+        unsafe { assert_eq!(slab.get(a), "c") };
+    }
+
+    /// Indices are stable across the chunk boundaries.
+    #[test]
+    fn chunks() {
+        const LEN: u32 = 1000;
+
+        let slab = Slab::new();
+        let indices = (0..LEN).map(|i| slab.alloc(i)).collect::<Vec<_>>();
+        assert_eq!(
+            indices.iter().copied().collect::<HashSet<_>>().len(),
+            LEN as usize
+        );
+        for (i, index) in indices.into_iter().enumerate() {
+            // SAFETY: `index` is allocated and not freed.
+            assert_eq!(unsafe { *slab.get(index) }, i as u32);
+        }
+    }
+
+    /// Values are dropped exactly once, whether freed or left in the slab.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let slab = Slab::new();
+        let indices = (0..100)
+            .map(|_| slab.alloc(Canary(&dropped)))
+            .collect::<Vec<_>>();
+        for index in &indices[..50] {
+            // SAFETY: `index` is allocated and freed only here.
+            drop(unsafe { slab.free(*index) });
+        }
+        assert_eq!(dropped.load(Relaxed), 50);
+        // Reused slots are live again.
+        for _ in 0..10 {
+            let _ = slab.alloc(Canary(&dropped));
+        }
+        drop(slab);
+        assert_eq!(dropped.load(Relaxed), 110);
+    }
+
+    /// No two threads are given the same slot at the same time.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 8;
+        const ITER: usize = 1024 * 16;
+
+        let slab = Slab::new();
+        scope(|s| {
+            for t in 0..THREADS {
+                let slab = &slab;
+                let _ = s.spawn(move || {
+                    let mut mine = Vec::new();
+                    for i in 0..ITER {
+                        mine.push((slab.alloc((t, i)), i));
+                        if i % 3 != 0 {
+                            let (index, i) = mine.swap_remove(i % mine.len());
+                            // SAFETY: This thread allocated `index` and frees it only here.
+                            assert_eq!(unsafe { slab.free(index) }, (t, i));
+                        }
+                    }
+                    for (index, i) in mine {
+                        // SAFETY: This thread allocated `index` and has not freed it.
+                        assert_eq!(unsafe { *slab.get(index) }, (t, i));
+                    }
+                });
+            }
+        });
+    }
+
+    /// Treiber's stack linking its nodes by indices, whose head is tagged against ABA.
+    struct Stack {
+        slab: Slab<(usize, AtomicU64)>,
+        /// The top index, or `u32::MAX` if empty, in the lower half and the tag in the upper half.
+        head: AtomicU64,
+    }
+
+    impl Stack {
+        fn push(&self, value: usize) {
+            let index = self.slab.alloc((value, AtomicU64::new(0)));
+            // SAFETY: The node is not freed until popped.
+            let next = unsafe { &self.slab.get(index).1 };
+            let mut head = self.head.load(Relaxed);
+            loop {
+                next.store(head & u32::MAX as u64, Relaxed);
+                let new = ((head >> 32) + 1) << 32 | index as u64;
+                match self.head.compare_exchange(head, new, Release, Relaxed) {
+                    Ok(_) => return,
+                    Err(current) => head = current,
+                }
+            }
+        }
+
+        fn pop(&self) -> Option<usize> {
+            let mut head = self.head.load(Acquire);
+            loop {
+                let index = head as u32;
+                if index == u32::MAX {
+                    return None;
+                }
+                // SAFETY: The node may be freed concurrently, but its slot stays valid memory, and
+                // only the atomic link is read before the CAS validates it.
+                let next = unsafe { self.slab.get(index).1.load(Relaxed) };
+                let new = ((head >> 32) + 1) << 32 | next;
+                match self.head.compare_exchange(head, new, Acquire, Acquire) {
+                    // SAFETY: The CAS took the node out of the stack, so only this thread frees it.
+                    Ok(_) => return Some(unsafe { self.slab.free(index) }.0),
+                    Err(current) => head = current,
+                }
+            }
+        }
+    }
+
+    /// Nodes popped and pushed again reuse their slots, which a tagged head tells apart.
+    #[test]
+    fn index_linked_stack() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 16;
+
+        let stack = Stack {
+            slab: Slab::new(),
+            head: AtomicU64::new(u32::MAX as u64),
+        };
+        let sum = AtomicUsize::new(0);
+        scope(|s| {
+            for t in 0..THREADS {
+                let (stack, sum) = (&stack, &sum);
+                let _ = s.spawn(move || {
+                    for i in 0..ITER {
+                        stack.push(t * ITER + i);
+                        let _ = sum.fetch_add(stack.pop().unwrap(), Relaxed);
+                    }
+                });
+            }
+        });
+        assert_eq!(stack.pop(), None);
+        let n = THREADS * ITER;
+        assert_eq!(sum.load(Relaxed), n * (n - 1) / 2);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::Slab;
+
+    /// A thread taking the top of the free list while another takes both of its slots and frees
+    /// the first again does not leave the slot the other still holds in the list.
+    #[test]
+    fn free_list_aba() {
+        model(|| {
+            let slab = Arc::new(Slab::new());
+            let (a, b) = (slab.alloc(0), slab.alloc(0));
+            // SAFETY: `a` and `b` are allocated and freed only here.
+            unsafe {
+                let _ = slab.free(b);
+                let _ = slab.free(a);
+            }
+
+            let th = {
+                let slab = slab.clone();
+                thread::spawn(move || {
+                    let x = slab.alloc(1);
+                    // SAFETY: This thread allocated `x` and does not free it.
+                    assert_eq!(unsafe { *slab.get(x) }, 1);
+                })
+            };
+            let y = slab.alloc(2);
+            let z = slab.alloc(3);
+            // SAFETY: This thread allocated `y` and `z`, and frees `y` only here.
+            unsafe {
+                assert_eq!(slab.free(y), 2);
+                assert_eq!(*slab.get(z), 3);
+            }
+            th.join().unwrap();
+
+            // The free list does not hand out `z` again.
+            let w = slab.alloc(4);
+            // SAFETY: This thread allocated `w` and `z`, and does not free them.
+            unsafe {
+                assert_eq!(*slab.get(w), 4);
+                assert_eq!(*slab.get(z), 3);
+            }
+        })
+    }
+}
@@ -0,0 +1,129 @@
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::threads::scope;
+    use crossbeam_channel::unbounded;
+    use cs431_homework::hello_server::{LiveTraffic, Report, Reporter, RequestId, StatusClass};
+    use cs431_homework::AtomicSnapshot;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn smoke() {
+        let snapshot = AtomicSnapshot::new([1, 2, 3]);
+        assert_eq!(snapshot.scan(), [1, 2, 3]);
+        snapshot.update(1, 20);
+        snapshot.update(2, 30);
+        assert_eq!(snapshot.scan(), [1, 20, 30]);
+        assert_eq!(AtomicSnapshot::<usize, 4>::default().scan(), [0; 4]);
+    }
+
+    /// Each writer counts up in its own register. The scans are totally ordered, and so are the
+    /// values of each register in them.
+    #[test]
+    fn stress() {
+        const WRITERS: usize = 3;
+        const ITER: usize = 1024 * 4;
+
+        let snapshot = AtomicSnapshot::<usize, WRITERS>::default();
+        let done = AtomicBool::new(false);
+        let scans = scope(|s| {
+            for i in 0..WRITERS {
+                let (snapshot, done) = (&snapshot, &done);
+                let _ = s.spawn(move || {
+                    for value in 1..=ITER {
+                        snapshot.update(i, value);
+                    }
+                    done.store(true, Ordering::Relaxed);
+                });
+            }
+            s.run(2, |_| {
+                let mut scans = Vec::<[usize; WRITERS]>::new();
+                while !done.load(Ordering::Relaxed) {
+                    let scan = snapshot.scan();
+                    if let Some(last) = scans.last() {
+                        assert!(last.iter().zip(&scan).all(|(last, value)| last <= value));
+                    }
+                    scans.push(scan);
+                }
+                scans
+            })
+        });
+        assert_eq!(snapshot.scan(), [ITER; WRITERS]);
+
+        // Scans of different threads are comparable as well.
+        let (first, second) = (&scans[0], &scans[1]);
+        for a in first.iter().step_by(first.len() / 64 + 1) {
+            for b in second {
+                let le = a.iter().zip(b).all(|(a, b)| a <= b);
+                let ge = a.iter().zip(b).all(|(a, b)| a >= b);
+                assert!(le || ge, "{a:?} and {b:?} are not comparable");
+            }
+        }
+    }
+
+    /// The live traffic of the classes is consistent while the reports are coming in, and matches
+    /// the statistics in the end.
+    #[test]
+    fn live_traffic() {
+        const REPORTS: usize = 1024;
+
+        let live = Arc::new(LiveTraffic::new());
+        let (report_sender, report_receiver) = unbounded();
+        let stats = scope(|s| {
+            let reporter = s.spawn(|| {
+                Reporter::new()
+                    .with_live_traffic(live.clone())
+                    .run(report_receiver)
+            });
+            for id in 0..REPORTS {
+                let report = Report::new(RequestId::new(id, 0), None)
+                    .with_status([200, 404, 500][id % 3])
+                    .with_bytes(1, 10);
+                report_sender.send(report).unwrap();
+
+                // The classes take turns, so those of the reports added up to any point differ
+                // by at most one, in the order of the turns.
+                let classes = live.scan();
+                let [ok, not_found, error] = [1, 3, 4].map(|i| classes[i].1.requests);
+                assert!(ok >= not_found && not_found >= error && ok <= error + 1);
+            }
+            drop(report_sender);
+            reporter.join()
+        });
+
+        assert_eq!(live.total(), stats.traffic());
+        for (class, traffic) in live.scan() {
+            assert_eq!(traffic, stats.class_traffic(class));
+        }
+        assert_eq!(live.scan()[0].0, StatusClass::Informational);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::AtomicSnapshot;
+
+    /// A scan sees the registers as they were at some point, even if one of them changes twice
+    /// while it reads the others.
+    #[test]
+    fn scan_sync() {
+        model(|| {
+            let snapshot = Arc::new(AtomicSnapshot::new([0, 0]));
+            let th = {
+                let snapshot = snapshot.clone();
+                thread::spawn(move || snapshot.scan())
+            };
+            snapshot.update(0, 1);
+            snapshot.update(1, 1);
+            snapshot.update(0, 2);
+            let scan = th.join().unwrap();
+            assert!([[0, 0], [1, 0], [1, 1], [2, 1]].contains(&scan), "{scan:?}");
+            assert_eq!(snapshot.scan(), [2, 1]);
+        })
+    }
+}
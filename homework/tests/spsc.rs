@@ -0,0 +1,128 @@
+mod canary;
+mod mock;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use cs431_homework::spsc::ring_buffer;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::thread::scope;
+
+    #[test]
+    fn push_pop() {
+        let (mut producer, mut consumer) = ring_buffer(4);
+        assert_eq!(producer.capacity(), 4);
+        assert!(consumer.is_empty());
+        assert_eq!(consumer.pop(), None);
+
+        for round in 0..3 {
+            for i in 0..4 {
+                assert_eq!(producer.push(round * 4 + i), Ok(()));
+            }
+            assert_eq!(producer.push(100), Err(100));
+            assert_eq!(consumer.len(), 4);
+            for i in 0..4 {
+                assert_eq!(consumer.pop(), Some(round * 4 + i));
+            }
+            assert_eq!(consumer.pop(), None);
+        }
+    }
+
+    #[test]
+    fn batch() {
+        let (mut producer, mut consumer) = ring_buffer(4);
+        let mut values = 0..6;
+        assert_eq!(producer.push_batch(&mut values), 4);
+        assert_eq!(values.next(), Some(4));
+
+        let mut out = Vec::new();
+        assert_eq!(consumer.pop_batch(&mut out, 3), 3);
+        assert_eq!(out, [0, 1, 2]);
+        assert_eq!(producer.push_batch(&mut (10..20)), 3);
+        assert_eq!(consumer.pop_batch(&mut out, 10), 4);
+        assert_eq!(out, [0, 1, 2, 3, 10, 11, 12]);
+        assert_eq!(consumer.pop_batch(&mut out, 10), 0);
+    }
+
+    /// Values are popped in the order they are pushed, across the wrap-around of the indices.
+    #[test]
+    fn stress() {
+        const ITER: usize = 1024 * 64;
+
+        let (mut producer, mut consumer) = ring_buffer(64);
+        scope(|s| {
+            let _ = s.spawn(move || {
+                let mut values = 0..ITER;
+                while !values.is_empty() {
+                    if producer.push_batch(&mut (&mut values).take(7)) == 0 {
+                        std::hint::spin_loop();
+                    }
+                }
+            });
+            let mut expected = 0;
+            let mut out = Vec::new();
+            while expected < ITER {
+                out.clear();
+                let _ = consumer.pop_batch(&mut out, 5);
+                if let Some(value) = consumer.pop() {
+                    out.push(value);
+                }
+                for value in &out {
+                    assert_eq!(*value, expected);
+                    expected += 1;
+                }
+            }
+            assert_eq!(consumer.pop(), None);
+        });
+    }
+
+    /// Values left in the buffer are dropped with it, even after one half is dropped.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let (mut producer, mut consumer) = ring_buffer(4);
+        for _ in 0..3 {
+            assert!(producer.push(Canary(&dropped)).is_ok());
+        }
+        drop(consumer.pop());
+        assert_eq!(dropped.load(Relaxed), 1);
+        drop(consumer);
+        assert!(producer.is_abandoned());
+        assert_eq!(dropped.load(Relaxed), 1);
+        drop(producer);
+        assert_eq!(dropped.load(Relaxed), 3);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::thread;
+    use cs431_homework::spsc::ring_buffer;
+
+    /// values pushed → values popped → values seen in order, while the slot is reused.
+    #[test]
+    fn push_pop_sync() {
+        model(|| {
+            let (mut producer, mut consumer) = ring_buffer(1);
+            let th = thread::spawn(move || {
+                for i in 0..2 {
+                    let mut value = Box::new(i);
+                    while let Err(returned) = producer.push(value) {
+                        value = returned;
+                        thread::yield_now();
+                    }
+                }
+            });
+            for i in 0..2 {
+                loop {
+                    if let Some(value) = consumer.pop() {
+                        assert_eq!(*value, i);
+                        break;
+                    }
+                    thread::yield_now();
+                }
+            }
+            th.join().unwrap();
+        })
+    }
+}
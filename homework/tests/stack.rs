@@ -0,0 +1,354 @@
+mod canary;
+mod linearizability;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::linearizability::{assert_linearizable, Recorder, StackOp, StackSpec};
+    use super::threads::{run, scope};
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::reclaim::Ebr;
+    use cs431_homework::{EliminationStack, Stack, VersionedStack};
+    use rand::prelude::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn push_pop() {
+        let stack = Stack::default();
+        assert!(stack.is_empty());
+        assert_eq!(stack.peek(), None);
+        assert_eq!(stack.pop(), None);
+
+        for i in 0..10 {
+            stack.push(i);
+        }
+        assert_eq!(stack.peek(), Some(9));
+        for i in (0..10).rev() {
+            assert_eq!(stack.try_pop(), Ok(Some(i)));
+        }
+        assert!(stack.is_empty());
+        assert_eq!(stack.try_pop(), Ok(None));
+    }
+
+    /// Every pushed value is popped exactly once.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 8;
+        const ITER: usize = 1024 * 16;
+
+        let stack = Stack::default();
+        let popped = run(THREADS, |t| {
+            let mut popped = Vec::new();
+            for i in 0..ITER {
+                stack.push(t * ITER + i);
+                if i % 2 == 1 {
+                    popped.push(stack.pop().unwrap());
+                    popped.push(stack.pop().unwrap());
+                }
+                collect();
+            }
+            popped
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        assert!(stack.is_empty());
+        assert_eq!(popped.len(), THREADS * ITER);
+        let popped = popped.into_iter().collect::<HashSet<_>>();
+        assert_eq!(popped.len(), THREADS * ITER);
+    }
+
+    /// Same as `stress`, with the nodes reclaimed by EBR instead.
+    #[test]
+    fn stress_ebr() {
+        const THREADS: usize = 8;
+        const ITER: usize = 1024 * 16;
+
+        let stack = Stack::<_, Ebr>::new();
+        let popped = run(THREADS, |t| {
+            let mut popped = Vec::new();
+            for i in 0..ITER {
+                stack.push(t * ITER + i);
+                if i % 2 == 1 {
+                    popped.push(stack.pop().unwrap());
+                    popped.push(stack.pop().unwrap());
+                }
+            }
+            popped
+        })
+        .into_iter()
+        .flatten()
+        .collect::<HashSet<_>>();
+
+        assert!(stack.is_empty());
+        assert_eq!(popped.len(), THREADS * ITER);
+    }
+
+    /// Same as `stress`, with the nodes recycled and tagged with versions instead. The threads keep
+    /// popping and pushing back the same few nodes, which is where ABA would show up.
+    #[test]
+    fn versioned_stress() {
+        const THREADS: usize = 8;
+        const ITER: usize = 1024 * 16;
+
+        let stack = VersionedStack::new();
+        let popped = run(THREADS, |t| {
+            let mut popped = Vec::new();
+            for i in 0..ITER {
+                stack.push(t * ITER + i);
+                if i % 2 == 1 {
+                    popped.push(stack.pop().unwrap());
+                    popped.push(stack.pop().unwrap());
+                }
+            }
+            popped
+        })
+        .into_iter()
+        .flatten()
+        .collect::<HashSet<_>>();
+
+        assert!(stack.is_empty());
+        assert_eq!(stack.pop(), None);
+        assert_eq!(popped.len(), THREADS * ITER);
+    }
+
+    /// Every pushed value is popped exactly once, whether it goes through the top of the stack or
+    /// the elimination array.
+    #[test]
+    fn elimination_stress() {
+        const THREADS: usize = 8;
+        const ITER: usize = 1024 * 16;
+
+        for (slots, backoff) in [(1, 1024), (EliminationStack::<()>::SLOTS, 16)] {
+            let stack = EliminationStack::new(slots, backoff);
+            let popped = run(THREADS, |t| {
+                let mut popped = Vec::new();
+                for i in 0..ITER {
+                    stack.push(t * ITER + i);
+                    if i % 2 == 1 {
+                        popped.push(stack.pop().unwrap());
+                        popped.push(stack.pop().unwrap());
+                    }
+                    collect();
+                }
+                popped
+            })
+            .into_iter()
+            .flatten()
+            .collect::<HashSet<_>>();
+
+            assert!(stack.is_empty());
+            assert_eq!(stack.pop(), None);
+            assert_eq!(popped.len(), THREADS * ITER);
+        }
+    }
+
+    #[test]
+    fn elimination_order() {
+        let stack = EliminationStack::default();
+        for i in 0..10 {
+            stack.push(i);
+        }
+        assert_eq!(stack.peek(), Some(9));
+        for i in (0..10).rev() {
+            assert_eq!(stack.pop(), Some(i));
+        }
+        assert_eq!(stack.pop(), None);
+    }
+
+    /// Values left in the stack are dropped with it, and popped values are not dropped again.
+    #[test]
+    fn drop_values() {
+        let dropped = AtomicUsize::new(0);
+        let stack = Stack::default();
+        for _ in 0..10 {
+            stack.push(Canary(&dropped));
+        }
+        drop(stack.pop());
+        assert_eq!(dropped.load(Relaxed), 1);
+        drop(stack);
+        assert_eq!(dropped.load(Relaxed), 10);
+
+        // The recycled nodes hold no values.
+        let stack = VersionedStack::new();
+        for _ in 0..10 {
+            stack.push(Canary(&dropped));
+        }
+        for _ in 0..5 {
+            drop(stack.pop());
+        }
+        stack.push(Canary(&dropped));
+        assert_eq!(dropped.load(Relaxed), 15);
+        drop(stack);
+        assert_eq!(dropped.load(Relaxed), 21);
+    }
+
+    /// Peeking races with popping and freeing the top node.
+    #[test]
+    fn peek_pop() {
+        const ITER: usize = 1024 * 16;
+
+        let stack = Stack::default();
+        scope(|s| {
+            let _ = s.spawn(|| {
+                for i in 0..ITER {
+                    stack.push(i);
+                    assert_eq!(stack.pop(), Some(i));
+                    collect();
+                }
+            });
+            for _ in 0..ITER {
+                assert!(matches!(stack.peek(), None | Some(0..=ITER)));
+            }
+        });
+    }
+
+    /// Histories of random operations on a stack with `push` and `pop` are linearizable.
+    fn linearizable<P, Q>(push: P, pop: Q)
+    where
+        P: Fn(usize) + Sync,
+        Q: Fn() -> Option<usize> + Sync,
+    {
+        const THREADS: usize = 3;
+        const ITER: usize = 256;
+
+        let recorder = Recorder::default();
+        let history = run(THREADS, |t| {
+            let mut rng = thread_rng();
+            let mut history = Vec::new();
+            for i in 0..ITER {
+                let op = if rng.gen() {
+                    StackOp::Push(t * ITER + i)
+                } else {
+                    StackOp::Pop
+                };
+                recorder.record(&mut history, op, |op| match *op {
+                    StackOp::Push(value) => {
+                        push(value);
+                        None
+                    }
+                    StackOp::Pop => pop(),
+                });
+            }
+            history
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        assert_linearizable(&StackSpec::default(), &history);
+    }
+
+    #[test]
+    fn stack_linearizable() {
+        let stack = Stack::default();
+        linearizable(|value| stack.push(value), || stack.pop());
+    }
+
+    #[test]
+    fn elimination_linearizable() {
+        let stack = EliminationStack::default();
+        linearizable(|value| stack.push(value), || stack.pop());
+    }
+
+    #[test]
+    fn versioned_linearizable() {
+        let stack = VersionedStack::new();
+        linearizable(|value| stack.push(value), || stack.pop());
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::hazard_pointer::collect;
+    use cs431_homework::{EliminationStack, Stack, VersionedStack};
+
+    /// value pushed → value popped → value seen
+    #[test]
+    fn push_pop_sync() {
+        model(|| {
+            let stack = Arc::new(Stack::default());
+            let th = {
+                let stack = stack.clone();
+                thread::spawn(move || stack.push(Box::new(123)))
+            };
+            if let Some(value) = stack.pop() {
+                assert_eq!(*value, 123);
+            }
+            th.join().unwrap();
+        })
+    }
+
+    /// Each value is popped by exactly one of the racing threads, and the node freed by one of
+    /// them is not accessed by the other.
+    #[test]
+    fn pop_pop_sync() {
+        model(|| {
+            let stack = Arc::new(Stack::default());
+            stack.push(1);
+            stack.push(2);
+            let th = {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    let value = stack.pop();
+                    collect();
+                    value
+                })
+            };
+            let mine = stack.pop();
+            collect();
+            let theirs = th.join().unwrap();
+            let mut popped = [mine.unwrap(), theirs.unwrap()];
+            popped.sort_unstable();
+            assert_eq!(popped, [1, 2]);
+            assert!(stack.is_empty());
+        })
+    }
+
+    /// A value handed over in the elimination array is neither lost nor duplicated.
+    #[test]
+    fn elimination_sync() {
+        model(|| {
+            let stack = Arc::new(EliminationStack::new(1, 0));
+            stack.push(1);
+            let th = {
+                let stack = stack.clone();
+                thread::spawn(move || stack.push(2))
+            };
+            let mine = stack.pop().unwrap();
+            collect();
+            th.join().unwrap();
+            let theirs = stack.pop().unwrap();
+            assert!(matches!((mine, theirs), (1, 2) | (2, 1)));
+            assert!(stack.is_empty());
+        })
+    }
+
+    /// A node popped by one thread, and recycled for a value pushed back, is not popped again by
+    /// another thread that loaded it before.
+    #[test]
+    fn versioned_sync() {
+        model(|| {
+            let stack = Arc::new(VersionedStack::new());
+            stack.push(1);
+            stack.push(2);
+            let th = {
+                let stack = stack.clone();
+                thread::spawn(move || stack.pop())
+            };
+            let mine = stack.pop().unwrap();
+            stack.push(3);
+            let theirs = th.join().unwrap().unwrap();
+            let last = stack.pop().unwrap();
+            let mut popped = [mine, theirs, last];
+            popped.sort_unstable();
+            assert_eq!(popped, [1, 2, 3]);
+            assert!(stack.is_empty());
+        })
+    }
+}
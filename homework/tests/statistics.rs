@@ -0,0 +1,237 @@
+use crossbeam_channel::unbounded;
+use cs431_homework::hello_server::{
+    relaxed_report_channel, report_channel, Histogram, Report, ReportPolicy, Reporter, RequestId,
+    Statistics, StatusClass,
+};
+use std::thread;
+use std::time::Duration;
+use std::{env, fs, process};
+
+#[test]
+fn histogram_percentiles() {
+    let mut histogram = Histogram::default();
+    assert_eq!(histogram.percentile(0.5), None);
+
+    for millis in 1..=1000 {
+        histogram.record(Duration::from_millis(millis));
+    }
+    assert_eq!(histogram.count(), 1000);
+    assert_eq!(histogram.min(), Some(Duration::from_millis(1)));
+    assert_eq!(histogram.max(), Some(Duration::from_millis(1000)));
+
+    // Each percentile is accurate up to the bucket precision.
+    for (quantile, expected) in [(0.5, 500.0), (0.9, 900.0), (0.99, 990.0)] {
+        let actual = histogram.percentile(quantile).unwrap().as_secs_f64() * 1000.0;
+        assert!(
+            (actual - expected).abs() / expected < 0.05,
+            "p{quantile}: {actual}ms"
+        );
+    }
+    assert_eq!(histogram.percentile(1.0), Some(Duration::from_millis(1000)));
+}
+
+#[test]
+fn statistics_latencies() {
+    let mut stats = Statistics::default();
+    for id in 0..10 {
+        let report = Report::new(RequestId::new(id, 0), None)
+            .with_duration(Duration::from_micros(id as u64));
+        stats.add_report(report);
+    }
+    let latencies = stats.latencies();
+    assert_eq!(latencies.count(), 10);
+    assert_eq!(latencies.percentile(0.5), Some(Duration::from_micros(4)));
+}
+
+#[test]
+fn reporter_snapshots() {
+    let (report_sender, report_receiver) = unbounded();
+    let (snapshot_sender, snapshot_receiver) = unbounded();
+    let reporter = thread::spawn(move || {
+        Reporter::new()
+            .with_interval(Duration::from_millis(10))
+            .with_snapshot_sender(snapshot_sender)
+            .run(report_receiver)
+    });
+
+    report_sender
+        .send(Report::new(RequestId::new(0, 0), Some("a".to_string())))
+        .unwrap();
+    // Snapshots are taken while the reports are still coming in.
+    while snapshot_receiver.recv().unwrap().latencies().count() != 1 {}
+
+    report_sender
+        .send(Report::new(RequestId::new(1, 0), Some("b".to_string())))
+        .unwrap();
+    drop(report_sender);
+    let stats = reporter.join().unwrap();
+    assert_eq!(stats.latencies().count(), 2);
+}
+
+#[test]
+fn reporter_report_policies() {
+    for policy in [ReportPolicy::Drop, ReportPolicy::Coalesce] {
+        let (report_sender, report_receiver) = report_channel(2, policy);
+        // Nothing is received until all reports are sent, so all but two of them overflow.
+        for id in 0..10 {
+            let report = Report::new(RequestId::new(id, 0), Some("a".to_string()))
+                .with_status(200)
+                .with_route("/{key}")
+                .with_bytes(10, 100)
+                .with_duration(Duration::from_micros(id as u64));
+            report_sender.send(report).unwrap();
+        }
+        drop(report_sender);
+        let stats = Reporter::new().run(report_receiver);
+
+        let (received, dropped) = match policy {
+            ReportPolicy::Drop => (2, 8),
+            _ => (10, 0),
+        };
+        assert_eq!(stats.latencies().count(), received, "{policy:?}");
+        assert_eq!(stats.responses(StatusClass::Success), received as usize);
+        assert_eq!(stats.route_traffic("/{key}").bytes_written, received * 100);
+        assert_eq!(stats.dropped(), dropped);
+    }
+
+    // Blocking waits for the reporter.
+    let (report_sender, report_receiver) = report_channel(1, ReportPolicy::Block);
+    let reporter = thread::spawn(move || Reporter::new().run(report_receiver));
+    for id in 0..10 {
+        report_sender
+            .send(Report::new(RequestId::new(id, 0), None))
+            .unwrap();
+    }
+    drop(report_sender);
+    let stats = reporter.join().unwrap();
+    assert_eq!(stats.latencies().count(), 10);
+    assert_eq!(stats.dropped(), 0);
+
+    // Sending fails once the reporter is gone.
+    let (report_sender, report_receiver) = report_channel(1, ReportPolicy::Drop);
+    drop(report_receiver);
+    assert!(report_sender
+        .send(Report::new(RequestId::new(0, 0), None))
+        .is_err());
+}
+
+/// The reports of a relaxed channel from many workers all reach the reporter, which runs until
+/// the workers are done.
+#[test]
+fn reporter_relaxed_channel() {
+    const WORKERS: usize = 4;
+    const REPORTS: usize = 1000;
+
+    let (report_sender, report_receiver) = relaxed_report_channel(4);
+    let reporter = thread::spawn(move || Reporter::new().run(report_receiver));
+    let workers = (0..WORKERS)
+        .map(|worker| {
+            let report_sender = report_sender.clone();
+            thread::spawn(move || {
+                for id in 0..REPORTS {
+                    let report = Report::new(RequestId::new(id, worker), None).with_status(200);
+                    report_sender.send(report).unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(report_sender);
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let stats = reporter.join().unwrap();
+    assert_eq!(stats.responses(StatusClass::Success), WORKERS * REPORTS);
+    assert_eq!(stats.dropped(), 0);
+
+    // Sending fails once the reporter is gone.
+    let (report_sender, report_receiver) = relaxed_report_channel(4);
+    drop(report_receiver);
+    assert!(report_sender
+        .send(Report::new(RequestId::new(0, 0), None))
+        .is_err());
+}
+
+#[test]
+fn statistics_status_classes() {
+    let mut stats = Statistics::default();
+    for (id, status) in [Some(200), Some(404), Some(405), Some(500), None]
+        .into_iter()
+        .enumerate()
+    {
+        let report = Report::new(RequestId::new(id, 0), None);
+        stats.add_report(match status {
+            Some(status) => report.with_status(status),
+            None => report,
+        });
+    }
+
+    assert_eq!(stats.responses(StatusClass::Success), 1);
+    assert_eq!(stats.responses(StatusClass::ClientError), 2);
+    assert_eq!(stats.responses(StatusClass::ServerError), 1);
+    assert_eq!(stats.responses(StatusClass::NoResponse), 1);
+    assert_eq!(stats.responses(StatusClass::Redirection), 0);
+}
+
+#[test]
+fn statistics_export() {
+    let mut stats = Statistics::default();
+    for (id, key, status, micros) in [
+        (0, Some("bob"), 200, 30),
+        (1, Some("alice"), 200, 10),
+        (2, Some("bob"), 200, 20),
+        (3, None, 404, 40),
+    ] {
+        let report = Report::new(RequestId::new(id, 0), key.map(str::to_string))
+            .with_route(if key.is_some() { "/{key}" } else { "/*" })
+            .with_status(status)
+            .with_bytes(100, 1000)
+            .with_duration(Duration::from_micros(micros));
+        stats.add_report(report);
+    }
+    stats.add_dropped(2);
+
+    let dir = env::temp_dir();
+    let csv = dir.join(format!("statistics_export_{}.csv", process::id()));
+    let json = dir.join(format!("statistics_export_{}.json", process::id()));
+    stats.write_csv(&csv).unwrap();
+    stats.write_json(&json).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&csv).unwrap(),
+        "metric,name,value\n\
+         requests,,4\n\
+         hits,alice,1\n\
+         hits,bob,2\n\
+         hits,,1\n\
+         responses,2xx,3\n\
+         responses,4xx,1\n\
+         latency_us,count,4\n\
+         latency_us,min,10\n\
+         latency_us,p50,20\n\
+         latency_us,p90,40\n\
+         latency_us,p99,40\n\
+         latency_us,max,40\n\
+         bytes,read,400\n\
+         bytes,written,4000\n\
+         route_requests,/*,1\n\
+         route_bytes_read,/*,100\n\
+         route_bytes_written,/*,1000\n\
+         route_requests,/{key},3\n\
+         route_bytes_read,/{key},300\n\
+         route_bytes_written,/{key},3000\n\
+         reports,dropped,2\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&json).unwrap(),
+        "{\"requests\": 4, \"hits\": {\"alice\": 1, \"bob\": 2}, \"invalid\": 1, \
+         \"responses\": {\"2xx\": 3, \"4xx\": 1}, \"latency_us\": {\"count\": 4, \"min\": 10, \
+         \"p50\": 20, \"p90\": 40, \"p99\": 40, \"max\": 40}, \
+         \"bytes\": {\"read\": 400, \"written\": 4000}, \"routes\": {\
+         \"/*\": {\"requests\": 1, \"bytes_read\": 100, \"bytes_written\": 1000}, \
+         \"/{key}\": {\"requests\": 3, \"bytes_read\": 300, \"bytes_written\": 3000}}, \
+         \"dropped_reports\": 2}\n"
+    );
+
+    fs::remove_file(csv).unwrap();
+    fs::remove_file(json).unwrap();
+}
@@ -0,0 +1,177 @@
+mod canary;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::canary::Canary;
+    use super::threads::scope;
+    use cs431_homework::ebr::collect;
+    use cs431_homework::stm::{atomically, TVar};
+    use rand::prelude::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+
+    #[test]
+    fn smoke() {
+        let count = TVar::new(1);
+        let name = TVar::new(String::from("a"));
+        let read = atomically(|tx| {
+            let c = tx.modify(&count, |c| c + 1)?;
+            tx.write(&name, "b".repeat(c));
+            // The transaction reads its own writes.
+            tx.read(&name)
+        });
+        assert_eq!(read, "bb");
+        assert_eq!(count.load(), 2);
+        assert_eq!(format!("{name:?}"), r#"TVar("bb")"#);
+        assert_eq!(name.into_inner(), "bb");
+    }
+
+    /// Money moved between accounts is neither created nor lost, as seen by the transactions
+    /// that sum the accounts meanwhile.
+    #[test]
+    fn transfer() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+        const ACCOUNTS: usize = 8;
+        const BALANCE: i64 = 100;
+
+        let accounts = (0..ACCOUNTS)
+            .map(|_| TVar::new(BALANCE))
+            .collect::<Vec<_>>();
+        let total = || {
+            atomically(|tx| {
+                accounts
+                    .iter()
+                    .map(|account| tx.read(account))
+                    .sum::<Result<i64, _>>()
+            })
+        };
+        let done = AtomicBool::new(false);
+        scope(|s| {
+            let _ = s.spawn(|| {
+                while !done.load(Relaxed) {
+                    assert_eq!(total(), BALANCE * ACCOUNTS as i64);
+                }
+            });
+            s.run(THREADS, |_| {
+                let mut rng = thread_rng();
+                for _ in 0..ITER {
+                    let from = &accounts[rng.gen_range(0..ACCOUNTS)];
+                    let to = &accounts[rng.gen_range(0..ACCOUNTS)];
+                    let amount = rng.gen_range(0..10);
+                    atomically(|tx| {
+                        let _ = tx.modify(from, |balance| balance - amount)?;
+                        let _ = tx.modify(to, |balance| balance + amount)?;
+                        Ok(())
+                    });
+                }
+            });
+            done.store(true, Relaxed);
+        });
+        assert_eq!(total(), BALANCE * ACCOUNTS as i64);
+    }
+
+    /// Increments of the same variable are not lost.
+    #[test]
+    fn increment() {
+        const THREADS: usize = 4;
+        const ITER: usize = 1024 * 4;
+
+        let count = TVar::new(0);
+        scope(|s| {
+            s.run(THREADS, |_| {
+                for _ in 0..ITER {
+                    let _ = atomically(|tx| tx.modify(&count, |count| count + 1));
+                }
+            })
+        });
+        assert_eq!(count.into_inner(), THREADS * ITER);
+    }
+
+    /// The values replaced, and those buffered by the runs that did not commit, are dropped.
+    #[test]
+    fn drop_values() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        let var = TVar::new(Canary(&DROPPED));
+        for _ in 0..10 {
+            atomically(|tx| {
+                tx.write(&var, Canary(&DROPPED));
+                Ok(())
+            });
+        }
+        let mut runs = 0;
+        atomically(|tx| {
+            tx.write(&var, Canary(&DROPPED));
+            runs += 1;
+            if runs == 1 {
+                return Err(cs431_homework::stm::Conflict);
+            }
+            Ok(())
+        });
+        drop(var);
+        for _ in 0..4 {
+            collect();
+        }
+        assert_eq!(DROPPED.load(Relaxed), 13);
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::stm::{atomically, TVar};
+
+    /// A transaction sees both or neither of the writes of another one.
+    #[test]
+    fn transfer_sync() {
+        model(|| {
+            let accounts = Arc::new([TVar::new(1), TVar::new(0)]);
+            let th = {
+                let accounts = accounts.clone();
+                thread::spawn(move || {
+                    let [from, to] = &*accounts;
+                    atomically(|tx| {
+                        tx.write(from, 0);
+                        tx.write(to, 1);
+                        Ok(())
+                    })
+                })
+            };
+            let [from, to] = &*accounts;
+            let total = atomically(|tx| Ok(tx.read(from)? + tx.read(to)?));
+            assert_eq!(total, 1);
+            th.join().unwrap();
+        })
+    }
+
+    /// Of two transactions that each write what the other reads, one sees the write of the other.
+    #[test]
+    fn write_skew_sync() {
+        model(|| {
+            let vars = Arc::new([TVar::new(false), TVar::new(false)]);
+            let th = {
+                let vars = vars.clone();
+                thread::spawn(move || {
+                    let [x, y] = &*vars;
+                    atomically(|tx| {
+                        if !tx.read(x)? {
+                            tx.write(y, true);
+                        }
+                        Ok(())
+                    })
+                })
+            };
+            let [x, y] = &*vars;
+            atomically(|tx| {
+                if !tx.read(y)? {
+                    tx.write(x, true);
+                }
+                Ok(())
+            });
+            th.join().unwrap();
+            assert!(!(x.load() && y.load()));
+        })
+    }
+}
@@ -0,0 +1,303 @@
+//! Randomized stress tests that compare a concurrent structure with a sequential model.
+//!
+//! The threads run random mixes of operations on the structure, generated from a seed that is
+//! printed at the start, so that a failing run can be repeated with `STRESS_SEED` set to it. Only
+//! the operations are repeated, not the interleaving of the threads.
+//!
+//! - In [`Stress::disjoint`], each thread owns the keys of its residue, so every return value is
+//!   compared with a model of the thread, and the final state with the union of the models.
+//! - In [`Stress::shared`], the threads race on the same keys. An operation returns the value the
+//!   key had before, so the operations on a key are the steps of a walk through its values, which
+//!   must start from the initial state and end at the final one.
+//!
+//! Meanwhile, another thread samples the invariants of the structure with [`Subject::check`].
+
+// Each test crate uses a part of it.
+#![allow(dead_code)]
+
+use core::fmt;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+use std::thread;
+
+/// Environment variable that overrides the random seed.
+const SEED_VAR: &str = "STRESS_SEED";
+
+/// Operation on a set or map of `u64`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Inserts the entry. A map replaces the value of the key, and a set is left as it is.
+    Insert(u64, u64),
+    /// Removes the entry of the key.
+    Remove(u64),
+    /// Looks up the value of the key.
+    Get(u64),
+}
+
+impl Op {
+    /// Returns the key.
+    pub fn key(self) -> u64 {
+        match self {
+            Self::Insert(key, _) | Self::Remove(key) | Self::Get(key) => key,
+        }
+    }
+}
+
+/// Sequential model of a structure. Every operation returns the value the key had before it, and
+/// the value of a key of a set is the key.
+pub trait Model: Default + PartialEq + fmt::Debug + Send {
+    /// Applies `op`, returning the value of the key before it.
+    fn apply(&mut self, op: Op) -> Option<u64>;
+
+    /// Returns the value of the key after inserting `value`, if it was present.
+    fn stored(key: u64, value: u64) -> u64;
+
+    /// Returns the entries.
+    fn entries(&self) -> Vec<(u64, u64)>;
+}
+
+impl Model for BTreeSet<u64> {
+    fn apply(&mut self, op: Op) -> Option<u64> {
+        match op {
+            Op::Insert(key, _) => (!self.insert(key)).then_some(key),
+            Op::Remove(key) => self.take(&key),
+            Op::Get(key) => self.get(&key).copied(),
+        }
+    }
+
+    fn stored(key: u64, _: u64) -> u64 {
+        key
+    }
+
+    fn entries(&self) -> Vec<(u64, u64)> {
+        self.iter().map(|&key| (key, key)).collect()
+    }
+}
+
+impl Model for HashMap<u64, u64> {
+    fn apply(&mut self, op: Op) -> Option<u64> {
+        match op {
+            Op::Insert(key, value) => self.insert(key, value),
+            Op::Remove(key) => self.remove(&key),
+            Op::Get(key) => self.get(&key).copied(),
+        }
+    }
+
+    fn stored(_: u64, value: u64) -> u64 {
+        value
+    }
+
+    fn entries(&self) -> Vec<(u64, u64)> {
+        self.iter().map(|(&key, &value)| (key, value)).collect()
+    }
+}
+
+/// Concurrent structure under test.
+pub trait Subject: Default + Sync {
+    /// Model of the structure.
+    type Model: Model;
+
+    /// Applies `op`, returning what [`Model::apply`] does.
+    fn apply(&self, op: Op) -> Option<u64>;
+
+    /// Returns the state when no operation is running. By default, the keys in `0..keys` are
+    /// looked up one by one.
+    fn state(&self, keys: u64) -> Self::Model {
+        let mut model = Self::Model::default();
+        for key in 0..keys {
+            if let Some(value) = self.apply(Op::Get(key)) {
+                let _ = model.apply(Op::Insert(key, value));
+            }
+        }
+        model
+    }
+
+    /// Panics if an invariant of the structure does not hold. Called while operations run.
+    fn check(&self) {}
+}
+
+/// Configuration of a stress test.
+#[derive(Debug, Clone, Copy)]
+pub struct Stress {
+    threads: usize,
+    /// Number of the operations of each thread.
+    ops: usize,
+    /// The keys are in `0..keys`.
+    keys: u64,
+    seed: u64,
+}
+
+impl Stress {
+    /// Creates a test of `ops` operations on each of `threads` threads, on the keys in
+    /// `0..keys`. Prints the seed, which is random unless `STRESS_SEED` is set.
+    pub fn new(threads: usize, ops: usize, keys: u64) -> Self {
+        let seed = match env::var(SEED_VAR) {
+            Ok(seed) => seed.parse().expect("STRESS_SEED is not a u64"),
+            Err(_) => random(),
+        };
+        println!("stress seed: {seed} (rerun with {SEED_VAR}={seed})");
+        Self {
+            threads,
+            ops,
+            keys,
+            seed,
+        }
+    }
+
+    /// Returns the random operation generator of thread `t`.
+    fn rng(&self, t: usize) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ (t as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    /// Returns a random operation on `key`.
+    fn op(rng: &mut StdRng, key: u64) -> Op {
+        match rng.gen_range(0..10) {
+            0..=3 => Op::Get(key),
+            4..=6 => Op::Insert(key, rng.gen()),
+            _ => Op::Remove(key),
+        }
+    }
+
+    /// Runs `f(t)` on each thread `t`, while another thread checks the invariants of `subject`,
+    /// and returns the results in the order of `t`.
+    fn run<S, T, F>(&self, subject: &S, f: F) -> Vec<T>
+    where
+        S: Subject,
+        T: Send,
+        F: Fn(usize) -> T + Sync,
+    {
+        let done = AtomicBool::new(false);
+        let checks = AtomicUsize::new(0);
+        let results = thread::scope(|s| {
+            let _ = s.spawn(|| {
+                while !done.load(Relaxed) {
+                    subject.check();
+                    let _ = checks.fetch_add(1, Relaxed);
+                }
+            });
+            // Spawns all threads before joining any of them, so that they run concurrently.
+            #[allow(clippy::needless_collect)]
+            let handles = (0..self.threads)
+                .map(|t| {
+                    let f = &f;
+                    s.spawn(move || f(t))
+                })
+                .collect::<Vec<_>>();
+            let results = handles
+                .into_iter()
+                .map(|handle| handle.join())
+                .collect::<Vec<_>>();
+            // Stops the checks before the panics of the threads are propagated.
+            done.store(true, Relaxed);
+            results
+        });
+        let results = results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|payload| panic::resume_unwind(payload)))
+            .collect();
+        subject.check();
+        println!("stress: {} checks while running", checks.into_inner());
+        results
+    }
+
+    /// Runs the test with each thread on its own keys, comparing every return value with the
+    /// model, and the final state with the union of the models.
+    pub fn disjoint<S: Subject>(&self) {
+        let subject = S::default();
+        let models = self.run(&subject, |t| {
+            let mut rng = self.rng(t);
+            let mut model = S::Model::default();
+            // The keys past the last multiple of the number of the threads are left out.
+            let owned = self.keys / self.threads as u64;
+            for i in 0..self.ops {
+                let key = rng.gen_range(0..owned) * self.threads as u64 + t as u64;
+                let op = Self::op(&mut rng, key);
+                let expected = model.apply(op);
+                assert_eq!(
+                    subject.apply(op),
+                    expected,
+                    "thread {t}, operation {i}: {op:?}"
+                );
+            }
+            model
+        });
+
+        let mut union = S::Model::default();
+        for model in models {
+            for (key, value) in model.entries() {
+                let _ = union.apply(Op::Insert(key, value));
+            }
+        }
+        assert_eq!(subject.state(self.keys), union);
+    }
+
+    /// Runs the test with the threads on the same keys, checking that the operations on each key
+    /// are the steps of a walk from the initial state to the final one, and that every value seen
+    /// was inserted.
+    pub fn shared<S: Subject>(&self) {
+        let subject = S::default();
+        let logs = self.run(&subject, |t| {
+            let mut rng = self.rng(t);
+            (0..self.ops)
+                .map(|_| {
+                    let key = rng.gen_range(0..self.keys);
+                    let op = Self::op(&mut rng, key);
+                    (op, subject.apply(op))
+                })
+                .collect::<Vec<_>>()
+        });
+        let state = subject.state(self.keys);
+        let finals = state.entries().into_iter().collect::<HashMap<_, _>>();
+
+        // For each key, the number of the steps from each value minus those to it.
+        let mut balances = HashMap::<u64, HashMap<Option<u64>, i64>>::new();
+        let mut reads = Vec::new();
+        for (op, before) in logs.into_iter().flatten() {
+            let after = match op {
+                Op::Insert(key, value) => Some(S::Model::stored(key, value)),
+                Op::Remove(_) => None,
+                Op::Get(_) => {
+                    reads.push((op, before));
+                    continue;
+                }
+            };
+            let balance = balances.entry(op.key()).or_default();
+            *balance.entry(before).or_default() += 1;
+            *balance.entry(after).or_default() -= 1;
+        }
+        for (key, balance) in &mut balances {
+            let last = finals.get(key).copied();
+            let _ = balance.entry(None).or_default();
+            let _ = balance.entry(last).or_default();
+            let unbalanced = balance
+                .iter()
+                .filter(|(&value, &count)| {
+                    count != i64::from(value.is_none()) - i64::from(value == last)
+                })
+                .collect::<Vec<_>>();
+            assert!(
+                unbalanced.is_empty(),
+                "the steps on key {key} do not walk from None to {last:?}: {unbalanced:?}"
+            );
+        }
+        for (op, value) in reads {
+            if value.is_some() {
+                let balance = balances.get(&op.key());
+                assert!(
+                    matches!(balance, Some(balance) if balance.contains_key(&value)),
+                    "{op:?} returned {value:?}, which was never inserted"
+                );
+            }
+        }
+        for (key, value) in finals {
+            assert!(
+                matches!(balances.get(&key), Some(balance) if balance.contains_key(&Some(value))),
+                "{key} is {value} at the end, which was never inserted"
+            );
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use crossbeam_channel::bounded;
-use cs431_homework::hello_server::CancellableTcpListener;
-use std::io::prelude::*;
+use cs431_homework::hello_server::{CancellableTcpListener, ListenerOptions};
+use cs431_homework::CountDownLatch;
+use std::io::{self, prelude::*};
 use std::net::TcpStream;
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
-use std::thread::scope;
-use std::time::Duration;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+use std::thread::{scope, sleep};
+use std::time::{Duration, Instant};
 
 #[test]
 fn cancellable_listener_cancel() {
@@ -17,7 +18,7 @@ fn cancellable_listener_cancel() {
         port += 1;
     };
 
-    let (done_sender, done_receiver) = bounded(0);
+    let done = CountDownLatch::new(1);
     scope(|s| {
         s.spawn(|| {
             for stream in listener.incoming() {
@@ -26,12 +27,220 @@ fn cancellable_listener_cancel() {
                 let _ = stream.read(&mut buf).unwrap();
                 assert_eq!(buf[0], 123);
             }
-            done_sender.send(()).unwrap();
+            done.count_down();
         });
         let mut stream = TcpStream::connect(addr).unwrap();
         let _ = stream.write(&[123]).unwrap();
 
         listener.cancel().unwrap();
-        done_receiver.recv_timeout(Duration::from_secs(3)).unwrap();
+        assert!(done.wait_timeout(Duration::from_secs(3)));
+    });
+}
+
+#[test]
+fn cancellable_listener_cancel_and_wait() {
+    let mut port = 23556;
+    let listener = loop {
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port));
+        if let Ok(listener) = CancellableTcpListener::bind(addr) {
+            break listener;
+        }
+        port += 1;
+    };
+
+    let done = CountDownLatch::new(1);
+    scope(|s| {
+        let incoming = listener.incoming();
+        s.spawn(move || {
+            // No connection is ever made, so this blocks until cancelled.
+            assert_eq!(incoming.count(), 0);
+        });
+        s.spawn(|| {
+            listener.cancel_and_wait().unwrap();
+            done.count_down();
+        });
+        assert!(done.wait_timeout(Duration::from_secs(3)));
+    });
+}
+
+#[test]
+fn cancellable_listener_multiple() {
+    let mut port = 23656;
+    let mut listeners = Vec::new();
+    while listeners.len() < 2 {
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port));
+        if let Ok(listener) = CancellableTcpListener::bind(addr) {
+            assert_eq!(listener.local_addr().unwrap(), addr);
+            listeners.push(listener);
+        }
+        port += 1;
+    }
+    let (first, second) = (&listeners[0], &listeners[1]);
+
+    let done = CountDownLatch::new(1);
+    scope(|s| {
+        s.spawn(|| {
+            assert_eq!(first.incoming().count(), 0);
+            done.count_down();
+        });
+
+        // Cancelling one listener does not affect the other.
+        first.cancel().unwrap();
+        assert!(done.wait_timeout(Duration::from_secs(3)));
+
+        let _stream = TcpStream::connect(second.local_addr().unwrap()).unwrap();
+        assert!(second.incoming().next().unwrap().is_ok());
+    });
+}
+
+#[test]
+fn cancellable_listener_cancel_after() {
+    let mut port = 23756;
+    let listener = loop {
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port));
+        if let Ok(listener) = CancellableTcpListener::bind(addr) {
+            break listener;
+        }
+        port += 1;
+    };
+
+    let start = Instant::now();
+    listener.cancel_after(Duration::from_secs(10)).unwrap();
+    scope(|s| {
+        let incoming = s.spawn(|| listener.incoming().count());
+        // The earlier deadline takes effect, even if the iterator is already waiting.
+        sleep(Duration::from_millis(100));
+        listener.cancel_after(Duration::from_millis(200)).unwrap();
+        assert_eq!(incoming.join().unwrap(), 0);
+    });
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(300), "took {elapsed:?}");
+    assert!(elapsed < Duration::from_secs(3), "took {elapsed:?}");
+}
+
+#[test]
+fn cancellable_listener_options() {
+    let mut port = 23856;
+    let (addr, listener) = loop {
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port));
+        if let Ok(listener) = CancellableTcpListener::bind(addr) {
+            break (addr, listener);
+        }
+        port += 1;
+    };
+
+    // The address is in use.
+    let err = CancellableTcpListener::bind(addr).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+    // Retries until the address is released.
+    let options = ListenerOptions {
+        bind_retries: 20,
+        retry_interval: Duration::from_millis(100),
+        ..ListenerOptions::default()
+    };
+    scope(|s| {
+        s.spawn(move || {
+            sleep(Duration::from_millis(300));
+            drop(listener);
+        });
+        let listener = CancellableTcpListener::bind_with(addr, &options).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+    });
+}
+
+#[cfg(unix)]
+#[test]
+fn cancellable_listener_reuse_port() {
+    let options = ListenerOptions {
+        reuse_port: true,
+        ..ListenerOptions::default()
+    };
+    let mut port = 23956;
+    let (addr, _listener) = loop {
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port));
+        if let Ok(listener) = CancellableTcpListener::bind_with(addr, &options) {
+            break (addr, listener);
+        }
+        port += 1;
+    };
+    let _other = CancellableTcpListener::bind_with(addr, &options).unwrap();
+}
+
+#[test]
+fn cancellable_listener_dual_stack() {
+    let mut port = 24056;
+    let listeners = loop {
+        if let Ok(listeners) =
+            CancellableTcpListener::bind_dual_stack(port, &ListenerOptions::default())
+        {
+            break listeners;
+        }
+        port += 1;
+    };
+    assert!(!listeners.is_empty());
+
+    // Connect to each address family the listeners accept.
+    let mut connected = 0;
+    for ip in [
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(Ipv6Addr::LOCALHOST),
+    ] {
+        let is_bound = listeners.iter().any(|listener| {
+            let local = listener.local_addr().unwrap();
+            assert_eq!(local.port(), port);
+            local.is_ipv6() || ip.is_ipv4()
+        });
+        if !is_bound || TcpStream::connect((ip, port)).is_err() {
+            continue;
+        }
+        connected += 1;
+    }
+    // At least IPv4 loopback must be reachable unless the host is IPv6-only.
+    assert!(connected > 0);
+}
+
+#[test]
+fn cancellable_listener_status() {
+    let mut port = 24156;
+    let (addr, listener) = loop {
+        let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port));
+        if let Ok(listener) = CancellableTcpListener::bind(addr) {
+            break (addr, listener);
+        }
+        port += 1;
+    };
+
+    let (accepted_sender, accepted_receiver) = bounded(0);
+    scope(|s| {
+        let accept_loop = s.spawn(|| {
+            let mut pending = Vec::new();
+            for stream in listener.incoming() {
+                pending.push((stream.unwrap(), listener.track()));
+                accepted_sender.send(()).unwrap();
+            }
+            pending
+        });
+
+        let _streams = [(); 2].map(|_| {
+            let stream = TcpStream::connect(addr).unwrap();
+            accepted_receiver.recv().unwrap();
+            stream
+        });
+        let status = listener.status();
+        assert_eq!((status.accepted, status.pending), (2, 2));
+        assert_eq!(status.accept_loops, 1);
+        assert!(!status.is_stopped());
+
+        listener.cancel().unwrap();
+        let mut pending = accept_loop.join().unwrap();
+        let status = listener.status();
+        assert!(status.is_stopped());
+        assert_eq!(status.pending, 2);
+
+        let _ = pending.pop();
+        assert_eq!(listener.status().pending, 1);
+        drop(pending);
+        assert_eq!(listener.status().pending, 0);
     });
 }
@@ -1,9 +1,14 @@
+// The thread pool waits for its jobs on a wait group, which is modeled with loom, so these run only
+// without it. See the tests of `ThreadPoolInner` for the model.
+#![cfg(not(feature = "check-loom"))]
+
 use crossbeam_channel::bounded;
 use cs431_homework::hello_server::ThreadPool;
+use cs431_homework::{Barrier, CountDownLatch};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{Arc, Barrier};
+use std::sync::Arc;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const NUM_THREADS: usize = 4;
 const NUM_JOBS: usize = 1024;
@@ -12,18 +17,16 @@ const NUM_JOBS: usize = 1024;
 fn thread_pool_parallel() {
     let pool = ThreadPool::new(NUM_THREADS);
     let barrier = Arc::new(Barrier::new(NUM_THREADS));
-    let (done_sender, done_receiver) = bounded(NUM_THREADS);
+    let done = Arc::new(CountDownLatch::new(NUM_THREADS));
     for _ in 0..NUM_THREADS {
         let barrier = barrier.clone();
-        let done_sender = done_sender.clone();
+        let done = done.clone();
         pool.execute(move || {
-            barrier.wait();
-            done_sender.send(()).unwrap();
+            let _ = barrier.wait();
+            done.count_down();
         });
     }
-    for _ in 0..NUM_THREADS {
-        done_receiver.recv_timeout(Duration::from_secs(3)).unwrap();
-    }
+    assert!(done.wait_timeout(Duration::from_secs(3)));
 }
 
 // Run jobs that take NUM_JOBS milliseconds as a whole.
@@ -67,3 +70,48 @@ fn thread_pool_drop_propagate_panic() {
         panic!();
     });
 }
+
+/// Delayed jobs run after their delays, unless they are cancelled.
+#[test]
+fn thread_pool_execute_after() {
+    let pool = ThreadPool::new(NUM_THREADS);
+    let (done_sender, done_receiver) = bounded(NUM_THREADS);
+    let start = Instant::now();
+    for millis in [300, 100, 200] {
+        let done_sender = done_sender.clone();
+        let _ = pool.execute_after(Duration::from_millis(millis), move || {
+            done_sender.send(millis).unwrap();
+        });
+    }
+    let cancelled = pool.execute_after(Duration::from_millis(150), move || {
+        done_sender.send(0).unwrap();
+    });
+    assert!(cancelled.cancel());
+    assert!(!cancelled.cancel());
+
+    for millis in [100, 200, 300] {
+        let done = done_receiver.recv_timeout(Duration::from_secs(3)).unwrap();
+        assert_eq!(done, millis);
+        assert!(start.elapsed() >= Duration::from_millis(millis));
+    }
+    // All senders are dropped once the cancelled job's time comes.
+    assert!(done_receiver.recv_timeout(Duration::from_secs(3)).is_err());
+}
+
+/// Delayed jobs that are not due are dropped with the pool.
+#[test]
+fn thread_pool_drop_delayed() {
+    let pool = ThreadPool::new(NUM_THREADS);
+    let counter = Arc::new(AtomicUsize::new(0));
+    let handle = {
+        let counter = counter.clone();
+        pool.execute_after(Duration::from_secs(60), move || {
+            counter.fetch_add(1, Ordering::Relaxed);
+        })
+    };
+    pool.join();
+    drop(pool);
+    assert!(handle.is_pending());
+    assert_eq!(counter.load(Ordering::Relaxed), 0);
+    assert_eq!(Arc::strong_count(&counter), 1);
+}
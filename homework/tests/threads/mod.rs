@@ -0,0 +1,157 @@
+//! Scoped threads for the tests, which join all threads and report all of their panics.
+//!
+//! `std::thread::scope` reports only that some thread panicked, and a test that joins its threads
+//! one by one reports only the first panic. Here, every panic of the threads is recorded with the
+//! index of its thread, and `scope` panics with all of them once every thread is joined.
+
+// Each test crate uses a part of it.
+#![allow(dead_code)]
+
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ScopedJoinHandle};
+
+/// Scope to spawn threads that borrow from the enclosing function, passed to the closure of
+/// [`scope`].
+pub struct Scope<'scope, 'env: 'scope> {
+    scope: &'scope thread::Scope<'scope, 'env>,
+    /// The messages of the panics of the threads.
+    panics: Arc<Mutex<Vec<String>>>,
+    /// Number of the threads spawned with `spawn`, which names them.
+    spawned: Cell<usize>,
+}
+
+/// Handle to join a thread spawned with [`Scope::spawn`].
+pub struct Handle<'scope, T> {
+    /// `None` if the thread panicked.
+    handle: ScopedJoinHandle<'scope, Option<T>>,
+}
+
+/// Runs `f` on the current thread with a scope to spawn threads, and joins the threads that are
+/// not joined yet before returning the result of `f`. Panics if any of the threads panicked, with
+/// the messages of all of them.
+pub fn scope<'env, F, T>(f: F) -> T
+where
+    F: for<'scope> FnOnce(&Scope<'scope, 'env>) -> T,
+{
+    let panics = Arc::new(Mutex::new(Vec::new()));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        thread::scope(|scope| {
+            f(&Scope {
+                scope,
+                panics: panics.clone(),
+                spawned: Cell::new(0),
+            })
+        })
+    }));
+
+    let panics = panics.lock().unwrap();
+    // The panic of the threads comes first, as it is likely to cause a panic of `f`, e.g., in
+    // `Handle::join`.
+    if !panics.is_empty() {
+        panic!(
+            "{} of the scoped threads panicked:\n{}",
+            panics.len(),
+            panics.join("\n")
+        );
+    }
+    result.unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
+
+/// Runs `f(t)` on thread `t` for each `t` in `0..threads`, and returns the results in the order of
+/// `t`. See [`Scope::run`].
+pub fn run<T, F>(threads: usize, f: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    scope(|s| s.run(threads, f))
+}
+
+/// Returns the message of a panic.
+fn message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Runs `f`, recording its panic as the one of the thread named `name`.
+fn record_panic<T, F: FnOnce() -> T>(panics: &Mutex<Vec<String>>, name: &str, f: F) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(t) => Some(t),
+        Err(payload) => {
+            let message = format!("{name}: {}", message(&*payload));
+            panics.lock().unwrap().push(message);
+            None
+        }
+    }
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Spawns a thread that runs `f`, which may borrow from outside of the scope.
+    pub fn spawn<T, F>(&self, f: F) -> Handle<'scope, T>
+    where
+        T: Send + 'scope,
+        F: FnOnce() -> T + Send + 'scope,
+    {
+        let index = self.spawned.get();
+        self.spawned.set(index + 1);
+        let panics = self.panics.clone();
+        Handle {
+            handle: self
+                .scope
+                .spawn(move || record_panic(&panics, &format!("spawned thread {index}"), f)),
+        }
+    }
+
+    /// Runs `f(t)` on thread `t` for each `t` in `0..threads`, and returns the results in the order
+    /// of `t` once all of them finish. `f` may also borrow from this call, unlike with `spawn`.
+    ///
+    /// Panics if any of the threads panicked, after joining all of them.
+    pub fn run<T, F>(&self, threads: usize, f: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(usize) -> T + Sync,
+    {
+        let f = &f;
+        let results = thread::scope(|scope| {
+            // Spawns all threads before joining any of them, so that they run concurrently.
+            #[allow(clippy::needless_collect)]
+            let handles = (0..threads)
+                .map(|t| {
+                    let panics = &self.panics;
+                    scope.spawn(move || record_panic(panics, &format!("thread {t}"), || f(t)))
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        results
+            .into_iter()
+            .map(|result| result.expect("a thread panicked"))
+            .collect()
+    }
+}
+
+impl<T> Handle<'_, T> {
+    /// Waits for the thread to finish, and returns its result. Panics if the thread panicked.
+    pub fn join(self) -> T {
+        self.handle
+            .join()
+            .unwrap()
+            .expect("the joined thread panicked")
+    }
+
+    /// Returns `true` if the thread has finished.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
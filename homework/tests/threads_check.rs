@@ -0,0 +1,63 @@
+mod threads;
+
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use threads::{run, scope};
+
+#[test]
+fn run_in_order() {
+    let data = (0..8).collect::<Vec<usize>>();
+    let results = run(8, |t| data[t] * 2);
+    assert_eq!(results, (0..8).map(|t| t * 2).collect::<Vec<_>>());
+}
+
+/// The threads spawned and not joined are joined at the end of the scope.
+#[test]
+fn spawn_join() {
+    let count = AtomicUsize::new(0);
+    let sum = scope(|s| {
+        for _ in 0..4 {
+            let _ = s.spawn(|| count.fetch_add(1, Ordering::Relaxed));
+        }
+        let handle = s.spawn(|| 1 + 2);
+        let ran = s.run(4, |t| t);
+        handle.join() + ran.into_iter().sum::<usize>()
+    });
+    assert_eq!(sum, 9);
+    assert_eq!(count.load(Ordering::Relaxed), 4);
+}
+
+/// All panics are reported, not just the first one, and only after all threads finish.
+#[test]
+fn panics_aggregated() {
+    let finished = AtomicUsize::new(0);
+    let payload = panic::catch_unwind(|| {
+        scope(|s| {
+            let _ = s.spawn(|| panic!("spawned"));
+            let _ = s.run(4, |t| {
+                if t % 2 == 1 {
+                    panic!("odd {t}");
+                }
+                let _ = finished.fetch_add(1, Ordering::Relaxed);
+            });
+        })
+    })
+    .unwrap_err();
+    let message = payload.downcast_ref::<String>().unwrap();
+    assert!(message.starts_with("3 of the scoped threads panicked"));
+    for expected in [
+        "spawned thread 0: spawned",
+        "thread 1: odd 1",
+        "thread 3: odd 3",
+    ] {
+        assert!(message.contains(expected), "{message}");
+    }
+    assert_eq!(finished.load(Ordering::Relaxed), 2);
+}
+
+/// A panic of the scope itself is passed on as it is.
+#[test]
+#[should_panic(expected = "scope")]
+fn scope_panics() {
+    scope(|_| panic!("scope"))
+}
@@ -0,0 +1,113 @@
+use crossbeam_channel::unbounded;
+use cs431_homework::hello_server::TimerWheel;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+const TICK: Duration = Duration::from_millis(1);
+
+/// Timers fire in the order of their deadlines, never early, whether their deadlines are in the
+/// finest wheel or have to be cascaded from coarser ones.
+#[test]
+fn timer_order() {
+    let timers = TimerWheel::new(TICK);
+    let (sender, receiver) = unbounded();
+    let start = Instant::now();
+    let delays = [70, 1, 300, 5, 0, 64, 130];
+    for millis in delays {
+        let sender = sender.clone();
+        let _ = timers.schedule(Duration::from_millis(millis), move || {
+            sender.send((millis, start.elapsed())).unwrap();
+        });
+    }
+    let mut expected = delays;
+    expected.sort_unstable();
+    for millis in expected {
+        let (fired, elapsed) = receiver.recv_timeout(Duration::from_secs(3)).unwrap();
+        assert_eq!(fired, millis);
+        assert!(elapsed >= Duration::from_millis(millis));
+    }
+}
+
+/// A cancelled timer does not fire, and a fired one cannot be cancelled.
+#[test]
+fn timer_cancel() {
+    let timers = TimerWheel::new(TICK);
+    let fired = Arc::new(AtomicUsize::new(0));
+    let handles = [10, 100].map(|millis| {
+        let fired = fired.clone();
+        timers.schedule(Duration::from_millis(millis), move || {
+            fired.fetch_add(1, Ordering::Relaxed);
+        })
+    });
+    assert!(handles[1].cancel());
+    assert!(!handles[1].is_pending());
+    sleep(Duration::from_millis(300));
+    assert_eq!(fired.load(Ordering::Relaxed), 1);
+    assert!(!handles[0].is_pending());
+    assert!(!handles[0].cancel());
+    // The cancelled callback is dropped when its time comes.
+    assert_eq!(Arc::strong_count(&fired), 1);
+}
+
+/// A repeating timer fires until its callback returns `false`, or until it is cancelled.
+#[test]
+fn timer_repeating() {
+    let timers = TimerWheel::new(TICK);
+    let fired = Arc::new(AtomicUsize::new(0));
+    let stopped = {
+        let fired = fired.clone();
+        timers.schedule_repeating(Duration::from_millis(10), move || {
+            fired.fetch_add(1, Ordering::Relaxed) + 1 < 3
+        })
+    };
+    let (sender, receiver) = unbounded();
+    let cancelled =
+        timers.schedule_repeating(Duration::from_millis(10), move || sender.send(()).is_ok());
+    for _ in 0..5 {
+        receiver.recv_timeout(Duration::from_secs(3)).unwrap();
+    }
+    assert!(cancelled.cancel());
+    sleep(Duration::from_millis(100));
+    assert!(!stopped.is_pending());
+    assert_eq!(fired.load(Ordering::Relaxed), 3);
+    // At most the run racing with `cancel` is received after it.
+    assert!(receiver.try_iter().count() <= 1);
+}
+
+/// Callbacks can schedule more timers, and a panicking callback does not stop the others.
+#[test]
+fn timer_callbacks() {
+    let timers = Arc::new(TimerWheel::new(TICK));
+    let (sender, receiver) = unbounded();
+    let _ = timers.schedule(Duration::from_millis(5), || panic!());
+    let _ = timers.schedule(Duration::from_millis(10), {
+        let timers = Arc::downgrade(&timers);
+        move || {
+            let _ = timers
+                .upgrade()
+                .unwrap()
+                .schedule(Duration::from_millis(10), move || sender.send(()).unwrap());
+        }
+    });
+    receiver.recv_timeout(Duration::from_secs(3)).unwrap();
+}
+
+/// Dropping the wheel drops the pending timers without firing them, and does not wait for them.
+#[test]
+fn timer_drop() {
+    let timers = TimerWheel::new(TICK);
+    let fired = Arc::new(AtomicUsize::new(0));
+    let handle = {
+        let fired = fired.clone();
+        timers.schedule(Duration::from_secs(3600), move || {
+            fired.fetch_add(1, Ordering::Relaxed);
+        })
+    };
+    let start = Instant::now();
+    drop(timers);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert!(handle.is_pending());
+    assert_eq!(Arc::strong_count(&fired), 1);
+}
@@ -0,0 +1,226 @@
+mod linearizability;
+mod mock;
+mod threads;
+
+#[cfg(not(feature = "check-loom"))]
+mod basic {
+    use super::linearizability::{assert_linearizable, Recorder, Spec};
+    use super::threads::run;
+    use cs431_homework::UnionFind;
+    use rand::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    /// Disjoint sets, each element labeled with the smallest element of its set.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Naive(Vec<usize>);
+
+    impl Naive {
+        fn new(len: usize) -> Self {
+            Self((0..len).collect())
+        }
+
+        fn union(&mut self, a: usize, b: usize) -> bool {
+            let (x, y) = (self.0[a], self.0[b]);
+            if x == y {
+                return false;
+            }
+            let (low, high) = (x.min(y), x.max(y));
+            for label in &mut self.0 {
+                if *label == high {
+                    *label = low;
+                }
+            }
+            true
+        }
+
+        fn same_set(&self, a: usize, b: usize) -> bool {
+            self.0[a] == self.0[b]
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    enum UnionFindOp {
+        Union(usize, usize),
+        SameSet(usize, usize),
+    }
+
+    impl Spec for Naive {
+        type Op = UnionFindOp;
+        type Ret = bool;
+
+        fn apply(&mut self, op: &UnionFindOp) -> bool {
+            match *op {
+                UnionFindOp::Union(a, b) => self.union(a, b),
+                UnionFindOp::SameSet(a, b) => self.same_set(a, b),
+            }
+        }
+    }
+
+    #[test]
+    fn smoke() {
+        let sets = UnionFind::new(5);
+        assert_eq!(sets.len(), 5);
+        assert!(!sets.same_set(0, 1));
+        assert!(sets.union(0, 1));
+        assert!(sets.union(3, 4));
+        assert!(!sets.union(1, 0));
+        assert!(sets.same_set(1, 0));
+        assert!(!sets.same_set(1, 3));
+        assert!(sets.union(1, 4));
+        assert!(sets.same_set(0, 3));
+        assert_eq!(sets.find(0), sets.find(4));
+        assert_eq!(sets.find(2), 2);
+    }
+
+    /// Random operations agree with the naive sets.
+    #[test]
+    fn sequential() {
+        const LEN: usize = 256;
+        const ITER: usize = 1024;
+
+        let sets = UnionFind::new(LEN);
+        let mut reference = Naive::new(LEN);
+        let mut rng = thread_rng();
+        for _ in 0..ITER {
+            let (a, b) = (rng.gen_range(0..LEN), rng.gen_range(0..LEN));
+            if rng.gen_ratio(1, 4) {
+                assert_eq!(sets.union(a, b), reference.union(a, b));
+            } else {
+                assert_eq!(sets.same_set(a, b), reference.same_set(a, b));
+            }
+        }
+        for a in 0..LEN {
+            assert_eq!(sets.find(a), sets.find(reference.0[a]));
+        }
+    }
+
+    /// Merging the sets of random pairs concurrently ends in the same sets as merging them one by
+    /// one, and each merge is counted once.
+    #[test]
+    fn stress() {
+        const THREADS: usize = 4;
+        const LEN: usize = 1024 * 16;
+        const ITER: usize = 1024 * 4;
+
+        let mut rng = thread_rng();
+        let pairs = (0..THREADS * ITER)
+            .map(|_| (rng.gen_range(0..LEN), rng.gen_range(0..LEN)))
+            .collect::<Vec<_>>();
+        let sets = UnionFind::new(LEN);
+        let merged = AtomicUsize::new(0);
+        let _ = run(THREADS, |t| {
+            for &(a, b) in &pairs[t * ITER..(t + 1) * ITER] {
+                if sets.union(a, b) {
+                    let _ = merged.fetch_add(1, Relaxed);
+                }
+            }
+        });
+
+        let reference = UnionFind::new(LEN);
+        let expected = pairs
+            .iter()
+            .filter(|(a, b)| reference.union(*a, *b))
+            .count();
+        assert_eq!(merged.load(Relaxed), expected);
+        for a in 0..LEN {
+            let root = sets.find(a);
+            assert!(reference.same_set(a, root));
+            assert_eq!(sets.find(root), root);
+        }
+    }
+
+    /// Histories of random operations on a few elements are linearizable.
+    #[test]
+    fn linearizable() {
+        const THREADS: usize = 3;
+        const LEN: usize = 8;
+        const ITER: usize = 16;
+        const ROUNDS: usize = 64;
+
+        for _ in 0..ROUNDS {
+            let sets = UnionFind::new(LEN);
+            let recorder = Recorder::default();
+            let history = run(THREADS, |_| {
+                let mut rng = thread_rng();
+                let mut history = Vec::new();
+                for _ in 0..ITER {
+                    let (a, b) = (rng.gen_range(0..LEN), rng.gen_range(0..LEN));
+                    let op = if rng.gen_ratio(1, 3) {
+                        UnionFindOp::Union(a, b)
+                    } else {
+                        UnionFindOp::SameSet(a, b)
+                    };
+                    recorder.record(&mut history, op, |op| match *op {
+                        UnionFindOp::Union(a, b) => sets.union(a, b),
+                        UnionFindOp::SameSet(a, b) => sets.same_set(a, b),
+                    });
+                }
+                history
+            })
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+            assert_linearizable(&Naive::new(LEN), &history);
+        }
+    }
+}
+
+mod correctness {
+    use super::mock::model;
+    use super::mock::sync::Arc;
+    use super::mock::thread;
+    use cs431_homework::UnionFind;
+
+    /// Threads merging overlapping sets both merge, and end up with one set.
+    #[test]
+    fn union_sync() {
+        model(|| {
+            let sets = Arc::new(UnionFind::new(3));
+            let th = {
+                let sets = sets.clone();
+                thread::spawn(move || sets.union(0, 1))
+            };
+            assert!(sets.union(2, 1));
+            assert!(th.join().unwrap());
+            assert!(sets.same_set(0, 2));
+            assert!(!sets.union(2, 0));
+        })
+    }
+
+    /// A thread that sees the later of two merges sees the earlier one as well, and elements in
+    /// the same set stay in the same set while their root is linked below another one.
+    #[test]
+    fn same_set_sync() {
+        model(|| {
+            let sets = Arc::new(UnionFind::new(4));
+            assert!(sets.union(0, 1));
+            let th = {
+                let sets = sets.clone();
+                thread::spawn(move || {
+                    assert!(sets.same_set(0, 1));
+                    if sets.same_set(3, 0) {
+                        assert!(sets.same_set(2, 0));
+                    }
+                })
+            };
+            assert!(sets.union(2, 3));
+            assert!(sets.union(1, 2));
+            th.join().unwrap();
+        })
+    }
+
+    /// Of the threads merging the same sets at once, one merges them.
+    #[test]
+    fn union_race_sync() {
+        model(|| {
+            let sets = Arc::new(UnionFind::new(2));
+            let th = {
+                let sets = sets.clone();
+                thread::spawn(move || sets.union(0, 1))
+            };
+            let mine = sets.union(1, 0);
+            let theirs = th.join().unwrap();
+            assert!(mine ^ theirs);
+        })
+    }
+}
@@ -0,0 +1,75 @@
+// The wait group is modeled with loom, so these run only without it.
+#![cfg(not(feature = "check-loom"))]
+
+use cs431_homework::WaitGroup;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, scope, sleep};
+use std::time::Duration;
+
+#[test]
+fn add_done() {
+    let group = WaitGroup::new();
+    group.wait();
+    group.add(2);
+    group.done();
+    assert_eq!(group.count(), 1);
+    group.done();
+    assert_eq!(group.count(), 0);
+    group.wait();
+}
+
+#[test]
+#[should_panic]
+fn done_without_add() {
+    WaitGroup::new().done();
+}
+
+/// `wait` returns after all tasks are done, seeing their work, including the tasks added by other
+/// tasks.
+#[test]
+fn wait_spawned() {
+    const THREADS: usize = 8;
+
+    let group = Arc::new(WaitGroup::new());
+    let finished = Arc::new(AtomicUsize::new(0));
+    for _ in 0..THREADS {
+        group.add(1);
+        let group = group.clone();
+        let finished = finished.clone();
+        let _ = thread::spawn(move || {
+            // The child is added before its parent is done.
+            group.add(1);
+            let child_group = group.clone();
+            let child_finished = finished.clone();
+            let _ = thread::spawn(move || {
+                sleep(Duration::from_millis(50));
+                let _ = child_finished.fetch_add(1, Ordering::Relaxed);
+                child_group.done();
+            });
+            let _ = finished.fetch_add(1, Ordering::Relaxed);
+            group.done();
+        });
+    }
+    group.wait();
+    assert_eq!(finished.load(Ordering::Relaxed), 2 * THREADS);
+}
+
+/// Multiple waiters are all woken up, and the group can be reused afterwards.
+#[test]
+fn reuse() {
+    const ROUNDS: usize = 128;
+    const WAITERS: usize = 4;
+
+    let group = WaitGroup::new();
+    for _ in 0..ROUNDS {
+        group.add(1);
+        scope(|s| {
+            for _ in 0..WAITERS {
+                let _ = s.spawn(|| group.wait());
+            }
+            group.done();
+        });
+        assert_eq!(group.count(), 0);
+    }
+}
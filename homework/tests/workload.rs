@@ -0,0 +1,76 @@
+use cs431_homework::workload::{Contention, KeyDist, Mix, Op, Workload};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const OPS: usize = 1024 * 16;
+
+fn key(op: Op) -> u64 {
+    match op {
+        Op::Read(key) | Op::Insert(key) | Op::Remove(key) => key,
+    }
+}
+
+/// The operations depend only on the seed and the thread.
+#[test]
+fn reproducible() {
+    let workload = Workload::new(KeyDist::Uniform { keys: 64 }, Mix::BALANCED, OPS);
+    assert_eq!(workload.ops(0), workload.ops(0));
+    assert_ne!(workload.ops(0), workload.ops(1));
+    assert_ne!(workload.ops(0), workload.with_seed(1).ops(0));
+}
+
+#[test]
+fn mix() {
+    let workload = Workload::new(KeyDist::Uniform { keys: 64 }, Mix::READ_MOSTLY, OPS);
+    let ops = workload.ops(0);
+    let reads = ops.iter().filter(|op| matches!(op, Op::Read(_))).count();
+    assert!((OPS * 85 / 100..OPS * 95 / 100).contains(&reads), "{reads}");
+
+    let ops = Workload::new(KeyDist::Uniform { keys: 64 }, Mix::READ_ONLY, OPS).ops(0);
+    assert!(ops.iter().all(|op| matches!(op, Op::Read(_))));
+}
+
+#[test]
+fn keys() {
+    let uniform = Workload::new(KeyDist::Uniform { keys: 64 }, Mix::BALANCED, OPS).ops(0);
+    let keys = uniform.into_iter().map(key).collect::<HashSet<_>>();
+    assert_eq!(keys, (0..64).collect());
+
+    let zipf = KeyDist::Zipf {
+        keys: 64,
+        exponent: 1.0,
+    };
+    let ops = Workload::new(zipf, Mix::BALANCED, OPS).ops(0);
+    let first = ops.iter().filter(|op| key(**op) == 0).count();
+    let last = ops.iter().filter(|op| key(**op) == 63).count();
+    assert!(first > 10 * last, "{first} {last}");
+    assert!(ops.iter().all(|op| key(*op) < 64));
+
+    let hotspot = KeyDist::Hotspot {
+        keys: 64,
+        hot: 4,
+        ratio: 0.9,
+    };
+    let ops = Workload::new(hotspot, Mix::BALANCED, OPS).ops(0);
+    let hot = ops.iter().filter(|op| key(**op) < 4).count();
+    assert!((OPS * 85 / 100..OPS * 95 / 100).contains(&hot), "{hot}");
+}
+
+#[test]
+fn run() {
+    let workload = Workload::new(KeyDist::Uniform { keys: 64 }, Mix::BALANCED, 1024);
+    for contention in Contention::ALL {
+        let threads = contention.threads();
+        let setups = AtomicUsize::new(0);
+        let count = AtomicUsize::new(0);
+        let _ = workload.run(threads, |_| {
+            let _ = setups.fetch_add(1, Ordering::Relaxed);
+            |_| {
+                let _ = count.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        assert_eq!(setups.into_inner(), threads);
+        assert_eq!(count.into_inner(), threads * 1024);
+    }
+    assert_eq!(workload.prefill().count(), 32);
+}